@@ -27,6 +27,32 @@ fn main() {
     println!("cargo:rerun-if-changed=vendor/minhook/src");
     println!("cargo:rustc-link-search=native={}", env::var("OUT_DIR").unwrap());
 
+    #[cfg(feature = "plugins")]
+    {
+        use std::process::Command;
+
+        // Plugins and their host share Rust types, which requires the same
+        // compiler on both sides.
+        let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+        let output = Command::new(rustc).arg("-V").output().unwrap();
+        let version = String::from_utf8_lossy(&output.stdout);
+        println!("cargo:rustc-env=HUDHOOK_RUSTC_VERSION={}", version.trim());
+    }
+
+    #[cfg(feature = "obfuscate-names")]
+    {
+        use std::collections::hash_map::RandomState;
+        use std::hash::{BuildHasher, Hasher};
+        use std::time::SystemTime;
+
+        // A random prefix, regenerated whenever the build script reruns.
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos(),
+        );
+        println!("cargo:rustc-env=HUDHOOK_NAME_PREFIX=x{:012x}", hasher.finish() >> 16);
+    }
+
     #[cfg(feature = "opengl3")]
     {
         use std::fs::File;
@@ -0,0 +1,107 @@
+//! Screen reader announcements for overlay menus.
+//!
+//! `imgui` draws its widgets itself, so screen readers can't see them. This
+//! module bridges the gap with announcements: widgets described with
+//! [`describe`] or [`describe_value`] are announced when they get the
+//! keyboard focus, and their value when it changes, through the
+//! [`Announcer`] set with [`set_announcer`]. Implement it on top of the
+//! screen reader client library of your choice, e.g. the NVDA controller
+//! client or Tolk.
+//!
+//! Enable keyboard navigation (`ConfigFlags::NAV_ENABLE_KEYBOARD`) so that
+//! users can move the focus between widgets without a mouse.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::accessibility::{self, Announcer};
+//!
+//! struct ScreenReader;
+//!
+//! impl Announcer for ScreenReader {
+//!     fn announce(&self, text: &str, interrupt: bool) {
+//!         // Forward to the screen reader.
+//!     }
+//! }
+//!
+//! accessibility::set_announcer(ScreenReader);
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // if ui.button("Start") { ... }
+//! // accessibility::describe(ui, "Start, button");
+//! // ui.slider("Volume", 0, 100, &mut volume);
+//! // accessibility::describe_value(ui, "Volume, slider", &volume.to_string());
+//! ```
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use imgui::{sys, Ui};
+use parking_lot::Mutex;
+
+static ANNOUNCER: Mutex<Option<Box<dyn Announcer>>> = Mutex::new(None);
+static FOCUSED_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Speaks or brails text for the user, typically through a screen reader.
+///
+/// Announcements are made from the render thread: implementations should
+/// hand the text over to the screen reader without blocking.
+pub trait Announcer: Send + Sync {
+    /// Announce `text`. With `interrupt`, the announcement replaces any
+    /// announcement still being spoken, e.g. when the focus moves on.
+    fn announce(&self, text: &str, interrupt: bool);
+}
+
+/// Set the announcer all announcements go through, replacing the previous
+/// one.
+pub fn set_announcer(announcer: impl Announcer + 'static) {
+    *ANNOUNCER.lock() = Some(Box::new(announcer));
+}
+
+/// Remove the announcer. Announcements are dropped until another one is set.
+pub fn clear_announcer() {
+    ANNOUNCER.lock().take();
+}
+
+/// Announce `text`, after the announcement being spoken if any. Use this for
+/// events that aren't tied to a widget, e.g. "Settings saved".
+pub fn announce(text: &str) {
+    if let Some(announcer) = ANNOUNCER.lock().as_ref() {
+        announcer.announce(text, false);
+    }
+}
+
+/// Describe the last widget with `label`, announced when the widget gets the
+/// keyboard focus. Call it right after the widget, every frame.
+///
+/// Include the kind of widget in the label, e.g. "Start, button", as screen
+/// readers can't tell on their own.
+pub fn describe(ui: &Ui, label: &str) {
+    if gained_focus(ui) {
+        announce_now(label);
+    }
+}
+
+/// Describe the last widget with `label` and its current `value`, announced
+/// when the widget gets the keyboard focus. The value alone is announced
+/// whenever the user changes it. Call it right after the widget, every frame.
+pub fn describe_value(ui: &Ui, label: &str, value: &str) {
+    if gained_focus(ui) {
+        announce_now(&format!("{label}, {value}"));
+    } else if ui.is_item_focused() && ui.is_item_edited() {
+        announce_now(value);
+    }
+}
+
+// Whether the last widget got the focus since the last described widget did.
+fn gained_focus(ui: &Ui) -> bool {
+    if !ui.is_item_focused() {
+        return false;
+    }
+
+    let id = unsafe { sys::igGetItemID() };
+    FOCUSED_ID.swap(id, Ordering::Relaxed) != id
+}
+
+fn announce_now(text: &str) {
+    if let Some(announcer) = ANNOUNCER.lock().as_ref() {
+        announcer.announce(text, true);
+    }
+}
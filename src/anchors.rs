@@ -0,0 +1,240 @@
+//! Named screen regions locating the game's own HUD elements.
+//!
+//! Games lay their HUD out differently at each resolution, so an overlay
+//! can't compute where e.g. the minimap or the health bar is. [`HudAnchors`]
+//! lets users calibrate those regions once, by dragging a rectangle over the
+//! HUD element, and stores them per resolution so that render loops can align
+//! overlay elements with the game's HUD.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::anchors::HudAnchors;
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let path = HudAnchors::game_path(".").unwrap();
+//! let mut anchors = HudAnchors::load(&path).unwrap_or_default();
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // match anchors.get("minimap", ui.io().display_size) {
+//! //     Some(minimap) => ui.get_foreground_draw_list().add_text(
+//! //         [minimap.min[0], minimap.max[1]],
+//! //         [1.0, 1.0, 1.0],
+//! //         "Objective: north",
+//! //     ),
+//! //     None => {
+//! //         anchors.calibrate(ui, "minimap");
+//! //     },
+//! // }
+//!
+//! // On exit:
+//! anchors.save(&path).unwrap();
+//! ```
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use imgui::{MouseButton, Ui};
+
+/// A rectangular region of the display, in pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Region {
+    /// Top-left corner.
+    pub min: [f32; 2],
+    /// Bottom-right corner.
+    pub max: [f32; 2],
+}
+
+impl Region {
+    /// Size of the region.
+    pub fn size(&self) -> [f32; 2] {
+        [self.max[0] - self.min[0], self.max[1] - self.min[1]]
+    }
+
+    /// Center of the region.
+    pub fn center(&self) -> [f32; 2] {
+        [(self.min[0] + self.max[0]) / 2.0, (self.min[1] + self.max[1]) / 2.0]
+    }
+
+    /// Whether `pos` lies within the region.
+    pub fn contains(&self, pos: [f32; 2]) -> bool {
+        (self.min[0]..=self.max[0]).contains(&pos[0])
+            && (self.min[1]..=self.max[1]).contains(&pos[1])
+    }
+
+    // Region spanned by two opposite corners.
+    fn from_corners(a: [f32; 2], b: [f32; 2]) -> Self {
+        Self { min: [a[0].min(b[0]), a[1].min(b[1])], max: [a[0].max(b[0]), a[1].max(b[1])] }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Calibration {
+    name: String,
+    resolution: [u32; 2],
+    region: Region,
+}
+
+/// Keeps track of the calibrated anchor regions of a game.
+#[derive(Debug, Default, Clone)]
+pub struct HudAnchors {
+    calibrations: Vec<Calibration>,
+    // Where the calibration drag started, if one is in progress.
+    drag_start: Option<[f32; 2]>,
+}
+
+impl HudAnchors {
+    /// Create an empty set of anchors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Path of the anchors file of the running game within `dir`, named after
+    /// its executable, e.g. `dir/game.anchors.txt`.
+    pub fn game_path<P: AsRef<Path>>(dir: P) -> io::Result<PathBuf> {
+        let exe = env::current_exe()?;
+        let stem = exe.file_stem().unwrap_or_default().to_string_lossy();
+
+        Ok(dir.as_ref().join(format!("{stem}.anchors.txt")))
+    }
+
+    /// Get the region named `name` at the given display size.
+    ///
+    /// Regions calibrated at another resolution are scaled to the display,
+    /// picking the calibration with the closest aspect ratio. As games
+    /// usually lay their HUD out differently per aspect ratio, calibrate each
+    /// aspect ratio you support.
+    pub fn get(&self, name: &str, display_size: [f32; 2]) -> Option<Region> {
+        let resolution = resolution(display_size);
+        let aspect_ratio = |[w, h]: [u32; 2]| w as f32 / h.max(1) as f32;
+
+        let calibration = self
+            .calibrations
+            .iter()
+            .filter(|c| c.name == name)
+            .find(|c| c.resolution == resolution)
+            .or_else(|| {
+                self.calibrations.iter().filter(|c| c.name == name).min_by(|a, b| {
+                    let distance = |c: &Calibration| {
+                        (aspect_ratio(c.resolution) - aspect_ratio(resolution)).abs()
+                    };
+                    distance(a).total_cmp(&distance(b))
+                })
+            })?;
+
+        let scale = [
+            display_size[0] / calibration.resolution[0].max(1) as f32,
+            display_size[1] / calibration.resolution[1].max(1) as f32,
+        ];
+        let Region { min, max } = calibration.region;
+
+        Some(Region {
+            min: [min[0] * scale[0], min[1] * scale[1]],
+            max: [max[0] * scale[0], max[1] * scale[1]],
+        })
+    }
+
+    /// Set the region named `name` at the given display size.
+    pub fn set(&mut self, name: &str, display_size: [f32; 2], region: Region) {
+        let resolution = resolution(display_size);
+
+        match self.calibrations.iter_mut().find(|c| c.name == name && c.resolution == resolution) {
+            Some(calibration) => calibration.region = region,
+            None => {
+                self.calibrations.push(Calibration { name: name.to_string(), resolution, region })
+            },
+        }
+    }
+
+    /// Forget the regions named `name`, at every resolution.
+    pub fn remove(&mut self, name: &str) {
+        self.calibrations.retain(|c| c.name != name);
+    }
+
+    /// Let the user calibrate the region named `name` at the current display
+    /// size, by dragging a rectangle over the HUD element with the left mouse
+    /// button. Call it every frame until it returns `true`, once the region is
+    /// set.
+    ///
+    /// The overlay must be capturing the mouse for the drag to reach it.
+    pub fn calibrate(&mut self, ui: &Ui, name: &str) -> bool {
+        let display_size = ui.io().display_size;
+        let mouse_pos = ui.io().mouse_pos;
+
+        let draw_list = ui.get_foreground_draw_list();
+        draw_list.add_rect([0.0, 0.0], display_size, [0.0, 0.0, 0.0, 0.35]).filled(true).build();
+        draw_list.add_text(
+            [16.0, 16.0],
+            [1.0, 1.0, 1.0],
+            format!("Drag a rectangle over \"{name}\" to calibrate it"),
+        );
+
+        if ui.is_mouse_clicked(MouseButton::Left) {
+            self.drag_start = Some(mouse_pos);
+        }
+
+        let Some(drag_start) = self.drag_start else {
+            return false;
+        };
+
+        let region = Region::from_corners(drag_start, mouse_pos);
+        draw_list.add_rect(region.min, region.max, [1.0, 0.8, 0.0, 1.0]).thickness(2.0).build();
+
+        if !ui.is_mouse_released(MouseButton::Left) {
+            return false;
+        }
+
+        self.drag_start = None;
+        self.set(name, display_size, region);
+        true
+    }
+
+    /// Load anchors previously written by [`save`](Self::save).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut anchors = Self::default();
+
+        for line in fs::read_to_string(path)?.lines() {
+            let fields: Vec<_> = line.split('\t').collect();
+            let [name, width, height, min_x, min_y, max_x, max_y] = fields[..] else {
+                continue;
+            };
+
+            let (Ok(width), Ok(height), Ok(min_x), Ok(min_y), Ok(max_x), Ok(max_y)) = (
+                width.parse(),
+                height.parse(),
+                min_x.parse(),
+                min_y.parse(),
+                max_x.parse(),
+                max_y.parse(),
+            ) else {
+                continue;
+            };
+
+            anchors.calibrations.push(Calibration {
+                name: name.to_string(),
+                resolution: [width, height],
+                region: Region { min: [min_x, min_y], max: [max_x, max_y] },
+            });
+        }
+
+        Ok(anchors)
+    }
+
+    /// Save the anchors to a file, one region per line.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let contents: String = self
+            .calibrations
+            .iter()
+            .map(|Calibration { name, resolution: [width, height], region }| {
+                format!(
+                    "{name}\t{width}\t{height}\t{}\t{}\t{}\t{}\n",
+                    region.min[0], region.min[1], region.max[0], region.max[1]
+                )
+            })
+            .collect();
+
+        fs::write(path, contents)
+    }
+}
+
+fn resolution(display_size: [f32; 2]) -> [u32; 2] {
+    [display_size[0].round() as u32, display_size[1].round() as u32]
+}
@@ -0,0 +1,309 @@
+//! Playback of short audio cues, such as alert sounds.
+//!
+//! Sounds are decoded once into a [`Sound`] and played back via XAudio2 on a
+//! background thread, so that playing a sound never blocks the render loop.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::audio::{self, Sound};
+//!
+//! let alert = Sound::from_wav(include_bytes!("alert.wav")).unwrap();
+//!
+//! // Later, e.g. from `ImguiRenderLoop::render`:
+//! audio::play(&alert);
+//! ```
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tracing::{debug, error};
+use windows::core::{Error, Result, HRESULT, PCWSTR};
+use windows::Win32::Media::Audio::XAudio2::{
+    IXAudio2, IXAudio2MasteringVoice, IXAudio2SourceVoice, IXAudio2VoiceCallback,
+    XAudio2CreateWithVersionInfo, XAUDIO2_BUFFER, XAUDIO2_COMMIT_NOW, XAUDIO2_DEFAULT_CHANNELS,
+    XAUDIO2_DEFAULT_FREQ_RATIO, XAUDIO2_DEFAULT_PROCESSOR, XAUDIO2_DEFAULT_SAMPLERATE,
+    XAUDIO2_END_OF_STREAM, XAUDIO2_VOICE_NOSAMPLESPLAYED, XAUDIO2_VOICE_STATE,
+};
+use windows::Win32::Media::Audio::{AudioCategory_GameEffects, WAVEFORMATEX, WAVE_FORMAT_PCM};
+use windows::Win32::Media::Multimedia::WAVE_FORMAT_IEEE_FLOAT;
+use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_MULTITHREADED};
+use windows::Win32::System::SystemInformation::NTDDI_WIN10;
+
+use crate::util;
+
+// How often the audio thread reaps voices that finished playing.
+const REAP_INTERVAL: Duration = Duration::from_millis(100);
+
+static AUDIO_THREAD: Mutex<Option<(Sender<Sound>, JoinHandle<()>)>> = Mutex::new(None);
+
+/// A pre-decoded sound, ready to be played back.
+///
+/// Cloning a [`Sound`] is cheap, as the sample data is shared.
+#[derive(Clone)]
+pub struct Sound {
+    format: WAVEFORMATEX,
+    data: Arc<[u8]>,
+}
+
+impl Sound {
+    /// Decode a sound from the contents of a WAV file.
+    ///
+    /// Only uncompressed PCM and IEEE float samples are supported.
+    pub fn from_wav(bytes: &[u8]) -> Result<Self> {
+        let Some((format, data)) = parse_wav(bytes) else {
+            error!("Invalid or unsupported WAV data");
+            return Err(Error::from_hresult(HRESULT(-1)));
+        };
+
+        Ok(Self { format, data: data.into() })
+    }
+
+    /// Read and decode a WAV file from disk.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| {
+            error!("Couldn't read {path:?}: {e:?}");
+            Error::from_hresult(HRESULT(-1))
+        })?;
+
+        Self::from_wav(&bytes)
+    }
+}
+
+// SAFETY: `WAVEFORMATEX` is plain data, and the samples are immutable.
+unsafe impl Send for Sound {}
+unsafe impl Sync for Sound {}
+
+/// Play a sound in the background.
+///
+/// The audio thread is started on first use. Sounds played in quick
+/// succession overlap rather than cut each other off.
+pub fn play(sound: &Sound) {
+    let mut audio_thread = AUDIO_THREAD.lock();
+
+    let (tx, _) = audio_thread.get_or_insert_with(|| {
+        let (tx, rx) = mpsc::channel();
+        (tx, thread::spawn(move || audio_thread_main(rx)))
+    });
+
+    if tx.send(sound.clone()).is_err() {
+        error!("Audio thread is not running");
+    }
+}
+
+/// Stop all playing sounds and terminate the audio thread.
+///
+/// This is called by [`eject`](crate::eject), as the thread must not outlive
+/// the library.
+pub fn shutdown() {
+    let audio_thread = AUDIO_THREAD.lock().take();
+
+    if let Some((tx, handle)) = audio_thread {
+        drop(tx);
+        if handle.join().is_err() {
+            error!("Audio thread panicked");
+        }
+    }
+}
+
+fn audio_thread_main(rx: Receiver<Sound>) {
+    if let Err(e) = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.ok() {
+        error!("Couldn't initialize COM: {e:?}");
+        // Drain the channel so senders don't pile up sounds.
+        while rx.recv().is_ok() {}
+        return;
+    }
+
+    if let Err(e) = unsafe { run_audio_loop(&rx) } {
+        error!("Audio playback failed: {e:?}");
+        while rx.recv().is_ok() {}
+    }
+
+    unsafe { CoUninitialize() };
+}
+
+unsafe fn run_audio_loop(rx: &Receiver<Sound>) -> Result<()> {
+    let xaudio2: IXAudio2 = util::try_out_ptr(|xaudio2| {
+        XAudio2CreateWithVersionInfo(xaudio2, 0, XAUDIO2_DEFAULT_PROCESSOR, NTDDI_WIN10)
+    })?;
+
+    let mastering_voice: IXAudio2MasteringVoice = util::try_out_ptr(|voice| {
+        xaudio2.CreateMasteringVoice(
+            voice,
+            XAUDIO2_DEFAULT_CHANNELS,
+            XAUDIO2_DEFAULT_SAMPLERATE,
+            0,
+            PCWSTR::null(),
+            None,
+            AudioCategory_GameEffects,
+        )
+    })?;
+
+    debug!("Audio thread started");
+
+    // Each voice keeps its sound alive, as XAudio2 reads the samples in place.
+    let mut voices: Vec<(IXAudio2SourceVoice, Sound)> = Vec::new();
+
+    loop {
+        match rx.recv_timeout(REAP_INTERVAL) {
+            Ok(sound) => match start_voice(&xaudio2, &sound) {
+                Ok(voice) => voices.push((voice, sound)),
+                Err(e) => error!("Couldn't play sound: {e:?}"),
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        voices.retain(|(voice, _)| {
+            let mut state = XAUDIO2_VOICE_STATE::default();
+            voice.GetState(&mut state, XAUDIO2_VOICE_NOSAMPLESPLAYED);
+            if state.BuffersQueued == 0 {
+                voice.DestroyVoice();
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    for (voice, _) in voices {
+        voice.DestroyVoice();
+    }
+    mastering_voice.DestroyVoice();
+
+    debug!("Audio thread terminated");
+
+    Ok(())
+}
+
+unsafe fn start_voice(xaudio2: &IXAudio2, sound: &Sound) -> Result<IXAudio2SourceVoice> {
+    let voice: IXAudio2SourceVoice = util::try_out_ptr(|voice| {
+        xaudio2.CreateSourceVoice(
+            voice,
+            &sound.format,
+            0,
+            XAUDIO2_DEFAULT_FREQ_RATIO,
+            None::<&IXAudio2VoiceCallback>,
+            None,
+            None,
+        )
+    })?;
+
+    let buffer = XAUDIO2_BUFFER {
+        Flags: XAUDIO2_END_OF_STREAM,
+        AudioBytes: sound.data.len() as u32,
+        pAudioData: sound.data.as_ptr(),
+        ..Default::default()
+    };
+
+    if let Err(e) =
+        voice.SubmitSourceBuffer(&buffer, None).and_then(|_| voice.Start(0, XAUDIO2_COMMIT_NOW))
+    {
+        voice.DestroyVoice();
+        return Err(e);
+    }
+
+    Ok(voice)
+}
+
+// Extract the format and the sample data from a RIFF/WAVE file.
+fn parse_wav(bytes: &[u8]) -> Option<(WAVEFORMATEX, &[u8])> {
+    fn u16_at(b: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([b[offset], b[offset + 1]])
+    }
+
+    fn u32_at(b: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([b[offset], b[offset + 1], b[offset + 2], b[offset + 3]])
+    }
+
+    if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WAVE" {
+        return None;
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut chunks = &bytes[12..];
+
+    while chunks.len() >= 8 {
+        let len = u32_at(chunks, 4) as usize;
+        let body = chunks.get(8..8 + len)?;
+
+        match &chunks[0..4] {
+            b"fmt " if len >= 16 => {
+                format = Some(WAVEFORMATEX {
+                    wFormatTag: u16_at(body, 0),
+                    nChannels: u16_at(body, 2),
+                    nSamplesPerSec: u32_at(body, 4),
+                    nAvgBytesPerSec: u32_at(body, 8),
+                    nBlockAlign: u16_at(body, 12),
+                    wBitsPerSample: u16_at(body, 14),
+                    cbSize: 0,
+                })
+            },
+            b"data" => data = Some(body),
+            _ => {},
+        }
+
+        // Chunks are padded to an even size.
+        chunks = chunks.get(8 + len + (len & 1)..).unwrap_or_default();
+    }
+
+    let format = format?;
+    if ![WAVE_FORMAT_PCM, WAVE_FORMAT_IEEE_FLOAT].contains(&(format.wFormatTag as u32))
+        || format.nChannels == 0
+        || format.nBlockAlign == 0
+    {
+        return None;
+    }
+
+    Some((format, data?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(fmt_tag: u16, data: &[u8]) -> Vec<u8> {
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&fmt_tag.to_le_bytes());
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&44100u32.to_le_bytes());
+        fmt.extend_from_slice(&88200u32.to_le_bytes());
+        fmt.extend_from_slice(&2u16.to_le_bytes());
+        fmt.extend_from_slice(&16u16.to_le_bytes());
+
+        let mut body = b"WAVE".to_vec();
+        for (id, chunk) in [(b"fmt ", &fmt[..]), (b"LIST", &[0u8; 3][..]), (b"data", data)] {
+            body.extend_from_slice(id);
+            body.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            body.extend_from_slice(chunk);
+            if chunk.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(&body);
+        out
+    }
+
+    #[test]
+    fn test_parse_wav() {
+        let bytes = wav(WAVE_FORMAT_PCM as u16, &[1, 2, 3, 4]);
+        let (format, data) = parse_wav(&bytes).unwrap();
+        assert_eq!(format.nSamplesPerSec, 44100);
+        assert_eq!(format.nBlockAlign, 2);
+        assert_eq!(data, &[1, 2, 3, 4]);
+
+        // Compressed formats are not supported.
+        assert!(parse_wav(&wav(2, &[1, 2, 3, 4])).is_none());
+        // Truncated files are rejected.
+        assert!(parse_wav(&bytes[..bytes.len() - 1]).is_none());
+        assert!(parse_wav(b"RIFF").is_none());
+    }
+}
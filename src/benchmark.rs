@@ -0,0 +1,246 @@
+//! Overlay self-benchmark.
+//!
+//! A benchmark draws a standardized stress UI, thousands of widgets in a
+//! window on top of the render loops, for a few seconds, and reports how long
+//! hudhook took to build and render each frame. Running the same benchmark on
+//! several machines, or with several hudhook versions, gives comparable
+//! numbers, independent of the mod's own UI.
+//!
+//! Start it from code with [`Benchmark::start`], or let users start it with
+//! the key set with [`set_hotkey`]. The report is logged when the benchmark
+//! completes, and kept for [`last_report`].
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::benchmark;
+//! use hudhook::windows::Win32::UI::Input::KeyboardAndMouse::VK_F12;
+//!
+//! // Run the default benchmark when the user presses F12.
+//! benchmark::set_hotkey(Some(VK_F12));
+//!
+//! // Later, e.g. in a diagnostics window:
+//! if let Some(report) = benchmark::last_report() {
+//!     println!("{report}");
+//! }
+//! ```
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use imgui::{Condition, ProgressBar, Ui};
+use parking_lot::Mutex;
+use tracing::info;
+use windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY;
+
+use crate::hooks::input::async_key_state;
+use crate::version::HUDHOOK_VERSION;
+
+static HOTKEY: Mutex<Option<VIRTUAL_KEY>> = Mutex::new(None);
+static HOTKEY_DOWN: AtomicBool = AtomicBool::new(false);
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+static LAST_REPORT: Mutex<Option<BenchmarkReport>> = Mutex::new(None);
+
+/// Settings of a benchmark run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Benchmark {
+    /// How long to run the benchmark for. Defaults to 5 seconds.
+    pub duration: Duration,
+    /// How many widgets the stress UI draws every frame. Defaults to 5000.
+    pub widgets: usize,
+}
+
+impl Default for Benchmark {
+    fn default() -> Self {
+        Self { duration: Duration::from_secs(5), widgets: 5000 }
+    }
+}
+
+impl Benchmark {
+    /// Start the benchmark on the next frame. Returns `false` if a benchmark
+    /// is already running.
+    pub fn start(self) -> bool {
+        let mut run = RUN.lock();
+        if run.is_some() {
+            return false;
+        }
+
+        info!("Starting benchmark: {} widgets for {:?}", self.widgets, self.duration);
+        *run = Some(Run { benchmark: self, started: None, last_frame: None, frames: Vec::new() });
+        true
+    }
+}
+
+/// Timings of a completed benchmark run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkReport {
+    /// The settings the benchmark ran with.
+    pub benchmark: Benchmark,
+    /// The hudhook version the benchmark ran with.
+    pub version: &'static str,
+    /// Number of frames rendered.
+    pub frames: usize,
+    /// Average time between two frames, game included.
+    pub frame_time: Duration,
+    /// Average time hudhook took to build and render a frame.
+    pub render_time: Duration,
+    /// Median time hudhook took to build and render a frame.
+    pub render_time_p50: Duration,
+    /// 99th percentile of the time hudhook took to build and render a frame.
+    pub render_time_p99: Duration,
+    /// Longest time hudhook took to build and render a frame.
+    pub render_time_max: Duration,
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hudhook {}, {} widgets, {} frames: frame {:.2} ms, render avg {:.2} ms, p50 {:.2} \
+             ms, p99 {:.2} ms, max {:.2} ms",
+            self.version,
+            self.benchmark.widgets,
+            self.frames,
+            ms(self.frame_time),
+            ms(self.render_time),
+            ms(self.render_time_p50),
+            ms(self.render_time_p99),
+            ms(self.render_time_max),
+        )
+    }
+}
+
+struct Run {
+    benchmark: Benchmark,
+    started: Option<Instant>,
+    last_frame: Option<Instant>,
+    // Time between frames, and time spent rendering, of every frame.
+    frames: Vec<(Duration, Duration)>,
+}
+
+/// Set the key starting the default benchmark, or `None` to disable it.
+pub fn set_hotkey(key: Option<VIRTUAL_KEY>) {
+    *HOTKEY.lock() = key;
+}
+
+/// Whether a benchmark is running.
+pub fn is_running() -> bool {
+    RUN.lock().is_some()
+}
+
+/// The report of the last completed benchmark.
+pub fn last_report() -> Option<BenchmarkReport> {
+    LAST_REPORT.lock().clone()
+}
+
+// Start the benchmark if the hotkey was just pressed, and return the number
+// of widgets to draw this frame if a benchmark is running.
+pub(crate) fn begin_frame() -> Option<usize> {
+    if let Some(key) = *HOTKEY.lock() {
+        let down = async_key_state(key) < 0;
+        if down && !HOTKEY_DOWN.swap(down, Ordering::Relaxed) {
+            Benchmark::default().start();
+        }
+        HOTKEY_DOWN.store(down, Ordering::Relaxed);
+    }
+
+    RUN.lock().as_ref().map(|run| run.benchmark.widgets)
+}
+
+// Draw the stress UI into the frame being built.
+pub(crate) fn draw(ui: &Ui, widgets: usize) {
+    ui.window("hudhook benchmark")
+        .position([0.0, 0.0], Condition::Always)
+        .size(ui.io().display_size, Condition::Always)
+        .build(|| {
+            let draw_list = ui.get_window_draw_list();
+
+            for i in 0..widgets {
+                let _id = ui.push_id_usize(i);
+                let fraction = (i % 100) as f32 / 100.0;
+
+                match i % 6 {
+                    0 => ui.text(format!("Widget {i}")),
+                    1 => {
+                        ui.button("Button");
+                    },
+                    2 => {
+                        let mut checked = i % 4 == 1;
+                        ui.checkbox("Checkbox", &mut checked);
+                    },
+                    3 => {
+                        let mut value = fraction;
+                        ui.slider("Slider", 0.0, 1.0, &mut value);
+                    },
+                    4 => ProgressBar::new(fraction).build(ui),
+                    _ => {
+                        let [x, y] = ui.cursor_screen_pos();
+                        draw_list
+                            .add_rect([x, y], [x + 100.0 * fraction, y + 10.0], [
+                                1.0, fraction, 0.0,
+                            ])
+                            .filled(true)
+                            .build();
+                        ui.dummy([100.0, 10.0]);
+                    },
+                }
+
+                if i % 4 != 3 {
+                    ui.same_line();
+                }
+            }
+        });
+}
+
+// Record the time the pipeline took to render the frame, and complete the
+// benchmark once it ran for long enough.
+pub(crate) fn end_frame(render_time: Duration) {
+    let now = Instant::now();
+
+    let mut run = RUN.lock();
+    let Some(r) = run.as_mut() else {
+        return;
+    };
+
+    let started = *r.started.get_or_insert(now);
+    let frame_time = r.last_frame.map_or(Duration::ZERO, |last| now - last);
+    r.last_frame = Some(now);
+    r.frames.push((frame_time, render_time));
+
+    if now - started < r.benchmark.duration {
+        return;
+    }
+
+    if let Some(run) = run.take() {
+        let report = run.report();
+        info!("Benchmark complete: {report}");
+        *LAST_REPORT.lock() = Some(report);
+    }
+}
+
+impl Run {
+    fn report(self) -> BenchmarkReport {
+        let frames = self.frames.len();
+        // The first frame has no previous frame to measure the time from.
+        let frame_time = self.frames.iter().skip(1).map(|&(f, _)| f).sum::<Duration>()
+            / (frames.saturating_sub(1).max(1) as u32);
+
+        let mut render_times: Vec<_> = self.frames.iter().map(|&(_, r)| r).collect();
+        render_times.sort_unstable();
+        let percentile = |p: usize| render_times[(frames - 1) * p / 100];
+
+        BenchmarkReport {
+            benchmark: self.benchmark,
+            version: HUDHOOK_VERSION,
+            frames,
+            frame_time,
+            render_time: render_times.iter().sum::<Duration>() / frames as u32,
+            render_time_p50: percentile(50),
+            render_time_p99: percentile(99),
+            render_time_max: percentile(100),
+        }
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,306 @@
+//! Widgets bound to values of the game.
+//!
+//! Trainers mostly consist of widgets editing a value the game keeps in
+//! memory: read the value, draw a slider with it, check the new value, write
+//! it back if it changed. A [`Bound`] value does all of that. It is bound
+//! either to an address behind a [`PointerChain`], or to a getter and a
+//! setter, and its widgets, e.g. [`Bound::slider`] or [`Bound::checkbox`],
+//! show the current value and edit it.
+//!
+//! Edits are rejected if they don't pass the
+//! [validator](Bound::with_validator), and are otherwise written between
+//! frames, on the render thread, before the render loops run again: the game
+//! never sees a value change while the overlay is reading it, and a value
+//! edited several times in a frame, e.g. while dragging a slider, is written
+//! only once.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::bind::Bound;
+//! use hudhook::watch::PointerChain;
+//! use hudhook::ImguiRenderLoop;
+//!
+//! struct Trainer {
+//!     health: Bound<f32>,
+//!     god_mode: Bound<bool>,
+//! }
+//!
+//! let health = PointerChain::module("game.exe", 0x1a2b3c).offset(0x10).offset(0x48);
+//! let trainer = Trainer {
+//!     health: unsafe { Bound::memory(health) }.with_validator(|health: &f32| {
+//!         if *health > 0.0 {
+//!             Ok(())
+//!         } else {
+//!             Err("Health must be positive".into())
+//!         }
+//!     }),
+//!     god_mode: Bound::new(|| false, |_enabled| { /* Patch the game. */ }),
+//! };
+//!
+//! impl ImguiRenderLoop for Trainer {
+//!     fn render(&mut self, ui: &mut hudhook::imgui::Ui) {
+//!         ui.window("Trainer").build(|| {
+//!             self.health.slider(ui, "Health", 1.0, 100.0);
+//!             self.god_mode.checkbox(ui, "God mode");
+//!         });
+//!     }
+//! }
+//! ```
+use std::mem;
+use std::sync::Arc;
+
+use imgui::internal::DataTypeKind;
+use imgui::Ui;
+use parking_lot::Mutex;
+use tracing::error;
+
+use crate::memory;
+use crate::watch::PointerChain;
+
+// Values edited during the last frame, written by `apply`.
+static QUEUE: Mutex<Vec<Arc<dyn Flush>>> = Mutex::new(Vec::new());
+
+const COLOR_ERROR: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+
+/// A value that can be bound to widgets: a number or a `bool`, stored in the
+/// game's memory as its little endian bytes.
+pub trait Scalar: Copy + PartialEq + Send + 'static {
+    /// Decode the value from `bytes`, unless they aren't a valid value.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+
+    /// Encode the value.
+    fn to_bytes(self) -> Vec<u8>;
+}
+
+macro_rules! impl_scalar {
+    ($($t:ty),*) => {
+        $(
+            impl Scalar for $t {
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    Some(<$t>::from_le_bytes(bytes.try_into().ok()?))
+                }
+
+                fn to_bytes(self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Scalar for bool {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        vec![self as u8]
+    }
+}
+
+type Validator<T> = Box<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+
+enum Source<T> {
+    Memory(PointerChain),
+    Accessors { get: Box<dyn Fn() -> T + Send>, set: Box<dyn FnMut(T) + Send> },
+}
+
+impl<T: Scalar> Source<T> {
+    fn read(&self) -> Option<T> {
+        match self {
+            Source::Memory(chain) => {
+                let bytes = memory::read(chain.resolve()?, mem::size_of::<T>())
+                    .into_iter()
+                    .collect::<Option<Vec<u8>>>()?;
+                T::from_bytes(&bytes)
+            },
+            Source::Accessors { get, .. } => Some(get()),
+        }
+    }
+
+    fn write(&mut self, value: T) -> Result<(), String> {
+        match self {
+            Source::Memory(chain) => {
+                let address = chain.resolve().ok_or_else(|| format!("{chain} is unresolved"))?;
+                unsafe { memory::write(address, &value.to_bytes()) }
+                    .map_err(|e| format!("Couldn't write to {address:#x}: {e}"))
+            },
+            Source::Accessors { set, .. } => {
+                set(value);
+                Ok(())
+            },
+        }
+    }
+}
+
+// The part of a bound value shared with the queue of pending writes.
+struct Shared<T> {
+    source: Source<T>,
+    pending: Option<T>,
+    write_error: Option<String>,
+}
+
+trait Flush: Send + Sync {
+    fn flush(&self);
+}
+
+impl<T: Scalar> Flush for Mutex<Shared<T>> {
+    fn flush(&self) {
+        let mut shared = self.lock();
+        if let Some(value) = shared.pending.take() {
+            shared.write_error = shared.source.write(value).err();
+            if let Some(e) = &shared.write_error {
+                error!("{e}");
+            }
+        }
+    }
+}
+
+/// A value of the game, edited through widgets.
+pub struct Bound<T: Scalar> {
+    shared: Arc<Mutex<Shared<T>>>,
+    validator: Option<Validator<T>>,
+    // The last value the validator rejected, kept in the widget until it is
+    // corrected or left.
+    rejected: Option<(T, String)>,
+}
+
+impl<T: Scalar> Bound<T> {
+    /// Bind to the value `chain` points to.
+    ///
+    /// # Safety
+    ///
+    /// Edits are written to whatever `chain` resolves to at the time, which
+    /// must hold a value of type `T`.
+    pub unsafe fn memory(chain: PointerChain) -> Self {
+        Self::with_source(Source::Memory(chain))
+    }
+
+    /// Bind to the value returned by `get`, edited by calling `set`.
+    pub fn new<G, S>(get: G, set: S) -> Self
+    where
+        G: Fn() -> T + Send + 'static,
+        S: FnMut(T) + Send + 'static,
+    {
+        Self::with_source(Source::Accessors { get: Box::new(get), set: Box::new(set) })
+    }
+
+    fn with_source(source: Source<T>) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Shared { source, pending: None, write_error: None })),
+            validator: None,
+            rejected: None,
+        }
+    }
+
+    /// Reject the edits for which `validator` returns an error, shown below
+    /// the widget.
+    pub fn with_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&T) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validator = Some(Box::new(validator));
+        self
+    }
+
+    /// The current value, or the value about to be written. `None` if the
+    /// value can't be read, e.g. while the pointer chain is unresolved.
+    pub fn get(&self) -> Option<T> {
+        let shared = self.shared.lock();
+        shared.pending.or_else(|| shared.source.read())
+    }
+
+    /// Validate `value` and write it between frames, if it differs from the
+    /// current value.
+    pub fn set(&mut self, value: T) -> Result<(), String> {
+        if let Some(Err(e)) = self.validator.as_ref().map(|validator| validator(&value)) {
+            self.rejected = Some((value, e.clone()));
+            return Err(e);
+        }
+        self.rejected = None;
+
+        if self.get() == Some(value) {
+            return Ok(());
+        }
+
+        let mut shared = self.shared.lock();
+        if shared.pending.replace(value).is_none() {
+            QUEUE.lock().push(Arc::clone(&self.shared) as Arc<dyn Flush>);
+        }
+        Ok(())
+    }
+
+    /// Why the last edit was rejected or couldn't be written, if it was.
+    pub fn error(&self) -> Option<String> {
+        match &self.rejected {
+            Some((_, e)) => Some(e.clone()),
+            None => self.shared.lock().write_error.clone(),
+        }
+    }
+
+    // Draw the value with `widget`, and set the value it is edited to. Returns
+    // whether an edit was accepted.
+    fn edit(&mut self, ui: &Ui, label: &str, widget: impl FnOnce(&mut T) -> bool) -> bool {
+        let Some(mut value) =
+            self.rejected.as_ref().map(|&(value, _)| value).or_else(|| self.get())
+        else {
+            let name = label.split("##").next().unwrap_or_default();
+            ui.text_colored(COLOR_ERROR, format!("{name}: ??"));
+            return false;
+        };
+
+        let changed = widget(&mut value);
+        let accepted = changed && self.set(value).is_ok();
+        if !changed && ui.is_item_deactivated() {
+            self.rejected = None;
+        }
+
+        if let Some(e) = self.error() {
+            ui.text_colored(COLOR_ERROR, e);
+        }
+        accepted
+    }
+}
+
+impl<T: Scalar + DataTypeKind> Bound<T> {
+    /// Edit the value with a slider between `min` and `max`. Returns whether
+    /// an edit was accepted.
+    pub fn slider(&mut self, ui: &Ui, label: impl AsRef<str>, min: T, max: T) -> bool {
+        let label = label.as_ref();
+        self.edit(ui, label, |value| ui.slider(label, min, max, value))
+    }
+
+    /// Edit the value with an input field. Returns whether an edit was
+    /// accepted.
+    pub fn input(&mut self, ui: &Ui, label: impl AsRef<str>) -> bool {
+        let label = label.as_ref();
+        self.edit(ui, label, |value| ui.input_scalar(label, value).build())
+    }
+}
+
+impl Bound<bool> {
+    /// Edit the value with a checkbox. Returns whether an edit was accepted.
+    pub fn checkbox(&mut self, ui: &Ui, label: impl AsRef<str>) -> bool {
+        let label = label.as_ref();
+        self.edit(ui, label, |value| ui.checkbox(label, value))
+    }
+}
+
+// Write the values edited since the last call. Called between frames.
+pub(crate) fn apply() {
+    let queue = mem::take(&mut *QUEUE.lock());
+    for shared in queue {
+        shared.flush();
+    }
+}
+
+// Drop the pending writes when the hooks are removed.
+pub(crate) fn clear() {
+    QUEUE.lock().clear();
+}
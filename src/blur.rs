@@ -0,0 +1,76 @@
+//! Blurred backgrounds behind overlay windows.
+//!
+//! Translucent windows are hard to read over busy game scenes. Calling
+//! [`blur_window_background`] within a window blurs the game behind it,
+//! like the acrylic material of Windows, so that the window background can
+//! stay translucent while its contents remain legible.
+//!
+//! The game is sampled from the back buffer every frame, before any overlay
+//! window is drawn, so overlapping windows don't blur each other. Window
+//! rounding is ignored: the blurred area is the window's rectangle.
+//!
+//! Only the DirectX 11 backend supports blurring for now. The other backends
+//! draw the windows as usual.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::blur;
+//!
+//! // In `ImguiRenderLoop::render`:
+//! ui.window("Stats").bg_alpha(0.5).build(|| {
+//!     blur::blur_window_background(ui, 8.0);
+//!     ui.text("Readable over any scene");
+//! });
+//! ```
+use std::ffi::CStr;
+
+use imgui::Ui;
+use parking_lot::Mutex;
+
+use crate::names;
+
+static PENDING: Mutex<Option<Blur>> = Mutex::new(None);
+
+// A request to blur a rectangle of the back buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Blur {
+    // Left, top, right and bottom edges, in display coordinates.
+    pub(crate) rect: [f32; 4],
+    // Standard deviation of the blur, in pixels.
+    pub(crate) radius: f32,
+}
+
+/// Blur the game behind the current window by `radius` pixels. Call it
+/// within the window, every frame the background should be blurred.
+///
+/// Only the DirectX 11 backend blurs. On the other backends this does
+/// nothing, and the window background is drawn as usual.
+pub fn blur_window_background(ui: &Ui, radius: f32) {
+    if !is_supported() {
+        return;
+    }
+
+    let [x, y] = ui.window_pos();
+    let [w, h] = ui.window_size();
+    let blur = Blur { rect: [x, y, x + w, y + h], radius: radius.max(0.0) };
+
+    // The background draw list is rendered before every window.
+    ui.get_background_draw_list().add_callback(move || request(blur)).build();
+}
+
+// Whether the render engine of the current `imgui` context blurs. The engines
+// report their name to `imgui` when they are created.
+fn is_supported() -> bool {
+    let name = unsafe { (*imgui::sys::igGetIO()).BackendRendererName };
+    !name.is_null()
+        && unsafe { CStr::from_ptr(name) }.to_str() == Ok(names::renderer("dx11").as_str())
+}
+
+fn request(blur: Blur) {
+    *PENDING.lock() = Some(blur);
+}
+
+// Take the blur requested by the callback that just ran, if any.
+pub(crate) fn take_pending() -> Option<Blur> {
+    PENDING.lock().take()
+}
@@ -0,0 +1,361 @@
+//! Building blocks for submitting DirectX 12 work alongside a game's frames.
+//!
+//! A renderer that doesn't stall the game keeps several frames in flight, and
+//! must not touch the resources of a frame before the GPU is done with it.
+//! These are the types the [DirectX 12 render engine](crate::renderer) is
+//! built on:
+//!
+//! - [`Fence`] numbers the submissions to a queue, and waits for the GPU to
+//!   complete them.
+//! - [`FrameRing`] hands out one [`FrameContext`] per frame in flight, e.g. a
+//!   command allocator and upload buffers, in turn, once the GPU is done with
+//!   their previous submission.
+//! - [`RetirementQueue`] keeps objects released while frames are in flight
+//!   alive until the GPU is done with the last submission using them.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::compose::{Fence, FrameRing, RetirementQueue};
+//! use hudhook::windows::core::Result;
+//! use hudhook::windows::Win32::Graphics::Direct3D12::*;
+//!
+//! # fn f(device: ID3D12Device, command_queue: ID3D12CommandQueue) -> Result<()> {
+//! let fence = Fence::new(&device)?;
+//! let mut frames = FrameRing::new(
+//!     (0..2)
+//!         .map(|_| unsafe { device.CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_DIRECT) })
+//!         .collect::<Result<Vec<ID3D12CommandAllocator>>>()?,
+//! );
+//! let mut retired = RetirementQueue::default();
+//!
+//! // Every frame:
+//! retired.collect(fence.completed_value());
+//! let command_allocator = frames.acquire(&fence)?;
+//! unsafe { command_allocator.Reset() }?;
+//! // ... record and execute command lists, and retire the objects they no
+//! // longer need with `retired.retire(fence.value(), object)` ...
+//! unsafe { command_queue.Signal(fence.fence(), fence.value()) }?;
+//! frames.submit(fence.value());
+//! fence.incr();
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::error;
+use windows::core::{Interface, Result};
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Graphics::Direct3D12::{
+    ID3D12Device, ID3D12Fence, ID3D12Pageable, D3D12_FENCE_FLAG_NONE,
+};
+use windows::Win32::System::Threading::{CreateEventExW, WaitForSingleObjectEx, CREATE_EVENT};
+
+/// Wrapper around [`windows::Win32::Graphics::Direct3D12::ID3D12Fence`].
+pub struct Fence {
+    fence: ID3D12Fence,
+    value: AtomicU64,
+    event: HANDLE,
+}
+
+impl Fence {
+    /// Construct the fence.
+    pub fn new(device: &ID3D12Device) -> Result<Self> {
+        let fence = unsafe { device.CreateFence(0, D3D12_FENCE_FLAG_NONE) }?;
+        let value = AtomicU64::new(0);
+        let event = unsafe { CreateEventExW(None, None, CREATE_EVENT(0), 0x1f0003) }?;
+
+        Ok(Fence { fence, value, event })
+    }
+
+    /// Retrieve the underlying fence object to pass to the D3D12 APIs.
+    pub fn fence(&self) -> &ID3D12Fence {
+        &self.fence
+    }
+
+    /// Retrieve the current fence value.
+    pub fn value(&self) -> u64 {
+        self.value.load(Ordering::SeqCst)
+    }
+
+    /// Retrieve the last value the fence was signaled with by the GPU.
+    pub fn completed_value(&self) -> u64 {
+        unsafe { self.fence.GetCompletedValue() }
+    }
+
+    /// Atomically increase the fence value.
+    pub fn incr(&self) {
+        self.value.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Wait for completion of the fence.
+    pub fn wait(&self) -> Result<()> {
+        self.wait_for(self.value())
+    }
+
+    /// Wait for the fence to reach `value`.
+    pub fn wait_for(&self, value: u64) -> Result<()> {
+        unsafe {
+            if self.fence.GetCompletedValue() < value {
+                self.fence.SetEventOnCompletion(value, self.event)?;
+                WaitForSingleObjectEx(self.event, u32::MAX, false);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { CloseHandle(self.event) } {
+            error!("Could not close the fence event: {e:?}");
+        }
+    }
+}
+
+/// The resources of one frame in flight, and the fence value signaled once
+/// the GPU is done with their last submission.
+#[derive(Debug)]
+pub struct FrameContext<T> {
+    /// Resources recorded into the frame's submission.
+    pub resources: T,
+    fence_value: u64,
+}
+
+impl<T> FrameContext<T> {
+    /// Value the fence is signaled with once the GPU is done with the
+    /// context's last submission, `0` if it was never submitted.
+    pub fn fence_value(&self) -> u64 {
+        self.fence_value
+    }
+}
+
+/// The [`FrameContext`]s of the frames that may be in flight, used in turn.
+///
+/// The fence value `0` marks contexts that were never submitted: signal the
+/// fence from `1` on.
+#[derive(Debug)]
+pub struct FrameRing<T> {
+    frames: Vec<FrameContext<T>>,
+    index: usize,
+}
+
+impl<T> FrameRing<T> {
+    /// Create a ring of one context per frame in flight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resources` is empty.
+    pub fn new(resources: Vec<T>) -> Self {
+        assert!(!resources.is_empty(), "at least one frame must be in flight");
+        let frames =
+            resources.into_iter().map(|resources| FrameContext { resources, fence_value: 0 });
+        Self { frames: frames.collect(), index: 0 }
+    }
+
+    /// Number of frames that may be in flight.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Always `false`: a ring has at least one context.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Index of the current context.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The context of the frame being recorded.
+    pub fn current(&self) -> &FrameContext<T> {
+        &self.frames[self.index]
+    }
+
+    /// The resources of the frame being recorded.
+    pub fn resources(&mut self) -> &mut T {
+        &mut self.frames[self.index].resources
+    }
+
+    /// Wait for the GPU to be done with the current context's last
+    /// submission, and return its resources for recording.
+    pub fn acquire(&mut self, fence: &Fence) -> Result<&mut T> {
+        fence.wait_for(self.current().fence_value)?;
+        Ok(self.resources())
+    }
+
+    /// Record that the current context was submitted, with `fence_value`
+    /// signaled once the GPU is done with it, and move on to the next one.
+    pub fn submit(&mut self, fence_value: u64) {
+        self.frames[self.index].fence_value = fence_value;
+        self.index = (self.index + 1) % self.frames.len();
+    }
+
+    /// All contexts, starting with the first one.
+    pub fn iter(&self) -> impl Iterator<Item = &FrameContext<T>> {
+        self.frames.iter()
+    }
+}
+
+/// Objects released while frames are in flight, kept alive until the GPU is
+/// done with them. Each is keyed by the value the fence is signaled with after
+/// the last submission that may reference it.
+#[derive(Debug)]
+pub struct RetirementQueue<T>(VecDeque<(u64, T)>);
+
+impl<T> Default for RetirementQueue<T> {
+    fn default() -> Self {
+        Self(VecDeque::new())
+    }
+}
+
+impl<T> RetirementQueue<T> {
+    /// Keep `object` alive until the fence reaches `fence_value`.
+    pub fn retire(&mut self, fence_value: u64, object: T) {
+        // Fence values only grow, unless the queue is fed out of order: keep
+        // the queue sorted so that `collect` can stop at the first live object.
+        let index = self.0.partition_point(|(value, _)| *value <= fence_value);
+        self.0.insert(index, (fence_value, object));
+    }
+
+    /// Release the objects the GPU is done with, now that the fence reached
+    /// `completed_value`.
+    pub fn collect(&mut self, completed_value: u64) {
+        while self.0.front().is_some_and(|(fence_value, _)| *fence_value <= completed_value) {
+            self.0.pop_front();
+        }
+    }
+
+    /// Number of objects kept alive.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no object is kept alive.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl RetirementQueue<ID3D12Pageable> {
+    /// Keep a GPU object alive until the fence reaches `fence_value`.
+    pub fn retire_object<I: Interface>(&mut self, object: &I, fence_value: u64) {
+        match object.cast() {
+            Ok(object) => self.retire(fence_value, object),
+            Err(e) => error!("Could not retire GPU object: {e:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use windows::Win32::Graphics::Direct3D::D3D_FEATURE_LEVEL_11_0;
+    use windows::Win32::Graphics::Direct3D12::D3D12CreateDevice;
+    use windows::Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIAdapter, IDXGIFactory4};
+
+    use super::*;
+    use crate::util;
+
+    fn warp_device() -> ID3D12Device {
+        let factory: IDXGIFactory4 = unsafe { CreateDXGIFactory1() }.unwrap();
+        let adapter: IDXGIAdapter = unsafe { factory.EnumWarpAdapter() }.unwrap();
+        util::try_out_ptr(|v| unsafe { D3D12CreateDevice(&adapter, D3D_FEATURE_LEVEL_11_0, v) })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fence() {
+        let fence = Fence::new(&warp_device()).unwrap();
+        assert_eq!(fence.value(), 0);
+        assert_eq!(fence.completed_value(), 0);
+
+        fence.incr();
+        assert_eq!(fence.value(), 1);
+        unsafe { fence.fence().Signal(1) }.unwrap();
+        assert_eq!(fence.completed_value(), 1);
+
+        // Reached values don't block.
+        fence.wait().unwrap();
+        fence.wait_for(0).unwrap();
+    }
+
+    #[test]
+    fn test_frame_ring() {
+        let mut frames = FrameRing::new(vec!['a', 'b', 'c']);
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|frame| frame.fence_value() == 0));
+
+        for (fence_value, resources) in (1..=4).zip(['a', 'b', 'c', 'a']) {
+            assert_eq!(*frames.resources(), resources);
+            frames.submit(fence_value);
+        }
+
+        assert_eq!(frames.index(), 1);
+        assert_eq!(frames.current().fence_value(), 2);
+        let fence_values: Vec<_> = frames.iter().map(FrameContext::fence_value).collect();
+        assert_eq!(fence_values, [4, 2, 3]);
+    }
+
+    #[test]
+    fn test_frame_ring_acquire() {
+        let fence = Fence::new(&warp_device()).unwrap();
+        fence.incr();
+
+        let mut frames = FrameRing::new(vec![0u32; 2]);
+        *frames.acquire(&fence).unwrap() += 1;
+        frames.submit(fence.value());
+        fence.incr();
+        *frames.acquire(&fence).unwrap() += 1;
+        frames.submit(fence.value());
+        fence.incr();
+
+        // The first context's submission completes: it can be reused.
+        unsafe { fence.fence().Signal(1) }.unwrap();
+        assert_eq!(*frames.acquire(&fence).unwrap(), 1);
+        assert_eq!(frames.current().fence_value(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_frame_ring_empty() {
+        FrameRing::<()>::new(Vec::new());
+    }
+
+    #[test]
+    fn test_retirement_queue() {
+        let object = Rc::new(());
+        let mut retired = RetirementQueue::default();
+        retired.retire(2, Rc::clone(&object));
+        retired.retire(3, Rc::clone(&object));
+        retired.retire(1, Rc::clone(&object));
+        assert_eq!(retired.len(), 3);
+        assert_eq!(Rc::strong_count(&object), 4);
+
+        retired.collect(0);
+        assert_eq!(retired.len(), 3);
+        retired.collect(2);
+        assert_eq!(retired.len(), 1);
+        assert_eq!(Rc::strong_count(&object), 2);
+        retired.collect(u64::MAX);
+        assert!(retired.is_empty());
+        assert_eq!(Rc::strong_count(&object), 1);
+    }
+
+    #[test]
+    fn test_retire_object() {
+        let fence = Fence::new(&warp_device()).unwrap();
+        let mut retired = RetirementQueue::default();
+        retired.retire_object(fence.fence(), 1);
+        assert_eq!(retired.len(), 1);
+
+        retired.collect(fence.completed_value());
+        assert_eq!(retired.len(), 1);
+        unsafe { fence.fence().Signal(1) }.unwrap();
+        retired.collect(fence.completed_value());
+        assert!(retired.is_empty());
+    }
+}
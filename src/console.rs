@@ -0,0 +1,169 @@
+//! The game's own console and debug output, captured for the overlay.
+//!
+//! With [`ConsoleHooks`](crate::hooks::console::ConsoleHooks) applied, the
+//! lines the game writes to its console or through `OutputDebugString` are
+//! kept here, up to a [capacity](set_capacity). [`LogViewer`] displays them in
+//! the overlay, with a filter, and lets the user copy them; [`lines`] returns
+//! them for mods displaying them their own way. Logs the mod itself writes to
+//! the console, e.g. through a `tracing` subscriber, are captured as well.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::console::LogViewer;
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut log_viewer = LogViewer::default();
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // ui.window("Game output").build(|| log_viewer.build(ui));
+//! ```
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use imgui::Ui;
+use parking_lot::Mutex;
+
+static LINES: Mutex<VecDeque<Line>> = Mutex::new(VecDeque::new());
+static CAPACITY: AtomicUsize = AtomicUsize::new(1000);
+// Console output not terminated by a newline yet.
+static PARTIAL_LINE: Mutex<String> = Mutex::new(String::new());
+
+/// Where a captured line was written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The console, with `WriteConsole`.
+    Console,
+    /// The debugger, with `OutputDebugString`.
+    DebugOutput,
+}
+
+/// A line of output captured from the game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    /// Where the line was written to.
+    pub source: Source,
+    /// The line, without its line terminator.
+    pub text: String,
+}
+
+/// The captured lines, oldest first.
+pub fn lines() -> Vec<Line> {
+    LINES.lock().iter().cloned().collect()
+}
+
+/// Forget the captured lines.
+pub fn clear() {
+    LINES.lock().clear();
+}
+
+/// Keep at most `capacity` lines, dropping the oldest ones first. Defaults to
+/// 1000.
+pub fn set_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+    truncate(&mut LINES.lock());
+}
+
+// Keep the lines within capacity.
+fn truncate(lines: &mut VecDeque<Line>) {
+    let capacity = CAPACITY.load(Ordering::Relaxed);
+    if lines.len() > capacity {
+        lines.drain(..lines.len() - capacity);
+    }
+}
+
+// Add the output the game wrote to `source`. Console output is split into
+// lines as the game writes them, while every debug string is a line of its own,
+// as debuggers display them.
+pub(crate) fn capture(source: Source, text: &str) {
+    let text = match source {
+        Source::Console => {
+            let mut partial_line = PARTIAL_LINE.lock();
+            partial_line.push_str(text);
+            let Some(end) = partial_line.rfind('\n') else {
+                return;
+            };
+            partial_line.drain(..=end).collect::<String>()
+        },
+        Source::DebugOutput => text.to_string(),
+    };
+
+    let mut lines = LINES.lock();
+    lines.extend(
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Line { source, text: line.trim_end().to_string() }),
+    );
+    truncate(&mut lines);
+}
+
+/// A window content displaying the captured lines.
+#[derive(Debug, Clone)]
+pub struct LogViewer {
+    /// Only show the lines containing this text, case insensitive.
+    pub filter: String,
+    /// Show the console output.
+    pub show_console: bool,
+    /// Show the debug output.
+    pub show_debug_output: bool,
+    /// Keep the view scrolled to the newest line while it is at the bottom.
+    pub auto_scroll: bool,
+}
+
+impl Default for LogViewer {
+    fn default() -> Self {
+        Self {
+            filter: String::new(),
+            show_console: true,
+            show_debug_output: true,
+            auto_scroll: true,
+        }
+    }
+}
+
+impl LogViewer {
+    /// Draw the captured lines, and the controls filtering them, within the
+    /// current window.
+    pub fn build(&mut self, ui: &Ui) {
+        ui.checkbox("Console", &mut self.show_console);
+        ui.same_line();
+        ui.checkbox("Debug output", &mut self.show_debug_output);
+        ui.same_line();
+        ui.checkbox("Auto-scroll", &mut self.auto_scroll);
+
+        ui.input_text("Filter", &mut self.filter).build();
+
+        let filter = self.filter.to_lowercase();
+        let lines: Vec<Line> = LINES
+            .lock()
+            .iter()
+            .filter(|line| match line.source {
+                Source::Console => self.show_console,
+                Source::DebugOutput => self.show_debug_output,
+            })
+            .filter(|line| filter.is_empty() || line.text.to_lowercase().contains(&filter))
+            .cloned()
+            .collect();
+
+        if ui.button("Copy") {
+            let text = lines.iter().map(|line| line.text.as_str()).collect::<Vec<_>>().join("\n");
+            ui.set_clipboard_text(text);
+        }
+        ui.same_line();
+        if ui.button("Clear") {
+            clear();
+        }
+
+        ui.child_window("##lines").horizontal_scrollbar(true).build(|| {
+            for line in &lines {
+                match line.source {
+                    Source::Console => ui.text(&line.text),
+                    Source::DebugOutput => ui.text_colored([0.6, 0.8, 1.0, 1.0], &line.text),
+                }
+            }
+
+            if self.auto_scroll && ui.scroll_y() >= ui.scroll_max_y() {
+                ui.set_scroll_here_y_with_ratio(1.0);
+            }
+        });
+    }
+}
@@ -0,0 +1,292 @@
+//! Crosshairs drawn over the game.
+//!
+//! A [`Crosshair`] is drawn in the background draw list, below every overlay
+//! window, at the center of the screen. Its edges are snapped to whole
+//! pixels, so that it stays sharp whatever its size and offset.
+//!
+//! [`Crosshairs`] holds named profiles, e.g. one per weapon, and draws the
+//! active one. With the `state` feature, crosshairs and profiles implement
+//! `Serialize` and `Deserialize`, so they can be kept in the state of a
+//! [`Persistent`](crate::state::Persistent) render loop.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::crosshair::{Crosshair, Crosshairs, Shape};
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut crosshairs = Crosshairs::default();
+//! crosshairs.insert("Sniper", Crosshair { shape: Shape::Dot, ..Default::default() });
+//! crosshairs.select("Sniper");
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // crosshairs.draw(ui);
+//! // ui.window("Crosshair").build(|| crosshairs.editor(ui));
+//! ```
+use std::collections::BTreeMap;
+
+use imgui::{DrawListMut, Ui};
+#[cfg(feature = "state")]
+use serde::{Deserialize, Serialize};
+
+/// Shape of a [`Crosshair`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "state", derive(Serialize, Deserialize))]
+pub enum Shape {
+    /// Four arms around the center.
+    #[default]
+    Cross,
+    /// Four arms around a center dot.
+    CrossDot,
+    /// Three arms, without the top one.
+    T,
+    /// A single dot.
+    Dot,
+    /// A ring.
+    Circle,
+}
+
+impl Shape {
+    const ALL: [Shape; 5] = [Shape::Cross, Shape::CrossDot, Shape::T, Shape::Dot, Shape::Circle];
+
+    fn name(self) -> &'static str {
+        match self {
+            Shape::Cross => "Cross",
+            Shape::CrossDot => "Cross with dot",
+            Shape::T => "T",
+            Shape::Dot => "Dot",
+            Shape::Circle => "Circle",
+        }
+    }
+}
+
+/// A crosshair at the center of the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "state", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "state", serde(default))]
+pub struct Crosshair {
+    /// Shape.
+    pub shape: Shape,
+    /// RGBA color.
+    pub color: [f32; 4],
+    /// Length of the arms, or radius of the ring, in pixels.
+    pub size: f32,
+    /// Width of the lines, and of the dot, in pixels.
+    pub thickness: f32,
+    /// Distance between the center and the arms, in pixels.
+    pub gap: f32,
+    /// Offset from the center of the screen, in pixels.
+    pub offset: [f32; 2],
+    /// Width of the black outline around the lines, in pixels. `0` draws no
+    /// outline.
+    pub outline: f32,
+}
+
+impl Default for Crosshair {
+    fn default() -> Self {
+        Self {
+            shape: Shape::Cross,
+            color: [0.0, 1.0, 0.0, 1.0],
+            size: 8.0,
+            thickness: 2.0,
+            gap: 3.0,
+            offset: [0.0, 0.0],
+            outline: 1.0,
+        }
+    }
+}
+
+impl Crosshair {
+    /// Draw the crosshair in the background draw list. Call it every frame
+    /// the crosshair should be shown.
+    pub fn draw(&self, ui: &Ui) {
+        let io = ui.io();
+        let [scale_x, scale_y] = io.display_framebuffer_scale;
+
+        // Work in framebuffer pixels, where snapping is meaningful, and
+        // convert back to display coordinates when drawing.
+        let [width, height] = io.display_size;
+        let cx = (width * scale_x / 2.0 + self.offset[0] * scale_x).floor();
+        let cy = (height * scale_y / 2.0 + self.offset[1] * scale_y).floor();
+        let thickness = self.thickness.round().max(1.0);
+        let size = self.size.round().max(0.0);
+        let gap = self.gap.round().max(0.0);
+        let outline = self.outline.round().max(0.0);
+
+        // Lines of odd thickness are centered on the pixel at the center, lines
+        // of even thickness on its top left corner.
+        let low = (thickness / 2.0).floor();
+        let high = thickness - low;
+        let mut rects = Vec::new();
+
+        if matches!(self.shape, Shape::Cross | Shape::CrossDot | Shape::T) && size > 0.0 {
+            let near = gap + high;
+            let far = near + size;
+            // Left, right, bottom, top.
+            rects.push([cx - gap - low - size, cy - low, cx - gap - low, cy + high]);
+            rects.push([cx + near, cy - low, cx + far, cy + high]);
+            rects.push([cx - low, cy + near, cx + high, cy + far]);
+            if self.shape != Shape::T {
+                rects.push([cx - low, cy - gap - low - size, cx + high, cy - gap - low]);
+            }
+        }
+        if matches!(self.shape, Shape::CrossDot | Shape::Dot) {
+            rects.push([cx - low, cy - low, cx + high, cy + high]);
+        }
+
+        let to_display = |[x0, y0, x1, y1]: [f32; 4]| {
+            ([x0 / scale_x, y0 / scale_y], [x1 / scale_x, y1 / scale_y])
+        };
+        let outline_color = [0.0, 0.0, 0.0, self.color[3]];
+        let draw_list = ui.get_background_draw_list();
+
+        if self.shape == Shape::Circle {
+            let center = [(cx + 0.5) / scale_x, (cy + 0.5) / scale_y];
+            let radius = size.max(1.0) / scale_x;
+            if outline > 0.0 {
+                draw_list
+                    .add_circle(center, radius, outline_color)
+                    .thickness((thickness + 2.0 * outline) / scale_x)
+                    .num_segments(64)
+                    .build();
+            }
+            draw_list
+                .add_circle(center, radius, self.color)
+                .thickness(thickness / scale_x)
+                .num_segments(64)
+                .build();
+            return;
+        }
+
+        // Outlines go first, so that they don't cover the neighboring lines.
+        if outline > 0.0 {
+            for [x0, y0, x1, y1] in &rects {
+                let (min, max) =
+                    to_display([x0 - outline, y0 - outline, x1 + outline, y1 + outline]);
+                fill(&draw_list, min, max, outline_color);
+            }
+        }
+        for rect in rects {
+            let (min, max) = to_display(rect);
+            fill(&draw_list, min, max, self.color);
+        }
+    }
+
+    /// Draw widgets editing the crosshair into the current window. Returns
+    /// whether it changed.
+    pub fn editor(&mut self, ui: &Ui) -> bool {
+        let mut changed = false;
+
+        let mut shape = Shape::ALL.iter().position(|&s| s == self.shape).unwrap_or(0);
+        if ui.combo("Shape", &mut shape, &Shape::ALL, |s| s.name().into()) {
+            self.shape = Shape::ALL[shape];
+            changed = true;
+        }
+        changed |= ui.color_edit4("Color", &mut self.color);
+        changed |= ui.slider("Size", 0.0, 64.0, &mut self.size);
+        changed |= ui.slider("Thickness", 1.0, 16.0, &mut self.thickness);
+        changed |= ui.slider("Gap", 0.0, 32.0, &mut self.gap);
+        changed |= ui.slider("Outline", 0.0, 4.0, &mut self.outline);
+        changed |= ui.input_float2("Offset", &mut self.offset).build();
+
+        changed
+    }
+}
+
+fn fill(draw_list: &DrawListMut<'_>, min: [f32; 2], max: [f32; 2], color: [f32; 4]) {
+    draw_list.add_rect(min, max, color).filled(true).build();
+}
+
+/// Named crosshair profiles, one of which is active.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "state", serde(default))]
+pub struct Crosshairs {
+    /// Profiles by name.
+    pub profiles: BTreeMap<String, Crosshair>,
+    /// Name of the active profile. `None`, or the name of a missing profile,
+    /// draws no crosshair.
+    pub active: Option<String>,
+    // Name typed in the editor for a new profile.
+    #[cfg_attr(feature = "state", serde(skip))]
+    new_name: String,
+}
+
+impl Crosshairs {
+    /// Add the profile `name`, replacing the one with the same name, if any.
+    pub fn insert(&mut self, name: &str, crosshair: Crosshair) {
+        self.profiles.insert(name.to_string(), crosshair);
+    }
+
+    /// Remove the profile `name`, deactivating it if it was active.
+    pub fn remove(&mut self, name: &str) -> Option<Crosshair> {
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        self.profiles.remove(name)
+    }
+
+    /// Activate the profile `name`, e.g. when the player switches weapons.
+    /// Returns whether it exists.
+    pub fn select(&mut self, name: &str) -> bool {
+        let exists = self.profiles.contains_key(name);
+        if exists {
+            self.active = Some(name.to_string());
+        }
+        exists
+    }
+
+    /// Hide the crosshair until a profile is selected.
+    pub fn deselect(&mut self) {
+        self.active = None;
+    }
+
+    /// The active profile.
+    pub fn active(&self) -> Option<&Crosshair> {
+        self.profiles.get(self.active.as_deref()?)
+    }
+
+    /// Draw the active profile in the background draw list. Call it every
+    /// frame.
+    pub fn draw(&self, ui: &Ui) {
+        if let Some(crosshair) = self.active() {
+            crosshair.draw(ui);
+        }
+    }
+
+    /// Draw widgets selecting, adding, removing and editing the profiles into
+    /// the current window.
+    pub fn editor(&mut self, ui: &Ui) {
+        let preview = self.active.as_deref().unwrap_or("None");
+        if let Some(_combo) = ui.begin_combo("Profile", preview) {
+            if ui.selectable_config("None").selected(self.active.is_none()).build() {
+                self.active = None;
+            }
+            for name in self.profiles.keys() {
+                let selected = self.active.as_deref() == Some(name.as_str());
+                if ui.selectable_config(name).selected(selected).build() {
+                    self.active = Some(name.clone());
+                }
+            }
+        }
+
+        ui.input_text("##hudhook_crosshair_name", &mut self.new_name).hint("Name").build();
+        ui.same_line();
+        if ui.button("Add") && !self.new_name.is_empty() {
+            let name = std::mem::take(&mut self.new_name);
+            let crosshair = self.active().copied().unwrap_or_default();
+            self.insert(&name, crosshair);
+            self.select(&name);
+        }
+        if let Some(active) = self.active.clone() {
+            ui.same_line();
+            if ui.button("Remove") {
+                self.remove(&active);
+            }
+        }
+
+        if let Some(crosshair) = self.active.as_ref().and_then(|name| self.profiles.get_mut(name)) {
+            ui.separator();
+            crosshair.editor(ui);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Inspecting the modules loaded by the game.
+//!
+//! Memory tools built next to the overlay need the base address of the game's
+//! modules, and the address of the functions they export, to find what they
+//! read or hook. [`modules`] lists the modules loaded in the process, and
+//! [`exports`] reads the export table of one of them. [`ModuleInspector`]
+//! displays both in the overlay, loading the exports of a module on demand,
+//! with buttons copying addresses to the clipboard.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::debug::{self, ModuleInspector};
+//!
+//! let game = debug::modules().unwrap().into_iter().next().unwrap();
+//! println!("{} is loaded at {:#x}", game.name, game.base);
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut inspector = ModuleInspector::default();
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // ui.window("Modules").build(|| inspector.build(ui));
+//! ```
+use std::ffi::CStr;
+use std::{mem, slice};
+
+#[cfg(feature = "renderer")]
+use imgui::Ui;
+#[cfg(feature = "renderer")]
+use tracing::error;
+use windows::core::{Result, PCWSTR};
+use windows::Win32::Foundation::{CloseHandle, FreeLibrary, HMODULE};
+use windows::Win32::System::Diagnostics::Debug::IMAGE_DIRECTORY_ENTRY_EXPORT;
+#[cfg(target_pointer_width = "32")]
+use windows::Win32::System::Diagnostics::Debug::IMAGE_NT_HEADERS32 as IMAGE_NT_HEADERS;
+#[cfg(target_pointer_width = "64")]
+use windows::Win32::System::Diagnostics::Debug::IMAGE_NT_HEADERS64 as IMAGE_NT_HEADERS;
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, MODULEENTRY32W, TH32CS_SNAPMODULE,
+    TH32CS_SNAPMODULE32,
+};
+use windows::Win32::System::LibraryLoader::{
+    GetModuleHandleExW, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+};
+use windows::Win32::System::SystemServices::{
+    IMAGE_DOS_HEADER, IMAGE_DOS_SIGNATURE, IMAGE_EXPORT_DIRECTORY,
+};
+use windows::Win32::System::Threading::GetCurrentProcessId;
+
+/// A module loaded in the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleInfo {
+    /// File name of the module, e.g. `kernel32.dll`.
+    pub name: String,
+    /// Full path of the module.
+    pub path: String,
+    /// Address the module is loaded at.
+    pub base: usize,
+    /// Size of the module's image in memory, in bytes.
+    pub size: usize,
+}
+
+/// A function or variable exported by a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    /// Name of the export, unless it is only exported by ordinal.
+    pub name: Option<String>,
+    /// Ordinal of the export.
+    pub ordinal: u32,
+    /// Address of the export.
+    pub address: usize,
+    /// The `module.function` the export forwards to, if it is forwarded.
+    /// `address` then points to that string.
+    pub forwarded_to: Option<String>,
+}
+
+/// The modules loaded in the process, the executable first.
+pub fn modules() -> Result<Vec<ModuleInfo>> {
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(
+            TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32,
+            GetCurrentProcessId(),
+        )?;
+        let mut entry = MODULEENTRY32W {
+            dwSize: mem::size_of::<MODULEENTRY32W>() as u32,
+            ..Default::default()
+        };
+
+        let mut modules = Vec::new();
+        let mut res = Module32FirstW(snapshot, &mut entry);
+        while res.is_ok() {
+            modules.push(ModuleInfo {
+                name: from_wide(&entry.szModule),
+                path: from_wide(&entry.szExePath),
+                base: entry.modBaseAddr as usize,
+                size: entry.modBaseSize as usize,
+            });
+            res = Module32NextW(snapshot, &mut entry);
+        }
+
+        CloseHandle(snapshot)?;
+
+        Ok(modules)
+    }
+}
+
+/// The exports of `module`, ordered by ordinal. Fails if the module was
+/// unloaded since it was listed.
+pub fn exports(module: &ModuleInfo) -> Result<Vec<Export>> {
+    unsafe {
+        // Hold a reference to the module, so it can't be unloaded while its
+        // export table is read.
+        let mut hmodule = HMODULE::default();
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            PCWSTR(module.base as *const u16),
+            &mut hmodule,
+        )?;
+
+        let exports = read_exports(hmodule.0 as usize);
+        FreeLibrary(hmodule)?;
+
+        Ok(exports)
+    }
+}
+
+// Read the export table of the module loaded at `base`.
+unsafe fn read_exports(base: usize) -> Vec<Export> {
+    let dos_header = &*(base as *const IMAGE_DOS_HEADER);
+    if dos_header.e_magic != IMAGE_DOS_SIGNATURE {
+        return Vec::new();
+    }
+
+    let nt_headers = &*((base + dos_header.e_lfanew as usize) as *const IMAGE_NT_HEADERS);
+    let directory =
+        nt_headers.OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_EXPORT.0 as usize];
+    if directory.VirtualAddress == 0 {
+        return Vec::new();
+    }
+
+    let export_directory =
+        &*((base + directory.VirtualAddress as usize) as *const IMAGE_EXPORT_DIRECTORY);
+    let functions = slice::from_raw_parts(
+        (base + export_directory.AddressOfFunctions as usize) as *const u32,
+        export_directory.NumberOfFunctions as usize,
+    );
+    let names = slice::from_raw_parts(
+        (base + export_directory.AddressOfNames as usize) as *const u32,
+        export_directory.NumberOfNames as usize,
+    );
+    let name_ordinals = slice::from_raw_parts(
+        (base + export_directory.AddressOfNameOrdinals as usize) as *const u16,
+        export_directory.NumberOfNames as usize,
+    );
+
+    let string_at =
+        |rva: u32| CStr::from_ptr((base + rva as usize) as *const _).to_string_lossy().into_owned();
+
+    let mut function_names = vec![None; functions.len()];
+    for (&name, &index) in names.iter().zip(name_ordinals) {
+        if let Some(function_name) = function_names.get_mut(index as usize) {
+            *function_name = Some(string_at(name));
+        }
+    }
+
+    // Forwarded exports point to a string within the export directory.
+    let directory_range = directory.VirtualAddress..directory.VirtualAddress + directory.Size;
+
+    functions
+        .iter()
+        .zip(function_names)
+        .enumerate()
+        .filter(|(_, (rva, _))| **rva != 0)
+        .map(|(index, (&rva, name))| Export {
+            name,
+            ordinal: export_directory.Base + index as u32,
+            address: base + rva as usize,
+            forwarded_to: directory_range.contains(&rva).then(|| string_at(rva)),
+        })
+        .collect()
+}
+
+fn from_wide(s: &[u16]) -> String {
+    let len = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+    String::from_utf16_lossy(&s[..len])
+}
+
+/// A window content listing the loaded modules and their exports.
+#[cfg(feature = "renderer")]
+#[derive(Debug, Default, Clone)]
+pub struct ModuleInspector {
+    /// Only show the modules whose name contains this text, case insensitive.
+    pub filter: String,
+    /// Only show the exports whose name contains this text, case insensitive.
+    pub export_filter: String,
+    modules: Option<Vec<ModuleInfo>>,
+    // The module whose exports are shown, and its exports.
+    exports: Option<(ModuleInfo, Vec<Export>)>,
+}
+
+#[cfg(feature = "renderer")]
+impl ModuleInspector {
+    /// Draw the module list within the current window. The modules are
+    /// listed on the first call, and again with the "Refresh" button.
+    pub fn build(&mut self, ui: &Ui) {
+        if ui.button("Refresh") || self.modules.is_none() {
+            self.modules = Some(modules().unwrap_or_default());
+        }
+        ui.same_line();
+        ui.input_text("Filter", &mut self.filter).build();
+
+        let filter = self.filter.to_lowercase();
+        let height = if self.exports.is_some() { ui.content_region_avail()[1] / 2.0 } else { 0.0 };
+        ui.child_window("##modules").size([0.0, height]).build(|| {
+            let modules = self.modules.as_deref().unwrap_or_default();
+            for (i, module) in modules.iter().enumerate() {
+                if !filter.is_empty() && !module.name.to_lowercase().contains(&filter) {
+                    continue;
+                }
+
+                let _id = ui.push_id_usize(i);
+                if ui.small_button("Copy") {
+                    ui.set_clipboard_text(format!("{:#x}", module.base));
+                }
+                ui.same_line();
+                if ui.small_button("Exports") {
+                    match exports(module) {
+                        Ok(exports) => self.exports = Some((module.clone(), exports)),
+                        Err(e) => error!("Couldn't read exports of {}: {e:?}", module.name),
+                    }
+                }
+                ui.same_line();
+                ui.text(format!(
+                    "{:#x}  {:>8} KiB  {}",
+                    module.base,
+                    module.size / 1024,
+                    module.name
+                ));
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(&module.path);
+                }
+            }
+        });
+
+        let Some((module, exports)) = &self.exports else {
+            return;
+        };
+
+        let mut close = false;
+        ui.separator();
+        ui.text(format!("Exports of {} ({})", module.name, exports.len()));
+        ui.same_line();
+        close |= ui.small_button("Close");
+        ui.input_text("Filter exports", &mut self.export_filter).build();
+
+        let filter = self.export_filter.to_lowercase();
+        ui.child_window("##exports").build(|| {
+            for (i, export) in exports.iter().enumerate() {
+                let name = export.name.as_deref().unwrap_or("");
+                if !filter.is_empty() && !name.to_lowercase().contains(&filter) {
+                    continue;
+                }
+
+                let _id = ui.push_id_usize(i);
+                if ui.small_button("Copy") {
+                    ui.set_clipboard_text(format!("{name} = {:#x}", export.address));
+                }
+                ui.same_line();
+                match &export.forwarded_to {
+                    Some(target) => ui.text(format!("#{:<5} {name} -> {target}", export.ordinal)),
+                    None => {
+                        ui.text(format!("#{:<5} {:#x}  {name}", export.ordinal, export.address))
+                    },
+                }
+            }
+        });
+
+        if close {
+            self.exports = None;
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Depth-tested overlay elements.
+//!
+//! Elements drawn between [`DrawListDepthExt::begin_depth_test`] and
+//! [`DrawListDepthExt::end_depth_test`] are tested against the game's depth
+//! buffer at a given depth, so that e.g. 3D markers projected onto the screen
+//! are occluded by the world geometry in front of them.
+//!
+//! Only the DirectX 11 backend supports depth testing for now, and the depth
+//! buffer must be provided via
+//! [`set_depth_target`](crate::hooks::dx11::set_depth_target). Without one,
+//! elements are drawn as usual. Depth testing ends with the draw list it was
+//! started on.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::depth::DrawListDepthExt;
+//!
+//! // In `ImguiRenderLoop::render`, with `pos` and `depth` obtained by
+//! // projecting a world position with the game's view-projection matrix:
+//! let draw_list = ui.get_background_draw_list();
+//! draw_list.begin_depth_test(depth);
+//! draw_list.add_circle(pos, 8.0, [1.0, 0.0, 0.0, 1.0]).filled(true).build();
+//! draw_list.end_depth_test();
+//! ```
+use imgui::DrawListMut;
+use parking_lot::Mutex;
+
+// Depth test requested by the last callback that ran during rendering, to be
+// picked up by the backend right after the callback.
+static PENDING: Mutex<Option<DepthTest>> = Mutex::new(None);
+
+/// Depth test of a range of draw commands.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DepthTest {
+    /// Draw on top of everything.
+    #[default]
+    Disabled,
+    /// Draw where the game's depth buffer is farther than `depth`.
+    ///
+    /// `depth` is in the game's normalized depth range, i.e. the `z / w` of
+    /// the position transformed by the game's projection matrix.
+    Enabled {
+        /// Depth of the drawn elements.
+        depth: f32,
+    },
+}
+
+/// Extension trait to toggle depth testing on an `imgui` draw list.
+pub trait DrawListDepthExt {
+    /// Depth test the elements drawn after this call at `depth`.
+    fn begin_depth_test(&self, depth: f32);
+
+    /// Stop depth testing the elements drawn after this call.
+    fn end_depth_test(&self);
+}
+
+impl DrawListDepthExt for DrawListMut<'_> {
+    fn begin_depth_test(&self, depth: f32) {
+        self.add_callback(move || request(DepthTest::Enabled { depth })).build();
+    }
+
+    fn end_depth_test(&self) {
+        self.add_callback(|| request(DepthTest::Disabled)).build();
+    }
+}
+
+fn request(depth_test: DepthTest) {
+    *PENDING.lock() = Some(depth_test);
+}
+
+// Take the depth test requested by the callback that just ran, if any.
+pub(crate) fn take_pending() -> Option<DepthTest> {
+    PENDING.lock().take()
+}
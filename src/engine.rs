@@ -0,0 +1,57 @@
+//! Render engines, for rendering `imgui` without hooks.
+//!
+//! The render engines are the graphics API backends the hooks draw the
+//! overlay with. Applications owning their swap chain, e.g. an overlay
+//! presenting through DirectComposition or a custom engine, can use them
+//! directly: create the engine of the API with the application's device or
+//! queue, build the `imgui` frame and render its draw data into the back
+//! buffer before presenting it.
+//!
+//! Without hooks, nothing feeds `imgui` with input or the display size: the
+//! application sets them in `imgui::Io` before every frame. Textures are
+//! loaded with the engine's [`RenderContext`](crate::RenderContext)
+//! implementation.
+//!
+//! With the `wgpu` feature, `WgpuRenderEngine` renders into `wgpu` texture
+//! views, for applications built on `wgpu`. The hooks never use it: `wgpu`
+//! can't draw with the device of a hooked game, so the hooks keep rendering
+//! with the native engines.
+//!
+//! The engines and the [`RenderEngine`] trait are part of the public API and
+//! follow semantic versioning like the rest of the crate.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::engine::{D3D11RenderEngine, RenderEngine};
+//! use hudhook::imgui::Context;
+//! use hudhook::windows::core::Result;
+//! use hudhook::windows::Win32::Graphics::Direct3D11::{ID3D11Device, ID3D11Texture2D};
+//! use hudhook::windows::Win32::Graphics::Dxgi::IDXGISwapChain;
+//!
+//! fn run(device: &ID3D11Device, swap_chain: &IDXGISwapChain) -> Result<()> {
+//!     let mut ctx = Context::create();
+//!     let mut engine = D3D11RenderEngine::new(device, &mut ctx)?;
+//!     engine.setup_fonts(&mut ctx)?;
+//!
+//!     loop {
+//!         ctx.io_mut().display_size = [1920.0, 1080.0];
+//!         let ui = ctx.new_frame();
+//!         ui.text("Hello from hudhook");
+//!
+//!         let back_buffer: ID3D11Texture2D = unsafe { swap_chain.GetBuffer(0)? };
+//!         engine.render(ctx.render(), back_buffer)?;
+//!         unsafe { swap_chain.Present(1, 0).ok()? };
+//!     }
+//! }
+//! ```
+#[cfg(feature = "dx11")]
+pub use crate::renderer::D3D11RenderEngine;
+#[cfg(feature = "dx12")]
+pub use crate::renderer::D3D12RenderEngine;
+#[cfg(feature = "dx9")]
+pub use crate::renderer::D3D9RenderEngine;
+#[cfg(feature = "opengl3")]
+pub use crate::renderer::OpenGl3RenderEngine;
+pub use crate::renderer::RenderEngine;
+#[cfg(feature = "wgpu")]
+pub use crate::renderer::WgpuRenderEngine;
@@ -0,0 +1,88 @@
+//! Errors creating the hooks.
+//!
+//! Creating hooks means creating throwaway devices and swap chains to find
+//! the functions to hook, then hooking them. Any of these steps can fail on
+//! a given machine, e.g. without a DirectX 12 capable adapter. Such failures
+//! are returned as an [`Error`] rather than crashing the game:
+//! [`HudhookBuilder::with`](crate::HudhookBuilder::with) logs them and leaves
+//! the overlay disabled.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::hooks::dx12::ImguiDx12Hooks;
+//! use hudhook::*;
+//!
+//! # struct MyRenderLoop;
+//! # impl ImguiRenderLoop for MyRenderLoop { fn render(&mut self, _: &mut imgui::Ui) {} }
+//! match unsafe { ImguiDx12Hooks::try_new(MyRenderLoop) } {
+//!     Ok(hooks) => Hudhook::builder().with_hooks(hooks).build().apply().unwrap(),
+//!     Err(e) => eprintln!("No overlay: {e}"),
+//! }
+//! ```
+use std::fmt;
+
+use crate::mh::MH_STATUS;
+
+/// Why hooks couldn't be created.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A Windows, DXGI, Direct3D or OpenGL call failed.
+    Windows {
+        /// The function that failed.
+        call: &'static str,
+        /// The error it returned.
+        error: windows::core::Error,
+    },
+    /// A call that returns an object or an address returned none.
+    Missing(&'static str),
+    /// MinHook couldn't hook a function.
+    Hook {
+        /// The function that couldn't be hooked.
+        function: &'static str,
+        /// The status MinHook returned.
+        status: MH_STATUS,
+    },
+    /// The hooks don't render `imgui`, and can't be created from a render
+    /// loop.
+    NoRenderLoop(&'static str),
+}
+
+impl Error {
+    // Map the status of `MhHook::named` for `function`.
+    pub(crate) fn hook(function: &'static str) -> impl FnOnce(MH_STATUS) -> Self {
+        move |status| Self::Hook { function, status }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Windows { call, error } => write!(f, "{call} failed: {error}"),
+            Error::Missing(call) => write!(f, "{call} returned nothing"),
+            Error::Hook { function, status } => write!(f, "couldn't hook {function}: {status:?}"),
+            Error::NoRenderLoop(hooks) => {
+                write!(f, "{hooks} don't render imgui; construct them with {hooks}::new")
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Windows { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Name the call a `windows` error comes from.
+pub(crate) trait Context<T> {
+    fn context(self, call: &'static str) -> Result<T, Error>;
+}
+
+impl<T> Context<T> for windows::core::Result<T> {
+    fn context(self, call: &'static str) -> Result<T, Error> {
+        self.map_err(|error| Error::Windows { call, error })
+    }
+}
@@ -0,0 +1,338 @@
+//! Applying changes to files edited while the game runs.
+//!
+//! Render loops often read their settings, layouts or themes from files next
+//! to the DLL. Files registered with [`watch`] are watched by a background
+//! thread per folder, with `ReadDirectoryChangesW`, so that end users can
+//! tweak them in a text editor while the game runs. When a file changes, its
+//! callback runs on the render thread at the next frame boundary, before any
+//! render loop, and every render loop is then notified through
+//! [`ImguiRenderLoop::on_file_changed`](crate::ImguiRenderLoop::on_file_changed).
+//!
+//! Editors often save a file in several writes, or by replacing it with a
+//! temporary file: a change is only applied once the file has been left alone
+//! for a short while, so that callbacks don't read it half-written.
+//!
+//! Example usage:
+//! ```no_run
+//! use std::sync::{Arc, Mutex};
+//!
+//! use hudhook::file_watch;
+//! use hudhook::layout::LayoutManager;
+//!
+//! let layout = Arc::new(Mutex::new(LayoutManager::load("layout.txt").unwrap_or_default()));
+//!
+//! let reloaded = Arc::clone(&layout);
+//! file_watch::watch("layout.txt", move |path| match LayoutManager::load(path) {
+//!     Ok(layout) => *reloaded.lock().unwrap() = layout,
+//!     Err(e) => eprintln!("Couldn't reload {path:?}: {e}"),
+//! })
+//! .unwrap();
+//! ```
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::{io, slice};
+
+use parking_lot::Mutex;
+use tracing::{debug, error};
+use windows::core::{Result, HSTRING};
+use windows::Win32::Foundation::{CloseHandle, HANDLE, WAIT_OBJECT_0};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, ReadDirectoryChangesW, FILE_ACTION_REMOVED, FILE_ACTION_RENAMED_OLD_NAME,
+    FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OVERLAPPED, FILE_LIST_DIRECTORY,
+    FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE, FILE_NOTIFY_INFORMATION,
+    FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+use windows::Win32::System::Threading::{CreateEventW, SetEvent, WaitForMultipleObjects, INFINITE};
+use windows::Win32::System::IO::{CancelIoEx, GetOverlappedResult, OVERLAPPED};
+
+type Callback = Arc<Mutex<dyn FnMut(&Path) + Send>>;
+
+static WATCHED: Mutex<Vec<(PathBuf, Callback)>> = Mutex::new(Vec::new());
+static WATCHERS: Mutex<Vec<DirWatcher>> = Mutex::new(Vec::new());
+// Files changed on disk, with the time of their last change, waiting for the
+// next frame boundary.
+static PENDING: Mutex<Vec<(PathBuf, Instant)>> = Mutex::new(Vec::new());
+// The last changes applied, numbered from `CHANGE_COUNT - CHANGES.len()`, for
+// each pipeline to notify its render loops of the ones it hasn't seen.
+static CHANGES: Mutex<VecDeque<PathBuf>> = Mutex::new(VecDeque::new());
+static CHANGE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// How long a file must be left alone before its change is applied.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+// How many applied changes are kept for pipelines that didn't render since.
+const MAX_CHANGES: usize = 64;
+// Size of the buffer `ReadDirectoryChangesW` fills, in `u32`s for alignment.
+const BUFFER_LEN: usize = 4096;
+
+/// Call `on_change` with the path of the file whenever the file at `path`
+/// changes, on the render thread at the next frame boundary.
+///
+/// The folder of the file must exist, the file itself doesn't have to: it is
+/// reported when created. Fails if the folder can't be watched.
+pub fn watch<P, F>(path: P, on_change: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Path) + Send + 'static,
+{
+    let path = absolute(path.as_ref())?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut watchers = WATCHERS.lock();
+    if !watchers.iter().any(|watcher| same_path(&watcher.dir, &dir)) {
+        watchers.push(DirWatcher::new(dir)?);
+    }
+
+    debug!("Watching {path:?}");
+    WATCHED.lock().push((path, Arc::new(Mutex::new(on_change))));
+    Ok(())
+}
+
+/// Stop calling the callbacks of the file at `path`.
+pub fn unwatch<P: AsRef<Path>>(path: P) {
+    let Ok(path) = absolute(path.as_ref()) else {
+        return;
+    };
+
+    // Watcher threads lock the watched files, which mustn't be held while
+    // joining them.
+    let dirs: Vec<PathBuf> = {
+        let mut watched = WATCHED.lock();
+        watched.retain(|(p, _)| !same_path(p, &path));
+        watched.iter().filter_map(|(p, _)| p.parent().map(Path::to_path_buf)).collect()
+    };
+    WATCHERS.lock().retain(|watcher| dirs.iter().any(|dir| same_path(dir, &watcher.dir)));
+}
+
+/// The files currently watched.
+pub fn watched() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = WATCHED.lock().iter().map(|(p, _)| p.clone()).collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+// Run the callbacks of the files that changed and settled since the last frame.
+pub(crate) fn apply() {
+    let settled: Vec<PathBuf> = {
+        let mut pending = PENDING.lock();
+        if pending.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut settled = Vec::new();
+        pending.retain(|(path, changed)| {
+            let done = now.duration_since(*changed) >= SETTLE_TIME;
+            if done {
+                settled.push(path.clone());
+            }
+            !done
+        });
+        settled
+    };
+
+    for path in settled {
+        debug!("{path:?} changed");
+
+        // Callbacks may watch or unwatch files, so they run without the lock held.
+        let callbacks: Vec<Callback> = WATCHED
+            .lock()
+            .iter()
+            .filter(|(p, _)| same_path(p, &path))
+            .map(|(_, callback)| Arc::clone(callback))
+            .collect();
+        for callback in callbacks {
+            (&mut *callback.lock())(&path);
+        }
+
+        let mut changes = CHANGES.lock();
+        if changes.len() == MAX_CHANGES {
+            changes.pop_front();
+        }
+        changes.push_back(path);
+        CHANGE_COUNT.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+// The number of changes applied so far.
+pub(crate) fn change_count() -> usize {
+    CHANGE_COUNT.load(Ordering::SeqCst)
+}
+
+// The files whose change was applied since the `seen`-th one, and mark them as
+// seen.
+pub(crate) fn changes_since(seen: &mut usize) -> Vec<PathBuf> {
+    if CHANGE_COUNT.load(Ordering::SeqCst) == *seen {
+        return Vec::new();
+    }
+
+    let changes = CHANGES.lock();
+    let count = CHANGE_COUNT.load(Ordering::SeqCst);
+    let first = count - changes.len();
+    let skip = seen.saturating_sub(first);
+    *seen = count;
+    changes.iter().skip(skip).cloned().collect()
+}
+
+// Stop every watcher and drop the callbacks, before the DLL is unloaded.
+pub(crate) fn unwatch_all() {
+    WATCHERS.lock().clear();
+    WATCHED.lock().clear();
+    PENDING.lock().clear();
+}
+
+// Watches the changes to the files of a folder, from a thread of its own.
+struct DirWatcher {
+    dir: PathBuf,
+    stop: HANDLE,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DirWatcher {
+    fn new(dir: PathBuf) -> io::Result<Self> {
+        let stop = unsafe { CreateEventW(None, true, false, None) }?;
+
+        let thread = {
+            let dir = dir.clone();
+            thread::spawn(move || {
+                if let Err(e) = unsafe { watch_dir(&dir, stop) } {
+                    error!("Stopped watching {dir:?}: {e:?}");
+                }
+            })
+        };
+
+        Ok(Self { dir, stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for DirWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = SetEvent(self.stop) {
+                error!("Couldn't stop watching {:?}: {e:?}", self.dir);
+                return;
+            }
+            if let Some(thread) = self.thread.take() {
+                thread.join().ok();
+            }
+            CloseHandle(self.stop).ok();
+        }
+    }
+}
+
+unsafe fn watch_dir(dir: &Path, stop: HANDLE) -> Result<()> {
+    let handle = CreateFileW(
+        &HSTRING::from(dir),
+        FILE_LIST_DIRECTORY.0,
+        FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+        None,
+        OPEN_EXISTING,
+        FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OVERLAPPED,
+        HANDLE::default(),
+    )?;
+    let event = match CreateEventW(None, true, false, None) {
+        Ok(event) => event,
+        Err(e) => {
+            CloseHandle(handle).ok();
+            return Err(e);
+        },
+    };
+
+    let res = read_changes(dir, handle, event, stop);
+
+    CloseHandle(event).ok();
+    CloseHandle(handle).ok();
+    res
+}
+
+unsafe fn read_changes(dir: &Path, handle: HANDLE, event: HANDLE, stop: HANDLE) -> Result<()> {
+    let mut buffer = vec![0u32; BUFFER_LEN];
+
+    loop {
+        let mut overlapped = OVERLAPPED { hEvent: event, ..Default::default() };
+        ReadDirectoryChangesW(
+            handle,
+            buffer.as_mut_ptr() as _,
+            (buffer.len() * 4) as u32,
+            false,
+            FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE,
+            None,
+            Some(&mut overlapped),
+            None,
+        )?;
+
+        let mut len = 0;
+        if WaitForMultipleObjects(&[event, stop], false, INFINITE) != WAIT_OBJECT_0 {
+            // The buffer must outlive the read, which is cancelled first.
+            CancelIoEx(handle, Some(&overlapped)).ok();
+            GetOverlappedResult(handle, &overlapped, &mut len, true).ok();
+            return Ok(());
+        }
+        GetOverlappedResult(handle, &overlapped, &mut len, false)?;
+
+        // Changes that didn't fit in the buffer are lost, any file may have changed.
+        if len == 0 {
+            let paths: Vec<PathBuf> = WATCHED
+                .lock()
+                .iter()
+                .filter(|(p, _)| p.parent() == Some(dir))
+                .map(|(p, _)| p.clone())
+                .collect();
+            paths.into_iter().for_each(changed);
+            continue;
+        }
+
+        let mut offset = 0;
+        loop {
+            let info =
+                &*((buffer.as_ptr() as *const u8).add(offset) as *const FILE_NOTIFY_INFORMATION);
+            let name =
+                slice::from_raw_parts(info.FileName.as_ptr(), info.FileNameLength as usize / 2);
+
+            if info.Action != FILE_ACTION_REMOVED && info.Action != FILE_ACTION_RENAMED_OLD_NAME {
+                changed(dir.join(String::from_utf16_lossy(name)));
+            }
+
+            if info.NextEntryOffset == 0 {
+                break;
+            }
+            offset += info.NextEntryOffset as usize;
+        }
+    }
+}
+
+// Queue the change of a file for the next frame boundary, if it is watched.
+fn changed(path: PathBuf) {
+    if !WATCHED.lock().iter().any(|(p, _)| same_path(p, &path)) {
+        return;
+    }
+
+    let now = Instant::now();
+    let mut pending = PENDING.lock();
+    match pending.iter_mut().find(|(p, _)| same_path(p, &path)) {
+        Some((_, changed)) => *changed = now,
+        None => pending.push((path, now)),
+    }
+}
+
+// The path of a file in the canonical form of its folder, which the watcher
+// threads report changes in.
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Ok(dir.canonicalize()?.join(name))
+}
+
+// Windows paths are case-insensitive.
+fn same_path(a: &Path, b: &Path) -> bool {
+    a.as_os_str().to_string_lossy().to_lowercase() == b.as_os_str().to_string_lossy().to_lowercase()
+}
@@ -0,0 +1,61 @@
+//! Font rasterization options.
+//!
+//! With the `imgui-freetype` feature, `imgui` rasterizes the font atlas with
+//! FreeType instead of stb_truetype. FreeType hints glyphs to the pixel grid,
+//! which keeps small text crisp at common HUD sizes, and can load color
+//! glyphs such as emoji.
+//!
+//! Rasterizer options are selected per font via [`FreetypeFlags`].
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::fonts::FreetypeFlags;
+//! use hudhook::imgui::{FontAtlas, FontConfig, FontSource};
+//!
+//! // In `ImguiRenderLoop::setup_fonts`:
+//! # fn setup_fonts(fonts: &mut FontAtlas) {
+//! fonts.add_font(&[FontSource::TtfData {
+//!     data: include_bytes!("font.ttf"),
+//!     size_pixels: 13.0,
+//!     config: Some(FontConfig {
+//!         font_builder_flags: FreetypeFlags::LightHinting.bits(),
+//!         ..FontConfig::default()
+//!     }),
+//! }]);
+//! # }
+//! ```
+use bitflags::bitflags;
+use imgui::sys;
+
+bitflags! {
+    /// Per-font options of the FreeType rasterizer.
+    ///
+    /// Pass the bits as `FontConfig::font_builder_flags`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct FreetypeFlags: u32 {
+        /// Disable hinting entirely.
+        const NoHinting = sys::ImGuiFreeTypeBuilderFlags_NoHinting as u32;
+        /// Disable the auto-hinter, using the font's native hinter only.
+        const NoAutoHint = sys::ImGuiFreeTypeBuilderFlags_NoAutoHint as u32;
+        /// Prefer the auto-hinter over the font's native hinter.
+        const ForceAutoHint = sys::ImGuiFreeTypeBuilderFlags_ForceAutoHint as u32;
+        /// Lighter hinting, closer to the glyph shapes. Usually the best
+        /// choice for small HUD text.
+        const LightHinting = sys::ImGuiFreeTypeBuilderFlags_LightHinting as u32;
+        /// Strong hinting, for monochrome output.
+        const MonoHinting = sys::ImGuiFreeTypeBuilderFlags_MonoHinting as u32;
+        /// Artificially embolden the font.
+        const Bold = sys::ImGuiFreeTypeBuilderFlags_Bold as u32;
+        /// Artificially slant the font.
+        const Oblique = sys::ImGuiFreeTypeBuilderFlags_Oblique as u32;
+        /// Disable anti-aliasing. Combine with `MonoHinting` for best results.
+        const Monochrome = sys::ImGuiFreeTypeBuilderFlags_Monochrome as u32;
+        /// Load color glyphs, e.g. emoji.
+        ///
+        /// Emoji outside of the basic multilingual plane additionally require
+        /// `imgui` to be built with 32-bit wide characters.
+        const LoadColor = sys::ImGuiFreeTypeBuilderFlags_LoadColor as u32;
+        /// Load embedded bitmaps, e.g. for bitmap emoji fonts.
+        const Bitmap = sys::ImGuiFreeTypeBuilderFlags_Bitmap as u32;
+    }
+}
@@ -0,0 +1,267 @@
+//! Frame stepping and slow motion, for speedrun practice tools.
+//!
+//! The game can't render a frame while the previous one is still being
+//! presented, so stalling `Present` slows the whole game down without
+//! touching its code or its clock. Every hook presenting frames calls into
+//! this module after the overlay is drawn and before the frame is passed on
+//! to the game's `Present`:
+//!
+//! - At a [speed](set_speed) below 1, each frame is held until it has taken as
+//!   long as it would at full speed, divided by the speed. With vsync, the
+//!   frame is released slightly before the vertical blank it should be shown
+//!   at, so that it isn't held for a whole extra refresh.
+//! - While [paused](pause), frames are held until they are [stepped](step)
+//!   through one by one, or the game is [resumed](resume).
+//!
+//! The overlay is held along with the game, so it can't be used to step or
+//! resume a paused game: bind keys to [`PAUSE_ACTION`] and [`STEP_ACTION`]
+//! with [`keybinds::bind`], or call these functions from another thread.
+//! Frames aren't held before the game window, or one of its other windows, is
+//! in the foreground, so that the game can't be left hanging in the
+//! background.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::windows::Win32::UI::Input::KeyboardAndMouse::{VK_F10, VK_F9};
+//! use hudhook::{frame_step, keybinds};
+//!
+//! keybinds::bind(frame_step::PAUSE_ACTION, VK_F9);
+//! keybinds::bind(frame_step::STEP_ACTION, VK_F10);
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // ui.window("Practice").build(|| frame_step::controls(ui));
+//! ```
+use std::thread;
+use std::time::{Duration, Instant};
+
+use imgui::Ui;
+use parking_lot::Mutex;
+use windows::Win32::System::Threading::GetCurrentProcessId;
+use windows::Win32::UI::Input::KeyboardAndMouse::GetAsyncKeyState;
+use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+use crate::{keybinds, timing};
+
+/// Action to bind with [`keybinds::bind`] to pause or resume the game.
+pub const PAUSE_ACTION: &str = "Pause or resume the game";
+/// Action to bind with [`keybinds::bind`] to step a paused game by one
+/// frame. Pauses the game if it runs.
+pub const STEP_ACTION: &str = "Step one frame";
+
+// Slowest speed supported, where a frame takes 100 times as long.
+const MIN_SPEED: f32 = 0.01;
+// Time left before the deadline of a frame when sleeping stops and spinning
+// starts, to make up for the coarse granularity of `thread::sleep`.
+const SPIN_TIME: Duration = Duration::from_millis(2);
+// Longest frame stretched in slow motion.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+
+struct Pacing {
+    paused: bool,
+    // Frames to let through while paused.
+    steps: u32,
+    speed: f32,
+    // When the last frame was released.
+    last_release: Option<Instant>,
+    // Whether the keys bound to the actions were down when last polled.
+    pause_key_down: bool,
+    step_key_down: bool,
+    // Set while the hooks are torn down, to release held frames for good.
+    released: bool,
+}
+
+static PACING: Mutex<Pacing> = Mutex::new(Pacing {
+    paused: false,
+    steps: 0,
+    speed: 1.0,
+    last_release: None,
+    pause_key_down: false,
+    step_key_down: false,
+    released: false,
+});
+
+/// Pause the game on the next frame.
+pub fn pause() {
+    PACING.lock().paused = true;
+}
+
+/// Resume the game at the current [speed](set_speed).
+pub fn resume() {
+    let mut pacing = PACING.lock();
+    pacing.paused = false;
+    pacing.steps = 0;
+}
+
+/// Pause the game if it runs, resume it if it is paused.
+pub fn toggle_pause() {
+    let mut pacing = PACING.lock();
+    pacing.paused = !pacing.paused;
+    pacing.steps = 0;
+}
+
+/// Whether the game is paused.
+pub fn is_paused() -> bool {
+    PACING.lock().paused
+}
+
+/// Let `frames` frames through, then pause again. Pauses the game if it runs.
+pub fn step(frames: u32) {
+    let mut pacing = PACING.lock();
+    if pacing.paused {
+        pacing.steps += frames;
+    } else {
+        pacing.paused = true;
+    }
+}
+
+/// Run the game at `speed` times its normal speed, between 0.01 and 1.
+/// Defaults to 1.
+pub fn set_speed(speed: f32) {
+    PACING.lock().speed = speed.clamp(MIN_SPEED, 1.0);
+}
+
+/// Speed the game runs at when it isn't paused.
+pub fn speed() -> f32 {
+    PACING.lock().speed
+}
+
+// Hold the frame about to be presented as long as the speed and the pause
+// require. Called by the hooks right before the trampoline of `Present`.
+pub(crate) fn pace() {
+    let now = Instant::now();
+    let foreground = is_foreground();
+    if foreground {
+        poll_keys();
+    }
+
+    let (speed, paused, last_release) = {
+        let pacing = PACING.lock();
+        if pacing.released {
+            return;
+        }
+        (pacing.speed, pacing.paused, pacing.last_release)
+    };
+
+    if let Some(last_release) = last_release.filter(|_| speed < 1.0 && foreground) {
+        // Loading screens and the like don't get stretched to minutes.
+        let frame_time = (now - last_release).min(MAX_FRAME_TIME).div_f32(speed);
+        // With vsync, `Present` then waits for the next vertical blank, which
+        // has to be the one the frame should be shown at.
+        let margin = timing::display_timing()
+            .frame_time()
+            .map(|vblank| Duration::from_secs_f32(vblank / 4.0))
+            .unwrap_or_default();
+        sleep_until(last_release + frame_time.saturating_sub(margin));
+    }
+
+    if paused && foreground {
+        hold();
+    }
+
+    PACING.lock().last_release = Some(Instant::now());
+}
+
+// Hold the frame until it is stepped through or the game is resumed.
+fn hold() {
+    loop {
+        {
+            let mut pacing = PACING.lock();
+            if !pacing.paused || pacing.released {
+                return;
+            }
+            if pacing.steps > 0 {
+                pacing.steps -= 1;
+                return;
+            }
+        }
+
+        if !is_foreground() {
+            return;
+        }
+        poll_keys();
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+// Release the frames held and stop holding them, as the hooks are torn down.
+pub(crate) fn release() {
+    PACING.lock().released = true;
+}
+
+// Pause and step when the keys bound to the actions are pressed.
+fn poll_keys() {
+    let is_down = |action| {
+        keybinds::binding(action)
+            .map(|key| unsafe { GetAsyncKeyState(key.0 as i32) } < 0)
+            .unwrap_or(false)
+    };
+    let pause_key_down = is_down(PAUSE_ACTION);
+    let step_key_down = is_down(STEP_ACTION);
+
+    let (pause_pressed, step_pressed) = {
+        let mut pacing = PACING.lock();
+        let pressed =
+            (pause_key_down && !pacing.pause_key_down, step_key_down && !pacing.step_key_down);
+        pacing.pause_key_down = pause_key_down;
+        pacing.step_key_down = step_key_down;
+        pressed
+    };
+
+    if pause_pressed {
+        toggle_pause();
+    }
+    if step_pressed {
+        step(1);
+    }
+}
+
+// Whether a window of the game is in the foreground.
+fn is_foreground() -> bool {
+    let mut process_id = 0;
+    unsafe { GetWindowThreadProcessId(GetForegroundWindow(), Some(&mut process_id as *mut u32)) };
+    process_id == unsafe { GetCurrentProcessId() }
+}
+
+fn sleep_until(deadline: Instant) {
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return;
+        }
+        if remaining > SPIN_TIME {
+            thread::sleep(remaining - SPIN_TIME);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// Draw a pause button and a speed slider into the current window, along
+/// with the keys bound to the actions.
+pub fn controls(ui: &Ui) {
+    let label = if is_paused() { "Resume" } else { "Pause" };
+    if ui.button(label) {
+        toggle_pause();
+    }
+    ui.same_line();
+    if ui.button("Step") {
+        step(1);
+    }
+
+    let mut speed = speed() * 100.0;
+    if ui
+        .slider_config("Speed", MIN_SPEED * 100.0, 100.0)
+        .display_format("%.0f%%")
+        .build(&mut speed)
+    {
+        set_speed(speed / 100.0);
+    }
+    if ui.button("Normal speed") {
+        set_speed(1.0);
+    }
+
+    for action in [PAUSE_ACTION, STEP_ACTION] {
+        let key = keybinds::binding(action).map(keybinds::key_name);
+        ui.text_disabled(format!("{action}: {}", key.as_deref().unwrap_or("unbound")));
+    }
+}
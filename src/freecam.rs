@@ -0,0 +1,287 @@
+//! Free camera flying through the game world.
+//!
+//! A freecam moves a camera with the keyboard and the mouse while the game's
+//! own controls are blocked. [`Freecam`] reads the keys and the mouse from
+//! the overlay's input, integrates them into a [`Camera`] transform with
+//! optional smoothing, and hands the transform every frame to a closure
+//! writing it into the game's camera. The closure runs on the game's render
+//! thread right before the frame is presented, like closures queued with
+//! [`run_on_game_thread`](crate::game_thread::run_on_game_thread), so the
+//! write doesn't race with the game.
+//!
+//! The module knows nothing about the game's world: collisions are left to a
+//! closure given to [`Freecam::set_collision`], e.g. one casting a ray with
+//! the game's physics engine.
+//!
+//! Controls, while the freecam is active:
+//! - `W`, `A`, `S`, `D` fly forward, left, backward and right;
+//! - `Space` or `E` flies up, `Left Ctrl` or `Q` down;
+//! - `Left Shift` flies faster;
+//! - moving the mouse outside of the overlay's windows looks around.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::freecam::{Camera, Freecam};
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut freecam = Freecam::new(|camera: &Camera| {
+//!     // Write `camera.position`, `camera.yaw` and `camera.pitch` into the
+//!     // game's camera.
+//! });
+//! freecam.set_active(true, Camera::default());
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // freecam.update(ui);
+//!
+//! // In `ImguiRenderLoop::message_filter`:
+//! // freecam.message_filter()
+//! ```
+use std::f32::consts::FRAC_PI_2;
+use std::sync::Arc;
+
+use imgui::{Key, Ui};
+use parking_lot::Mutex;
+
+use crate::{game_thread, MessageFilter};
+
+type Writer = Arc<Mutex<dyn FnMut(&Camera) + Send>>;
+type Collision = Box<dyn Fn([f32; 3], [f32; 3]) -> [f32; 3] + Send + Sync>;
+
+// Pitch is kept short of straight up or down, where yaw is undefined.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Which world axis points up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpAxis {
+    /// Y up and Z forward at zero yaw, as in most DirectX games.
+    Y,
+    /// Z up and X forward at zero yaw, as in Unreal Engine games.
+    Z,
+}
+
+/// Position and orientation of the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// Position in world units.
+    pub position: [f32; 3],
+    /// Rotation around the up axis, in radians.
+    pub yaw: f32,
+    /// Rotation up from the horizon, in radians.
+    pub pitch: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self { position: [0.0; 3], yaw: 0.0, pitch: 0.0 }
+    }
+}
+
+impl Camera {
+    /// Unit vector the camera looks along.
+    pub fn forward(&self, up_axis: UpAxis) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        match up_axis {
+            UpAxis::Y => [sin_yaw * cos_pitch, sin_pitch, cos_yaw * cos_pitch],
+            UpAxis::Z => [cos_yaw * cos_pitch, sin_yaw * cos_pitch, sin_pitch],
+        }
+    }
+
+    /// Horizontal unit vector to the right of the camera.
+    pub fn right(&self, up_axis: UpAxis) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        match up_axis {
+            UpAxis::Y => [cos_yaw, 0.0, -sin_yaw],
+            UpAxis::Z => [-sin_yaw, cos_yaw, 0.0],
+        }
+    }
+}
+
+/// Tunables of a [`Freecam`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FreecamSettings {
+    /// Which world axis points up.
+    pub up_axis: UpAxis,
+    /// Flying speed, in world units per second.
+    pub speed: f32,
+    /// Factor applied to the speed while `Left Shift` is held.
+    pub fast_factor: f32,
+    /// Radians turned per pixel the mouse moves.
+    pub mouse_sensitivity: f32,
+    /// Time it takes the camera to cover most of the way to where the input
+    /// moved it, in seconds. `0` moves it immediately.
+    pub smoothing: f32,
+    /// Pass the moves through the closure given to
+    /// [`Freecam::set_collision`].
+    pub collision: bool,
+}
+
+impl Default for FreecamSettings {
+    fn default() -> Self {
+        Self {
+            up_axis: UpAxis::Y,
+            speed: 10.0,
+            fast_factor: 4.0,
+            mouse_sensitivity: 0.003,
+            smoothing: 0.1,
+            collision: false,
+        }
+    }
+}
+
+/// A camera flown with the keyboard and the mouse.
+pub struct Freecam {
+    /// Tunables, which can be changed at any time.
+    pub settings: FreecamSettings,
+    active: bool,
+    // Where the input moved the camera, and where it is after smoothing.
+    target: Camera,
+    current: Camera,
+    writer: Writer,
+    collision: Option<Collision>,
+}
+
+impl Freecam {
+    /// Construct an inactive freecam handing its camera to `writer` every
+    /// frame while it is active.
+    pub fn new(writer: impl FnMut(&Camera) + Send + 'static) -> Self {
+        Self {
+            settings: FreecamSettings::default(),
+            active: false,
+            target: Camera::default(),
+            current: Camera::default(),
+            writer: Arc::new(Mutex::new(writer)),
+            collision: None,
+        }
+    }
+
+    /// Activate or deactivate the freecam. When activated, the camera starts
+    /// from `from`, typically the game's camera.
+    pub fn set_active(&mut self, active: bool, from: Camera) {
+        if active && !self.active {
+            self.target = from;
+            self.current = from;
+        }
+        self.active = active;
+    }
+
+    /// Whether the freecam is active.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// The camera as last handed to the writer.
+    pub fn camera(&self) -> Camera {
+        self.current
+    }
+
+    /// Pass the moves of the camera through `collision` while
+    /// [`FreecamSettings::collision`] is set. It gets the position before and
+    /// after the move, and returns where the camera can actually go.
+    pub fn set_collision(
+        &mut self,
+        collision: impl Fn([f32; 3], [f32; 3]) -> [f32; 3] + Send + Sync + 'static,
+    ) {
+        self.collision = Some(Box::new(collision));
+    }
+
+    /// Messages to block from the game, from
+    /// [`ImguiRenderLoop::message_filter`](crate::ImguiRenderLoop::message_filter):
+    /// the game's controls are blocked while the freecam is active.
+    pub fn message_filter(&self) -> MessageFilter {
+        if self.active {
+            MessageFilter::InputAll
+        } else {
+            MessageFilter::empty()
+        }
+    }
+
+    /// Move the camera with this frame's input, then queue handing it to the
+    /// writer before the frame is presented. Call it every frame from
+    /// [`ImguiRenderLoop::render`](crate::ImguiRenderLoop::render).
+    pub fn update(&mut self, ui: &Ui) {
+        if !self.active {
+            return;
+        }
+
+        let io = ui.io();
+        let settings = self.settings;
+        let delta_time = io.delta_time;
+
+        if !io.want_capture_mouse {
+            let [dx, dy] = io.mouse_delta;
+            self.target.yaw += dx * settings.mouse_sensitivity;
+            self.target.pitch =
+                (self.target.pitch - dy * settings.mouse_sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        if !io.want_capture_keyboard {
+            let axis = |positive: &[Key], negative: &[Key]| {
+                let down = |keys: &[Key]| keys.iter().any(|&key| ui.is_key_down(key));
+                down(positive) as i32 as f32 - down(negative) as i32 as f32
+            };
+            let forward = axis(&[Key::W], &[Key::S]);
+            let right = axis(&[Key::D], &[Key::A]);
+            let up = axis(&[Key::Space, Key::E], &[Key::LeftCtrl, Key::Q]);
+
+            let mut speed = settings.speed * delta_time;
+            if ui.is_key_down(Key::LeftShift) {
+                speed *= settings.fast_factor;
+            }
+
+            let forward_dir = self.target.forward(settings.up_axis);
+            let right_dir = self.target.right(settings.up_axis);
+            let up_dir = match settings.up_axis {
+                UpAxis::Y => [0.0, 1.0, 0.0],
+                UpAxis::Z => [0.0, 0.0, 1.0],
+            };
+
+            let from = self.target.position;
+            let to: [f32; 3] = std::array::from_fn(|i| {
+                from[i] + (forward_dir[i] * forward + right_dir[i] * right + up_dir[i] * up) * speed
+            });
+            self.target.position = match &self.collision {
+                Some(collision) if settings.collision && to != from => collision(from, to),
+                _ => to,
+            };
+        }
+
+        // Exponential smoothing, independent of the framerate.
+        let t = if settings.smoothing > 0.0 {
+            1.0 - (-delta_time * 3.0 / settings.smoothing).exp()
+        } else {
+            1.0
+        };
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        self.current = Camera {
+            position: std::array::from_fn(|i| {
+                lerp(self.current.position[i], self.target.position[i])
+            }),
+            yaw: lerp(self.current.yaw, self.target.yaw),
+            pitch: lerp(self.current.pitch, self.target.pitch),
+        };
+
+        let writer = Arc::clone(&self.writer);
+        let camera = self.current;
+        game_thread::run_on_game_thread(move || (&mut *writer.lock())(&camera));
+    }
+
+    /// Draw the freecam settings into the current window.
+    pub fn settings(&mut self, ui: &Ui) {
+        let mut active = self.active;
+        if ui.checkbox("Freecam", &mut active) {
+            let from = self.current;
+            self.set_active(active, from);
+        }
+
+        let settings = &mut self.settings;
+        ui.slider("Speed", 0.1, 1000.0, &mut settings.speed);
+        ui.slider("Fast factor", 1.0, 20.0, &mut settings.fast_factor);
+        ui.slider("Mouse sensitivity", 0.0005, 0.02, &mut settings.mouse_sensitivity);
+        ui.slider("Smoothing", 0.0, 1.0, &mut settings.smoothing);
+
+        ui.disabled(self.collision.is_none(), || {
+            ui.checkbox("Collision", &mut settings.collision);
+        });
+    }
+}
@@ -0,0 +1,89 @@
+//! Running code on the game's render thread.
+//!
+//! Memory writes that must be synchronized with the game's frames, e.g.
+//! patching a value the game reads once per frame, race with the game when
+//! made from another thread. [`run_on_game_thread`] queues a closure to run
+//! on the thread presenting the frames, right before the hooked present call
+//! hands the frame to the original function, after the overlay has rendered.
+//! Closures only run while a render loop is registered: until then, the hook
+//! passes frames through untouched.
+//!
+//! A panicking closure is logged and dropped; it doesn't unwind into the hook
+//! or the game, and the other queued closures still run.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::game_thread;
+//!
+//! // From a background thread:
+//! let frame_count = game_thread::run_on_game_thread(|| {
+//!     // Read or write the game's memory.
+//!     42u32
+//! });
+//!
+//! // `Err` if the closure panicked, or was dropped on eject.
+//! println!("{:?}", frame_count.recv());
+//! ```
+use std::any::Any;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver};
+
+use parking_lot::Mutex;
+use tracing::error;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+static QUEUE: Mutex<Vec<Task>> = Mutex::new(Vec::new());
+
+/// Queue `f` to run on the game's render thread before the next frame is
+/// presented, after the closures queued before it.
+///
+/// The returned receiver gets the result of `f`. Don't block on it from a
+/// render loop: render loops run on the game's render thread, which would
+/// wait for itself.
+pub fn run_on_game_thread<F, R>(f: F) -> Receiver<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let (tx, rx) = mpsc::sync_channel(1);
+
+    QUEUE.lock().push(Box::new(move || {
+        // The caller may have dropped the receiver if it isn't interested in
+        // the result.
+        let _ = tx.send(f());
+    }));
+
+    rx
+}
+
+// Run the queued closures. Called by the hooks right before calling the
+// present trampoline. Closures queued while running are left for the next
+// frame.
+pub(crate) fn run_queued() {
+    let tasks = match QUEUE.try_lock() {
+        Some(mut queue) if !queue.is_empty() => mem::take(&mut *queue),
+        _ => return,
+    };
+
+    for task in tasks {
+        if let Err(e) = panic::catch_unwind(AssertUnwindSafe(task)) {
+            error!("Game thread closure panicked: {}", panic_message(&*e));
+        }
+    }
+}
+
+// Drop the closures that didn't get to run, e.g. on eject, disconnecting
+// their receivers.
+pub(crate) fn clear() {
+    QUEUE.lock().clear();
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
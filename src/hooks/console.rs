@@ -0,0 +1,318 @@
+//! Hooks capturing the game's console and debug output.
+//!
+//! Games and their engines often print diagnostics to a console they never
+//! show, or through `OutputDebugString` for a debugger that isn't attached.
+//! [`ConsoleHooks`] hook the functions writing that output and collect the
+//! lines in the [`console`](crate::console) module, so that mods can display
+//! them in the overlay with a [`LogViewer`](crate::console::LogViewer). The
+//! output still reaches the console or the debugger as usual.
+//!
+//! Output written to the console handle with `WriteFile`, as the C runtime
+//! does when the standard output is redirected, isn't captured.
+//!
+//! Example usage:
+//! ```no_run
+//! # use hudhook::*;
+//! # use hudhook::hooks::dx11::ImguiDx11Hooks;
+//! use hudhook::hooks::console::ConsoleHooks;
+//!
+//! # struct MyRenderLoop;
+//! # impl ImguiRenderLoop for MyRenderLoop {
+//! #     fn render(&mut self, _: &mut imgui::Ui) {}
+//! # }
+//! Hudhook::builder()
+//!     .with::<ImguiDx11Hooks>(MyRenderLoop)
+//!     .with_hooks(unsafe { ConsoleHooks::new() })
+//!     .apply()
+//!     .ok();
+//! ```
+use std::cell::Cell;
+use std::ffi::{c_void, CStr};
+use std::sync::OnceLock;
+use std::{mem, slice};
+
+use tracing::trace;
+use windows::core::{s, PCSTR, PCWSTR};
+use windows::Win32::Foundation::{BOOL, HANDLE};
+use windows::Win32::System::LibraryLoader::{GetModuleHandleA, GetProcAddress};
+
+use super::{resolve_target, HookCall};
+use crate::console::{self, Source};
+use crate::error::Context as _;
+use crate::mh::MhHook;
+use crate::Hooks;
+
+type WriteConsoleAType = unsafe extern "system" fn(
+    console_output: HANDLE,
+    buffer: *const u8,
+    chars_to_write: u32,
+    chars_written: *mut u32,
+    reserved: *const c_void,
+) -> BOOL;
+type WriteConsoleWType = unsafe extern "system" fn(
+    console_output: HANDLE,
+    buffer: *const u16,
+    chars_to_write: u32,
+    chars_written: *mut u32,
+    reserved: *const c_void,
+) -> BOOL;
+type OutputDebugStringAType = unsafe extern "system" fn(output_string: PCSTR);
+type OutputDebugStringWType = unsafe extern "system" fn(output_string: PCWSTR);
+
+struct Trampolines {
+    write_console_a: WriteConsoleAType,
+    write_console_w: WriteConsoleWType,
+    output_debug_string_a: OutputDebugStringAType,
+    output_debug_string_w: OutputDebugStringWType,
+}
+
+static mut TRAMPOLINES: OnceLock<Trampolines> = OnceLock::new();
+
+thread_local! {
+    // Set while a hook captures output. `OutputDebugStringW` calls
+    // `OutputDebugStringA` on some versions of Windows, and the capture itself
+    // may log, so nested calls are passed through without capturing them again.
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+}
+
+// Run `capture` unless the current thread is already capturing output.
+fn capture_once(capture: impl FnOnce()) {
+    if !CAPTURING.with(Cell::get) {
+        without_capture(capture);
+    }
+}
+
+// Run `f` without capturing the output it writes.
+fn without_capture<R>(f: impl FnOnce() -> R) -> R {
+    let capturing = CAPTURING.with(|capturing| capturing.replace(true));
+    let r = f();
+    CAPTURING.with(|c| c.set(capturing));
+    r
+}
+
+unsafe extern "system" fn write_console_a_impl(
+    console_output: HANDLE,
+    buffer: *const u8,
+    chars_to_write: u32,
+    chars_written: *mut u32,
+    reserved: *const c_void,
+) -> BOOL {
+    let _call = HookCall::enter();
+
+    let Trampolines { write_console_a, .. } =
+        TRAMPOLINES.get().expect("Console trampolines uninitialized");
+
+    if !buffer.is_null() {
+        capture_once(|| {
+            let text = slice::from_raw_parts(buffer, chars_to_write as usize);
+            console::capture(Source::Console, &String::from_utf8_lossy(text));
+        });
+    }
+
+    write_console_a(console_output, buffer, chars_to_write, chars_written, reserved)
+}
+
+unsafe extern "system" fn write_console_w_impl(
+    console_output: HANDLE,
+    buffer: *const u16,
+    chars_to_write: u32,
+    chars_written: *mut u32,
+    reserved: *const c_void,
+) -> BOOL {
+    let _call = HookCall::enter();
+
+    let Trampolines { write_console_w, .. } =
+        TRAMPOLINES.get().expect("Console trampolines uninitialized");
+
+    if !buffer.is_null() {
+        capture_once(|| {
+            let text = slice::from_raw_parts(buffer, chars_to_write as usize);
+            console::capture(Source::Console, &String::from_utf16_lossy(text));
+        });
+    }
+
+    write_console_w(console_output, buffer, chars_to_write, chars_written, reserved)
+}
+
+unsafe extern "system" fn output_debug_string_a_impl(output_string: PCSTR) {
+    let _call = HookCall::enter();
+
+    let Trampolines { output_debug_string_a, .. } =
+        TRAMPOLINES.get().expect("Console trampolines uninitialized");
+
+    if !output_string.is_null() {
+        capture_once(|| {
+            let text = CStr::from_ptr(output_string.0 as _).to_string_lossy();
+            console::capture(Source::DebugOutput, &text);
+        });
+    }
+
+    output_debug_string_a(output_string)
+}
+
+unsafe extern "system" fn output_debug_string_w_impl(output_string: PCWSTR) {
+    let _call = HookCall::enter();
+
+    let Trampolines { output_debug_string_w, .. } =
+        TRAMPOLINES.get().expect("Console trampolines uninitialized");
+
+    if !output_string.is_null() {
+        capture_once(|| {
+            let text = String::from_utf16_lossy(output_string.as_wide());
+            console::capture(Source::DebugOutput, &text);
+        });
+    }
+
+    // Nested `OutputDebugStringA` calls were captured above already.
+    without_capture(|| output_debug_string_w(output_string))
+}
+
+unsafe fn get_target_addrs() -> std::result::Result<
+    (WriteConsoleAType, WriteConsoleWType, OutputDebugStringAType, OutputDebugStringWType),
+    crate::Error,
+> {
+    let kernel32 =
+        GetModuleHandleA(s!("kernel32.dll")).context("GetModuleHandleA(kernel32.dll)")?;
+
+    macro_rules! resolve {
+        ($name:literal) => {{
+            let func = GetProcAddress(kernel32, s!($name))
+                .ok_or(crate::Error::Missing(concat!("GetProcAddress(", $name, ")")))?;
+            resolve_target::<()>(concat!("kernel32.", $name), None, func as usize)
+        }};
+    }
+
+    Ok((
+        mem::transmute::<usize, WriteConsoleAType>(resolve!("WriteConsoleA")),
+        mem::transmute::<usize, WriteConsoleWType>(resolve!("WriteConsoleW")),
+        mem::transmute::<usize, OutputDebugStringAType>(resolve!("OutputDebugStringA")),
+        mem::transmute::<usize, OutputDebugStringWType>(resolve!("OutputDebugStringW")),
+    ))
+}
+
+/// Hooks capturing the game's console and debug output.
+pub struct ConsoleHooks([MhHook; 4]);
+
+impl ConsoleHooks {
+    /// Construct a set of [`MhHook`]s that capture the game's console and
+    /// debug output into the [`console`](crate::console) module.
+    ///
+    /// The following functions are hooked:
+    /// - `kernel32.WriteConsoleA`
+    /// - `kernel32.WriteConsoleW`
+    /// - `kernel32.OutputDebugStringA`
+    /// - `kernel32.OutputDebugStringW`
+    ///
+    /// # Panics
+    ///
+    /// If the hooks can't be created. See [`try_new`](Self::try_new).
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn new() -> Self {
+        Self::try_new().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`new`](Self::new), returning an [`Error`](crate::Error) if the
+    /// hooks can't be created.
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn try_new() -> std::result::Result<Self, crate::Error> {
+        let (
+            write_console_a_addr,
+            write_console_w_addr,
+            output_debug_string_a_addr,
+            output_debug_string_w_addr,
+        ) = get_target_addrs()?;
+
+        let hook = |name: &'static str, target: *const c_void, detour: *const c_void| {
+            trace!("{name} = {target:p}");
+            MhHook::named(name, target as *mut _, detour as *mut _)
+                .map_err(crate::Error::hook(name))
+        };
+
+        let hooks = [
+            hook(
+                "kernel32.WriteConsoleA",
+                write_console_a_addr as *const c_void,
+                write_console_a_impl as *const c_void,
+            )?,
+            hook(
+                "kernel32.WriteConsoleW",
+                write_console_w_addr as *const c_void,
+                write_console_w_impl as *const c_void,
+            )?,
+            hook(
+                "kernel32.OutputDebugStringA",
+                output_debug_string_a_addr as *const c_void,
+                output_debug_string_a_impl as *const c_void,
+            )?,
+            hook(
+                "kernel32.OutputDebugStringW",
+                output_debug_string_w_addr as *const c_void,
+                output_debug_string_w_impl as *const c_void,
+            )?,
+        ];
+
+        TRAMPOLINES.get_or_init(|| trampolines(&hooks));
+
+        Ok(Self(hooks))
+    }
+}
+
+unsafe fn trampolines(
+    [hook_write_console_a, hook_write_console_w, hook_output_debug_string_a, hook_output_debug_string_w]: &[MhHook; 4],
+) -> Trampolines {
+    Trampolines {
+        write_console_a: mem::transmute::<*mut c_void, WriteConsoleAType>(
+            hook_write_console_a.trampoline(),
+        ),
+        write_console_w: mem::transmute::<*mut c_void, WriteConsoleWType>(
+            hook_write_console_w.trampoline(),
+        ),
+        output_debug_string_a: mem::transmute::<*mut c_void, OutputDebugStringAType>(
+            hook_output_debug_string_a.trampoline(),
+        ),
+        output_debug_string_w: mem::transmute::<*mut c_void, OutputDebugStringWType>(
+            hook_output_debug_string_w.trampoline(),
+        ),
+    }
+}
+
+impl Hooks for ConsoleHooks {
+    fn from_render_loop<T>(t: T) -> Box<Self>
+    where
+        Self: Sized,
+        T: crate::ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Self::try_from_render_loop(t).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    fn try_from_render_loop<T>(_: T) -> std::result::Result<Box<Self>, crate::Error>
+    where
+        Self: Sized,
+        T: crate::ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Err(crate::Error::NoRenderLoop("ConsoleHooks"))
+    }
+
+    fn hooks(&self) -> &[MhHook] {
+        &self.0
+    }
+
+    fn hooks_mut(&mut self) -> &mut [MhHook] {
+        &mut self.0
+    }
+
+    unsafe fn reload_trampolines(&mut self) {
+        TRAMPOLINES.take();
+        TRAMPOLINES.get_or_init(|| trampolines(&self.0));
+    }
+
+    unsafe fn unhook(&mut self) {
+        TRAMPOLINES.take();
+    }
+}
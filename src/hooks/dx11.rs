@@ -1,8 +1,9 @@
 //! Hooks for DirectX 11.
 
 use std::ffi::c_void;
-use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::OnceLock;
+use std::{mem, ptr};
 
 use imgui::Context;
 use once_cell::sync::OnceCell;
@@ -14,32 +15,114 @@ use windows::Win32::Graphics::Direct3D::{
     D3D_DRIVER_TYPE_NULL, D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_11_0,
 };
 use windows::Win32::Graphics::Direct3D11::{
-    D3D11CreateDeviceAndSwapChain, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D,
-    D3D11_CREATE_DEVICE_FLAG, D3D11_SDK_VERSION,
+    D3D11CreateDeviceAndSwapChain, ID3D11Device, ID3D11DeviceContext, ID3D11RenderTargetView,
+    ID3D11Texture2D, D3D11_CREATE_DEVICE_FLAG, D3D11_DEVICE_CONTEXT_IMMEDIATE, D3D11_SDK_VERSION,
+    D3D11_TEXTURE2D_DESC,
 };
 use windows::Win32::Graphics::Dxgi::Common::{
-    DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_MODE_DESC, DXGI_MODE_SCALING_UNSPECIFIED,
+    DXGI_FORMAT, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_MODE_DESC, DXGI_MODE_SCALING_UNSPECIFIED,
     DXGI_MODE_SCANLINE_ORDER_UNSPECIFIED, DXGI_SAMPLE_DESC,
 };
 use windows::Win32::Graphics::Dxgi::{
     IDXGISwapChain, DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_EFFECT_DISCARD, DXGI_USAGE_RENDER_TARGET_OUTPUT,
 };
 
-use super::DummyHwnd;
+use super::{
+    active_api, bypasses, claim_api, release_api, render_frame, resolve_target, DummyHwnd, HookCall,
+};
+use crate::error::Context as _;
+use crate::instances::HookedApis;
 use crate::mh::MhHook;
-use crate::renderer::{D3D11RenderEngine, Pipeline};
-use crate::{util, Hooks, ImguiRenderLoop};
+pub use crate::renderer::DepthTarget;
+use crate::renderer::{reset_if_stale, D3D11RenderEngine, Pipeline, StateBackup};
+use crate::util::trace_hot_path;
+use crate::{frame_step, game_thread, timing, util, Hooks, ImguiRenderLoop};
 
 type DXGISwapChainPresentType =
     unsafe extern "system" fn(This: IDXGISwapChain, SyncInterval: u32, Flags: u32) -> HRESULT;
 
+type D3D11OMSetRenderTargetsType = unsafe extern "system" fn(
+    This: ID3D11DeviceContext,
+    num_views: u32,
+    render_target_views: *const *mut c_void,
+    depth_stencil_view: *mut c_void,
+);
+
 struct Trampolines {
     dxgi_swap_chain_present: DXGISwapChainPresentType,
+    d3d11_om_set_render_targets: Option<D3D11OMSetRenderTargetsType>,
 }
 
 static mut TRAMPOLINES: OnceLock<Trampolines> = OnceLock::new();
+static DEPTH_TARGET: Mutex<Option<DepthTarget>> = Mutex::new(None);
 static mut PIPELINE: OnceCell<Mutex<Pipeline<D3D11RenderEngine>>> = OnceCell::new();
-static mut RENDER_LOOP: OnceCell<Box<dyn ImguiRenderLoop + Send + Sync>> = OnceCell::new();
+static mut RENDER_LOOPS: OnceCell<Vec<Box<dyn ImguiRenderLoop + Send + Sync>>> = OnceCell::new();
+// Whether a render loop is registered, either waiting in `RENDER_LOOPS` or
+// moved into the pipeline. Until then, the present hook passes through at the
+// cost of a single load.
+static RENDER_LOOP_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Set the depth buffer that [depth-tested](crate::depth) overlay elements are
+/// tested against. Takes effect on the next frame.
+///
+/// The game usually creates its depth buffer once and recreates it when the
+/// resolution changes, so the view has to be updated accordingly. Pass `None`
+/// to draw depth-tested elements on top of everything.
+pub fn set_depth_target(depth_target: Option<DepthTarget>) {
+    *DEPTH_TARGET.lock() = depth_target;
+}
+
+type EarlyPass = Box<dyn FnMut(&ID3D11DeviceContext, &ID3D11RenderTargetView) + Send>;
+
+static EARLY_PASS: Mutex<Option<(RenderTargetPattern, EarlyPass)>> = Mutex::new(None);
+// Render targets matching the early pass pattern unbound in the current frame.
+static EARLY_PASS_MATCHES: AtomicUsize = AtomicUsize::new(0);
+static IN_EARLY_PASS: AtomicBool = AtomicBool::new(false);
+
+/// The render target an early pass draws into. See [`set_early_pass`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RenderTargetPattern {
+    /// Format of the render target, e.g. `DXGI_FORMAT_R11G11B10_FLOAT` for
+    /// the HDR scene target of many engines. Any format if `None`.
+    pub format: Option<DXGI_FORMAT>,
+    /// Width and height of the render target. Any size if `None`.
+    pub size: Option<(u32, u32)>,
+    /// Which of the matching render targets to draw into, counting from 0 in
+    /// the order the game unbinds them during the frame.
+    pub occurrence: usize,
+}
+
+impl RenderTargetPattern {
+    fn matches(&self, desc: &D3D11_TEXTURE2D_DESC) -> bool {
+        self.format.map_or(true, |format| format == desc.Format)
+            && self.size.map_or(true, |size| size == (desc.Width, desc.Height))
+    }
+}
+
+/// Draw into one of the game's render targets earlier in the frame, e.g.
+/// into the scene before the game's post-processing reads it, so that the
+/// drawing gets tone mapped, blurred or distorted along with the world.
+///
+/// `draw` is called on the render thread with the game's immediate context
+/// and the render target matching `pattern`, right before the game binds
+/// other render targets. The pipeline state is saved before `draw` and
+/// restored after it, so `draw` may change it freely.
+///
+/// The `ID3D11DeviceContext::OMSetRenderTargets` hook this relies on is only
+/// installed if an early pass is set when the DirectX 11 hooks are created:
+/// call this before applying them. Calling it again later replaces the
+/// pattern and the callback.
+pub fn set_early_pass(
+    pattern: RenderTargetPattern,
+    draw: impl FnMut(&ID3D11DeviceContext, &ID3D11RenderTargetView) + Send + 'static,
+) {
+    *EARLY_PASS.lock() = Some((pattern, Box::new(draw)));
+}
+
+/// Stop drawing the early pass set with [`set_early_pass`].
+pub fn clear_early_pass() {
+    EARLY_PASS.lock().take();
+}
 
 unsafe fn init_pipeline(swap_chain: &IDXGISwapChain) -> Result<Mutex<Pipeline<D3D11RenderEngine>>> {
     let hwnd = util::try_out_param(|v| swap_chain.GetDesc(v)).map(|desc| desc.OutputWindow)?;
@@ -47,21 +130,24 @@ unsafe fn init_pipeline(swap_chain: &IDXGISwapChain) -> Result<Mutex<Pipeline<D3
     let mut ctx = Context::create();
     let engine = D3D11RenderEngine::new(&swap_chain.GetDevice()?, &mut ctx)?;
 
-    let Some(render_loop) = RENDER_LOOP.take() else {
+    let Some(render_loops) = RENDER_LOOPS.take() else {
         error!("Render loop not yet initialized");
         return Err(Error::from_hresult(HRESULT(-1)));
     };
 
-    let pipeline = Pipeline::new(hwnd, ctx, engine, render_loop).map_err(|(e, render_loop)| {
-        RENDER_LOOP.get_or_init(move || render_loop);
-        e
-    })?;
+    let pipeline =
+        Pipeline::new(hwnd, ctx, engine, render_loops).map_err(|(e, render_loops)| {
+            RENDER_LOOPS.get_or_init(move || render_loops);
+            e
+        })?;
 
     Ok(Mutex::new(pipeline))
 }
 
 fn render(swap_chain: &IDXGISwapChain) -> Result<()> {
     unsafe {
+        reset_if_stale(&mut PIPELINE, &mut RENDER_LOOPS);
+
         let pipeline = PIPELINE.get_or_try_init(|| init_pipeline(swap_chain))?;
 
         let Some(mut pipeline) = pipeline.try_lock() else {
@@ -73,6 +159,8 @@ fn render(swap_chain: &IDXGISwapChain) -> Result<()> {
 
         let target: ID3D11Texture2D = swap_chain.GetBuffer(0)?;
 
+        pipeline.engine_mut().set_depth_target(DEPTH_TARGET.lock().clone());
+
         pipeline.render(target)?;
     }
     Ok(())
@@ -83,18 +171,104 @@ unsafe extern "system" fn dxgi_swap_chain_present_impl(
     sync_interval: u32,
     flags: u32,
 ) -> HRESULT {
-    let Trampolines { dxgi_swap_chain_present } =
+    let _call = HookCall::enter();
+
+    let Trampolines { dxgi_swap_chain_present, .. } =
         TRAMPOLINES.get().expect("DirectX 11 trampolines uninitialized");
 
-    if let Err(e) = render(&swap_chain) {
-        error!("Render error: {e:?}");
+    if !RENDER_LOOP_REGISTERED.load(Ordering::Relaxed)
+        || bypasses(|| util::try_out_param(|v| swap_chain.GetDesc(v)).ok().map(|d| d.OutputWindow))
+        || !claim_api(HookedApis::Dx11)
+    {
+        return dxgi_swap_chain_present(swap_chain, sync_interval, flags);
     }
 
-    trace!("Call IDXGISwapChain::Present trampoline");
+    timing::set_sync_interval(sync_interval);
+    timing::update_present_stats(&swap_chain);
+
+    render_frame(|| render(&swap_chain));
+
+    game_thread::run_queued();
+    EARLY_PASS_MATCHES.store(0, Ordering::Relaxed);
+    frame_step::pace();
+
+    trace_hot_path!("Call IDXGISwapChain::Present trampoline");
     dxgi_swap_chain_present(swap_chain, sync_interval, flags)
 }
 
-fn get_target_addrs() -> DXGISwapChainPresentType {
+unsafe extern "system" fn d3d11_om_set_render_targets_impl(
+    context: ID3D11DeviceContext,
+    num_views: u32,
+    render_target_views: *const *mut c_void,
+    depth_stencil_view: *mut c_void,
+) {
+    let _call = HookCall::enter();
+
+    let Trampolines { d3d11_om_set_render_targets, .. } =
+        TRAMPOLINES.get().expect("DirectX 11 trampolines uninitialized");
+    let d3d11_om_set_render_targets =
+        d3d11_om_set_render_targets.expect("OMSetRenderTargets trampoline uninitialized");
+
+    // Deferred contexts only record commands, and calls made by the early pass
+    // itself must not trigger it again.
+    if !IN_EARLY_PASS.load(Ordering::Relaxed)
+        && active_api() == Some(HookedApis::Dx11)
+        && context.GetType() == D3D11_DEVICE_CONTEXT_IMMEDIATE
+    {
+        let next_target = match num_views {
+            0 => ptr::null_mut(),
+            _ if render_target_views.is_null() => ptr::null_mut(),
+            _ => *render_target_views,
+        };
+        draw_early_pass(&context, next_target);
+    }
+
+    trace_hot_path!("Call ID3D11DeviceContext::OMSetRenderTargets trampoline");
+    d3d11_om_set_render_targets(context, num_views, render_target_views, depth_stencil_view)
+}
+
+// Draw the early pass into the render target the game is about to unbind, if
+// it is the one the pattern designates.
+unsafe fn draw_early_pass(context: &ID3D11DeviceContext, next_target: *mut c_void) {
+    let Some(mut early_pass) = EARLY_PASS.try_lock() else {
+        return;
+    };
+    let Some((pattern, draw)) = early_pass.as_mut() else {
+        return;
+    };
+
+    let mut bound = [None];
+    context.OMGetRenderTargets(Some(&mut bound), None);
+    let [Some(target)] = bound else {
+        return;
+    };
+
+    // Binding the same target again doesn't end the work on it.
+    if target.as_raw() == next_target {
+        return;
+    }
+
+    let Ok(texture) = target.GetResource().and_then(|r| r.cast::<ID3D11Texture2D>()) else {
+        return;
+    };
+    let mut desc = D3D11_TEXTURE2D_DESC::default();
+    texture.GetDesc(&mut desc);
+
+    if !pattern.matches(&desc)
+        || EARLY_PASS_MATCHES.fetch_add(1, Ordering::Relaxed) != pattern.occurrence
+    {
+        return;
+    }
+
+    IN_EARLY_PASS.store(true, Ordering::Relaxed);
+    let state_backup = StateBackup::backup(context);
+    draw(context, &target);
+    state_backup.restore(context);
+    IN_EARLY_PASS.store(false, Ordering::Relaxed);
+}
+
+fn get_target_addrs(
+) -> std::result::Result<(DXGISwapChainPresentType, D3D11OMSetRenderTargetsType), crate::Error> {
     let mut p_device: Option<ID3D11Device> = None;
     let mut p_context: Option<ID3D11DeviceContext> = None;
     let mut p_swap_chain: Option<IDXGISwapChain> = None;
@@ -128,23 +302,35 @@ fn get_target_addrs() -> DXGISwapChainPresentType {
             None,
             Some(&mut p_context),
         )
-        .expect("D3D11CreateDeviceAndSwapChain failed");
+        .context("D3D11CreateDeviceAndSwapChain")?;
     }
 
-    let swap_chain = p_swap_chain.unwrap();
+    let swap_chain = p_swap_chain.ok_or(crate::Error::Missing("D3D11CreateDeviceAndSwapChain"))?;
+    let context = p_context.ok_or(crate::Error::Missing("D3D11CreateDeviceAndSwapChain"))?;
 
-    let present_ptr: DXGISwapChainPresentType = unsafe {
-        mem::transmute::<
-            unsafe extern "system" fn(*mut c_void, u32, u32) -> HRESULT,
-            DXGISwapChainPresentType,
-        >(swap_chain.vtable().Present)
+    let vtable = swap_chain.vtable();
+    let present_addr =
+        unsafe { resolve_target("IDXGISwapChain::Present", Some(vtable), vtable.Present as usize) };
+
+    let vtable = context.vtable();
+    let om_set_render_targets_addr = unsafe {
+        resolve_target(
+            "ID3D11DeviceContext::OMSetRenderTargets",
+            Some(vtable),
+            vtable.OMSetRenderTargets as usize,
+        )
     };
 
-    present_ptr
+    unsafe {
+        Ok((
+            mem::transmute::<usize, DXGISwapChainPresentType>(present_addr),
+            mem::transmute::<usize, D3D11OMSetRenderTargetsType>(om_set_render_targets_addr),
+        ))
+    }
 }
 
 /// Hooks for DirectX 11.
-pub struct ImguiDx11Hooks([MhHook; 1]);
+pub struct ImguiDx11Hooks(Vec<MhHook>);
 
 impl ImguiDx11Hooks {
     /// Construct a set of [`MhHook`]s that will render UI via the
@@ -152,6 +338,12 @@ impl ImguiDx11Hooks {
     ///
     /// The following functions are hooked:
     /// - `IDXGISwapChain::Present`
+    /// - `ID3D11DeviceContext::OMSetRenderTargets`, if an [early
+    ///   pass](set_early_pass) is set
+    ///
+    /// # Panics
+    ///
+    /// If the hooks can't be created. See [`try_new`](Self::try_new).
     ///
     /// # Safety
     ///
@@ -160,23 +352,65 @@ impl ImguiDx11Hooks {
     where
         T: ImguiRenderLoop + Send + Sync + 'static,
     {
-        let dxgi_swap_chain_present_addr = get_target_addrs();
+        Self::try_new(t).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`new`](Self::new), returning an [`Error`](crate::Error) if the
+    /// hooks can't be created.
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn try_new<T>(t: T) -> std::result::Result<Self, crate::Error>
+    where
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        let (dxgi_swap_chain_present_addr, d3d11_om_set_render_targets_addr) = get_target_addrs()?;
 
         trace!("IDXGISwapChain::Present = {:p}", dxgi_swap_chain_present_addr as *const c_void);
-        let hook_present = MhHook::new(
+        let hook_present = MhHook::named(
+            "IDXGISwapChain::Present",
             dxgi_swap_chain_present_addr as *mut _,
             dxgi_swap_chain_present_impl as *mut _,
         )
-        .expect("couldn't create IDXGISwapChain::Present hook");
+        .map_err(crate::Error::hook("IDXGISwapChain::Present"))?;
+
+        let mut hooks = vec![hook_present];
+
+        if EARLY_PASS.lock().is_some() {
+            trace!(
+                "ID3D11DeviceContext::OMSetRenderTargets = {:p}",
+                d3d11_om_set_render_targets_addr as *const c_void
+            );
+            let hook_om_set_render_targets = MhHook::named(
+                "ID3D11DeviceContext::OMSetRenderTargets",
+                d3d11_om_set_render_targets_addr as *mut _,
+                d3d11_om_set_render_targets_impl as *mut _,
+            )
+            .map_err(crate::Error::hook("ID3D11DeviceContext::OMSetRenderTargets"))?;
+            hooks.push(hook_om_set_render_targets);
+        }
+
+        RENDER_LOOPS.get_or_init(|| vec![Box::new(t)]);
+        RENDER_LOOP_REGISTERED.store(true, Ordering::SeqCst);
+        TRAMPOLINES.get_or_init(|| trampolines(&hooks));
+
+        Ok(Self(hooks))
+    }
+}
 
-        RENDER_LOOP.get_or_init(|| Box::new(t));
-        TRAMPOLINES.get_or_init(|| Trampolines {
-            dxgi_swap_chain_present: mem::transmute::<*mut c_void, DXGISwapChainPresentType>(
-                hook_present.trampoline(),
-            ),
-        });
+unsafe fn trampolines(hooks: &[MhHook]) -> Trampolines {
+    let [hook_present, hook_om_set_render_targets @ ..] = hooks else {
+        panic!("IDXGISwapChain::Present hook missing");
+    };
 
-        Self([hook_present])
+    Trampolines {
+        dxgi_swap_chain_present: mem::transmute::<*mut c_void, DXGISwapChainPresentType>(
+            hook_present.trampoline(),
+        ),
+        d3d11_om_set_render_targets: hook_om_set_render_targets.first().map(|hook| {
+            mem::transmute::<*mut c_void, D3D11OMSetRenderTargetsType>(hook.trampoline())
+        }),
     }
 }
 
@@ -189,13 +423,39 @@ impl Hooks for ImguiDx11Hooks {
         Box::new(unsafe { Self::new(t) })
     }
 
+    fn try_from_render_loop<T>(t: T) -> std::result::Result<Box<Self>, crate::Error>
+    where
+        Self: Sized,
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        unsafe { Self::try_new(t) }.map(Box::new)
+    }
+
     fn hooks(&self) -> &[MhHook] {
         &self.0
     }
 
+    fn hooks_mut(&mut self) -> &mut [MhHook] {
+        &mut self.0
+    }
+
+    unsafe fn reload_trampolines(&mut self) {
+        TRAMPOLINES.take();
+        TRAMPOLINES.get_or_init(|| trampolines(&self.0));
+    }
+
+    fn add_render_loop(&mut self, render_loop: Box<dyn ImguiRenderLoop + Send + Sync>) {
+        match unsafe { RENDER_LOOPS.get_mut() } {
+            Some(render_loops) => render_loops.push(render_loop),
+            None => error!("Render loops already moved into the pipeline"),
+        }
+    }
+
     unsafe fn unhook(&mut self) {
+        RENDER_LOOP_REGISTERED.store(false, Ordering::SeqCst);
+        release_api(HookedApis::Dx11);
         TRAMPOLINES.take();
         PIPELINE.take().map(|p| p.into_inner().take());
-        RENDER_LOOP.take(); // should already be null
+        RENDER_LOOPS.take(); // should already be null
     }
 }
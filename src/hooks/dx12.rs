@@ -2,33 +2,42 @@
 
 use std::ffi::c_void;
 use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicU32, AtomicUsize, Ordering};
 use std::sync::OnceLock;
 
 use imgui::Context;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 use tracing::{debug, error, trace, warn};
-use windows::core::{Error, Interface, Result, HRESULT};
-use windows::Win32::Foundation::BOOL;
+use windows::core::{Error, IUnknown_Vtbl, Interface, Result, GUID, HRESULT, HSTRING, PCWSTR};
+use windows::Win32::Foundation::{BOOL, HANDLE};
 use windows::Win32::Graphics::Direct3D::D3D_FEATURE_LEVEL_11_0;
+use windows::Win32::Graphics::Direct3D11::ID3D11Device;
+use windows::Win32::Graphics::Direct3D11on12::ID3D11On12Device;
 use windows::Win32::Graphics::Direct3D12::{
-    D3D12CreateDevice, ID3D12CommandList, ID3D12CommandQueue, ID3D12Device, ID3D12Resource,
-    D3D12_COMMAND_LIST_TYPE_DIRECT, D3D12_COMMAND_QUEUE_DESC, D3D12_COMMAND_QUEUE_FLAG_NONE,
+    D3D12CreateDevice, ID3D12CommandList, ID3D12CommandQueue, ID3D12Device, ID3D12Fence,
+    ID3D12Resource, D3D12_COMMAND_LIST_TYPE_COMPUTE, D3D12_COMMAND_LIST_TYPE_DIRECT,
+    D3D12_COMMAND_QUEUE_DESC, D3D12_COMMAND_QUEUE_FLAG_NONE,
 };
 use windows::Win32::Graphics::Dxgi::Common::{
     DXGI_FORMAT, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_MODE_DESC, DXGI_MODE_SCALING_UNSPECIFIED,
     DXGI_MODE_SCANLINE_ORDER_UNSPECIFIED, DXGI_RATIONAL, DXGI_SAMPLE_DESC,
 };
 use windows::Win32::Graphics::Dxgi::{
-    CreateDXGIFactory2, IDXGIFactory2, IDXGISwapChain, IDXGISwapChain3, DXGI_SWAP_CHAIN_DESC,
-    DXGI_SWAP_CHAIN_FLAG_ALLOW_MODE_SWITCH, DXGI_SWAP_EFFECT_FLIP_DISCARD,
+    CreateDXGIFactory2, IDXGIFactory2, IDXGISwapChain, IDXGISwapChain3, DXGI_ERROR_INVALID_CALL,
+    DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_CHAIN_FLAG_ALLOW_MODE_SWITCH, DXGI_SWAP_EFFECT_FLIP_DISCARD,
     DXGI_USAGE_RENDER_TARGET_OUTPUT,
 };
+use windows::Win32::System::Threading::GetCurrentProcessId;
 
-use super::DummyHwnd;
+use super::{bypasses, claim_api, release_api, render_frame, resolve_target, DummyHwnd, HookCall};
+use crate::error::Context as _;
+use crate::instances::HookedApis;
 use crate::mh::MhHook;
-use crate::renderer::{D3D12RenderEngine, Pipeline};
-use crate::{util, Hooks, ImguiRenderLoop};
+use crate::renderer::{self, reset_if_stale, D3D12RenderEngine, Pipeline};
+pub use crate::renderer::{D3D12Capabilities, FrameCompletion, VideoMemoryInfo};
+use crate::util::trace_hot_path;
+use crate::{frame_step, game_thread, names, timing, util, Hooks, ImguiRenderLoop};
 
 type DXGISwapChainPresentType =
     unsafe extern "system" fn(This: IDXGISwapChain3, SyncInterval: u32, Flags: u32) -> HRESULT;
@@ -42,6 +51,11 @@ type DXGISwapChainResizeBuffersType = unsafe extern "system" fn(
     flags: u32,
 ) -> HRESULT;
 
+type DXGISwapChainResizeTargetType = unsafe extern "system" fn(
+    This: IDXGISwapChain3,
+    new_target_parameters: *const DXGI_MODE_DESC,
+) -> HRESULT;
+
 type D3D12CommandQueueExecuteCommandListsType = unsafe extern "system" fn(
     This: ID3D12CommandQueue,
     num_command_lists: u32,
@@ -51,24 +65,68 @@ type D3D12CommandQueueExecuteCommandListsType = unsafe extern "system" fn(
 struct Trampolines {
     dxgi_swap_chain_present: DXGISwapChainPresentType,
     dxgi_swap_chain_resize_buffers: DXGISwapChainResizeBuffersType,
+    dxgi_swap_chain_resize_target: DXGISwapChainResizeTargetType,
     d3d12_command_queue_execute_command_lists: D3D12CommandQueueExecuteCommandListsType,
 }
 
 static mut TRAMPOLINES: OnceLock<Trampolines> = OnceLock::new();
 
+// Presents to wait for the game to submit on the swap chain's command queue
+// before falling back to another queue. Some games only submit on queues
+// created before the hooks were applied, or through indirect submission.
+static COMMAND_QUEUE_FALLBACK_PRESENTS: AtomicU32 = AtomicU32::new(120);
+
+// The fallback queue the overlay renders on, if it owns it, and the direct
+// queue the game last submitted work on. The overlay is ordered after the work
+// on that queue, which is the game's frame as long as the game renders on a
+// single direct queue: the fallback is best-effort otherwise.
+static FALLBACK_QUEUE: AtomicUsize = AtomicUsize::new(0);
+static GAME_QUEUE: Mutex<Option<ID3D12CommandQueue>> = Mutex::new(None);
+
+// Set how many presents to wait for the game's command queue before falling
+// back to another one.
+pub(crate) fn set_command_queue_fallback_presents(presents: u32) {
+    COMMAND_QUEUE_FALLBACK_PRESENTS.store(presents, Ordering::Relaxed);
+}
+
 enum InitializationContext {
     Empty,
-    WithSwapChain(IDXGISwapChain3),
-    Complete(IDXGISwapChain3, ID3D12CommandQueue),
+    // The swap chain, and how many times it presented since.
+    WithSwapChain(IDXGISwapChain3, u32),
+    // The swap chain, its command queue, and whether the queue is hudhook's own.
+    Complete(IDXGISwapChain3, ID3D12CommandQueue, bool),
     Done,
 }
 
 impl InitializationContext {
-    // Transition to a state where the swap chain is set. Ignore other mutations.
+    // Transition to a state where the swap chain is set, and fall back to
+    // another command queue if the game's one can't be captured. Ignore other
+    // mutations.
     fn insert_swap_chain(&mut self, swap_chain: &IDXGISwapChain3) {
         *self = match mem::replace(self, InitializationContext::Empty) {
             InitializationContext::Empty => {
-                InitializationContext::WithSwapChain(swap_chain.clone())
+                InitializationContext::WithSwapChain(swap_chain.clone(), 0)
+            },
+            InitializationContext::WithSwapChain(swap_chain, presents)
+                if presents + 1 >= COMMAND_QUEUE_FALLBACK_PRESENTS.load(Ordering::Relaxed) =>
+            {
+                match unsafe { Self::fallback_command_queue(&swap_chain) } {
+                    Ok((command_queue, owned)) => {
+                        warn!(
+                            "No command queue captured after {presents} presents, falling back to \
+                             {command_queue:?}; the overlay is ordered after the game's last \
+                             direct queue, which may not be the one rendering the frame"
+                        );
+                        InitializationContext::Complete(swap_chain, command_queue, owned)
+                    },
+                    Err(e) => {
+                        error!("Could not create a fallback command queue: {e:?}");
+                        InitializationContext::WithSwapChain(swap_chain, 0)
+                    },
+                }
+            },
+            InitializationContext::WithSwapChain(swap_chain, presents) => {
+                InitializationContext::WithSwapChain(swap_chain, presents + 1)
             },
             s => s,
         }
@@ -78,15 +136,15 @@ impl InitializationContext {
     // is associated with it.
     fn insert_command_queue(&mut self, command_queue: &ID3D12CommandQueue) {
         *self = match mem::replace(self, InitializationContext::Empty) {
-            InitializationContext::WithSwapChain(swap_chain) => {
+            InitializationContext::WithSwapChain(swap_chain, presents) => {
                 if unsafe { Self::check_command_queue(&swap_chain, command_queue) } {
                     trace!(
                         "Found command queue matching swap chain {swap_chain:?} at \
                          {command_queue:?}"
                     );
-                    InitializationContext::Complete(swap_chain, command_queue.clone())
+                    InitializationContext::Complete(swap_chain, command_queue.clone(), false)
                 } else {
-                    InitializationContext::WithSwapChain(swap_chain)
+                    InitializationContext::WithSwapChain(swap_chain, presents)
                 }
             },
             s => s,
@@ -94,9 +152,9 @@ impl InitializationContext {
     }
 
     // Retrieve the values if the context is complete.
-    fn get(&self) -> Option<(IDXGISwapChain3, ID3D12CommandQueue)> {
-        if let InitializationContext::Complete(swap_chain, command_queue) = self {
-            Some((swap_chain.clone(), command_queue.clone()))
+    fn get(&self) -> Option<(IDXGISwapChain3, ID3D12CommandQueue, bool)> {
+        if let InitializationContext::Complete(swap_chain, command_queue, owned) = self {
+            Some((swap_chain.clone(), command_queue.clone(), *owned))
         } else {
             None
         }
@@ -109,10 +167,44 @@ impl InitializationContext {
         }
     }
 
+    // The queue DXGI reports for a D3D12 swap chain is the one it presents
+    // from. If it doesn't report one, create a queue of our own on the swap
+    // chain's device.
+    unsafe fn fallback_command_queue(
+        swap_chain: &IDXGISwapChain3,
+    ) -> Result<(ID3D12CommandQueue, bool)> {
+        if let Ok(command_queue) = swap_chain.GetDevice::<ID3D12CommandQueue>() {
+            return Ok((command_queue, false));
+        }
+
+        let back_buffer: ID3D12Resource = swap_chain.GetBuffer(0)?;
+        let device: ID3D12Device = util::try_out_ptr(|v| back_buffer.GetDevice(v))?;
+        let command_queue: ID3D12CommandQueue =
+            device.CreateCommandQueue(&D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: 0,
+                Flags: D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            })?;
+        command_queue.SetName(&names::debug_object("Fallback Command Queue"))?;
+
+        Ok((command_queue, true))
+    }
+
     unsafe fn check_command_queue(
         swap_chain: &IDXGISwapChain3,
         command_queue: &ID3D12CommandQueue,
     ) -> bool {
+        // The swap chain presents from a direct queue, or from a compute queue in a
+        // few engines. This skips the copy queues of the game, and those D3D11On12
+        // creates internally.
+        if !matches!(
+            command_queue.GetDesc().Type,
+            D3D12_COMMAND_LIST_TYPE_DIRECT | D3D12_COMMAND_LIST_TYPE_COMPUTE
+        ) {
+            return false;
+        }
+
         let swap_chain_ptr = swap_chain.as_raw() as *mut *mut c_void;
         let readable_ptrs = util::readable_region(swap_chain_ptr, 512);
 
@@ -136,42 +228,256 @@ impl InitializationContext {
     }
 }
 
+/// Where the DirectX 12 overlay is rendered.
+///
+/// Use [`set_overlay_output`] to change it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayOutput {
+    /// Render into the game's back buffer.
+    #[default]
+    BackBuffer,
+    /// Render only into a shared texture, keeping the game's own screen
+    /// clean.
+    SharedTexture,
+    /// Render into both the back buffer and a shared texture.
+    Both,
+}
+
+static OVERLAY_OUTPUT: Mutex<OverlayOutput> = Mutex::new(OverlayOutput::BackBuffer);
+static SHARED_TEXTURE_HANDLE: AtomicIsize = AtomicIsize::new(0);
+// Built on the first frame with a shared output, as the name prefix is set
+// once before the hooks are applied.
+static SHARED_TEXTURE_NAME: OnceCell<HSTRING> = OnceCell::new();
+
+/// Set where the overlay is rendered. Takes effect on the next frame.
+///
+/// In the [`OverlayOutput::SharedTexture`] and [`OverlayOutput::Both`]
+/// modes, the overlay is rendered into a `B8G8R8A8_UNORM` texture, the size
+/// of the back buffer and cleared to transparent every frame. It is shared
+/// via a named NT handle, so that other processes (e.g. a companion OBS
+/// plugin) can open it with `ID3D12Device::OpenSharedHandleByName` or
+/// `ID3D11Device1::OpenSharedResourceByName` and composite it onto the
+/// stream. The texture is recreated when the back buffer is resized, so
+/// consumers should reopen it when its size changes.
+pub fn set_overlay_output(output: OverlayOutput) {
+    *OVERLAY_OUTPUT.lock() = output;
+}
+
+/// Name of the shared overlay texture for the current process.
+///
+/// The name is `Local\hudhook-overlay-<pid>`, with `hudhook` replaced by the
+/// [name prefix](crate::names) if one is set.
+pub fn shared_texture_name() -> String {
+    names::kernel_object(&format!("overlay-{}", unsafe { GetCurrentProcessId() })).to_string()
+}
+
+/// NT handle of the shared overlay texture, if one is currently in use.
+///
+/// The handle is owned by hudhook and only valid until the texture is
+/// recreated; duplicate it with `DuplicateHandle` to pass it to another
+/// process.
+pub fn shared_texture_handle() -> Option<HANDLE> {
+    match SHARED_TEXTURE_HANDLE.load(Ordering::SeqCst) {
+        0 => None,
+        handle => Some(HANDLE(handle)),
+    }
+}
+
+/// Where an individual window is visible when the overlay is also rendered
+/// into a shared texture. See [`set_window_visibility`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WindowVisibility {
+    /// Visible both on the game's screen and in the shared texture.
+    #[default]
+    Everywhere,
+    /// Visible only on the game's screen, e.g. for private cheat sheets
+    /// that shouldn't show up on stream.
+    BackBufferOnly,
+    /// Visible only in the shared texture, e.g. for stream-only widgets.
+    SharedTextureOnly,
+}
+
+static WINDOW_VISIBILITY: Mutex<Vec<(String, WindowVisibility)>> = Mutex::new(Vec::new());
+static WINDOW_VISIBILITY_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Set where the `imgui` window named `window_name` is visible. Child
+/// windows follow their parent. Takes effect on the next frame.
+///
+/// `window_name` is the full name passed to `Ui::window`, including any
+/// `##` suffix. Popups and tooltips are separate windows and must be
+/// registered on their own.
+pub fn set_window_visibility(window_name: &str, visibility: WindowVisibility) {
+    let mut windows = WINDOW_VISIBILITY.lock();
+    windows.retain(|(name, _)| name != window_name);
+    if visibility != WindowVisibility::Everywhere {
+        windows.push((window_name.to_string(), visibility));
+    }
+    WINDOW_VISIBILITY_CHANGED.store(true, Ordering::SeqCst);
+}
+
+/// Add a callback fired once the GPU has finished the overlay work of a
+/// frame, e.g. to read back resources written during the overlay pass.
+///
+/// Callbacks are fired on the render thread, at the start of a later frame,
+/// and must not add callbacks themselves. Frames that failed to render are
+/// never completed.
+pub fn on_frame_complete(callback: impl FnMut(FrameCompletion) + Send + 'static) {
+    renderer::add_frame_callback(Box::new(callback));
+}
+
+/// Remove all the callbacks added with [`on_frame_complete`].
+pub fn clear_frame_callbacks() {
+    renderer::clear_frame_callbacks();
+}
+
+/// Number of the overlay frame being rendered, or of the next one outside of
+/// rendering. Compare it with [`FrameCompletion::frame`].
+///
+/// Every render loop renders its own pass of a frame: with several render
+/// loops, each pass gets a number of its own.
+pub fn overlay_frame() -> u64 {
+    renderer::overlay_frame()
+}
+
+/// Fence the render engine signals with [`FrameCompletion::fence_value`] once
+/// the overlay work of a frame is done, if the engine is initialized. It is
+/// signaled on the game's command queue, or on a direct queue of hudhook's
+/// own for games presenting from a compute queue.
+///
+/// Wait for it on another queue with `ID3D12CommandQueue::Wait` to order GPU
+/// work after the overlay pass without blocking the CPU.
+pub fn overlay_fence() -> Option<ID3D12Fence> {
+    renderer::overlay_fence()
+}
+
+/// Objects created by the hooks that were still alive after the hooks were
+/// last unapplied, e.g. on [`eject`](crate::eject), along with the back buffer
+/// references still held. Empty if nothing leaked.
+///
+/// Leaked objects are only found with the D3D12 debug layer enabled: see
+/// [`util::enable_debug_interface`] and [`util::live_hudhook_objects`]. They
+/// are also logged as warnings.
+pub fn leaked_objects() -> Vec<String> {
+    LEAKED_OBJECTS.lock().clone()
+}
+
+static LEAKED_OBJECTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Size passed to the last `IDXGISwapChain::ResizeTarget` not yet followed by
+// `IDXGISwapChain::ResizeBuffers`.
+static PENDING_TARGET_SIZE: Mutex<Option<(u32, u32)>> = Mutex::new(None);
+// Size to resize the game's window to on the next present.
+static FORCED_TARGET_SIZE: Mutex<Option<(u32, u32)>> = Mutex::new(None);
+
+/// Size the game last asked its window or display mode to be resized to with
+/// `IDXGISwapChain::ResizeTarget`, until it resizes its back buffers. Some
+/// games change their resolution this way, before resizing the buffers.
+pub fn pending_target_size() -> Option<(u32, u32)> {
+    *PENDING_TARGET_SIZE.lock()
+}
+
+/// Resize the game's window, or its display mode when fullscreen, to `width`
+/// by `height` through `IDXGISwapChain::ResizeTarget` on the next present,
+/// like the game itself would. The game is expected to resize its back
+/// buffers in turn, which most games do on `WM_SIZE`.
+pub fn force_target_size(width: u32, height: u32) {
+    *FORCED_TARGET_SIZE.lock() = Some((width, height));
+}
+
+// Apply the size set with `force_target_size`, if any.
+unsafe fn apply_forced_target_size(swap_chain: &IDXGISwapChain3) {
+    let Some((width, height)) = FORCED_TARGET_SIZE.lock().take() else {
+        return;
+    };
+
+    // An unknown format and refresh rate keep the current ones.
+    let desc = DXGI_MODE_DESC { Width: width, Height: height, ..Default::default() };
+    if let Err(e) = swap_chain.ResizeTarget(&desc) {
+        error!("Couldn't resize the target to {width}x{height}: {e:?}");
+    }
+}
+
+// Record the objects created by the hooks that outlived the render engine.
+fn check_leaks(device: Option<ID3D12Device>) {
+    let mut leaked = device.map(|device| util::live_hudhook_objects(&device)).unwrap_or_default();
+    leaked.extend(
+        BACK_BUFFER_HOLDERS
+            .lock()
+            .iter()
+            .map(|(ptr, holder)| format!("Back buffer {ptr:#x} referenced by {holder}")),
+    );
+
+    for object in &leaked {
+        warn!("Leaked after unhooking: {object}");
+    }
+    *LEAKED_OBJECTS.lock() = leaked;
+}
+
 static INITIALIZATION_CONTEXT: Mutex<InitializationContext> =
     Mutex::new(InitializationContext::Empty);
 static mut PIPELINE: OnceCell<Mutex<Pipeline<D3D12RenderEngine>>> = OnceCell::new();
-static mut RENDER_LOOP: OnceCell<Box<dyn ImguiRenderLoop + Send + Sync>> = OnceCell::new();
+static mut RENDER_LOOPS: OnceCell<Vec<Box<dyn ImguiRenderLoop + Send + Sync>>> = OnceCell::new();
+// Whether a render loop is registered, either waiting in `RENDER_LOOPS` or
+// moved into the pipeline. Until then, the present hook passes through at the
+// cost of a single load.
+static RENDER_LOOP_REGISTERED: AtomicBool = AtomicBool::new(false);
 
-unsafe fn init_pipeline() -> Result<Mutex<Pipeline<D3D12RenderEngine>>> {
-    let Some((swap_chain, command_queue)) = ({ INITIALIZATION_CONTEXT.lock().get() }) else {
-        error!("Initialization context incomplete");
-        return Err(Error::from_hresult(HRESULT(-1)));
+// Create the pipeline, or return `None` while the initialization context is
+// incomplete, i.e. the game's command queue is yet to be captured, which isn't
+// a render error.
+unsafe fn init_pipeline() -> Result<Option<Mutex<Pipeline<D3D12RenderEngine>>>> {
+    let Some((swap_chain, command_queue, owned)) = ({ INITIALIZATION_CONTEXT.lock().get() }) else {
+        trace!("Initialization context incomplete");
+        return Ok(None);
     };
 
     let hwnd = util::try_out_param(|v| swap_chain.GetDesc(v)).map(|desc| desc.OutputWindow)?;
 
     let mut ctx = Context::create();
-    let engine = D3D12RenderEngine::new(&command_queue, &mut ctx)?;
+    let mut engine = D3D12RenderEngine::new(&command_queue, &mut ctx)?;
+    // The game's work on its own queue isn't ordered with ours: make sure the
+    // overlay is done before the frame is presented.
+    engine.set_wait_for_completion(owned);
+    FALLBACK_QUEUE.store(if owned { command_queue.as_raw() as usize } else { 0 }, Ordering::SeqCst);
+    GAME_QUEUE.lock().take();
 
-    let Some(render_loop) = RENDER_LOOP.take() else {
+    let Some(render_loops) = RENDER_LOOPS.take() else {
         error!("Render loop not yet initialized");
         return Err(Error::from_hresult(HRESULT(-1)));
     };
 
-    let pipeline = Pipeline::new(hwnd, ctx, engine, render_loop).map_err(|(e, render_loop)| {
-        RENDER_LOOP.get_or_init(move || render_loop);
-        e
-    })?;
+    let pipeline =
+        Pipeline::new(hwnd, ctx, engine, render_loops).map_err(|(e, render_loops)| {
+            RENDER_LOOPS.get_or_init(move || render_loops);
+            e
+        })?;
 
     {
         INITIALIZATION_CONTEXT.lock().done();
     }
 
-    Ok(Mutex::new(pipeline))
+    // The new engine has yet to pick up the window visibility settings.
+    WINDOW_VISIBILITY_CHANGED.store(true, Ordering::SeqCst);
+
+    Ok(Some(Mutex::new(pipeline)))
 }
 
 fn render(swap_chain: &IDXGISwapChain3) -> Result<()> {
     unsafe {
-        let pipeline = PIPELINE.get_or_try_init(|| init_pipeline())?;
+        // The swap chain and command queue are captured again, in case the
+        // reinitialization was caused by a device reset.
+        if reset_if_stale(&mut PIPELINE, &mut RENDER_LOOPS) {
+            *INITIALIZATION_CONTEXT.lock() = InitializationContext::Empty;
+            return Ok(());
+        }
+
+        let pipeline = match PIPELINE.get() {
+            Some(pipeline) => pipeline,
+            None => match init_pipeline()? {
+                Some(pipeline) => PIPELINE.get_or_init(|| pipeline),
+                None => return Ok(()),
+            },
+        };
 
         let Some(mut pipeline) = pipeline.try_lock() else {
             error!("Could not lock pipeline");
@@ -180,10 +486,54 @@ fn render(swap_chain: &IDXGISwapChain3) -> Result<()> {
 
         pipeline.prepare_render()?;
 
-        let target: ID3D12Resource =
-            swap_chain.GetBuffer(swap_chain.GetCurrentBackBufferIndex())?;
+        let target = BackBufferRef::get(
+            swap_chain,
+            swap_chain.GetCurrentBackBufferIndex(),
+            "the DirectX 12 hooks' render target",
+        )?;
+
+        let output = *OVERLAY_OUTPUT.lock();
+        let shared_size = match output {
+            OverlayOutput::BackBuffer => None,
+            OverlayOutput::SharedTexture | OverlayOutput::Both => {
+                let desc = target.resource.GetDesc();
+                Some((desc.Width as u32, desc.Height))
+            },
+        };
+
+        let engine = pipeline.engine_mut();
+        let name = match shared_size {
+            Some(_) => PCWSTR(
+                SHARED_TEXTURE_NAME.get_or_init(|| HSTRING::from(shared_texture_name())).as_ptr(),
+            ),
+            None => PCWSTR::null(),
+        };
+        let res = engine.update_shared_texture(shared_size, name);
+        SHARED_TEXTURE_HANDLE
+            .store(engine.shared_texture_handle().map_or(0, |h| h.0), Ordering::SeqCst);
+        res?;
+        engine.set_draw_to_target(output != OverlayOutput::SharedTexture);
+
+        if FALLBACK_QUEUE.load(Ordering::Relaxed) != 0 {
+            let game_queue = GAME_QUEUE.lock().clone();
+            match game_queue {
+                Some(game_queue) => engine.order_with_queue(&game_queue)?,
+                None => trace!("No game command queue seen yet, rendering unordered"),
+            }
+        }
 
-        pipeline.render(target)?;
+        if WINDOW_VISIBILITY_CHANGED.swap(false, Ordering::SeqCst) {
+            let windows = WINDOW_VISIBILITY.lock();
+            let hidden_on = |visibility| {
+                windows.iter().filter(|(_, v)| *v == visibility).map(|(n, _)| n.clone()).collect()
+            };
+            engine.set_hidden_windows(
+                hidden_on(WindowVisibility::SharedTextureOnly),
+                hidden_on(WindowVisibility::BackBufferOnly),
+            );
+        }
+
+        pipeline.render(target.resource.clone())?;
     }
 
     Ok(())
@@ -194,20 +544,92 @@ unsafe extern "system" fn dxgi_swap_chain_present_impl(
     sync_interval: u32,
     flags: u32,
 ) -> HRESULT {
+    let _call = HookCall::enter();
+
+    let Trampolines { dxgi_swap_chain_present, .. } =
+        TRAMPOLINES.get().expect("DirectX 12 trampolines uninitialized");
+
+    if !RENDER_LOOP_REGISTERED.load(Ordering::Relaxed) {
+        return dxgi_swap_chain_present(swap_chain, sync_interval, flags);
+    }
+
+    if is_d3d11_swap_chain(&swap_chain)
+        || bypasses(|| util::try_out_param(|v| swap_chain.GetDesc(v)).ok().map(|d| d.OutputWindow))
+        || !claim_api(HookedApis::Dx12)
+    {
+        return dxgi_swap_chain_present(swap_chain, sync_interval, flags);
+    }
+
     {
         INITIALIZATION_CONTEXT.lock().insert_swap_chain(&swap_chain);
     }
 
-    let Trampolines { dxgi_swap_chain_present, .. } =
-        TRAMPOLINES.get().expect("DirectX 12 trampolines uninitialized");
+    timing::set_sync_interval(sync_interval);
+    if let Ok(swap_chain) = swap_chain.cast() {
+        timing::update_present_stats(&swap_chain);
+    }
 
-    if let Err(e) = render(&swap_chain) {
-        util::print_dxgi_debug_messages();
-        error!("Render error: {e:?}");
+    render_frame(|| {
+        render(&swap_chain).map_err(|e| {
+            util::print_dxgi_debug_messages();
+            e
+        })
+    });
+
+    game_thread::run_queued();
+    frame_step::pace();
+
+    trace_hot_path!("Call IDXGISwapChain::Present trampoline");
+    let res = dxgi_swap_chain_present(swap_chain.clone(), sync_interval, flags);
+
+    // Once the frame is out, so that the game doesn't resize its buffers in
+    // the middle of it.
+    apply_forced_target_size(&swap_chain);
+
+    res
+}
+
+// Private data of the swap chains checked by `is_d3d11_swap_chain`, holding the
+// answer as a byte. It goes away with the swap chain, unlike its address, which
+// a later swap chain may reuse.
+const D3D11_SWAP_CHAIN: GUID = GUID::from_u128(0x5c1e4b0e_8a4f_4d2b_9f3e_6d0a7c21b845);
+
+// Whether the swap chain was created on a D3D11 device rather than on a D3D12
+// command queue. This is the case for games drawing through D3D11On12: the
+// DirectX 12 hooks leave those swap chains alone, and the DirectX 11 hooks can
+// render to them through the resources D3D11On12 wraps. The device of a swap
+// chain never changes, so the answer is kept on the swap chain.
+unsafe fn is_d3d11_swap_chain(swap_chain: &IDXGISwapChain3) -> bool {
+    static LOGGED: AtomicBool = AtomicBool::new(false);
+
+    let mut cached = 0u8;
+    let mut size = mem::size_of_val(&cached) as u32;
+    if swap_chain
+        .GetPrivateData(&D3D11_SWAP_CHAIN, &mut size, &mut cached as *mut u8 as *mut c_void)
+        .is_ok()
+    {
+        return cached != 0;
+    }
+
+    let device = swap_chain.GetDevice::<ID3D11Device>().ok();
+    if let Some(device) = &device {
+        if !LOGGED.swap(true, Ordering::SeqCst) {
+            if device.cast::<ID3D11On12Device>().is_ok() {
+                debug!("Skipping swap chain {swap_chain:?} created on a D3D11On12 device");
+            } else {
+                debug!("Skipping swap chain {swap_chain:?} created on a D3D11 device");
+            }
+        }
+    }
+
+    let is_d3d11 = device.is_some() as u8;
+    if let Err(e) =
+        swap_chain.SetPrivateData(&D3D11_SWAP_CHAIN, 1, &is_d3d11 as *const u8 as *const c_void)
+    {
+        trace!("Could not keep the device type on swap chain {swap_chain:?}: {e:?}");
     }
 
-    trace!("Call IDXGISwapChain::Present trampoline");
-    dxgi_swap_chain_present(swap_chain, sync_interval, flags)
+    is_d3d11 != 0
 }
 
 unsafe extern "system" fn dxgi_swap_chain_resize_buffers_impl(
@@ -218,11 +640,137 @@ unsafe extern "system" fn dxgi_swap_chain_resize_buffers_impl(
     new_format: DXGI_FORMAT,
     flags: u32,
 ) -> HRESULT {
+    let _call = HookCall::enter();
+
     let Trampolines { dxgi_swap_chain_resize_buffers, .. } =
         TRAMPOLINES.get().expect("DirectX 12 trampolines uninitialized");
 
+    // Auditing the buffers takes a few calls per buffer, so release builds
+    // only audit them once a resize failed.
+    if cfg!(debug_assertions) {
+        report_stale_back_buffers(&p_this);
+    }
+
     trace!("Call IDXGISwapChain::ResizeBuffers trampoline");
-    dxgi_swap_chain_resize_buffers(p_this, buffer_count, width, height, new_format, flags)
+    let res = dxgi_swap_chain_resize_buffers(
+        p_this.clone(),
+        buffer_count,
+        width,
+        height,
+        new_format,
+        flags,
+    );
+
+    if res == DXGI_ERROR_INVALID_CALL && !cfg!(debug_assertions) {
+        report_stale_back_buffers(&p_this);
+    }
+    if res.is_ok() {
+        PENDING_TARGET_SIZE.lock().take();
+    }
+
+    res
+}
+
+unsafe extern "system" fn dxgi_swap_chain_resize_target_impl(
+    p_this: IDXGISwapChain3,
+    new_target_parameters: *const DXGI_MODE_DESC,
+) -> HRESULT {
+    let _call = HookCall::enter();
+
+    let Trampolines { dxgi_swap_chain_resize_target, .. } =
+        TRAMPOLINES.get().expect("DirectX 12 trampolines uninitialized");
+
+    // The window is resized, and the game possibly resizes its buffers in
+    // turn, before `ResizeTarget` returns: the size is pending from now on.
+    let size = new_target_parameters.as_ref().map(|desc| (desc.Width, desc.Height));
+    let previous = mem::replace(&mut *PENDING_TARGET_SIZE.lock(), size);
+    debug!("IDXGISwapChain::ResizeTarget to {size:?}");
+
+    trace!("Call IDXGISwapChain::ResizeTarget trampoline");
+    let res = dxgi_swap_chain_resize_target(p_this, new_target_parameters);
+
+    if res.is_err() {
+        *PENDING_TARGET_SIZE.lock() = previous;
+    }
+
+    res
+}
+
+// Back buffer references held by the hooks, by holder, to tell who holds a
+// reference when `IDXGISwapChain::ResizeBuffers` fails.
+static BACK_BUFFER_HOLDERS: Mutex<Vec<(usize, &'static str)>> = Mutex::new(Vec::new());
+
+// A reference to a swap chain buffer, registered as held by `holder` for as
+// long as it lives.
+struct BackBufferRef {
+    resource: ID3D12Resource,
+    holder: &'static str,
+}
+
+impl BackBufferRef {
+    unsafe fn get(swap_chain: &IDXGISwapChain3, index: u32, holder: &'static str) -> Result<Self> {
+        let resource: ID3D12Resource = swap_chain.GetBuffer(index)?;
+        BACK_BUFFER_HOLDERS.lock().push((resource.as_raw() as usize, holder));
+        Ok(Self { resource, holder })
+    }
+}
+
+impl Drop for BackBufferRef {
+    fn drop(&mut self) {
+        let key = (self.resource.as_raw() as usize, self.holder);
+        let mut holders = BACK_BUFFER_HOLDERS.lock();
+        if let Some(idx) = holders.iter().position(|&holder| holder == key) {
+            holders.swap_remove(idx);
+        }
+    }
+}
+
+// Number of references to `resource` besides the caller's.
+unsafe fn other_refs(resource: &ID3D12Resource) -> u32 {
+    let raw = resource.as_raw();
+    let vtable = *(raw as *const *const IUnknown_Vtbl);
+    ((*vtable).AddRef)(raw);
+    ((*vtable).Release)(raw).saturating_sub(1)
+}
+
+// Log the buffers of `swap_chain` that are still referenced, and by whom. The
+// swap chain's own references aren't counted, so every reference left makes
+// `ResizeBuffers` fail with `DXGI_ERROR_INVALID_CALL`.
+unsafe fn report_stale_back_buffers(swap_chain: &IDXGISwapChain3) {
+    let Ok(desc) = util::try_out_param(|v| swap_chain.GetDesc(v)) else {
+        return;
+    };
+
+    for index in 0..desc.BufferCount {
+        let Ok(buffer) = swap_chain.GetBuffer::<ID3D12Resource>(index) else {
+            continue;
+        };
+
+        let refs = other_refs(&buffer);
+        if refs == 0 {
+            continue;
+        }
+
+        let mut holders: Vec<String> = BACK_BUFFER_HOLDERS
+            .lock()
+            .iter()
+            .filter(|(ptr, _)| *ptr == buffer.as_raw() as usize)
+            .map(|(_, holder)| holder.to_string())
+            .collect();
+        let untracked = refs.saturating_sub(holders.len() as u32);
+        if untracked > 0 {
+            holders.push(format!(
+                "{untracked} outside the hooks (the game, or a render engine or render loop \
+                 leaking a clone)"
+            ));
+        }
+
+        error!(
+            "Back buffer {index} still has {refs} reference(s) before \
+             IDXGISwapChain::ResizeBuffers: {}",
+            holders.join(", ")
+        );
+    }
 }
 
 unsafe extern "system" fn d3d12_command_queue_execute_command_lists_impl(
@@ -230,7 +778,9 @@ unsafe extern "system" fn d3d12_command_queue_execute_command_lists_impl(
     num_command_lists: u32,
     command_lists: *mut ID3D12CommandList,
 ) {
-    trace!(
+    let _call = HookCall::enter();
+
+    trace_hot_path!(
         "ID3D12CommandQueue::ExecuteCommandLists({command_queue:?}, {num_command_lists}, \
          {command_lists:p}) invoked",
     );
@@ -239,25 +789,37 @@ unsafe extern "system" fn d3d12_command_queue_execute_command_lists_impl(
         INITIALIZATION_CONTEXT.lock().insert_command_queue(&command_queue);
     }
 
+    let fallback_queue = FALLBACK_QUEUE.load(Ordering::Relaxed);
+    if fallback_queue != 0
+        && command_queue.as_raw() as usize != fallback_queue
+        && command_queue.GetDesc().Type == D3D12_COMMAND_LIST_TYPE_DIRECT
+    {
+        *GAME_QUEUE.lock() = Some(command_queue.clone());
+    }
+
     let Trampolines { d3d12_command_queue_execute_command_lists, .. } =
         TRAMPOLINES.get().expect("DirectX 12 trampolines uninitialized");
 
     d3d12_command_queue_execute_command_lists(command_queue, num_command_lists, command_lists);
 }
 
-fn get_target_addrs() -> (
-    DXGISwapChainPresentType,
-    DXGISwapChainResizeBuffersType,
-    D3D12CommandQueueExecuteCommandListsType,
-) {
+fn get_target_addrs() -> std::result::Result<
+    (
+        DXGISwapChainPresentType,
+        DXGISwapChainResizeBuffersType,
+        DXGISwapChainResizeTargetType,
+        D3D12CommandQueueExecuteCommandListsType,
+    ),
+    crate::Error,
+> {
     let dummy_hwnd = DummyHwnd::new();
 
-    let factory: IDXGIFactory2 = unsafe { CreateDXGIFactory2(0) }.unwrap();
-    let adapter = unsafe { factory.EnumAdapters(0) }.unwrap();
+    let factory: IDXGIFactory2 = unsafe { CreateDXGIFactory2(0) }.context("CreateDXGIFactory2")?;
+    let adapter = unsafe { factory.EnumAdapters(0) }.context("IDXGIFactory::EnumAdapters")?;
 
     let device: ID3D12Device =
         util::try_out_ptr(|v| unsafe { D3D12CreateDevice(&adapter, D3D_FEATURE_LEVEL_11_0, v) })
-            .expect("D3D12CreateDevice failed");
+            .context("D3D12CreateDevice")?;
 
     let command_queue: ID3D12CommandQueue = unsafe {
         device.CreateCommandQueue(&D3D12_COMMAND_QUEUE_DESC {
@@ -267,9 +829,9 @@ fn get_target_addrs() -> (
             NodeMask: 0,
         })
     }
-    .unwrap();
+    .context("ID3D12Device::CreateCommandQueue")?;
 
-    let swap_chain: IDXGISwapChain = match util::try_out_ptr(|v| unsafe {
+    let swap_chain: IDXGISwapChain = util::try_out_ptr(|v| unsafe {
         factory
             .CreateSwapChain(
                 &command_queue,
@@ -293,83 +855,159 @@ fn get_target_addrs() -> (
                 v,
             )
             .ok()
-    }) {
-        Ok(swap_chain) => swap_chain,
-        Err(e) => {
-            util::print_dxgi_debug_messages();
-            panic!("{e:?}");
-        },
-    };
+    })
+    .map_err(|e| {
+        util::print_dxgi_debug_messages();
+        e
+    })
+    .context("IDXGIFactory::CreateSwapChain")?;
+
+    let swap_chain_vtable = swap_chain.vtable();
+    let command_queue_vtable = command_queue.vtable();
 
-    let present_ptr: DXGISwapChainPresentType =
-        unsafe { mem::transmute(swap_chain.vtable().Present) };
-    let resize_buffers_ptr: DXGISwapChainResizeBuffersType =
-        unsafe { mem::transmute(swap_chain.vtable().ResizeBuffers) };
-    let cqecl_ptr: D3D12CommandQueueExecuteCommandListsType =
-        unsafe { mem::transmute(command_queue.vtable().ExecuteCommandLists) };
+    let present_ptr: DXGISwapChainPresentType = unsafe {
+        mem::transmute(resolve_target(
+            "IDXGISwapChain::Present",
+            Some(swap_chain_vtable),
+            swap_chain_vtable.Present as usize,
+        ))
+    };
+    let resize_buffers_ptr: DXGISwapChainResizeBuffersType = unsafe {
+        mem::transmute(resolve_target(
+            "IDXGISwapChain::ResizeBuffers",
+            Some(swap_chain_vtable),
+            swap_chain_vtable.ResizeBuffers as usize,
+        ))
+    };
+    let resize_target_ptr: DXGISwapChainResizeTargetType = unsafe {
+        mem::transmute(resolve_target(
+            "IDXGISwapChain::ResizeTarget",
+            Some(swap_chain_vtable),
+            swap_chain_vtable.ResizeTarget as usize,
+        ))
+    };
+    let cqecl_ptr: D3D12CommandQueueExecuteCommandListsType = unsafe {
+        mem::transmute(resolve_target(
+            "ID3D12CommandQueue::ExecuteCommandLists",
+            Some(command_queue_vtable),
+            command_queue_vtable.ExecuteCommandLists as usize,
+        ))
+    };
 
-    (present_ptr, resize_buffers_ptr, cqecl_ptr)
+    Ok((present_ptr, resize_buffers_ptr, resize_target_ptr, cqecl_ptr))
 }
 
 /// Hooks for DirectX 12.
-pub struct ImguiDx12Hooks([MhHook; 3]);
+///
+/// Games that draw parts of their UI through D3D11On12 are supported as long
+/// as their swap chain is created on a D3D12 command queue. Swap chains
+/// created on the D3D11On12 device itself are skipped, as these hooks don't
+/// render through the resources D3D11On12 wraps: use
+/// [`ImguiDx11Hooks`](crate::hooks::dx11::ImguiDx11Hooks) instead for those
+/// games.
+///
+/// While the process is over its video memory budget, the least recently used
+/// textures loaded with
+/// [`RenderContext::load_texture`](crate::RenderContext::load_texture)
+/// are evicted from video memory, and made resident again the next time they
+/// are drawn. Their [`TextureId`](imgui::TextureId)s stay valid.
+pub struct ImguiDx12Hooks([MhHook; 4]);
 
 impl ImguiDx12Hooks {
     /// Construct a set of [`MhHook`]s that will render UI via the
     /// provided [`ImguiRenderLoop`].
     ///
     /// The following functions are hooked:
-    /// - `IDXGISwapChain3::Present`
-    /// - `IDXGISwapChain3::ResizeBuffers`
+    /// - `IDXGISwapChain::Present`
+    /// - `IDXGISwapChain::ResizeBuffers`
+    /// - `IDXGISwapChain::ResizeTarget`
     /// - `ID3D12CommandQueue::ExecuteCommandLists`
     ///
+    /// # Panics
+    ///
+    /// If the hooks can't be created. See [`try_new`](Self::try_new).
+    ///
     /// # Safety
     ///
     /// yolo
     pub unsafe fn new<T>(t: T) -> Self
+    where
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Self::try_new(t).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`new`](Self::new), returning an [`Error`](crate::Error) if the
+    /// hooks can't be created, e.g. without a DirectX 12 capable adapter.
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn try_new<T>(t: T) -> std::result::Result<Self, crate::Error>
     where
         T: ImguiRenderLoop + Send + Sync + 'static,
     {
         let (
             dxgi_swap_chain_present_addr,
             dxgi_swap_chain_resize_buffers_addr,
+            dxgi_swap_chain_resize_target_addr,
             d3d12_command_queue_execute_command_lists_addr,
-        ) = get_target_addrs();
+        ) = get_target_addrs()?;
 
         trace!("IDXGISwapChain::Present = {:p}", dxgi_swap_chain_present_addr as *const c_void);
-        let hook_present = MhHook::new(
+        let hook_present = MhHook::named(
+            "IDXGISwapChain::Present",
             dxgi_swap_chain_present_addr as *mut _,
             dxgi_swap_chain_present_impl as *mut _,
         )
-        .expect("couldn't create IDXGISwapChain::Present hook");
-        let hook_resize_buffers = MhHook::new(
+        .map_err(crate::Error::hook("IDXGISwapChain::Present"))?;
+        let hook_resize_buffers = MhHook::named(
+            "IDXGISwapChain::ResizeBuffers",
             dxgi_swap_chain_resize_buffers_addr as *mut _,
             dxgi_swap_chain_resize_buffers_impl as *mut _,
         )
-        .expect("couldn't create IDXGISwapChain::ResizeBuffers hook");
-        let hook_cqecl = MhHook::new(
+        .map_err(crate::Error::hook("IDXGISwapChain::ResizeBuffers"))?;
+        let hook_resize_target = MhHook::named(
+            "IDXGISwapChain::ResizeTarget",
+            dxgi_swap_chain_resize_target_addr as *mut _,
+            dxgi_swap_chain_resize_target_impl as *mut _,
+        )
+        .map_err(crate::Error::hook("IDXGISwapChain::ResizeTarget"))?;
+        let hook_cqecl = MhHook::named(
+            "ID3D12CommandQueue::ExecuteCommandLists",
             d3d12_command_queue_execute_command_lists_addr as *mut _,
             d3d12_command_queue_execute_command_lists_impl as *mut _,
         )
-        .expect("couldn't create ID3D12CommandQueue::ExecuteCommandLists hook");
+        .map_err(crate::Error::hook("ID3D12CommandQueue::ExecuteCommandLists"))?;
 
-        RENDER_LOOP.get_or_init(|| Box::new(t));
+        let hooks = [hook_present, hook_resize_buffers, hook_resize_target, hook_cqecl];
 
-        TRAMPOLINES.get_or_init(|| Trampolines {
-            dxgi_swap_chain_present: mem::transmute::<*mut c_void, DXGISwapChainPresentType>(
-                hook_present.trampoline(),
-            ),
-            dxgi_swap_chain_resize_buffers: mem::transmute::<
-                *mut c_void,
-                DXGISwapChainResizeBuffersType,
-            >(hook_resize_buffers.trampoline()),
-            d3d12_command_queue_execute_command_lists: mem::transmute::<
-                *mut c_void,
-                D3D12CommandQueueExecuteCommandListsType,
-            >(hook_cqecl.trampoline()),
-        });
+        RENDER_LOOPS.get_or_init(|| vec![Box::new(t)]);
+        RENDER_LOOP_REGISTERED.store(true, Ordering::SeqCst);
+
+        TRAMPOLINES.get_or_init(|| trampolines(&hooks));
+
+        Ok(Self(hooks))
+    }
+}
 
-        Self([hook_present, hook_resize_buffers, hook_cqecl])
+unsafe fn trampolines(
+    [hook_present, hook_resize_buffers, hook_resize_target, hook_cqecl]: &[MhHook; 4],
+) -> Trampolines {
+    Trampolines {
+        dxgi_swap_chain_present: mem::transmute::<*mut c_void, DXGISwapChainPresentType>(
+            hook_present.trampoline(),
+        ),
+        dxgi_swap_chain_resize_buffers: mem::transmute::<*mut c_void, DXGISwapChainResizeBuffersType>(
+            hook_resize_buffers.trampoline(),
+        ),
+        dxgi_swap_chain_resize_target: mem::transmute::<*mut c_void, DXGISwapChainResizeTargetType>(
+            hook_resize_target.trampoline(),
+        ),
+        d3d12_command_queue_execute_command_lists: mem::transmute::<
+            *mut c_void,
+            D3D12CommandQueueExecuteCommandListsType,
+        >(hook_cqecl.trampoline()),
     }
 }
 
@@ -382,14 +1020,142 @@ impl Hooks for ImguiDx12Hooks {
         Box::new(unsafe { Self::new(t) })
     }
 
+    fn try_from_render_loop<T>(t: T) -> std::result::Result<Box<Self>, crate::Error>
+    where
+        Self: Sized,
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        unsafe { Self::try_new(t) }.map(Box::new)
+    }
+
     fn hooks(&self) -> &[MhHook] {
         &self.0
     }
 
+    fn hooks_mut(&mut self) -> &mut [MhHook] {
+        &mut self.0
+    }
+
+    unsafe fn reload_trampolines(&mut self) {
+        TRAMPOLINES.take();
+        TRAMPOLINES.get_or_init(|| trampolines(&self.0));
+    }
+
+    fn add_render_loop(&mut self, render_loop: Box<dyn ImguiRenderLoop + Send + Sync>) {
+        match unsafe { RENDER_LOOPS.get_mut() } {
+            Some(render_loops) => render_loops.push(render_loop),
+            None => error!("Render loops already moved into the pipeline"),
+        }
+    }
+
     unsafe fn unhook(&mut self) {
+        RENDER_LOOP_REGISTERED.store(false, Ordering::SeqCst);
+        release_api(HookedApis::Dx12);
+        renderer::clear_frame_callbacks();
         TRAMPOLINES.take();
+        let device = PIPELINE.get().map(|p| p.lock().engine_mut().device().clone());
         PIPELINE.take().map(|p| p.into_inner().take());
-        RENDER_LOOP.take(); // should already be null
+        SHARED_TEXTURE_HANDLE.store(0, Ordering::SeqCst);
+        RENDER_LOOPS.take(); // should already be null
         *INITIALIZATION_CONTEXT.lock() = InitializationContext::Empty;
+        FALLBACK_QUEUE.store(0, Ordering::SeqCst);
+        GAME_QUEUE.lock().take();
+        PENDING_TARGET_SIZE.lock().take();
+        FORCED_TARGET_SIZE.lock().take();
+        check_leaks(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_NULL;
+    use windows::Win32::Graphics::Direct3D11::{
+        D3D11CreateDeviceAndSwapChain, D3D11_CREATE_DEVICE_FLAG, D3D11_SDK_VERSION,
+    };
+    use windows::Win32::Graphics::Dxgi::DXGI_SWAP_EFFECT_DISCARD;
+
+    use super::*;
+    use crate::hooks::{is_render_disabled, set_max_render_errors};
+
+    #[test]
+    fn test_incomplete_context_is_not_a_render_error() {
+        set_max_render_errors(Some(2));
+
+        // No swap chain nor command queue were captured: the pipeline waits,
+        // however many presents it takes.
+        for _ in 0..10 {
+            render_frame(|| {
+                let pipeline = unsafe { init_pipeline() }?;
+                assert!(pipeline.is_none());
+                Ok(())
+            });
+        }
+        let disabled = is_render_disabled();
+        set_max_render_errors(Some(120));
+
+        assert!(!disabled);
+    }
+
+    // A swap chain on a null DirectX 11 device.
+    fn d3d11_swap_chain(dummy_hwnd: &DummyHwnd) -> IDXGISwapChain3 {
+        let mut swap_chain: Option<IDXGISwapChain> = None;
+        unsafe {
+            D3D11CreateDeviceAndSwapChain(
+                None,
+                D3D_DRIVER_TYPE_NULL,
+                None,
+                D3D11_CREATE_DEVICE_FLAG(0),
+                None,
+                D3D11_SDK_VERSION,
+                Some(&DXGI_SWAP_CHAIN_DESC {
+                    BufferDesc: DXGI_MODE_DESC {
+                        Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                        ..Default::default()
+                    },
+                    BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+                    BufferCount: 1,
+                    OutputWindow: dummy_hwnd.hwnd(),
+                    Windowed: BOOL(1),
+                    SwapEffect: DXGI_SWAP_EFFECT_DISCARD,
+                    SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+                    ..Default::default()
+                }),
+                Some(&mut swap_chain),
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        }
+        swap_chain.unwrap().cast().unwrap()
+    }
+
+    unsafe fn cached_answer(swap_chain: &IDXGISwapChain3) -> Option<u8> {
+        let mut cached = 0u8;
+        let mut size = 1;
+        swap_chain
+            .GetPrivateData(&D3D11_SWAP_CHAIN, &mut size, &mut cached as *mut u8 as *mut c_void)
+            .ok()
+            .map(|_| cached)
+    }
+
+    #[test]
+    fn test_is_d3d11_swap_chain() {
+        let dummy_hwnd = DummyHwnd::new();
+
+        unsafe {
+            let swap_chain = d3d11_swap_chain(&dummy_hwnd);
+            assert_eq!(cached_answer(&swap_chain), None);
+            assert!(is_d3d11_swap_chain(&swap_chain));
+            assert_eq!(cached_answer(&swap_chain), Some(1));
+            assert!(is_d3d11_swap_chain(&swap_chain));
+            drop(swap_chain);
+
+            // The answer is kept on the swap chain: a new one, possibly at the
+            // same address, is checked anew.
+            let swap_chain = d3d11_swap_chain(&dummy_hwnd);
+            assert_eq!(cached_answer(&swap_chain), None);
+            assert!(is_d3d11_swap_chain(&swap_chain));
+        }
     }
 }
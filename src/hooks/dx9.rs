@@ -2,6 +2,7 @@
 
 use std::ffi::c_void;
 use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::OnceLock;
 
 use imgui::Context;
@@ -17,10 +18,13 @@ use windows::Win32::Graphics::Direct3D9::{
 };
 use windows::Win32::Graphics::Gdi::RGNDATA;
 
-use super::DummyHwnd;
+use super::{bypasses, claim_api, release_api, render_frame, resolve_target, DummyHwnd, HookCall};
+use crate::error::Context as _;
+use crate::instances::HookedApis;
 use crate::mh::MhHook;
-use crate::renderer::{D3D9RenderEngine, Pipeline};
-use crate::{util, Hooks, ImguiRenderLoop};
+use crate::renderer::{reset_if_stale, D3D9RenderEngine, Pipeline};
+use crate::util::trace_hot_path;
+use crate::{frame_step, game_thread, util, Hooks, ImguiRenderLoop};
 
 type Dx9PresentType = unsafe extern "system" fn(
     this: IDirect3DDevice9,
@@ -40,7 +44,11 @@ struct Trampolines {
 
 static mut TRAMPOLINES: OnceLock<Trampolines> = OnceLock::new();
 static mut PIPELINE: OnceCell<Mutex<Pipeline<D3D9RenderEngine>>> = OnceCell::new();
-static mut RENDER_LOOP: OnceCell<Box<dyn ImguiRenderLoop + Send + Sync>> = OnceCell::new();
+static mut RENDER_LOOPS: OnceCell<Vec<Box<dyn ImguiRenderLoop + Send + Sync>>> = OnceCell::new();
+// Whether a render loop is registered, either waiting in `RENDER_LOOPS` or
+// moved into the pipeline. Until then, the present hook passes through at the
+// cost of a single load.
+static RENDER_LOOP_REGISTERED: AtomicBool = AtomicBool::new(false);
 
 unsafe fn init_pipeline(device: &IDirect3DDevice9) -> Result<Mutex<Pipeline<D3D9RenderEngine>>> {
     trace!("initializing pipeline");
@@ -53,20 +61,23 @@ unsafe fn init_pipeline(device: &IDirect3DDevice9) -> Result<Mutex<Pipeline<D3D9
     trace!("creating engine");
     let engine = D3D9RenderEngine::new(device, &mut ctx)?;
 
-    let Some(render_loop) = RENDER_LOOP.take() else {
+    let Some(render_loops) = RENDER_LOOPS.take() else {
         error!("Render loop not yet initialized");
         return Err(Error::from_hresult(HRESULT(-1)));
     };
 
     trace!("creating pipeline");
-    let pipeline = Pipeline::new(hwnd, ctx, engine, render_loop).map_err(|(e, render_loop)| {
-        RENDER_LOOP.get_or_init(move || render_loop);
-        e
-    })?;
+    let pipeline =
+        Pipeline::new(hwnd, ctx, engine, render_loops).map_err(|(e, render_loops)| {
+            RENDER_LOOPS.get_or_init(move || render_loops);
+            e
+        })?;
     Ok(Mutex::new(pipeline))
 }
 
 fn render(device: &IDirect3DDevice9) -> Result<()> {
+    unsafe { reset_if_stale(&mut PIPELINE, &mut RENDER_LOOPS) };
+
     let pipeline = unsafe { PIPELINE.get_or_try_init(|| init_pipeline(device)) }?;
 
     let Some(mut pipeline) = pipeline.try_lock() else {
@@ -92,39 +103,65 @@ unsafe extern "system" fn dx9_present_impl(
     hdestwindowoverride: HWND,
     pdirtyregion: *const RGNDATA,
 ) -> HRESULT {
+    let _call = HookCall::enter();
+
     let Trampolines { dx9_present, .. } =
         TRAMPOLINES.get().expect("DirectX 9 trampolines uninitialized");
 
-    if let Err(e) = render(&device) {
-        error!("Render error: {e:?}");
+    if !RENDER_LOOP_REGISTERED.load(Ordering::Relaxed)
+        || bypasses(|| present_window(&device, hdestwindowoverride))
+        || !claim_api(HookedApis::Dx9)
+    {
+        return dx9_present(device, psourcerect, pdestrect, hdestwindowoverride, pdirtyregion);
     }
 
-    trace!("Call IDirect3DDevice9::Present trampoline");
+    render_frame(|| render(&device));
+
+    game_thread::run_queued();
+    frame_step::pace();
+
+    trace_hot_path!("Call IDirect3DDevice9::Present trampoline");
     dx9_present(device, psourcerect, pdestrect, hdestwindowoverride, pdirtyregion)
 }
+
+// The window a frame is presented to: the override, if any, or the window of
+// the device.
+unsafe fn present_window(device: &IDirect3DDevice9, hdestwindowoverride: HWND) -> Option<HWND> {
+    if hdestwindowoverride.0 != 0 {
+        return Some(hdestwindowoverride);
+    }
+    let mut creation_parameters = Default::default();
+    device.GetCreationParameters(&mut creation_parameters).ok()?;
+    Some(creation_parameters.hFocusWindow)
+}
+
 unsafe extern "system" fn dx9_reset_impl(
     this: IDirect3DDevice9,
     present_params: *const D3DPRESENT_PARAMETERS,
 ) -> HRESULT {
+    let _call = HookCall::enter();
+
     let Trampolines { dx9_reset, .. } =
         TRAMPOLINES.get().expect("DirectX 9 trampolines uninitialized");
 
     trace!("Resetting pipeline");
     if let Some(pipeline) = PIPELINE.take() {
-        let render_loop = pipeline.into_inner().take();
+        let render_loops = pipeline.into_inner().take();
 
-        RENDER_LOOP.set(render_loop).map_err(|_| ()).expect("Render loop cell should be empty");
+        RENDER_LOOPS.set(render_loops).map_err(|_| ()).expect("Render loop cell should be empty");
     }
 
     dx9_reset(this, present_params)
 }
 
-fn get_target_addrs() -> (Dx9PresentType, Dx9ResetType) {
-    let d9 = unsafe { Direct3DCreate9(D3D_SDK_VERSION).unwrap() };
+fn get_target_addrs() -> std::result::Result<(Dx9PresentType, Dx9ResetType), crate::Error> {
+    let d9 = unsafe { Direct3DCreate9(D3D_SDK_VERSION) }
+        .ok_or(crate::Error::Missing("Direct3DCreate9"))?;
 
     let mut d3d_display_mode =
         D3DDISPLAYMODE { Width: 0, Height: 0, RefreshRate: 0, Format: D3DFORMAT(0) };
-    unsafe { d9.GetAdapterDisplayMode(D3DADAPTER_DEFAULT, &mut d3d_display_mode).unwrap() };
+    unsafe { d9.GetAdapterDisplayMode(D3DADAPTER_DEFAULT, &mut d3d_display_mode) }
+        .context("IDirect3D9::GetAdapterDisplayMode")?;
 
     let mut present_params = D3DPRESENT_PARAMETERS {
         Windowed: BOOL(1),
@@ -146,28 +183,20 @@ fn get_target_addrs() -> (Dx9PresentType, Dx9ResetType) {
             )
         }
     })
-    .expect("IDirect3DDevice9::CreateDevice: failed to create device");
+    .context("IDirect3D9::CreateDevice")?;
 
-    let present_ptr = device.vtable().Present;
-    let reset_ptr = device.vtable().Reset;
+    let vtable = device.vtable();
 
     unsafe {
-        (
-            mem::transmute::<
-                unsafe extern "system" fn(
-                    *mut c_void,
-                    *const RECT,
-                    *const RECT,
-                    HWND,
-                    *const RGNDATA,
-                ) -> HRESULT,
-                Dx9PresentType,
-            >(present_ptr),
-            mem::transmute::<
-                unsafe extern "system" fn(*mut c_void, *mut D3DPRESENT_PARAMETERS) -> HRESULT,
-                Dx9ResetType,
-            >(reset_ptr),
-        )
+        let present_addr =
+            resolve_target("IDirect3DDevice9::Present", Some(vtable), vtable.Present as usize);
+        let reset_addr =
+            resolve_target("IDirect3DDevice9::Reset", Some(vtable), vtable.Reset as usize);
+
+        Ok((
+            mem::transmute::<usize, Dx9PresentType>(present_addr),
+            mem::transmute::<usize, Dx9ResetType>(reset_addr),
+        ))
     }
 }
 
@@ -180,6 +209,11 @@ impl ImguiDx9Hooks {
     ///
     /// The following functions are hooked:
     /// - `IDirect3DDevice9::Present`
+    /// - `IDirect3DDevice9::Reset`
+    ///
+    /// # Panics
+    ///
+    /// If the hooks can't be created. See [`try_new`](Self::try_new).
     ///
     /// # Safety
     ///
@@ -188,22 +222,49 @@ impl ImguiDx9Hooks {
     where
         T: ImguiRenderLoop + Send + Sync + 'static,
     {
-        let (dx9_present_addr, dx9_reset_addr) = get_target_addrs();
+        Self::try_new(t).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`new`](Self::new), returning an [`Error`](crate::Error) if the
+    /// hooks can't be created.
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn try_new<T>(t: T) -> std::result::Result<Self, crate::Error>
+    where
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        let (dx9_present_addr, dx9_reset_addr) = get_target_addrs()?;
 
         trace!("IDirect3DDevice9::Present = {:p}", dx9_present_addr as *const c_void);
-        let hook_present =
-            MhHook::new(dx9_present_addr as *mut c_void, dx9_present_impl as *mut c_void)
-                .expect("couldn't create IDirect3DDevice9::Present hook");
-        let hook_reset = MhHook::new(dx9_reset_addr as *mut c_void, dx9_reset_impl as *mut c_void)
-            .expect("couldn't create IDirect3DDevice9::Reset hook");
-
-        RENDER_LOOP.get_or_init(|| Box::new(t));
-        TRAMPOLINES.get_or_init(|| Trampolines {
-            dx9_present: mem::transmute::<*mut c_void, Dx9PresentType>(hook_present.trampoline()),
-            dx9_reset: mem::transmute::<*mut c_void, Dx9ResetType>(hook_reset.trampoline()),
-        });
-
-        Self([hook_present, hook_reset])
+        let hook_present = MhHook::named(
+            "IDirect3DDevice9::Present",
+            dx9_present_addr as *mut c_void,
+            dx9_present_impl as *mut c_void,
+        )
+        .map_err(crate::Error::hook("IDirect3DDevice9::Present"))?;
+        let hook_reset = MhHook::named(
+            "IDirect3DDevice9::Reset",
+            dx9_reset_addr as *mut c_void,
+            dx9_reset_impl as *mut c_void,
+        )
+        .map_err(crate::Error::hook("IDirect3DDevice9::Reset"))?;
+
+        let hooks = [hook_present, hook_reset];
+
+        RENDER_LOOPS.get_or_init(|| vec![Box::new(t)]);
+        RENDER_LOOP_REGISTERED.store(true, Ordering::SeqCst);
+        TRAMPOLINES.get_or_init(|| trampolines(&hooks));
+
+        Ok(Self(hooks))
+    }
+}
+
+unsafe fn trampolines([hook_present, hook_reset]: &[MhHook; 2]) -> Trampolines {
+    Trampolines {
+        dx9_present: mem::transmute::<*mut c_void, Dx9PresentType>(hook_present.trampoline()),
+        dx9_reset: mem::transmute::<*mut c_void, Dx9ResetType>(hook_reset.trampoline()),
     }
 }
 
@@ -216,13 +277,39 @@ impl Hooks for ImguiDx9Hooks {
         Box::new(unsafe { Self::new(t) })
     }
 
+    fn try_from_render_loop<T>(t: T) -> std::result::Result<Box<Self>, crate::Error>
+    where
+        Self: Sized,
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        unsafe { Self::try_new(t) }.map(Box::new)
+    }
+
     fn hooks(&self) -> &[MhHook] {
         &self.0
     }
 
+    fn hooks_mut(&mut self) -> &mut [MhHook] {
+        &mut self.0
+    }
+
+    unsafe fn reload_trampolines(&mut self) {
+        TRAMPOLINES.take();
+        TRAMPOLINES.get_or_init(|| trampolines(&self.0));
+    }
+
+    fn add_render_loop(&mut self, render_loop: Box<dyn ImguiRenderLoop + Send + Sync>) {
+        match unsafe { RENDER_LOOPS.get_mut() } {
+            Some(render_loops) => render_loops.push(render_loop),
+            None => error!("Render loops already moved into the pipeline"),
+        }
+    }
+
     unsafe fn unhook(&mut self) {
+        RENDER_LOOP_REGISTERED.store(false, Ordering::SeqCst);
+        release_api(HookedApis::Dx9);
         TRAMPOLINES.take();
         PIPELINE.take().map(|p| p.into_inner().take());
-        RENDER_LOOP.take();
+        RENDER_LOOPS.take();
     }
 }
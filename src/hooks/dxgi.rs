@@ -0,0 +1,380 @@
+//! Raw hooks for DXGI swap chains, without any `imgui` rendering.
+//!
+//! These hooks are available without the `renderer` feature, for users with
+//! their own rendering stack who want to reuse the hooking and lifecycle
+//! machinery of [`hudhook`](crate).
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::hooks::dxgi::{DxgiHooks, SwapChainCallbacks};
+//! use hudhook::windows::Win32::Graphics::Dxgi::IDXGISwapChain;
+//! use hudhook::Hudhook;
+//!
+//! struct MyCallbacks;
+//!
+//! impl SwapChainCallbacks for MyCallbacks {
+//!     fn present(&mut self, swap_chain: &IDXGISwapChain, _sync_interval: u32, _flags: u32) {
+//!         // Draw with your own renderer.
+//!     }
+//! }
+//!
+//! let hooks = unsafe { DxgiHooks::new(MyCallbacks) };
+//! Hudhook::builder().with_hooks(hooks).build().apply().unwrap();
+//! ```
+use std::ffi::c_void;
+use std::mem;
+use std::sync::OnceLock;
+
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use tracing::{error, trace};
+use windows::core::{Interface, HRESULT};
+use windows::Win32::Foundation::BOOL;
+use windows::Win32::Graphics::Direct3D::{
+    D3D_DRIVER_TYPE_NULL, D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_11_0,
+};
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11CreateDeviceAndSwapChain, D3D11_CREATE_DEVICE_FLAG, D3D11_SDK_VERSION,
+};
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_MODE_DESC, DXGI_MODE_SCALING_UNSPECIFIED,
+    DXGI_MODE_SCANLINE_ORDER_UNSPECIFIED, DXGI_SAMPLE_DESC,
+};
+use windows::Win32::Graphics::Dxgi::{
+    IDXGISwapChain, DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_EFFECT_DISCARD, DXGI_USAGE_RENDER_TARGET_OUTPUT,
+};
+
+use super::{resolve_target, DummyHwnd, HookCall};
+use crate::error::Context as _;
+use crate::mh::MhHook;
+use crate::util::trace_hot_path;
+use crate::{game_thread, timing, Hooks};
+
+type DXGISwapChainPresentType =
+    unsafe extern "system" fn(This: IDXGISwapChain, SyncInterval: u32, Flags: u32) -> HRESULT;
+
+type DXGISwapChainResizeBuffersType = unsafe extern "system" fn(
+    This: IDXGISwapChain,
+    buffer_count: u32,
+    width: u32,
+    height: u32,
+    new_format: DXGI_FORMAT,
+    flags: u32,
+) -> HRESULT;
+
+type DXGISwapChainResizeTargetType = unsafe extern "system" fn(
+    This: IDXGISwapChain,
+    new_target_parameters: *const DXGI_MODE_DESC,
+) -> HRESULT;
+
+struct Trampolines {
+    dxgi_swap_chain_present: DXGISwapChainPresentType,
+    dxgi_swap_chain_resize_buffers: DXGISwapChainResizeBuffersType,
+    dxgi_swap_chain_resize_target: DXGISwapChainResizeTargetType,
+}
+
+static mut TRAMPOLINES: OnceLock<Trampolines> = OnceLock::new();
+static mut CALLBACKS: OnceCell<Mutex<Box<dyn SwapChainCallbacks>>> = OnceCell::new();
+
+/// Callbacks invoked by [`DxgiHooks`] before the hooked functions run.
+pub trait SwapChainCallbacks: Send + Sync {
+    /// Called before `IDXGISwapChain::Present`.
+    fn present(&mut self, _swap_chain: &IDXGISwapChain, _sync_interval: u32, _flags: u32) {}
+
+    /// Called before `IDXGISwapChain::ResizeBuffers`. Release every reference
+    /// to the swap chain's buffers here, or the resize fails.
+    fn resize_buffers(
+        &mut self,
+        _swap_chain: &IDXGISwapChain,
+        _buffer_count: u32,
+        _width: u32,
+        _height: u32,
+        _format: DXGI_FORMAT,
+        _flags: u32,
+    ) {
+    }
+
+    /// Called before `IDXGISwapChain::ResizeTarget`, which some games call to
+    /// change the resolution before resizing the buffers.
+    fn resize_target(&mut self, _swap_chain: &IDXGISwapChain, _parameters: &DXGI_MODE_DESC) {}
+}
+
+unsafe extern "system" fn dxgi_swap_chain_present_impl(
+    swap_chain: IDXGISwapChain,
+    sync_interval: u32,
+    flags: u32,
+) -> HRESULT {
+    let _call = HookCall::enter();
+
+    let Trampolines { dxgi_swap_chain_present, .. } =
+        TRAMPOLINES.get().expect("DXGI trampolines uninitialized");
+
+    // Without callbacks, pass through at the cost of a single load.
+    let Some(callbacks) = CALLBACKS.get() else {
+        return dxgi_swap_chain_present(swap_chain, sync_interval, flags);
+    };
+
+    timing::set_sync_interval(sync_interval);
+    timing::update_present_stats(&swap_chain);
+
+    match callbacks.try_lock() {
+        Some(mut callbacks) => callbacks.present(&swap_chain, sync_interval, flags),
+        None => error!("Could not lock swap chain callbacks"),
+    }
+
+    game_thread::run_queued();
+
+    trace_hot_path!("Call IDXGISwapChain::Present trampoline");
+    dxgi_swap_chain_present(swap_chain, sync_interval, flags)
+}
+
+unsafe extern "system" fn dxgi_swap_chain_resize_buffers_impl(
+    swap_chain: IDXGISwapChain,
+    buffer_count: u32,
+    width: u32,
+    height: u32,
+    new_format: DXGI_FORMAT,
+    flags: u32,
+) -> HRESULT {
+    let _call = HookCall::enter();
+
+    let Trampolines { dxgi_swap_chain_resize_buffers, .. } =
+        TRAMPOLINES.get().expect("DXGI trampolines uninitialized");
+
+    match CALLBACKS.get().map(Mutex::try_lock) {
+        Some(Some(mut callbacks)) => {
+            callbacks.resize_buffers(&swap_chain, buffer_count, width, height, new_format, flags)
+        },
+        Some(None) => error!("Could not lock swap chain callbacks"),
+        None => {},
+    }
+
+    trace!("Call IDXGISwapChain::ResizeBuffers trampoline");
+    dxgi_swap_chain_resize_buffers(swap_chain, buffer_count, width, height, new_format, flags)
+}
+
+unsafe extern "system" fn dxgi_swap_chain_resize_target_impl(
+    swap_chain: IDXGISwapChain,
+    new_target_parameters: *const DXGI_MODE_DESC,
+) -> HRESULT {
+    let _call = HookCall::enter();
+
+    let Trampolines { dxgi_swap_chain_resize_target, .. } =
+        TRAMPOLINES.get().expect("DXGI trampolines uninitialized");
+
+    match (CALLBACKS.get().map(Mutex::try_lock), new_target_parameters.as_ref()) {
+        (Some(Some(mut callbacks)), Some(parameters)) => {
+            callbacks.resize_target(&swap_chain, parameters)
+        },
+        (Some(None), _) => error!("Could not lock swap chain callbacks"),
+        _ => {},
+    }
+
+    trace!("Call IDXGISwapChain::ResizeTarget trampoline");
+    dxgi_swap_chain_resize_target(swap_chain, new_target_parameters)
+}
+
+fn get_target_addrs() -> std::result::Result<
+    (DXGISwapChainPresentType, DXGISwapChainResizeBuffersType, DXGISwapChainResizeTargetType),
+    crate::Error,
+> {
+    let mut p_swap_chain: Option<IDXGISwapChain> = None;
+
+    // `IDXGISwapChain` is implemented by DXGI itself, so the vtable of a swap
+    // chain created on a null DirectX 11 device is shared by the swap chains of
+    // every DirectX 10+ game.
+    let dummy_hwnd = DummyHwnd::new();
+    unsafe {
+        D3D11CreateDeviceAndSwapChain(
+            None,
+            D3D_DRIVER_TYPE_NULL,
+            None,
+            D3D11_CREATE_DEVICE_FLAG(0),
+            Some(&[D3D_FEATURE_LEVEL_10_0, D3D_FEATURE_LEVEL_11_0]),
+            D3D11_SDK_VERSION,
+            Some(&DXGI_SWAP_CHAIN_DESC {
+                BufferDesc: DXGI_MODE_DESC {
+                    Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                    ScanlineOrdering: DXGI_MODE_SCANLINE_ORDER_UNSPECIFIED,
+                    Scaling: DXGI_MODE_SCALING_UNSPECIFIED,
+                    ..Default::default()
+                },
+                BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+                BufferCount: 1,
+                OutputWindow: dummy_hwnd.hwnd(),
+                Windowed: BOOL(1),
+                SwapEffect: DXGI_SWAP_EFFECT_DISCARD,
+                SampleDesc: DXGI_SAMPLE_DESC { Count: 1, ..Default::default() },
+                ..Default::default()
+            }),
+            Some(&mut p_swap_chain),
+            None,
+            None,
+            None,
+        )
+        .context("D3D11CreateDeviceAndSwapChain")?;
+    }
+
+    let swap_chain = p_swap_chain.ok_or(crate::Error::Missing("D3D11CreateDeviceAndSwapChain"))?;
+
+    let vtable = swap_chain.vtable();
+
+    let present_ptr: DXGISwapChainPresentType = unsafe {
+        mem::transmute(resolve_target(
+            "IDXGISwapChain::Present",
+            Some(vtable),
+            vtable.Present as usize,
+        ))
+    };
+    let resize_buffers_ptr: DXGISwapChainResizeBuffersType = unsafe {
+        mem::transmute(resolve_target(
+            "IDXGISwapChain::ResizeBuffers",
+            Some(vtable),
+            vtable.ResizeBuffers as usize,
+        ))
+    };
+    let resize_target_ptr: DXGISwapChainResizeTargetType = unsafe {
+        mem::transmute(resolve_target(
+            "IDXGISwapChain::ResizeTarget",
+            Some(vtable),
+            vtable.ResizeTarget as usize,
+        ))
+    };
+
+    Ok((present_ptr, resize_buffers_ptr, resize_target_ptr))
+}
+
+/// Raw hooks for DXGI swap chains.
+pub struct DxgiHooks([MhHook; 3]);
+
+impl DxgiHooks {
+    /// Construct a set of [`MhHook`]s that will invoke the provided
+    /// [`SwapChainCallbacks`].
+    ///
+    /// The following functions are hooked:
+    /// - `IDXGISwapChain::Present`
+    /// - `IDXGISwapChain::ResizeBuffers`
+    /// - `IDXGISwapChain::ResizeTarget`
+    ///
+    /// # Panics
+    ///
+    /// If the hooks can't be created. See [`try_new`](Self::try_new).
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn new<T>(t: T) -> Self
+    where
+        T: SwapChainCallbacks + 'static,
+    {
+        Self::try_new(t).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`new`](Self::new), returning an [`Error`](crate::Error) if the
+    /// hooks can't be created.
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn try_new<T>(t: T) -> std::result::Result<Self, crate::Error>
+    where
+        T: SwapChainCallbacks + 'static,
+    {
+        let (
+            dxgi_swap_chain_present_addr,
+            dxgi_swap_chain_resize_buffers_addr,
+            dxgi_swap_chain_resize_target_addr,
+        ) = get_target_addrs()?;
+
+        trace!("IDXGISwapChain::Present = {:p}", dxgi_swap_chain_present_addr as *const c_void);
+        let hook_present = MhHook::named(
+            "IDXGISwapChain::Present",
+            dxgi_swap_chain_present_addr as *mut _,
+            dxgi_swap_chain_present_impl as *mut _,
+        )
+        .map_err(crate::Error::hook("IDXGISwapChain::Present"))?;
+
+        trace!(
+            "IDXGISwapChain::ResizeBuffers = {:p}",
+            dxgi_swap_chain_resize_buffers_addr as *const c_void
+        );
+        let hook_resize_buffers = MhHook::named(
+            "IDXGISwapChain::ResizeBuffers",
+            dxgi_swap_chain_resize_buffers_addr as *mut _,
+            dxgi_swap_chain_resize_buffers_impl as *mut _,
+        )
+        .map_err(crate::Error::hook("IDXGISwapChain::ResizeBuffers"))?;
+
+        trace!(
+            "IDXGISwapChain::ResizeTarget = {:p}",
+            dxgi_swap_chain_resize_target_addr as *const c_void
+        );
+        let hook_resize_target = MhHook::named(
+            "IDXGISwapChain::ResizeTarget",
+            dxgi_swap_chain_resize_target_addr as *mut _,
+            dxgi_swap_chain_resize_target_impl as *mut _,
+        )
+        .map_err(crate::Error::hook("IDXGISwapChain::ResizeTarget"))?;
+
+        let hooks = [hook_present, hook_resize_buffers, hook_resize_target];
+
+        CALLBACKS.get_or_init(|| Mutex::new(Box::new(t)));
+        TRAMPOLINES.get_or_init(|| trampolines(&hooks));
+
+        Ok(Self(hooks))
+    }
+}
+
+unsafe fn trampolines(
+    [hook_present, hook_resize_buffers, hook_resize_target]: &[MhHook; 3],
+) -> Trampolines {
+    Trampolines {
+        dxgi_swap_chain_present: mem::transmute::<*mut c_void, DXGISwapChainPresentType>(
+            hook_present.trampoline(),
+        ),
+        dxgi_swap_chain_resize_buffers: mem::transmute::<*mut c_void, DXGISwapChainResizeBuffersType>(
+            hook_resize_buffers.trampoline(),
+        ),
+        dxgi_swap_chain_resize_target: mem::transmute::<*mut c_void, DXGISwapChainResizeTargetType>(
+            hook_resize_target.trampoline(),
+        ),
+    }
+}
+
+impl Hooks for DxgiHooks {
+    #[cfg(feature = "renderer")]
+    fn from_render_loop<T>(t: T) -> Box<Self>
+    where
+        Self: Sized,
+        T: crate::ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Self::try_from_render_loop(t).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    #[cfg(feature = "renderer")]
+    fn try_from_render_loop<T>(_: T) -> std::result::Result<Box<Self>, crate::Error>
+    where
+        Self: Sized,
+        T: crate::ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Err(crate::Error::NoRenderLoop("DxgiHooks"))
+    }
+
+    fn hooks(&self) -> &[MhHook] {
+        &self.0
+    }
+
+    fn hooks_mut(&mut self) -> &mut [MhHook] {
+        &mut self.0
+    }
+
+    unsafe fn reload_trampolines(&mut self) {
+        TRAMPOLINES.take();
+        TRAMPOLINES.get_or_init(|| trampolines(&self.0));
+    }
+
+    unsafe fn unhook(&mut self) {
+        TRAMPOLINES.take();
+        CALLBACKS.take();
+    }
+}
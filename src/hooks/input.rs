@@ -0,0 +1,236 @@
+//! Hooks masking the keyboard state polled by the game.
+//!
+//! While `imgui` captures the keyboard, e.g. while the user types into an
+//! overlay text field, the key messages are withheld from the game window,
+//! but games polling the keyboard with `GetAsyncKeyState` or
+//! `GetKeyboardState` still see the keys. [`InputHooks`] hook both functions
+//! and report the keys as released to the game for as long as the keyboard is
+//! captured. Mouse buttons are left untouched.
+//!
+//! The hooks also report the keys [remapped](crate::remap) as the keys they
+//! are sent as.
+//!
+//! Example usage:
+//! ```no_run
+//! # use hudhook::*;
+//! # use hudhook::hooks::dx11::ImguiDx11Hooks;
+//! use hudhook::hooks::input::InputHooks;
+//!
+//! # struct MyRenderLoop;
+//! # impl ImguiRenderLoop for MyRenderLoop {
+//! #     fn render(&mut self, _: &mut imgui::Ui) {}
+//! # }
+//! Hudhook::builder()
+//!     .with::<ImguiDx11Hooks>(MyRenderLoop)
+//!     .with_hooks(unsafe { InputHooks::new() })
+//!     .apply()
+//!     .ok();
+//! ```
+use std::ffi::c_void;
+use std::sync::OnceLock;
+use std::{mem, slice};
+
+use tracing::trace;
+use windows::core::s;
+use windows::Win32::Foundation::BOOL;
+use windows::Win32::System::LibraryLoader::{GetModuleHandleA, GetProcAddress};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    GetAsyncKeyState, VIRTUAL_KEY, VK_LBUTTON, VK_MBUTTON, VK_RBUTTON, VK_XBUTTON1, VK_XBUTTON2,
+};
+
+use super::{resolve_target, HookCall};
+use crate::error::Context as _;
+use crate::mh::MhHook;
+use crate::{keyboard, remap, Hooks};
+
+type GetAsyncKeyStateType = unsafe extern "system" fn(vkey: i32) -> i16;
+type GetKeyboardStateType = unsafe extern "system" fn(key_state: *mut u8) -> BOOL;
+
+struct Trampolines {
+    get_async_key_state: GetAsyncKeyStateType,
+    get_keyboard_state: GetKeyboardStateType,
+}
+
+static mut TRAMPOLINES: OnceLock<Trampolines> = OnceLock::new();
+
+const MOUSE_BUTTONS: [VIRTUAL_KEY; 5] =
+    [VK_LBUTTON, VK_RBUTTON, VK_MBUTTON, VK_XBUTTON1, VK_XBUTTON2];
+
+// Whether the state of `vk` is hidden from the game right now.
+fn is_masked(vk: usize) -> bool {
+    keyboard::is_keyboard_captured() && !MOUSE_BUTTONS.iter().any(|button| button.0 as usize == vk)
+}
+
+// The actual asynchronous state of `vk`, for the overlay's own polling, which
+// must not be masked.
+pub(crate) fn async_key_state(vk: VIRTUAL_KEY) -> i16 {
+    unsafe {
+        match TRAMPOLINES.get() {
+            Some(Trampolines { get_async_key_state, .. }) => get_async_key_state(vk.0 as i32),
+            None => GetAsyncKeyState(vk.0 as i32),
+        }
+    }
+}
+
+unsafe extern "system" fn get_async_key_state_impl(vkey: i32) -> i16 {
+    let _call = HookCall::enter();
+
+    let Trampolines { get_async_key_state, .. } =
+        TRAMPOLINES.get().expect("Input trampolines uninitialized");
+
+    let state = remap::game_state(vkey as usize & 0xff, |vk| get_async_key_state(vk as i32));
+    if is_masked(vkey as usize & 0xff) {
+        0
+    } else {
+        state
+    }
+}
+
+unsafe extern "system" fn get_keyboard_state_impl(key_state: *mut u8) -> BOOL {
+    let _call = HookCall::enter();
+
+    let Trampolines { get_keyboard_state, .. } =
+        TRAMPOLINES.get().expect("Input trampolines uninitialized");
+
+    let res = get_keyboard_state(key_state);
+    if res.as_bool() && !key_state.is_null() {
+        let key_state = slice::from_raw_parts_mut(key_state, 256);
+        let mut pressed = [0u8; 256];
+        pressed.copy_from_slice(key_state);
+        for (vk, state) in key_state.iter_mut().enumerate() {
+            let down = remap::game_state(vk, |key| pressed[key] & 0x80);
+            *state = (*state & !0x80) | down;
+        }
+
+        // Clear the down bit, keeping the toggle bit of e.g. Caps Lock.
+        for (vk, state) in key_state.iter_mut().enumerate() {
+            if is_masked(vk) {
+                *state &= !0x80;
+            }
+        }
+    }
+    res
+}
+
+unsafe fn get_target_addrs(
+) -> std::result::Result<(GetAsyncKeyStateType, GetKeyboardStateType), crate::Error> {
+    let user32 = GetModuleHandleA(s!("user32.dll")).context("GetModuleHandleA(user32.dll)")?;
+
+    let get_async_key_state_func = GetProcAddress(user32, s!("GetAsyncKeyState"))
+        .ok_or(crate::Error::Missing("GetProcAddress(GetAsyncKeyState)"))?;
+    let get_keyboard_state_func = GetProcAddress(user32, s!("GetKeyboardState"))
+        .ok_or(crate::Error::Missing("GetProcAddress(GetKeyboardState)"))?;
+
+    let get_async_key_state_addr =
+        resolve_target::<()>("user32.GetAsyncKeyState", None, get_async_key_state_func as usize);
+    let get_keyboard_state_addr =
+        resolve_target::<()>("user32.GetKeyboardState", None, get_keyboard_state_func as usize);
+
+    Ok((
+        mem::transmute::<usize, GetAsyncKeyStateType>(get_async_key_state_addr),
+        mem::transmute::<usize, GetKeyboardStateType>(get_keyboard_state_addr),
+    ))
+}
+
+/// Hooks hiding the keyboard from the game while `imgui` captures it.
+pub struct InputHooks([MhHook; 2]);
+
+impl InputHooks {
+    /// Construct a set of [`MhHook`]s that mask the keyboard state polled by
+    /// the game while `imgui` captures the keyboard.
+    ///
+    /// The following functions are hooked:
+    /// - `user32.GetAsyncKeyState`
+    /// - `user32.GetKeyboardState`
+    ///
+    /// # Panics
+    ///
+    /// If the hooks can't be created. See [`try_new`](Self::try_new).
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn new() -> Self {
+        Self::try_new().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`new`](Self::new), returning an [`Error`](crate::Error) if the
+    /// hooks can't be created.
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn try_new() -> std::result::Result<Self, crate::Error> {
+        let (get_async_key_state_addr, get_keyboard_state_addr) = get_target_addrs()?;
+
+        trace!("user32.GetAsyncKeyState = {:p}", get_async_key_state_addr as *const c_void);
+        let hook_get_async_key_state = MhHook::named(
+            "user32.GetAsyncKeyState",
+            get_async_key_state_addr as *mut _,
+            get_async_key_state_impl as *mut _,
+        )
+        .map_err(crate::Error::hook("user32.GetAsyncKeyState"))?;
+
+        trace!("user32.GetKeyboardState = {:p}", get_keyboard_state_addr as *const c_void);
+        let hook_get_keyboard_state = MhHook::named(
+            "user32.GetKeyboardState",
+            get_keyboard_state_addr as *mut _,
+            get_keyboard_state_impl as *mut _,
+        )
+        .map_err(crate::Error::hook("user32.GetKeyboardState"))?;
+
+        let hooks = [hook_get_async_key_state, hook_get_keyboard_state];
+
+        TRAMPOLINES.get_or_init(|| trampolines(&hooks));
+
+        Ok(Self(hooks))
+    }
+}
+
+unsafe fn trampolines(
+    [hook_get_async_key_state, hook_get_keyboard_state]: &[MhHook; 2],
+) -> Trampolines {
+    Trampolines {
+        get_async_key_state: mem::transmute::<*mut c_void, GetAsyncKeyStateType>(
+            hook_get_async_key_state.trampoline(),
+        ),
+        get_keyboard_state: mem::transmute::<*mut c_void, GetKeyboardStateType>(
+            hook_get_keyboard_state.trampoline(),
+        ),
+    }
+}
+
+impl Hooks for InputHooks {
+    fn from_render_loop<T>(t: T) -> Box<Self>
+    where
+        Self: Sized,
+        T: crate::ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Self::try_from_render_loop(t).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    fn try_from_render_loop<T>(_: T) -> std::result::Result<Box<Self>, crate::Error>
+    where
+        Self: Sized,
+        T: crate::ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Err(crate::Error::NoRenderLoop("InputHooks"))
+    }
+
+    fn hooks(&self) -> &[MhHook] {
+        &self.0
+    }
+
+    fn hooks_mut(&mut self) -> &mut [MhHook] {
+        &mut self.0
+    }
+
+    unsafe fn reload_trampolines(&mut self) {
+        TRAMPOLINES.take();
+        TRAMPOLINES.get_or_init(|| trampolines(&self.0));
+    }
+
+    unsafe fn unhook(&mut self) {
+        TRAMPOLINES.take();
+    }
+}
@@ -1,28 +1,349 @@
 //! Implementations of render engine hooks.
 
-use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use std::{mem, thread};
 
-use tracing::{debug, error};
-use windows::core::w;
+use parking_lot::Mutex;
+use tracing::{debug, error, info, warn};
+use windows::core::{Error, Result, HSTRING, PCWSTR};
 use windows::Win32::Foundation::{BOOL, HWND, LPARAM, LRESULT, WPARAM};
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
 use windows::Win32::System::Threading::GetCurrentProcessId;
 use windows::Win32::UI::WindowsAndMessaging::{
-    CreateWindowExW, DefWindowProcW, DestroyWindow, EnumWindows, GetWindowThreadProcessId,
-    RegisterClassExW, UnregisterClassW, CS_HREDRAW, CS_VREDRAW, WNDCLASSEXW,
-    WS_EX_OVERLAPPEDWINDOW, WS_OVERLAPPEDWINDOW,
+    CreateWindowExW, DefWindowProcW, DestroyWindow, EnumWindows, GetClassNameW,
+    GetWindowThreadProcessId, IsWindow, RegisterClassExW, UnregisterClassW, CS_HREDRAW, CS_VREDRAW,
+    WNDCLASSEXW, WS_EX_OVERLAPPEDWINDOW, WS_OVERLAPPEDWINDOW,
 };
 
+use crate::instances::HookedApis;
+use crate::names;
+
+#[cfg(feature = "renderer")]
+pub mod console;
 #[cfg(feature = "dx11")]
 pub mod dx11;
 #[cfg(feature = "dx12")]
 pub mod dx12;
 #[cfg(feature = "dx9")]
 pub mod dx9;
+pub mod dxgi;
+#[cfg(feature = "renderer")]
+pub mod input;
 #[cfg(feature = "opengl3")]
 pub mod opengl3;
 
+static TARGET_OVERRIDES: Mutex<Vec<(String, HookTarget)>> = Mutex::new(Vec::new());
+static ACTIVE_API: AtomicU32 = AtomicU32::new(0);
+// Dormant APIs already reported, so that each is only reported once.
+static DORMANT_APIS: AtomicU32 = AtomicU32::new(0);
+// Hook invocations currently executing code of this DLL.
+static CALLS_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+// Marks a hook invocation as in flight until dropped, so that the hooks aren't
+// torn down, nor the DLL unloaded, while the game executes it. Created first
+// thing in every hook, and kept until the hook returns, trampoline call
+// included.
+pub(crate) struct HookCall(());
+
+impl HookCall {
+    pub(crate) fn enter() -> Self {
+        CALLS_IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        Self(())
+    }
+}
+
+impl Drop for HookCall {
+    fn drop(&mut self) {
+        CALLS_IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Wait for the hook invocations in flight to return, once the hooks are
+// disabled and no new ones can start. Returns `false` if some are still in
+// flight after `timeout`, e.g. because a hook is waiting for the caller.
+pub(crate) fn wait_for_calls(timeout: Duration) -> bool {
+    let start = Instant::now();
+    while CALLS_IN_FLIGHT.load(Ordering::SeqCst) > 0 {
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    true
+}
+
+// Number of hook invocations in flight.
+pub(crate) fn calls_in_flight() -> usize {
+    CALLS_IN_FLIGHT.load(Ordering::SeqCst)
+}
+
+type RenderDisabledCallback = Box<dyn Fn(&Error) + Send + Sync>;
+
+// 0 never disables rendering.
+static MAX_RENDER_ERRORS: AtomicU32 = AtomicU32::new(120);
+static RENDER_ERRORS: AtomicU32 = AtomicU32::new(0);
+static RENDER_DISABLED: AtomicBool = AtomicBool::new(false);
+static ON_RENDER_DISABLED: Mutex<Option<RenderDisabledCallback>> = Mutex::new(None);
+
+/// Stop rendering the overlay after `max_errors` consecutive frames failed to
+/// render, e.g. because a driver update broke the renderer, instead of
+/// failing, and logging the error, on every frame. The game keeps running
+/// without the overlay. `None` never stops rendering. Frames presented while
+/// the renderer waits for the game, e.g. for its DirectX 12 command queue,
+/// aren't errors.
+///
+/// Defaults to 120 frames, i.e. a couple of seconds.
+pub fn set_max_render_errors(max_errors: Option<u32>) {
+    MAX_RENDER_ERRORS.store(max_errors.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Call `callback` with the last error when rendering is stopped after
+/// repeated errors, e.g. to notify the user. Called on the render thread.
+pub fn on_render_disabled(callback: impl Fn(&Error) + Send + Sync + 'static) {
+    *ON_RENDER_DISABLED.lock() = Some(Box::new(callback));
+}
+
+/// Whether rendering was stopped after repeated errors. See
+/// [`set_max_render_errors`].
+pub fn is_render_disabled() -> bool {
+    RENDER_DISABLED.load(Ordering::Relaxed)
+}
+
+/// Try rendering the overlay again after it was stopped after repeated
+/// errors, e.g. after the user changed the game's graphics settings.
+pub fn enable_render() {
+    RENDER_ERRORS.store(0, Ordering::Relaxed);
+    RENDER_DISABLED.store(false, Ordering::Relaxed);
+}
+
+// Render a frame with `render`, unless rendering was stopped after repeated
+// errors, and keep track of its errors.
+pub(crate) fn render_frame(render: impl FnOnce() -> Result<()>) {
+    if is_render_disabled() {
+        return;
+    }
+
+    let Err(e) = render() else {
+        RENDER_ERRORS.store(0, Ordering::Relaxed);
+        return;
+    };
+
+    error!("Render error: {e:?}");
+
+    let errors = RENDER_ERRORS.fetch_add(1, Ordering::Relaxed) + 1;
+    let max_errors = MAX_RENDER_ERRORS.load(Ordering::Relaxed);
+    if max_errors == 0 || errors < max_errors {
+        return;
+    }
+
+    error!("{errors} consecutive render errors, disabling the overlay");
+    RENDER_DISABLED.store(true, Ordering::Relaxed);
+    if let Some(callback) = ON_RENDER_DISABLED.lock().as_ref() {
+        callback(&e);
+    }
+}
+
+// Whether any window is bypassed, sparing the present hooks from looking up
+// the window of the frame otherwise.
+static BYPASSING: AtomicBool = AtomicBool::new(false);
+static BYPASSED_WINDOWS: Mutex<Vec<isize>> = Mutex::new(Vec::new());
+static BYPASSED_CLASSES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// Whether the windows that presented frames are bypassed, decided the first
+// time each presents. Forgotten once the windows are destroyed, as their
+// handles may be reused.
+static BYPASS_DECISIONS: Mutex<Vec<(isize, bool)>> = Mutex::new(Vec::new());
+
+/// Never render the overlay on `hwnd`, e.g. a launcher window, or a video
+/// player embedded in the game. Its frames are presented untouched, and
+/// don't claim the overlay for their graphics API.
+pub fn bypass_window(hwnd: HWND) {
+    BYPASSED_WINDOWS.lock().push(hwnd.0);
+    BYPASS_DECISIONS.lock().clear();
+    BYPASSING.store(true, Ordering::SeqCst);
+}
+
+/// Never render the overlay on the windows whose class name matches
+/// `pattern`, where `*` matches any characters and `?` any single character,
+/// e.g. `"Launcher*"`. Case insensitive. See [`bypass_window`].
+pub fn bypass_window_class(pattern: &str) {
+    BYPASSED_CLASSES.lock().push(pattern.to_string());
+    BYPASS_DECISIONS.lock().clear();
+    BYPASSING.store(true, Ordering::SeqCst);
+}
+
+/// Whether the overlay is never rendered on `hwnd`. Class names are matched
+/// the first time a window presents a frame, before its renderer is created.
+pub fn is_bypassed(hwnd: HWND) -> bool {
+    if let Some(&(_, bypassed)) = BYPASS_DECISIONS.lock().iter().find(|(h, _)| *h == hwnd.0) {
+        return bypassed;
+    }
+
+    let bypassed = BYPASSED_WINDOWS.lock().contains(&hwnd.0) || {
+        let mut buf = [0u16; 256];
+        let len = unsafe { GetClassNameW(hwnd, &mut buf) } as usize;
+        let class_name = String::from_utf16_lossy(&buf[..len]);
+        BYPASSED_CLASSES.lock().iter().any(|pattern| wildcard_match(pattern, &class_name))
+    };
+    if bypassed {
+        info!("Not rendering the overlay on window {hwnd:?}");
+    }
+
+    let mut decisions = BYPASS_DECISIONS.lock();
+    // Windows without a pipeline, e.g. bypassed ones, aren't forgotten when
+    // destroyed: drop their decisions whenever a new one is made.
+    decisions.retain(|&(h, _)| unsafe { IsWindow(HWND(h)) }.as_bool());
+    decisions.push((hwnd.0, bypassed));
+    bypassed
+}
+
+// Forget whether `hwnd` is bypassed. Called when the window is destroyed.
+pub(crate) fn forget_window(hwnd: HWND) {
+    BYPASS_DECISIONS.lock().retain(|&(h, _)| h != hwnd.0);
+}
+
+// Whether the frame presented to the window `hwnd` returns, if any, must be
+// presented untouched. Called by the present hooks before claiming the API.
+pub(crate) fn bypasses(hwnd: impl FnOnce() -> Option<HWND>) -> bool {
+    BYPASSING.load(Ordering::Relaxed) && hwnd().is_some_and(is_bypassed)
+}
+
+// Whether `text` matches `pattern`, where `*` matches any characters and `?`
+// any single character. Case insensitive.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern, and of the text it was tried at.
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            // Let the last `*` match one more character.
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The graphics API the overlay renders with, or `None` until the game
+/// presents its first frame.
+///
+/// When hooks for several APIs are applied, e.g. because the game's API isn't
+/// known in advance, the first API the game presents a frame with renders the
+/// overlay. The hooks of the other APIs stay dormant, passing every call
+/// through, so that games presenting with several APIs, e.g. a DirectX 9
+/// launcher followed by a DirectX 11 game, don't get two overlays fighting
+/// over the same window.
+pub fn active_api() -> Option<HookedApis> {
+    match ACTIVE_API.load(Ordering::SeqCst) {
+        0 => None,
+        bits => Some(HookedApis::from_bits_retain(bits)),
+    }
+}
+
+// Claim the overlay for `api` if no other API did yet. Returns whether `api`
+// renders the overlay; if not, its hooks must pass the call through.
+pub(crate) fn claim_api(api: HookedApis) -> bool {
+    match ACTIVE_API.compare_exchange(0, api.bits(), Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => {
+            info!("Rendering the overlay with {api:?}");
+            true
+        },
+        Err(active) if active == api.bits() => true,
+        Err(active) => {
+            if DORMANT_APIS.fetch_or(api.bits(), Ordering::Relaxed) & api.bits() == 0 {
+                warn!(
+                    "Game presented a {api:?} frame while rendering the overlay with {:?}; the \
+                     {api:?} hooks stay dormant",
+                    HookedApis::from_bits_retain(active)
+                );
+            }
+            false
+        },
+    }
+}
+
+// Let another API claim the overlay once the hooks of `api` are removed.
+pub(crate) fn release_api(api: HookedApis) {
+    ACTIVE_API.compare_exchange(api.bits(), 0, Ordering::SeqCst, Ordering::SeqCst).ok();
+    DORMANT_APIS.fetch_and(!api.bits(), Ordering::Relaxed);
+}
+
+/// Alternative location of a hooked function.
+///
+/// By default, hooked functions are found through dummy objects, e.g. the
+/// vtable of a swap chain created on a hidden window. Games shipping modified
+/// runtimes or shim layers may need a different function hooked instead. See
+/// [`HudhookBuilder::with_target`](crate::HudhookBuilder::with_target).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookTarget {
+    /// The function at slot `index` of the vtable of the dummy object, e.g.
+    /// `8` for `IDXGISwapChain::Present`.
+    VtableIndex(usize),
+    /// The function at `offset` bytes from the base address of `module`, which
+    /// must already be loaded in the process.
+    ModuleOffset {
+        /// Name or path of the module, e.g. `dxgi.dll`.
+        module: String,
+        /// Offset of the function from the base address of the module.
+        offset: usize,
+    },
+}
+
+// Override the location of the hooked function `name`, e.g.
+// `IDXGISwapChain::Present`.
+pub(crate) fn set_target(name: &str, target: HookTarget) {
+    let mut overrides = TARGET_OVERRIDES.lock();
+    overrides.retain(|(n, _)| n != name);
+    overrides.push((String::from(name), target));
+}
+
+// Address of the hooked function `name`: `default`, as found in `vtable`,
+// unless its location was overridden.
+pub(crate) unsafe fn resolve_target<V>(name: &str, vtable: Option<&V>, default: usize) -> usize {
+    let target = TARGET_OVERRIDES.lock().iter().find(|(n, _)| n == name).map(|(_, t)| t.clone());
+
+    let addr = match target {
+        None => return default,
+        Some(HookTarget::VtableIndex(index)) => match vtable {
+            Some(vtable) => *(vtable as *const V as *const usize).add(index),
+            None => {
+                error!("{name} is not found through a vtable, ignoring vtable index {index}");
+                return default;
+            },
+        },
+        Some(HookTarget::ModuleOffset { module, offset }) => {
+            match GetModuleHandleW(&HSTRING::from(module.as_str())) {
+                Ok(hmodule) => hmodule.0 as usize + offset,
+                Err(e) => {
+                    error!("{name}: module {module} is not loaded: {e:?}");
+                    return default;
+                },
+            }
+        },
+    };
+
+    debug!("{name} overridden: {addr:#x} instead of {default:#x}");
+    addr
+}
+
 /// A utility function to retrieve the top level [`HWND`] belonging to this
 /// process.
 pub fn find_process_hwnd() -> Option<HWND> {
@@ -52,7 +373,7 @@ pub fn find_process_hwnd() -> Option<HWND> {
 ///
 /// Registers a class and creates a window on instantiation.
 /// Destroys the window and unregisters the class on drop.
-pub struct DummyHwnd(HWND, WNDCLASSEXW);
+pub struct DummyHwnd(HWND, WNDCLASSEXW, HSTRING);
 
 impl Default for DummyHwnd {
     fn default() -> Self {
@@ -73,7 +394,8 @@ impl DummyHwnd {
             DefWindowProcW(hwnd, msg, wparam, lparam)
         }
 
-        // Create and register the class.
+        // Create and register the class. The name must outlive the class.
+        let class_name = names::window_class();
         let wndclass = WNDCLASSEXW {
             cbSize: mem::size_of::<WNDCLASSEXW>() as u32,
             style: CS_HREDRAW | CS_VREDRAW,
@@ -81,7 +403,7 @@ impl DummyHwnd {
             cbClsExtra: 0,
             cbWndExtra: 0,
             hInstance: unsafe { GetModuleHandleW(None).unwrap().into() },
-            lpszClassName: w!("HUDHOOK"),
+            lpszClassName: PCWSTR(class_name.as_ptr()),
             ..Default::default()
         };
         debug!("{:?}", wndclass);
@@ -92,7 +414,7 @@ impl DummyHwnd {
             CreateWindowExW(
                 WS_EX_OVERLAPPEDWINDOW,
                 wndclass.lpszClassName,
-                w!("HUDHOOK"),
+                wndclass.lpszClassName,
                 WS_OVERLAPPEDWINDOW,
                 0,
                 0,
@@ -106,7 +428,7 @@ impl DummyHwnd {
         };
         debug!("{:?}", hwnd);
 
-        Self(hwnd, wndclass)
+        Self(hwnd, wndclass, class_name)
     }
 
     /// Retrieve the window handle.
@@ -128,3 +450,55 @@ impl Drop for DummyHwnd {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "renderer")]
+    struct TestLoop;
+
+    #[cfg(feature = "renderer")]
+    impl crate::ImguiRenderLoop for TestLoop {
+        fn render(&mut self, _: &mut imgui::Ui) {}
+    }
+
+    #[cfg(feature = "renderer")]
+    #[test]
+    fn test_try_from_render_loop_without_imgui() {
+        use crate::Hooks;
+
+        let error = |hooks| Some(crate::Error::NoRenderLoop(hooks));
+        assert_eq!(dxgi::DxgiHooks::try_from_render_loop(TestLoop).err(), error("DxgiHooks"));
+        assert_eq!(input::InputHooks::try_from_render_loop(TestLoop).err(), error("InputHooks"));
+        assert_eq!(
+            console::ConsoleHooks::try_from_render_loop(TestLoop).err(),
+            error("ConsoleHooks")
+        );
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("Launcher", "Launcher"));
+        assert!(!wildcard_match("Launcher", "LauncherWindow"));
+
+        assert!(wildcard_match("Launcher*", "Launcher"));
+        assert!(wildcard_match("Launcher*", "LauncherWindow"));
+        assert!(wildcard_match("*Window", "LauncherWindow"));
+        assert!(wildcard_match("L*n*r", "Launcher"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("Launcher*", "GameWindow"));
+
+        assert!(wildcard_match("Window?", "Window1"));
+        assert!(wildcard_match("?aunch?r", "Launcher"));
+        assert!(!wildcard_match("Window?", "Window"));
+        assert!(!wildcard_match("Window?", "Window12"));
+        assert!(wildcard_match("*?1", "Window1"));
+
+        assert!(wildcard_match("", ""));
+        assert!(!wildcard_match("", "Launcher"));
+
+        assert!(wildcard_match("launcher*", "LAUNCHERWindow"));
+        assert!(wildcard_match("UNITY?WNDCLASS", "Unity_WndClass"));
+    }
+}
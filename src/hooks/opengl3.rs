@@ -1,20 +1,25 @@
 //! Hooks for OpenGL 3.
 
-use std::ffi::{c_void, CString};
+use std::ffi::c_void;
 use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::OnceLock;
 
 use imgui::Context;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
-use tracing::{error, trace};
-use windows::core::{Error, Result, HRESULT, PCSTR};
+use tracing::error;
+use windows::core::{s, Error, Result, HRESULT};
 use windows::Win32::Graphics::Gdi::{WindowFromDC, HDC};
 use windows::Win32::System::LibraryLoader::{GetModuleHandleA, GetProcAddress};
 
+use super::{bypasses, claim_api, release_api, render_frame, resolve_target, HookCall};
+use crate::error::Context as _;
+use crate::instances::HookedApis;
 use crate::mh::MhHook;
-use crate::renderer::{OpenGl3RenderEngine, Pipeline};
-use crate::{Hooks, ImguiRenderLoop};
+use crate::renderer::{reset_if_stale, OpenGl3RenderEngine, Pipeline};
+use crate::util::trace_hot_path;
+use crate::{frame_step, game_thread, Hooks, ImguiRenderLoop};
 
 type OpenGl32wglSwapBuffersType = unsafe extern "system" fn(HDC) -> ();
 
@@ -24,7 +29,11 @@ struct Trampolines {
 
 static mut TRAMPOLINES: OnceLock<Trampolines> = OnceLock::new();
 static mut PIPELINE: OnceCell<Mutex<Pipeline<OpenGl3RenderEngine>>> = OnceCell::new();
-static mut RENDER_LOOP: OnceCell<Box<dyn ImguiRenderLoop + Send + Sync>> = OnceCell::new();
+static mut RENDER_LOOPS: OnceCell<Vec<Box<dyn ImguiRenderLoop + Send + Sync>>> = OnceCell::new();
+// Whether a render loop is registered, either waiting in `RENDER_LOOPS` or
+// moved into the pipeline. Until then, the present hook passes through at the
+// cost of a single load.
+static RENDER_LOOP_REGISTERED: AtomicBool = AtomicBool::new(false);
 
 unsafe fn init_pipeline(dc: HDC) -> Result<Mutex<Pipeline<OpenGl3RenderEngine>>> {
     let hwnd = WindowFromDC(dc);
@@ -32,21 +41,24 @@ unsafe fn init_pipeline(dc: HDC) -> Result<Mutex<Pipeline<OpenGl3RenderEngine>>>
     let mut ctx = Context::create();
     let engine = OpenGl3RenderEngine::new(&mut ctx)?;
 
-    let Some(render_loop) = RENDER_LOOP.take() else {
+    let Some(render_loops) = RENDER_LOOPS.take() else {
         error!("Render loop not yet initialized");
         return Err(Error::from_hresult(HRESULT(-1)));
     };
 
-    let pipeline = Pipeline::new(hwnd, ctx, engine, render_loop).map_err(|(e, render_loop)| {
-        RENDER_LOOP.get_or_init(move || render_loop);
-        e
-    })?;
+    let pipeline =
+        Pipeline::new(hwnd, ctx, engine, render_loops).map_err(|(e, render_loops)| {
+            RENDER_LOOPS.get_or_init(move || render_loops);
+            e
+        })?;
 
     Ok(Mutex::new(pipeline))
 }
 
 fn render(dc: HDC) -> Result<()> {
     unsafe {
+        reset_if_stale(&mut PIPELINE, &mut RENDER_LOOPS);
+
         let pipeline = PIPELINE.get_or_try_init(|| init_pipeline(dc))?;
 
         let Some(mut pipeline) = pipeline.try_lock() else {
@@ -63,32 +75,43 @@ fn render(dc: HDC) -> Result<()> {
 }
 
 unsafe extern "system" fn opengl32_wgl_swap_buffers_impl(dc: HDC) {
+    let _call = HookCall::enter();
+
     let Trampolines { opengl32_wgl_swap_buffers } =
         TRAMPOLINES.get().expect("OpenGL3 trampolines uninitialized");
 
-    if let Err(e) = render(dc) {
-        error!("Render error: {e:?}");
+    if !RENDER_LOOP_REGISTERED.load(Ordering::Relaxed)
+        || bypasses(|| Some(WindowFromDC(dc)))
+        || !claim_api(HookedApis::OpenGl3)
+    {
+        opengl32_wgl_swap_buffers(dc);
+        return;
     }
 
-    trace!("Call OpenGL3 wglSwapBuffers trampoline");
+    render_frame(|| render(dc));
+
+    game_thread::run_queued();
+    frame_step::pace();
+
+    trace_hot_path!("Call OpenGL3 wglSwapBuffers trampoline");
     opengl32_wgl_swap_buffers(dc);
 }
 
 // Get the address of wglSwapBuffers in opengl32.dll
-unsafe fn get_opengl_wglswapbuffers_addr() -> OpenGl32wglSwapBuffersType {
+unsafe fn get_opengl_wglswapbuffers_addr(
+) -> std::result::Result<OpenGl32wglSwapBuffersType, crate::Error> {
     // Grab a handle to opengl32.dll
-    let opengl32dll = CString::new("opengl32.dll").unwrap();
-    let opengl32module = GetModuleHandleA(PCSTR(opengl32dll.as_ptr() as *mut _))
-        .expect("failed finding opengl32.dll");
+    let opengl32module =
+        GetModuleHandleA(s!("opengl32.dll")).context("GetModuleHandleA(opengl32.dll)")?;
 
     // Grab the address of wglSwapBuffers
-    let wglswapbuffers = CString::new("wglSwapBuffers").unwrap();
-    let wglswapbuffers_func =
-        GetProcAddress(opengl32module, PCSTR(wglswapbuffers.as_ptr() as *mut _)).unwrap();
+    let wglswapbuffers_func = GetProcAddress(opengl32module, s!("wglSwapBuffers"))
+        .ok_or(crate::Error::Missing("GetProcAddress(wglSwapBuffers)"))?;
 
-    mem::transmute::<unsafe extern "system" fn() -> isize, OpenGl32wglSwapBuffersType>(
-        wglswapbuffers_func,
-    )
+    let wglswapbuffers_addr =
+        resolve_target::<()>("opengl32.wglSwapBuffers", None, wglswapbuffers_func as usize);
+
+    Ok(mem::transmute::<usize, OpenGl32wglSwapBuffersType>(wglswapbuffers_addr))
 }
 
 /// Hooks for OpenGL 3.
@@ -99,34 +122,59 @@ impl ImguiOpenGl3Hooks {
     /// provided [`ImguiRenderLoop`].
     ///
     /// The following functions are hooked:
-    /// - `opengl32::wglSwapBuffers`
+    /// - `opengl32.wglSwapBuffers`
+    ///
+    /// # Panics
+    ///
+    /// If the hooks can't be created. See [`try_new`](Self::try_new).
     ///
     /// # Safety
     ///
     /// yolo
     pub unsafe fn new<T>(t: T) -> Self
+    where
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Self::try_new(t).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`new`](Self::new), returning an [`Error`](crate::Error) if the
+    /// hooks can't be created, e.g. if `opengl32.dll` isn't loaded.
+    ///
+    /// # Safety
+    ///
+    /// yolo
+    pub unsafe fn try_new<T>(t: T) -> std::result::Result<Self, crate::Error>
     where
         T: ImguiRenderLoop + Send + Sync + 'static,
     {
         // Grab the addresses
-        let hook_opengl_swap_buffers_address = get_opengl_wglswapbuffers_addr();
+        let hook_opengl_swap_buffers_address = get_opengl_wglswapbuffers_addr()?;
 
         // Create detours
-        let hook_opengl_wgl_swap_buffers = MhHook::new(
+        let hook_opengl_wgl_swap_buffers = MhHook::named(
+            "opengl32.wglSwapBuffers",
             hook_opengl_swap_buffers_address as *mut _,
             opengl32_wgl_swap_buffers_impl as *mut _,
         )
-        .expect("couldn't create opengl32.wglSwapBuffers hook");
+        .map_err(crate::Error::hook("opengl32.wglSwapBuffers"))?;
+
+        let hooks = [hook_opengl_wgl_swap_buffers];
 
         // Initialize the render loop and store detours
-        RENDER_LOOP.get_or_init(move || Box::new(t));
-        TRAMPOLINES.get_or_init(|| Trampolines {
-            opengl32_wgl_swap_buffers: mem::transmute::<*mut c_void, OpenGl32wglSwapBuffersType>(
-                hook_opengl_wgl_swap_buffers.trampoline(),
-            ),
-        });
-
-        Self([hook_opengl_wgl_swap_buffers])
+        RENDER_LOOPS.get_or_init(move || vec![Box::new(t)]);
+        RENDER_LOOP_REGISTERED.store(true, Ordering::SeqCst);
+        TRAMPOLINES.get_or_init(|| trampolines(&hooks));
+
+        Ok(Self(hooks))
+    }
+}
+
+unsafe fn trampolines([hook_opengl_wgl_swap_buffers]: &[MhHook; 1]) -> Trampolines {
+    Trampolines {
+        opengl32_wgl_swap_buffers: mem::transmute::<*mut c_void, OpenGl32wglSwapBuffersType>(
+            hook_opengl_wgl_swap_buffers.trampoline(),
+        ),
     }
 }
 
@@ -139,13 +187,39 @@ impl Hooks for ImguiOpenGl3Hooks {
         Box::new(unsafe { ImguiOpenGl3Hooks::new(t) })
     }
 
+    fn try_from_render_loop<T>(t: T) -> std::result::Result<Box<Self>, crate::Error>
+    where
+        Self: Sized,
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        unsafe { ImguiOpenGl3Hooks::try_new(t) }.map(Box::new)
+    }
+
     fn hooks(&self) -> &[MhHook] {
         &self.0
     }
 
+    fn hooks_mut(&mut self) -> &mut [MhHook] {
+        &mut self.0
+    }
+
+    unsafe fn reload_trampolines(&mut self) {
+        TRAMPOLINES.take();
+        TRAMPOLINES.get_or_init(|| trampolines(&self.0));
+    }
+
+    fn add_render_loop(&mut self, render_loop: Box<dyn ImguiRenderLoop + Send + Sync>) {
+        match unsafe { RENDER_LOOPS.get_mut() } {
+            Some(render_loops) => render_loops.push(render_loop),
+            None => error!("Render loops already moved into the pipeline"),
+        }
+    }
+
     unsafe fn unhook(&mut self) {
+        RENDER_LOOP_REGISTERED.store(false, Ordering::SeqCst);
+        release_api(HookedApis::OpenGl3);
         TRAMPOLINES.take();
         PIPELINE.take().map(|p| p.into_inner().take());
-        RENDER_LOOP.take();
+        RENDER_LOOPS.take();
     }
 }
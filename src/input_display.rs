@@ -0,0 +1,264 @@
+//! On-screen display of the keyboard and controller input.
+//!
+//! Streamers and practice tools show the inputs the player makes, to explain
+//! a trick or check its timing. [`InputDisplay`] draws the state of a set of
+//! keys and of an XInput controller, with its sticks and triggers, followed
+//! by a timeline of the last few seconds of presses of every input.
+//!
+//! Keys are read from the keyboard state directly, unmasked by
+//! [`InputHooks`](crate::hooks::input::InputHooks), so they show whether or
+//! not the overlay captures the keyboard. Controllers are polled with XInput.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::input_display::InputDisplay;
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut input_display = InputDisplay::default();
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // ui.window("Inputs").build(|| input_display.build(ui));
+//! ```
+use std::collections::VecDeque;
+use std::mem;
+use std::time::{Duration, Instant};
+
+use imgui::{DrawListMut, Ui};
+use windows::Win32::Foundation::ERROR_SUCCESS;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    VIRTUAL_KEY, VK_A, VK_D, VK_LSHIFT, VK_S, VK_SPACE, VK_W,
+};
+use windows::Win32::UI::Input::XboxController::{
+    XInputGetState, XINPUT_GAMEPAD, XINPUT_GAMEPAD_A, XINPUT_GAMEPAD_B, XINPUT_GAMEPAD_BACK,
+    XINPUT_GAMEPAD_BUTTON_FLAGS, XINPUT_GAMEPAD_DPAD_DOWN, XINPUT_GAMEPAD_DPAD_LEFT,
+    XINPUT_GAMEPAD_DPAD_RIGHT, XINPUT_GAMEPAD_DPAD_UP, XINPUT_GAMEPAD_LEFT_SHOULDER,
+    XINPUT_GAMEPAD_LEFT_THUMB, XINPUT_GAMEPAD_RIGHT_SHOULDER, XINPUT_GAMEPAD_RIGHT_THUMB,
+    XINPUT_GAMEPAD_START, XINPUT_GAMEPAD_TRIGGER_THRESHOLD, XINPUT_GAMEPAD_X, XINPUT_GAMEPAD_Y,
+    XINPUT_STATE,
+};
+
+use crate::hooks::input::async_key_state;
+use crate::keybinds;
+
+const COLOR_PRESSED: [f32; 4] = [0.3, 0.85, 0.3, 1.0];
+const COLOR_RELEASED: [f32; 4] = [0.25, 0.25, 0.25, 0.8];
+const COLOR_OUTLINE: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+const COLOR_TEXT: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+const BUTTONS: [(XINPUT_GAMEPAD_BUTTON_FLAGS, &str); 14] = [
+    (XINPUT_GAMEPAD_A, "A"),
+    (XINPUT_GAMEPAD_B, "B"),
+    (XINPUT_GAMEPAD_X, "X"),
+    (XINPUT_GAMEPAD_Y, "Y"),
+    (XINPUT_GAMEPAD_LEFT_SHOULDER, "LB"),
+    (XINPUT_GAMEPAD_RIGHT_SHOULDER, "RB"),
+    (XINPUT_GAMEPAD_LEFT_THUMB, "LS"),
+    (XINPUT_GAMEPAD_RIGHT_THUMB, "RS"),
+    (XINPUT_GAMEPAD_BACK, "Back"),
+    (XINPUT_GAMEPAD_START, "Start"),
+    (XINPUT_GAMEPAD_DPAD_UP, "Up"),
+    (XINPUT_GAMEPAD_DPAD_DOWN, "Down"),
+    (XINPUT_GAMEPAD_DPAD_LEFT, "Left"),
+    (XINPUT_GAMEPAD_DPAD_RIGHT, "Right"),
+];
+
+// Presses of one input within the timeline, oldest first, the last one
+// possibly still held.
+struct Timeline {
+    label: String,
+    presses: VecDeque<(Instant, Option<Instant>)>,
+}
+
+/// A display of the keyboard and controller input.
+pub struct InputDisplay {
+    /// Keys shown, in order.
+    pub keys: Vec<VIRTUAL_KEY>,
+    /// XInput user index of the controller shown, from 0 to 3, or `None` to
+    /// show no controller.
+    pub controller: Option<u32>,
+    /// Time span covered by the timeline.
+    pub history: Duration,
+    timelines: Vec<Timeline>,
+}
+
+impl Default for InputDisplay {
+    fn default() -> Self {
+        Self {
+            keys: vec![VK_W, VK_A, VK_S, VK_D, VK_SPACE, VK_LSHIFT],
+            controller: Some(0),
+            history: Duration::from_secs(3),
+            timelines: Vec::new(),
+        }
+    }
+}
+
+impl InputDisplay {
+    /// Sample the input, then draw it into the current window: the keys, the
+    /// controller, and the timeline.
+    pub fn build(&mut self, ui: &Ui) {
+        let now = Instant::now();
+
+        let keys: Vec<(String, bool)> = self
+            .keys
+            .iter()
+            .map(|&key| (keybinds::key_name(key), async_key_state(key) < 0))
+            .collect();
+        let gamepad = self.controller.and_then(gamepad_state);
+
+        let mut inputs = keys.clone();
+        if let Some(gamepad) = &gamepad {
+            inputs.extend(
+                BUTTONS
+                    .iter()
+                    .map(|(flag, label)| (label.to_string(), gamepad.wButtons.0 & flag.0 != 0)),
+            );
+            let threshold = XINPUT_GAMEPAD_TRIGGER_THRESHOLD.0 as u8;
+            inputs.push(("LT".to_string(), gamepad.bLeftTrigger > threshold));
+            inputs.push(("RT".to_string(), gamepad.bRightTrigger > threshold));
+        }
+        self.record(now, &inputs);
+
+        let draw_list = ui.get_window_draw_list();
+        draw_keys(ui, &draw_list, &keys);
+        match (&gamepad, self.controller) {
+            (Some(gamepad), _) => draw_gamepad(ui, &draw_list, gamepad),
+            (None, Some(index)) => ui.text_disabled(format!("No controller {index} connected")),
+            (None, None) => {},
+        }
+        ui.separator();
+        self.draw_timelines(ui, &draw_list, now);
+    }
+
+    // Open and close the presses of the timelines.
+    fn record(&mut self, now: Instant, inputs: &[(String, bool)]) {
+        for (label, down) in inputs {
+            let index = match self.timelines.iter().position(|t| &t.label == label) {
+                Some(index) => index,
+                None => {
+                    self.timelines
+                        .push(Timeline { label: label.clone(), presses: VecDeque::new() });
+                    self.timelines.len() - 1
+                },
+            };
+            let presses = &mut self.timelines[index].presses;
+
+            let held = matches!(presses.back(), Some((_, None)));
+            if *down && !held {
+                presses.push_back((now, None));
+            } else if !*down && held {
+                if let Some((_, end)) = presses.back_mut() {
+                    *end = Some(now);
+                }
+            }
+        }
+
+        // Forget the presses that scrolled out, and the inputs no longer shown.
+        let start = now.checked_sub(self.history).unwrap_or(now);
+        for timeline in &mut self.timelines {
+            while matches!(timeline.presses.front(), Some((_, Some(end))) if *end < start) {
+                timeline.presses.pop_front();
+            }
+        }
+        self.timelines.retain(|t| inputs.iter().any(|(label, _)| label == &t.label));
+    }
+
+    fn draw_timelines(&self, ui: &Ui, draw_list: &DrawListMut<'_>, now: Instant) {
+        let history = self.history.as_secs_f32().max(f32::EPSILON);
+        let label_width = ui.calc_text_size("Start ")[0];
+        let row_height = ui.text_line_height();
+        let width = ui.content_region_avail()[0] - label_width;
+
+        for timeline in self.timelines.iter().filter(|t| !t.presses.is_empty()) {
+            let [x, y] = ui.cursor_screen_pos();
+            ui.text(&timeline.label);
+            let left = x + label_width;
+
+            draw_list
+                .add_rect([left, y], [left + width, y + row_height], COLOR_RELEASED)
+                .filled(true)
+                .build();
+
+            // Time flows from left to right, now being the right edge.
+            let pos = |t: Instant| {
+                let age = now.duration_since(t).as_secs_f32();
+                left + width * (1.0 - (age / history).min(1.0))
+            };
+            for (start, end) in &timeline.presses {
+                let min = [pos(*start), y];
+                let max = [end.map(pos).unwrap_or(left + width), y + row_height];
+                draw_list.add_rect(min, max, COLOR_PRESSED).filled(true).build();
+            }
+        }
+    }
+}
+
+fn gamepad_state(index: u32) -> Option<XINPUT_GAMEPAD> {
+    let mut state: XINPUT_STATE = unsafe { mem::zeroed() };
+    let res = unsafe { XInputGetState(index, &mut state) };
+    (res == ERROR_SUCCESS.0).then_some(state.Gamepad)
+}
+
+// Draw a labeled box, filled while pressed, and advance the cursor past it.
+fn draw_button(ui: &Ui, draw_list: &DrawListMut<'_>, label: &str, pressed: bool) {
+    let [w, h] = ui.calc_text_size(label);
+    let padding = 4.0;
+    let min = ui.cursor_screen_pos();
+    let max = [min[0] + w + 2.0 * padding, min[1] + h + 2.0 * padding];
+
+    let color = if pressed { COLOR_PRESSED } else { COLOR_RELEASED };
+    draw_list.add_rect(min, max, color).filled(true).rounding(3.0).build();
+    draw_list.add_rect(min, max, COLOR_OUTLINE).rounding(3.0).build();
+    draw_list.add_text([min[0] + padding, min[1] + padding], COLOR_TEXT, label);
+
+    ui.dummy([max[0] - min[0], max[1] - min[1]]);
+}
+
+fn draw_keys(ui: &Ui, draw_list: &DrawListMut<'_>, keys: &[(String, bool)]) {
+    for (i, (label, down)) in keys.iter().enumerate() {
+        if i > 0 {
+            ui.same_line();
+        }
+        draw_button(ui, draw_list, label, *down);
+    }
+}
+
+fn draw_gamepad(ui: &Ui, draw_list: &DrawListMut<'_>, gamepad: &XINPUT_GAMEPAD) {
+    let radius = 30.0;
+    let origin = ui.cursor_screen_pos();
+
+    // Sticks, with the position of the thumb, and triggers as bars filling up.
+    let sticks = [
+        (gamepad.sThumbLX, gamepad.sThumbLY, gamepad.bLeftTrigger),
+        (gamepad.sThumbRX, gamepad.sThumbRY, gamepad.bRightTrigger),
+    ];
+    for (i, (x, y, trigger)) in sticks.into_iter().enumerate() {
+        let left = origin[0] + i as f32 * (radius * 2.0 + 24.0);
+        let center = [left + radius, origin[1] + radius];
+        draw_list.add_circle(center, radius, COLOR_RELEASED).filled(true).build();
+        draw_list.add_circle(center, radius, COLOR_OUTLINE).build();
+
+        // Y points up on the stick and down on the screen.
+        let thumb = [
+            center[0] + x as f32 / i16::MAX as f32 * radius,
+            center[1] - y as f32 / i16::MAX as f32 * radius,
+        ];
+        draw_list.add_circle(thumb, 5.0, COLOR_PRESSED).filled(true).build();
+
+        let bar_min = [left + radius * 2.0 + 6.0, origin[1]];
+        let bar_max = [bar_min[0] + 8.0, origin[1] + radius * 2.0];
+        let fill = trigger as f32 / u8::MAX as f32 * radius * 2.0;
+        draw_list.add_rect(bar_min, bar_max, COLOR_RELEASED).filled(true).build();
+        draw_list
+            .add_rect([bar_min[0], bar_max[1] - fill], bar_max, COLOR_PRESSED)
+            .filled(true)
+            .build();
+    }
+    ui.dummy([2.0 * (radius * 2.0 + 24.0), radius * 2.0]);
+
+    for (i, (flag, label)) in BUTTONS.iter().enumerate() {
+        if i % 7 != 0 {
+            ui.same_line();
+        }
+        draw_button(ui, draw_list, label, gamepad.wButtons.0 & flag.0 != 0);
+    }
+}
@@ -0,0 +1,265 @@
+//! Handshake between multiple [`hudhook`](crate)-based DLLs in the same
+//! process.
+//!
+//! Every DLL that applies its hooks advertises its crate version and the
+//! graphics APIs it hooks in a small shared memory registry. When two DLLs
+//! built against incompatible versions hook the same API, the combination is
+//! reported instead of silently corrupting each other's hooks.
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::{mem, ptr, slice};
+
+use bitflags::bitflags;
+use tracing::{error, warn};
+use windows::core::Result;
+use windows::Win32::Foundation::{
+    CloseHandle, HANDLE, INVALID_HANDLE_VALUE, WAIT_ABANDONED, WAIT_OBJECT_0,
+};
+use windows::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_ALL_ACCESS, PAGE_READWRITE,
+};
+use windows::Win32::System::Threading::{
+    CreateMutexW, GetCurrentProcessId, ReleaseMutex, WaitForSingleObject, INFINITE,
+};
+
+use crate::names;
+use crate::version::Version;
+
+const REGISTRY_MAGIC: u32 = u32::from_le_bytes(*b"HUDH");
+// Bump this whenever the layout of `Registry` changes.
+const REGISTRY_LAYOUT: u32 = 1;
+const MAX_INSTANCES: usize = 16;
+const MODULE_NAME_LEN: usize = 64;
+
+// Keeps the shared registry alive for as long as this DLL is registered.
+static REGISTRY_MAPPING: AtomicIsize = AtomicIsize::new(0);
+
+bitflags! {
+    /// Graphics APIs hooked by a [`hudhook`](crate) instance.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[repr(transparent)]
+    pub struct HookedApis: u32 {
+        /// DirectX 9.
+        const Dx9 = 1u32 << 0;
+        /// DirectX 11.
+        const Dx11 = 1u32 << 1;
+        /// DirectX 12.
+        const Dx12 = 1u32 << 2;
+        /// OpenGL 3.
+        const OpenGl3 = 1u32 << 3;
+    }
+}
+
+/// A [`hudhook`](crate)-based DLL loaded in the current process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceInfo {
+    /// File name of the DLL.
+    pub module: String,
+    /// Version of [`hudhook`](crate) the DLL was built with.
+    pub version: Version,
+    /// Graphics APIs hooked by the DLL.
+    pub hooked_apis: HookedApis,
+}
+
+impl InstanceInfo {
+    /// Whether this instance can safely hook the same APIs as `other`, i.e.
+    /// whether their versions are semver-compatible.
+    pub fn is_compatible_with(&self, other: &InstanceInfo) -> bool {
+        self.version.is_compatible_with(&other.version)
+    }
+
+    /// Whether the two instances hook at least one API in common.
+    pub fn overlaps(&self, other: &InstanceInfo) -> bool {
+        self.hooked_apis.intersects(other.hooked_apis)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RegistryEntry {
+    in_use: u32,
+    hooked_apis: u32,
+    version: [u16; 3],
+    module: [u16; MODULE_NAME_LEN],
+}
+
+#[repr(C)]
+struct Registry {
+    magic: u32,
+    layout: u32,
+    entries: [RegistryEntry; MAX_INSTANCES],
+}
+
+impl RegistryEntry {
+    fn info(&self) -> InstanceInfo {
+        let len = self.module.iter().position(|&c| c == 0).unwrap_or(MODULE_NAME_LEN);
+        InstanceInfo {
+            module: String::from_utf16_lossy(&self.module[..len]),
+            version: Version::new(self.version[0], self.version[1], self.version[2]),
+            hooked_apis: HookedApis::from_bits_retain(self.hooked_apis),
+        }
+    }
+}
+
+/// Information about this DLL's own [`hudhook`](crate) instance.
+pub fn current_instance(hooked_apis: HookedApis) -> InstanceInfo {
+    InstanceInfo { module: crate::module_file_name(), version: Version::hudhook(), hooked_apis }
+}
+
+/// List the [`hudhook`](crate)-based DLLs that currently have their hooks
+/// applied in this process, including this one.
+pub fn instances() -> Vec<InstanceInfo> {
+    let res = with_registry(|registry| {
+        registry.entries.iter().filter(|e| e.in_use != 0).map(RegistryEntry::info).collect()
+    });
+
+    match res {
+        Ok(Some(instances)) => instances,
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            error!("Couldn't read instance registry: {e:?}");
+            Vec::new()
+        },
+    }
+}
+
+// Advertise this instance in the registry, and report every registered instance
+// it is incompatible with.
+pub(crate) fn register(hooked_apis: HookedApis) {
+    let this = current_instance(hooked_apis);
+
+    let res = with_registry(|registry| {
+        for other in registry.entries.iter().filter(|e| e.in_use != 0).map(RegistryEntry::info) {
+            if this.overlaps(&other) && !this.is_compatible_with(&other) {
+                error!(
+                    "{} (hudhook {}) and {} (hudhook {}) both hook {:?} but are incompatible; \
+                     expect crashes",
+                    this.module,
+                    this.version,
+                    other.module,
+                    other.version,
+                    this.hooked_apis & other.hooked_apis,
+                );
+            }
+        }
+
+        let Some(entry) = registry.entries.iter_mut().find(|e| e.in_use == 0) else {
+            warn!("Instance registry is full, not advertising {}", this.module);
+            return;
+        };
+
+        let mut module = [0u16; MODULE_NAME_LEN];
+        this.module
+            .encode_utf16()
+            .take(MODULE_NAME_LEN - 1)
+            .zip(module.iter_mut())
+            .for_each(|(c, m)| *m = c);
+
+        *entry = RegistryEntry {
+            in_use: unsafe { GetCurrentProcessId() },
+            hooked_apis: hooked_apis.bits(),
+            version: [this.version.major, this.version.minor, this.version.patch],
+            module,
+        };
+    });
+
+    if let Err(e) = res {
+        error!("Couldn't register in instance registry: {e:?}");
+    }
+}
+
+// Remove this instance from the registry.
+pub(crate) fn unregister() {
+    let module = crate::module_file_name();
+
+    let res = with_registry(|registry| {
+        if let Some(entry) =
+            registry.entries.iter_mut().find(|e| e.in_use != 0 && e.info().module == module)
+        {
+            entry.in_use = 0;
+        }
+    });
+
+    if let Err(e) = res {
+        error!("Couldn't unregister from instance registry: {e:?}");
+    }
+
+    let mapping = REGISTRY_MAPPING.swap(0, Ordering::SeqCst);
+    if mapping != 0 {
+        let _ = unsafe { CloseHandle(HANDLE(mapping)) };
+    }
+}
+
+// Run `f` on the shared registry while holding its lock. Returns `None` if the
+// registry was created by an incompatible layout version.
+fn with_registry<R>(f: impl FnOnce(&mut Registry) -> R) -> Result<Option<R>> {
+    let pid = unsafe { GetCurrentProcessId() };
+    let lock_name = names::kernel_object(&format!("registry-lock-{pid}"));
+    let mapping_name = names::kernel_object(&format!("registry-{pid}"));
+
+    unsafe {
+        let lock = CreateMutexW(None, false, &lock_name)?;
+        let wait = WaitForSingleObject(lock, INFINITE);
+        if wait != WAIT_OBJECT_0 && wait != WAIT_ABANDONED {
+            let _ = CloseHandle(lock);
+            return Err(windows::core::Error::from_win32());
+        }
+
+        let res = (|| {
+            let mapping = CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                None,
+                PAGE_READWRITE,
+                0,
+                mem::size_of::<Registry>() as u32,
+                &mapping_name,
+            )?;
+
+            let view =
+                MapViewOfFile(mapping, FILE_MAP_ALL_ACCESS, 0, 0, mem::size_of::<Registry>());
+            if view.Value.is_null() {
+                let e = windows::core::Error::from_win32();
+                let _ = CloseHandle(mapping);
+                return Err(e);
+            }
+
+            // The mapping is zero-initialized when first created.
+            let registry = &mut *(view.Value as *mut Registry);
+            if registry.magic == 0 {
+                ptr::write_bytes(
+                    registry as *mut Registry as *mut u8,
+                    0,
+                    mem::size_of::<Registry>(),
+                );
+                registry.magic = REGISTRY_MAGIC;
+                registry.layout = REGISTRY_LAYOUT;
+            }
+
+            let res = if registry.magic == REGISTRY_MAGIC && registry.layout == REGISTRY_LAYOUT {
+                Some(f(registry))
+            } else {
+                error!(
+                    "Instance registry has an unknown layout ({:?}); another hudhook-based DLL is \
+                     incompatible with this one",
+                    slice::from_raw_parts(view.Value as *const u32, 2)
+                );
+                None
+            };
+
+            let _ = UnmapViewOfFile(view);
+
+            // Keep one handle open so the registry outlives this call.
+            let previous =
+                REGISTRY_MAPPING.compare_exchange(0, mapping.0, Ordering::SeqCst, Ordering::SeqCst);
+            if previous.is_err() {
+                let _ = CloseHandle(mapping);
+            }
+
+            Ok(res)
+        })();
+
+        let _ = ReleaseMutex(lock);
+        let _ = CloseHandle(lock);
+
+        res
+    }
+}
@@ -0,0 +1,214 @@
+//! Key bindings of overlay actions, and their conflicts with the game.
+//!
+//! Mods declare the keys their actions are bound to with [`bind`]. While the
+//! user plays, i.e. while `imgui` doesn't capture the keyboard, the key
+//! presses reaching the game window are counted: a bound key the user
+//! presses while playing is likely bound to something in the game too.
+//! [`conflicts`] reports those keys, and keys bound to several actions, and
+//! [`settings`] lists the bindings with their conflicts so that users can
+//! rebind them.
+//!
+//! Counting presses is a heuristic: it can't tell whether the game actually
+//! reacts to a key, only that the user pressed it while playing.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::keybinds;
+//! use hudhook::windows::Win32::UI::Input::KeyboardAndMouse::VK_F1;
+//!
+//! keybinds::bind("Toggle overlay", VK_F1);
+//!
+//! // In `ImguiRenderLoop::render`, in a settings window:
+//! // if let Some((action, key)) = keybinds::settings(ui) {
+//! //     // Persist the new binding.
+//! // }
+//! ```
+use imgui::Ui;
+use parking_lot::Mutex;
+use windows::Win32::Foundation::{LPARAM, WPARAM};
+use windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY;
+use windows::Win32::UI::WindowsAndMessaging::{WM_KEYDOWN, WM_SYSKEYDOWN};
+
+use crate::keyboard;
+use crate::renderer::keys::KEYS;
+
+// Presses needed before a key is considered used by the game, so that a
+// single stray press doesn't raise a conflict.
+const GAME_KEY_THRESHOLD: u32 = 3;
+
+const COLOR_CONFLICT: [f32; 4] = [1.0, 0.6, 0.2, 1.0];
+
+static BINDINGS: Mutex<Vec<(String, VIRTUAL_KEY)>> = Mutex::new(Vec::new());
+static GAME_KEY_PRESSES: Mutex<[u32; 256]> = Mutex::new([0; 256]);
+static REBINDING: Mutex<Option<String>> = Mutex::new(None);
+
+/// Why a binding conflicts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// The user pressed the key `presses` times while playing.
+    Game {
+        /// Presses of the key that reached the game.
+        presses: u32,
+    },
+    /// The key is also bound to another overlay action.
+    Binding(String),
+}
+
+/// A binding whose key is likely used for something else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// The conflicting action.
+    pub action: String,
+    /// The key the action is bound to.
+    pub key: VIRTUAL_KEY,
+    /// Why the binding conflicts.
+    pub kind: ConflictKind,
+}
+
+/// Bind `action` to `key`, replacing the previous binding of `action`.
+pub fn bind(action: &str, key: VIRTUAL_KEY) {
+    let mut bindings = BINDINGS.lock();
+    match bindings.iter_mut().find(|(a, _)| a == action) {
+        Some(binding) => binding.1 = key,
+        None => bindings.push((action.to_string(), key)),
+    }
+}
+
+/// Forget the binding of `action`.
+pub fn unbind(action: &str) {
+    BINDINGS.lock().retain(|(a, _)| a != action);
+}
+
+/// The key `action` is bound to.
+pub fn binding(action: &str) -> Option<VIRTUAL_KEY> {
+    BINDINGS.lock().iter().find(|(a, _)| a == action).map(|&(_, key)| key)
+}
+
+/// How many times the user pressed `key` while playing.
+pub fn game_key_presses(key: VIRTUAL_KEY) -> u32 {
+    GAME_KEY_PRESSES.lock().get(key.0 as usize).copied().unwrap_or(0)
+}
+
+/// Whether the game appears to use `key`, i.e. the user pressed it several
+/// times while playing.
+pub fn game_uses_key(key: VIRTUAL_KEY) -> bool {
+    game_key_presses(key) >= GAME_KEY_THRESHOLD
+}
+
+/// Forget the key presses counted so far, e.g. after the user rebound keys
+/// in the game.
+pub fn reset_game_keys() {
+    *GAME_KEY_PRESSES.lock() = [0; 256];
+}
+
+/// The bindings that conflict with the game or with each other.
+pub fn conflicts() -> Vec<Conflict> {
+    let bindings = BINDINGS.lock().clone();
+    let mut conflicts = Vec::new();
+
+    for (action, key) in &bindings {
+        if game_uses_key(*key) {
+            conflicts.push(Conflict {
+                action: action.clone(),
+                key: *key,
+                kind: ConflictKind::Game { presses: game_key_presses(*key) },
+            });
+        }
+
+        for (other, _) in bindings.iter().filter(|(a, k)| k == key && a != action) {
+            conflicts.push(Conflict {
+                action: action.clone(),
+                key: *key,
+                kind: ConflictKind::Binding(other.clone()),
+            });
+        }
+    }
+
+    conflicts
+}
+
+/// Human readable name of `key`.
+pub fn key_name(key: VIRTUAL_KEY) -> String {
+    match KEYS.iter().find(|(_, vk)| *vk == key) {
+        Some((imgui_key, _)) => format!("{imgui_key:?}"),
+        None => format!("0x{:02X}", key.0),
+    }
+}
+
+/// Draw the bindings into the current window, one row per action with its
+/// key, its conflicts and a button to rebind it. Returns the action the user
+/// just rebound, with its new key.
+///
+/// Rebinding waits for the next key pressed in the overlay; Escape cancels.
+pub fn settings(ui: &Ui) -> Option<(String, VIRTUAL_KEY)> {
+    let bindings = BINDINGS.lock().clone();
+    let conflicts = conflicts();
+    let mut rebinding = REBINDING.lock();
+    let mut rebound = None;
+
+    ui.columns(4, "##hudhook_keybinds", false);
+    for (action, key) in bindings {
+        let _id = ui.push_id(action.as_str());
+
+        ui.text(&action);
+        ui.next_column();
+
+        ui.text(key_name(key));
+        ui.next_column();
+
+        let reasons: Vec<_> = conflicts
+            .iter()
+            .filter(|c| c.action == action)
+            .map(|c| match &c.kind {
+                ConflictKind::Game { .. } => String::from("used by the game"),
+                ConflictKind::Binding(other) => format!("also bound to {other}"),
+            })
+            .collect();
+        if reasons.is_empty() {
+            ui.text("");
+        } else {
+            ui.text_colored(COLOR_CONFLICT, reasons.join(", "));
+        }
+        ui.next_column();
+
+        if rebinding.as_deref() == Some(action.as_str()) {
+            ui.text("Press a key...");
+            if ui.is_key_pressed(imgui::Key::Escape) {
+                *rebinding = None;
+            } else if let Some(&(_, new_key)) =
+                KEYS.iter().find(|&&(imgui_key, _)| ui.is_key_pressed_no_repeat(imgui_key))
+            {
+                *rebinding = None;
+                rebound = Some((action, new_key));
+            }
+        } else if ui.button("Rebind") {
+            *rebinding = Some(action);
+        }
+        ui.next_column();
+    }
+    ui.columns(1, "##hudhook_keybinds", false);
+
+    if let Some((action, key)) = &rebound {
+        bind(action, *key);
+    }
+
+    rebound
+}
+
+// Count a key press reaching the game window while the user plays. Presses
+// synthesized by the overlay and auto-repeated key downs are ignored.
+pub(crate) fn observe(msg: u32, wparam: WPARAM, lparam: LPARAM) {
+    let is_repeat = lparam.0 & (1 << 30) != 0;
+
+    if !matches!(msg, WM_KEYDOWN | WM_SYSKEYDOWN)
+        || is_repeat
+        || keyboard::is_keyboard_captured()
+        || keyboard::is_playing()
+    {
+        return;
+    }
+
+    if let Some(presses) = GAME_KEY_PRESSES.lock().get_mut(wparam.0 & 0xff) {
+        *presses = presses.saturating_add(1);
+    }
+}
@@ -0,0 +1,244 @@
+//! Synthesized keyboard input, for overlay buttons that trigger in-game
+//! actions.
+//!
+//! A [`KeySequence`] lists key presses and pauses, and is played by a
+//! background worker so that render loops never block on its timing.
+//! Sequences are refused, and stopped midway, while `imgui` captures the
+//! keyboard: keys synthesized while the user types into an overlay text field
+//! would otherwise land in the field, or in the game behind the user's back.
+//!
+//! Example usage:
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use hudhook::keyboard::KeySequence;
+//! use hudhook::windows::Win32::UI::Input::KeyboardAndMouse::{VK_E, VK_SHIFT};
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // if ui.button("Interact") {
+//! KeySequence::new()
+//!     .down(VK_SHIFT)
+//!     .press(VK_E)
+//!     .up(VK_SHIFT)
+//!     .wait(Duration::from_millis(500))
+//!     .press(VK_E)
+//!     .send();
+//! // }
+//! ```
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use tracing::{debug, error};
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    MapVirtualKeyW, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS,
+    KEYEVENTF_KEYUP, MAPVK_VK_TO_VSC, VIRTUAL_KEY,
+};
+use windows::Win32::UI::WindowsAndMessaging::{PostMessageW, WM_KEYDOWN, WM_KEYUP};
+
+static KEYBOARD_CAPTURED: AtomicBool = AtomicBool::new(false);
+static PLAYING: AtomicBool = AtomicBool::new(false);
+static TARGET_HWND: AtomicIsize = AtomicIsize::new(0);
+static WORKER: OnceCell<Mutex<Sender<KeySequence>>> = OnceCell::new();
+
+/// How a [`KeySequence`] reaches the game.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Inject the keys into the system input stream with `SendInput`, as if
+    /// typed on a keyboard. Works with games reading raw input, but only
+    /// while the game window has the focus.
+    #[default]
+    SendInput,
+    /// Post `WM_KEYDOWN`/`WM_KEYUP` messages to the hooked window. Works in
+    /// the background, but only with games reading window messages.
+    PostMessage,
+}
+
+/// One step of a [`KeySequence`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Press a key.
+    Down(VIRTUAL_KEY),
+    /// Release a key.
+    Up(VIRTUAL_KEY),
+    /// Pause before the next step.
+    Wait(Duration),
+}
+
+/// A sequence of key presses and pauses to play to the game.
+#[derive(Debug, Clone)]
+pub struct KeySequence {
+    steps: Vec<Step>,
+    delivery: Delivery,
+    hold: Duration,
+}
+
+impl Default for KeySequence {
+    fn default() -> Self {
+        Self { steps: Vec::new(), delivery: Delivery::default(), hold: Duration::from_millis(30) }
+    }
+}
+
+impl KeySequence {
+    /// Create an empty sequence.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deliver the sequence with `delivery`. Defaults to
+    /// [`Delivery::SendInput`].
+    pub fn with_delivery(mut self, delivery: Delivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    /// Set how long [`press`](Self::press) holds keys down. Defaults to 30ms,
+    /// as games polling the keyboard once per frame miss shorter presses.
+    pub fn with_hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Press a key.
+    pub fn down(mut self, vk: VIRTUAL_KEY) -> Self {
+        self.steps.push(Step::Down(vk));
+        self
+    }
+
+    /// Release a key.
+    pub fn up(mut self, vk: VIRTUAL_KEY) -> Self {
+        self.steps.push(Step::Up(vk));
+        self
+    }
+
+    /// Press a key, hold it down, and release it.
+    pub fn press(self, vk: VIRTUAL_KEY) -> Self {
+        let hold = self.hold;
+        self.down(vk).wait(hold).up(vk)
+    }
+
+    /// Pause before the next step.
+    pub fn wait(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Wait(duration));
+        self
+    }
+
+    /// The steps of the sequence.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Queue the sequence on the background worker, after the sequences sent
+    /// before it. Returns `false`, dropping the sequence, if `imgui`
+    /// currently captures the keyboard.
+    ///
+    /// Keys still held down when the sequence is stopped midway are released.
+    pub fn send(self) -> bool {
+        if KEYBOARD_CAPTURED.load(Ordering::SeqCst) {
+            debug!("Not sending key sequence while imgui captures the keyboard");
+            return false;
+        }
+
+        let worker = WORKER.get_or_init(|| {
+            let (tx, rx) = mpsc::channel::<KeySequence>();
+            thread::spawn(move || rx.into_iter().for_each(|sequence| sequence.play()));
+            Mutex::new(tx)
+        });
+
+        worker.lock().send(self).is_ok()
+    }
+
+    fn play(self) {
+        PLAYING.store(true, Ordering::SeqCst);
+        let mut held = Vec::new();
+
+        for step in &self.steps {
+            if KEYBOARD_CAPTURED.load(Ordering::SeqCst) {
+                debug!("Stopping key sequence as imgui captures the keyboard");
+                break;
+            }
+
+            match *step {
+                Step::Down(vk) => {
+                    self.deliver(vk, false);
+                    held.push(vk);
+                },
+                Step::Up(vk) => {
+                    self.deliver(vk, true);
+                    held.retain(|&h| h != vk);
+                },
+                Step::Wait(duration) => thread::sleep(duration),
+            }
+        }
+
+        for vk in held.into_iter().rev() {
+            self.deliver(vk, true);
+        }
+        PLAYING.store(false, Ordering::SeqCst);
+    }
+
+    fn deliver(&self, vk: VIRTUAL_KEY, up: bool) {
+        let scan_code = unsafe { MapVirtualKeyW(vk.0 as u32, MAPVK_VK_TO_VSC) } as u16;
+
+        match self.delivery {
+            Delivery::SendInput => {
+                let input = INPUT {
+                    r#type: INPUT_KEYBOARD,
+                    Anonymous: INPUT_0 {
+                        ki: KEYBDINPUT {
+                            wVk: vk,
+                            wScan: scan_code,
+                            dwFlags: if up { KEYEVENTF_KEYUP } else { KEYBD_EVENT_FLAGS(0) },
+                            time: 0,
+                            dwExtraInfo: 0,
+                        },
+                    },
+                };
+
+                if unsafe { SendInput(&[input], size_of::<INPUT>() as i32) } != 1 {
+                    error!("Could not send input for key {vk:?}");
+                }
+            },
+            Delivery::PostMessage => {
+                let hwnd = HWND(TARGET_HWND.load(Ordering::SeqCst));
+                if hwnd.0 == 0 {
+                    error!("No hooked window to post key {vk:?} to");
+                    return;
+                }
+
+                // Repeat count of 1, scan code, and the previous key state and
+                // transition state bits for key up messages.
+                let lparam = 1 | (scan_code as isize) << 16 | if up { 0b11 << 30 } else { 0 };
+                let msg = if up { WM_KEYUP } else { WM_KEYDOWN };
+
+                if let Err(e) =
+                    unsafe { PostMessageW(hwnd, msg, WPARAM(vk.0 as usize), LPARAM(lparam)) }
+                {
+                    error!("Could not post key {vk:?}: {e:?}");
+                }
+            },
+        }
+    }
+}
+
+// Record the window the overlay last rendered to, and whether imgui captured
+// the keyboard in that frame.
+pub(crate) fn set_frame_state(hwnd: HWND, keyboard_captured: bool) {
+    TARGET_HWND.store(hwnd.0, Ordering::SeqCst);
+    KEYBOARD_CAPTURED.store(keyboard_captured, Ordering::SeqCst);
+}
+
+// Whether imgui captured the keyboard in the last frame.
+pub(crate) fn is_keyboard_captured() -> bool {
+    KEYBOARD_CAPTURED.load(Ordering::SeqCst)
+}
+
+// Whether a key sequence is being played.
+pub(crate) fn is_playing() -> bool {
+    PLAYING.load(Ordering::SeqCst)
+}
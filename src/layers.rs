@@ -0,0 +1,126 @@
+//! Compositing order of the render loops.
+//!
+//! Render loops registered on the same hooks, e.g. the mods of a modpack,
+//! each render in their own `imgui` context, and the UI of each loop is drawn
+//! over the UI of the loops before it. Loops are drawn by increasing
+//! [layer](crate::ImguiRenderLoop::layer), in the order they were registered
+//! within the same layer, so the loop with the highest layer draws on top.
+//!
+//! The layer of a loop can be changed at runtime with [`set_layer`], which
+//! finds loops by their [name](crate::ImguiRenderLoop::name), and the loops
+//! currently rendered are listed by [`layers`]. [`draw`] shows them in the
+//! overlay, with widgets changing their layer.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::layers;
+//!
+//! // Draw the minimap of another mod over every other loop.
+//! layers::set_layer("minimap", 100);
+//!
+//! // From `ImguiRenderLoop::render`:
+//! // ui.window("Layers").build(|| layers::draw(ui));
+//! ```
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use imgui::Ui;
+use parking_lot::Mutex;
+
+// Layers set at runtime, by render loop name.
+static OVERRIDES: Mutex<Vec<(String, i32)>> = Mutex::new(Vec::new());
+// Bumped every time an override changes, so that pipelines sort their layers
+// again.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+// Render loops of the last pipeline that sorted its layers, in drawing order.
+static REGISTERED: Mutex<Vec<LayerInfo>> = Mutex::new(Vec::new());
+
+/// A render loop being rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerInfo {
+    /// Name of the render loop.
+    pub name: String,
+    /// Layer the render loop is drawn in.
+    pub layer: i32,
+    /// Layer the render loop asked for, before any [`set_layer`].
+    pub default_layer: i32,
+}
+
+/// The render loops currently rendered, from the bottom one to the top one.
+/// Empty until the overlay is initialized.
+pub fn layers() -> Vec<LayerInfo> {
+    REGISTERED.lock().clone()
+}
+
+/// Draw the render loops named `name` in `layer` instead of the layer they
+/// asked for. Takes effect on the next frame, and applies to loops
+/// registered later as well.
+pub fn set_layer(name: &str, layer: i32) {
+    let mut overrides = OVERRIDES.lock();
+    match overrides.iter_mut().find(|(n, _)| n == name) {
+        Some((_, l)) => *l = layer,
+        None => overrides.push((name.to_string(), layer)),
+    }
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Draw the render loops named `name` in the layer they asked for again.
+pub fn reset_layer(name: &str) {
+    OVERRIDES.lock().retain(|(n, _)| n != name);
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Draw the render loops into the current window, from the top one to the
+/// bottom one, with widgets changing their layer.
+pub fn draw(ui: &Ui) {
+    let layers = layers();
+    if layers.is_empty() {
+        ui.text_disabled("No render loops");
+        return;
+    }
+
+    let top = layers.iter().map(|l| l.layer).max().unwrap_or(0);
+    let bottom = layers.iter().map(|l| l.layer).min().unwrap_or(0);
+
+    for (i, info) in layers.iter().enumerate().rev() {
+        let _id = ui.push_id_usize(i);
+
+        let mut layer = info.layer;
+        ui.set_next_item_width(ui.current_font_size() * 6.0);
+        if ui.input_int("##layer", &mut layer).build() {
+            set_layer(&info.name, layer);
+        }
+        ui.same_line();
+        if ui.small_button("Top") {
+            set_layer(&info.name, top + 1);
+        }
+        ui.same_line();
+        if ui.small_button("Bottom") {
+            set_layer(&info.name, bottom - 1);
+        }
+        ui.same_line();
+        if info.layer == info.default_layer {
+            ui.text(&info.name);
+        } else {
+            ui.text(format!("{} (default layer {})", info.name, info.default_layer));
+            ui.same_line();
+            if ui.small_button("Reset") {
+                reset_layer(&info.name);
+            }
+        }
+    }
+}
+
+// Counter changing whenever the layers must be sorted again.
+pub(crate) fn generation() -> usize {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+// The layer the render loop `name` is drawn in.
+pub(crate) fn layer_of(name: &str, default_layer: i32) -> i32 {
+    OVERRIDES.lock().iter().find(|(n, _)| n == name).map_or(default_layer, |&(_, l)| l)
+}
+
+// Publish the render loops of a pipeline, in drawing order.
+pub(crate) fn publish(layers: Vec<LayerInfo>) {
+    *REGISTERED.lock() = layers;
+}
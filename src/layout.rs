@@ -0,0 +1,330 @@
+//! Resolution-independent window layouts with edge snapping.
+//!
+//! `imgui` stores window positions in pixels, so a resolution change scatters
+//! them. The [`LayoutManager`] instead anchors each window to a screen edge,
+//! corner or center, with an offset relative to the display size, and snaps
+//! windows dragged close to an anchor onto it.
+//!
+//! Anchors can be made relative to a [`SafeArea`] rather than the whole
+//! display, so that a HUD designed for 16:9 stays in the same place on
+//! ultrawide and 4:3 outputs.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::layout::LayoutManager;
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut layout = LayoutManager::load("layout.txt").unwrap_or_default();
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // layout.window(ui, "Stats", || ui.text("..."));
+//!
+//! // On exit:
+//! layout.save("layout.txt").unwrap();
+//! ```
+use std::path::Path;
+use std::{fs, io};
+
+use imgui::{Condition, MouseButton, Ui};
+
+/// Alignment of a window along one axis of the display.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// Left or top edge.
+    #[default]
+    Start,
+    /// Center of the display.
+    Center,
+    /// Right or bottom edge.
+    End,
+}
+
+impl Align {
+    // Fraction of the display size at which the anchor lies. Doubles as the
+    // window pivot, so that the matching window edge sits on the anchor.
+    fn fraction(self) -> f32 {
+        match self {
+            Align::Start => 0.0,
+            Align::Center => 0.5,
+            Align::End => 1.0,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Align::Start => "start",
+            Align::Center => "center",
+            Align::End => "end",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "start" => Some(Align::Start),
+            "center" => Some(Align::Center),
+            "end" => Some(Align::End),
+            _ => None,
+        }
+    }
+}
+
+/// Position of a window, relative to the display.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct WindowLayout {
+    /// Horizontal anchor.
+    pub horizontal: Align,
+    /// Vertical anchor.
+    pub vertical: Align,
+    /// Offset from the anchor, as a fraction of the display size.
+    pub offset: [f32; 2],
+}
+
+impl WindowLayout {
+    /// Anchor a window to the given alignment, with no offset.
+    pub fn anchored(horizontal: Align, vertical: Align) -> Self {
+        Self { horizontal, vertical, offset: [0.0, 0.0] }
+    }
+
+    // Position and pivot of the window within the given area.
+    fn placement(&self, [min, size]: [[f32; 2]; 2]) -> ([f32; 2], [f32; 2]) {
+        let pivot = [self.horizontal.fraction(), self.vertical.fraction()];
+        let position = [
+            min[0] + (pivot[0] + self.offset[0]) * size[0],
+            min[1] + (pivot[1] + self.offset[1]) * size[1],
+        ];
+        (position, pivot)
+    }
+}
+
+/// The region of the display that HUD elements are laid out in.
+///
+/// The safe area is the largest centered rectangle with the given aspect
+/// ratio that fits the display, shrunk by a margin on each side. On a 21:9
+/// display, a 16:9 safe area keeps HUD elements out of the outer edges
+/// instead of stretching them to the corners; on a 4:3 display, it keeps them
+/// out of the top and bottom.
+///
+/// Example usage:
+/// ```no_run
+/// use hudhook::layout::{Align, SafeArea, WindowLayout};
+///
+/// // In `ImguiRenderLoop::render`:
+/// let text = "Health: 100";
+/// let pos = SafeArea::TITLE_SAFE_16_9.place(
+///     ui.io().display_size,
+///     WindowLayout::anchored(Align::End, Align::End),
+///     ui.calc_text_size(text),
+/// );
+/// ui.get_foreground_draw_list().add_text(pos, [1.0, 1.0, 1.0], text);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SafeArea {
+    /// Aspect ratio (width / height) of the area, or `None` to use the
+    /// display's own.
+    pub aspect_ratio: Option<f32>,
+    /// Margin on each side, as a fraction of the area size.
+    pub margin: f32,
+}
+
+impl Default for SafeArea {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl SafeArea {
+    /// The 16:9 area of the display.
+    pub const ASPECT_16_9: SafeArea = SafeArea { aspect_ratio: Some(16.0 / 9.0), margin: 0.0 };
+    /// The whole display.
+    pub const FULL: SafeArea = SafeArea { aspect_ratio: None, margin: 0.0 };
+    /// The title-safe region of the 16:9 area of the display, i.e. its inner
+    /// 90%.
+    pub const TITLE_SAFE_16_9: SafeArea = SafeArea { aspect_ratio: Some(16.0 / 9.0), margin: 0.05 };
+
+    /// Create a safe area with the given aspect ratio and margin.
+    pub fn new(aspect_ratio: Option<f32>, margin: f32) -> Self {
+        Self { aspect_ratio, margin }
+    }
+
+    /// Top-left corner and size of the safe area for the given display size.
+    pub fn rect(&self, display_size: [f32; 2]) -> [[f32; 2]; 2] {
+        let [w, h] = display_size;
+
+        let [aw, ah] = match self.aspect_ratio {
+            Some(aspect) if aspect > 0.0 && h > 0.0 => {
+                if w / h > aspect {
+                    [h * aspect, h]
+                } else {
+                    [w, w / aspect]
+                }
+            },
+            _ => [w, h],
+        };
+
+        let [mx, my] = [aw * self.margin, ah * self.margin];
+
+        [[(w - aw) / 2.0 + mx, (h - ah) / 2.0 + my], [aw - 2.0 * mx, ah - 2.0 * my]]
+    }
+
+    /// Top-left corner of an item of size `item_size` placed within the safe
+    /// area according to `layout`.
+    pub fn place(
+        &self,
+        display_size: [f32; 2],
+        layout: WindowLayout,
+        item_size: [f32; 2],
+    ) -> [f32; 2] {
+        let ([x, y], [px, py]) = layout.placement(self.rect(display_size));
+        [x - item_size[0] * px, y - item_size[1] * py]
+    }
+}
+
+/// Keeps track of the layout of a set of windows.
+#[derive(Debug, Clone)]
+pub struct LayoutManager {
+    windows: Vec<(String, WindowLayout)>,
+    snap_distance: f32,
+    safe_area: SafeArea,
+}
+
+impl Default for LayoutManager {
+    fn default() -> Self {
+        Self { windows: Vec::new(), snap_distance: 16.0, safe_area: SafeArea::FULL }
+    }
+}
+
+impl LayoutManager {
+    /// Create an empty layout manager.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how close, in pixels, a window has to be dragged to an anchor to
+    /// snap onto it. Defaults to 16.
+    pub fn with_snap_distance(mut self, snap_distance: f32) -> Self {
+        self.snap_distance = snap_distance;
+        self
+    }
+
+    /// Lay windows out relative to `safe_area` rather than the whole display.
+    pub fn with_safe_area(mut self, safe_area: SafeArea) -> Self {
+        self.safe_area = safe_area;
+        self
+    }
+
+    /// Get the layout of a window.
+    pub fn get(&self, name: &str) -> Option<WindowLayout> {
+        self.windows.iter().find(|(n, _)| n == name).map(|(_, layout)| *layout)
+    }
+
+    /// Set the layout of a window.
+    pub fn set(&mut self, name: &str, layout: WindowLayout) {
+        match self.windows.iter_mut().find(|(n, _)| n == name) {
+            Some((_, l)) => *l = layout,
+            None => self.windows.push((name.to_string(), layout)),
+        }
+    }
+
+    /// Build a window whose position is managed by the layout manager.
+    ///
+    /// Windows without a layout keep their initial position, which is then
+    /// recorded. Dragging a window updates its layout.
+    pub fn window<R, F: FnOnce() -> R>(&mut self, ui: &Ui, name: &str, f: F) -> Option<R> {
+        let area = self.safe_area.rect(ui.io().display_size);
+        let dragging = ui.is_mouse_down(MouseButton::Left);
+        let layout = self.get(name);
+
+        let mut window = ui.window(name);
+        if let (Some(layout), false) = (layout, dragging) {
+            let (position, pivot) = layout.placement(area);
+            window = window.position(position, Condition::Always).position_pivot(pivot);
+        }
+
+        let mut rect = None;
+        let ret = window.build(|| {
+            rect = Some((ui.window_pos(), ui.window_size()));
+            f()
+        });
+
+        if let (Some((pos, size)), true) = (rect, dragging || layout.is_none()) {
+            self.set(name, self.layout_from_rect(pos, size, area));
+        }
+
+        ret
+    }
+
+    /// Load a layout previously written by [`save`](Self::save).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut manager = Self::default();
+
+        for line in fs::read_to_string(path)?.lines() {
+            let mut fields = line.split('\t');
+            let (Some(name), Some(h), Some(v), Some(x), Some(y)) =
+                (fields.next(), fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+
+            let (Some(horizontal), Some(vertical), Ok(x), Ok(y)) =
+                (Align::from_str(h), Align::from_str(v), x.parse(), y.parse())
+            else {
+                continue;
+            };
+
+            manager.set(name, WindowLayout { horizontal, vertical, offset: [x, y] });
+        }
+
+        Ok(manager)
+    }
+
+    /// Save the layout to a file, one window per line.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let contents: String = self
+            .windows
+            .iter()
+            .map(|(name, layout)| {
+                format!(
+                    "{name}\t{}\t{}\t{}\t{}\n",
+                    layout.horizontal.as_str(),
+                    layout.vertical.as_str(),
+                    layout.offset[0],
+                    layout.offset[1]
+                )
+            })
+            .collect();
+
+        fs::write(path, contents)
+    }
+
+    // Find the anchor of `area` closest to the window on each axis, snapping
+    // onto it if close enough.
+    fn layout_from_rect(
+        &self,
+        pos: [f32; 2],
+        size: [f32; 2],
+        [area_min, area_size]: [[f32; 2]; 2],
+    ) -> WindowLayout {
+        let axis = |pos: f32, size: f32, display: f32| {
+            [Align::Start, Align::Center, Align::End]
+                .into_iter()
+                .map(|align| {
+                    let f = align.fraction();
+                    (align, (pos + size * f) - display * f)
+                })
+                .min_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+                .map(|(align, distance)| {
+                    if distance.abs() <= self.snap_distance || display <= 0.0 {
+                        (align, 0.0)
+                    } else {
+                        (align, distance / display)
+                    }
+                })
+                .unwrap_or_default()
+        };
+
+        let (horizontal, x) = axis(pos[0] - area_min[0], size[0], area_size[0]);
+        let (vertical, y) = axis(pos[1] - area_min[1], size[1], area_size[1]);
+
+        WindowLayout { horizontal, vertical, offset: [x, y] }
+    }
+}
@@ -25,6 +25,26 @@
 //! [`darksoulsiii-practice-tool`]: https://github.com/veeenu/darksoulsiii-practice-tool
 //! [`eldenring-practice-tool`]: https://github.com/veeenu/eldenring-practice-tool
 //!
+//! ## Re-exported crates
+//!
+//! `imgui`, `windows` and `tracing` are re-exported, and should be used
+//! through [`hudhook`](crate) rather than as direct dependencies. So is
+//! `wgpu`, with the `wgpu` feature. See
+//! [`version`] for the re-export policy and for runtime version checks.
+//!
+//! ## Building without `imgui`
+//!
+//! The `imgui` renderer and everything built on it are behind the `renderer`
+//! feature, which the `dx9`, `dx11`, `dx12`, `opengl3` and `wgpu` features
+//! enable.
+//! Disable the default features to build only the hooking and lifecycle
+//! layers, and use [`DxgiHooks`](hooks::dxgi::DxgiHooks) with
+//! [`HudhookBuilder::with_hooks`] to drive your own rendering stack:
+//!
+//! ```toml
+//! hudhook = { version = "0.8", default-features = false, features = ["inject"] }
+//! ```
+//!
 //! ## Fair warning
 //!
 //! [`hudhook`](crate) provides essential, crash-safe features for memory
@@ -114,43 +134,186 @@
 #![allow(static_mut_refs)]
 #![deny(missing_docs)]
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::any::{type_name, TypeId};
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
 use std::thread;
+use std::time::Duration;
 
-use imgui::{Context, Io, TextureId, Ui};
+#[cfg(feature = "renderer")]
+pub use imgui;
+#[cfg(feature = "renderer")]
+use imgui::{Context, FontAtlas, Io, TextureId, Ui};
 use once_cell::sync::OnceCell;
-use tracing::error;
-use windows::core::Error;
-use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, WPARAM};
+pub use tracing;
+use tracing::{error, info, warn};
+#[cfg(feature = "wgpu")]
+pub use wgpu;
+pub use windows;
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, HINSTANCE, HMODULE, MAX_PATH,
+};
+#[cfg(feature = "renderer")]
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
 use windows::Win32::System::Console::{
     AllocConsole, FreeConsole, GetConsoleMode, GetStdHandle, SetConsoleMode, CONSOLE_MODE,
     ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_OUTPUT_HANDLE,
 };
-use windows::Win32::System::LibraryLoader::FreeLibraryAndExitThread;
-pub use {imgui, tracing, windows};
+use windows::Win32::System::LibraryLoader::{FreeLibraryAndExitThread, GetModuleFileNameW};
+use windows::Win32::System::Threading::{CreateMutexW, GetCurrentProcessId};
 
+use crate::instances::HookedApis;
 use crate::mh::{MH_ApplyQueued, MH_Initialize, MH_Uninitialize, MhHook, MH_STATUS};
 
+#[cfg(feature = "renderer")]
+pub mod accessibility;
+#[cfg(feature = "renderer")]
+pub mod anchors;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "renderer")]
+pub mod benchmark;
+#[cfg(feature = "renderer")]
+pub mod bind;
+#[cfg(feature = "renderer")]
+pub mod blur;
+pub mod compose;
+#[cfg(feature = "renderer")]
+pub mod console;
+#[cfg(feature = "renderer")]
+pub mod crosshair;
+pub mod debug;
+#[cfg(feature = "renderer")]
+pub mod depth;
+#[cfg(feature = "renderer")]
+pub mod engine;
+pub mod error;
+#[cfg(feature = "renderer")]
+pub mod file_watch;
+#[cfg(feature = "imgui-freetype")]
+pub mod fonts;
+#[cfg(feature = "renderer")]
+pub mod frame_step;
+#[cfg(feature = "renderer")]
+pub mod freecam;
+pub mod game_thread;
 pub mod hooks;
 #[cfg(feature = "inject")]
 pub mod inject;
+#[cfg(feature = "renderer")]
+pub mod input_display;
+pub mod instances;
+#[cfg(feature = "renderer")]
+pub mod keybinds;
+#[cfg(feature = "renderer")]
+pub mod keyboard;
+#[cfg(feature = "renderer")]
+pub mod layers;
+#[cfg(feature = "renderer")]
+pub mod layout;
+#[cfg(feature = "livesplit")]
+pub mod livesplit;
+#[cfg(feature = "renderer")]
+pub mod locale;
+#[cfg(feature = "renderer")]
+pub mod magnifier;
+pub mod memory;
 pub mod mh;
+pub mod modules;
+pub mod names;
+#[cfg(feature = "renderer")]
+pub mod palette;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+#[cfg(feature = "renderer")]
 pub(crate) mod renderer;
 
-pub use renderer::msg_filter::MessageFilter;
+pub use error::Error;
+#[cfg(feature = "renderer")]
+pub use renderer::activation::Activation;
+#[cfg(feature = "renderer")]
+pub use renderer::fullscreen::{is_borderless, set_borderless, window_state, WindowState};
+#[cfg(feature = "renderer")]
+pub use renderer::mouse::MouseLatching;
+#[cfg(feature = "renderer")]
+pub use renderer::msg_filter::{InputBlocking, MessageFilter};
 
+pub mod quirks;
+#[cfg(feature = "renderer")]
+pub mod remap;
+#[cfg(feature = "renderer")]
+pub mod replay;
+pub mod savestate;
+#[cfg(feature = "renderer")]
+pub mod schedule;
+#[cfg(feature = "renderer")]
+pub mod sessions;
+#[cfg(feature = "renderer")]
+pub mod simple;
+#[cfg(feature = "state")]
+pub mod state;
+#[cfg(feature = "renderer")]
+pub mod text;
+#[cfg(feature = "renderer")]
+pub mod textures;
+#[cfg(feature = "renderer")]
+pub mod timers;
+pub mod timing;
+#[cfg(feature = "renderer")]
+pub mod undo;
 pub mod util;
+pub mod version;
+#[cfg(feature = "renderer")]
+pub mod watch;
 
 // Global state objects.
 static mut MODULE: OnceCell<HINSTANCE> = OnceCell::new();
 static mut HUDHOOK: OnceCell<Hudhook> = OnceCell::new();
 static CONSOLE_ALLOCATED: AtomicBool = AtomicBool::new(false);
+static INSTANCE_MUTEX: AtomicIsize = AtomicIsize::new(0);
+
+// How long to wait for the calls in flight to leave the hooks when removing
+// them. Present can block for a few frames, but not for seconds.
+const RUNDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A texture created outside of [`hudhook`](crate), e.g. by a third-party
+/// `imgui` extension crate, to be registered via
+/// [`RenderContext::register_texture`].
+///
+/// The variant must match the render engine in use.
+#[cfg(feature = "renderer")]
+#[derive(Debug, Clone)]
+pub enum ExternalTexture {
+    /// A DirectX 9 texture.
+    #[cfg(feature = "dx9")]
+    Dx9(windows::Win32::Graphics::Direct3D9::IDirect3DTexture9),
+    /// A shader resource view of a DirectX 11 2D texture.
+    #[cfg(feature = "dx11")]
+    Dx11(windows::Win32::Graphics::Direct3D11::ID3D11ShaderResourceView),
+    /// A DirectX 12 2D texture resource.
+    #[cfg(feature = "dx12")]
+    Dx12(windows::Win32::Graphics::Direct3D12::ID3D12Resource),
+    /// An OpenGL 3 texture name.
+    #[cfg(feature = "opengl3")]
+    OpenGl3(u32),
+    /// A view of a `wgpu` 2D texture.
+    #[cfg(feature = "wgpu")]
+    Wgpu(std::sync::Arc<wgpu::TextureView>),
+}
 
 /// Texture Loader for ImguiRenderLoop callbacks to load and replace textures
+#[cfg(feature = "renderer")]
 pub trait RenderContext {
     /// Load texture and return TextureId to use. Invoke it in your
     /// [`crate::ImguiRenderLoop::initialize`] method for setting up textures.
-    fn load_texture(&mut self, data: &[u8], width: u32, height: u32) -> Result<TextureId, Error>;
+    /// To load textures from elsewhere, e.g. from
+    /// [`crate::ImguiRenderLoop::render`] or another thread, queue them with
+    /// [`textures::load`].
+    fn load_texture(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<TextureId, windows::core::Error>;
 
     /// Upload an image to an existing texture, replacing its content. Invoke it
     /// in your [`crate::ImguiRenderLoop::before_render`] method for
@@ -161,11 +324,51 @@ pub trait RenderContext {
         data: &[u8],
         width: u32,
         height: u32,
-    ) -> Result<(), Error>;
+    ) -> Result<(), windows::core::Error>;
+
+    /// Register a texture created outside of [`hudhook`](crate) in the render
+    /// engine's texture registry, and return the [`TextureId`] to draw it
+    /// with. The texture stays owned by the caller, and can't be replaced
+    /// via [`RenderContext::replace_texture`].
+    ///
+    /// # Safety
+    ///
+    /// The texture must have been created on the same device the render engine
+    /// draws with, must be a 2D texture, and must be kept in a state that
+    /// allows it to be sampled from the pixel shader (e.g.
+    /// `D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE` in DirectX 12) whenever
+    /// it is drawn.
+    unsafe fn register_texture(
+        &mut self,
+        texture: ExternalTexture,
+    ) -> Result<TextureId, windows::core::Error>;
+
+    /// Capabilities of the DirectX 12 device the render engine draws with,
+    /// including its current video memory budget, or `None` on other render
+    /// engines. Use it to pick texture sizes or effects appropriate for the
+    /// machine.
+    #[cfg(feature = "dx12")]
+    fn d3d12_capabilities(&self) -> Option<hooks::dx12::D3D12Capabilities> {
+        None
+    }
+}
+
+/// Return the raw `imgui` context pointer, for sharing the context with
+/// third-party `imgui` extension crates (e.g. `implot` or `imnodes` bindings).
+///
+/// Invoke it from within [`ImguiRenderLoop`] methods, while the context is
+/// active. The pointer stays valid until the render loop is dropped.
+#[cfg(feature = "renderer")]
+pub fn imgui_context_ptr(ctx: &mut Context) -> *mut imgui::sys::ImGuiContext {
+    renderer::raw_context(ctx).unwrap_or_else(|| {
+        // A context created outside of the hooks, e.g. for a standalone render
+        // engine: `imgui` keeps the only context that isn't suspended current.
+        unsafe { imgui::sys::igGetCurrentContext() }
+    })
 }
 
 /// Allocate a Windows console.
-pub fn alloc_console() -> Result<(), Error> {
+pub fn alloc_console() -> Result<(), windows::core::Error> {
     if !CONSOLE_ALLOCATED.swap(true, Ordering::SeqCst) {
         unsafe { AllocConsole()? };
     }
@@ -195,7 +398,7 @@ pub fn enable_console_colors() {
 }
 
 /// Free the previously allocated Windows console.
-pub fn free_console() -> Result<(), Error> {
+pub fn free_console() -> Result<(), windows::core::Error> {
     if CONSOLE_ALLOCATED.swap(false, Ordering::SeqCst) {
         unsafe { FreeConsole()? };
     }
@@ -208,22 +411,34 @@ pub fn free_console() -> Result<(), Error> {
 /// ## Ejecting a DLL
 ///
 /// To eject your DLL, invoke the [`eject`] method from anywhere in your
-/// render loop. This will disable the hooks, free the console (if it has
-/// been created before) and invoke
+/// render loop. This will free the console (if it has been created before),
+/// disable and remove the hooks, call [`Hooks::unhook`] on each of them to
+/// restore the original window procedures, wait for the GPU and free the
+/// renderer resources, and finally invoke
 /// [`windows::Win32::System::LibraryLoader::FreeLibraryAndExitThread`].
 ///
+/// The DLL is only unloaded once the game's calls already inside the hooks,
+/// e.g. a frame being rendered on another thread, have returned. If some
+/// don't return within a few seconds, or if another window procedure was
+/// installed on top of the overlay's and still calls it, the DLL stays loaded
+/// rather than crashing the game.
+///
 /// Befor calling [`eject`], make sure to perform any manual cleanup (e.g.
 /// dropping/resetting the contents of static mutable variables).
 pub fn eject() {
     thread::spawn(|| unsafe {
-        if let Err(e) = free_console() {
-            error!("{e:?}");
-        }
+        teardown();
 
-        if let Some(mut hudhook) = HUDHOOK.take() {
-            if let Err(e) = hudhook.unapply() {
-                error!("Couldn't unapply hooks: {e:?}");
-            }
+        // Unloading the DLL under a call still executing its code would crash
+        // the game: rather leak the DLL.
+        if hooks::calls_in_flight() > 0 {
+            error!("Hook calls still in flight, not unloading the DLL");
+            return;
+        }
+        #[cfg(feature = "renderer")]
+        if renderer::wnd_procs_chained() {
+            error!("Window procedures still chained, not unloading the DLL");
+            return;
         }
 
         if let Some(module) = MODULE.take() {
@@ -232,8 +447,130 @@ pub fn eject() {
     });
 }
 
+// Free the console and disable the applied hooks, leaving the DLL loaded.
+unsafe fn teardown() {
+    if let Err(e) = free_console() {
+        error!("{e:?}");
+    }
+
+    #[cfg(feature = "audio")]
+    audio::shutdown();
+
+    if let Some(mut hudhook) = HUDHOOK.take() {
+        if let Err(e) = hudhook.unapply() {
+            error!("Couldn't unapply hooks: {e:?}");
+        }
+    }
+}
+
+/// Keeps the hooks applied by [`HudhookBuilder::apply`] alive.
+///
+/// Dropping the guard disables the hooks and frees the console, like
+/// [`eject`], without unloading the DLL. This makes the lifetime of the hooks
+/// explicit for host applications and tests that load `hudhook` themselves.
+///
+/// Disabling the hooks waits for the frame being rendered to end. When the
+/// guard is dropped from within a render loop, the hooks are disabled from
+/// another thread once the frame ends, like [`eject`] does.
+#[must_use = "the hooks are disabled when the guard is dropped"]
+pub struct HudhookGuard(());
+
+impl Drop for HudhookGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "renderer")]
+        if renderer::is_rendering() {
+            warn!("HudhookGuard dropped from a render loop, disabling the hooks after the frame");
+            thread::spawn(|| unsafe { teardown() });
+            return;
+        }
+
+        unsafe { teardown() };
+    }
+}
+
+/// Tear down and rebuild the renderer on the next frame, keeping the render
+/// loops.
+///
+/// Use this to recover from stale renderer state, e.g. a black or missing
+/// overlay after a driver reset, without restarting the game. The render
+/// engine, its textures and the `imgui` contexts are recreated, and
+/// [`ImguiRenderLoop::initialize`] is called again so that render loops can
+/// reload their textures. Any [`TextureId`] obtained before is invalid
+/// afterwards.
+///
+/// Safe to call from within a render loop: the renderer is rebuilt before the
+/// following frame.
+#[cfg(feature = "renderer")]
+pub fn reinitialize_renderer() {
+    renderer::request_reinitialization();
+}
+
+/// Set when the overlay samples the cursor position.
+///
+/// Defaults to [`MouseLatching::Messages`]. The other modes read the cursor
+/// position right before drawing, which reduces the perceived UI lag at low
+/// frame rates.
+#[cfg(feature = "renderer")]
+pub fn set_mouse_latching(mouse_latching: MouseLatching) {
+    mouse_latching.set();
+}
+
+/// Set whether the game stops receiving the input the overlay uses, e.g. so
+/// that typing into a text field doesn't move the game character.
+///
+/// Defaults to [`InputBlocking::Never`], leaving it to
+/// [`ImguiRenderLoop::message_filter`].
+#[cfg(feature = "renderer")]
+pub fn set_input_blocking(input_blocking: InputBlocking) {
+    input_blocking.set();
+}
+
+/// Disable the hooks and restore the hooked window procedures, without
+/// releasing any other resource.
+///
+/// Call this on `DLL_PROCESS_DETACH` when the process is terminating (i.e.
+/// when the `lpReserved` argument of `DllMain` is not null), so that no
+/// hook or window procedure calls into the DLL while the game tears itself
+/// down. Other threads have already been terminated at that point, possibly
+/// while holding locks, so nothing else is cleaned up. The [`hudhook!`] macro
+/// does this automatically.
+///
+/// Calling it more than once has no effect.
+pub fn shutdown_on_process_exit() {
+    static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+    if SHUT_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    #[cfg(feature = "renderer")]
+    renderer::restore_wnd_procs();
+
+    if let Some(hudhook) = unsafe { HUDHOOK.get() } {
+        for hook in hudhook.hooks() {
+            if let Err(e) = unsafe { hook.queue_disable() } {
+                error!("Couldn't queue disabling hook: {e:?}");
+            }
+        }
+
+        if let Err(e) = unsafe { MH_ApplyQueued().ok_context("MH_ApplyQueued") } {
+            error!("Couldn't disable hooks: {e:?}");
+        }
+    }
+}
+
 /// Implement your `imgui` rendering logic via this trait.
+#[cfg(feature = "renderer")]
 pub trait ImguiRenderLoop {
+    /// Called once at the first occurrence of the hook, before
+    /// [`ImguiRenderLoop::initialize`] and before the font atlas is built.
+    /// Implement this to add fonts and glyph ranges to `fonts`; the default
+    /// `imgui` font is used if none is added.
+    ///
+    /// To change the fonts later, e.g. when the user picks another font size,
+    /// call [`reinitialize_renderer`], which calls this again.
+    fn setup_fonts(&mut self, _fonts: &mut FontAtlas) {}
+
     /// Called once at the first occurrence of the hook. Implement this to
     /// initialize your data.
     /// `ctx` is the imgui context, and `render_context` is meant to access
@@ -267,6 +604,45 @@ pub trait ImguiRenderLoop {
     fn message_filter(&self, _io: &Io) -> MessageFilter {
         MessageFilter::empty()
     }
+
+    /// Returns the regions of the window, in display coordinates, where mouse
+    /// input always reaches the game, even while
+    /// [`ImguiRenderLoop::message_filter`] blocks mouse input. Use it to
+    /// keep e.g. the center of the screen interactive while an overlay
+    /// captures the mouse elsewhere.
+    ///
+    /// The overlay keeps receiving the mouse input in these regions too.
+    fn passthrough_regions(&self, _io: &Io) -> Vec<anchors::Region> {
+        Vec::new()
+    }
+
+    /// Returns the activation mode of the overlay.
+    ///
+    /// While the overlay is not active, [`ImguiRenderLoop::render`] is not
+    /// called, nothing is drawn and no window message is filtered.
+    fn activation(&self) -> Activation {
+        Activation::Always
+    }
+
+    /// Returns the name of the render loop, which [`layers`] finds it by.
+    /// Defaults to the name of the type.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Returns the layer the render loop is drawn in, when several are
+    /// registered: loops in higher layers draw on top. Loops in the same
+    /// layer draw in the order they were registered. Defaults to `0`.
+    ///
+    /// Read once, when the overlay is initialized. Use [`layers::set_layer`]
+    /// to change it afterwards.
+    fn layer(&self) -> i32 {
+        0
+    }
+
+    /// Called at the start of a frame when a file registered with
+    /// [`file_watch::watch`] changed, after its callbacks ran.
+    fn on_file_changed(&mut self, _path: &std::path::Path) {}
 }
 
 /// Generic trait for platform-specific hooks.
@@ -280,19 +656,74 @@ pub trait ImguiRenderLoop {
 /// - [`ImguiDx11Hooks`](crate::hooks::dx11::ImguiDx11Hooks)
 /// - [`ImguiDx12Hooks`](crate::hooks::dx12::ImguiDx12Hooks)
 /// - [`ImguiOpenGl3Hooks`](crate::hooks::opengl3::ImguiOpenGl3Hooks)
+/// - [`DxgiHooks`](crate::hooks::dxgi::DxgiHooks), for hooks that don't render
+///   with `imgui`
 pub trait Hooks {
     /// Construct a boxed instance of the implementor, storing the provided
     /// render loop where appropriate.
+    #[cfg(feature = "renderer")]
     fn from_render_loop<T>(t: T) -> Box<Self>
     where
         Self: Sized,
         T: ImguiRenderLoop + Send + Sync + 'static;
 
+    /// Like [`from_render_loop`](Self::from_render_loop), returning an
+    /// [`Error`] if the hooks can't be created instead of panicking.
+    ///
+    /// The default implementation calls
+    /// [`from_render_loop`](Self::from_render_loop).
+    #[cfg(feature = "renderer")]
+    fn try_from_render_loop<T>(t: T) -> Result<Box<Self>, Error>
+    where
+        Self: Sized,
+        T: ImguiRenderLoop + Send + Sync + 'static,
+    {
+        Ok(Self::from_render_loop(t))
+    }
+
     /// Return the list of hooks to be enabled, in order.
     fn hooks(&self) -> &[MhHook];
 
+    /// Return the list of hooks mutably, so that hooks into a module the game
+    /// unloads and loads again can be recreated; see
+    /// [`reload_trampolines`](Self::reload_trampolines).
+    ///
+    /// The default implementation returns no hooks: hooks into an unloaded
+    /// module are then left in place.
+    fn hooks_mut(&mut self) -> &mut [MhHook] {
+        &mut []
+    }
+
+    /// Reload the trampolines stored by the implementor from
+    /// [`hooks`](Self::hooks), after some of the hooks were recreated at
+    /// another address. The recreated hooks are enabled afterwards.
+    ///
+    /// # Safety
+    ///
+    /// Is most definitely UB.
+    unsafe fn reload_trampolines(&mut self) {}
+
+    /// Register an additional render loop on this set of hooks.
+    ///
+    /// Every render loop gets its own `imgui` context, so that independent
+    /// render loops can't interfere with each other's ID stacks, styles or ini
+    /// data, while still sharing the same hooks and render engine. Render
+    /// loops are rendered sequentially, in registration order.
+    ///
+    /// The default implementation does not support multiple render loops and
+    /// discards the render loop.
+    #[cfg(feature = "renderer")]
+    fn add_render_loop(&mut self, _render_loop: Box<dyn ImguiRenderLoop + Send + Sync>) {
+        error!("These hooks do not support multiple render loops");
+    }
+
     /// Cleanup global data and disable the hooks.
     ///
+    /// Called by [`Hudhook::unapply`] once the hooks are disabled and the
+    /// calls inside them returned. Implementations drop their render
+    /// pipeline, which restores the window procedure and frees the renderer
+    /// resources, waiting for the GPU where needed.
+    ///
     /// # Safety
     ///
     /// Is most definitely UB.
@@ -300,69 +731,271 @@ pub trait Hooks {
 }
 
 /// Holds all the activated hooks and manages their lifetime.
-pub struct Hudhook(Vec<Box<dyn Hooks>>);
+pub struct Hudhook(Vec<(TypeId, Box<dyn Hooks>)>);
 unsafe impl Send for Hudhook {}
 unsafe impl Sync for Hudhook {}
 
 impl Hudhook {
     /// Create a builder object.
+    ///
+    /// Applies the [`quirks`] matching the current executable.
+    ///
+    /// # Panics
+    ///
+    /// If minhook can't be initialized. See [`try_builder`](Self::try_builder).
     pub fn builder() -> HudhookBuilder {
-        HudhookBuilder(Hudhook::new())
+        Self::try_builder().unwrap_or_else(|status| panic!("MH_Initialize: {status:?}"))
     }
 
-    fn new() -> Self {
+    /// Like [`builder`](Self::builder), returning the status of
+    /// `MH_Initialize` if minhook can't be initialized.
+    pub fn try_builder() -> Result<HudhookBuilder, MH_STATUS> {
+        quirks::apply();
+        Hudhook::try_new().map(HudhookBuilder)
+    }
+
+    fn try_new() -> Result<Self, MH_STATUS> {
         // Initialize minhook.
         match unsafe { MH_Initialize() } {
             MH_STATUS::MH_ERROR_ALREADY_INITIALIZED | MH_STATUS::MH_OK => {},
-            status @ MH_STATUS::MH_ERROR_MEMORY_ALLOC => panic!("MH_Initialize: {status:?}"),
-            _ => unreachable!(),
+            status => return Err(status),
         }
 
-        Hudhook(Vec::new())
+        Ok(Hudhook(Vec::new()))
     }
 
     /// Return an iterator of all the activated raw hooks.
     fn hooks(&self) -> impl IntoIterator<Item = &MhHook> {
-        self.0.iter().flat_map(|h| h.hooks())
+        self.0.iter().flat_map(|(_, h)| h.hooks())
+    }
+
+    /// Return the graphics APIs hooked by the activated hook objects.
+    fn hooked_apis(&self) -> HookedApis {
+        let known: &[(TypeId, HookedApis)] = &[
+            #[cfg(feature = "dx9")]
+            (TypeId::of::<hooks::dx9::ImguiDx9Hooks>(), HookedApis::Dx9),
+            #[cfg(feature = "dx11")]
+            (TypeId::of::<hooks::dx11::ImguiDx11Hooks>(), HookedApis::Dx11),
+            #[cfg(feature = "dx12")]
+            (TypeId::of::<hooks::dx12::ImguiDx12Hooks>(), HookedApis::Dx12),
+            #[cfg(feature = "opengl3")]
+            (TypeId::of::<hooks::opengl3::ImguiOpenGl3Hooks>(), HookedApis::OpenGl3),
+            (TypeId::of::<hooks::dxgi::DxgiHooks>(), HookedApis::Dx11.union(HookedApis::Dx12)),
+        ];
+
+        self.0
+            .iter()
+            .filter_map(|(id, _)| known.iter().find(|(known_id, _)| known_id == id))
+            .fold(HookedApis::empty(), |apis, (_, api)| apis | *api)
     }
 
     /// Apply the hooks.
+    ///
+    /// Fails with [`MH_STATUS::MH_ERROR_ALREADY_INITIALIZED`] if another copy
+    /// of the same DLL has already applied its hooks in this process, e.g.
+    /// because it was injected twice from different paths. Hooking the same
+    /// functions twice would crash the process.
+    ///
+    /// Other `hudhook`-based DLLs built against an incompatible version that
+    /// hook the same graphics APIs are reported in the logs; see
+    /// [`instances`].
+    ///
+    /// When hooks for several graphics APIs are applied, only the first API
+    /// the game presents a frame with renders the overlay; see
+    /// [`hooks::active_api`].
+    ///
+    /// The installed hooks are logged and can be inspected at runtime via
+    /// [`mh::hook_report`].
+    ///
+    /// Hooks into a module the game unloads, e.g. `dxgi.dll` swapped out by
+    /// an anti-cheat, are removed before the module is unloaded, and created
+    /// again if the same module is loaded again; see [`modules`].
     pub fn apply(self) -> Result<(), MH_STATUS> {
-        // Queue enabling all the hooks.
+        unsafe { acquire_instance_mutex()? };
+        let mut instance = InstanceGuard::new();
+
+        let hooked_apis = self.hooked_apis();
+        if hooked_apis.bits().count_ones() > 1 {
+            info!(
+                "Hooking {hooked_apis:?}: the first API the game presents a frame with renders \
+                 the overlay, the others stay dormant"
+            );
+        }
+        instances::register(hooked_apis);
+        instance.registered();
+
+        // Queue enabling all the hooks, keeping track of the status of each for the
+        // hook report.
+        let mut statuses = Vec::new();
+        let mut res = Ok(());
         for hook in self.hooks() {
-            unsafe { hook.queue_enable()? };
+            let status = unsafe { hook.queue_enable() }.err().unwrap_or(MH_STATUS::MH_OK);
+            statuses.push(status);
+            res = res.and(status.ok());
         }
 
         // Apply the queue of enable actions.
-        unsafe { MH_ApplyQueued().ok_context("MH_ApplyQueued")? };
+        if res.is_ok() {
+            res = unsafe { MH_ApplyQueued().ok_context("MH_ApplyQueued") };
+            if let Err(status) = res {
+                statuses.fill(status);
+            }
+        }
+
+        mh::set_hook_report(self.hooks(), statuses);
+        res?;
 
+        instance.disarm();
         unsafe { HUDHOOK.set(self).ok() };
 
+        modules::watch_reloads();
+        #[cfg(feature = "renderer")]
+        sessions::start();
+
         Ok(())
     }
 
     /// Disable and cleanup the hooks.
     pub fn unapply(&mut self) -> Result<(), MH_STATUS> {
-        // Queue disabling all the hooks.
-        for hook in self.hooks() {
+        modules::unwatch_reloads();
+        #[cfg(feature = "renderer")]
+        file_watch::unwatch_all();
+        #[cfg(feature = "renderer")]
+        sessions::end();
+        #[cfg(feature = "renderer")]
+        schedule::clear();
+        #[cfg(feature = "renderer")]
+        bind::clear();
+        #[cfg(feature = "renderer")]
+        remap::clear();
+        #[cfg(feature = "renderer")]
+        textures::clear();
+
+        // Queue disabling all the hooks, except those already removed along with
+        // their module.
+        for hook in self.hooks().into_iter().filter(|hook| !hook.is_detached()) {
             unsafe { hook.queue_disable()? };
         }
 
         // Apply the queue of disable actions.
         unsafe { MH_ApplyQueued().ok_context("MH_ApplyQueued")? };
 
+        // Frames held by a paused game would keep their hook calls in flight.
+        #[cfg(feature = "renderer")]
+        frame_step::release();
+
+        // Let the calls already inside the hooks return before freeing the
+        // trampolines and the state they use.
+        if !hooks::wait_for_calls(RUNDOWN_TIMEOUT) {
+            error!("{} hook calls still in flight, tearing down anyway", hooks::calls_in_flight());
+        }
+
         // Uninitialize minhook.
         unsafe { MH_Uninitialize().ok_context("MH_Uninitialize")? };
 
         // Invoke cleanup for all hooks.
-        for hook in &mut self.0 {
+        for (_, hook) in &mut self.0 {
             unsafe { hook.unhook() };
         }
 
+        mh::clear_hook_report();
+        game_thread::clear();
+        instances::unregister();
+        release_instance_mutex();
+
         Ok(())
     }
 }
 
+// Claim the named mutex identifying this DLL in the current process. Another
+// copy of the same DLL, e.g. one injected from a different path, maps to the
+// same name.
+unsafe fn acquire_instance_mutex() -> Result<(), MH_STATUS> {
+    let name =
+        names::kernel_object(&format!("instance-{}-{}", GetCurrentProcessId(), module_file_name()));
+
+    let handle = match CreateMutexW(None, false, &name) {
+        Ok(handle) => handle,
+        Err(e) => {
+            // Don't prevent the hooks from working just because the guard couldn't be set
+            // up.
+            warn!("Couldn't create instance mutex {name}: {e:?}");
+            return Ok(());
+        },
+    };
+
+    if GetLastError() == ERROR_ALREADY_EXISTS {
+        let _ = CloseHandle(handle);
+        warn!("Another instance of this DLL is already running ({name}), not applying hooks");
+        return Err(MH_STATUS::MH_ERROR_ALREADY_INITIALIZED);
+    }
+
+    INSTANCE_MUTEX.store(handle.0, Ordering::SeqCst);
+
+    Ok(())
+}
+
+// Undoes the instance bookkeeping of [`Hudhook::apply`] when it returns early
+// with an error, so a later attempt isn't refused as a second instance.
+struct InstanceGuard {
+    registered: bool,
+    armed: bool,
+}
+
+impl InstanceGuard {
+    fn new() -> Self {
+        Self { registered: false, armed: true }
+    }
+
+    fn registered(&mut self) {
+        self.registered = true;
+    }
+
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        if self.registered {
+            instances::unregister();
+        }
+        release_instance_mutex();
+    }
+}
+
+fn release_instance_mutex() {
+    let handle = INSTANCE_MUTEX.swap(0, Ordering::SeqCst);
+    if handle != 0 {
+        if let Err(e) = unsafe { CloseHandle(HANDLE(handle)) } {
+            error!("Couldn't close instance mutex: {e:?}");
+        }
+    }
+}
+
+// Lowercase file name of this DLL: the module set with
+// [`HudhookBuilder::with_hmodule`], or else the one containing this code.
+fn module_file_name() -> String {
+    let module = match unsafe { MODULE.get() } {
+        Some(module) => HMODULE(module.0),
+        None => match util::current_module() {
+            Some(module) => module,
+            // Still keyed on the process, so one instance per process at worst.
+            None => return String::from("hudhook"),
+        },
+    };
+
+    let mut buf = [0u16; MAX_PATH as usize];
+    let len = unsafe { GetModuleFileNameW(module, &mut buf) } as usize;
+    let path = String::from_utf16_lossy(&buf[..len]);
+
+    path.rsplit('\\').next().unwrap_or_default().to_lowercase()
+}
+
 /// Builder object for [`Hudhook`].
 ///
 /// Example usage:
@@ -399,11 +1032,104 @@ pub struct HudhookBuilder(Hudhook);
 
 impl HudhookBuilder {
     /// Add a hook object.
+    ///
+    /// If a hook object of the same type was already added, the render loop is
+    /// registered on it via [`Hooks::add_render_loop`] and rendered in its own
+    /// `imgui` context after the previously registered ones.
+    ///
+    /// If the hooks can't be created, e.g. because the graphics API isn't
+    /// available, the [`Error`] is logged and the render loop is dropped:
+    /// the game keeps running without the overlay.
+    #[cfg(feature = "renderer")]
     pub fn with<T: Hooks + 'static>(
         mut self,
         render_loop: impl ImguiRenderLoop + Send + Sync + 'static,
     ) -> Self {
-        self.0 .0.push(T::from_render_loop(render_loop));
+        let type_id = TypeId::of::<T>();
+
+        match self.0 .0.iter_mut().find(|(id, _)| *id == type_id) {
+            Some((_, hooks)) => hooks.add_render_loop(Box::new(render_loop)),
+            None => match T::try_from_render_loop(render_loop) {
+                Ok(hooks) => self.0 .0.push((type_id, hooks)),
+                Err(e) => error!("Couldn't create {}, not rendering: {e}", type_name::<T>()),
+            },
+        }
+
+        self
+    }
+
+    /// Add a hook object that was constructed manually, e.g.
+    /// [`DxgiHooks`](crate::hooks::dxgi::DxgiHooks), which doesn't take a
+    /// render loop.
+    ///
+    /// Hook objects of a type that was already added are discarded.
+    pub fn with_hooks<T: Hooks + 'static>(mut self, hooks: T) -> Self {
+        let type_id = TypeId::of::<T>();
+
+        if self.0 .0.iter().any(|(id, _)| *id == type_id) {
+            error!("Hooks of this type were already added");
+        } else {
+            self.0 .0.push((type_id, Box::new(hooks)));
+        }
+
+        self
+    }
+
+    /// Set the prefix of the names of the objects [`hudhook`](crate) creates.
+    /// See [`names`] for details.
+    ///
+    /// Call this before adding any hooks, as the hook objects create named
+    /// objects when they are constructed.
+    pub fn with_name_prefix(self, prefix: &str) -> Self {
+        names::set_prefix(prefix);
+        self
+    }
+
+    /// Hook the function `name` at an alternative location, for games
+    /// shipping modified runtimes or shim layers.
+    ///
+    /// `name` is one of the functions listed in the documentation of the hook
+    /// objects, e.g. `IDXGISwapChain::Present`. Call this before adding any
+    /// hooks, as the hook objects look up their functions when they are
+    /// constructed.
+    ///
+    /// ```no_run
+    /// # use hudhook::hooks::HookTarget;
+    /// # use hudhook::*;
+    /// let builder =
+    ///     Hudhook::builder().with_target("IDXGISwapChain::Present", HookTarget::ModuleOffset {
+    ///         module: String::from("dxgi.dll"),
+    ///         offset: 0x1820,
+    ///     });
+    /// ```
+    pub fn with_target(self, name: &str, target: hooks::HookTarget) -> Self {
+        hooks::set_target(name, target);
+        self
+    }
+
+    /// Set how many overlay frames the DirectX 12 render engine may submit
+    /// before waiting for the GPU, between 1 and 3 (defaults to 1). Values
+    /// outside this range are clamped.
+    ///
+    /// With a single frame, the engine waits for the GPU to finish the
+    /// previous overlay frame before drawing the next one, which stalls the
+    /// game's render thread when the GPU lags behind. More frames in flight
+    /// avoid the stall, at the cost of more memory and of a wait whenever a
+    /// texture is replaced. Frames rendered into a shared texture are always
+    /// waited for. The other render engines leave buffering to the driver.
+    #[cfg(feature = "dx12")]
+    pub fn with_frames_in_flight(self, frames_in_flight: usize) -> Self {
+        renderer::set_frames_in_flight(frames_in_flight);
+        self
+    }
+
+    /// Load the render loops of the plugin DLLs in `dir` and add them to
+    /// the hook object `T`, like [`HudhookBuilder::with`]. See [`plugins`].
+    #[cfg(feature = "plugins")]
+    pub fn with_plugins<T: Hooks + 'static>(mut self, dir: impl AsRef<std::path::Path>) -> Self {
+        for plugin in plugins::load(dir.as_ref()) {
+            self = self.with::<T>(plugin);
+        }
         self
     }
 
@@ -417,6 +1143,23 @@ impl HudhookBuilder {
     pub fn build(self) -> Hudhook {
         self.0
     }
+
+    /// Build the [`Hudhook`] object and [apply](Hudhook::apply) it,
+    /// returning a guard that disables the hooks when dropped.
+    ///
+    /// ```no_run
+    /// # use hudhook::hooks::dx12::ImguiDx12Hooks;
+    /// # use hudhook::*;
+    /// # struct MyRenderLoop;
+    /// # impl ImguiRenderLoop for MyRenderLoop { fn render(&mut self, _: &mut imgui::Ui) {} }
+    /// let guard = Hudhook::builder().with::<ImguiDx12Hooks>(MyRenderLoop).apply().unwrap();
+    /// // The overlay renders until the guard is dropped.
+    /// drop(guard);
+    /// ```
+    pub fn apply(self) -> Result<HudhookGuard, MH_STATUS> {
+        self.build().apply()?;
+        Ok(HudhookGuard(()))
+    }
 }
 
 /// Entry point generator for the library.
@@ -441,6 +1184,7 @@ impl HudhookBuilder {
 ///
 /// hudhook::hudhook!(MyRenderLoop.into_hook::<ImguiDx12Hooks>());
 /// ```
+#[cfg(feature = "renderer")]
 #[macro_export]
 macro_rules! hudhook {
     ($t:ty, $hooks:expr) => {
@@ -449,19 +1193,23 @@ macro_rules! hudhook {
         pub unsafe extern "stdcall" fn DllMain(
             hmodule: ::hudhook::windows::Win32::Foundation::HINSTANCE,
             reason: u32,
-            _: *mut ::std::ffi::c_void,
+            reserved: *mut ::std::ffi::c_void,
         ) {
             use ::hudhook::*;
 
-            if reason == ::hudhook::windows::Win32::System::SystemServices::DLL_PROCESS_ATTACH {
+            if reason == ::hudhook::windows::Win32::System::SystemServices::DLL_PROCESS_DETACH
+                && !reserved.is_null()
+            {
+                ::hudhook::shutdown_on_process_exit();
+            } else if reason
+                == ::hudhook::windows::Win32::System::SystemServices::DLL_PROCESS_ATTACH
+            {
                 ::hudhook::tracing::trace!("DllMain()");
                 ::std::thread::spawn(move || {
-                    if let Err(e) = ::hudhook::Hudhook::builder()
-                        .with::<$t>({ $hooks })
-                        .with_hmodule(hmodule)
-                        .build()
-                        .apply()
-                    {
+                    let res = ::hudhook::Hudhook::try_builder().and_then(|builder| {
+                        builder.with::<$t>({ $hooks }).with_hmodule(hmodule).build().apply()
+                    });
+                    if let Err(e) = res {
                         ::hudhook::tracing::error!("Couldn't apply hooks: {e:?}");
                         ::hudhook::eject();
                     }
@@ -0,0 +1,249 @@
+//! Client for the [LiveSplit Server](https://github.com/LiveSplit/LiveSplit.Server)
+//! TCP protocol.
+//!
+//! Lets autosplitter overlays control and query a LiveSplit instance running
+//! outside of the game.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::livesplit::LiveSplitClient;
+//!
+//! let mut client = LiveSplitClient::connect_default().unwrap();
+//! client.start_or_split().unwrap();
+//! let time = client.current_time().unwrap();
+//! ```
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::timers::TimerEvent;
+
+/// Default address LiveSplit Server listens on.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:16834";
+
+// How long to wait for a response before giving up.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A connection to a LiveSplit Server.
+pub struct LiveSplitClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl LiveSplitClient {
+    /// Connect to a LiveSplit Server at `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let writer = TcpStream::connect(addr)?;
+        writer.set_nodelay(true)?;
+        writer.set_read_timeout(Some(READ_TIMEOUT))?;
+        let reader = BufReader::new(writer.try_clone()?);
+
+        Ok(Self { reader, writer })
+    }
+
+    /// Connect to a LiveSplit Server at [`DEFAULT_ADDRESS`].
+    pub fn connect_default() -> io::Result<Self> {
+        Self::connect(DEFAULT_ADDRESS)
+    }
+
+    /// Start the timer.
+    pub fn start(&mut self) -> io::Result<()> {
+        self.send("starttimer")
+    }
+
+    /// Start the timer if it's not running, split otherwise.
+    pub fn start_or_split(&mut self) -> io::Result<()> {
+        self.send("startorsplit")
+    }
+
+    /// Split the current segment.
+    pub fn split(&mut self) -> io::Result<()> {
+        self.send("split")
+    }
+
+    /// Undo the last split.
+    pub fn unsplit(&mut self) -> io::Result<()> {
+        self.send("unsplit")
+    }
+
+    /// Skip the current segment.
+    pub fn skip_split(&mut self) -> io::Result<()> {
+        self.send("skipsplit")
+    }
+
+    /// Pause the timer.
+    pub fn pause(&mut self) -> io::Result<()> {
+        self.send("pause")
+    }
+
+    /// Resume the timer.
+    pub fn resume(&mut self) -> io::Result<()> {
+        self.send("resume")
+    }
+
+    /// Reset the timer.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.send("reset")
+    }
+
+    /// Switch LiveSplit to game time. Call this once before
+    /// [`set_game_time`](Self::set_game_time).
+    pub fn init_game_time(&mut self) -> io::Result<()> {
+        self.send("initgametime")
+    }
+
+    /// Set the game time, e.g. to an in-game timer read from memory.
+    pub fn set_game_time(&mut self, time: Duration) -> io::Result<()> {
+        self.send(&format!("setgametime {}", time.as_secs_f64()))
+    }
+
+    /// Pause the game time, e.g. during loading screens.
+    pub fn pause_game_time(&mut self) -> io::Result<()> {
+        self.send("pausegametime")
+    }
+
+    /// Resume the game time.
+    pub fn resume_game_time(&mut self) -> io::Result<()> {
+        self.send("unpausegametime")
+    }
+
+    /// Forward a [`TimerEvent`] to LiveSplit.
+    pub fn send_event(&mut self, event: TimerEvent) -> io::Result<()> {
+        match event {
+            TimerEvent::Start => self.start(),
+            TimerEvent::Split => self.split(),
+            TimerEvent::SkipSplit => self.skip_split(),
+            TimerEvent::UndoSplit => self.unsplit(),
+            TimerEvent::Pause => self.pause(),
+            TimerEvent::Resume => self.resume(),
+            TimerEvent::Reset => self.reset(),
+        }
+    }
+
+    /// Query the current time of the timer.
+    pub fn current_time(&mut self) -> io::Result<Duration> {
+        let response = self.query("getcurrenttime")?;
+        parse_time(&response).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid time: {response:?}"))
+        })
+    }
+
+    /// Query the index of the current segment. `-1` if the timer is not
+    /// running.
+    pub fn split_index(&mut self) -> io::Result<i32> {
+        let response = self.query("getsplitindex")?;
+        response.parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid index: {response:?}"))
+        })
+    }
+
+    /// Query the phase of the timer, e.g. `"Running"` or `"Ended"`.
+    pub fn timer_phase(&mut self) -> io::Result<String> {
+        self.query("getcurrenttimerphase")
+    }
+
+    // Send a command that has no response.
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        self.writer.write_all(format!("{command}\r\n").as_bytes())
+    }
+
+    // Send a command and read its single line response.
+    fn query(&mut self, command: &str) -> io::Result<String> {
+        self.send(command)?;
+
+        let mut response = String::new();
+        if self.reader.read_line(&mut response)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        Ok(response.trim_end().to_string())
+    }
+}
+
+// Parse a LiveSplit time such as `1:02:03.45`, `2:03.45` or `3.45`.
+fn parse_time(s: &str) -> Option<Duration> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+
+    let mut secs = 0f64;
+    for part in s.split(':') {
+        let value: f64 = part.parse().ok()?;
+        if !value.is_finite() || value < 0.0 {
+            return None;
+        }
+        secs = secs * 60.0 + value;
+    }
+
+    // Negative times only occur with a start offset; clamp them to zero.
+    Some(if negative { Duration::ZERO } else { Duration::from_secs_f64(secs) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("3.75"), Some(Duration::from_millis(3_750)));
+        assert_eq!(parse_time("2:03.25"), Some(Duration::from_millis(123_250)));
+        assert_eq!(parse_time("1:02:03.5"), Some(Duration::from_millis(3_723_500)));
+        assert_eq!(parse_time("0"), Some(Duration::ZERO));
+
+        // Negative times are clamped.
+        assert_eq!(parse_time("-0:05.00"), Some(Duration::ZERO));
+
+        assert_eq!(parse_time(""), None);
+        assert_eq!(parse_time("1::03"), None);
+        assert_eq!(parse_time("1:-2"), None);
+        assert_eq!(parse_time("inf"), None);
+        assert_eq!(parse_time("Not running"), None);
+    }
+
+    #[test]
+    fn test_queries() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+
+        // A server answering the queries with canned responses.
+        let server = thread::spawn(move || -> io::Result<Vec<String>> {
+            let (stream, _) = listener.accept()?;
+            let mut writer = stream.try_clone()?;
+            let mut commands = Vec::new();
+            for line in BufReader::new(stream).lines() {
+                let command = line?;
+                match command.as_str() {
+                    "getcurrenttime" => writer.write_all(b"1:02.50\r\n")?,
+                    "getsplitindex" => writer.write_all(b"-1\r\n")?,
+                    "getcurrenttimerphase" => writer.write_all(b"bogus\r\n")?,
+                    _ => {},
+                }
+                commands.push(command);
+            }
+            Ok(commands)
+        });
+
+        let mut client = LiveSplitClient::connect(addr)?;
+        client.send_event(TimerEvent::Start)?;
+        assert_eq!(client.current_time()?, Duration::from_millis(62_500));
+        assert_eq!(client.split_index()?, -1);
+        client.set_game_time(Duration::from_millis(1_500))?;
+        assert_eq!(client.timer_phase()?, "bogus");
+        drop(client);
+
+        let commands = server.join().unwrap()?;
+        assert_eq!(commands, [
+            "starttimer",
+            "getcurrenttime",
+            "getsplitindex",
+            "setgametime 1.5",
+            "getcurrenttimerphase"
+        ]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+//! Translated overlay strings.
+//!
+//! A [`Localization`] holds one bundle of strings per language, looked up by
+//! key, and switches between languages at runtime. Its
+//! [`glyph_ranges`](Localization::glyph_ranges) cover every character of
+//! every loaded language, so that the font atlas built once in
+//! `ImguiRenderLoop::initialize` keeps rendering all of them after switching
+//! language, instead of each mod rebuilding the atlas for its own strings.
+//!
+//! Bundles are text files named after their language, e.g. `fr.txt`, with
+//! one string per line: the key, a tab, and the translation. `\n` and `\t` in
+//! translations stand for new lines and tabs.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::imgui::{FontConfig, FontSource};
+//! use hudhook::locale::Localization;
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut l10n = Localization::load_dir("lang", "en").unwrap();
+//! l10n.set_language("fr");
+//!
+//! // In `ImguiRenderLoop::setup_fonts`:
+//! // fonts.add_font(&[FontSource::TtfData {
+//! //     data: include_bytes!("font.ttf"),
+//! //     size_pixels: 13.0,
+//! //     config: Some(FontConfig { glyph_ranges: l10n.glyph_ranges(), ..FontConfig::default() }),
+//! // }]);
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // ui.text(l10n.get("menu.title"));
+//! // ui.text(l10n.format("hud.ammo", &[("count", "42")]));
+//! ```
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::{fs, io};
+
+use imgui::FontGlyphRanges;
+
+/// Strings translated in several languages.
+#[derive(Debug, Default, Clone)]
+pub struct Localization {
+    bundles: HashMap<String, HashMap<String, String>>,
+    language: String,
+    fallback: String,
+}
+
+impl Localization {
+    /// Create an empty localization, in the `fallback` language until
+    /// another is set.
+    ///
+    /// Strings missing from the current language are looked up in the
+    /// `fallback` language.
+    pub fn new(fallback: &str) -> Self {
+        Self {
+            bundles: HashMap::new(),
+            language: fallback.to_string(),
+            fallback: fallback.to_string(),
+        }
+    }
+
+    /// Load every bundle of `dir`, i.e. every `.txt` file, named after its
+    /// language.
+    pub fn load_dir<P: AsRef<Path>>(dir: P, fallback: &str) -> io::Result<Self> {
+        let mut localization = Self::new(fallback);
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "txt") {
+                let language = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                localization.load_bundle(&language, &path)?;
+            }
+        }
+
+        Ok(localization)
+    }
+
+    /// Load the strings of `language` from a bundle file, replacing strings
+    /// with the same keys.
+    pub fn load_bundle<P: AsRef<Path>>(&mut self, language: &str, path: P) -> io::Result<()> {
+        let bundle = self.bundles.entry(language.to_string()).or_default();
+
+        for line in fs::read_to_string(path)?.lines() {
+            if let Some((key, value)) = line.split_once('\t') {
+                bundle.insert(key.to_string(), unescape(value));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the string `key` of `language`.
+    pub fn insert(&mut self, language: &str, key: &str, value: &str) {
+        self.bundles
+            .entry(language.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_string());
+    }
+
+    /// The languages with loaded strings, sorted.
+    pub fn languages(&self) -> Vec<&str> {
+        let mut languages: Vec<_> = self.bundles.keys().map(String::as_str).collect();
+        languages.sort_unstable();
+        languages
+    }
+
+    /// The current language.
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Switch to `language`. Returns `false`, keeping the current language,
+    /// if no strings were loaded for it.
+    pub fn set_language(&mut self, language: &str) -> bool {
+        if !self.bundles.contains_key(language) {
+            return false;
+        }
+
+        self.language = language.to_string();
+        true
+    }
+
+    /// The string `key` in the current language, else in the fallback
+    /// language, else the key itself, so that missing translations show up
+    /// in the UI.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        [&self.language, &self.fallback]
+            .into_iter()
+            .find_map(|language| self.bundles.get(language)?.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    /// The string `key`, as with [`get`](Self::get), with each `{name}`
+    /// replaced by the value of `name` in `args`.
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        args.iter().fold(self.get(key).to_string(), |s, (name, value)| {
+            s.replace(&format!("{{{name}}}"), value)
+        })
+    }
+
+    /// Glyph ranges covering printable ASCII and every character of every
+    /// loaded language, to pass as `FontConfig::glyph_ranges`.
+    ///
+    /// The ranges live as long as the process, as `imgui` requires: build
+    /// them once per font, not every frame. Characters outside of the basic
+    /// multilingual plane are skipped, as `imgui` doesn't render them by
+    /// default.
+    pub fn glyph_ranges(&self) -> FontGlyphRanges {
+        let chars: BTreeSet<u32> = (0x20..=0x7e)
+            .chain(
+                self.bundles
+                    .values()
+                    .flat_map(|b| b.values())
+                    .flat_map(|s| s.chars())
+                    .map(u32::from),
+            )
+            .filter(|&c| (0x20..=0xffff).contains(&c))
+            .collect();
+
+        // Merge consecutive characters into inclusive ranges, terminated by 0.
+        let mut ranges: Vec<u32> = Vec::new();
+        for c in chars {
+            match ranges.last_mut() {
+                Some(end) if *end + 1 == c => *end = c,
+                _ => ranges.extend([c, c]),
+            }
+        }
+        ranges.push(0);
+
+        FontGlyphRanges::from_slice(Box::leak(ranges.into_boxed_slice()))
+    }
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("\\n", "\n").replace("\\t", "\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn localization() -> Localization {
+        let mut l10n = Localization::new("en");
+        l10n.insert("en", "title", "Settings");
+        l10n.insert("en", "ammo", "Ammo: {count}/{max}");
+        l10n.insert("fr", "title", "Paramètres");
+        l10n
+    }
+
+    #[test]
+    fn test_fallback() {
+        let mut l10n = localization();
+        assert!(l10n.set_language("fr"));
+        assert_eq!(l10n.get("title"), "Paramètres");
+        // Missing from the current language, then from the fallback one.
+        assert_eq!(l10n.get("ammo"), "Ammo: {count}/{max}");
+        assert_eq!(l10n.get("missing"), "missing");
+
+        assert!(!l10n.set_language("de"));
+        assert_eq!(l10n.language(), "fr");
+        assert_eq!(l10n.languages(), ["en", "fr"]);
+    }
+
+    #[test]
+    fn test_format() {
+        let l10n = localization();
+        assert_eq!(l10n.format("ammo", &[("count", "12"), ("max", "30")]), "Ammo: 12/30");
+        assert_eq!(l10n.format("ammo", &[("count", "12")]), "Ammo: 12/{max}");
+        assert_eq!(l10n.format("missing", &[("count", "12")]), "missing");
+    }
+
+    #[test]
+    fn test_load_dir() -> io::Result<()> {
+        let dir = env::temp_dir().join(format!("hudhook-locale-{}", process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("en.txt"), "title\tSettings\nhelp\tLine 1\\nLine 2\nno tab\n")?;
+        fs::write(dir.join("fr.txt"), "title\tParamètres\n")?;
+        fs::write(dir.join("notes.md"), "title\tIgnored\n")?;
+
+        let l10n = Localization::load_dir(&dir, "en");
+        fs::remove_dir_all(&dir)?;
+        let mut l10n = l10n?;
+
+        assert_eq!(l10n.languages(), ["en", "fr"]);
+        assert_eq!(l10n.get("help"), "Line 1\nLine 2");
+        assert_eq!(l10n.get("no tab"), "no tab");
+        assert!(l10n.set_language("fr"));
+        assert_eq!(l10n.get("title"), "Paramètres");
+        assert_eq!(l10n.get("help"), "Line 1\nLine 2");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! Magnified view of the game around the mouse cursor, with pixel colors.
+//!
+//! Aligning overlay elements with the game's HUD, or checking what a shader
+//! outputs, calls for looking at individual pixels. [`Magnifier`] reads back
+//! a small region of the back buffer around the mouse cursor and draws it
+//! enlarged within the current window, along with the color of the pixel
+//! under the cursor. Tools needing the pixels themselves can request a
+//! region with [`request_sample`] and get it with [`latest_sample`].
+//!
+//! The region is copied before the overlay is drawn, so it only contains the
+//! game. The copy is read back once the GPU is done with it, without waiting
+//! for it, so a sample is available a frame or two after it is requested.
+//!
+//! [`ZoomView`] shows a larger region, e.g. for accessibility tools or scopes,
+//! without reading it back: the region is copied into a texture on the GPU
+//! every frame, before the overlay is drawn, and drawn scaled as an image.
+//! Only one zoom view can be shown per frame.
+//!
+//! Only the DirectX 11 backend supports reading back and copying the back
+//! buffer for now, reading it back in 8-bit and 10-bit RGBA or BGRA formats.
+//! With the other backends, no sample is ever available, and zoom views stay
+//! empty.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::magnifier::{Magnifier, ZoomView};
+//!
+//! // Keep these around in your `ImguiRenderLoop`.
+//! let magnifier = Magnifier::default();
+//! let scope = ZoomView::centered([960.0, 540.0], [120.0, 120.0], 3.0);
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // ui.window("Magnifier").build(|| {
+//! //     if let Some([r, g, b, _]) = magnifier.build(ui) {
+//! //         if ui.button("Copy") {
+//! //             ui.set_clipboard_text(format!("#{r:02X}{g:02X}{b:02X}"));
+//! //         }
+//! //     }
+//! // });
+//! // ui.window("Scope").build(|| scope.build(ui));
+//! ```
+use imgui::{Image, TextureId, Ui};
+use parking_lot::Mutex;
+
+static REQUEST: Mutex<Option<SampleRequest>> = Mutex::new(None);
+static SAMPLE: Mutex<Option<Sample>> = Mutex::new(None);
+static ZOOM_REQUEST: Mutex<Option<[f32; 4]>> = Mutex::new(None);
+static ZOOM_TEXTURE: Mutex<Option<ZoomTexture>> = Mutex::new(None);
+
+// Texture the render engine copies the region of a zoom view into, at its
+// top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ZoomTexture {
+    pub(crate) id: TextureId,
+    // Width and height of the whole texture, in pixels.
+    pub(crate) size: [u32; 2],
+}
+
+// A request to read back the pixels around a point of the back buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SampleRequest {
+    // Center of the region, in display coordinates.
+    pub(crate) center: [f32; 2],
+    // Distance from the center to the edges of the region, in pixels.
+    pub(crate) radius: u32,
+}
+
+/// Pixels read back from the back buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// Position of the top-left pixel, in display coordinates.
+    pub pos: [i32; 2],
+    /// Width and height of the region, in pixels. Regions reaching past the
+    /// edges of the back buffer are cropped.
+    pub size: [u32; 2],
+    /// Colors of the pixels as RGBA, row by row.
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Sample {
+    /// Color of the pixel at `pos`, in display coordinates, if it lies within
+    /// the sample.
+    pub fn pixel(&self, pos: [i32; 2]) -> Option<[u8; 4]> {
+        let x = u32::try_from(pos[0] - self.pos[0]).ok().filter(|&x| x < self.size[0])?;
+        let y = u32::try_from(pos[1] - self.pos[1]).ok().filter(|&y| y < self.size[1])?;
+        self.pixels.get((y * self.size[0] + x) as usize).copied()
+    }
+}
+
+/// Read back the pixels within `radius` pixels of `center`, in display
+/// coordinates, from the next frame rendered. The sample is available a frame
+/// or two later. Call it every frame the sample should be kept up to date.
+pub fn request_sample(center: [f32; 2], radius: u32) {
+    *REQUEST.lock() = Some(SampleRequest { center, radius });
+}
+
+/// The last sample read back after a call to [`request_sample`].
+pub fn latest_sample() -> Option<Sample> {
+    SAMPLE.lock().clone()
+}
+
+// Take the sample requested for the frame being rendered, if any.
+pub(crate) fn take_request() -> Option<SampleRequest> {
+    REQUEST.lock().take()
+}
+
+// Store the sample read back by the render engine.
+pub(crate) fn store(sample: Sample) {
+    *SAMPLE.lock() = Some(sample);
+}
+
+// Take the region to copy for the zoom view of the frame being rendered, if
+// any, as left, top, right and bottom edges in display coordinates.
+pub(crate) fn take_zoom_request() -> Option<[f32; 4]> {
+    ZOOM_REQUEST.lock().take()
+}
+
+// Store the texture the render engine copies zoomed regions into, or `None`
+// if it can't copy them.
+pub(crate) fn store_zoom_texture(texture: Option<ZoomTexture>) {
+    *ZOOM_TEXTURE.lock() = texture;
+}
+
+/// A magnified view of the game around the mouse cursor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Magnifier {
+    /// Pixels shown on each side of the pixel under the cursor.
+    pub radius: u32,
+    /// Size of each magnified pixel, in pixels.
+    pub zoom: f32,
+}
+
+impl Default for Magnifier {
+    fn default() -> Self {
+        Self { radius: 7, zoom: 10.0 }
+    }
+}
+
+impl Magnifier {
+    /// Draw the magnified view within the current window, followed by the
+    /// position and color of the pixel under the cursor. Returns that color
+    /// as RGBA, once it has been read back.
+    pub fn build(&self, ui: &Ui) -> Option<[u8; 4]> {
+        let mouse_pos = ui.io().mouse_pos;
+        let valid = ui.is_mouse_pos_valid(mouse_pos);
+        if valid {
+            request_sample(mouse_pos, self.radius);
+        }
+
+        let radius = self.radius as i32;
+        let zoom = self.zoom.max(1.0);
+        let side = (2 * radius + 1) as f32 * zoom;
+        let origin = ui.cursor_screen_pos();
+        ui.dummy([side, side]);
+
+        let sample = latest_sample().filter(|_| valid);
+        let center = [mouse_pos[0].floor() as i32, mouse_pos[1].floor() as i32];
+        let pixel = |dx: i32, dy: i32| {
+            sample.as_ref().and_then(|sample| sample.pixel([center[0] + dx, center[1] + dy]))
+        };
+
+        let draw_list = ui.get_window_draw_list();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let min = [
+                    origin[0] + (dx + radius) as f32 * zoom,
+                    origin[1] + (dy + radius) as f32 * zoom,
+                ];
+                let max = [min[0] + zoom, min[1] + zoom];
+                let [r, g, b, _] = pixel(dx, dy).unwrap_or_default();
+                let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0];
+                draw_list.add_rect(min, max, color).filled(true).build();
+            }
+        }
+
+        // Outline the pixel under the cursor.
+        let min = [origin[0] + radius as f32 * zoom, origin[1] + radius as f32 * zoom];
+        let max = [min[0] + zoom, min[1] + zoom];
+        draw_list.add_rect(min, max, [1.0, 1.0, 1.0, 1.0]).thickness(2.0).build();
+
+        let color = pixel(0, 0);
+        match color {
+            Some([r, g, b, a]) => {
+                ui.text(format!("{}, {}", center[0], center[1]));
+                ui.text(format!("#{r:02X}{g:02X}{b:02X}  ({r}, {g}, {b}, {a})"));
+            },
+            None => ui.text_disabled("No pixel under the cursor"),
+        }
+
+        color
+    }
+}
+
+/// A scaled copy of a rectangular region of the game, updated every frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomView {
+    /// Left, top, right and bottom edges of the region, in display
+    /// coordinates. The parts outside of the display are cropped.
+    pub region: [f32; 4],
+    /// Size of each pixel of the region in the view, in pixels.
+    pub zoom: f32,
+}
+
+impl Default for ZoomView {
+    fn default() -> Self {
+        Self { region: [0.0, 0.0, 64.0, 64.0], zoom: 4.0 }
+    }
+}
+
+impl ZoomView {
+    /// A view of `size` pixels around `center`, in display coordinates,
+    /// magnified `zoom` times.
+    pub fn centered(center: [f32; 2], size: [f32; 2], zoom: f32) -> Self {
+        let [cx, cy] = center;
+        let [w, h] = size;
+        Self { region: [cx - w / 2.0, cy - h / 2.0, cx + w / 2.0, cy + h / 2.0], zoom }
+    }
+
+    /// Draw the magnified region within the current window. Call it every
+    /// frame the view should be shown.
+    pub fn build(&self, ui: &Ui) {
+        let [display_w, display_h] = ui.io().display_size;
+        let [left, top, right, bottom] = self.region;
+        let region = [
+            left.max(0.0).floor(),
+            top.max(0.0).floor(),
+            right.min(display_w).ceil(),
+            bottom.min(display_h).ceil(),
+        ];
+        let [left, top, right, bottom] = region;
+        let (width, height) = (right - left, bottom - top);
+        let size = [width.max(0.0) * self.zoom.max(0.0), height.max(0.0) * self.zoom.max(0.0)];
+
+        if width < 1.0 || height < 1.0 {
+            ui.dummy(size);
+            return;
+        }
+        ZOOM_REQUEST.lock().replace(region);
+
+        let texture = *ZOOM_TEXTURE.lock();
+        match texture {
+            // The region is copied at the top left corner of the texture, before
+            // this frame's overlay is drawn.
+            Some(ZoomTexture { id, size: [texture_w, texture_h] }) => Image::new(id, size)
+                .uv1([(width / texture_w as f32).min(1.0), (height / texture_h as f32).min(1.0)])
+                .build(ui),
+            // The render engine creates the texture at the first request.
+            None => ui.dummy(size),
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! Reading and writing the game's memory, and a hex view of it.
+//!
+//! Trainers and other memory tools need to look at memory they don't own,
+//! which may be unmapped, or freed by the game while they look at it.
+//! [`read`] copies memory without ever faulting, marking the bytes that
+//! couldn't be read, and [`write`] patches it, code included.
+//! [`MemoryViewer`] displays memory in the overlay as a hex dump refreshed
+//! every frame, with bookmarks, and, once
+//! [enabled](MemoryViewer::set_editable), edits bytes in place.
+//!
+//! Tools reading or writing values, e.g. when the user clicks a button, can
+//! use [`try_read`] and [`try_write`], which return the access violation the
+//! access would cause as an error instead of crashing the game. Memory that
+//! must be written through pointers can be made writable for a while with a
+//! [`ProtectGuard`].
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::memory::{self, MemoryViewer, ProtectGuard};
+//!
+//! let health = memory::read(0x7ff6_1234_5678, 4);
+//!
+//! // Patch a read-only constant.
+//! let address = 0x7ff6_1234_9abc;
+//! if let Err(e) = unsafe { memory::try_write(address, &2.0f32.to_le_bytes()) } {
+//!     eprintln!("Couldn't patch the constant: {e}");
+//! }
+//!
+//! // Write through a pointer to read-only memory.
+//! let guard = ProtectGuard::writable(address, 4).unwrap();
+//! unsafe { *(address as *mut f32) = 2.0 };
+//! drop(guard);
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut viewer = MemoryViewer::new(0x7ff6_1234_5678);
+//! viewer.add_bookmark("Health", 0x7ff6_1234_5678);
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // ui.window("Memory").build(|| viewer.build(ui));
+//! ```
+use std::ffi::c_void;
+use std::{fmt, iter};
+
+#[cfg(feature = "renderer")]
+use imgui::Ui;
+use tracing::error;
+use windows::core::{Error, Result};
+use windows::Win32::Foundation::STATUS_ACCESS_VIOLATION;
+use windows::Win32::System::Diagnostics::Debug::{
+    FlushInstructionCache, ReadProcessMemory, WriteProcessMemory,
+};
+use windows::Win32::System::Memory::{
+    VirtualProtect, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
+    PAGE_EXECUTE_WRITECOPY, PAGE_PROTECTION_FLAGS,
+};
+use windows::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO};
+use windows::Win32::System::Threading::GetCurrentProcess;
+
+fn page_size() -> usize {
+    let mut system_info = SYSTEM_INFO::default();
+    unsafe { GetSystemInfo(&mut system_info) };
+    system_info.dwPageSize as usize
+}
+
+/// Copy `len` bytes starting at `address`. Bytes in pages that can't be read,
+/// e.g. because they aren't mapped, are `None`.
+pub fn read(address: usize, len: usize) -> Vec<Option<u8>> {
+    let page_size = page_size();
+
+    let mut bytes = Vec::with_capacity(len);
+    let mut buf = vec![0u8; page_size];
+    while bytes.len() < len {
+        let Some(start) = address.checked_add(bytes.len()) else {
+            break;
+        };
+
+        // Read up to the end of the page, so an unreadable page only hides its
+        // own bytes.
+        let chunk = (page_size - start % page_size).min(len - bytes.len());
+        let readable = unsafe {
+            ReadProcessMemory(
+                GetCurrentProcess(),
+                start as *const c_void,
+                buf.as_mut_ptr() as *mut c_void,
+                chunk,
+                None,
+            )
+        }
+        .is_ok();
+
+        if readable {
+            bytes.extend(buf[..chunk].iter().copied().map(Some));
+        } else {
+            bytes.extend(iter::repeat(None).take(chunk));
+        }
+    }
+    bytes.resize(len, None);
+
+    bytes
+}
+
+/// Copy `bytes` to `address`. Read-only pages, such as the game's code, are
+/// made writable for the duration of the write.
+///
+/// # Safety
+///
+/// The game must be fine with the new bytes: overwriting memory it is using
+/// is undefined behavior as far as it's concerned.
+pub unsafe fn write(address: usize, bytes: &[u8]) -> Result<()> {
+    WriteProcessMemory(
+        GetCurrentProcess(),
+        address as *const c_void,
+        bytes.as_ptr() as *const c_void,
+        bytes.len(),
+        None,
+    )
+}
+
+/// Copy `len` bytes starting at `address`, or return the access violation
+/// reading the first byte that can't be read would cause.
+pub fn try_read(address: usize, len: usize) -> std::result::Result<Vec<u8>, AccessViolation> {
+    read(address, len)
+        .into_iter()
+        .enumerate()
+        .map(|(i, byte)| {
+            byte.ok_or(AccessViolation { address: address.wrapping_add(i), kind: AccessKind::Read })
+        })
+        .collect()
+}
+
+/// Copy `bytes` to `address`, as with [`write`], or return the access
+/// violation writing to the first page that can't be written would cause.
+/// The pages before it are written.
+///
+/// # Safety
+///
+/// Same as [`write`].
+pub unsafe fn try_write(address: usize, bytes: &[u8]) -> std::result::Result<(), AccessViolation> {
+    let page_size = page_size();
+
+    let mut written = 0;
+    while written < bytes.len() {
+        let start = address.wrapping_add(written);
+        // Write up to the end of the page, to tell which page can't be written.
+        let chunk = (page_size - start % page_size).min(bytes.len() - written);
+        if write(start, &bytes[written..written + chunk]).is_err() {
+            return Err(AccessViolation { address: start, kind: AccessKind::Write });
+        }
+        written += chunk;
+    }
+
+    Ok(())
+}
+
+/// Memory made writable for as long as the guard lives. The previous
+/// protection of every page is restored when it is dropped, and the
+/// instruction cache is flushed for pages holding code.
+#[derive(Debug)]
+pub struct ProtectGuard {
+    // Address of each page and its previous protection.
+    pages: Vec<(usize, PAGE_PROTECTION_FLAGS)>,
+    page_size: usize,
+}
+
+impl ProtectGuard {
+    /// Make the pages holding the `len` bytes at `address` writable. Pages
+    /// holding code stay executable.
+    pub fn writable(address: usize, len: usize) -> Result<Self> {
+        let page_size = page_size();
+        let first_page = address & !(page_size - 1);
+        let end = address.checked_add(len.max(1)).ok_or_else(|| {
+            error!("Can't make {len} bytes at {address:#x} writable");
+            Error::from_hresult(STATUS_ACCESS_VIOLATION.to_hresult())
+        })?;
+
+        let mut guard = Self { pages: Vec::new(), page_size };
+        for page in (first_page..end).step_by(page_size) {
+            let mut old = PAGE_PROTECTION_FLAGS::default();
+            // Dropping the guard restores the pages made writable so far.
+            unsafe {
+                VirtualProtect(page as *const c_void, page_size, PAGE_EXECUTE_READWRITE, &mut old)
+            }?;
+            guard.pages.push((page, old));
+        }
+
+        Ok(guard)
+    }
+}
+
+impl Drop for ProtectGuard {
+    fn drop(&mut self) {
+        const PAGE_EXECUTABLE: PAGE_PROTECTION_FLAGS = PAGE_PROTECTION_FLAGS(
+            PAGE_EXECUTE.0
+                | PAGE_EXECUTE_READ.0
+                | PAGE_EXECUTE_READWRITE.0
+                | PAGE_EXECUTE_WRITECOPY.0,
+        );
+
+        for &(page, old) in &self.pages {
+            let mut protection = PAGE_PROTECTION_FLAGS::default();
+            if let Err(e) = unsafe {
+                VirtualProtect(page as *const c_void, self.page_size, old, &mut protection)
+            } {
+                error!("Couldn't restore the protection of {page:#x}: {e:?}");
+            }
+
+            if (old & PAGE_EXECUTABLE).0 != 0 {
+                unsafe {
+                    FlushInstructionCache(
+                        GetCurrentProcess(),
+                        Some(page as *const c_void),
+                        self.page_size,
+                    )
+                }
+                .ok();
+            }
+        }
+    }
+}
+
+/// How the memory was accessed when an [`AccessViolation`] happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// Reading from it.
+    Read,
+    /// Writing to it.
+    Write,
+}
+
+/// An access violation that [`try_read`] or [`try_write`] avoided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessViolation {
+    /// Address that couldn't be accessed.
+    pub address: usize,
+    /// How it was accessed.
+    pub kind: AccessKind,
+}
+
+impl fmt::Display for AccessViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match self.kind {
+            AccessKind::Read => "reading",
+            AccessKind::Write => "writing",
+        };
+        write!(f, "access violation {access} {:#x}", self.address)
+    }
+}
+
+impl std::error::Error for AccessViolation {}
+
+impl From<AccessViolation> for Error {
+    fn from(_: AccessViolation) -> Self {
+        Error::from_hresult(STATUS_ACCESS_VIOLATION.to_hresult())
+    }
+}
+
+/// A named address, to jump back to it in a [`MemoryViewer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    /// Name of the bookmark.
+    pub name: String,
+    /// Address it points to.
+    pub address: usize,
+}
+
+/// A window content displaying memory as a hex dump.
+#[cfg(feature = "renderer")]
+#[derive(Debug, Clone)]
+pub struct MemoryViewer {
+    /// Address of the first byte shown.
+    pub address: usize,
+    /// Number of rows of 16 bytes shown.
+    pub rows: usize,
+    /// Read the memory again every frame. Otherwise, it is only read again
+    /// with the "Refresh" button.
+    pub live: bool,
+    /// Bookmarked addresses.
+    pub bookmarks: Vec<Bookmark>,
+    editable: bool,
+    // Contents of the address input.
+    address_input: String,
+    // Name given to the next bookmark.
+    bookmark_name: String,
+    // Address and contents of the `rows` rows last read.
+    bytes: Option<(usize, Vec<Option<u8>>)>,
+    // Address of the byte being edited, and the input editing it.
+    editing: Option<(usize, String)>,
+    // Focus the input on the frame the edit starts.
+    focus_edit: bool,
+}
+
+#[cfg(feature = "renderer")]
+impl Default for MemoryViewer {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(feature = "renderer")]
+impl MemoryViewer {
+    const ROW_LEN: usize = 16;
+
+    /// Construct a read-only, live viewer showing the memory at `address`.
+    pub fn new(address: usize) -> Self {
+        Self {
+            address,
+            rows: 16,
+            live: true,
+            bookmarks: Vec::new(),
+            editable: false,
+            address_input: format!("{address:X}"),
+            bookmark_name: String::new(),
+            bytes: None,
+            editing: None,
+            focus_edit: false,
+        }
+    }
+
+    /// Bookmark `address` as `name`.
+    pub fn add_bookmark(&mut self, name: impl Into<String>, address: usize) {
+        self.bookmarks.push(Bookmark { name: name.into(), address });
+    }
+
+    /// Show the memory at `address`.
+    pub fn go_to(&mut self, address: usize) {
+        self.address = address;
+        self.address_input = format!("{address:X}");
+        self.bytes = None;
+        self.editing = None;
+    }
+
+    /// Let the user edit bytes by clicking them. Viewers are read-only by
+    /// default.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`write`], for whatever memory the user decides to edit.
+    pub unsafe fn set_editable(&mut self, editable: bool) {
+        self.editable = editable;
+        self.editing = None;
+    }
+
+    /// Whether the user can edit bytes.
+    pub fn is_editable(&self) -> bool {
+        self.editable
+    }
+
+    /// Draw the controls and the hex dump within the current window.
+    pub fn build(&mut self, ui: &Ui) {
+        self.build_controls(ui);
+        ui.separator();
+
+        let len = self.rows * Self::ROW_LEN;
+        let stale = match &self.bytes {
+            Some((address, bytes)) => *address != self.address || bytes.len() != len,
+            None => true,
+        };
+        if self.live || stale {
+            self.bytes = Some((self.address, read(self.address, len)));
+        }
+        let Some((address, bytes)) = self.bytes.clone() else {
+            return;
+        };
+
+        let byte_width = ui.calc_text_size("FF")[0];
+        ui.child_window("##hex").horizontal_scrollbar(true).build(|| {
+            for (row, row_bytes) in bytes.chunks(Self::ROW_LEN).enumerate() {
+                let row_address = address.wrapping_add(row * Self::ROW_LEN);
+                ui.text(format!("{row_address:016X}"));
+
+                for (column, byte) in row_bytes.iter().enumerate() {
+                    ui.same_line();
+                    let byte_address = row_address.wrapping_add(column);
+                    let _id = ui.push_id_usize(byte_address);
+                    self.build_byte(ui, byte_address, *byte, byte_width);
+                }
+
+                ui.same_line();
+                let ascii: String = row_bytes
+                    .iter()
+                    .map(|byte| match byte {
+                        Some(byte) if byte.is_ascii_graphic() || *byte == b' ' => *byte as char,
+                        Some(_) => '.',
+                        None => '?',
+                    })
+                    .collect();
+                ui.text(ascii);
+            }
+        });
+    }
+
+    fn build_controls(&mut self, ui: &Ui) {
+        ui.set_next_item_width(ui.calc_text_size("0000000000000000")[0] * 1.5);
+        let entered = ui
+            .input_text("Address", &mut self.address_input)
+            .chars_hexadecimal(true)
+            .enter_returns_true(true)
+            .build();
+        ui.same_line();
+        if ui.button("Go") || entered {
+            let input = self.address_input.trim_start_matches("0x");
+            match usize::from_str_radix(input, 16) {
+                Ok(address) => self.go_to(address),
+                Err(e) => error!("Invalid address {:?}: {e}", self.address_input),
+            }
+        }
+        ui.same_line();
+        if ui.button("Copy") {
+            ui.set_clipboard_text(format!("{:#x}", self.address));
+        }
+
+        ui.checkbox("Live", &mut self.live);
+        if !self.live {
+            ui.same_line();
+            if ui.button("Refresh") {
+                self.bytes = None;
+            }
+        }
+        ui.same_line();
+        let mut rows = self.rows as i32;
+        ui.set_next_item_width(ui.calc_text_size("0000")[0] * 4.0);
+        if ui.input_int("Rows", &mut rows).build() {
+            self.rows = rows.clamp(1, 256) as usize;
+        }
+
+        ui.set_next_item_width(ui.calc_text_size("0000000000000000")[0] * 1.5);
+        ui.input_text("##bookmark_name", &mut self.bookmark_name).hint("Bookmark name").build();
+        ui.same_line();
+        if ui.button("Bookmark") {
+            let name = match self.bookmark_name.trim() {
+                "" => format!("{:#x}", self.address),
+                name => name.to_string(),
+            };
+            self.add_bookmark(name, self.address);
+            self.bookmark_name.clear();
+        }
+
+        let mut go_to = None;
+        let mut remove = None;
+        for (i, bookmark) in self.bookmarks.iter().enumerate() {
+            let _id = ui.push_id_usize(i);
+            if ui.small_button("Go") {
+                go_to = Some(bookmark.address);
+            }
+            ui.same_line();
+            if ui.small_button("Remove") {
+                remove = Some(i);
+            }
+            ui.same_line();
+            ui.text(format!("{:#x}  {}", bookmark.address, bookmark.name));
+        }
+        if let Some(address) = go_to {
+            self.go_to(address);
+        }
+        if let Some(i) = remove {
+            self.bookmarks.remove(i);
+        }
+    }
+
+    fn build_byte(&mut self, ui: &Ui, address: usize, byte: Option<u8>, width: f32) {
+        if let Some((editing_address, input)) = &mut self.editing {
+            if *editing_address == address {
+                ui.set_next_item_width(width + 8.0);
+                if self.focus_edit {
+                    ui.set_keyboard_focus_here();
+                    self.focus_edit = false;
+                }
+                let entered = ui
+                    .input_text("##edit", input)
+                    .chars_hexadecimal(true)
+                    .auto_select_all(true)
+                    .enter_returns_true(true)
+                    .build();
+
+                if entered {
+                    match u8::from_str_radix(input, 16) {
+                        Ok(value) => {
+                            // SAFETY: the caller of `set_editable` vouched for it.
+                            if let Err(e) = unsafe { write(address, &[value]) } {
+                                error!("Couldn't write {value:#04x} at {address:#x}: {e:?}");
+                            }
+                            self.bytes = None;
+                        },
+                        Err(e) => error!("Invalid byte {input:?}: {e}"),
+                    }
+                    self.editing = None;
+                } else if ui.is_item_deactivated() {
+                    self.editing = None;
+                }
+                return;
+            }
+        }
+
+        let Some(byte) = byte else {
+            ui.selectable_config("??").disabled(true).size([width, 0.0]).build();
+            return;
+        };
+
+        let clicked = ui.selectable_config(format!("{byte:02X}")).size([width, 0.0]).build();
+        if clicked && self.editable {
+            self.editing = Some((address, format!("{byte:02X}")));
+            self.focus_edit = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::System::Memory::{
+        VirtualAlloc, VirtualFree, MEM_COMMIT, MEM_RELEASE, PAGE_NOACCESS, PAGE_READWRITE,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_try_read_write() -> Result<()> {
+        let page_size = page_size();
+        let region = unsafe { VirtualAlloc(None, 2 * page_size, MEM_COMMIT, PAGE_READWRITE) };
+        if region.is_null() {
+            return Err(Error::from_win32());
+        }
+        let address = region as usize;
+
+        // Make the second page inaccessible.
+        let mut old = PAGE_PROTECTION_FLAGS::default();
+        unsafe { VirtualProtect((address + page_size) as _, page_size, PAGE_NOACCESS, &mut old) }?;
+
+        let last = address + page_size - 2;
+        assert_eq!(unsafe { try_write(last, &[1, 2]) }, Ok(()));
+        assert_eq!(try_read(last, 2), Ok(vec![1, 2]));
+
+        let violation = AccessViolation { address: address + page_size, kind: AccessKind::Read };
+        assert_eq!(try_read(last, 4), Err(violation));
+        let violation = AccessViolation { kind: AccessKind::Write, ..violation };
+        assert_eq!(unsafe { try_write(last, &[3, 4, 5, 6]) }, Err(violation));
+        // The accessible bytes were written.
+        assert_eq!(try_read(last, 2), Ok(vec![3, 4]));
+
+        unsafe { VirtualFree(region, 0, MEM_RELEASE) }
+    }
+}
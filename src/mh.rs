@@ -1,10 +1,21 @@
 //! Thin FFI wrapper around [`minhook`](https://github.com/TsudaKageyu/minhook).
 #![allow(dead_code, non_snake_case, non_camel_case_types, missing_docs)]
 
-use std::ffi::c_void;
+use std::ffi::{c_void, OsString};
+use std::fmt;
+use std::os::windows::ffi::OsStringExt;
 use std::ptr::null_mut;
 
-use tracing::error;
+use parking_lot::Mutex;
+use tracing::{debug, error};
+use windows::core::PCSTR;
+use windows::Win32::Foundation::{HMODULE, MAX_PATH};
+use windows::Win32::System::LibraryLoader::{
+    GetModuleFileNameW, GetModuleHandleExA, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+    GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+};
+
+static HOOK_REPORT: Mutex<Vec<HookInfo>> = Mutex::new(Vec::new());
 
 #[allow(non_camel_case_types)]
 #[must_use]
@@ -51,6 +62,7 @@ extern "system" {
         pDetour: *mut c_void,
         ppOriginal: *mut *mut c_void,
     ) -> MH_STATUS;
+    pub fn MH_RemoveHook(pTarget: *mut c_void) -> MH_STATUS;
     pub fn MH_EnableHook(pTarget: *mut c_void) -> MH_STATUS;
     pub fn MH_QueueEnableHook(pTarget: *mut c_void) -> MH_STATUS;
     pub fn MH_DisableHook(pTarget: *mut c_void) -> MH_STATUS;
@@ -80,20 +92,52 @@ impl MH_STATUS {
 /// Structure that holds original address, hook function address, and trampoline
 /// address for a given hook.
 pub struct MhHook {
+    name: &'static str,
     addr: *mut c_void,
     hook_impl: *mut c_void,
     trampoline: *mut c_void,
+    detached: Option<Detached>,
+}
+
+// Where a hook removed because its module was unloaded goes back to once the
+// module is loaded again.
+struct Detached {
+    module: String,
+    offset: usize,
+    prologue: [u8; PROLOGUE_LEN],
 }
 
+// Bytes compared to tell whether a reloaded module is the same as the
+// unloaded one.
+const PROLOGUE_LEN: usize = 16;
+
 impl MhHook {
     /// # Safety
     ///
     /// Most definitely undefined behavior.
     pub unsafe fn new(addr: *mut c_void, hook_impl: *mut c_void) -> Result<Self, MH_STATUS> {
+        Self::named("<unnamed>", addr, hook_impl)
+    }
+
+    /// Like [`MhHook::new`], naming the hooked function in the
+    /// [hook report](hook_report).
+    ///
+    /// # Safety
+    ///
+    /// Most definitely undefined behavior.
+    pub unsafe fn named(
+        name: &'static str,
+        addr: *mut c_void,
+        hook_impl: *mut c_void,
+    ) -> Result<Self, MH_STATUS> {
         let mut trampoline = null_mut();
         MH_CreateHook(addr, hook_impl, &mut trampoline).ok_context("MH_CreateHook")?;
 
-        Ok(Self { addr, hook_impl, trampoline })
+        Ok(Self { name, addr, hook_impl, trampoline, detached: None })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
     }
 
     pub fn trampoline(&self) -> *mut c_void {
@@ -113,4 +157,155 @@ impl MhHook {
     pub unsafe fn queue_disable(&self) -> Result<(), MH_STATUS> {
         MH_QueueDisableHook(self.addr).ok_context("MH_QueueDisableHook")
     }
+
+    // Whether the hooked function lies within `size` bytes from `base`.
+    pub(crate) fn is_within(&self, base: usize, size: usize) -> bool {
+        (base..base.saturating_add(size)).contains(&(self.addr as usize))
+    }
+
+    // Remove the hook from `module`, loaded at `base`, which is being unloaded,
+    // remembering where to recreate it when the module is loaded again.
+    pub(crate) unsafe fn detach(&mut self, module: &str, base: usize) -> Result<(), MH_STATUS> {
+        match MH_DisableHook(self.addr) {
+            MH_STATUS::MH_OK | MH_STATUS::MH_ERROR_DISABLED => {},
+            status => return status.ok_context("MH_DisableHook"),
+        }
+        MH_RemoveHook(self.addr).ok_context("MH_RemoveHook")?;
+
+        // The original bytes are back in place now. The stale trampoline is kept,
+        // as the trampolines stored by the hooks can't be null, but isn't called
+        // while the hook is removed.
+        let prologue = (self.addr as *const [u8; PROLOGUE_LEN]).read_unaligned();
+        self.detached = Some(Detached {
+            module: module.to_lowercase(),
+            offset: self.addr as usize - base,
+            prologue,
+        });
+
+        Ok(())
+    }
+
+    // Recreate, without enabling it, a hook detached from `module`, now loaded
+    // at `base`. Returns whether the hook was recreated.
+    pub(crate) unsafe fn reattach(&mut self, module: &str, base: usize) -> Result<bool, MH_STATUS> {
+        let Some(detached) = self.detached.as_ref() else {
+            return Ok(false);
+        };
+        if !detached.module.eq_ignore_ascii_case(module) {
+            return Ok(false);
+        }
+
+        // Another build of the module may have been loaded, with the function at
+        // another offset: patching it blindly would corrupt unrelated code.
+        let addr = (base + detached.offset) as *mut c_void;
+        if (addr as *const [u8; PROLOGUE_LEN]).read_unaligned() != detached.prologue {
+            error!("{}: reloaded {module} differs from the unloaded one, not hooking", self.name);
+            return Ok(false);
+        }
+
+        let mut trampoline = null_mut();
+        MH_CreateHook(addr, self.hook_impl, &mut trampoline).ok_context("MH_CreateHook")?;
+
+        self.addr = addr;
+        self.trampoline = trampoline;
+        self.detached = None;
+
+        Ok(true)
+    }
+
+    pub(crate) fn is_detached(&self) -> bool {
+        self.detached.is_some()
+    }
+
+    pub(crate) unsafe fn enable(&self) -> Result<(), MH_STATUS> {
+        MH_EnableHook(self.addr).ok_context("MH_EnableHook")
+    }
+
+    fn info(&self, status: MH_STATUS) -> HookInfo {
+        HookInfo {
+            name: self.name,
+            target: self.addr as usize,
+            module: module_of(self.addr),
+            detour: self.hook_impl as usize,
+            trampoline: self.trampoline as usize,
+            status,
+        }
+    }
+}
+
+/// Details of a hook installed by [`Hudhook::apply`](crate::Hudhook::apply),
+/// to diagnose e.g. overlays that never show up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookInfo {
+    /// Name of the hooked function, e.g. `IDXGISwapChain::Present`.
+    pub name: &'static str,
+    /// Address of the hooked function.
+    pub target: usize,
+    /// Path of the module the hooked function belongs to, if any.
+    pub module: Option<String>,
+    /// Address of the function replacing the hooked one.
+    pub detour: usize,
+    /// Address of the trampoline calling the original function.
+    pub trampoline: usize,
+    /// Status of enabling the hook.
+    pub status: MH_STATUS,
+}
+
+impl fmt::Display for HookInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {:#x} ({}) -> detour {:#x}, trampoline {:#x}: {:?}",
+            self.name,
+            self.target,
+            self.module.as_deref().unwrap_or("unknown module"),
+            self.detour,
+            self.trampoline,
+            self.status
+        )
+    }
+}
+
+/// The hooks installed by the last call to
+/// [`Hudhook::apply`](crate::Hudhook::apply), empty if none are installed.
+pub fn hook_report() -> Vec<HookInfo> {
+    HOOK_REPORT.lock().clone()
+}
+
+// Store the report of the hooks just applied, logging it.
+pub(crate) fn set_hook_report<'a>(
+    hooks: impl IntoIterator<Item = &'a MhHook>,
+    statuses: impl IntoIterator<Item = MH_STATUS>,
+) {
+    let report: Vec<_> =
+        hooks.into_iter().zip(statuses).map(|(hook, status)| hook.info(status)).collect();
+
+    for info in &report {
+        debug!("Hook {info}");
+    }
+
+    *HOOK_REPORT.lock() = report;
+}
+
+// Forget the report once the hooks are removed.
+pub(crate) fn clear_hook_report() {
+    HOOK_REPORT.lock().clear();
+}
+
+// Path of the module containing `addr`.
+fn module_of(addr: *const c_void) -> Option<String> {
+    let mut hmodule = HMODULE(0);
+    unsafe {
+        GetModuleHandleExA(
+            GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT | GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            PCSTR(addr as *const u8),
+            &mut hmodule,
+        )
+    }
+    .ok()?;
+
+    let mut buf = [0u16; MAX_PATH as usize];
+    let len = unsafe { GetModuleFileNameW(hmodule, &mut buf) } as usize;
+
+    Some(OsString::from_wide(&buf[..len]).to_string_lossy().into_owned())
 }
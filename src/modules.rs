@@ -0,0 +1,273 @@
+//! Watching the modules loaded by the game.
+//!
+//! Some games only load their graphics modules, e.g. `d3d12.dll`, long after
+//! starting, once a launcher phase is done. Hook objects look their functions
+//! up when they are constructed, which fails or hooks the wrong runtime
+//! before then. [`apply_when_loaded`] defers building and applying the hooks
+//! until one of the given modules is loaded.
+//!
+//! Once applied, hooks into a module the game unloads are removed before the
+//! module goes away, as the patched code would otherwise jump into freed
+//! memory, and are created again when the module is loaded again. Objects the
+//! render engine created from the unloaded module are not recreated.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::hooks::dx12::ImguiDx12Hooks;
+//! use hudhook::*;
+//!
+//! # struct MyRenderLoop;
+//! # impl ImguiRenderLoop for MyRenderLoop { fn render(&mut self, _: &mut imgui::Ui) {} }
+//! # let hmodule = windows::Win32::Foundation::HINSTANCE(0);
+//! // In a thread spawned from `DllMain`:
+//! modules::apply_when_loaded(&["d3d12.dll"], move || {
+//!     Hudhook::builder().with::<ImguiDx12Hooks>(MyRenderLoop).with_hmodule(hmodule).build()
+//! })
+//! .unwrap();
+//! ```
+use std::ffi::c_void;
+use std::ptr::null_mut;
+use std::sync::mpsc;
+use std::{slice, thread};
+
+use parking_lot::Mutex;
+use tracing::{debug, error, warn};
+use windows::core::{Result, HSTRING};
+use windows::Win32::Foundation::{NTSTATUS, UNICODE_STRING};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+
+use crate::{eject, Hudhook, HUDHOOK};
+
+static RELOAD_WATCHER: Mutex<Option<ModuleWatcher>> = Mutex::new(None);
+
+const LDR_DLL_NOTIFICATION_REASON_LOADED: u32 = 1;
+const LDR_DLL_NOTIFICATION_REASON_UNLOADED: u32 = 2;
+
+// Layout shared by `LDR_DLL_LOADED_NOTIFICATION_DATA` and
+// `LDR_DLL_UNLOADED_NOTIFICATION_DATA`.
+#[repr(C)]
+#[allow(dead_code, non_snake_case)]
+struct LdrDllNotificationData {
+    Flags: u32,
+    FullDllName: *const UNICODE_STRING,
+    BaseDllName: *const UNICODE_STRING,
+    DllBase: *mut c_void,
+    SizeOfImage: u32,
+}
+
+type LdrDllNotificationFunction = unsafe extern "system" fn(
+    reason: u32,
+    data: *const LdrDllNotificationData,
+    context: *mut c_void,
+);
+
+#[link(name = "ntdll")]
+extern "system" {
+    fn LdrRegisterDllNotification(
+        flags: u32,
+        notification_function: LdrDllNotificationFunction,
+        context: *mut c_void,
+        cookie: *mut *mut c_void,
+    ) -> NTSTATUS;
+    fn LdrUnregisterDllNotification(cookie: *mut c_void) -> NTSTATUS;
+}
+
+// A module being loaded into or unloaded from the process.
+#[derive(Debug, Clone)]
+pub(crate) struct ModuleEvent {
+    pub(crate) loaded: bool,
+    // Base name of the module, e.g. `d3d12.dll`.
+    pub(crate) name: String,
+    pub(crate) base: usize,
+    pub(crate) size: usize,
+}
+
+type Callback = Box<dyn Fn(&ModuleEvent) + Send + Sync>;
+
+// Calls a callback whenever a module is loaded or unloaded, until dropped.
+//
+// The callback runs while the loader lock is held: it must not load modules,
+// create threads and wait for them, or do anything else taking the lock.
+pub(crate) struct ModuleWatcher {
+    cookie: *mut c_void,
+    callback: *mut Callback,
+}
+
+unsafe impl Send for ModuleWatcher {}
+unsafe impl Sync for ModuleWatcher {}
+
+impl ModuleWatcher {
+    pub(crate) fn new(callback: impl Fn(&ModuleEvent) + Send + Sync + 'static) -> Result<Self> {
+        let callback: *mut Callback = Box::into_raw(Box::new(Box::new(callback)));
+        let mut cookie = null_mut();
+
+        if let Err(e) = unsafe {
+            LdrRegisterDllNotification(0, notification, callback as *mut c_void, &mut cookie).ok()
+        } {
+            error!("Could not register DLL notification: {e:?}");
+            drop(unsafe { Box::from_raw(callback) });
+            return Err(e);
+        }
+
+        Ok(Self { cookie, callback })
+    }
+}
+
+impl Drop for ModuleWatcher {
+    fn drop(&mut self) {
+        // Unregistering waits for running notifications, as they hold the loader
+        // lock, so the callback can be freed afterwards.
+        if let Err(e) = unsafe { LdrUnregisterDllNotification(self.cookie).ok() } {
+            error!("Could not unregister DLL notification: {e:?}");
+            return;
+        }
+
+        drop(unsafe { Box::from_raw(self.callback) });
+    }
+}
+
+unsafe extern "system" fn notification(
+    reason: u32,
+    data: *const LdrDllNotificationData,
+    context: *mut c_void,
+) {
+    let loaded = match reason {
+        LDR_DLL_NOTIFICATION_REASON_LOADED => true,
+        LDR_DLL_NOTIFICATION_REASON_UNLOADED => false,
+        _ => return,
+    };
+
+    let Some(data) = data.as_ref() else {
+        return;
+    };
+
+    let name = match data.BaseDllName.as_ref() {
+        Some(s) if !s.Buffer.is_null() => {
+            String::from_utf16_lossy(slice::from_raw_parts(s.Buffer.0, s.Length as usize / 2))
+        },
+        _ => return,
+    };
+
+    let callback = &*(context as *const Callback);
+    callback(&ModuleEvent {
+        loaded,
+        name,
+        base: data.DllBase as usize,
+        size: data.SizeOfImage as usize,
+    });
+}
+
+/// Build and apply the hooks once one of `modules`, e.g. `d3d12.dll` or
+/// `dxgi.dll`, is loaded in the process.
+///
+/// If one of the modules is already loaded, the hooks are applied right away
+/// on the calling thread. Otherwise a background thread applies them as soon
+/// as the game loads one. As with [`hudhook!`](crate::hudhook), the DLL is
+/// ejected if the hooks can't be applied.
+pub fn apply_when_loaded<F>(modules: &[&str], build: F) -> Result<()>
+where
+    F: FnOnce() -> Hudhook + Send + 'static,
+{
+    let modules: Vec<String> = modules.iter().map(|m| m.to_lowercase()).collect();
+    let (tx, rx) = mpsc::channel();
+
+    // Watch before checking whether the modules are loaded, so that a module
+    // loaded in between isn't missed.
+    let watcher = {
+        let modules = modules.clone();
+        let tx = Mutex::new(tx);
+        ModuleWatcher::new(move |event| {
+            if event.loaded && modules.contains(&event.name.to_lowercase()) {
+                tx.lock().send(event.name.clone()).ok();
+            }
+        })?
+    };
+
+    if let Some(module) =
+        modules.iter().find(|m| unsafe { GetModuleHandleW(&HSTRING::from(m.as_str())) }.is_ok())
+    {
+        debug!("{module} is already loaded, applying hooks");
+        drop(watcher);
+        apply(build);
+        return Ok(());
+    }
+
+    debug!("Waiting for one of {modules:?} to be loaded");
+    thread::spawn(move || {
+        let Ok(module) = rx.recv() else {
+            return;
+        };
+
+        drop(watcher);
+        debug!("{module} was loaded, applying hooks");
+        apply(build);
+    });
+
+    Ok(())
+}
+
+fn apply(build: impl FnOnce() -> Hudhook) {
+    if let Err(e) = build().apply() {
+        error!("Couldn't apply hooks: {e:?}");
+        eject();
+    }
+}
+
+// Start keeping the applied hooks in sync with the modules they hook.
+pub(crate) fn watch_reloads() {
+    match ModuleWatcher::new(sync_hooks) {
+        Ok(watcher) => *RELOAD_WATCHER.lock() = Some(watcher),
+        Err(e) => warn!("Hooks won't survive their module being reloaded: {e:?}"),
+    }
+}
+
+pub(crate) fn unwatch_reloads() {
+    RELOAD_WATCHER.lock().take();
+}
+
+// Remove the hooks into a module being unloaded, and recreate them when it is
+// loaded again. Runs while the loader lock is held, before the unloaded module
+// is unmapped and before the loaded module runs any code.
+fn sync_hooks(event: &ModuleEvent) {
+    let Some(hudhook) = (unsafe { HUDHOOK.get_mut() }) else {
+        return;
+    };
+
+    for (_, hooks) in &mut hudhook.0 {
+        if event.loaded {
+            let mut reattached = Vec::new();
+            for (i, hook) in hooks.hooks_mut().iter_mut().enumerate() {
+                match unsafe { hook.reattach(&event.name, event.base) } {
+                    Ok(true) => reattached.push(i),
+                    Ok(false) => {},
+                    Err(e) => error!("Could not recreate hook {}: {e:?}", hook.name()),
+                }
+            }
+
+            if reattached.is_empty() {
+                continue;
+            }
+
+            // The trampolines must be up to date before the hooks can be called.
+            unsafe { hooks.reload_trampolines() };
+
+            for i in reattached {
+                let hook = &hooks.hooks_mut()[i];
+                if unsafe { hook.enable() }.is_ok() {
+                    debug!("{} loaded again, hooked {}", event.name, hook.name());
+                }
+            }
+        } else {
+            for hook in hooks.hooks_mut() {
+                if !hook.is_within(event.base, event.size) {
+                    continue;
+                }
+
+                match unsafe { hook.detach(&event.name, event.base) } {
+                    Ok(()) => debug!("{} unloaded, unhooked {}", event.name, hook.name()),
+                    Err(e) => error!("Could not remove hook {}: {e:?}", hook.name()),
+                }
+            }
+        }
+    }
+}
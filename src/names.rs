@@ -0,0 +1,60 @@
+//! Names of the objects [`hudhook`](crate) creates.
+//!
+//! Window classes, named kernel objects, debug names of graphics resources and
+//! the `imgui` renderer name all derive from a common prefix, which is
+//! `hudhook` by default. With the `obfuscate-names` feature, the default
+//! prefix is randomized at compile time instead, so that the DLL isn't
+//! trivially fingerprintable by its strings or by the objects it creates.
+//!
+//! The prefix can also be set explicitly via
+//! [`HudhookBuilder::with_name_prefix`](crate::HudhookBuilder::with_name_prefix).
+//!
+//! Named objects shared with other DLLs, such as the
+//! [instance registry](crate::instances), only match up between DLLs that use
+//! the same prefix.
+use parking_lot::Mutex;
+use windows::core::HSTRING;
+
+#[cfg(not(feature = "obfuscate-names"))]
+const DEFAULT_PREFIX: &str = "hudhook";
+#[cfg(feature = "obfuscate-names")]
+const DEFAULT_PREFIX: &str = env!("HUDHOOK_NAME_PREFIX");
+
+static PREFIX: Mutex<Option<String>> = Mutex::new(None);
+
+/// The current name prefix.
+pub fn prefix() -> String {
+    PREFIX.lock().clone().unwrap_or_else(|| String::from(DEFAULT_PREFIX))
+}
+
+// Override the name prefix. Objects that were already created keep their name.
+pub(crate) fn set_prefix(prefix: &str) {
+    *PREFIX.lock() = Some(String::from(prefix));
+}
+
+// Name of a kernel object scoped to the current session, e.g.
+// `Local\hudhook-overlay-1234`.
+pub(crate) fn kernel_object(name: &str) -> HSTRING {
+    HSTRING::from(format!("Local\\{}-{name}", prefix()))
+}
+
+// Debug name of a graphics resource, e.g. `hudhook Render Engine Command List`.
+pub(crate) fn debug_object(name: &str) -> HSTRING {
+    HSTRING::from(format!("{} {name}", prefix()))
+}
+
+// Name of the window class of dummy windows.
+pub(crate) fn window_class() -> HSTRING {
+    HSTRING::from(prefix().to_uppercase())
+}
+
+// Name reported to `imgui` as the renderer name, e.g. `hudhook-dx12@0.8.0`.
+// The version is left out when names are obfuscated.
+#[cfg(feature = "renderer")]
+pub(crate) fn renderer(backend: &str) -> String {
+    if cfg!(feature = "obfuscate-names") {
+        format!("{}-{backend}", prefix())
+    } else {
+        format!("{}-{backend}@{}", prefix(), env!("CARGO_PKG_VERSION"))
+    }
+}
@@ -0,0 +1,254 @@
+//! Color-blind friendly palettes and color transforms.
+//!
+//! About one man in twelve can't tell some colors apart, typically red from
+//! green. This module provides:
+//!
+//! - [`OKABE_ITO`], a palette whose colors stay distinguishable under the
+//!   common color vision deficiencies, for e.g. team or status colors;
+//! - [`ColorTransform`], to simulate a deficiency while designing a HUD, or to
+//!   shift colors so that users with the deficiency can tell them apart;
+//! - [`set_style_transform`], applying a transform to the style colors of every
+//!   render loop.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::palette::{self, ColorTransform, Deficiency};
+//!
+//! // Preview the overlay as seen with deuteranopia.
+//! palette::set_style_transform(Some(ColorTransform::Simulate(Deficiency::Deuteranopia)));
+//!
+//! // In `ImguiRenderLoop::render`, transform the colors you draw with:
+//! let ally = palette::transform_color(palette::OKABE_ITO[5]);
+//! ```
+use imgui::{sys, Ui};
+use parking_lot::Mutex;
+
+static STYLE_TRANSFORM: Mutex<Option<ColorTransform>> = Mutex::new(None);
+
+/// The Okabe-Ito palette: black, orange, sky blue, bluish green, yellow,
+/// blue, vermillion and reddish purple, distinguishable under all common
+/// color vision deficiencies.
+pub const OKABE_ITO: [[f32; 4]; 8] = [
+    [0.0, 0.0, 0.0, 1.0],
+    [0.902, 0.624, 0.0, 1.0],
+    [0.337, 0.706, 0.914, 1.0],
+    [0.0, 0.620, 0.451, 1.0],
+    [0.941, 0.894, 0.259, 1.0],
+    [0.0, 0.447, 0.698, 1.0],
+    [0.835, 0.369, 0.0, 1.0],
+    [0.800, 0.475, 0.655, 1.0],
+];
+
+/// A color vision deficiency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deficiency {
+    /// Missing red cones: reds look dark, and red and green are confused.
+    Protanopia,
+    /// Missing green cones: red and green are confused.
+    Deuteranopia,
+    /// Missing blue cones: blue and green, and yellow and violet, are
+    /// confused.
+    Tritanopia,
+}
+
+impl Deficiency {
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Deficiency::Protanopia => PROTANOPIA,
+            Deficiency::Deuteranopia => DEUTERANOPIA,
+            Deficiency::Tritanopia => TRITANOPIA,
+        }
+    }
+}
+
+// Simulation matrices in linear RGB, from Machado, Oliveira and Fernandes
+// (2009), at full severity.
+#[rustfmt::skip]
+const PROTANOPIA: [[f32; 3]; 3] = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+#[rustfmt::skip]
+const DEUTERANOPIA: [[f32; 3]; 3] = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+#[rustfmt::skip]
+const TRITANOPIA: [[f32; 3]; 3] = [
+    [1.255528, -0.076749, -0.178779],
+    [-0.078411, 0.930809, 0.147602],
+    [0.004733, 0.691367, 0.303900],
+];
+
+/// A transform applied to colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorTransform {
+    /// Show colors as seen with the deficiency, to check that a HUD stays
+    /// readable.
+    Simulate(Deficiency),
+    /// Shift the colors confused with the deficiency towards colors that
+    /// can be told apart (daltonization).
+    Daltonize(Deficiency),
+}
+
+impl ColorTransform {
+    /// Apply the transform to an sRGB color, leaving alpha untouched.
+    pub fn apply(self, [r, g, b, a]: [f32; 4]) -> [f32; 4] {
+        let rgb = [r, g, b];
+
+        let [r, g, b] = match self {
+            ColorTransform::Simulate(deficiency) => simulate(deficiency, rgb),
+            ColorTransform::Daltonize(deficiency) => {
+                // Spread the information lost by the deficiency over the channels
+                // that are still perceived (Fidaner et al.).
+                let [sr, sg, sb] = simulate(deficiency, rgb);
+                let [er, eg, eb] = [r - sr, g - sg, b - sb];
+                [r, g + 0.7 * er + eg, b + 0.7 * er + eb]
+            },
+        };
+
+        [r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0), a]
+    }
+}
+
+/// Set the transform applied to the style colors of every render loop, or
+/// `None` to leave them untouched.
+///
+/// Only the style is transformed: colors that render loops pass to widgets
+/// or draw lists themselves must go through [`transform_color`].
+pub fn set_style_transform(transform: Option<ColorTransform>) {
+    *STYLE_TRANSFORM.lock() = transform;
+}
+
+/// The transform set with [`set_style_transform`].
+pub fn style_transform() -> Option<ColorTransform> {
+    *STYLE_TRANSFORM.lock()
+}
+
+/// Apply the transform set with [`set_style_transform`] to `color`, if any.
+pub fn transform_color(color: [f32; 4]) -> [f32; 4] {
+    match style_transform() {
+        Some(transform) => transform.apply(color),
+        None => color,
+    }
+}
+
+// Push the transformed style colors for the frame being built. Returns the
+// number of colors to pop with `pop_style_transform` once the render loop is
+// done.
+pub(crate) fn push_style_transform(ui: &Ui) -> i32 {
+    let Some(transform) = style_transform() else {
+        return 0;
+    };
+
+    let colors = ui.clone_style().colors;
+    for (i, &color) in colors.iter().enumerate() {
+        unsafe { sys::igPushStyleColor_Vec4(i as i32, transform.apply(color).into()) };
+    }
+
+    colors.len() as i32
+}
+
+pub(crate) fn pop_style_transform(count: i32) {
+    if count > 0 {
+        unsafe { sys::igPopStyleColor(count) };
+    }
+}
+
+fn simulate(deficiency: Deficiency, rgb: [f32; 3]) -> [f32; 3] {
+    let linear = rgb.map(to_linear);
+    let m = deficiency.matrix();
+
+    [0, 1, 2].map(|i| to_srgb(m[i][0] * linear[0] + m[i][1] * linear[1] + m[i][2] * linear[2]))
+}
+
+fn to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFICIENCIES: [Deficiency; 3] =
+        [Deficiency::Protanopia, Deficiency::Deuteranopia, Deficiency::Tritanopia];
+
+    fn assert_close(a: [f32; 4], b: [f32; 4]) {
+        assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for i in 0..=20 {
+            let c = i as f32 / 20.0;
+            assert!((to_srgb(to_linear(c)) - c).abs() < 1e-4, "{c}");
+        }
+    }
+
+    #[test]
+    fn test_transforms_keep_grays_and_alpha() {
+        for deficiency in DEFICIENCIES {
+            for transform in
+                [ColorTransform::Simulate(deficiency), ColorTransform::Daltonize(deficiency)]
+            {
+                for gray in [0.0, 0.25, 0.5, 1.0] {
+                    let color = [gray, gray, gray, 0.5];
+                    assert_close(transform.apply(color), color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transforms_stay_in_range() {
+        for deficiency in DEFICIENCIES {
+            for transform in
+                [ColorTransform::Simulate(deficiency), ColorTransform::Daltonize(deficiency)]
+            {
+                for color in OKABE_ITO.iter().chain(&[[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0]]) {
+                    let transformed = transform.apply(*color);
+                    assert!(transformed.iter().all(|c| (0.0..=1.0).contains(c)), "{transformed:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_simulation_confuses_red_and_green() {
+        let simulate = ColorTransform::Simulate(Deficiency::Deuteranopia);
+        let red = simulate.apply([1.0, 0.0, 0.0, 1.0]);
+        let green = simulate.apply([0.0, 1.0, 0.0, 1.0]);
+        // Both end up as shades of yellow.
+        for [r, g, b, _] in [red, green] {
+            assert!(r > g && g > b, "{red:?} {green:?}");
+        }
+    }
+
+    #[test]
+    fn test_style_transform() {
+        let color = [1.0, 0.0, 0.0, 1.0];
+        set_style_transform(None);
+        assert_eq!(transform_color(color), color);
+
+        let transform = ColorTransform::Simulate(Deficiency::Protanopia);
+        set_style_transform(Some(transform));
+        assert_eq!(style_transform(), Some(transform));
+        assert_eq!(transform_color(color), transform.apply(color));
+        set_style_transform(None);
+    }
+}
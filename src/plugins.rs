@@ -0,0 +1,570 @@
+//! Loading render loops from plugin DLLs.
+//!
+//! With the `plugins` feature, a DLL built with [`hudhook`](crate) can act as
+//! a mod loader:
+//! [`HudhookBuilder::with_plugins`](crate::HudhookBuilder::with_plugins)
+//! loads every DLL of a folder, usually [`default_dir`], that exports the
+//! [`hudhook_plugin!`](crate::hudhook_plugin) entry points, and registers its
+//! render loop on the hooks. Each plugin renders in its own `imgui` context
+//! and [layer](crate::layers), under the name it was exported with.
+//!
+//! Plugins are called through a C ABI, except for the `imgui::Ui` and
+//! `imgui::Io` they are handed, which are Rust types. A panic in a plugin is
+//! caught
+//! within the plugin, as unwinding can't cross from one Rust runtime into
+//! another: the plugin that panicked stops rendering, while the others and
+//! the game keep going. [`ImguiRenderLoop::render`],
+//! [`ImguiRenderLoop::message_filter`] and [`ImguiRenderLoop::layer`] are
+//! forwarded to plugin render loops.
+//!
+//! # ABI negotiation
+//!
+//! Plugins are often built against another [`hudhook`](crate) version than
+//! the host. Before anything else, the host reads the [`ABI_VERSION`] of the
+//! plugin from its `hudhook_plugin_abi` export, which never changes, and
+//! rejects plugins of another ABI version, or built before the ABI was
+//! versioned. Host and plugin then exchange a [`HostInfo`] and a
+//! [`PluginInfo`], and each side rejects the other unless their
+//! [`hudhook`](crate) versions are semver-compatible, their Dear ImGui
+//! versions match exactly, as plugins draw into the host's context with their
+//! own copy of Dear ImGui, and they were built with the same compiler and the
+//! same `imgui` crate, as `imgui::Ui` has no stable layout. See
+//! [`version`](crate::version).
+//!
+//! Within an ABI version, both structs only grow at the end, with their
+//! `size` telling which fields are there, and optional callbacks are
+//! announced through [`Capabilities`]: each side accepts any struct holding
+//! at least the fields of the first release of the ABI version, only uses
+//! what both know about, and falls back to the defaults of
+//! [`ImguiRenderLoop`] otherwise.
+//!
+//! Example usage, in the plugin:
+//! ```no_run
+//! use hudhook::ImguiRenderLoop;
+//!
+//! struct MyPlugin;
+//!
+//! impl ImguiRenderLoop for MyPlugin {
+//!     fn render(&mut self, ui: &mut hudhook::imgui::Ui) {
+//!         ui.window("My plugin").build(|| ui.text("Hello from a plugin"));
+//!     }
+//! }
+//!
+//! hudhook::hudhook_plugin!("My plugin", MyPlugin);
+//! ```
+//!
+//! And in the host, in a thread spawned from `DllMain`:
+//! ```no_run
+//! use hudhook::hooks::dx12::ImguiDx12Hooks;
+//! use hudhook::*;
+//!
+//! let hooks =
+//!     Hudhook::builder().with_plugins::<ImguiDx12Hooks>(plugins::default_dir().unwrap()).build();
+//! ```
+use std::any::TypeId;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use bitflags::bitflags;
+use imgui::{Io, Ui};
+use tracing::{debug, error, info};
+use windows::core::{s, HSTRING};
+use windows::Win32::Foundation::{FreeLibrary, HMODULE};
+use windows::Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryW};
+
+use crate::version::{self, Version};
+use crate::{util, ImguiRenderLoop, MessageFilter};
+
+/// Version of the plugin ABI. Bumped on any change host and plugin can't
+/// adapt to; fields and capabilities added at the end of the ABI structs
+/// don't bump it.
+pub const ABI_VERSION: u32 = 2;
+
+bitflags! {
+    /// Optional parts of the plugin ABI a host or a plugin supports.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[repr(transparent)]
+    pub struct Capabilities: u64 {
+        /// [`PluginCallbacks::message_filter`] is set.
+        const MESSAGE_FILTER = 1;
+        /// [`PluginInfo::layer`] is set.
+        const LAYER = 1 << 1;
+    }
+}
+
+/// What the host tells a plugin about itself, passed to the
+/// `hudhook_plugin_entry` function of the plugin.
+#[repr(C)]
+pub struct HostInfo {
+    /// Size of the struct, in bytes.
+    pub size: u32,
+    /// [`ABI_VERSION`] of the host.
+    pub abi_version: u32,
+    /// Major, minor and patch version of the host's [`hudhook`](crate).
+    pub hudhook_version: [u16; 3],
+    /// Nul-terminated version of the host's Dear ImGui.
+    pub dear_imgui_version: *const c_char,
+    /// Nul-terminated compiler and `imgui` crate the host was built with.
+    pub rust_abi: *const c_char,
+    /// [`Capabilities`] the host supports.
+    pub capabilities: u64,
+}
+
+/// Functions of a plugin, called with its [`PluginInfo::instance`].
+#[repr(C)]
+pub struct PluginCallbacks {
+    /// Render a frame into the `imgui` context `ctx` through `ui`, a
+    /// `*mut imgui::Ui`, which only has the same layout on both sides as
+    /// their `rust_abi` match. Returns `false` if the render loop panicked.
+    pub render:
+        unsafe extern "C" fn(instance: *mut c_void, ctx: *mut c_void, ui: *mut c_void) -> bool,
+    /// Drop the render loop.
+    pub destroy: unsafe extern "C" fn(instance: *mut c_void),
+    /// Returns the [`MessageFilter`] bits of the render loop for `io`, a
+    /// `*const imgui::Io`. Set with [`Capabilities::MESSAGE_FILTER`].
+    pub message_filter:
+        Option<unsafe extern "C" fn(instance: *mut c_void, io: *const c_void) -> u32>,
+}
+
+/// What a plugin hands to the host, returned by its `hudhook_plugin_entry`
+/// function. Lives as long as the plugin is loaded.
+#[repr(C)]
+pub struct PluginInfo {
+    /// Size of the struct, in bytes.
+    pub size: u32,
+    /// [`ABI_VERSION`] of the plugin.
+    pub abi_version: u32,
+    /// Major, minor and patch version of the plugin's [`hudhook`](crate).
+    pub hudhook_version: [u16; 3],
+    /// Nul-terminated version of the plugin's Dear ImGui.
+    pub dear_imgui_version: *const c_char,
+    /// Nul-terminated compiler and `imgui` crate the plugin was built with.
+    pub rust_abi: *const c_char,
+    /// [`Capabilities`] the plugin supports.
+    pub capabilities: u64,
+    /// Nul-terminated name of the plugin.
+    pub name: *const c_char,
+    /// Layer the render loop asks for. Set with [`Capabilities::LAYER`].
+    pub layer: i32,
+    /// The plugin's render loop, passed back to the callbacks.
+    pub instance: *mut c_void,
+    /// Functions of the plugin.
+    pub callbacks: PluginCallbacks,
+}
+
+// Sizes of the structs in the first release of `ABI_VERSION`. Fields added
+// since go after these, and must only be read when `size` covers them.
+const HOST_INFO_MIN_SIZE: usize = mem::offset_of!(HostInfo, capabilities) + mem::size_of::<u64>();
+const PLUGIN_INFO_MIN_SIZE: usize =
+    mem::offset_of!(PluginInfo, callbacks) + mem::size_of::<PluginCallbacks>();
+
+type AbiFn = unsafe extern "C" fn() -> u32;
+
+// The compiler and the build of the `imgui` crate this library was built with.
+// The `TypeId` of a type differs between builds of its crate, e.g. other
+// versions or features, and between compilers.
+fn rust_abi() -> CString {
+    let mut hasher = DefaultHasher::new();
+    TypeId::of::<Ui>().hash(&mut hasher);
+    let rust_abi = format!("{}; imgui {:016x}", env!("HUDHOOK_RUSTC_VERSION"), hasher.finish());
+    CString::new(rust_abi).unwrap_or_default()
+}
+type EntryFn = unsafe extern "C" fn(host: *const HostInfo) -> *const PluginInfo;
+
+/// Folder plugins are loaded from by default: `plugins` next to the DLL
+/// [`hudhook`](crate) is linked into.
+pub fn default_dir() -> Option<PathBuf> {
+    Some(util::get_dll_path()?.parent()?.join("plugins"))
+}
+
+/// Load every plugin DLL in `dir`, in file name order. DLLs that fail to load,
+/// don't export the entry points or fail the ABI negotiation are skipped.
+pub fn load(dir: &Path) -> Vec<Plugin> {
+    let mut paths = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("dll")))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            error!("Couldn't read plugin folder {dir:?}: {e:?}");
+            return Vec::new();
+        },
+    };
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| match unsafe { Plugin::load(&path) } {
+            Ok(plugin) => {
+                info!("Loaded plugin {:?} from {path:?}", plugin.name);
+                Some(plugin)
+            },
+            Err(e) => {
+                error!("Couldn't load plugin {path:?}: {e}");
+                None
+            },
+        })
+        .collect()
+}
+
+/// A render loop loaded from a plugin DLL. The DLL stays loaded for as long
+/// as the render loop lives.
+pub struct Plugin {
+    name: String,
+    info: *const PluginInfo,
+    // Capabilities both the host and the plugin support.
+    capabilities: Capabilities,
+    module: HMODULE,
+    panicked: bool,
+}
+
+// The plugin's render loop is `Send + Sync`, as `hudhook_plugin!` requires.
+unsafe impl Send for Plugin {}
+unsafe impl Sync for Plugin {}
+
+impl Plugin {
+    unsafe fn load(path: &Path) -> Result<Self, String> {
+        let module = LoadLibraryW(&HSTRING::from(path))
+            .map_err(|e| format!("LoadLibraryW failed: {e:?}"))?;
+
+        match Self::negotiate(module) {
+            Ok((info, capabilities)) => {
+                let name = CStr::from_ptr((*info).name).to_string_lossy().into_owned();
+                Ok(Self { name, info, capabilities, module, panicked: false })
+            },
+            Err(e) => {
+                FreeLibrary(module).ok();
+                Err(e)
+            },
+        }
+    }
+
+    // Check that the plugin speaks the same ABI, and create its render loop.
+    unsafe fn negotiate(module: HMODULE) -> Result<(*const PluginInfo, Capabilities), String> {
+        let Some(abi) = GetProcAddress(module, s!("hudhook_plugin_abi")) else {
+            return Err(String::from(
+                "No hudhook_plugin_abi export: built against a hudhook version without ABI \
+                 negotiation",
+            ));
+        };
+        let abi_version = mem::transmute::<_, AbiFn>(abi)();
+        if abi_version != ABI_VERSION {
+            return Err(format!("Plugin ABI version {abi_version}, expected {ABI_VERSION}"));
+        }
+
+        let Some(entry) = GetProcAddress(module, s!("hudhook_plugin_entry")) else {
+            return Err(String::from("No hudhook_plugin_entry export"));
+        };
+        let dear_imgui_version = CString::new(version::dear_imgui_version()).unwrap_or_default();
+        let rust_abi = rust_abi();
+        let Version { major, minor, patch } = Version::hudhook();
+        let host = HostInfo {
+            size: mem::size_of::<HostInfo>() as u32,
+            abi_version: ABI_VERSION,
+            hudhook_version: [major, minor, patch],
+            dear_imgui_version: dear_imgui_version.as_ptr(),
+            rust_abi: rust_abi.as_ptr(),
+            capabilities: Capabilities::all().bits(),
+        };
+
+        let info = mem::transmute::<_, EntryFn>(entry)(&host);
+        if info.is_null() {
+            return Err(format!("Plugin rejected hudhook {}", Version::hudhook()));
+        }
+
+        // The render loop exists from here on, and must be destroyed if the plugin
+        // is rejected.
+        let reject = |e: String| {
+            ((*info).callbacks.destroy)((*info).instance);
+            Err(e)
+        };
+
+        if ((*info).size as usize) < PLUGIN_INFO_MIN_SIZE {
+            return reject(format!("Plugin info of {} bytes is too small", (*info).size));
+        }
+        let [major, minor, patch] = (*info).hudhook_version;
+        let plugin_version = Version::new(major, minor, patch);
+        if !plugin_version.is_compatible_with(&Version::hudhook()) {
+            return reject(format!(
+                "Plugin built against hudhook {plugin_version}, incompatible with {}",
+                Version::hudhook()
+            ));
+        }
+        let plugin_dear_imgui = CStr::from_ptr((*info).dear_imgui_version).to_string_lossy();
+        if !version::check_dear_imgui_version(&plugin_dear_imgui) {
+            return reject(format!(
+                "Plugin built against Dear ImGui {plugin_dear_imgui}, expected {}",
+                version::dear_imgui_version()
+            ));
+        }
+        let plugin_rust_abi = CStr::from_ptr((*info).rust_abi);
+        if plugin_rust_abi != rust_abi.as_c_str() {
+            return reject(format!(
+                "Plugin built with {}, expected {}",
+                plugin_rust_abi.to_string_lossy(),
+                rust_abi.to_string_lossy()
+            ));
+        }
+
+        // Capabilities one side doesn't know about are left unused.
+        Ok((info, Capabilities::from_bits_truncate((*info).capabilities)))
+    }
+
+    fn info(&self) -> &PluginInfo {
+        unsafe { &*self.info }
+    }
+}
+
+impl ImguiRenderLoop for Plugin {
+    fn render(&mut self, ui: &mut Ui) {
+        if self.panicked {
+            return;
+        }
+
+        let info = self.info();
+        let ctx = unsafe { imgui::sys::igGetCurrentContext() };
+        let ok = unsafe {
+            (info.callbacks.render)(info.instance, ctx as _, ui as *mut Ui as *mut c_void)
+        };
+        if !ok {
+            error!("Plugin {:?} panicked, it won't render anymore", self.name);
+            self.panicked = true;
+        }
+    }
+
+    fn message_filter(&self, io: &Io) -> MessageFilter {
+        let info = self.info();
+        match info.callbacks.message_filter {
+            Some(message_filter)
+                if !self.panicked && self.capabilities.contains(Capabilities::MESSAGE_FILTER) =>
+            {
+                let bits = unsafe { message_filter(info.instance, io as *const Io as _) };
+                MessageFilter::from_bits_truncate(bits)
+            },
+            _ => MessageFilter::empty(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn layer(&self) -> i32 {
+        if self.capabilities.contains(Capabilities::LAYER) {
+            self.info().layer
+        } else {
+            0
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        debug!("Unloading plugin {:?}", self.name);
+        unsafe {
+            let info = self.info();
+            (info.callbacks.destroy)(info.instance);
+            if let Err(e) = FreeLibrary(self.module) {
+                error!("Couldn't unload plugin {:?}: {e:?}", self.name);
+            }
+        }
+    }
+}
+
+/// Check the host and build the [`PluginInfo`] of a plugin, or return null to
+/// reject the host. Used by [`hudhook_plugin!`](crate::hudhook_plugin).
+///
+/// # Safety
+///
+/// `host` must point to a valid [`HostInfo`].
+#[doc(hidden)]
+pub unsafe fn export<T, F>(host: *const HostInfo, name: &str, render_loop: F) -> *const PluginInfo
+where
+    T: ImguiRenderLoop + Send + Sync + 'static,
+    F: FnOnce() -> T,
+{
+    let host = &*host;
+    if host.abi_version != ABI_VERSION || (host.size as usize) < HOST_INFO_MIN_SIZE {
+        return std::ptr::null();
+    }
+    let [major, minor, patch] = host.hudhook_version;
+    let host_dear_imgui = CStr::from_ptr(host.dear_imgui_version).to_string_lossy();
+    if !Version::new(major, minor, patch).is_compatible_with(&Version::hudhook())
+        || !version::check_dear_imgui_version(&host_dear_imgui)
+        || CStr::from_ptr(host.rust_abi) != rust_abi().as_c_str()
+    {
+        return std::ptr::null();
+    }
+
+    let Ok(render_loop) = panic::catch_unwind(AssertUnwindSafe(render_loop)) else {
+        return std::ptr::null();
+    };
+
+    // Strings are static, and the info is leaked: both are created once per load
+    // of the plugin.
+    let dear_imgui_version = CString::new(version::dear_imgui_version()).unwrap_or_default();
+    let Version { major, minor, patch } = Version::hudhook();
+    let info = PluginInfo {
+        size: mem::size_of::<PluginInfo>() as u32,
+        abi_version: ABI_VERSION,
+        hudhook_version: [major, minor, patch],
+        dear_imgui_version: dear_imgui_version.into_raw(),
+        rust_abi: rust_abi().into_raw(),
+        capabilities: Capabilities::all().bits(),
+        name: CString::new(name).unwrap_or_default().into_raw(),
+        layer: render_loop.layer(),
+        instance: Box::into_raw(Box::new(render_loop)) as *mut c_void,
+        callbacks: PluginCallbacks {
+            render: render::<T>,
+            destroy: destroy::<T>,
+            message_filter: Some(message_filter::<T>),
+        },
+    };
+
+    Box::into_raw(Box::new(info))
+}
+
+unsafe extern "C" fn render<T: ImguiRenderLoop>(
+    instance: *mut c_void,
+    ctx: *mut c_void,
+    ui: *mut c_void,
+) -> bool {
+    // The plugin links its own copy of Dear ImGui, which must draw into the
+    // host's context.
+    imgui::sys::igSetCurrentContext(ctx as _);
+
+    let render_loop = &mut *(instance as *mut T);
+    let ui = &mut *(ui as *mut Ui);
+    panic::catch_unwind(AssertUnwindSafe(|| render_loop.render(ui))).is_ok()
+}
+
+unsafe extern "C" fn message_filter<T: ImguiRenderLoop>(
+    instance: *mut c_void,
+    io: *const c_void,
+) -> u32 {
+    let render_loop = &*(instance as *const T);
+    let io = &*(io as *const Io);
+    panic::catch_unwind(AssertUnwindSafe(|| render_loop.message_filter(io).bits())).unwrap_or(0)
+}
+
+unsafe extern "C" fn destroy<T>(instance: *mut c_void) {
+    let render_loop = Box::from_raw(instance as *mut T);
+    if panic::catch_unwind(AssertUnwindSafe(|| drop(render_loop))).is_err() {
+        error!("Plugin panicked while being dropped");
+    }
+}
+
+/// Entry point generator for plugins.
+///
+/// Exports the functions the plugin host looks for, handing it the render
+/// loop `$render_loop` evaluates to under the name `$name`, once the host
+/// passed the ABI negotiation. Build the plugin as a `cdylib` with the
+/// `plugins` feature. See [`plugins`](crate::plugins).
+#[macro_export]
+macro_rules! hudhook_plugin {
+    ($name:expr, $render_loop:expr) => {
+        /// Plugin ABI version export created by the `hudhook` library.
+        #[no_mangle]
+        pub extern "C" fn hudhook_plugin_abi() -> u32 {
+            ::hudhook::plugins::ABI_VERSION
+        }
+
+        /// Plugin entry point created by the `hudhook` library.
+        #[no_mangle]
+        pub unsafe extern "C" fn hudhook_plugin_entry(
+            host: *const ::hudhook::plugins::HostInfo,
+        ) -> *const ::hudhook::plugins::PluginInfo {
+            ::hudhook::plugins::export(host, $name, || $render_loop)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestLoop;
+
+    impl ImguiRenderLoop for TestLoop {
+        fn render(&mut self, _: &mut Ui) {}
+
+        fn layer(&self) -> i32 {
+            3
+        }
+    }
+
+    // A host info as a newer host would pass it, with a field this version
+    // doesn't know about.
+    #[repr(C)]
+    struct NewerHostInfo {
+        info: HostInfo,
+        unknown: u64,
+    }
+
+    // Export `TestLoop` to `host`, and destroy it. Returns whether the host
+    // was accepted.
+    unsafe fn accepts(host: &HostInfo) -> bool {
+        let info = export(host, "Test", || TestLoop);
+        if info.is_null() {
+            return false;
+        }
+
+        let info = &*info;
+        assert_eq!(info.abi_version, ABI_VERSION);
+        assert_eq!(CStr::from_ptr(info.name).to_str(), Ok("Test"));
+        assert_eq!(info.layer, 3);
+        assert!(info.size as usize >= PLUGIN_INFO_MIN_SIZE);
+        (info.callbacks.destroy)(info.instance);
+        true
+    }
+
+    #[test]
+    fn test_export() {
+        let dear_imgui_version = CString::new(version::dear_imgui_version()).unwrap();
+        let rust_abi = rust_abi();
+        let Version { major, minor, patch } = Version::hudhook();
+        let host = || HostInfo {
+            size: mem::size_of::<HostInfo>() as u32,
+            abi_version: ABI_VERSION,
+            hudhook_version: [major, minor, patch],
+            dear_imgui_version: dear_imgui_version.as_ptr(),
+            rust_abi: rust_abi.as_ptr(),
+            capabilities: Capabilities::all().bits(),
+        };
+
+        unsafe {
+            assert!(accepts(&host()));
+
+            // Unknown capabilities are ignored.
+            assert!(accepts(&HostInfo { capabilities: u64::MAX, ..host() }));
+
+            // Other ABI and hudhook versions.
+            assert!(!accepts(&HostInfo { abi_version: ABI_VERSION + 1, ..host() }));
+            assert!(!accepts(&HostInfo { abi_version: ABI_VERSION - 1, ..host() }));
+            assert!(!accepts(&HostInfo { hudhook_version: [major + 1, 0, 0], ..host() }));
+            let other_minor = if major == 0 { [0, minor + 1, 0] } else { [major, minor + 1, 0] };
+            assert_eq!(accepts(&HostInfo { hudhook_version: other_minor, ..host() }), major != 0);
+            assert!(accepts(&HostInfo { hudhook_version: [major, minor, patch + 1], ..host() }));
+
+            // Other Dear ImGui or Rust ABI.
+            let other = CString::new("other").unwrap();
+            assert!(!accepts(&HostInfo { dear_imgui_version: other.as_ptr(), ..host() }));
+            assert!(!accepts(&HostInfo { rust_abi: other.as_ptr(), ..host() }));
+
+            // Newer hosts pass larger structs, and structs missing fields of the ABI
+            // version are rejected.
+            let newer = NewerHostInfo {
+                info: HostInfo { size: mem::size_of::<NewerHostInfo>() as u32, ..host() },
+                unknown: u64::MAX,
+            };
+            assert!(accepts(&newer.info));
+            assert!(accepts(&HostInfo { size: HOST_INFO_MIN_SIZE as u32, ..host() }));
+            assert!(!accepts(&HostInfo { size: HOST_INFO_MIN_SIZE as u32 - 1, ..host() }));
+            assert!(!accepts(&HostInfo { size: 0, ..host() }));
+        }
+    }
+}
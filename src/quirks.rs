@@ -0,0 +1,148 @@
+//! Per-game defaults for games needing special handling.
+//!
+//! A [`Quirk`] adjusts the defaults of [`hudhook`](crate) for one game,
+//! identified by the file name of its executable. The quirks matching the
+//! current process are applied by [`Hudhook::builder`](crate::Hudhook::builder)
+//! before any hook is created, so that the builder's own settings still take
+//! precedence.
+//!
+//! An embedded table of known quirks ships with the crate. Mods can add
+//! their own with [`add`], or, with the `quirks` feature, load them from a
+//! JSON file with [`load`]; quirks added later override the earlier ones for
+//! the same executable. [`applied`] lists the quirks applied to the current
+//! process, e.g. for bug reports.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::quirks::{self, Quirk};
+//!
+//! quirks::add(Quirk {
+//!     exe: String::from("game.exe"),
+//!     command_queue_fallback_presents: Some(10),
+//!     ..Default::default()
+//! });
+//!
+//! // Quirks from a file shipped next to the mod, e.g.
+//! // [{ "exe": "game.exe", "frames_in_flight": 3 }]
+//! # #[cfg(feature = "quirks")]
+//! quirks::load("quirks.json").ok();
+//! ```
+#[cfg(feature = "quirks")]
+use std::path::Path;
+#[cfg(feature = "quirks")]
+use std::{fs, io};
+
+use parking_lot::Mutex;
+#[cfg(feature = "quirks")]
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use windows::Win32::Foundation::{HMODULE, MAX_PATH};
+use windows::Win32::System::LibraryLoader::GetModuleFileNameW;
+
+// Quirks of games known to need them. Keep entries sorted by executable, with
+// a comment on the symptom they fix.
+fn embedded() -> Vec<Quirk> {
+    Vec::new()
+}
+
+static QUIRKS: Mutex<Vec<Quirk>> = Mutex::new(Vec::new());
+static APPLIED: Mutex<Vec<Quirk>> = Mutex::new(Vec::new());
+
+/// Defaults to adjust for one game.
+///
+/// Settings left to `None` keep the crate's defaults.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "quirks", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "quirks", serde(default))]
+pub struct Quirk {
+    /// File name of the game's executable, e.g. `game.exe`. Case insensitive.
+    pub exe: String,
+    /// Frames the DirectX 12 render engine keeps in flight; see
+    /// [`HudhookBuilder::with_frames_in_flight`](crate::HudhookBuilder::with_frames_in_flight).
+    pub frames_in_flight: Option<usize>,
+    /// Presents to wait for the game's DirectX 12 command queue before
+    /// falling back to another one. Defaults to 120.
+    pub command_queue_fallback_presents: Option<u32>,
+    /// Consecutive render errors after which the overlay stops rendering, or
+    /// 0 to never stop; see
+    /// [`set_max_render_errors`](crate::hooks::set_max_render_errors).
+    pub max_render_errors: Option<u32>,
+    /// Class names of the windows to never render the overlay on, e.g. a
+    /// launcher; see
+    /// [`bypass_window_class`](crate::hooks::bypass_window_class).
+    pub bypass_window_classes: Vec<String>,
+}
+
+impl Quirk {
+    fn matches(&self, exe: &str) -> bool {
+        self.exe.eq_ignore_ascii_case(exe)
+    }
+
+    fn apply(&self) {
+        #[cfg(feature = "dx12")]
+        if let Some(frames_in_flight) = self.frames_in_flight {
+            crate::renderer::set_frames_in_flight(frames_in_flight);
+        }
+
+        #[cfg(feature = "dx12")]
+        if let Some(presents) = self.command_queue_fallback_presents {
+            crate::hooks::dx12::set_command_queue_fallback_presents(presents);
+        }
+
+        if let Some(max_errors) = self.max_render_errors {
+            crate::hooks::set_max_render_errors(Some(max_errors).filter(|&n| n > 0));
+        }
+
+        for pattern in &self.bypass_window_classes {
+            crate::hooks::bypass_window_class(pattern);
+        }
+    }
+}
+
+/// Add a quirk, overriding the embedded and previously added quirks for the
+/// same executable. Must be called before
+/// [`Hudhook::builder`](crate::Hudhook::builder) to take effect.
+pub fn add(quirk: Quirk) {
+    let mut quirks = QUIRKS.lock();
+    quirks.retain(|q| !q.matches(&quirk.exe));
+    quirks.push(quirk);
+}
+
+/// Add the quirks listed in the JSON file at `path`, an array of [`Quirk`]
+/// objects, as with [`add`]. Returns the number of quirks loaded.
+#[cfg(feature = "quirks")]
+pub fn load(path: impl AsRef<Path>) -> io::Result<usize> {
+    let contents = fs::read_to_string(path)?;
+    let quirks: Vec<Quirk> = serde_json::from_str(&contents)?;
+    let count = quirks.len();
+    quirks.into_iter().for_each(add);
+    Ok(count)
+}
+
+/// The quirks applied to the current process.
+pub fn applied() -> Vec<Quirk> {
+    APPLIED.lock().clone()
+}
+
+// Apply the embedded and added quirks matching the current executable.
+pub(crate) fn apply() {
+    let exe = exe_file_name();
+
+    let added = QUIRKS.lock().clone();
+
+    let mut applied = APPLIED.lock();
+    applied.clear();
+    for quirk in embedded().into_iter().chain(added).filter(|q| q.matches(&exe)) {
+        info!("Applying quirk: {quirk:?}");
+        quirk.apply();
+        applied.push(quirk);
+    }
+}
+
+fn exe_file_name() -> String {
+    let mut buf = [0u16; MAX_PATH as usize];
+    let len = unsafe { GetModuleFileNameW(HMODULE::default(), &mut buf) } as usize;
+    let path = String::from_utf16_lossy(&buf[..len]);
+
+    path.rsplit('\\').next().unwrap_or_default().to_lowercase()
+}
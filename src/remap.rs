@@ -0,0 +1,306 @@
+//! Remapping the keys the game sees.
+//!
+//! Not every game lets its players rebind keys, which matters to players who
+//! can't use the default layout, e.g. left-handed players or players using
+//! an adapted keyboard. A [`Profile`] maps keys the user presses to the keys
+//! the game sees: once [applied](KeyRemaps::apply), the key messages reaching
+//! the game window are rewritten, and, with the
+//! [`InputHooks`](crate::hooks::input::InputHooks) applied, so is the
+//! keyboard state the game polls. The overlay itself still sees the keys
+//! actually pressed.
+//!
+//! [`KeyRemaps`] holds named profiles, e.g. one per game mode, and applies the
+//! active one. With the `state` feature, profiles implement `Serialize` and
+//! `Deserialize`, so they can be kept in the state of a
+//! [`Persistent`](crate::state::Persistent) render loop, and applied again once
+//! restored.
+//!
+//! Characters typed into the game, and keys the game reads through raw input
+//! or DirectInput, are not remapped.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::remap::{KeyRemaps, Profile};
+//! use hudhook::windows::Win32::UI::Input::KeyboardAndMouse::{VK_A, VK_D, VK_LEFT, VK_RIGHT};
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut remaps = KeyRemaps::default();
+//! let mut arrows = Profile::default();
+//! arrows.remap(VK_LEFT, VK_A);
+//! arrows.remap(VK_RIGHT, VK_D);
+//! remaps.insert("Arrows", arrows);
+//! remaps.select("Arrows");
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // ui.window("Key remapping").build(|| remaps.editor(ui));
+//! ```
+use std::collections::BTreeMap;
+use std::ops::BitOr;
+
+use imgui::Ui;
+use parking_lot::Mutex;
+#[cfg(feature = "state")]
+use serde::{Deserialize, Serialize};
+use windows::Win32::Foundation::{LPARAM, WPARAM};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    MapVirtualKeyW, MAPVK_VK_TO_VSC_EX, VIRTUAL_KEY, VK_CONTROL, VK_LCONTROL, VK_LMENU, VK_LSHIFT,
+    VK_MENU, VK_RCONTROL, VK_RMENU, VK_RSHIFT, VK_SHIFT,
+};
+use windows::Win32::UI::WindowsAndMessaging::{WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP};
+
+use crate::keybinds::key_name;
+use crate::renderer::keys::KEYS;
+use crate::renderer::map_vkey;
+
+// The keys of the applied profile.
+static REMAPS: Mutex<BTreeMap<u16, u16>> = Mutex::new(BTreeMap::new());
+
+/// Keys the user presses, mapped to the keys the game sees.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "state", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "state", serde(default))]
+pub struct Profile {
+    /// Virtual key codes of the keys pressed, mapped to those the game sees.
+    pub keys: BTreeMap<u16, u16>,
+}
+
+impl Profile {
+    /// Make pressing `from` look like pressing `to` to the game, replacing
+    /// the previous remap of `from`.
+    pub fn remap(&mut self, from: VIRTUAL_KEY, to: VIRTUAL_KEY) {
+        if from == to {
+            self.keys.remove(&from.0);
+        } else {
+            self.keys.insert(from.0, to.0);
+        }
+    }
+
+    /// Let the game see `from` again.
+    pub fn unmap(&mut self, from: VIRTUAL_KEY) {
+        self.keys.remove(&from.0);
+    }
+
+    /// The key the game sees when `from` is pressed, if it is remapped.
+    pub fn target(&self, from: VIRTUAL_KEY) -> Option<VIRTUAL_KEY> {
+        self.keys.get(&from.0).map(|&to| VIRTUAL_KEY(to))
+    }
+}
+
+// Step of adding a remap in the editor.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Capture {
+    #[default]
+    None,
+    From,
+    To(VIRTUAL_KEY),
+}
+
+/// Named remapping profiles, one of which is applied.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "state", serde(default))]
+pub struct KeyRemaps {
+    /// Profiles by name.
+    pub profiles: BTreeMap<String, Profile>,
+    /// Name of the active profile. `None`, or the name of a missing profile,
+    /// remaps nothing.
+    pub active: Option<String>,
+    // Name typed in the editor for a new profile.
+    #[cfg_attr(feature = "state", serde(skip))]
+    new_name: String,
+    #[cfg_attr(feature = "state", serde(skip))]
+    capture: Capture,
+}
+
+impl KeyRemaps {
+    /// Add the profile `name`, replacing the one with the same name, if any.
+    pub fn insert(&mut self, name: &str, profile: Profile) {
+        self.profiles.insert(name.to_string(), profile);
+        self.apply();
+    }
+
+    /// Remove the profile `name`, deactivating it if it was active.
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        let profile = self.profiles.remove(name);
+        self.apply();
+        profile
+    }
+
+    /// Activate the profile `name`. Returns whether it exists.
+    pub fn select(&mut self, name: &str) -> bool {
+        let exists = self.profiles.contains_key(name);
+        if exists {
+            self.active = Some(name.to_string());
+            self.apply();
+        }
+        exists
+    }
+
+    /// Stop remapping keys until a profile is selected.
+    pub fn deselect(&mut self) {
+        self.active = None;
+        self.apply();
+    }
+
+    /// The active profile.
+    pub fn active(&self) -> Option<&Profile> {
+        self.profiles.get(self.active.as_deref()?)
+    }
+
+    /// Remap the keys of the active profile, or none. The other methods do
+    /// this already; call it after changing the fields, e.g. once restored
+    /// from a saved state.
+    pub fn apply(&self) {
+        *REMAPS.lock() = self.active().map(|profile| profile.keys.clone()).unwrap_or_default();
+    }
+
+    /// Draw widgets selecting, adding and removing the profiles and their
+    /// remaps into the current window.
+    ///
+    /// Adding a remap waits for the key to remap, then for the key the game
+    /// must see, pressed in the overlay; Escape cancels.
+    pub fn editor(&mut self, ui: &Ui) {
+        let preview = self.active.as_deref().unwrap_or("None");
+        if let Some(_combo) = ui.begin_combo("Profile", preview) {
+            if ui.selectable_config("None").selected(self.active.is_none()).build() {
+                self.deselect();
+            }
+            for name in self.profiles.keys().cloned().collect::<Vec<_>>() {
+                let selected = self.active.as_deref() == Some(name.as_str());
+                if ui.selectable_config(&name).selected(selected).build() {
+                    self.select(&name);
+                }
+            }
+        }
+
+        ui.input_text("##hudhook_remap_name", &mut self.new_name).hint("Name").build();
+        ui.same_line();
+        if ui.button("Add") && !self.new_name.is_empty() {
+            let name = std::mem::take(&mut self.new_name);
+            let profile = self.active().cloned().unwrap_or_default();
+            self.insert(&name, profile);
+            self.select(&name);
+        }
+        if let Some(active) = self.active.clone() {
+            ui.same_line();
+            if ui.button("Remove") {
+                self.remove(&active);
+            }
+        }
+
+        let Some(profile) = self.active.as_ref().and_then(|name| self.profiles.get_mut(name))
+        else {
+            return;
+        };
+        let mut changed = false;
+
+        ui.separator();
+        ui.columns(3, "##hudhook_remaps", false);
+        for header in ["Key", "Sent as", ""] {
+            ui.text_disabled(header);
+            ui.next_column();
+        }
+        for (from, to) in profile.keys.clone() {
+            let _id = ui.push_id_usize(from as usize);
+            ui.text(key_name(VIRTUAL_KEY(from)));
+            ui.next_column();
+            ui.text(key_name(VIRTUAL_KEY(to)));
+            ui.next_column();
+            if ui.small_button("Remove") {
+                profile.unmap(VIRTUAL_KEY(from));
+                changed = true;
+            }
+            ui.next_column();
+        }
+        ui.columns(1, "##hudhook_remaps", false);
+
+        let pressed = || {
+            KEYS.iter()
+                .find(|&&(imgui_key, _)| ui.is_key_pressed_no_repeat(imgui_key))
+                .map(|&(_, vk)| vk)
+        };
+        match self.capture {
+            Capture::None => {
+                if ui.button("Add remap") {
+                    self.capture = Capture::From;
+                }
+            },
+            _ if ui.is_key_pressed(imgui::Key::Escape) => self.capture = Capture::None,
+            Capture::From => {
+                ui.text("Press the key to remap...");
+                if let Some(from) = pressed() {
+                    self.capture = Capture::To(from);
+                }
+            },
+            Capture::To(from) => {
+                ui.text(format!("Press the key {} is sent as...", key_name(from)));
+                if let Some(to) = pressed() {
+                    profile.remap(from, to);
+                    self.capture = Capture::None;
+                    changed = true;
+                }
+            },
+        }
+
+        if changed {
+            self.apply();
+        }
+    }
+}
+
+// The key message the game sees for a key message the window received.
+pub(crate) fn rewrite(msg: u32, wparam: WPARAM, lparam: LPARAM) -> (WPARAM, LPARAM) {
+    if !matches!(msg, WM_KEYDOWN | WM_KEYUP | WM_SYSKEYDOWN | WM_SYSKEYUP) {
+        return (wparam, lparam);
+    }
+
+    // Modifiers are sent without their side, which the scan code tells.
+    let from = map_vkey(wparam.0 as u16, lparam.0 as usize);
+    let Some(to) = REMAPS.lock().get(&from.0).map(|&to| VIRTUAL_KEY(to)) else {
+        return (wparam, lparam);
+    };
+
+    let sent = match to {
+        VK_LSHIFT | VK_RSHIFT => VK_SHIFT,
+        VK_LCONTROL | VK_RCONTROL => VK_CONTROL,
+        VK_LMENU | VK_RMENU => VK_MENU,
+        to => to,
+    };
+
+    // Bits 16 to 23 hold the scan code, and bit 24 whether the key is extended.
+    let scan_code = unsafe { MapVirtualKeyW(to.0 as u32, MAPVK_VK_TO_VSC_EX) } as isize;
+    let mut lparam = lparam.0 & !(0x1ff << 16);
+    lparam |= (scan_code & 0xff) << 16;
+    if scan_code & 0xff00 == 0xe000 {
+        lparam |= 1 << 24;
+    }
+
+    (WPARAM(sent.0 as usize), LPARAM(lparam))
+}
+
+// The state of `vk` as the game sees it: the combined `state` of the keys
+// sent as `vk`.
+pub(crate) fn game_state<S>(vk: usize, state: impl Fn(usize) -> S) -> S
+where
+    S: Default + BitOr<Output = S>,
+{
+    let remaps = REMAPS.lock();
+    if remaps.is_empty() {
+        return state(vk);
+    }
+
+    let remapped = |key: usize| remaps.get(&(key as u16)).map(|&to| to as usize);
+    let own = if remapped(vk).is_none() { state(vk) } else { S::default() };
+    remaps
+        .iter()
+        .filter(|&(_, &to)| to as usize == vk)
+        .fold(own, |combined, (&from, _)| combined | state(from as usize))
+}
+
+// Stop remapping when the hooks are removed.
+pub(crate) fn clear() {
+    REMAPS.lock().clear();
+}
@@ -0,0 +1,58 @@
+//! This module contains logic for deciding when the overlay is active.
+
+use windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY;
+
+use crate::hooks::input::async_key_state;
+
+/// Activation mode of the overlay.
+///
+/// Return this on
+/// [`ImguiRenderLoop::activation`](crate::ImguiRenderLoop::activation)
+/// to control when the overlay is rendered and captures input.
+///
+/// Example usage:
+/// ```no_run
+/// use hudhook::windows::Win32::UI::Input::KeyboardAndMouse::VK_TAB;
+/// use hudhook::{Activation, ImguiRenderLoop};
+///
+/// pub struct MyRenderLoop;
+///
+/// impl ImguiRenderLoop for MyRenderLoop {
+///     fn render(&mut self, ui: &mut imgui::Ui) {
+///         ui.text("Hello, hello!");
+///     }
+///
+///     fn activation(&self) -> Activation {
+///         // Only show the overlay while Tab is held down.
+///         Activation::Hold(VK_TAB)
+///     }
+/// }
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Activation {
+    /// The overlay is always rendered.
+    #[default]
+    Always,
+    /// The overlay is rendered and captures input only while the given key is
+    /// held down.
+    ///
+    /// When the key is released, all keyboard and mouse buttons are released
+    /// in the `imgui` context, so no input stays stuck until the next
+    /// activation.
+    Hold(VIRTUAL_KEY),
+}
+
+impl Activation {
+    /// Check whether the overlay should be active right now.
+    ///
+    /// The key state is polled asynchronously rather than derived from window
+    /// messages, as the messages for the activation key may well have been
+    /// filtered out by the render loop's
+    /// [`MessageFilter`](crate::MessageFilter).
+    pub(crate) fn is_active(&self) -> bool {
+        match self {
+            Activation::Always => true,
+            Activation::Hold(vk) => async_key_state(*vk) < 0,
+        }
+    }
+}
@@ -5,29 +5,60 @@ use std::{mem, ptr, slice};
 use imgui::internal::RawWrapper;
 use imgui::{BackendFlags, Context, DrawCmd, DrawData, DrawIdx, DrawVert, TextureId};
 use tracing::error;
-use windows::core::{s, Error, Result, HRESULT};
+use windows::core::{s, Error, Interface, Result, HRESULT, PCSTR};
 use windows::Win32::Foundation::RECT;
 use windows::Win32::Graphics::Direct3D::Fxc::D3DCompile;
 use windows::Win32::Graphics::Direct3D::*;
 use windows::Win32::Graphics::Direct3D11::*;
 use windows::Win32::Graphics::Dxgi::Common::*;
+use windows::Win32::Graphics::Dxgi::DXGI_ERROR_WAS_STILL_DRAWING;
 
+use crate::blur::{self, Blur};
+use crate::depth::{self, DepthTest};
+use crate::magnifier::{self, Sample, SampleRequest, ZoomTexture};
 use crate::renderer::RenderEngine;
-use crate::{util, RenderContext};
+use crate::{names, util, ExternalTexture, RenderContext};
+
+/// A depth buffer to test overlay elements against.
+#[derive(Debug, Clone)]
+pub struct DepthTarget {
+    /// View of the depth buffer. It must be the same size as the back
+    /// buffer.
+    pub view: ID3D11DepthStencilView,
+    /// Whether the game uses a reversed depth buffer, where the near plane is
+    /// at `1.0` and the far plane at `0.0`.
+    pub reversed_z: bool,
+}
 
+/// Render engine for DirectX 11 devices.
 pub struct D3D11RenderEngine {
     device: ID3D11Device,
     device_context: ID3D11DeviceContext,
 
     shader_program: ShaderProgram,
+    blur_pass: BlurPass,
+    // Staging textures the magnifier's samples are copied into, read back on a
+    // later frame so that the GPU is never waited for.
+    readback_textures: [Option<ReadbackTexture>; 2],
+    readback_seq: u64,
+    zoom_texture: Option<ZoomCopyTexture>,
     texture_heap: TextureHeap,
 
     vertex_buffer: Buffer<DrawVert>,
     index_buffer: Buffer<DrawIdx>,
     projection_buffer: Buffer<[[f32; 4]; 4]>,
+
+    render_target_view: Option<ID3D11RenderTargetView>,
+    depth_target: Option<DepthTarget>,
+    depth_test: DepthTest,
 }
 
 impl D3D11RenderEngine {
+    /// Create a render engine drawing with the immediate context of `device`,
+    /// and set up `ctx` for it.
+    ///
+    /// The render target is a texture created with
+    /// `D3D11_BIND_RENDER_TARGET`, e.g. a swap chain back buffer.
     pub fn new(device: &ID3D11Device, ctx: &mut Context) -> Result<Self> {
         let device = device.clone();
         let device_context = unsafe { device.GetImmediateContext() }?;
@@ -37,22 +68,46 @@ impl D3D11RenderEngine {
         let projection_buffer = Buffer::new(&device, 1, D3D11_BIND_CONSTANT_BUFFER)?;
 
         let shader_program = ShaderProgram::new(&device)?;
+        let blur_pass = BlurPass::new(&device)?;
         let texture_heap = TextureHeap::new(&device, &device_context)?;
 
         ctx.set_ini_filename(None);
         ctx.io_mut().backend_flags |= BackendFlags::RENDERER_HAS_VTX_OFFSET;
-        ctx.set_renderer_name(String::from(concat!("hudhook-dx11@", env!("CARGO_PKG_VERSION"))));
+        ctx.set_renderer_name(names::renderer("dx11"));
+
+        // A texture registered by a previous engine doesn't exist in this one.
+        magnifier::store_zoom_texture(None);
 
         Ok(Self {
             device,
             device_context,
             shader_program,
+            blur_pass,
+            readback_textures: [None, None],
+            readback_seq: 0,
+            zoom_texture: None,
             texture_heap,
             vertex_buffer,
             index_buffer,
             projection_buffer,
+            render_target_view: None,
+            depth_target: None,
+            depth_test: DepthTest::Disabled,
         })
     }
+
+    // Set the depth buffer to test depth-tested overlay elements against.
+    pub(crate) fn set_depth_target(&mut self, depth_target: Option<DepthTarget>) {
+        self.depth_target = depth_target;
+    }
+}
+
+impl Drop for D3D11RenderEngine {
+    fn drop(&mut self) {
+        // Submit the commands still queued with the engine's resources, so
+        // that they are released once the GPU is done with them.
+        unsafe { self.device_context.Flush() };
+    }
 }
 
 impl RenderContext for D3D11RenderEngine {
@@ -69,6 +124,19 @@ impl RenderContext for D3D11RenderEngine {
     ) -> Result<()> {
         unsafe { self.texture_heap.update_texture(texture_id, data, width, height) }
     }
+
+    unsafe fn register_texture(&mut self, texture: ExternalTexture) -> Result<TextureId> {
+        let shader_resource_view = match texture {
+            ExternalTexture::Dx11(shader_resource_view) => shader_resource_view,
+            #[allow(unreachable_patterns)]
+            texture => {
+                error!("Texture {texture:?} can't be registered on a DirectX 11 engine");
+                return Err(Error::from_hresult(HRESULT(-1)));
+            },
+        };
+
+        self.texture_heap.insert_texture(shader_resource_view)
+    }
 }
 
 impl RenderEngine for D3D11RenderEngine {
@@ -86,9 +154,12 @@ impl RenderEngine for D3D11RenderEngine {
                 self.device.CreateRenderTargetView(&render_target, None, Some(v))
             })?;
 
-            self.device_context.OMSetRenderTargets(Some(&[Some(render_target)]), None);
-            self.render_draw_data(draw_data)?;
+            self.device_context.OMSetRenderTargets(Some(&[Some(render_target.clone())]), None);
+            self.render_target_view = Some(render_target);
+            let res = self.render_draw_data(draw_data);
+            self.render_target_view = None;
             state_backup.restore(&self.device_context);
+            res?;
         };
 
         Ok(())
@@ -119,32 +190,42 @@ impl D3D11RenderEngine {
                 self.index_buffer.extend(indices);
             });
 
-        self.projection_buffer.push({
-            let [l, t, r, b] = [
-                draw_data.display_pos[0],
-                draw_data.display_pos[1],
-                draw_data.display_pos[0] + draw_data.display_size[0],
-                draw_data.display_pos[1] + draw_data.display_size[1],
-            ];
-
-            [[2. / (r - l), 0., 0., 0.], [0., 2. / (t - b), 0., 0.], [0., 0., 0.5, 0.], [
-                (r + l) / (l - r),
-                (t + b) / (b - t),
-                0.5,
-                1.0,
-            ]]
-        });
+        self.projection_buffer.push(projection(draw_data, 0.5));
 
         self.vertex_buffer.upload(&self.device, &self.device_context)?;
         self.index_buffer.upload(&self.device, &self.device_context)?;
         self.projection_buffer.upload(&self.device, &self.device_context)?;
 
+        // Sample the game before the overlay is drawn over it, and pick up the
+        // samples of previous frames.
+        self.collect_readbacks();
+        if let Some(request) = magnifier::take_request() {
+            if let Err(e) = self.read_back(draw_data, request) {
+                error!("Couldn't read back the render target: {e:?}");
+            }
+        }
+        if let Some(region) = magnifier::take_zoom_request() {
+            if let Err(e) = self.copy_zoom_region(draw_data, region) {
+                error!("Couldn't copy the zoomed region: {e:?}");
+            }
+        }
+
         self.setup_render_state(draw_data);
 
+        // Discard requests left over by other backends.
+        depth::take_pending();
+        blur::take_pending();
+        self.depth_test = DepthTest::Disabled;
+
         let mut vtx_offset = 0usize;
         let mut idx_offset = 0usize;
 
         for cl in draw_data.draw_lists() {
+            // Depth testing doesn't carry over from one draw list to the next.
+            if self.depth_test != DepthTest::Disabled {
+                self.apply_depth_test(draw_data, DepthTest::Disabled)?;
+            }
+
             for cmd in cl.commands() {
                 match cmd {
                     DrawCmd::Elements { count, cmd_params } => {
@@ -177,9 +258,23 @@ impl D3D11RenderEngine {
                         // doesn't seem like this should have any effect
                         // whatsoever. What am I doing wrong?
                         self.setup_render_state(draw_data);
+                        self.apply_depth_test(draw_data, self.depth_test)?;
                     },
-                    DrawCmd::RawCallback { callback, raw_cmd } => unsafe {
-                        callback(cl.raw(), raw_cmd)
+                    DrawCmd::RawCallback { callback, raw_cmd } => {
+                        unsafe { callback(cl.raw(), raw_cmd) };
+
+                        if let Some(depth_test) = depth::take_pending() {
+                            self.apply_depth_test(draw_data, depth_test)?;
+                        }
+
+                        if let Some(blur) = blur::take_pending() {
+                            // Draw the window without the blur rather than fail the frame.
+                            if let Err(e) = self.apply_blur(draw_data, blur) {
+                                error!("Couldn't blur the window background: {e:?}");
+                            }
+                            self.setup_render_state(draw_data);
+                            self.apply_depth_test(draw_data, self.depth_test)?;
+                        }
                     },
                 }
             }
@@ -190,6 +285,263 @@ impl D3D11RenderEngine {
         Ok(())
     }
 
+    // Bind the depth buffer and move the elements to the given depth, or unbind it.
+    unsafe fn apply_depth_test(
+        &mut self,
+        draw_data: &DrawData,
+        depth_test: DepthTest,
+    ) -> Result<()> {
+        self.depth_test = depth_test;
+
+        let (depth_stencil_view, depth_stencil_state, depth) =
+            match (depth_test, &self.depth_target) {
+                (DepthTest::Enabled { depth }, Some(target)) => (
+                    Some(&target.view),
+                    if target.reversed_z {
+                        &self.shader_program.depth_test_reversed_state
+                    } else {
+                        &self.shader_program.depth_test_state
+                    },
+                    depth.clamp(0.0, 1.0),
+                ),
+                _ => (None, &self.shader_program.depth_stencil_state, 0.5),
+            };
+
+        self.device_context
+            .OMSetRenderTargets(Some(&[self.render_target_view.clone()]), depth_stencil_view);
+        self.device_context.OMSetDepthStencilState(depth_stencil_state, 0);
+
+        self.projection_buffer.clear();
+        self.projection_buffer.push(projection(draw_data, depth));
+        self.projection_buffer.upload(&self.device, &self.device_context)?;
+        self.device_context
+            .VSSetConstantBuffers(0, Some(&[Some(self.projection_buffer.resource.clone())]));
+
+        Ok(())
+    }
+
+    // Blur the rectangle of the render target requested by a callback.
+    unsafe fn apply_blur(&mut self, draw_data: &DrawData, blur: Blur) -> Result<()> {
+        let Some(render_target_view) = self.render_target_view.clone() else {
+            return Ok(());
+        };
+        let render_target: ID3D11Texture2D = render_target_view.GetResource()?.cast()?;
+        let desc: D3D11_TEXTURE2D_DESC = util::out_param(|desc| render_target.GetDesc(desc));
+
+        // Regions of multisampled render targets can't be copied.
+        if desc.SampleDesc.Count > 1 || blur.radius <= 0.0 {
+            return Ok(());
+        }
+
+        let [x, y] = draw_data.display_pos;
+        let [left, top, right, bottom] = blur.rect;
+        let left = (left - x).max(0.) as u32;
+        let top = (top - y).max(0.) as u32;
+        let right = ((right - x).max(0.) as u32).min(desc.Width);
+        let bottom = ((bottom - y).max(0.) as u32).min(desc.Height);
+        if right <= left || bottom <= top {
+            return Ok(());
+        }
+
+        self.blur_pass.blur(
+            &self.device,
+            &self.device_context,
+            (&render_target, &render_target_view, desc.Format),
+            [left, top, right, bottom],
+            blur.radius,
+        )
+    }
+
+    // Copy the pixels of the render target requested by the magnifier into a
+    // staging texture, to be read back by `collect_readbacks` once the GPU is
+    // done with the copy.
+    unsafe fn read_back(&mut self, draw_data: &DrawData, request: SampleRequest) -> Result<()> {
+        let Some(render_target_view) = self.render_target_view.clone() else {
+            return Ok(());
+        };
+        let render_target: ID3D11Texture2D = render_target_view.GetResource()?.cast()?;
+        let desc: D3D11_TEXTURE2D_DESC = util::out_param(|desc| render_target.GetDesc(desc));
+
+        // Regions of multisampled render targets can't be copied.
+        let Some(read_pixel) = pixel_reader(desc.Format).filter(|_| desc.SampleDesc.Count == 1)
+        else {
+            return Ok(());
+        };
+
+        let [x, y] = draw_data.display_pos;
+        let [cx, cy] = request.center;
+        let (cx, cy) = ((cx - x).floor() as i64, (cy - y).floor() as i64);
+        let radius = request.radius as i64;
+        let left = (cx - radius).clamp(0, desc.Width as i64) as u32;
+        let top = (cy - radius).clamp(0, desc.Height as i64) as u32;
+        let right = (cx + radius + 1).clamp(0, desc.Width as i64) as u32;
+        let bottom = (cy + radius + 1).clamp(0, desc.Height as i64) as u32;
+        if right <= left || bottom <= top {
+            return Ok(());
+        }
+        let (width, height) = (right - left, bottom - top);
+
+        // Use a texture with no copy in flight, or else replace the oldest copy.
+        let index = self
+            .readback_textures
+            .iter()
+            .position(|t| t.as_ref().map_or(true, |t| t.pending.is_none()))
+            .or_else(|| {
+                (0..self.readback_textures.len()).min_by_key(|&i| {
+                    self.readback_textures[i].as_ref().and_then(|t| t.pending).map(|p| p.seq)
+                })
+            })
+            .unwrap_or(0);
+
+        let fits =
+            |t: &ReadbackTexture| t.format == desc.Format && t.width >= width && t.height >= height;
+        if !self.readback_textures[index].as_ref().is_some_and(fits) {
+            let side = 2 * request.radius + 1;
+            self.readback_textures[index] =
+                Some(ReadbackTexture::new(&self.device, side, side, desc.Format)?);
+        }
+        let Some(readback_texture) = self.readback_textures[index].as_mut() else {
+            return Ok(());
+        };
+
+        self.device_context.CopySubresourceRegion(
+            &readback_texture.texture,
+            0,
+            0,
+            0,
+            0,
+            &render_target,
+            0,
+            Some(&D3D11_BOX { left, top, front: 0, right, bottom, back: 1 }),
+        );
+
+        self.readback_seq += 1;
+        readback_texture.pending = Some(PendingSample {
+            seq: self.readback_seq,
+            pos: [left as i32 + x as i32, top as i32 + y as i32],
+            size: [width, height],
+            read_pixel,
+        });
+
+        Ok(())
+    }
+
+    // Store the latest sample whose copy the GPU completed, without waiting for
+    // the copies still in flight.
+    unsafe fn collect_readbacks(&mut self) {
+        let mut latest: Option<(u64, Sample)> = None;
+
+        for readback_texture in self.readback_textures.iter_mut().flatten() {
+            let Some(pending) = readback_texture.pending else {
+                continue;
+            };
+
+            let mut mapped = Default::default();
+            match self.device_context.Map(
+                &readback_texture.texture,
+                0,
+                D3D11_MAP_READ,
+                D3D11_MAP_FLAG_DO_NOT_WAIT.0 as u32,
+                Some(&mut mapped),
+            ) {
+                Ok(()) => {},
+                Err(e) if e.code() == DXGI_ERROR_WAS_STILL_DRAWING => continue,
+                Err(e) => {
+                    error!("Couldn't map the readback texture: {e:?}");
+                    readback_texture.pending = None;
+                    continue;
+                },
+            }
+
+            let [width, height] = pending.size;
+            let pixels = (0..height)
+                .flat_map(|row| {
+                    let row = slice::from_raw_parts(
+                        (mapped.pData as *const u8).add((row * mapped.RowPitch) as usize),
+                        width as usize * 4,
+                    );
+                    row.chunks_exact(4).map(pending.read_pixel)
+                })
+                .collect();
+            self.device_context.Unmap(&readback_texture.texture, 0);
+            readback_texture.pending = None;
+
+            if latest.as_ref().map_or(true, |(seq, _)| *seq < pending.seq) {
+                latest =
+                    Some((pending.seq, Sample { pos: pending.pos, size: pending.size, pixels }));
+            }
+        }
+
+        if let Some((_, sample)) = latest {
+            magnifier::store(sample);
+        }
+    }
+
+    // Copy the region of the render target requested by a zoom view into the
+    // texture it draws.
+    unsafe fn copy_zoom_region(&mut self, draw_data: &DrawData, region: [f32; 4]) -> Result<()> {
+        let Some(render_target_view) = self.render_target_view.clone() else {
+            return Ok(());
+        };
+        let render_target: ID3D11Texture2D = render_target_view.GetResource()?.cast()?;
+        let desc: D3D11_TEXTURE2D_DESC = util::out_param(|desc| render_target.GetDesc(desc));
+
+        // Regions of multisampled render targets can't be copied.
+        if desc.SampleDesc.Count > 1 {
+            return Ok(());
+        }
+
+        let [x, y] = draw_data.display_pos;
+        let [left, top, right, bottom] = region;
+        let left = (left - x).max(0.) as u32;
+        let top = (top - y).max(0.) as u32;
+        let right = ((right - x).max(0.) as u32).min(desc.Width);
+        let bottom = ((bottom - y).max(0.) as u32).min(desc.Height);
+        if right <= left || bottom <= top {
+            return Ok(());
+        }
+        let (width, height) = (right - left, bottom - top);
+
+        let fits =
+            |t: &ZoomCopyTexture| t.format == desc.Format && t.width >= width && t.height >= height;
+        if !self.zoom_texture.as_ref().is_some_and(fits) {
+            // Grow the texture rather than shrink it, so that a view being
+            // resized doesn't recreate it every frame.
+            let (width, height) = match &self.zoom_texture {
+                Some(t) if t.format == desc.Format => (t.width.max(width), t.height.max(height)),
+                _ => (width, height),
+            };
+            let (texture, shader_resource_view) =
+                ZoomCopyTexture::create(&self.device, width, height, desc.Format)?;
+            let id = match self.zoom_texture.take() {
+                Some(old) => {
+                    self.texture_heap.replace_view(old.id, shader_resource_view)?;
+                    old.id
+                },
+                None => self.texture_heap.insert_texture(shader_resource_view)?,
+            };
+            self.zoom_texture =
+                Some(ZoomCopyTexture { width, height, format: desc.Format, texture, id });
+            magnifier::store_zoom_texture(Some(ZoomTexture { id, size: [width, height] }));
+        }
+        let Some(zoom_texture) = self.zoom_texture.as_ref() else {
+            return Ok(());
+        };
+
+        self.device_context.CopySubresourceRegion(
+            &zoom_texture.texture,
+            0,
+            0,
+            0,
+            0,
+            &render_target,
+            0,
+            Some(&D3D11_BOX { left, top, front: 0, right, bottom, back: 1 }),
+        );
+
+        Ok(())
+    }
+
     unsafe fn setup_render_state(&self, draw_data: &DrawData) {
         self.device_context.RSSetViewports(Some(&[D3D11_VIEWPORT {
             TopLeftX: 0f32,
@@ -233,6 +585,23 @@ impl D3D11RenderEngine {
     }
 }
 
+// Orthographic projection of the draw data, placing every vertex at `depth`.
+fn projection(draw_data: &DrawData, depth: f32) -> [[f32; 4]; 4] {
+    let [l, t, r, b] = [
+        draw_data.display_pos[0],
+        draw_data.display_pos[1],
+        draw_data.display_pos[0] + draw_data.display_size[0],
+        draw_data.display_pos[1] + draw_data.display_size[1],
+    ];
+
+    [[2. / (r - l), 0., 0., 0.], [0., 2. / (t - b), 0., 0.], [0., 0., 0.5, 0.], [
+        (r + l) / (l - r),
+        (t + b) / (b - t),
+        depth,
+        1.0,
+    ]]
+}
+
 struct ShaderProgram {
     vertex_shader: ID3D11VertexShader,
     pixel_shader: ID3D11PixelShader,
@@ -240,6 +609,8 @@ struct ShaderProgram {
     sampler_state: ID3D11SamplerState,
     blend_state: ID3D11BlendState,
     depth_stencil_state: ID3D11DepthStencilState,
+    depth_test_state: ID3D11DepthStencilState,
+    depth_test_reversed_state: ID3D11DepthStencilState,
     rasterizer_state: ID3D11RasterizerState,
 }
 
@@ -302,8 +673,7 @@ impl ShaderProgram {
                 Some(err_blob),
             )
         })
-        .map_err(util::print_error_blob("Compiling vertex shader"))
-        .expect("D3DCompile");
+        .map_err(util::print_error_blob("Compiling vertex shader"))?;
 
         let ps_blob = util::try_out_err_blob(|v, err_blob| unsafe {
             D3DCompile(
@@ -320,8 +690,7 @@ impl ShaderProgram {
                 Some(err_blob),
             )
         })
-        .map_err(util::print_error_blob("Compiling pixel shader"))
-        .expect("D3DCompile");
+        .map_err(util::print_error_blob("Compiling pixel shader"))?;
 
         let vertex_shader = util::try_out_ptr(|v| unsafe {
             let ptr = vs_blob.GetBufferPointer();
@@ -437,31 +806,12 @@ impl ShaderProgram {
             )
         })?;
 
-        let depth_stencil_state = util::try_out_ptr(|v| unsafe {
-            device.CreateDepthStencilState(
-                &D3D11_DEPTH_STENCIL_DESC {
-                    DepthEnable: false.into(),
-                    DepthFunc: D3D11_COMPARISON_ALWAYS,
-                    DepthWriteMask: D3D11_DEPTH_WRITE_MASK_ALL,
-                    StencilEnable: false.into(),
-                    StencilReadMask: 0,
-                    StencilWriteMask: 0,
-                    FrontFace: D3D11_DEPTH_STENCILOP_DESC {
-                        StencilFailOp: D3D11_STENCIL_OP_KEEP,
-                        StencilDepthFailOp: D3D11_STENCIL_OP_KEEP,
-                        StencilPassOp: D3D11_STENCIL_OP_KEEP,
-                        StencilFunc: D3D11_COMPARISON_ALWAYS,
-                    },
-                    BackFace: D3D11_DEPTH_STENCILOP_DESC {
-                        StencilFailOp: D3D11_STENCIL_OP_KEEP,
-                        StencilDepthFailOp: D3D11_STENCIL_OP_KEEP,
-                        StencilPassOp: D3D11_STENCIL_OP_KEEP,
-                        StencilFunc: D3D11_COMPARISON_ALWAYS,
-                    },
-                },
-                Some(v),
-            )
-        })?;
+        let depth_stencil_state =
+            create_depth_stencil_state(device, false, D3D11_COMPARISON_ALWAYS)?;
+        let depth_test_state =
+            create_depth_stencil_state(device, true, D3D11_COMPARISON_LESS_EQUAL)?;
+        let depth_test_reversed_state =
+            create_depth_stencil_state(device, true, D3D11_COMPARISON_GREATER_EQUAL)?;
 
         Ok(ShaderProgram {
             vertex_shader,
@@ -470,11 +820,468 @@ impl ShaderProgram {
             sampler_state,
             blend_state,
             depth_stencil_state,
+            depth_test_state,
+            depth_test_reversed_state,
             rasterizer_state,
         })
     }
 }
 
+// Gaussian blur of a rectangle of the render target, in two separable passes:
+// the rectangle is copied into a texture, blurred horizontally into another,
+// then vertically back into the render target.
+struct BlurPass {
+    vertex_shader: ID3D11VertexShader,
+    pixel_shader: ID3D11PixelShader,
+    sampler_state: ID3D11SamplerState,
+    params: ID3D11Buffer,
+    textures: Option<BlurTextures>,
+}
+
+// Textures holding the rectangle being blurred, grown as needed.
+struct BlurTextures {
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    source: ID3D11Texture2D,
+    source_view: ID3D11ShaderResourceView,
+    intermediate_target: ID3D11RenderTargetView,
+    intermediate_view: ID3D11ShaderResourceView,
+}
+
+#[repr(C)]
+struct BlurParams {
+    direction: [f32; 2],
+    uv_scale: [f32; 2],
+    max_uv: [f32; 2],
+    radius: f32,
+    padding: f32,
+}
+
+impl BlurPass {
+    fn new(device: &ID3D11Device) -> Result<Self> {
+        const BLUR_SHADER_SRC: &str = r"
+        cbuffer blur_params: register(b0) {
+          float2 direction;
+          float2 uv_scale;
+          float2 max_uv;
+          float radius;
+        };
+
+        struct PS_INPUT {
+          float4 pos: SV_POSITION;
+          float2 uv: TEXCOORD0;
+        };
+
+        // A triangle covering the viewport.
+        PS_INPUT vs_main(uint id: SV_VertexID) {
+          PS_INPUT output;
+          float2 uv = float2((id << 1) & 2, id & 2);
+          output.pos = float4(uv * float2(2.0f, -2.0f) + float2(-1.0f, 1.0f), 0.0f, 1.0f);
+          output.uv = uv * uv_scale;
+          return output;
+        }
+
+        Texture2D texture0: register(t0);
+        SamplerState sampler0: register(s0);
+
+        // 25 taps, spanning three standard deviations on each side.
+        float4 ps_main(PS_INPUT input): SV_Target {
+          float3 sum = 0.0f;
+          float weights = 0.0f;
+          for (int i = -12; i <= 12; i++) {
+            float x = i / 4.0f;
+            float weight = exp(-0.5f * x * x);
+            float2 uv = clamp(input.uv + direction * x * radius, 0.0f, max_uv);
+            sum += weight * texture0.Sample(sampler0, uv).rgb;
+            weights += weight;
+          }
+          return float4(sum / weights, 1.0f);
+        }
+        ";
+
+        let compile = |entry_point: PCSTR, target: PCSTR| {
+            util::try_out_err_blob(|v, err_blob| unsafe {
+                D3DCompile(
+                    BLUR_SHADER_SRC.as_ptr() as _,
+                    BLUR_SHADER_SRC.len(),
+                    None,
+                    None,
+                    None,
+                    entry_point,
+                    target,
+                    0,
+                    0,
+                    v,
+                    Some(err_blob),
+                )
+            })
+            .map_err(util::print_error_blob("Compiling blur shader"))
+        };
+
+        let vs_blob: ID3DBlob = compile(s!("vs_main"), s!("vs_4_0"))?;
+        let ps_blob: ID3DBlob = compile(s!("ps_main"), s!("ps_4_0"))?;
+
+        let vertex_shader = util::try_out_ptr(|v| unsafe {
+            let ptr = vs_blob.GetBufferPointer();
+            let size = vs_blob.GetBufferSize();
+            device.CreateVertexShader(slice::from_raw_parts(ptr as _, size), None, Some(v))
+        })?;
+
+        let pixel_shader = util::try_out_ptr(|v| unsafe {
+            let ptr = ps_blob.GetBufferPointer();
+            let size = ps_blob.GetBufferSize();
+            device.CreatePixelShader(slice::from_raw_parts(ptr as _, size), None, Some(v))
+        })?;
+
+        let sampler_state = util::try_out_ptr(|v| unsafe {
+            device.CreateSamplerState(
+                &D3D11_SAMPLER_DESC {
+                    Filter: D3D11_FILTER_MIN_MAG_MIP_LINEAR,
+                    AddressU: D3D11_TEXTURE_ADDRESS_CLAMP,
+                    AddressV: D3D11_TEXTURE_ADDRESS_CLAMP,
+                    AddressW: D3D11_TEXTURE_ADDRESS_CLAMP,
+                    MipLODBias: 0.,
+                    ComparisonFunc: D3D11_COMPARISON_ALWAYS,
+                    MinLOD: 0.,
+                    MaxLOD: 0.,
+                    BorderColor: [0.; 4],
+                    MaxAnisotropy: 0,
+                },
+                Some(v),
+            )
+        })?;
+
+        let params = util::try_out_ptr(|v| unsafe {
+            device.CreateBuffer(
+                &D3D11_BUFFER_DESC {
+                    ByteWidth: mem::size_of::<BlurParams>() as u32,
+                    Usage: D3D11_USAGE_DEFAULT,
+                    BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+                    CPUAccessFlags: 0,
+                    MiscFlags: 0,
+                    StructureByteStride: 0,
+                },
+                None,
+                Some(v),
+            )
+        })?;
+
+        Ok(Self { vertex_shader, pixel_shader, sampler_state, params, textures: None })
+    }
+
+    unsafe fn blur(
+        &mut self,
+        device: &ID3D11Device,
+        device_context: &ID3D11DeviceContext,
+        (render_target, render_target_view, format): (
+            &ID3D11Texture2D,
+            &ID3D11RenderTargetView,
+            DXGI_FORMAT,
+        ),
+        [left, top, right, bottom]: [u32; 4],
+        radius: f32,
+    ) -> Result<()> {
+        let (width, height) = (right - left, bottom - top);
+
+        let fits = |t: &BlurTextures| t.format == format && t.width >= width && t.height >= height;
+        if !self.textures.as_ref().is_some_and(fits) {
+            let (width, height) = match &self.textures {
+                Some(t) if t.format == format => (t.width.max(width), t.height.max(height)),
+                _ => (width, height),
+            };
+            self.textures = Some(BlurTextures::new(device, width, height, format)?);
+        }
+        let Some(textures) = self.textures.as_ref() else {
+            return Ok(());
+        };
+
+        device_context.CopySubresourceRegion(
+            &textures.source,
+            0,
+            0,
+            0,
+            0,
+            render_target,
+            0,
+            Some(&D3D11_BOX { left, top, front: 0, right, bottom, back: 1 }),
+        );
+
+        device_context.IASetInputLayout(None::<&ID3D11InputLayout>);
+        device_context.IASetPrimitiveTopology(D3D11_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+        device_context.VSSetShader(&self.vertex_shader, Some(&[]));
+        device_context.VSSetConstantBuffers(0, Some(&[Some(self.params.clone())]));
+        device_context.PSSetShader(&self.pixel_shader, Some(&[]));
+        device_context.PSSetConstantBuffers(0, Some(&[Some(self.params.clone())]));
+        device_context.PSSetSamplers(0, Some(&[Some(self.sampler_state.clone())]));
+        device_context.OMSetBlendState(None::<&ID3D11BlendState>, None, 0xffffffff);
+
+        let (tw, th) = (textures.width as f32, textures.height as f32);
+        let params = |direction| BlurParams {
+            direction,
+            uv_scale: [width as f32 / tw, height as f32 / th],
+            max_uv: [(width as f32 - 0.5) / tw, (height as f32 - 0.5) / th],
+            radius,
+            padding: 0.,
+        };
+
+        self.pass(
+            device_context,
+            &params([1. / tw, 0.]),
+            &textures.source_view,
+            &textures.intermediate_target,
+            [0, 0, width, height],
+        );
+        self.pass(
+            device_context,
+            &params([0., 1. / th]),
+            &textures.intermediate_view,
+            render_target_view,
+            [left, top, width, height],
+        );
+
+        // The intermediate texture is a render target in the next blur.
+        device_context.PSSetShaderResources(0, Some(&[None]));
+
+        Ok(())
+    }
+
+    unsafe fn pass(
+        &self,
+        device_context: &ID3D11DeviceContext,
+        params: &BlurParams,
+        source: &ID3D11ShaderResourceView,
+        target: &ID3D11RenderTargetView,
+        [x, y, width, height]: [u32; 4],
+    ) {
+        device_context.UpdateSubresource(
+            &self.params,
+            0,
+            None,
+            params as *const BlurParams as *const c_void,
+            0,
+            0,
+        );
+        device_context.OMSetRenderTargets(Some(&[Some(target.clone())]), None);
+        device_context.PSSetShaderResources(0, Some(&[Some(source.clone())]));
+        device_context.RSSetViewports(Some(&[D3D11_VIEWPORT {
+            TopLeftX: x as f32,
+            TopLeftY: y as f32,
+            Width: width as f32,
+            Height: height as f32,
+            MinDepth: 0.,
+            MaxDepth: 1.,
+        }]));
+        device_context.RSSetScissorRects(Some(&[RECT {
+            left: x as i32,
+            top: y as i32,
+            right: (x + width) as i32,
+            bottom: (y + height) as i32,
+        }]));
+        device_context.Draw(3, 0);
+    }
+}
+
+impl BlurTextures {
+    unsafe fn new(
+        device: &ID3D11Device,
+        width: u32,
+        height: u32,
+        format: DXGI_FORMAT,
+    ) -> Result<Self> {
+        let create_texture = |bind_flags: D3D11_BIND_FLAG| {
+            util::try_out_ptr(|v| {
+                device.CreateTexture2D(
+                    &D3D11_TEXTURE2D_DESC {
+                        Width: width,
+                        Height: height,
+                        MipLevels: 1,
+                        ArraySize: 1,
+                        Format: format,
+                        SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+                        Usage: D3D11_USAGE_DEFAULT,
+                        BindFlags: bind_flags.0 as u32,
+                        CPUAccessFlags: 0,
+                        MiscFlags: 0,
+                    },
+                    None,
+                    Some(v),
+                )
+            })
+        };
+
+        let source: ID3D11Texture2D = create_texture(D3D11_BIND_SHADER_RESOURCE)?;
+        let intermediate: ID3D11Texture2D =
+            create_texture(D3D11_BIND_SHADER_RESOURCE | D3D11_BIND_RENDER_TARGET)?;
+
+        let source_view =
+            util::try_out_ptr(|v| device.CreateShaderResourceView(&source, None, Some(v)))?;
+        let intermediate_target =
+            util::try_out_ptr(|v| device.CreateRenderTargetView(&intermediate, None, Some(v)))?;
+        let intermediate_view =
+            util::try_out_ptr(|v| device.CreateShaderResourceView(&intermediate, None, Some(v)))?;
+
+        Ok(Self {
+            width,
+            height,
+            format,
+            source,
+            source_view,
+            intermediate_target,
+            intermediate_view,
+        })
+    }
+}
+
+// CPU-readable copy of a region of the render target, for the magnifier.
+struct ReadbackTexture {
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    texture: ID3D11Texture2D,
+    // The copy in flight in the texture, if any.
+    pending: Option<PendingSample>,
+}
+
+// Where a sample copied into a `ReadbackTexture` comes from.
+#[derive(Clone, Copy)]
+struct PendingSample {
+    // Order of the copies, to keep the latest one.
+    seq: u64,
+    pos: [i32; 2],
+    size: [u32; 2],
+    read_pixel: fn(&[u8]) -> [u8; 4],
+}
+
+impl ReadbackTexture {
+    unsafe fn new(
+        device: &ID3D11Device,
+        width: u32,
+        height: u32,
+        format: DXGI_FORMAT,
+    ) -> Result<Self> {
+        let texture = util::try_out_ptr(|v| {
+            device.CreateTexture2D(
+                &D3D11_TEXTURE2D_DESC {
+                    Width: width,
+                    Height: height,
+                    MipLevels: 1,
+                    ArraySize: 1,
+                    Format: format,
+                    SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+                    Usage: D3D11_USAGE_STAGING,
+                    BindFlags: 0,
+                    CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+                    MiscFlags: 0,
+                },
+                None,
+                Some(v),
+            )
+        })?;
+
+        Ok(Self { width, height, format, texture, pending: None })
+    }
+}
+
+// Copy of a region of the render target drawn by zoom views, registered in the
+// texture heap.
+struct ZoomCopyTexture {
+    width: u32,
+    height: u32,
+    format: DXGI_FORMAT,
+    texture: ID3D11Texture2D,
+    id: TextureId,
+}
+
+impl ZoomCopyTexture {
+    unsafe fn create(
+        device: &ID3D11Device,
+        width: u32,
+        height: u32,
+        format: DXGI_FORMAT,
+    ) -> Result<(ID3D11Texture2D, ID3D11ShaderResourceView)> {
+        let texture: ID3D11Texture2D = util::try_out_ptr(|v| {
+            device.CreateTexture2D(
+                &D3D11_TEXTURE2D_DESC {
+                    Width: width,
+                    Height: height,
+                    MipLevels: 1,
+                    ArraySize: 1,
+                    Format: format,
+                    SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+                    Usage: D3D11_USAGE_DEFAULT,
+                    BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
+                    CPUAccessFlags: 0,
+                    MiscFlags: 0,
+                },
+                None,
+                Some(v),
+            )
+        })?;
+        let shader_resource_view =
+            util::try_out_ptr(|v| device.CreateShaderResourceView(&texture, None, Some(v)))?;
+
+        Ok((texture, shader_resource_view))
+    }
+}
+
+// Conversion of the 4-byte pixels of `format` to RGBA, if supported.
+fn pixel_reader(format: DXGI_FORMAT) -> Option<fn(&[u8]) -> [u8; 4]> {
+    match format {
+        DXGI_FORMAT_R8G8B8A8_UNORM | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => {
+            Some(|p| [p[0], p[1], p[2], p[3]])
+        },
+        DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => {
+            Some(|p| [p[2], p[1], p[0], p[3]])
+        },
+        DXGI_FORMAT_B8G8R8X8_UNORM | DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => {
+            Some(|p| [p[2], p[1], p[0], 0xff])
+        },
+        DXGI_FORMAT_R10G10B10A2_UNORM => Some(|p| {
+            let v = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+            [(v >> 2) as u8, (v >> 12) as u8, (v >> 22) as u8, ((v >> 30) * 0x55) as u8]
+        }),
+        _ => None,
+    }
+}
+
+// Depth tests never write to the game's depth buffer.
+fn create_depth_stencil_state(
+    device: &ID3D11Device,
+    depth_enable: bool,
+    depth_func: D3D11_COMPARISON_FUNC,
+) -> Result<ID3D11DepthStencilState> {
+    util::try_out_ptr(|v| unsafe {
+        device.CreateDepthStencilState(
+            &D3D11_DEPTH_STENCIL_DESC {
+                DepthEnable: depth_enable.into(),
+                DepthFunc: depth_func,
+                DepthWriteMask: if depth_enable {
+                    D3D11_DEPTH_WRITE_MASK_ZERO
+                } else {
+                    D3D11_DEPTH_WRITE_MASK_ALL
+                },
+                StencilEnable: false.into(),
+                StencilReadMask: 0,
+                StencilWriteMask: 0,
+                FrontFace: D3D11_DEPTH_STENCILOP_DESC {
+                    StencilFailOp: D3D11_STENCIL_OP_KEEP,
+                    StencilDepthFailOp: D3D11_STENCIL_OP_KEEP,
+                    StencilPassOp: D3D11_STENCIL_OP_KEEP,
+                    StencilFunc: D3D11_COMPARISON_ALWAYS,
+                },
+                BackFace: D3D11_DEPTH_STENCILOP_DESC {
+                    StencilFailOp: D3D11_STENCIL_OP_KEEP,
+                    StencilDepthFailOp: D3D11_STENCIL_OP_KEEP,
+                    StencilPassOp: D3D11_STENCIL_OP_KEEP,
+                    StencilFunc: D3D11_COMPARISON_ALWAYS,
+                },
+            },
+            Some(v),
+        )
+    })
+}
+
 struct Buffer<T: Sized> {
     bind_flag: D3D11_BIND_FLAG,
     resource: ID3D11Buffer,
@@ -631,6 +1438,45 @@ impl TextureHeap {
         Ok(id)
     }
 
+    unsafe fn insert_texture(
+        &mut self,
+        shader_resource_view: ID3D11ShaderResourceView,
+    ) -> Result<TextureId> {
+        let resource: ID3D11Texture2D = shader_resource_view.GetResource()?.cast()?;
+        let desc: D3D11_TEXTURE2D_DESC = util::out_param(|desc| resource.GetDesc(desc));
+
+        let id = TextureId::from(self.textures.len());
+        self.textures.push(Texture {
+            resource,
+            shader_resource_view,
+            id,
+            width: desc.Width,
+            height: desc.Height,
+        });
+
+        Ok(id)
+    }
+
+    // Point `texture_id` to another texture.
+    unsafe fn replace_view(
+        &mut self,
+        texture_id: TextureId,
+        shader_resource_view: ID3D11ShaderResourceView,
+    ) -> Result<()> {
+        let resource: ID3D11Texture2D = shader_resource_view.GetResource()?.cast()?;
+        let desc: D3D11_TEXTURE2D_DESC = util::out_param(|desc| resource.GetDesc(desc));
+
+        self.textures[texture_id.id()] = Texture {
+            resource,
+            shader_resource_view,
+            id: texture_id,
+            width: desc.Width,
+            height: desc.Height,
+        };
+
+        Ok(())
+    }
+
     unsafe fn update_texture(
         &mut self,
         texture_id: TextureId,
@@ -662,7 +1508,7 @@ impl TextureHeap {
 
 const BACKUP_OBJECT_COUNT: usize = 16;
 
-struct StateBackup {
+pub(crate) struct StateBackup {
     scissor_count: u32,
     scissor_rects: [RECT; BACKUP_OBJECT_COUNT],
     viewport_count: u32,
@@ -701,7 +1547,7 @@ struct StateBackup {
 }
 
 impl StateBackup {
-    unsafe fn backup(device_context: &ID3D11DeviceContext) -> StateBackup {
+    pub(crate) unsafe fn backup(device_context: &ID3D11DeviceContext) -> StateBackup {
         let mut scissor_count = 0;
         let mut scissor_rects: [RECT; BACKUP_OBJECT_COUNT] = Default::default();
         device_context.RSGetScissorRects(&mut scissor_count, None);
@@ -802,7 +1648,7 @@ impl StateBackup {
         }
     }
 
-    unsafe fn restore(self, device_context: &ID3D11DeviceContext) {
+    pub(crate) unsafe fn restore(self, device_context: &ID3D11DeviceContext) {
         device_context.RSSetScissorRects(Some(&self.scissor_rects[..self.scissor_count as usize]));
         device_context.RSSetViewports(Some(&self.viewports[..self.viewport_count as usize]));
 
@@ -1,82 +1,234 @@
 // NOTE: see this for ManuallyDrop instances https://github.com/microsoft/windows-rs/issues/2386
 
-use std::ffi::c_void;
+use std::collections::VecDeque;
+use std::ffi::{c_void, CStr};
 use std::mem::{offset_of, ManuallyDrop};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::{mem, ptr, slice};
 
 use imgui::internal::RawWrapper;
-use imgui::{BackendFlags, Context, DrawCmd, DrawData, DrawIdx, DrawVert, TextureId};
-use tracing::error;
-use windows::core::{s, w, Error, Interface, Result, HRESULT};
+use imgui::{BackendFlags, Context, DrawCmd, DrawData, DrawIdx, DrawList, DrawVert, TextureId};
+use parking_lot::Mutex;
+use tracing::{debug, error, trace};
+use windows::core::{s, Error, Interface, Result, HRESULT, PCWSTR};
 use windows::Win32::Foundation::*;
 use windows::Win32::Graphics::Direct3D::Fxc::*;
 use windows::Win32::Graphics::Direct3D::*;
 use windows::Win32::Graphics::Direct3D12::*;
 use windows::Win32::Graphics::Dxgi::Common::*;
+use windows::Win32::Graphics::Dxgi::{
+    CreateDXGIFactory1, IDXGIAdapter3, IDXGIFactory4, DXGI_MEMORY_SEGMENT_GROUP_LOCAL,
+    DXGI_QUERY_VIDEO_MEMORY_INFO,
+};
+use windows::Win32::System::Threading::{CreateEventW, WaitForSingleObject};
 
+use crate::compose::{Fence, FrameRing, RetirementQueue};
 use crate::renderer::RenderEngine;
-use crate::util::{self, Fence};
-use crate::RenderContext;
+use crate::{names, util, ExternalTexture, RenderContext};
+
+/// Capabilities of the DirectX 12 device the overlay is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D12Capabilities {
+    /// Highest feature level supported by the device.
+    pub feature_level: D3D_FEATURE_LEVEL,
+    /// Highest shader model supported by the device.
+    pub shader_model: D3D_SHADER_MODEL,
+    /// Whether the device supports enhanced barriers.
+    pub enhanced_barriers: bool,
+    /// Resource binding tier of the device. From tier 3, the overlay samples
+    /// its textures bindlessly.
+    pub resource_binding_tier: D3D12_RESOURCE_BINDING_TIER,
+    /// Local video memory budget of the adapter at the time of the query, if
+    /// the adapter reports it.
+    pub video_memory: Option<VideoMemoryInfo>,
+}
+
+/// Video memory budget of an adapter, as reported by
+/// `IDXGIAdapter3::QueryVideoMemoryInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoMemoryInfo {
+    /// Bytes of video memory the process should stay within.
+    pub budget: u64,
+    /// Bytes of video memory the process currently uses.
+    pub current_usage: u64,
+}
+
+// Games rarely queue more than three frames: further frames in flight only
+// hold on to more command allocators and upload buffers. This doesn't bound the
+// swap chain's buffer count: the back buffer to draw on is passed every frame,
+// and its render target view recreated in the same descriptor.
+const MAX_FRAMES_IN_FLIGHT: usize = 3;
+
+static FRAMES_IN_FLIGHT: AtomicUsize = AtomicUsize::new(1);
+
+/// Set how many frames the engine may submit before waiting for the GPU to
+/// finish the oldest one, between 1 and 3. Only engines created afterwards
+/// are affected.
+pub(crate) fn set_frames_in_flight(frames_in_flight: usize) {
+    FRAMES_IN_FLIGHT.store(frames_in_flight.clamp(1, MAX_FRAMES_IN_FLIGHT), Ordering::SeqCst);
+}
+
+/// Completion of the overlay work of a frame, passed to the callbacks added
+/// with [`on_frame_complete`](crate::hooks::dx12::on_frame_complete).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCompletion {
+    /// Number of the overlay frame, as returned by
+    /// [`overlay_frame`](crate::hooks::dx12::overlay_frame) while it was
+    /// rendered.
+    pub frame: u64,
+    /// Value the [overlay fence](crate::hooks::dx12::overlay_fence) was
+    /// signaled with once the GPU finished the frame.
+    pub fence_value: u64,
+}
 
+type FrameCallback = Box<dyn FnMut(FrameCompletion) + Send>;
+
+static FRAME_CALLBACKS: Mutex<Vec<FrameCallback>> = Mutex::new(Vec::new());
+static OVERLAY_FENCE: Mutex<Option<ID3D12Fence>> = Mutex::new(None);
+static OVERLAY_FRAME: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn add_frame_callback(callback: FrameCallback) {
+    FRAME_CALLBACKS.lock().push(callback);
+}
+
+pub(crate) fn clear_frame_callbacks() {
+    FRAME_CALLBACKS.lock().clear();
+}
+
+pub(crate) fn overlay_fence() -> Option<ID3D12Fence> {
+    OVERLAY_FENCE.lock().clone()
+}
+
+pub(crate) fn overlay_frame() -> u64 {
+    OVERLAY_FRAME.load(Ordering::SeqCst)
+}
+
+/// Render engine for DirectX 12 command queues.
 pub struct D3D12RenderEngine {
     device: ID3D12Device,
+    adapter: Option<IDXGIAdapter3>,
+    capabilities: D3D12Capabilities,
+    budget_monitor: Option<BudgetMonitor>,
 
     command_queue: ID3D12CommandQueue,
-    command_allocator: ID3D12CommandAllocator,
+    present_queue: Option<PresentQueue>,
     command_list: ID3D12GraphicsCommandList,
+    // Resources of the submissions that may be in flight, used in turn.
+    frames: FrameRing<FrameResources>,
 
     #[allow(unused)]
     rtv_heap: ID3D12DescriptorHeap,
     rtv_heap_start: D3D12_CPU_DESCRIPTOR_HANDLE,
+    shared_rtv: D3D12_CPU_DESCRIPTOR_HANDLE,
     texture_heap: TextureHeap,
 
+    shared_texture: Option<SharedTexture>,
+    draw_to_target: bool,
+    wait_for_completion: bool,
+    // Windows excluded from the render target and from the shared texture.
+    hidden_windows: [Vec<String>; 2],
+
+    // Whether all textures are bound at once, and indexed by a root constant.
+    bindless: bool,
     root_signature: ID3D12RootSignature,
     pipeline_state: ID3D12PipelineState,
 
-    vertex_buffer: Buffer<DrawVert>,
-    index_buffer: Buffer<u16>,
     projection_buffer: [[f32; 4]; 4],
 
     fence: Fence,
+    retired: RetirementQueue<ID3D12Pageable>,
+    // Overlay frames the GPU may still be working on, with the fence value
+    // signaled after their last submission.
+    pending_frames: VecDeque<FrameCompletion>,
 }
 
 impl D3D12RenderEngine {
+    /// Create a render engine submitting its work to `command_queue`, and set
+    /// up `ctx` for it. Compute queues are supported: the engine then renders
+    /// on a direct queue of its own, synchronized with `command_queue`.
+    ///
+    /// The render target is a resource in the `D3D12_RESOURCE_STATE_PRESENT`
+    /// state, e.g. a swap chain back buffer; it is left in the
+    /// `D3D12_RESOURCE_STATE_COMMON` state, which is equivalent.
     pub fn new(command_queue: &ID3D12CommandQueue, ctx: &mut Context) -> Result<Self> {
-        let (device, command_queue, command_allocator, command_list) =
-            unsafe { create_command_objects(command_queue) }?;
+        let frames_in_flight = FRAMES_IN_FLIGHT.load(Ordering::SeqCst);
+        let (device, command_queue, present_queue, command_list, frames) =
+            unsafe { create_command_objects(command_queue, frames_in_flight) }?;
 
         let (rtv_heap, texture_heap) = unsafe { create_heaps(&device) }?;
         let rtv_heap_start = unsafe { rtv_heap.GetCPUDescriptorHandleForHeapStart() };
+        let shared_rtv = D3D12_CPU_DESCRIPTOR_HANDLE {
+            ptr: rtv_heap_start.ptr
+                + unsafe { device.GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_RTV) }
+                    as usize,
+        };
 
-        let (root_signature, pipeline_state) = unsafe { create_shader_program(&device) }?;
-
-        let vertex_buffer = Buffer::new(&device, 5000)?;
-        let index_buffer = Buffer::new(&device, 10000)?;
+        let capabilities = unsafe { query_capabilities(&device) };
+        let bindless = capabilities.resource_binding_tier.0 >= D3D12_RESOURCE_BINDING_TIER_3.0;
+        let (root_signature, pipeline_state) = unsafe { create_shader_program(&device, bindless) }?;
 
         let fence = Fence::new(&device)?;
+        // The fence starts out completed at 0, which marks unused frames: signal
+        // it from 1 on.
+        fence.incr();
+        *OVERLAY_FENCE.lock() = Some(fence.fence().clone());
+
+        let adapter = unsafe {
+            CreateDXGIFactory1::<IDXGIFactory4>()
+                .and_then(|factory| factory.EnumAdapterByLuid(device.GetAdapterLuid()))
+        }
+        .map_err(|e| error!("Could not find the adapter of the device: {e:?}"))
+        .ok();
+        let budget_monitor = adapter.as_ref().and_then(|adapter| {
+            unsafe { BudgetMonitor::new(adapter) }
+                .map_err(|e| error!("Could not monitor the video memory budget: {e:?}"))
+                .ok()
+        });
 
         ctx.set_ini_filename(None);
         ctx.io_mut().backend_flags |= BackendFlags::RENDERER_HAS_VTX_OFFSET;
-        ctx.set_renderer_name(String::from(concat!("hudhook-dx12@", env!("CARGO_PKG_VERSION"))));
+        ctx.set_renderer_name(names::renderer("dx12"));
 
         Ok(Self {
             device,
+            adapter,
+            capabilities,
+            budget_monitor,
             command_queue,
-            command_allocator,
+            present_queue,
             command_list,
+            frames: FrameRing::new(frames),
             rtv_heap,
             rtv_heap_start,
+            shared_rtv,
             texture_heap,
+            shared_texture: None,
+            draw_to_target: true,
+            wait_for_completion: false,
+            hidden_windows: Default::default(),
+            bindless,
             root_signature,
             pipeline_state,
-            vertex_buffer,
-            index_buffer,
             projection_buffer: Default::default(),
             fence,
+            retired: RetirementQueue::default(),
+            pending_frames: VecDeque::new(),
         })
     }
 }
 
+impl Drop for D3D12RenderEngine {
+    fn drop(&mut self) {
+        // The frames in flight may still use the engine's resources.
+        if let Err(e) = self.wait_idle() {
+            error!("Could not wait for the GPU to finish rendering: {e:?}");
+        }
+
+        self.complete_frames(u64::MAX);
+        OVERLAY_FENCE.lock().take();
+    }
+}
+
 impl RenderContext for D3D12RenderEngine {
     fn load_texture(&mut self, data: &[u8], width: u32, height: u32) -> Result<TextureId> {
         unsafe {
@@ -93,8 +245,36 @@ impl RenderContext for D3D12RenderEngine {
         width: u32,
         height: u32,
     ) -> Result<()> {
+        // Frames in flight may still sample the texture.
+        self.wait_idle()?;
         unsafe { self.texture_heap.upload_texture(texture_id, data, width, height) }
     }
+
+    unsafe fn register_texture(&mut self, texture: ExternalTexture) -> Result<TextureId> {
+        let resource = match texture {
+            ExternalTexture::Dx12(resource) => resource,
+            #[allow(unreachable_patterns)]
+            texture => {
+                error!("Texture {texture:?} can't be registered on a DirectX 12 engine");
+                return Err(Error::from_hresult(HRESULT(-1)));
+            },
+        };
+
+        let desc = resource.GetDesc();
+        self.texture_heap.insert_texture(resource, desc.Format, desc.Width as u32, desc.Height)
+    }
+
+    fn d3d12_capabilities(&self) -> Option<D3D12Capabilities> {
+        let video_memory = self.adapter.as_ref().and_then(|adapter| {
+            let mut info = DXGI_QUERY_VIDEO_MEMORY_INFO::default();
+            unsafe { adapter.QueryVideoMemoryInfo(0, DXGI_MEMORY_SEGMENT_GROUP_LOCAL, &mut info) }
+                .ok()?;
+
+            Some(VideoMemoryInfo { budget: info.Budget, current_usage: info.CurrentUsage })
+        });
+
+        Some(D3D12Capabilities { video_memory, ..self.capabilities })
+    }
 }
 
 impl RenderEngine for D3D12RenderEngine {
@@ -102,40 +282,62 @@ impl RenderEngine for D3D12RenderEngine {
 
     fn render(&mut self, draw_data: &DrawData, render_target: Self::RenderTarget) -> Result<()> {
         unsafe {
-            self.device.CreateRenderTargetView(&render_target, None, self.rtv_heap_start);
-
-            self.command_allocator.Reset()?;
-            self.command_list.Reset(&self.command_allocator, None)?;
-
-            let present_to_rtv_barriers = [util::create_barrier(
-                &render_target,
-                D3D12_RESOURCE_STATE_PRESENT,
-                D3D12_RESOURCE_STATE_RENDER_TARGET,
-            )];
+            self.complete_frames(self.fence.completed_value());
+            self.retired.collect(self.fence.completed_value());
+            for object in self.texture_heap.retired.drain(..) {
+                self.retired.retire_object(&object, self.fence.value());
+            }
 
-            let rtv_to_present_barriers = [util::create_barrier(
-                &render_target,
-                D3D12_RESOURCE_STATE_RENDER_TARGET,
-                D3D12_RESOURCE_STATE_COMMON,
-            )];
+            self.texture_heap.begin_frame(draw_data)?;
+            if let Some(budget_monitor) = self.budget_monitor.as_mut() {
+                if let Some(excess) = budget_monitor.excess() {
+                    self.texture_heap.evict(excess, self.frames.len() as u64);
+                }
+            }
 
-            self.command_list.ResourceBarrier(&present_to_rtv_barriers);
-            self.command_list.OMSetRenderTargets(1, Some(&self.rtv_heap_start), false, None);
-            self.command_list.SetDescriptorHeaps(&[Some(self.texture_heap.srv_heap.clone())]);
+            // imgui-rs frees the closure of a callback when it runs it, so the
+            // callbacks run in the first pass only.
+            let mut run_callbacks = true;
+
+            if self.draw_to_target {
+                self.render_to(
+                    draw_data,
+                    &render_target,
+                    self.rtv_heap_start,
+                    OUTPUT_TARGET,
+                    run_callbacks,
+                )?;
+                run_callbacks = false;
+            }
 
-            self.render_draw_data(draw_data)?;
+            if let Some(resource) = self.shared_texture.as_ref().map(|t| t.resource.clone()) {
+                self.render_to(
+                    draw_data,
+                    &resource,
+                    self.shared_rtv,
+                    OUTPUT_SHARED,
+                    run_callbacks,
+                )?;
+                run_callbacks = false;
+                // Other processes read the shared texture as soon as it is
+                // signaled, so it must be complete by then.
+                self.wait_idle()?;
+            }
 
-            self.command_list.ResourceBarrier(&rtv_to_present_barriers);
-            self.command_list.Close()?;
-            self.command_queue.ExecuteCommandLists(&[Some(self.command_list.cast()?)]);
-            self.command_queue.Signal(self.fence.fence(), self.fence.value())?;
-            self.fence.wait()?;
-            self.fence.incr();
+            if self.wait_for_completion {
+                self.wait_idle()?;
+            }
 
-            present_to_rtv_barriers.into_iter().for_each(util::drop_barrier);
-            rtv_to_present_barriers.into_iter().for_each(util::drop_barrier);
+            // Nothing was drawn, but the callbacks must still run once.
+            if run_callbacks {
+                draw_data.draw_lists().for_each(|draw_list| run_callbacks_of(draw_list));
+            }
         }
 
+        let frame = OVERLAY_FRAME.fetch_add(1, Ordering::SeqCst);
+        self.pending_frames
+            .push_back(FrameCompletion { frame, fence_value: self.fence.value() - 1 });
+
         Ok(())
     }
 
@@ -144,27 +346,245 @@ impl RenderEngine for D3D12RenderEngine {
         let fonts_texture = fonts.build_rgba32_texture();
         fonts.tex_id =
             self.load_texture(fonts_texture.data, fonts_texture.width, fonts_texture.height)?;
+        // Every frame needs the font atlas, so never evict it.
+        self.texture_heap.textures[fonts.tex_id.id()].evictable = false;
         Ok(())
     }
 }
 
 impl D3D12RenderEngine {
-    unsafe fn render_draw_data(&mut self, draw_data: &DrawData) -> Result<()> {
-        self.vertex_buffer.clear();
-        self.index_buffer.clear();
+    /// Render into a shared texture of the given size, so that other processes
+    /// can composite the overlay. The texture is (re)created as needed and
+    /// cleared to transparent. `None` releases the texture.
+    ///
+    /// Must be called once per frame, before rendering.
+    pub(crate) unsafe fn update_shared_texture(
+        &mut self,
+        size: Option<(u32, u32)>,
+        name: PCWSTR,
+    ) -> Result<()> {
+        let Some((width, height)) = size else {
+            self.retire_shared_texture();
+            return Ok(());
+        };
+
+        if !matches!(&self.shared_texture, Some(t) if t.width == width && t.height == height) {
+            // Drop the old texture first, as its handle owns the name.
+            self.retire_shared_texture();
+            self.shared_texture = Some(SharedTexture::new(&self.device, width, height, name)?);
+        }
+
+        let Some(resource) = self.shared_texture.as_ref().map(|t| t.resource.clone()) else {
+            return Ok(());
+        };
+
+        self.device.CreateRenderTargetView(&resource, None, self.shared_rtv);
+
+        self.begin_submission()?;
+
+        let barriers = [
+            util::create_barrier(
+                &resource,
+                D3D12_RESOURCE_STATE_COMMON,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+            ),
+            util::create_barrier(
+                &resource,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+                D3D12_RESOURCE_STATE_COMMON,
+            ),
+        ];
+
+        self.command_list.ResourceBarrier(&barriers[..1]);
+        self.command_list.ClearRenderTargetView(self.shared_rtv, &[0f32; 4], None);
+        self.command_list.ResourceBarrier(&barriers[1..]);
+        self.end_submission()?;
+
+        barriers.into_iter().for_each(util::drop_barrier);
+
+        Ok(())
+    }
+
+    // Close the shared texture's handle, keeping the texture itself alive until
+    // the GPU is done with it.
+    fn retire_shared_texture(&mut self) {
+        if let Some(shared_texture) = self.shared_texture.take() {
+            self.retired.retire_object(&shared_texture.resource, self.fence.value());
+        }
+    }
+
+    /// Whether to keep rendering into the render target passed to
+    /// [`RenderEngine::render`] while a shared texture is in use.
+    pub(crate) fn set_draw_to_target(&mut self, draw_to_target: bool) {
+        self.draw_to_target = draw_to_target;
+    }
+
+    /// Whether to wait for the GPU to finish every frame before returning from
+    /// [`RenderEngine::render`], e.g. when rendering on a queue the game's
+    /// presentation isn't ordered with.
+    pub(crate) fn set_wait_for_completion(&mut self, wait_for_completion: bool) {
+        self.wait_for_completion = wait_for_completion;
+    }
+
+    /// Start the engine's work after the work submitted on `command_queue`,
+    /// and let later work on it start after the engine's, e.g. when rendering
+    /// on a queue of our own while the game renders on `command_queue`.
+    ///
+    /// Queues of another device are ignored.
+    pub(crate) unsafe fn order_with_queue(
+        &mut self,
+        command_queue: &ID3D12CommandQueue,
+    ) -> Result<()> {
+        if matches!(&self.present_queue, Some(p) if p.command_queue == *command_queue) {
+            return Ok(());
+        }
+
+        let device: ID3D12Device = util::try_out_ptr(|v| command_queue.GetDevice(v))?;
+        if device != self.device {
+            debug!("{command_queue:?} belongs to another device, not ordering with it");
+            return Ok(());
+        }
+
+        let fence = Fence::new(&self.device)?;
+        fence.fence().SetName(&names::debug_object("Present Queue Fence"))?;
+        fence.incr();
+        self.present_queue = Some(PresentQueue { command_queue: command_queue.clone(), fence });
+
+        Ok(())
+    }
+
+    /// Exclude windows, and their child windows, from the render target and
+    /// from the shared texture respectively.
+    pub(crate) fn set_hidden_windows(&mut self, on_target: Vec<String>, on_shared: Vec<String>) {
+        self.hidden_windows = [on_target, on_shared];
+    }
+
+    /// NT handle of the shared texture, if any.
+    pub(crate) fn shared_texture_handle(&self) -> Option<HANDLE> {
+        self.shared_texture.as_ref().map(|t| t.handle)
+    }
+
+    /// The device the engine renders with.
+    pub(crate) fn device(&self) -> &ID3D12Device {
+        &self.device
+    }
+
+    // Wait for the GPU to be done with the current frame's resources, and start
+    // recording commands with them.
+    unsafe fn begin_submission(&mut self) -> Result<()> {
+        let frame = self.frames.acquire(&self.fence)?;
+        frame.command_allocator.Reset()?;
+        self.command_list.Reset(&frame.command_allocator, None)
+    }
+
+    // Submit the recorded commands, and move on to the next frame's resources
+    // without waiting for the GPU.
+    unsafe fn end_submission(&mut self) -> Result<()> {
+        self.command_list.Close()?;
+
+        // Start after the game's work on the back buffer.
+        if let Some(PresentQueue { command_queue, fence }) = &self.present_queue {
+            command_queue.Signal(fence.fence(), fence.value())?;
+            self.command_queue.Wait(fence.fence(), fence.value())?;
+            fence.incr();
+        }
+
+        self.command_queue.ExecuteCommandLists(&[Some(self.command_list.cast()?)]);
+        self.command_queue.Signal(self.fence.fence(), self.fence.value())?;
+
+        // Present after the overlay.
+        if let Some(PresentQueue { command_queue, .. }) = &self.present_queue {
+            command_queue.Wait(self.fence.fence(), self.fence.value())?;
+        }
+        self.frames.submit(self.fence.value());
+        self.fence.incr();
+
+        Ok(())
+    }
+
+    // Wait for the GPU to be done with every submission.
+    // Fire the frame callbacks for the frames the GPU is done with.
+    fn complete_frames(&mut self, completed_value: u64) {
+        while let Some(&completion) =
+            self.pending_frames.front().filter(|c| c.fence_value <= completed_value)
+        {
+            self.pending_frames.pop_front();
+            FRAME_CALLBACKS.lock().iter_mut().for_each(|callback| callback(completion));
+        }
+    }
+
+    fn wait_idle(&self) -> Result<()> {
+        self.fence.wait_for(self.fence.value() - 1)
+    }
+
+    unsafe fn render_to(
+        &mut self,
+        draw_data: &DrawData,
+        render_target: &ID3D12Resource,
+        rtv: D3D12_CPU_DESCRIPTOR_HANDLE,
+        output: usize,
+        run_callbacks: bool,
+    ) -> Result<()> {
+        self.device.CreateRenderTargetView(render_target, None, rtv);
+
+        self.begin_submission()?;
+
+        let present_to_rtv_barriers = [util::create_barrier(
+            render_target,
+            D3D12_RESOURCE_STATE_PRESENT,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+        )];
+
+        let rtv_to_present_barriers = [util::create_barrier(
+            render_target,
+            D3D12_RESOURCE_STATE_RENDER_TARGET,
+            D3D12_RESOURCE_STATE_COMMON,
+        )];
+
+        self.command_list.ResourceBarrier(&present_to_rtv_barriers);
+        self.command_list.OMSetRenderTargets(1, Some(&rtv), false, None);
+        self.command_list.SetDescriptorHeaps(&[Some(self.texture_heap.srv_heap.clone())]);
+
+        let hidden_windows = mem::take(&mut self.hidden_windows[output]);
+        let res = self.render_draw_data(draw_data, &hidden_windows, run_callbacks);
+        self.hidden_windows[output] = hidden_windows;
+        let res = res.and_then(|()| {
+            self.command_list.ResourceBarrier(&rtv_to_present_barriers);
+            self.end_submission()
+        });
+
+        // The barriers hold references to the render target. Release them on
+        // errors too, or the game can't resize its swap chain anymore.
+        present_to_rtv_barriers.into_iter().for_each(util::drop_barrier);
+        rtv_to_present_barriers.into_iter().for_each(util::drop_barrier);
+
+        res
+    }
+
+    unsafe fn render_draw_data(
+        &mut self,
+        draw_data: &DrawData,
+        hidden_windows: &[String],
+        run_callbacks: bool,
+    ) -> Result<()> {
+        let frame = self.frames.resources();
+        frame.vertex_buffer.clear();
+        frame.index_buffer.clear();
 
         draw_data
             .draw_lists()
+            .filter(|draw_list| !is_hidden(draw_list, hidden_windows))
             .map(|draw_list| {
                 (draw_list.vtx_buffer().iter().copied(), draw_list.idx_buffer().iter().copied())
             })
             .for_each(|(vertices, indices)| {
-                self.vertex_buffer.extend(vertices);
-                self.index_buffer.extend(indices);
+                frame.vertex_buffer.extend(vertices);
+                frame.index_buffer.extend(indices);
             });
 
-        self.vertex_buffer.upload(&self.device)?;
-        self.index_buffer.upload(&self.device)?;
+        let fence_value = self.fence.value();
+        frame.vertex_buffer.upload(&self.device, &mut self.retired, fence_value)?;
+        frame.index_buffer.upload(&self.device, &mut self.retired, fence_value)?;
 
         self.projection_buffer = {
             let [l, t, r, b] = [
@@ -187,7 +607,7 @@ impl D3D12RenderEngine {
         let mut vtx_offset = 0usize;
         let mut idx_offset = 0usize;
 
-        for cl in draw_data.draw_lists() {
+        for cl in draw_data.draw_lists().filter(|cl| !is_hidden(cl, hidden_windows)) {
             for cmd in cl.commands() {
                 match cmd {
                     DrawCmd::Elements { count, cmd_params } => {
@@ -201,9 +621,17 @@ impl D3D12RenderEngine {
                         };
 
                         if r.right > r.left && r.bottom > r.top {
-                            let tex_handle =
-                                self.texture_heap.textures[cmd_params.texture_id.id()].gpu_desc;
-                            self.command_list.SetGraphicsRootDescriptorTable(1, tex_handle);
+                            let texture_index = cmd_params.texture_id.id();
+                            if self.bindless {
+                                self.command_list.SetGraphicsRoot32BitConstant(
+                                    2,
+                                    texture_index as u32,
+                                    0,
+                                );
+                            } else {
+                                let tex_handle = self.texture_heap.textures[texture_index].gpu_desc;
+                                self.command_list.SetGraphicsRootDescriptorTable(1, tex_handle);
+                            }
                             self.command_list.RSSetScissorRects(&[r]);
                             self.command_list.DrawIndexedInstanced(
                                 count as _,
@@ -220,17 +648,31 @@ impl D3D12RenderEngine {
                         // whatsoever. What am I doing wrong?
                         self.setup_render_state(draw_data);
                     },
-                    DrawCmd::RawCallback { callback, raw_cmd } => callback(cl.raw(), raw_cmd),
+                    DrawCmd::RawCallback { callback, raw_cmd } if run_callbacks => {
+                        callback(cl.raw(), raw_cmd)
+                    },
+                    DrawCmd::RawCallback { .. } => {},
                 }
             }
             idx_offset += cl.idx_buffer().len();
             vtx_offset += cl.vtx_buffer().len();
         }
 
+        // The callbacks of hidden windows run too, or their closures would
+        // leak: a callback runs once per frame whichever output it is drawn
+        // in, if any.
+        if run_callbacks {
+            for cl in draw_data.draw_lists().filter(|cl| is_hidden(cl, hidden_windows)) {
+                run_callbacks_of(cl);
+            }
+        }
+
         Ok(())
     }
 
     unsafe fn setup_render_state(&self, draw_data: &DrawData) {
+        let FrameResources { vertex_buffer, index_buffer, .. } = &self.frames.current().resources;
+
         self.command_list.RSSetViewports(&[D3D12_VIEWPORT {
             TopLeftX: 0f32,
             TopLeftY: 0f32,
@@ -243,15 +685,15 @@ impl D3D12RenderEngine {
         self.command_list.IASetVertexBuffers(
             0,
             Some(&[D3D12_VERTEX_BUFFER_VIEW {
-                BufferLocation: self.vertex_buffer.resource.GetGPUVirtualAddress(),
-                SizeInBytes: (self.vertex_buffer.data.len() * mem::size_of::<DrawVert>()) as _,
+                BufferLocation: vertex_buffer.resource.GetGPUVirtualAddress(),
+                SizeInBytes: (vertex_buffer.data.len() * mem::size_of::<DrawVert>()) as _,
                 StrideInBytes: mem::size_of::<DrawVert>() as _,
             }]),
         );
 
         self.command_list.IASetIndexBuffer(Some(&D3D12_INDEX_BUFFER_VIEW {
-            BufferLocation: self.index_buffer.resource.GetGPUVirtualAddress(),
-            SizeInBytes: (self.index_buffer.data.len() * mem::size_of::<DrawIdx>()) as _,
+            BufferLocation: index_buffer.resource.GetGPUVirtualAddress(),
+            SizeInBytes: (index_buffer.data.len() * mem::size_of::<DrawIdx>()) as _,
             Format: if mem::size_of::<DrawIdx>() == 2 {
                 DXGI_FORMAT_R16_UINT
             } else {
@@ -267,37 +709,191 @@ impl D3D12RenderEngine {
             self.projection_buffer.as_ptr() as *const c_void,
             0,
         );
+        if self.bindless {
+            self.command_list.SetGraphicsRootDescriptorTable(
+                1,
+                self.texture_heap.srv_heap.GetGPUDescriptorHandleForHeapStart(),
+            );
+        }
         self.command_list.OMSetBlendFactor(Some(&[0f32; 4]));
     }
 }
 
+// Run the callbacks of a draw list that isn't drawn, e.g. of a hidden window.
+unsafe fn run_callbacks_of(draw_list: &DrawList) {
+    for cmd in draw_list.commands() {
+        if let DrawCmd::RawCallback { callback, raw_cmd } = cmd {
+            callback(draw_list.raw(), raw_cmd);
+        }
+    }
+}
+
+const OUTPUT_TARGET: usize = 0;
+const OUTPUT_SHARED: usize = 1;
+
+// Whether the draw list belongs to one of the hidden windows or to one of their
+// child windows, which are named `Parent/Child_XXXXXXXX`.
+fn is_hidden(draw_list: &DrawList, hidden_windows: &[String]) -> bool {
+    if hidden_windows.is_empty() {
+        return false;
+    }
+
+    let owner_name = unsafe { draw_list.raw()._OwnerName };
+    if owner_name.is_null() {
+        return false;
+    }
+
+    let Ok(owner_name) = unsafe { CStr::from_ptr(owner_name) }.to_str() else {
+        return false;
+    };
+
+    hidden_windows.iter().any(|name| {
+        owner_name
+            .strip_prefix(name.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+// Query the capabilities that don't change over the lifetime of the device.
+unsafe fn query_capabilities(device: &ID3D12Device) -> D3D12Capabilities {
+    const FEATURE_LEVELS: [D3D_FEATURE_LEVEL; 5] = [
+        D3D_FEATURE_LEVEL_12_2,
+        D3D_FEATURE_LEVEL_12_1,
+        D3D_FEATURE_LEVEL_12_0,
+        D3D_FEATURE_LEVEL_11_1,
+        D3D_FEATURE_LEVEL_11_0,
+    ];
+    const SHADER_MODELS: [D3D_SHADER_MODEL; 10] = [
+        D3D_SHADER_MODEL_6_8,
+        D3D_SHADER_MODEL_6_7,
+        D3D_SHADER_MODEL_6_6,
+        D3D_SHADER_MODEL_6_5,
+        D3D_SHADER_MODEL_6_4,
+        D3D_SHADER_MODEL_6_3,
+        D3D_SHADER_MODEL_6_2,
+        D3D_SHADER_MODEL_6_1,
+        D3D_SHADER_MODEL_6_0,
+        D3D_SHADER_MODEL_5_1,
+    ];
+
+    fn check<T>(device: &ID3D12Device, feature: D3D12_FEATURE, data: &mut T) -> bool {
+        unsafe {
+            device
+                .CheckFeatureSupport(
+                    feature,
+                    data as *mut T as *mut c_void,
+                    mem::size_of::<T>() as u32,
+                )
+                .is_ok()
+        }
+    }
+
+    let mut feature_levels = D3D12_FEATURE_DATA_FEATURE_LEVELS {
+        NumFeatureLevels: FEATURE_LEVELS.len() as u32,
+        pFeatureLevelsRequested: FEATURE_LEVELS.as_ptr(),
+        MaxSupportedFeatureLevel: D3D_FEATURE_LEVEL_11_0,
+    };
+    check(device, D3D12_FEATURE_FEATURE_LEVELS, &mut feature_levels);
+
+    // Runtimes that don't know about a shader model reject the query, so ask for
+    // the highest one first and go down from there.
+    let shader_model = SHADER_MODELS
+        .into_iter()
+        .find_map(|shader_model| {
+            let mut data = D3D12_FEATURE_DATA_SHADER_MODEL { HighestShaderModel: shader_model };
+            check(device, D3D12_FEATURE_SHADER_MODEL, &mut data).then_some(data.HighestShaderModel)
+        })
+        .unwrap_or(D3D_SHADER_MODEL_5_1);
+
+    let mut options = D3D12_FEATURE_DATA_D3D12_OPTIONS::default();
+    check(device, D3D12_FEATURE_D3D12_OPTIONS, &mut options);
+
+    let mut options12 = D3D12_FEATURE_DATA_D3D12_OPTIONS12::default();
+    let enhanced_barriers = check(device, D3D12_FEATURE_D3D12_OPTIONS12, &mut options12)
+        && options12.EnhancedBarriersSupported.as_bool();
+
+    D3D12Capabilities {
+        feature_level: feature_levels.MaxSupportedFeatureLevel,
+        shader_model,
+        enhanced_barriers,
+        resource_binding_tier: options.ResourceBindingTier,
+        video_memory: None,
+    }
+}
+
+// The game's queue a non-direct queue is presented from, or the game renders
+// on while the engine uses a fallback queue, and the fence ordering the
+// engine's own queue after the game's work.
+struct PresentQueue {
+    command_queue: ID3D12CommandQueue,
+    fence: Fence,
+}
+
+type CommandObjects = (
+    ID3D12Device,
+    ID3D12CommandQueue,
+    Option<PresentQueue>,
+    ID3D12GraphicsCommandList,
+    Vec<FrameResources>,
+);
+
 unsafe fn create_command_objects(
     command_queue: &ID3D12CommandQueue,
-) -> Result<(ID3D12Device, ID3D12CommandQueue, ID3D12CommandAllocator, ID3D12GraphicsCommandList)> {
+    frames_in_flight: usize,
+) -> Result<CommandObjects> {
     let device: ID3D12Device = util::try_out_ptr(|v| unsafe { command_queue.GetDevice(v) })?;
-    let command_queue = command_queue.clone();
 
-    let command_allocator: ID3D12CommandAllocator =
-        device.CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_DIRECT)?;
+    // Compute queues can't execute the engine's direct command lists: render on
+    // a direct queue of our own, synchronized with the game's queue.
+    let (command_queue, present_queue) = match command_queue.GetDesc().Type {
+        D3D12_COMMAND_LIST_TYPE_DIRECT => (command_queue.clone(), None),
+        ty => {
+            debug!("Presenting from a {ty:?} queue, rendering on a direct queue");
+
+            let direct_queue: ID3D12CommandQueue =
+                device.CreateCommandQueue(&D3D12_COMMAND_QUEUE_DESC {
+                    Type: D3D12_COMMAND_LIST_TYPE_DIRECT,
+                    Priority: 0,
+                    Flags: D3D12_COMMAND_QUEUE_FLAG_NONE,
+                    NodeMask: 0,
+                })?;
+            direct_queue.SetName(&names::debug_object("Render Engine Command Queue"))?;
+
+            let fence = Fence::new(&device)?;
+            fence.fence().SetName(&names::debug_object("Present Queue Fence"))?;
+            fence.incr();
+
+            (direct_queue, Some(PresentQueue { command_queue: command_queue.clone(), fence }))
+        },
+    };
+
+    let frames = (0..frames_in_flight)
+        .map(|index| FrameResources::new(&device, index))
+        .collect::<Result<Vec<_>>>()?;
 
-    let command_list: ID3D12GraphicsCommandList =
-        device.CreateCommandList(0, D3D12_COMMAND_LIST_TYPE_DIRECT, &command_allocator, None)?;
+    let command_list: ID3D12GraphicsCommandList = device.CreateCommandList(
+        0,
+        D3D12_COMMAND_LIST_TYPE_DIRECT,
+        &frames[0].command_allocator,
+        None,
+    )?;
     command_list.Close()?;
 
-    command_allocator.SetName(w!("hudhook Render Engine Command Allocator"))?;
-    command_list.SetName(w!("hudhook Render Engine Command List"))?;
+    command_list.SetName(&names::debug_object("Render Engine Command List"))?;
 
-    Ok((device, command_queue, command_allocator, command_list))
+    Ok((device, command_queue, present_queue, command_list, frames))
 }
 
 unsafe fn create_heaps(device: &ID3D12Device) -> Result<(ID3D12DescriptorHeap, TextureHeap)> {
     let rtv_heap: ID3D12DescriptorHeap =
         device.CreateDescriptorHeap(&D3D12_DESCRIPTOR_HEAP_DESC {
             Type: D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-            NumDescriptors: 1,
+            // One for the render target, one for the shared texture.
+            NumDescriptors: 2,
             Flags: D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
             NodeMask: 1,
         })?;
+    rtv_heap.SetName(&names::debug_object("Render Target Heap"))?;
 
     let srv_heap: ID3D12DescriptorHeap =
         device.CreateDescriptorHeap(&D3D12_DESCRIPTOR_HEAP_DESC {
@@ -306,14 +902,19 @@ unsafe fn create_heaps(device: &ID3D12Device) -> Result<(ID3D12DescriptorHeap, T
             Flags: D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE,
             NodeMask: 0,
         })?;
+    srv_heap.SetName(&names::debug_object("Texture Heap"))?;
 
     let texture_heap = TextureHeap::new(device, srv_heap)?;
 
     Ok((rtv_heap, texture_heap))
 }
 
+// With `bindless`, the pixel shader sees every texture of the heap through an
+// unbounded descriptor table, and picks one with a root constant; otherwise it
+// sees a single texture, bound per draw call.
 unsafe fn create_shader_program(
     device: &ID3D12Device,
+    bindless: bool,
 ) -> Result<(ID3D12RootSignature, ID3D12PipelineState)> {
     let parameters = [
         D3D12_ROOT_PARAMETER {
@@ -334,7 +935,7 @@ unsafe fn create_shader_program(
                     NumDescriptorRanges: 1,
                     pDescriptorRanges: &D3D12_DESCRIPTOR_RANGE {
                         RangeType: D3D12_DESCRIPTOR_RANGE_TYPE_SRV,
-                        NumDescriptors: 1,
+                        NumDescriptors: if bindless { u32::MAX } else { 1 },
                         BaseShaderRegister: 0,
                         RegisterSpace: 0,
                         OffsetInDescriptorsFromTableStart: 0,
@@ -343,10 +944,21 @@ unsafe fn create_shader_program(
             },
             ShaderVisibility: D3D12_SHADER_VISIBILITY_PIXEL,
         },
+        D3D12_ROOT_PARAMETER {
+            ParameterType: D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
+            Anonymous: D3D12_ROOT_PARAMETER_0 {
+                Constants: D3D12_ROOT_CONSTANTS {
+                    ShaderRegister: 1,
+                    RegisterSpace: 0,
+                    Num32BitValues: 1,
+                },
+            },
+            ShaderVisibility: D3D12_SHADER_VISIBILITY_PIXEL,
+        },
     ];
 
     let root_signature_desc = D3D12_ROOT_SIGNATURE_DESC {
-        NumParameters: 2,
+        NumParameters: if bindless { 3 } else { 2 },
         pParameters: parameters.as_ptr(),
         NumStaticSamplers: 1,
         pStaticSamplers: &D3D12_STATIC_SAMPLER_DESC {
@@ -378,13 +990,13 @@ unsafe fn create_shader_program(
             Some(err_blob),
         )
     })
-    .map_err(util::print_error_blob("Serializing root signature"))
-    .expect("D3D12SerializeRootSignature");
+    .map_err(util::print_error_blob("Serializing root signature"))?;
 
     let root_signature: ID3D12RootSignature = device.CreateRootSignature(
         0,
         slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize()),
     )?;
+    root_signature.SetName(&names::debug_object("Root Signature"))?;
 
     const VS: &str = r#"
     cbuffer vertexBuffer : register(b0) {
@@ -426,6 +1038,28 @@ unsafe fn create_shader_program(
       return out_col;
     }"#;
 
+    const PS_BINDLESS: &str = r#"
+    struct PS_INPUT {
+      float4 pos: SV_POSITION;
+      float4 col: COLOR0;
+      float2 uv: TEXCOORD0;
+    };
+
+    cbuffer textureBuffer : register(b1) {
+      uint TextureIndex;
+    };
+
+    SamplerState sampler0: register(s0);
+    Texture2D textures[]: register(t0);
+
+    float4 main(PS_INPUT input): SV_Target {
+      float4 out_col = input.col * textures[TextureIndex].Sample(sampler0, input.uv);
+      return out_col;
+    }"#;
+
+    let (ps, ps_target) =
+        if bindless { (PS_BINDLESS, s!("ps_5_1\0")) } else { (PS, s!("ps_5_0\0")) };
+
     let vtx_shader: ID3DBlob = util::try_out_err_blob(|v, err_blob| unsafe {
         D3DCompile(
             VS.as_ptr() as _,
@@ -441,26 +1075,24 @@ unsafe fn create_shader_program(
             Some(err_blob),
         )
     })
-    .map_err(util::print_error_blob("Compiling vertex shader"))
-    .expect("D3DCompile");
+    .map_err(util::print_error_blob("Compiling vertex shader"))?;
 
     let pix_shader = util::try_out_err_blob(|v, err_blob| unsafe {
         D3DCompile(
-            PS.as_ptr() as _,
-            PS.len(),
+            ps.as_ptr() as _,
+            ps.len(),
             None,
             None,
             None::<&ID3DInclude>,
             s!("main\0"),
-            s!("ps_5_0\0"),
+            ps_target,
             0,
             0,
             v,
             Some(err_blob),
         )
     })
-    .map_err(util::print_error_blob("Compiling pixel shader"))
-    .expect("D3DCompile");
+    .map_err(util::print_error_blob("Compiling pixel shader"))?;
 
     let input_elements = [
         D3D12_INPUT_ELEMENT_DESC {
@@ -564,12 +1196,157 @@ unsafe fn create_shader_program(
         ..Default::default()
     };
 
-    let pipeline_state = unsafe { device.CreateGraphicsPipelineState(&pso_desc)? };
+    let pipeline_state: ID3D12PipelineState =
+        unsafe { device.CreateGraphicsPipelineState(&pso_desc)? };
+    pipeline_state.SetName(&names::debug_object("Pipeline State"))?;
     let _ = ManuallyDrop::into_inner(pso_desc.pRootSignature);
 
     Ok((root_signature, pipeline_state))
 }
 
+// A render target texture shared with other processes via a named NT handle.
+struct SharedTexture {
+    resource: ID3D12Resource,
+    handle: HANDLE,
+    width: u32,
+    height: u32,
+}
+
+impl SharedTexture {
+    unsafe fn new(device: &ID3D12Device, width: u32, height: u32, name: PCWSTR) -> Result<Self> {
+        let resource: ID3D12Resource = util::try_out_ptr(|v| unsafe {
+            device.CreateCommittedResource(
+                &D3D12_HEAP_PROPERTIES {
+                    Type: D3D12_HEAP_TYPE_DEFAULT,
+                    CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+                    MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+                    CreationNodeMask: Default::default(),
+                    VisibleNodeMask: Default::default(),
+                },
+                D3D12_HEAP_FLAG_SHARED,
+                &D3D12_RESOURCE_DESC {
+                    Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+                    Alignment: 0,
+                    Width: width as _,
+                    Height: height as _,
+                    DepthOrArraySize: 1,
+                    MipLevels: 1,
+                    Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                    SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
+                    Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+                    Flags: D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET
+                        | D3D12_RESOURCE_FLAG_ALLOW_SIMULTANEOUS_ACCESS,
+                },
+                D3D12_RESOURCE_STATE_COMMON,
+                Some(&D3D12_CLEAR_VALUE {
+                    Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                    Anonymous: D3D12_CLEAR_VALUE_0 { Color: [0f32; 4] },
+                }),
+                v,
+            )
+        })?;
+        resource.SetName(&names::debug_object("Shared Overlay Texture"))?;
+
+        let handle = device.CreateSharedHandle(&resource, None, GENERIC_ALL.0, name)?;
+
+        Ok(Self { resource, handle, width, height })
+    }
+}
+
+impl Drop for SharedTexture {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { CloseHandle(self.handle) } {
+            error!("Couldn't close shared texture handle: {e:?}");
+        }
+    }
+}
+
+// Tracks whether the process is over its local video memory budget, requerying
+// it when the adapter signals a budget change and for as long as the process
+// stays over budget.
+struct BudgetMonitor {
+    adapter: IDXGIAdapter3,
+    event: HANDLE,
+    cookie: u32,
+    over_budget: bool,
+}
+
+impl BudgetMonitor {
+    unsafe fn new(adapter: &IDXGIAdapter3) -> Result<Self> {
+        let event = CreateEventW(None, false, false, None)?;
+        let cookie = match adapter.RegisterVideoMemoryBudgetChangeNotificationEvent(event) {
+            Ok(cookie) => cookie,
+            Err(e) => {
+                let _ = CloseHandle(event);
+                return Err(e);
+            },
+        };
+
+        // Query once upfront, as the event only fires on changes.
+        Ok(Self { adapter: adapter.clone(), event, cookie, over_budget: true })
+    }
+
+    // Bytes of video memory used beyond the budget, if any.
+    unsafe fn excess(&mut self) -> Option<u64> {
+        let changed = WaitForSingleObject(self.event, 0) == WAIT_OBJECT_0;
+        if !changed && !self.over_budget {
+            return None;
+        }
+
+        let mut info = DXGI_QUERY_VIDEO_MEMORY_INFO::default();
+        if let Err(e) =
+            self.adapter.QueryVideoMemoryInfo(0, DXGI_MEMORY_SEGMENT_GROUP_LOCAL, &mut info)
+        {
+            error!("Could not query the video memory budget: {e:?}");
+            self.over_budget = false;
+            return None;
+        }
+
+        let excess = info.CurrentUsage.checked_sub(info.Budget).filter(|&excess| excess > 0);
+        self.over_budget = excess.is_some();
+        excess
+    }
+}
+
+impl Drop for BudgetMonitor {
+    fn drop(&mut self) {
+        unsafe {
+            self.adapter.UnregisterVideoMemoryBudgetChangeNotification(self.cookie);
+            if let Err(e) = CloseHandle(self.event) {
+                error!("Couldn't close video memory budget event: {e:?}");
+            }
+        }
+    }
+}
+
+// Resources recorded into a submission, which can only be reused once the GPU
+// is done with it.
+struct FrameResources {
+    command_allocator: ID3D12CommandAllocator,
+    vertex_buffer: Buffer<DrawVert>,
+    index_buffer: Buffer<u16>,
+}
+
+impl FrameResources {
+    // Resources are named after the frame index, so that debug layers and
+    // captures tell the allocators of the frames in flight apart.
+    fn new(device: &ID3D12Device, index: usize) -> Result<Self> {
+        let command_allocator: ID3D12CommandAllocator =
+            unsafe { device.CreateCommandAllocator(D3D12_COMMAND_LIST_TYPE_DIRECT) }?;
+        unsafe {
+            command_allocator.SetName(&names::debug_object(&format!(
+                "Render Engine Command Allocator {index}"
+            )))?
+        };
+
+        Ok(Self {
+            command_allocator,
+            vertex_buffer: Buffer::new(device, 5000)?,
+            index_buffer: Buffer::new(device, 10000)?,
+        })
+    }
+}
+
 struct Buffer<T: Sized> {
     resource: ID3D12Resource,
     resource_capacity: usize,
@@ -585,7 +1362,7 @@ impl<T> Buffer<T> {
     }
 
     fn create_resource(device: &ID3D12Device, resource_capacity: usize) -> Result<ID3D12Resource> {
-        util::try_out_ptr(|v| unsafe {
+        let resource: ID3D12Resource = util::try_out_ptr(|v| unsafe {
             device.CreateCommittedResource(
                 &D3D12_HEAP_PROPERTIES {
                     Type: D3D12_HEAP_TYPE_UPLOAD,
@@ -611,7 +1388,10 @@ impl<T> Buffer<T> {
                 None,
                 v,
             )
-        })
+        })?;
+        unsafe { resource.SetName(&names::debug_object("Draw Buffer"))? };
+
+        Ok(resource)
     }
 
     fn clear(&mut self) {
@@ -622,10 +1402,17 @@ impl<T> Buffer<T> {
         self.data.extend(it)
     }
 
-    fn upload(&mut self, device: &ID3D12Device) -> Result<()> {
+    fn upload(
+        &mut self,
+        device: &ID3D12Device,
+        retired: &mut RetirementQueue<ID3D12Pageable>,
+        fence_value: u64,
+    ) -> Result<()> {
         let capacity = self.data.capacity();
         if capacity > self.resource_capacity {
-            drop(mem::replace(&mut self.resource, Self::create_resource(device, capacity)?));
+            let resource =
+                mem::replace(&mut self.resource, Self::create_resource(device, capacity)?);
+            retired.retire_object(&resource, fence_value);
             self.resource_capacity = capacity;
         }
 
@@ -647,6 +1434,11 @@ struct Texture {
     gpu_desc: D3D12_GPU_DESCRIPTOR_HANDLE,
     width: u32,
     height: u32,
+    // Only textures loaded through the engine can be evicted: registered
+    // textures belong to the caller.
+    evictable: bool,
+    resident: bool,
+    last_used: u64,
 }
 
 struct TextureHeap {
@@ -654,6 +1446,13 @@ struct TextureHeap {
     srv_heap: ID3D12DescriptorHeap,
     srv_staging_heap: ID3D12DescriptorHeap,
     textures: Vec<Texture>,
+    frame: u64,
+    // Scratch buffer for the textures drawn in a frame, kept to avoid allocating
+    // every frame.
+    used: Vec<usize>,
+    // Descriptor heaps replaced since the last frame, which the GPU may still
+    // reference.
+    retired: Vec<ID3D12Pageable>,
     command_queue: ID3D12CommandQueue,
     command_allocator: ID3D12CommandAllocator,
     command_list: ID3D12GraphicsCommandList,
@@ -680,8 +1479,8 @@ impl TextureHeap {
 
         unsafe {
             command_list.Close()?;
-            command_allocator.SetName(w!("hudhook Render Engine Command Allocator"))?;
-            command_list.SetName(w!("hudhook Render Engine Command List"))?;
+            command_allocator.SetName(&names::debug_object("Texture Upload Command Allocator"))?;
+            command_list.SetName(&names::debug_object("Texture Upload Command List"))?;
         }
 
         let srv_staging_heap: ID3D12DescriptorHeap = unsafe {
@@ -692,14 +1491,19 @@ impl TextureHeap {
                 NodeMask: 0,
             })
         }?;
+        unsafe { srv_staging_heap.SetName(&names::debug_object("Texture Staging Heap"))? };
 
         let fence = Fence::new(device)?;
+        unsafe { fence.fence().SetName(&names::debug_object("Texture Upload Fence"))? };
 
         Ok(Self {
             device: device.clone(),
             srv_heap,
             srv_staging_heap,
             textures: Vec::new(),
+            frame: 0,
+            used: Vec::new(),
+            retired: Vec::new(),
             command_queue,
             command_allocator,
             command_list,
@@ -719,6 +1523,8 @@ impl TextureHeap {
             let srv_heap: ID3D12DescriptorHeap = self.device.CreateDescriptorHeap(&desc)?;
             let srv_staging_heap: ID3D12DescriptorHeap =
                 self.device.CreateDescriptorHeap(&desc_staging)?;
+            srv_heap.SetName(&names::debug_object("Texture Heap"))?;
+            srv_staging_heap.SetName(&names::debug_object("Texture Staging Heap"))?;
             self.device.CopyDescriptorsSimple(
                 old_num_descriptors,
                 srv_staging_heap.GetCPUDescriptorHandleForHeapStart(),
@@ -731,7 +1537,8 @@ impl TextureHeap {
                 srv_staging_heap.GetCPUDescriptorHandleForHeapStart(),
                 D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
             );
-            self.srv_heap = srv_heap;
+            let srv_heap = mem::replace(&mut self.srv_heap, srv_heap);
+            self.retired.push(srv_heap.cast()?);
             self.srv_staging_heap = srv_staging_heap;
 
             // Adjust texture GPU pointers.
@@ -751,28 +1558,6 @@ impl TextureHeap {
     }
 
     unsafe fn create_texture(&mut self, width: u32, height: u32) -> Result<TextureId> {
-        self.resize_heap()?;
-
-        let cpu_heap_stg_start = self.srv_staging_heap.GetCPUDescriptorHandleForHeapStart();
-        let cpu_heap_start = self.srv_heap.GetCPUDescriptorHandleForHeapStart();
-        let gpu_heap_start = self.srv_heap.GetGPUDescriptorHandleForHeapStart();
-        let heap_inc_size =
-            self.device.GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV);
-
-        let texture_index = self.textures.len() as u32;
-
-        let cpu_desc_stg = D3D12_CPU_DESCRIPTOR_HANDLE {
-            ptr: cpu_heap_stg_start.ptr + (texture_index * heap_inc_size) as usize,
-        };
-
-        let cpu_desc = D3D12_CPU_DESCRIPTOR_HANDLE {
-            ptr: cpu_heap_start.ptr + (texture_index * heap_inc_size) as usize,
-        };
-
-        let gpu_desc = D3D12_GPU_DESCRIPTOR_HANDLE {
-            ptr: gpu_heap_start.ptr + (texture_index * heap_inc_size) as u64,
-        };
-
         let texture: ID3D12Resource = util::try_out_ptr(|v| unsafe {
             self.device.CreateCommittedResource(
                 &D3D12_HEAP_PROPERTIES {
@@ -800,11 +1585,47 @@ impl TextureHeap {
                 v,
             )
         })?;
+        texture.SetName(&names::debug_object("Texture"))?;
+
+        let texture_id = self.insert_texture(texture, DXGI_FORMAT_R8G8B8A8_UNORM, width, height)?;
+        self.textures[texture_id.id()].evictable = true;
+        Ok(texture_id)
+    }
+
+    // Allocate a descriptor for the texture and create its shader resource view.
+    unsafe fn insert_texture(
+        &mut self,
+        texture: ID3D12Resource,
+        format: DXGI_FORMAT,
+        width: u32,
+        height: u32,
+    ) -> Result<TextureId> {
+        self.resize_heap()?;
+
+        let cpu_heap_stg_start = self.srv_staging_heap.GetCPUDescriptorHandleForHeapStart();
+        let cpu_heap_start = self.srv_heap.GetCPUDescriptorHandleForHeapStart();
+        let gpu_heap_start = self.srv_heap.GetGPUDescriptorHandleForHeapStart();
+        let heap_inc_size =
+            self.device.GetDescriptorHandleIncrementSize(D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV);
+
+        let texture_index = self.textures.len() as u32;
+
+        let cpu_desc_stg = D3D12_CPU_DESCRIPTOR_HANDLE {
+            ptr: cpu_heap_stg_start.ptr + (texture_index * heap_inc_size) as usize,
+        };
+
+        let cpu_desc = D3D12_CPU_DESCRIPTOR_HANDLE {
+            ptr: cpu_heap_start.ptr + (texture_index * heap_inc_size) as usize,
+        };
+
+        let gpu_desc = D3D12_GPU_DESCRIPTOR_HANDLE {
+            ptr: gpu_heap_start.ptr + (texture_index * heap_inc_size) as u64,
+        };
 
         self.device.CreateShaderResourceView(
             &texture,
             Some(&D3D12_SHADER_RESOURCE_VIEW_DESC {
-                Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                Format: format,
                 ViewDimension: D3D12_SRV_DIMENSION_TEXTURE2D,
                 Shader4ComponentMapping: D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
                 Anonymous: D3D12_SHADER_RESOURCE_VIEW_DESC_0 {
@@ -827,7 +1648,15 @@ impl TextureHeap {
         );
 
         let id = TextureId::from(self.textures.len());
-        self.textures.push(Texture { resource: texture.clone(), gpu_desc, width, height });
+        self.textures.push(Texture {
+            resource: texture,
+            gpu_desc,
+            width,
+            height,
+            evictable: false,
+            resident: true,
+            last_used: self.frame,
+        });
 
         Ok(id)
     }
@@ -839,6 +1668,8 @@ impl TextureHeap {
         width: u32,
         height: u32,
     ) -> Result<()> {
+        self.make_resident(&[texture_id.id()])?;
+
         let texture = &self.textures[texture_id.id()];
         if texture.width != width || texture.height != height {
             error!(
@@ -880,6 +1711,7 @@ impl TextureHeap {
                 v,
             )
         })?;
+        upload_buffer.SetName(&names::debug_object("Texture Upload Buffer"))?;
 
         let mut upload_buffer_ptr = ptr::null_mut();
         upload_buffer.Map(0, None, Some(&mut upload_buffer_ptr))?;
@@ -945,4 +1777,94 @@ impl TextureHeap {
 
         Ok(())
     }
+
+    // Mark the textures drawn this frame as used, making evicted ones resident
+    // again before they are sampled.
+    unsafe fn begin_frame(&mut self, draw_data: &DrawData) -> Result<()> {
+        self.frame += 1;
+
+        let mut used = mem::take(&mut self.used);
+        used.clear();
+        for cl in draw_data.draw_lists() {
+            for cmd in cl.commands() {
+                if let DrawCmd::Elements { cmd_params, .. } = cmd {
+                    used.push(cmd_params.texture_id.id());
+                }
+            }
+        }
+        used.sort_unstable();
+        used.dedup();
+
+        for &index in &used {
+            if let Some(texture) = self.textures.get_mut(index) {
+                texture.last_used = self.frame;
+            }
+        }
+
+        let res = self.make_resident(&used);
+        self.used = used;
+        res
+    }
+
+    unsafe fn make_resident(&mut self, indices: &[usize]) -> Result<()> {
+        let mut evicted = Vec::new();
+        for &index in indices {
+            if let Some(texture) = self.textures.get_mut(index).filter(|t| !t.resident) {
+                texture.resident = true;
+                evicted.push(texture.resource.cast::<ID3D12Pageable>().ok());
+            }
+        }
+
+        if evicted.is_empty() {
+            return Ok(());
+        }
+
+        self.device.MakeResident(&evicted)
+    }
+
+    // Evict the least recently used textures that none of the last
+    // `frames_in_flight` frames drew, until about `excess` bytes are freed.
+    // Their texture ids stay valid, and they are made resident again as soon
+    // as they are drawn.
+    unsafe fn evict(&mut self, excess: u64, frames_in_flight: u64) {
+        let mut candidates: Vec<_> = self
+            .textures
+            .iter()
+            .enumerate()
+            .filter(|(_, texture)| {
+                texture.evictable
+                    && texture.resident
+                    && texture.last_used + frames_in_flight <= self.frame
+            })
+            .map(|(index, texture)| (texture.last_used, index))
+            .collect();
+        candidates.sort_unstable();
+
+        let mut freed = 0u64;
+        let mut indices = Vec::new();
+        for (_, index) in candidates {
+            if freed >= excess {
+                break;
+            }
+
+            let desc = self.textures[index].resource.GetDesc();
+            freed += self.device.GetResourceAllocationInfo(0, &[desc]).SizeInBytes;
+            indices.push(index);
+        }
+
+        if indices.is_empty() {
+            return;
+        }
+
+        let evicted: Vec<_> = indices
+            .iter()
+            .map(|&index| self.textures[index].resource.cast::<ID3D12Pageable>().ok())
+            .collect();
+
+        trace!("Evicting {} textures ({freed} bytes) to get back within the budget", indices.len());
+        match self.device.Evict(&evicted) {
+            Ok(()) => indices.into_iter().for_each(|index| self.textures[index].resident = false),
+            Err(e) => error!("Could not evict textures: {e:?}"),
+        }
+    }
 }
@@ -11,7 +11,7 @@ use windows::Win32::Foundation::RECT;
 use windows::Win32::Graphics::Direct3D9::*;
 
 use crate::renderer::RenderEngine;
-use crate::{util, RenderContext};
+use crate::{names, util, ExternalTexture, RenderContext};
 
 const D3DFVF_CUSTOMVERTEX: u32 = D3DFVF_XYZ | D3DFVF_DIFFUSE | D3DFVF_TEX1;
 const MAT_IDENTITY: Matrix4x4 = Matrix4x4 {
@@ -40,6 +40,7 @@ struct CustomVertex {
     uv: [f32; 2],
 }
 
+/// Render engine for DirectX 9 devices.
 pub struct D3D9RenderEngine {
     device: IDirect3DDevice9,
 
@@ -51,6 +52,10 @@ pub struct D3D9RenderEngine {
 }
 
 impl D3D9RenderEngine {
+    /// Create a render engine drawing with `device`, and set up `ctx` for it.
+    ///
+    /// The render target is a surface of `device`, e.g. its back buffer. The
+    /// device state, render target included, is restored after rendering.
     pub fn new(device: &IDirect3DDevice9, ctx: &mut Context) -> Result<Self> {
         let device = device.clone();
 
@@ -62,7 +67,7 @@ impl D3D9RenderEngine {
 
         ctx.set_ini_filename(None);
         ctx.io_mut().backend_flags |= BackendFlags::RENDERER_HAS_VTX_OFFSET;
-        ctx.set_renderer_name(String::from(concat!("hudhook-dx9@", env!("CARGO_PKG_VERSION"))));
+        ctx.set_renderer_name(names::renderer("dx9"));
 
         Ok(Self { device, texture_heap, vertex_buffer, index_buffer, projection_buffer })
     }
@@ -86,6 +91,30 @@ impl RenderContext for D3D9RenderEngine {
     ) -> Result<()> {
         unsafe { self.texture_heap.upload_texture(texture_id, data, width, height) }
     }
+
+    unsafe fn register_texture(&mut self, texture: ExternalTexture) -> Result<TextureId> {
+        let resource = match texture {
+            ExternalTexture::Dx9(resource) => resource,
+            #[allow(unreachable_patterns)]
+            texture => {
+                error!("Texture {texture:?} can't be registered on a DirectX 9 engine");
+                return Err(Error::from_hresult(HRESULT(-1)));
+            },
+        };
+
+        let mut desc = Default::default();
+        resource.GetLevelDesc(0, &mut desc)?;
+
+        let id = TextureId::from(self.texture_heap.textures.len());
+        self.texture_heap.textures.push(Texture {
+            resource,
+            id,
+            width: desc.Width,
+            height: desc.Height,
+        });
+
+        Ok(id)
+    }
 }
 
 impl RenderEngine for D3D9RenderEngine {
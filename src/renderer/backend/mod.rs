@@ -6,3 +6,5 @@ pub mod dx12;
 pub mod dx9;
 #[cfg(feature = "opengl3")]
 pub mod opengl3;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
@@ -14,7 +14,7 @@ use windows::Win32::Graphics::OpenGL::*;
 use windows::Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryA};
 
 use crate::renderer::RenderEngine;
-use crate::{util, RenderContext};
+use crate::{names, util, ExternalTexture, RenderContext};
 
 mod gl {
     #![allow(
@@ -46,6 +46,7 @@ unsafe fn load_func(function_string: CString) -> *const c_void {
     }
 }
 
+/// Render engine for OpenGL 3 contexts.
 pub struct OpenGl3RenderEngine {
     gl: gl::Gl,
     program: GLuint,
@@ -66,6 +67,10 @@ pub struct OpenGl3RenderEngine {
 }
 
 impl OpenGl3RenderEngine {
+    /// Create a render engine drawing with the OpenGL context current on the
+    /// calling thread, and set up `ctx` for it.
+    ///
+    /// The engine draws into the bound framebuffer; it has no render target.
     pub fn new(ctx: &mut Context) -> Result<Self> {
         let gl = gl::Gl::load_with(|s| unsafe { load_func(CString::new(s).unwrap()) });
 
@@ -81,7 +86,7 @@ impl OpenGl3RenderEngine {
         let texture_heap = TextureHeap::new();
 
         ctx.set_ini_filename(None);
-        ctx.set_renderer_name(String::from(concat!("hudhook-opengl3@", env!("CARGO_PKG_VERSION"))));
+        ctx.set_renderer_name(names::renderer("opengl3"));
 
         Ok(Self {
             gl,
@@ -114,6 +119,39 @@ impl RenderContext for OpenGl3RenderEngine {
     ) -> Result<()> {
         unsafe { self.texture_heap.update_texture(&self.gl, texture_id, data, width, height) }
     }
+
+    unsafe fn register_texture(&mut self, texture: ExternalTexture) -> Result<TextureId> {
+        let gl_texture = match texture {
+            ExternalTexture::OpenGl3(gl_texture) => gl_texture,
+            #[allow(unreachable_patterns)]
+            texture => {
+                error!("Texture {texture:?} can't be registered on an OpenGL 3 engine");
+                return Err(Error::from_hresult(HRESULT(-1)));
+            },
+        };
+
+        let gl = &self.gl;
+
+        let mut bound_texture = 0;
+        gl.GetIntegerv(gl::TEXTURE_BINDING_2D, &mut bound_texture);
+
+        gl.BindTexture(gl::TEXTURE_2D, gl_texture);
+        let width: GLint =
+            util::out_param(|x| gl.GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_WIDTH, x));
+        let height: GLint = util::out_param(|x| {
+            gl.GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_HEIGHT, x)
+        });
+        gl.BindTexture(gl::TEXTURE_2D, bound_texture as _);
+
+        let id = TextureId::from(self.texture_heap.textures.len());
+        self.texture_heap.textures.push(Texture {
+            gl_texture,
+            width: width as u32,
+            height: height as u32,
+        });
+
+        Ok(id)
+    }
 }
 
 impl RenderEngine for OpenGl3RenderEngine {
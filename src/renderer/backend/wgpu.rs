@@ -0,0 +1,569 @@
+// Based on https://github.com/Yatekii/imgui-wgpu-rs
+
+use std::mem::{self, size_of_val};
+use std::slice;
+use std::sync::Arc;
+
+use imgui::internal::RawWrapper;
+use imgui::{BackendFlags, Context, DrawCmd, DrawData, DrawIdx, DrawVert, TextureId};
+use tracing::error;
+use windows::core::{Error, Result, HRESULT};
+
+use crate::renderer::RenderEngine;
+use crate::{names, ExternalTexture, RenderContext};
+
+const SHADER: &str = r#"
+struct Uniforms {
+    projection: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+@group(1) @binding(0)
+var tex: texture_2d<f32>;
+@group(1) @binding(1)
+var tex_sampler: sampler;
+
+struct VertexInput {
+    @location(0) pos: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) col: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) col: vec4<f32>,
+};
+
+@vertex
+fn vs_main(input: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    output.pos = uniforms.projection * vec4<f32>(input.pos, 0.0, 1.0);
+    output.uv = input.uv;
+    output.col = input.col;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return input.col * textureSample(tex, tex_sampler, input.uv);
+}
+"#;
+
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+    wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4];
+
+const INDEX_FORMAT: wgpu::IndexFormat = if mem::size_of::<DrawIdx>() == 2 {
+    wgpu::IndexFormat::Uint16
+} else {
+    wgpu::IndexFormat::Uint32
+};
+
+/// Render engine for `wgpu` devices.
+///
+/// Unlike the other engines, it isn't used by the hooks: it doesn't wrap the
+/// device and swap chain buffers of a hooked game through `wgpu-hal`
+/// interop. Use it to render `imgui` in an application that owns a `wgpu`
+/// device, e.g. on a window or surface of its own; see
+/// [`engine`](crate::engine).
+pub struct WgpuRenderEngine {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    pipeline: wgpu::RenderPipeline,
+
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    texture_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+
+    vertex_buffer: Option<wgpu::Buffer>,
+    index_buffer: Option<wgpu::Buffer>,
+
+    texture_heap: TextureHeap,
+}
+
+impl WgpuRenderEngine {
+    /// Create a render engine drawing with `device` into render targets of
+    /// `format`, and set up `ctx` for it.
+    ///
+    /// Colors are written as `imgui` outputs them: render into a non-sRGB
+    /// format to get the same colors as the other engines.
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        format: wgpu::TextureFormat,
+        ctx: &mut Context,
+    ) -> Result<Self> {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("hudhook imgui shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("hudhook imgui uniform layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("hudhook imgui texture layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hudhook imgui uniform buffer"),
+            size: mem::size_of::<[[f32; 4]; 4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("hudhook imgui uniform bind group"),
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("hudhook imgui sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("hudhook imgui pipeline layout"),
+            bind_group_layouts: &[&uniform_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("hudhook imgui pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: mem::size_of::<DrawVert>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &VERTEX_ATTRIBUTES,
+                }],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        ctx.set_ini_filename(None);
+        ctx.io_mut().backend_flags |= BackendFlags::RENDERER_HAS_VTX_OFFSET;
+        ctx.set_renderer_name(names::renderer("wgpu"));
+
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+            texture_layout,
+            sampler,
+            vertex_buffer: None,
+            index_buffer: None,
+            texture_heap: TextureHeap::new(),
+        })
+    }
+
+    fn bind_group(&self, view: &wgpu::TextureView) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("hudhook imgui texture bind group"),
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+
+    fn write_texture(&self, texture: &wgpu::Texture, data: &[u8], width: u32, height: u32) {
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+    }
+}
+
+impl RenderContext for WgpuRenderEngine {
+    fn load_texture(&mut self, data: &[u8], width: u32, height: u32) -> Result<TextureId> {
+        check_size(data, width, height)?;
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hudhook imgui texture"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.write_texture(&texture, data, width, height);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = self.bind_group(&view);
+
+        let id = TextureId::from(self.texture_heap.textures.len());
+        self.texture_heap.textures.push(Texture { texture: Some(texture), bind_group });
+
+        Ok(id)
+    }
+
+    fn replace_texture(
+        &mut self,
+        texture_id: TextureId,
+        data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        check_size(data, width, height)?;
+
+        let Some(texture) = &self.texture_heap.get(texture_id)?.texture else {
+            error!("Texture {texture_id:?} was registered and can't be replaced");
+            return Err(Error::from_hresult(HRESULT(-1)));
+        };
+
+        if texture.width() != width || texture.height() != height {
+            error!(
+                "image size {width}x{height} do not match expected {}x{}",
+                texture.width(),
+                texture.height()
+            );
+            return Err(Error::from_hresult(HRESULT(-1)));
+        }
+
+        self.write_texture(texture, data, width, height);
+
+        Ok(())
+    }
+
+    unsafe fn register_texture(&mut self, texture: ExternalTexture) -> Result<TextureId> {
+        let view = match texture {
+            ExternalTexture::Wgpu(view) => view,
+            #[allow(unreachable_patterns)]
+            texture => {
+                error!("Texture {texture:?} can't be registered on a wgpu engine");
+                return Err(Error::from_hresult(HRESULT(-1)));
+            },
+        };
+
+        let bind_group = self.bind_group(&view);
+
+        let id = TextureId::from(self.texture_heap.textures.len());
+        self.texture_heap.textures.push(Texture { texture: None, bind_group });
+
+        Ok(id)
+    }
+}
+
+impl RenderEngine for WgpuRenderEngine {
+    type RenderTarget = Arc<wgpu::TextureView>;
+
+    fn render(&mut self, draw_data: &DrawData, render_target: Self::RenderTarget) -> Result<()> {
+        let fb_size = [
+            draw_data.display_size[0] * draw_data.framebuffer_scale[0],
+            draw_data.display_size[1] * draw_data.framebuffer_scale[1],
+        ];
+        if fb_size[0] <= 0. || fb_size[1] <= 0. || draw_data.total_vtx_count == 0 {
+            return Ok(());
+        }
+
+        self.queue.write_buffer(&self.uniform_buffer, 0, as_bytes(&projection(draw_data)));
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for cl in draw_data.draw_lists() {
+            vertices.extend_from_slice(as_bytes(cl.vtx_buffer()));
+            indices.extend_from_slice(as_bytes(cl.idx_buffer()));
+        }
+        upload(
+            &self.device,
+            &self.queue,
+            &mut self.vertex_buffer,
+            wgpu::BufferUsages::VERTEX,
+            &mut vertices,
+        );
+        upload(
+            &self.device,
+            &self.queue,
+            &mut self.index_buffer,
+            wgpu::BufferUsages::INDEX,
+            &mut indices,
+        );
+
+        let (Some(vertex_buffer), Some(index_buffer)) = (&self.vertex_buffer, &self.index_buffer)
+        else {
+            return Ok(());
+        };
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("hudhook imgui encoder"),
+        });
+
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("hudhook imgui pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &render_target,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.setup_render_state(&mut pass, vertex_buffer, index_buffer, fb_size);
+            self.render_draw_data(&mut pass, draw_data, vertex_buffer, index_buffer, fb_size);
+        }
+
+        self.queue.submit(Some(encoder.finish()));
+
+        Ok(())
+    }
+
+    fn setup_fonts(&mut self, ctx: &mut Context) -> Result<()> {
+        let fonts = ctx.fonts();
+        let fonts_texture = fonts.build_rgba32_texture();
+        fonts.tex_id =
+            self.load_texture(fonts_texture.data, fonts_texture.width, fonts_texture.height)?;
+        Ok(())
+    }
+}
+
+impl WgpuRenderEngine {
+    fn render_draw_data<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        draw_data: &DrawData,
+        vertex_buffer: &'a wgpu::Buffer,
+        index_buffer: &'a wgpu::Buffer,
+        fb_size: [f32; 2],
+    ) {
+        let [clip_offset_x, clip_offset_y] = draw_data.display_pos;
+        let [clip_scale_w, clip_scale_h] = draw_data.framebuffer_scale;
+
+        let mut vtx_offset = 0usize;
+        let mut idx_offset = 0usize;
+
+        for cl in draw_data.draw_lists() {
+            for cmd in cl.commands() {
+                match cmd {
+                    DrawCmd::Elements { count, cmd_params } => {
+                        let [cx, cy, cz, cw] = cmd_params.clip_rect;
+
+                        let clip_min_x = ((cx - clip_offset_x) * clip_scale_w).max(0.);
+                        let clip_min_y = ((cy - clip_offset_y) * clip_scale_h).max(0.);
+                        let clip_max_x = ((cz - clip_offset_x) * clip_scale_w).min(fb_size[0]);
+                        let clip_max_y = ((cw - clip_offset_y) * clip_scale_h).min(fb_size[1]);
+
+                        if clip_max_x <= clip_min_x || clip_max_y <= clip_min_y {
+                            continue;
+                        }
+
+                        // Skip the draws of unknown textures rather than the whole frame.
+                        let Ok(texture) = self.texture_heap.get(cmd_params.texture_id) else {
+                            continue;
+                        };
+
+                        pass.set_scissor_rect(
+                            clip_min_x as u32,
+                            clip_min_y as u32,
+                            (clip_max_x - clip_min_x) as u32,
+                            (clip_max_y - clip_min_y) as u32,
+                        );
+                        pass.set_bind_group(1, &texture.bind_group, &[]);
+
+                        let start = (idx_offset + cmd_params.idx_offset) as u32;
+                        pass.draw_indexed(
+                            start..start + count as u32,
+                            (vtx_offset + cmd_params.vtx_offset) as i32,
+                            0..1,
+                        );
+                    },
+                    DrawCmd::ResetRenderState => {
+                        self.setup_render_state(pass, vertex_buffer, index_buffer, fb_size);
+                    },
+                    DrawCmd::RawCallback { callback, raw_cmd } => unsafe {
+                        callback(cl.raw(), raw_cmd)
+                    },
+                }
+            }
+
+            vtx_offset += cl.vtx_buffer().len();
+            idx_offset += cl.idx_buffer().len();
+        }
+    }
+
+    fn setup_render_state<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        vertex_buffer: &'a wgpu::Buffer,
+        index_buffer: &'a wgpu::Buffer,
+        fb_size: [f32; 2],
+    ) {
+        pass.set_viewport(0., 0., fb_size[0], fb_size[1], 0., 1.);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        pass.set_index_buffer(index_buffer.slice(..), INDEX_FORMAT);
+    }
+}
+
+fn projection(draw_data: &DrawData) -> [[f32; 4]; 4] {
+    let [l, t, r, b] = [
+        draw_data.display_pos[0],
+        draw_data.display_pos[1],
+        draw_data.display_pos[0] + draw_data.display_size[0],
+        draw_data.display_pos[1] + draw_data.display_size[1],
+    ];
+
+    [[2. / (r - l), 0., 0., 0.], [0., 2. / (t - b), 0., 0.], [0., 0., 0.5, 0.], [
+        (r + l) / (l - r),
+        (t + b) / (b - t),
+        0.5,
+        1.0,
+    ]]
+}
+
+fn check_size(data: &[u8], width: u32, height: u32) -> Result<()> {
+    if data.len() != (width * height * 4) as usize {
+        error!("image data of {} bytes isn't a {width}x{height} RGBA image", data.len());
+        return Err(Error::from_hresult(HRESULT(-1)));
+    }
+    Ok(())
+}
+
+fn as_bytes<T>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, size_of_val(data)) }
+}
+
+// Write `data` to `buffer`, growing it if needed. Buffer copies must be a
+// multiple of 4 bytes, so `data` is padded.
+fn upload(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &mut Option<wgpu::Buffer>,
+    usage: wgpu::BufferUsages,
+    data: &mut Vec<u8>,
+) {
+    let len = data.len().next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT as usize);
+    data.resize(len, 0);
+
+    if buffer.as_ref().map_or(true, |buffer| buffer.size() < len as u64) {
+        *buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hudhook imgui buffer"),
+            size: len.max(4).next_power_of_two() as u64,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+    }
+
+    if let Some(buffer) = buffer {
+        queue.write_buffer(buffer, 0, data);
+    }
+}
+
+struct TextureHeap {
+    textures: Vec<Texture>,
+}
+
+struct Texture {
+    // `None` for the textures registered with `register_texture`.
+    texture: Option<wgpu::Texture>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TextureHeap {
+    fn new() -> Self {
+        Self { textures: Vec::new() }
+    }
+
+    fn get(&self, texture_id: TextureId) -> Result<&Texture> {
+        self.textures.get(texture_id.id()).ok_or_else(|| {
+            error!("Texture {texture_id:?} does not exist");
+            Error::from_hresult(HRESULT(-1))
+        })
+    }
+}
@@ -0,0 +1,267 @@
+//! This module tracks whether the game window is fullscreen, active or
+//! minimized.
+//!
+//! Alt-tabbing out of an exclusive fullscreen game minimizes its window and
+//! changes the display mode back. The overlay must not block the messages
+//! notifying the game and DXGI of those changes, must release the keys held
+//! when focus was lost, and must not render while minimized.
+//!
+//! It also makes game windows borderless on demand, see [`set_borderless`].
+
+use std::mem;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::OnceLock;
+
+use parking_lot::Mutex;
+use tracing::error;
+use windows::core::{Error, Result, HSTRING};
+use windows::Win32::Foundation::{E_INVALIDARG, HWND, LPARAM, RECT, WPARAM};
+use windows::Win32::Graphics::Gdi::{
+    GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetWindowLongPtrW, GetWindowRect, IsIconic, PostMessageW, RegisterWindowMessageW,
+    SetWindowLongPtrW, SetWindowPos, GWL_EXSTYLE, GWL_STYLE, SIZE_MINIMIZED, SWP_FRAMECHANGED,
+    SWP_NOOWNERZORDER, SWP_NOZORDER, WM_ACTIVATE, WM_ACTIVATEAPP, WM_DISPLAYCHANGE, WM_SIZE,
+    WM_WINDOWPOSCHANGED, WS_BORDER, WS_DLGFRAME, WS_EX_CLIENTEDGE, WS_EX_DLGMODALFRAME,
+    WS_EX_STATICEDGE, WS_EX_WINDOWEDGE, WS_OVERLAPPEDWINDOW, WS_POPUP,
+};
+
+use crate::names;
+use crate::renderer::pipeline;
+
+// Styles and extended styles drawing a window frame.
+const FRAME_STYLES: isize = (WS_OVERLAPPEDWINDOW.0 | WS_BORDER.0 | WS_DLGFRAME.0) as isize;
+const FRAME_EX_STYLES: isize =
+    (WS_EX_DLGMODALFRAME.0 | WS_EX_CLIENTEDGE.0 | WS_EX_STATICEDGE.0 | WS_EX_WINDOWEDGE.0) as isize;
+
+// Frames of the windows made borderless, restored by `set_borderless`.
+static SAVED_FRAMES: Mutex<Vec<(isize, SavedFrame)>> = Mutex::new(Vec::new());
+// The window whose frame is being changed. All its messages reach it meanwhile.
+static REFRAMING: AtomicIsize = AtomicIsize::new(0);
+
+#[derive(Clone, Copy)]
+struct SavedFrame {
+    style: isize,
+    ex_style: isize,
+    rect: RECT,
+}
+
+/// State of a hooked game window, tracked from the messages it receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowState {
+    /// The window is active and doesn't cover its monitor.
+    Windowed,
+    /// The window is active and covers its monitor, in exclusive or
+    /// borderless fullscreen.
+    Fullscreen,
+    /// Another application is active, e.g. after alt-tabbing out.
+    Inactive {
+        /// Whether the window covers its monitor.
+        fullscreen: bool,
+    },
+    /// The window is minimized, as exclusive fullscreen games are when they
+    /// lose focus. The overlay doesn't render meanwhile.
+    Minimized {
+        /// Whether the window covered its monitor before it was minimized.
+        fullscreen: bool,
+    },
+}
+
+impl WindowState {
+    // The state of `hwnd`, as measured right now, assuming it is active. A
+    // minimized window keeps the fullscreen state it had before.
+    pub(crate) fn measure(hwnd: HWND, fullscreen: bool) -> Self {
+        if unsafe { IsIconic(hwnd) }.as_bool() {
+            Self::Minimized { fullscreen }
+        } else if covers_monitor(hwnd) {
+            Self::Fullscreen
+        } else {
+            Self::Windowed
+        }
+    }
+
+    /// Whether the window covers its monitor.
+    pub fn is_fullscreen(&self) -> bool {
+        matches!(
+            self,
+            Self::Fullscreen
+                | Self::Inactive { fullscreen: true }
+                | Self::Minimized { fullscreen: true }
+        )
+    }
+
+    // The state after `hwnd` received `msg`.
+    pub(crate) fn next(self, hwnd: HWND, msg: u32, wparam: WPARAM) -> Self {
+        let fullscreen = self.is_fullscreen();
+
+        match msg {
+            WM_ACTIVATEAPP if wparam.0 == 0 => match self {
+                Self::Minimized { .. } => self,
+                _ => Self::Inactive { fullscreen },
+            },
+            WM_ACTIVATEAPP => Self::measure(hwnd, fullscreen),
+            WM_SIZE if wparam.0 as u32 == SIZE_MINIMIZED => Self::Minimized { fullscreen },
+            WM_SIZE | WM_DISPLAYCHANGE => match self {
+                Self::Inactive { .. } => Self::Inactive { fullscreen: covers_monitor(hwnd) },
+                _ => Self::measure(hwnd, fullscreen),
+            },
+            _ => self,
+        }
+    }
+
+    // Whether `msg` must reach the window regardless of the message filter.
+    // Fullscreen games, and DXGI on their behalf, leave and restore the display
+    // mode on these messages.
+    pub(crate) fn must_pass(&self, msg: u32) -> bool {
+        self.is_fullscreen()
+            && matches!(
+                msg,
+                WM_ACTIVATEAPP | WM_ACTIVATE | WM_SIZE | WM_WINDOWPOSCHANGED | WM_DISPLAYCHANGE
+            )
+    }
+}
+
+/// The state of `hwnd`, if the overlay renders on it.
+pub fn window_state(hwnd: HWND) -> Option<WindowState> {
+    pipeline::window_state(hwnd)
+}
+
+/// Make `hwnd` a borderless window covering its monitor, or give it back the
+/// frame, position and size it had. A popular fix for older games without a
+/// borderless fullscreen mode.
+///
+/// The window is changed by its own thread, from the window procedure of the
+/// overlay, the next time it handles its messages: changing it from another
+/// thread could deadlock with the game. The messages the change causes reach
+/// the game regardless of the [message filter](crate::MessageFilter), so the
+/// game resizes its swap chain as if the user resized the window.
+///
+/// Returns an error if the overlay doesn't render on `hwnd`. Games in
+/// exclusive fullscreen must be switched to windowed mode first. The window
+/// stays borderless after [`eject`](crate::eject).
+pub fn set_borderless(hwnd: HWND, borderless: bool) -> Result<()> {
+    if pipeline::window_state(hwnd).is_none() {
+        return Err(Error::from_hresult(E_INVALIDARG));
+    }
+
+    unsafe { PostMessageW(hwnd, reframe_message(), WPARAM(borderless as usize), LPARAM(0)) }
+}
+
+/// Whether `hwnd` was made borderless with [`set_borderless`].
+pub fn is_borderless(hwnd: HWND) -> bool {
+    SAVED_FRAMES.lock().iter().any(|(h, _)| *h == hwnd.0)
+}
+
+// The message asking the window procedure of the overlay to change the frame
+// of its window. `wparam` is whether to make it borderless.
+pub(crate) fn reframe_message() -> u32 {
+    static MESSAGE: OnceLock<u32> = OnceLock::new();
+
+    *MESSAGE.get_or_init(|| {
+        let name = HSTRING::from(format!("{}-reframe", names::prefix()));
+        unsafe { RegisterWindowMessageW(&name) }
+    })
+}
+
+// Whether the frame of `hwnd` is being changed, in which case all of its
+// messages must reach it.
+pub(crate) fn is_reframing(hwnd: HWND) -> bool {
+    REFRAMING.load(Ordering::SeqCst) == hwnd.0
+}
+
+// Change the frame of `hwnd` as asked by a `reframe_message`. Called by the
+// window procedure of the overlay, on the thread of the window.
+pub(crate) fn reframe(hwnd: HWND, borderless: bool) {
+    REFRAMING.store(hwnd.0, Ordering::SeqCst);
+    let res = unsafe {
+        if borderless {
+            remove_frame(hwnd)
+        } else {
+            restore_frame(hwnd)
+        }
+    };
+    REFRAMING.store(0, Ordering::SeqCst);
+
+    if let Err(e) = res {
+        error!("Couldn't change the frame of window {hwnd:?}: {e:?}");
+    }
+}
+
+// No lock is held while the window is changed: the game handles the messages
+// this sends, and may wait for threads calling `is_borderless` meanwhile.
+unsafe fn remove_frame(hwnd: HWND) -> Result<()> {
+    if is_borderless(hwnd) {
+        return Ok(());
+    }
+
+    let mut rect = RECT::default();
+    GetWindowRect(hwnd, &mut rect)?;
+    let style = GetWindowLongPtrW(hwnd, GWL_STYLE) as isize;
+    let ex_style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as isize;
+
+    let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+    let mut info =
+        MONITORINFO { cbSize: mem::size_of::<MONITORINFO>() as u32, ..Default::default() };
+    if !GetMonitorInfoW(monitor, &mut info).as_bool() {
+        return Err(Error::from_win32());
+    }
+    let monitor = info.rcMonitor;
+
+    SAVED_FRAMES.lock().push((hwnd.0, SavedFrame { style, ex_style, rect }));
+
+    SetWindowLongPtrW(hwnd, GWL_STYLE, ((style & !FRAME_STYLES) | WS_POPUP.0 as isize) as _);
+    SetWindowLongPtrW(hwnd, GWL_EXSTYLE, (ex_style & !FRAME_EX_STYLES) as _);
+    SetWindowPos(
+        hwnd,
+        HWND(0),
+        monitor.left,
+        monitor.top,
+        monitor.right - monitor.left,
+        monitor.bottom - monitor.top,
+        SWP_FRAMECHANGED | SWP_NOZORDER | SWP_NOOWNERZORDER,
+    )
+}
+
+unsafe fn restore_frame(hwnd: HWND) -> Result<()> {
+    let saved = {
+        let mut frames = SAVED_FRAMES.lock();
+        let Some(idx) = frames.iter().position(|(h, _)| *h == hwnd.0) else {
+            return Ok(());
+        };
+        frames.swap_remove(idx).1
+    };
+
+    let SavedFrame { style, ex_style, rect } = saved;
+    SetWindowLongPtrW(hwnd, GWL_STYLE, style as _);
+    SetWindowLongPtrW(hwnd, GWL_EXSTYLE, ex_style as _);
+    SetWindowPos(
+        hwnd,
+        HWND(0),
+        rect.left,
+        rect.top,
+        rect.right - rect.left,
+        rect.bottom - rect.top,
+        SWP_FRAMECHANGED | SWP_NOZORDER | SWP_NOOWNERZORDER,
+    )
+}
+
+// Whether `hwnd` covers the whole monitor it is on.
+fn covers_monitor(hwnd: HWND) -> bool {
+    unsafe {
+        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+        let mut info =
+            MONITORINFO { cbSize: mem::size_of::<MONITORINFO>() as u32, ..Default::default() };
+        let mut rect = RECT::default();
+        if !GetMonitorInfoW(monitor, &mut info).as_bool() || GetWindowRect(hwnd, &mut rect).is_err()
+        {
+            return false;
+        }
+
+        let monitor = info.rcMonitor;
+        rect.left <= monitor.left
+            && rect.top <= monitor.top
+            && rect.right >= monitor.right
+            && rect.bottom >= monitor.bottom
+    }
+}
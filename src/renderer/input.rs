@@ -3,7 +3,7 @@
 use std::ffi::c_void;
 use std::mem::size_of;
 
-use imgui::{Io, Key, MouseButton};
+use imgui::{Context, Io, Key, MouseButton};
 use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
 use windows::Win32::UI::Input::{
@@ -12,8 +12,10 @@ use windows::Win32::UI::Input::{
 };
 use windows::Win32::UI::WindowsAndMessaging::*;
 
-use super::keys::vk_to_imgui;
-use crate::renderer::{Pipeline, RenderEngine};
+use super::keys::{vk_to_imgui, KEYS};
+use crate::renderer::pipeline::RenderLoop;
+use crate::replay::InputEvent;
+use crate::util;
 
 pub type WndProcType =
     unsafe extern "system" fn(hwnd: HWND, umsg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT;
@@ -42,6 +44,26 @@ pub fn lowordi(l: u32) -> i16 {
     (l & 0xffff) as i16
 }
 
+// Submits translated input events to `imgui`, collecting them for the input
+// recording if needed.
+pub struct InputSink<'a> {
+    io: &'a mut Io,
+    recorded: Option<&'a mut Vec<InputEvent>>,
+}
+
+impl<'a> InputSink<'a> {
+    pub fn new(io: &'a mut Io, recorded: Option<&'a mut Vec<InputEvent>>) -> Self {
+        Self { io, recorded }
+    }
+
+    fn push(&mut self, event: InputEvent) {
+        event.apply(self.io);
+        if let Some(recorded) = self.recorded.as_deref_mut() {
+            recorded.push(event);
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Raw input
 ////////////////////////////////////////////////////////////////////////////////
@@ -49,15 +71,15 @@ pub fn lowordi(l: u32) -> i16 {
 // Handle raw mouse input events.
 //
 // Given the RAWINPUT structure, check each possible mouse flag status and
-// update the Io object accordingly. Both the key_down indices associated to the
+// submit the matching events. Both the key_down indices associated to the
 // mouse click (VK_...) and the values in mouse_down are updated.
-fn handle_raw_mouse_input(io: &mut Io, raw_mouse: &RAWMOUSE) {
+fn handle_raw_mouse_input(input: &mut InputSink, raw_mouse: &RAWMOUSE) {
     let button_data = unsafe { raw_mouse.Anonymous.Anonymous };
     let button_flags = button_data.usButtonFlags as u32;
 
     let mut event = |flag, button, state| {
         if (button_flags & flag) != 0 {
-            io.add_mouse_button_event(button, state);
+            input.push(InputEvent::MouseButton(button, state));
         }
     };
 
@@ -89,20 +111,21 @@ fn handle_raw_mouse_input(io: &mut Io, raw_mouse: &RAWMOUSE) {
         0.0
     };
 
-    io.add_mouse_wheel_event([wheel_delta_x, wheel_delta_y]);
+    input.push(InputEvent::MouseWheel([wheel_delta_x, wheel_delta_y]));
 
     let mouse_flags = raw_mouse.usFlags;
     let (last_x, last_y) = (raw_mouse.lLastX as f32, raw_mouse.lLastY as f32);
 
     if (mouse_flags.0 & MOUSE_MOVE_ABSOLUTE.0) != 0 {
-        io.add_mouse_pos_event([last_x, last_y]);
+        input.push(InputEvent::MousePos([last_x, last_y]));
     } else {
-        io.add_mouse_pos_event([io.mouse_pos[0] + last_x, io.mouse_pos[1] + last_y]);
+        let [x, y] = input.io.mouse_pos;
+        input.push(InputEvent::MousePos([x + last_x, y + last_y]));
     }
 }
 
 // Handle raw keyboard input.
-fn handle_raw_keyboard_input(io: &mut Io, raw_keyboard: &RAWKEYBOARD) {
+fn handle_raw_keyboard_input(input: &mut InputSink, raw_keyboard: &RAWKEYBOARD) {
     // Ignore messages without a valid key code
     if raw_keyboard.VKey == 0 {
         return;
@@ -144,17 +167,17 @@ fn handle_raw_keyboard_input(io: &mut Io, raw_keyboard: &RAWKEYBOARD) {
     if virtual_key < 0xFF {
         if let Some(key) = vk_to_imgui(VIRTUAL_KEY(virtual_key as _)) {
             if is_key_down {
-                io.add_key_event(key, true);
+                input.push(InputEvent::Key(key, true));
             }
             if is_key_up {
-                io.add_key_event(key, false);
+                input.push(InputEvent::Key(key, false));
             }
         }
     }
 }
 
 // Handle WM_INPUT events.
-fn handle_raw_input(io: &mut Io, WPARAM(wparam): WPARAM, LPARAM(lparam): LPARAM) {
+fn handle_raw_input(input: &mut InputSink, WPARAM(wparam): WPARAM, LPARAM(lparam): LPARAM) {
     let mut raw_data = RAWINPUT { ..Default::default() };
     let mut raw_data_size = size_of::<RAWINPUT>() as u32;
     let raw_data_header_size = size_of::<RAWINPUTHEADER>() as u32;
@@ -183,10 +206,10 @@ fn handle_raw_input(io: &mut Io, WPARAM(wparam): WPARAM, LPARAM(lparam): LPARAM)
     // Dispatch to the appropriate raw input processing method.
     match RID_DEVICE_INFO_TYPE(raw_data.header.dwType) {
         RIM_TYPEMOUSE => {
-            handle_raw_mouse_input(io, unsafe { &raw_data.data.mouse });
+            handle_raw_mouse_input(input, unsafe { &raw_data.data.mouse });
         },
         RIM_TYPEKEYBOARD => {
-            handle_raw_keyboard_input(io, unsafe { &raw_data.data.keyboard });
+            handle_raw_keyboard_input(input, unsafe { &raw_data.data.keyboard });
         },
         _ => {},
     }
@@ -196,7 +219,7 @@ fn handle_raw_input(io: &mut Io, WPARAM(wparam): WPARAM, LPARAM(lparam): LPARAM)
 // Regular input
 ////////////////////////////////////////////////////////////////////////////////
 
-fn map_vkey(wparam: u16, lparam: usize) -> VIRTUAL_KEY {
+pub(crate) fn map_vkey(wparam: u16, lparam: usize) -> VIRTUAL_KEY {
     match VIRTUAL_KEY(wparam) {
         VK_SHIFT => unsafe {
             match MapVirtualKeyW(((lparam & 0x00ff0000) >> 16) as u32, MAPVK_VSC_TO_VK_EX) {
@@ -227,71 +250,93 @@ fn is_vk_down(vk: VIRTUAL_KEY) -> bool {
 }
 
 // Handle WM_(SYS)KEYDOWN/WM_(SYS)KEYUP events.
-fn handle_input(io: &mut Io, state: u32, WPARAM(wparam): WPARAM, LPARAM(lparam): LPARAM) {
+fn handle_input(input: &mut InputSink, state: u32, WPARAM(wparam): WPARAM, LPARAM(lparam): LPARAM) {
     let is_key_down = (state == WM_KEYDOWN) || (state == WM_SYSKEYDOWN);
     let scancode = map_vkey(wparam as _, lparam as _);
 
     if let Some(key) = vk_to_imgui(scancode) {
-        io.add_key_event(key, is_key_down);
+        input.push(InputEvent::Key(key, is_key_down));
     }
 
-    io.add_key_event(Key::ModCtrl, is_vk_down(VK_CONTROL));
-    io.add_key_event(Key::ModShift, is_vk_down(VK_SHIFT));
-    io.add_key_event(Key::ModAlt, is_vk_down(VK_MENU));
-    io.add_key_event(Key::ModSuper, is_vk_down(VK_APPS));
+    input.push(InputEvent::Key(Key::ModCtrl, is_vk_down(VK_CONTROL)));
+    input.push(InputEvent::Key(Key::ModShift, is_vk_down(VK_SHIFT)));
+    input.push(InputEvent::Key(Key::ModAlt, is_vk_down(VK_MENU)));
+    input.push(InputEvent::Key(Key::ModSuper, is_vk_down(VK_APPS)));
 
     if scancode == VK_SHIFT {
         if is_vk_down(VK_LSHIFT) == is_key_down {
-            io.add_key_event(Key::LeftShift, is_key_down);
+            input.push(InputEvent::Key(Key::LeftShift, is_key_down));
         }
         if is_vk_down(VK_RSHIFT) == is_key_down {
-            io.add_key_event(Key::RightShift, is_key_down);
+            input.push(InputEvent::Key(Key::RightShift, is_key_down));
         }
     } else if scancode == VK_CONTROL {
         if is_vk_down(VK_LCONTROL) == is_key_down {
-            io.add_key_event(Key::LeftCtrl, is_key_down);
+            input.push(InputEvent::Key(Key::LeftCtrl, is_key_down));
         }
         if is_vk_down(VK_RCONTROL) == is_key_down {
-            io.add_key_event(Key::RightCtrl, is_key_down);
+            input.push(InputEvent::Key(Key::RightCtrl, is_key_down));
         }
     } else if scancode == VK_MENU {
         if is_vk_down(VK_LMENU) == is_key_down {
-            io.add_key_event(Key::LeftAlt, is_key_down);
+            input.push(InputEvent::Key(Key::LeftAlt, is_key_down));
         }
         if is_vk_down(VK_RMENU) == is_key_down {
-            io.add_key_event(Key::RightAlt, is_key_down);
+            input.push(InputEvent::Key(Key::RightAlt, is_key_down));
         }
     }
 
     // TODO: Workarounds https://github.com/ocornut/imgui/blob/da29b776eed289db16a8527e5f16a0e1fa540251/backends/imgui_impl_win32.cpp#L263
 }
 
+// Release every key and mouse button, so that no input stays stuck when the
+// overlay stops receiving messages.
+pub fn release_all_inputs(io: &mut Io) {
+    for (key, vk) in KEYS {
+        // Mouse keys are aliases and can't be submitted as key events.
+        if !matches!(vk, VK_LBUTTON | VK_RBUTTON | VK_MBUTTON | VK_XBUTTON1 | VK_XBUTTON2) {
+            io.add_key_event(key, false);
+        }
+    }
+
+    for button in MouseButton::VARIANTS {
+        io.add_mouse_button_event(button, false);
+    }
+
+    io.add_key_event(Key::ModCtrl, false);
+    io.add_key_event(Key::ModShift, false);
+    io.add_key_event(Key::ModAlt, false);
+    io.add_key_event(Key::ModSuper, false);
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Window procedure
 ////////////////////////////////////////////////////////////////////////////////
 
-pub fn imgui_wnd_proc_impl<T: RenderEngine>(
+pub fn imgui_wnd_proc_impl(
     hwnd: HWND,
     umsg: u32,
     WPARAM(wparam): WPARAM,
     LPARAM(lparam): LPARAM,
-    pipeline: &mut Pipeline<T>,
+    ctx: &mut Context,
+    render_loop: &RenderLoop,
+    recorded: Option<&mut Vec<InputEvent>>,
 ) {
-    let io = pipeline.context().io_mut();
+    let mut input = InputSink::new(ctx.io_mut(), recorded);
 
     match umsg {
-        WM_INPUT => handle_raw_input(io, WPARAM(wparam), LPARAM(lparam)),
+        WM_INPUT => handle_raw_input(&mut input, WPARAM(wparam), LPARAM(lparam)),
         state @ (WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP) if wparam < 256 => {
-            handle_input(io, state, WPARAM(wparam), LPARAM(lparam))
+            handle_input(&mut input, state, WPARAM(wparam), LPARAM(lparam))
         },
         WM_LBUTTONDOWN | WM_LBUTTONDBLCLK => {
-            io.add_mouse_button_event(MouseButton::Left, true);
+            input.push(InputEvent::MouseButton(MouseButton::Left, true));
         },
         WM_RBUTTONDOWN | WM_RBUTTONDBLCLK => {
-            io.add_mouse_button_event(MouseButton::Right, true);
+            input.push(InputEvent::MouseButton(MouseButton::Right, true));
         },
         WM_MBUTTONDOWN | WM_MBUTTONDBLCLK => {
-            io.add_mouse_button_event(MouseButton::Middle, true);
+            input.push(InputEvent::MouseButton(MouseButton::Middle, true));
         },
         WM_XBUTTONDOWN | WM_XBUTTONDBLCLK => {
             let btn = if hiword(wparam as _) == XBUTTON1 {
@@ -299,16 +344,16 @@ pub fn imgui_wnd_proc_impl<T: RenderEngine>(
             } else {
                 MouseButton::Extra2
             };
-            io.add_mouse_button_event(btn, true);
+            input.push(InputEvent::MouseButton(btn, true));
         },
         WM_LBUTTONUP => {
-            io.add_mouse_button_event(MouseButton::Left, false);
+            input.push(InputEvent::MouseButton(MouseButton::Left, false));
         },
         WM_RBUTTONUP => {
-            io.add_mouse_button_event(MouseButton::Right, false);
+            input.push(InputEvent::MouseButton(MouseButton::Right, false));
         },
         WM_MBUTTONUP => {
-            io.add_mouse_button_event(MouseButton::Middle, false);
+            input.push(InputEvent::MouseButton(MouseButton::Middle, false));
         },
         WM_XBUTTONUP => {
             let btn = if hiword(wparam as _) == XBUTTON1 {
@@ -316,31 +361,52 @@ pub fn imgui_wnd_proc_impl<T: RenderEngine>(
             } else {
                 MouseButton::Extra2
             };
-            io.add_mouse_button_event(btn, false);
+            input.push(InputEvent::MouseButton(btn, false));
         },
         WM_MOUSEWHEEL => {
             // This `hiword` call is equivalent to GET_WHEEL_DELTA_WPARAM
             let wheel_delta_wparam = hiword(wparam as _);
             let wheel_delta = WHEEL_DELTA as f32;
-            io.add_mouse_wheel_event([0.0, (wheel_delta_wparam as i16 as f32) / wheel_delta]);
+            input.push(InputEvent::MouseWheel([
+                0.0,
+                (wheel_delta_wparam as i16 as f32) / wheel_delta,
+            ]));
         },
         WM_MOUSEHWHEEL => {
             // This `hiword` call is equivalent to GET_WHEEL_DELTA_WPARAM
             let wheel_delta_wparam = hiword(wparam as _);
             let wheel_delta = WHEEL_DELTA as f32;
-            io.add_mouse_wheel_event([(wheel_delta_wparam as i16 as f32) / wheel_delta, 0.0]);
+            input.push(InputEvent::MouseWheel([
+                (wheel_delta_wparam as i16 as f32) / wheel_delta,
+                0.0,
+            ]));
         },
         WM_MOUSEMOVE => {
             let x = lowordi(lparam as u32) as f32;
             let y = hiwordi(lparam as u32) as f32;
-            io.add_mouse_pos_event([x, y]);
+            input.push(InputEvent::MousePos([x, y]));
+        },
+        // Halves of surrogate pairs and garbage aren't characters on their own.
+        WM_CHAR => {
+            if let Some(c) = char::from_u32(wparam as u32) {
+                input.push(InputEvent::Char(c));
+            }
+        },
+        // Minimized windows report an empty client area: keep the last size until
+        // the window is restored.
+        WM_SIZE if wparam as u32 != SIZE_MINIMIZED => {
+            input.io.display_size = [loword(lparam as u32) as f32, hiword(lparam as u32) as f32];
         },
-        WM_CHAR => io.add_input_character(char::from_u32(wparam as u32).unwrap()),
-        WM_SIZE => {
-            pipeline.resize(loword(lparam as u32) as u32, hiword(lparam as u32) as u32);
+        // The client area follows the new display mode, but no `WM_SIZE` comes
+        // with it when an exclusive fullscreen window changes modes.
+        WM_DISPLAYCHANGE => {
+            let (width, height) = util::win_size(hwnd);
+            input.io.display_size = [width as f32, height as f32];
         },
+        // The keys released after alt-tabbing out reach another window.
+        WM_ACTIVATEAPP if wparam == 0 => release_all_inputs(&mut *input.io),
         _ => {},
     };
 
-    pipeline.render_loop().on_wnd_proc(hwnd, umsg, WPARAM(wparam), LPARAM(lparam));
+    render_loop.on_wnd_proc(hwnd, umsg, WPARAM(wparam), LPARAM(lparam));
 }
@@ -1,7 +1,10 @@
 //! The [`hudhook`](crate) overlay rendering engine.
+pub(crate) mod activation;
 mod backend;
+pub(crate) mod fullscreen;
 mod input;
-mod keys;
+pub(crate) mod keys;
+pub(crate) mod mouse;
 pub(crate) mod msg_filter;
 mod pipeline;
 
@@ -10,18 +13,40 @@ use windows::core::Result;
 
 use crate::RenderContext;
 
-pub(crate) trait RenderEngine: RenderContext {
-    type RenderTarget;
+/// A graphics API backend rendering `imgui` draw data.
+///
+/// See [`engine`](crate::engine) for using render engines without hooks.
+pub trait RenderEngine: RenderContext {
+    /// The surface the engine renders into.
+    type RenderTarget: Clone;
 
+    /// Render `draw_data` into `render_target`, on top of its contents.
     fn render(&mut self, draw_data: &DrawData, render_target: Self::RenderTarget) -> Result<()>;
+
+    /// Build the font atlas of `ctx` and upload it. Call it once before the
+    /// first frame, and again whenever the fonts change.
     fn setup_fonts(&mut self, ctx: &mut Context) -> Result<()>;
 }
 #[cfg(feature = "dx11")]
-pub(crate) use backend::dx11::D3D11RenderEngine;
+pub use backend::dx11::D3D11RenderEngine;
+#[cfg(feature = "dx11")]
+pub use backend::dx11::DepthTarget;
+#[cfg(feature = "dx11")]
+pub(crate) use backend::dx11::StateBackup;
+#[cfg(feature = "dx12")]
+pub(crate) use backend::dx12::{
+    add_frame_callback, clear_frame_callbacks, overlay_fence, overlay_frame, set_frames_in_flight,
+};
 #[cfg(feature = "dx12")]
-pub(crate) use backend::dx12::D3D12RenderEngine;
+pub use backend::dx12::{D3D12Capabilities, D3D12RenderEngine, FrameCompletion, VideoMemoryInfo};
 #[cfg(feature = "dx9")]
-pub(crate) use backend::dx9::D3D9RenderEngine;
+pub use backend::dx9::D3D9RenderEngine;
 #[cfg(feature = "opengl3")]
-pub(crate) use backend::opengl3::OpenGl3RenderEngine;
-pub(crate) use pipeline::Pipeline;
+pub use backend::opengl3::OpenGl3RenderEngine;
+#[cfg(feature = "wgpu")]
+pub use backend::wgpu::WgpuRenderEngine;
+pub(crate) use input::map_vkey;
+pub(crate) use pipeline::{
+    is_rendering, raw_context, request_reinitialization, reset_if_stale, restore_wnd_procs,
+    wnd_procs_chained, Pipeline,
+};
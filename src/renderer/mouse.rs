@@ -0,0 +1,59 @@
+//! This module contains logic for sampling the cursor position late in the
+//! frame.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use windows::Win32::Foundation::{HWND, POINT};
+use windows::Win32::Graphics::Gdi::ScreenToClient;
+use windows::Win32::UI::WindowsAndMessaging::{GetCursorPos, GetForegroundWindow};
+
+static MOUSE_LATCHING: AtomicU8 = AtomicU8::new(MouseLatching::Messages as u8);
+
+/// When the overlay samples the cursor position.
+///
+/// At low frame rates, the cursor position from the last window message can
+/// be stale by the time the frame is drawn, which makes the UI feel laggy.
+/// Sampling the position right before drawing reduces the perceived latency.
+///
+/// Set it via [`set_mouse_latching`](crate::set_mouse_latching).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MouseLatching {
+    /// Only use the positions reported by window messages.
+    #[default]
+    Messages,
+    /// Sample the cursor position right before building the frame.
+    BeforeFrame,
+    /// Sample the cursor position right before building the frame, and again
+    /// right before submitting it, moving the software cursor drawn by
+    /// `imgui` (see `Io::mouse_draw_cursor`) to the latest position.
+    BeforeSubmit,
+}
+
+impl MouseLatching {
+    pub(crate) fn get() -> Self {
+        match MOUSE_LATCHING.load(Ordering::SeqCst) {
+            1 => MouseLatching::BeforeFrame,
+            2 => MouseLatching::BeforeSubmit,
+            _ => MouseLatching::Messages,
+        }
+    }
+
+    pub(crate) fn set(self) {
+        MOUSE_LATCHING.store(self as u8, Ordering::SeqCst);
+    }
+}
+
+// Current cursor position in client coordinates of `hwnd`, if the window is in
+// the foreground.
+pub(crate) fn cursor_pos(hwnd: HWND) -> Option<[f32; 2]> {
+    unsafe {
+        if GetForegroundWindow() != hwnd {
+            return None;
+        }
+
+        let mut point = POINT::default();
+        GetCursorPos(&mut point).ok()?;
+        ScreenToClient(hwnd, &mut point).as_bool().then_some([point.x as f32, point.y as f32])
+    }
+}
@@ -1,8 +1,21 @@
 //! This module contains logic for filtering windows messages.
 
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU8, Ordering};
+
 use bitflags::bitflags;
+use imgui::Io;
+use windows::Win32::Foundation::{HWND, LPARAM};
+use windows::Win32::UI::Input::{
+    GetRawInputData, HRAWINPUT, RAWINPUTHEADER, RID_DEVICE_INFO_TYPE, RID_HEADER, RIM_TYPEMOUSE,
+};
 use windows::Win32::UI::WindowsAndMessaging::*;
 
+use super::input::{hiwordi, lowordi};
+use super::mouse;
+use crate::anchors::Region;
+
 bitflags! {
     /// Bitflag for specifying types of window message to be filtered.
     ///
@@ -89,3 +102,108 @@ impl MessageFilter {
         }
     }
 }
+
+static INPUT_BLOCKING: AtomicU8 = AtomicU8::new(InputBlocking::Never as u8);
+
+/// Whether the game stops receiving the input the overlay uses, on top of the
+/// messages blocked by
+/// [`ImguiRenderLoop::message_filter`](crate::ImguiRenderLoop::message_filter).
+///
+/// Key and mouse button releases always reach the game, so that keys held
+/// when the overlay started capturing don't stay pressed in the game. Games
+/// polling the keyboard, e.g. with `GetAsyncKeyState`, still see the keys.
+///
+/// Set it via [`set_input_blocking`](crate::set_input_blocking).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum InputBlocking {
+    /// Only block the messages the render loops filter.
+    #[default]
+    Never,
+    /// Block the keyboard, mouse and raw input messages whenever the overlay
+    /// is active.
+    Always,
+    /// Block the mouse messages while `imgui` wants to capture the mouse, e.g.
+    /// while hovering a window, and the keyboard messages while it wants to
+    /// capture the keyboard, e.g. while typing into a text field. Raw input
+    /// messages are blocked while it wants to capture either.
+    Automatic,
+}
+
+impl InputBlocking {
+    pub(crate) fn get() -> Self {
+        match INPUT_BLOCKING.load(Ordering::SeqCst) {
+            1 => InputBlocking::Always,
+            2 => InputBlocking::Automatic,
+            _ => InputBlocking::Never,
+        }
+    }
+
+    pub(crate) fn set(self) {
+        INPUT_BLOCKING.store(self as u8, Ordering::SeqCst);
+    }
+
+    // The messages to block for an active layer, given the capture requests
+    // of its last frame in `io`.
+    pub(crate) fn filter(self, io: &Io) -> MessageFilter {
+        match self {
+            InputBlocking::Never => MessageFilter::empty(),
+            InputBlocking::Always => MessageFilter::InputAll,
+            InputBlocking::Automatic => {
+                let mut filter = MessageFilter::empty();
+                if io.want_capture_mouse {
+                    filter |= MessageFilter::InputMouse | MessageFilter::InputRaw;
+                }
+                if io.want_capture_keyboard {
+                    filter |= MessageFilter::InputKeyboard | MessageFilter::InputRaw;
+                }
+                filter
+            },
+        }
+    }
+}
+
+// Whether `msg` releases a key or a mouse button.
+pub(crate) fn is_release(msg: u32) -> bool {
+    matches!(
+        msg,
+        WM_KEYUP | WM_SYSKEYUP | WM_LBUTTONUP | WM_RBUTTONUP | WM_MBUTTONUP | WM_XBUTTONUP
+    )
+}
+
+// Whether the mouse message `msg` happened within one of the passthrough
+// `regions`, in which case it reaches the window regardless of the filter.
+pub(crate) fn is_passing_through(hwnd: HWND, msg: u32, lparam: LPARAM, regions: &[Region]) -> bool {
+    if regions.is_empty() {
+        return false;
+    }
+
+    // Wheel messages carry screen coordinates, and raw input none at all.
+    let pos = match msg {
+        WM_MOUSEWHEEL | WM_MOUSEHWHEEL => mouse::cursor_pos(hwnd),
+        WM_MOUSEFIRST..=WM_MOUSELAST => {
+            Some([lowordi(lparam.0 as u32) as f32, hiwordi(lparam.0 as u32) as f32])
+        },
+        WM_INPUT if is_raw_mouse_input(lparam) => mouse::cursor_pos(hwnd),
+        _ => None,
+    };
+
+    pos.is_some_and(|pos| regions.iter().any(|region| region.contains(pos)))
+}
+
+fn is_raw_mouse_input(LPARAM(lparam): LPARAM) -> bool {
+    let mut header = RAWINPUTHEADER::default();
+    let mut header_size = size_of::<RAWINPUTHEADER>() as u32;
+
+    let r = unsafe {
+        GetRawInputData(
+            HRAWINPUT(lparam),
+            RID_HEADER,
+            Some(&mut header as *mut _ as *mut c_void),
+            &mut header_size,
+            size_of::<RAWINPUTHEADER>() as u32,
+        )
+    };
+
+    r != u32::MAX && RID_DEVICE_INFO_TYPE(header.dwType) == RIM_TYPEMOUSE
+}
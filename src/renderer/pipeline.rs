@@ -1,29 +1,83 @@
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::mem;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use std::{iter, mem};
 
-use imgui::Context;
+use imgui::{Context, SuspendedContext};
 use once_cell::sync::{Lazy, OnceCell};
 use parking_lot::Mutex;
-use tracing::error;
+use tracing::{error, warn};
 use windows::core::{Error, Result, HRESULT};
 use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
 use windows::Win32::UI::WindowsAndMessaging::{
-    CallWindowProcW, DefWindowProcW, SetWindowLongPtrW, GWLP_WNDPROC,
+    CallWindowProcW, DefWindowProcW, GetWindowLongPtrW, SetWindowLongPtrW, GWLP_WNDPROC,
+    WM_NCDESTROY,
 };
 
-use crate::renderer::input::{imgui_wnd_proc_impl, WndProcType};
+use crate::anchors::Region;
+use crate::hooks::{self, HookCall};
+use crate::layers::{self, LayerInfo};
+use crate::renderer::fullscreen::{self, WindowState};
+use crate::renderer::input::{imgui_wnd_proc_impl, release_all_inputs, WndProcType};
+use crate::renderer::mouse::{self, MouseLatching};
+use crate::renderer::msg_filter::{self, InputBlocking};
 use crate::renderer::RenderEngine;
-use crate::{util, ImguiRenderLoop, MessageFilter};
+use crate::{
+    benchmark, bind, file_watch, keybinds, keyboard, palette, remap, replay, schedule, sessions,
+    textures, timing, util, watch, ImguiRenderLoop, MessageFilter,
+};
 
-type RenderLoop = Box<dyn ImguiRenderLoop + Send + Sync>;
+pub(crate) type RenderLoop = Box<dyn ImguiRenderLoop + Send + Sync>;
 
 static PIPELINE_STATES: Lazy<Mutex<HashMap<isize, Arc<PipelineSharedState>>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+// Bumped every time a reinitialization of the renderers is requested. Pipelines
+// created before the last bump are stale.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Set while the current thread prepares or renders a frame, and so holds
+    // the locks of the render hooks.
+    static RENDERING: Cell<bool> = const { Cell::new(false) };
+    // Address of the context `Layer::with_context` lends out, and its raw
+    // `imgui` context.
+    static LENT_CONTEXT: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// The raw `imgui` context of `ctx`, if it is the context of a layer lent to
+/// its render loop.
+pub(crate) fn raw_context(ctx: &Context) -> Option<*mut imgui::sys::ImGuiContext> {
+    LENT_CONTEXT
+        .with(Cell::get)
+        .filter(|&(addr, _)| addr == ctx as *const Context as usize)
+        .map(|(_, raw)| raw as *mut imgui::sys::ImGuiContext)
+}
+
+/// Check whether the current thread is rendering a frame, i.e. is called back
+/// from a render loop.
+pub(crate) fn is_rendering() -> bool {
+    RENDERING.with(Cell::get)
+}
+
+// Marks the current thread as rendering until dropped.
+struct RenderingScope(bool);
+
+impl RenderingScope {
+    fn enter() -> Self {
+        Self(RENDERING.with(|rendering| rendering.replace(true)))
+    }
+}
+
+impl Drop for RenderingScope {
+    fn drop(&mut self) {
+        RENDERING.with(|rendering| rendering.set(self.0));
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct PipelineMessage(
     pub(crate) HWND,
@@ -34,39 +88,138 @@ pub(crate) struct PipelineMessage(
 
 pub(crate) struct PipelineSharedState {
     pub(crate) message_filter: AtomicU32,
+    // Input blocked per `InputBlocking`, releases excepted.
+    pub(crate) capture_filter: AtomicU32,
+    pub(crate) passthrough_regions: Mutex<Vec<Region>>,
+    pub(crate) window_state: Mutex<WindowState>,
     pub(crate) wnd_proc: WndProcType,
     pub(crate) tx: Sender<PipelineMessage>,
+    // Set when the pipeline is gone but its window procedure couldn't be
+    // removed, as another one was installed on top of it: messages are then
+    // forwarded to `wnd_proc` untouched.
+    pub(crate) detached: AtomicBool,
+}
+
+// A render loop together with its own, isolated `imgui` context.
+//
+// The context is kept suspended while the layer is not in use, so that several
+// layers can share the same pipeline. Only one `imgui` context can be active
+// at any given time.
+struct Layer {
+    ctx: Option<SuspendedContext>,
+    render_loop: RenderLoop,
+    active: bool,
+    // Position among the registered render loops, which breaks ties between
+    // loops in the same layer.
+    order: usize,
+    index: i32,
+}
+
+impl Layer {
+    // Activate the layer's context for the duration of `f`.
+    fn with_context<R>(&mut self, f: impl FnOnce(&mut Self, &mut Context) -> R) -> Result<R> {
+        let Some(suspended) = self.ctx.take() else {
+            error!("Layer context is already in use");
+            return Err(Error::from_hresult(HRESULT(-1)));
+        };
+
+        let mut ctx = match suspended.activate() {
+            Ok(ctx) => ctx,
+            Err(suspended) => {
+                error!("Could not activate layer context: another context is active");
+                self.ctx = Some(suspended);
+                return Err(Error::from_hresult(HRESULT(-1)));
+            },
+        };
+
+        // The context just activated is the current one.
+        let raw = unsafe { imgui::sys::igGetCurrentContext() } as usize;
+        let lent = LENT_CONTEXT.with(|lent_context| {
+            lent_context.replace(Some((&ctx as *const Context as usize, raw)))
+        });
+        let r = f(self, &mut ctx);
+        LENT_CONTEXT.with(|lent_context| lent_context.set(lent));
+        self.ctx = Some(ctx.suspend());
+
+        Ok(r)
+    }
 }
 
 pub(crate) struct Pipeline<T: RenderEngine> {
     hwnd: HWND,
-    ctx: Context,
     engine: T,
-    render_loop: RenderLoop,
+    layers: Vec<Layer>,
     rx: Receiver<PipelineMessage>,
     shared_state: Arc<PipelineSharedState>,
     queue_buffer: OnceCell<Vec<PipelineMessage>>,
+    regions_buffer: OnceCell<Vec<Region>>,
     start_of_first_frame: OnceCell<Instant>,
+    generation: usize,
+    layers_generation: Option<usize>,
+    // Number of watched file changes the render loops were notified of.
+    file_changes_seen: usize,
 }
 
 impl<T: RenderEngine> Pipeline<T> {
     pub(crate) fn new(
         hwnd: HWND,
-        mut ctx: Context,
+        ctx: Context,
         mut engine: T,
-        mut render_loop: RenderLoop,
-    ) -> std::result::Result<Self, (Error, RenderLoop)> {
+        render_loops: Vec<RenderLoop>,
+    ) -> std::result::Result<Self, (Error, Vec<RenderLoop>)> {
+        let generation = GENERATION.load(Ordering::SeqCst);
         let (width, height) = util::win_size(hwnd);
 
-        ctx.io_mut().display_size = [width as f32, height as f32];
-
-        render_loop.initialize(&mut ctx, &mut engine);
-
-        if let Err(e) = engine.setup_fonts(&mut ctx) {
-            return Err((e, render_loop));
+        // Every additional layer gets a context configured like the one the engine
+        // was created with. The additional contexts must be created while the
+        // first one is still active, or imgui would implicitly activate them.
+        let backend_flags = ctx.io().backend_flags;
+        let renderer_name = ctx.renderer_name().map(String::from);
+        let extra_contexts =
+            (1..render_loops.len()).map(|_| SuspendedContext::create()).collect::<Vec<_>>();
+
+        let mut layers = iter::once(ctx.suspend())
+            .chain(extra_contexts)
+            .zip(render_loops)
+            .enumerate()
+            .map(|(order, (ctx, render_loop))| Layer {
+                ctx: Some(ctx),
+                render_loop,
+                active: true,
+                order,
+                index: 0,
+            })
+            .collect::<Vec<_>>();
+
+        for layer in &mut layers {
+            let res = layer
+                .with_context(|layer, ctx| {
+                    ctx.set_ini_filename(None);
+                    ctx.set_renderer_name(renderer_name.clone());
+                    ctx.io_mut().backend_flags |= backend_flags;
+                    ctx.io_mut().display_size = [width as f32, height as f32];
+
+                    layer.render_loop.setup_fonts(ctx.fonts());
+                    layer.render_loop.initialize(ctx, &mut engine);
+
+                    engine.setup_fonts(ctx)
+                })
+                .and_then(|res| res);
+
+            if let Err(e) = res {
+                return Err((e, layers.into_iter().map(|layer| layer.render_loop).collect()));
+            }
         }
 
-        let wnd_proc = unsafe {
+        // A detached window procedure is still called: take it over rather than
+        // installing another one.
+        let detached = PIPELINE_STATES
+            .lock()
+            .get(&hwnd.0)
+            .filter(|shared_state| shared_state.detached.load(Ordering::SeqCst))
+            .map(|shared_state| shared_state.wnd_proc);
+
+        let wnd_proc = detached.unwrap_or_else(|| unsafe {
             #[cfg(target_arch = "x86")]
             type SwlpRet = i32;
             #[cfg(target_arch = "x86_64")]
@@ -77,110 +230,341 @@ impl<T: RenderEngine> Pipeline<T> {
                 GWLP_WNDPROC,
                 pipeline_wnd_proc as usize as _,
             ))
-        };
+        });
 
         let (tx, rx) = mpsc::channel();
         let shared_state = Arc::new(PipelineSharedState {
             message_filter: AtomicU32::new(MessageFilter::empty().bits()),
+            capture_filter: AtomicU32::new(MessageFilter::empty().bits()),
+            passthrough_regions: Mutex::new(Vec::new()),
+            window_state: Mutex::new(WindowState::measure(hwnd, false)),
             wnd_proc,
             tx,
+            detached: AtomicBool::new(false),
         });
 
         PIPELINE_STATES.lock().insert(hwnd.0, Arc::clone(&shared_state));
 
         let queue_buffer = OnceCell::from(Vec::new());
+        let regions_buffer = OnceCell::from(Vec::new());
 
         Ok(Self {
             hwnd,
-            ctx,
             engine,
-            render_loop,
+            layers,
             rx,
             shared_state: Arc::clone(&shared_state),
             queue_buffer,
+            regions_buffer,
             start_of_first_frame: OnceCell::new(),
+            generation,
+            layers_generation: None,
+            file_changes_seen: file_watch::change_count(),
         })
     }
 
+    // Sort the layers in drawing order, if it may have changed.
+    fn sort_layers(&mut self) {
+        let generation = layers::generation();
+        if self.layers_generation == Some(generation) {
+            return;
+        }
+        self.layers_generation = Some(generation);
+
+        for layer in &mut self.layers {
+            layer.index = layers::layer_of(layer.render_loop.name(), layer.render_loop.layer());
+        }
+        self.layers.sort_by_key(|layer| (layer.index, layer.order));
+
+        layers::publish(
+            self.layers
+                .iter()
+                .map(|layer| LayerInfo {
+                    name: layer.render_loop.name().to_string(),
+                    layer: layer.index,
+                    default_layer: layer.render_loop.layer(),
+                })
+                .collect(),
+        );
+    }
+
     pub(crate) fn prepare_render(&mut self) -> Result<()> {
+        let _rendering = RenderingScope::enter();
+        timing::update_monitor(self.hwnd);
+        self.sort_layers();
+
+        // Edited files and bound values are applied between frames, before any
+        // layer reads them.
+        file_watch::apply();
+        bind::apply();
+        textures::upload(&mut self.engine);
+        for path in file_watch::changes_since(&mut self.file_changes_seen) {
+            for layer in &mut self.layers {
+                layer.render_loop.on_file_changed(&path);
+            }
+        }
+
         let mut queue_buffer = self.queue_buffer.take().unwrap();
         queue_buffer.clear();
         queue_buffer.extend(self.rx.try_iter());
-        queue_buffer.drain(..).for_each(|PipelineMessage(hwnd, umsg, wparam, lparam)| {
-            imgui_wnd_proc_impl(hwnd, umsg, wparam, lparam, self);
-        });
-        self.queue_buffer.set(queue_buffer).expect("OnceCell should be empty");
 
-        let message_filter = self.render_loop.message_filter(self.ctx.io());
+        let mut message_filter = MessageFilter::empty();
+        let input_blocking = InputBlocking::get();
+        let mut capture_filter = MessageFilter::empty();
+        // The regions of the previous frame, swapped out of the shared state, are
+        // reused so that no frame allocates once the buffers are large enough.
+        let mut passthrough_regions = self.regions_buffer.take().unwrap();
+        passthrough_regions.clear();
+
+        // During a replay, the recorded events replace live input. Otherwise, the
+        // events translated for the first layer are recorded if needed.
+        let replayed = replay::next_frame();
+        let mut recorded = replay::is_recording().then(Vec::new);
+
+        let res = self.layers.iter_mut().enumerate().try_for_each(|(i, layer)| {
+            layer.with_context(|layer, ctx| {
+                if let Some(replayed) = &replayed {
+                    for event in replayed {
+                        event.apply(ctx.io_mut());
+                    }
+                } else {
+                    for &PipelineMessage(hwnd, umsg, wparam, lparam) in &queue_buffer {
+                        let recorded = recorded.as_mut().filter(|_| i == 0);
+                        imgui_wnd_proc_impl(
+                            hwnd,
+                            umsg,
+                            wparam,
+                            lparam,
+                            ctx,
+                            &layer.render_loop,
+                            recorded,
+                        );
+                    }
+                }
+
+                // Latch the activation state once per frame, so that a key released
+                // while rendering can't leave the frame half-drawn.
+                let active = layer.render_loop.activation().is_active();
+                if layer.active && !active {
+                    release_all_inputs(ctx.io_mut());
+                }
+                layer.active = active;
+
+                if active {
+                    message_filter |= layer.render_loop.message_filter(ctx.io());
+                    capture_filter |= input_blocking.filter(ctx.io());
+                    passthrough_regions.extend(layer.render_loop.passthrough_regions(ctx.io()));
+                }
+
+                let io = ctx.io_mut();
+
+                io.nav_active = true;
+                io.nav_visible = true;
+
+                layer.render_loop.before_render(ctx, &mut self.engine);
+            })
+        });
 
-        self.shared_state.message_filter.store(message_filter.bits(), Ordering::SeqCst);
+        if let Some(recorded) = &mut recorded {
+            replay::record(recorded);
+        }
 
-        let io = self.ctx.io_mut();
+        queue_buffer.clear();
+        self.queue_buffer.set(queue_buffer).expect("OnceCell should be empty");
 
-        io.nav_active = true;
-        io.nav_visible = true;
+        if res.is_ok() {
+            mem::swap(&mut *self.shared_state.passthrough_regions.lock(), &mut passthrough_regions);
+        }
+        self.regions_buffer.set(passthrough_regions).expect("OnceCell should be empty");
+        res?;
 
-        self.render_loop.before_render(&mut self.ctx, &mut self.engine);
+        self.shared_state.message_filter.store(message_filter.bits(), Ordering::SeqCst);
+        self.shared_state.capture_filter.store(capture_filter.bits(), Ordering::SeqCst);
 
         Ok(())
     }
 
     pub(crate) fn render(&mut self, render_target: T::RenderTarget) -> Result<()> {
-        let delta_time = Instant::now()
-            .checked_duration_since(*self.start_of_first_frame.get_or_init(Instant::now))
-            .unwrap_or(Duration::ZERO)
-            .checked_sub(Duration::from_secs_f64(self.ctx.time()))
-            .unwrap_or(Duration::ZERO);
-
-        self.ctx.io_mut().update_delta_time(delta_time);
-
-        let [w, h] = self.ctx.io().display_size;
-        let [fsw, fsh] = self.ctx.io().display_framebuffer_scale;
-
-        if (w * fsw) <= 0.0 || (h * fsh) <= 0.0 {
-            error!("Insufficient display size: {w}x{h}");
-            return Err(Error::from_hresult(HRESULT(-1)));
+        let _rendering = RenderingScope::enter();
+        // Minimized windows have no area to draw in, which isn't an error.
+        if matches!(*self.shared_state.window_state.lock(), WindowState::Minimized { .. }) {
+            return Ok(());
         }
 
-        let ui = self.ctx.frame();
-        self.render_loop.render(ui);
-        let draw_data = self.ctx.render();
+        let start_of_first_frame = *self.start_of_first_frame.get_or_init(Instant::now);
+        let mouse_latching = MouseLatching::get();
+        let hwnd = self.hwnd;
+        let mut keyboard_captured = false;
+        let render_start = Instant::now();
+        // The benchmark UI is drawn on top of the first active layer.
+        let mut benchmark_widgets = benchmark::begin_frame();
+        // Watched values are read once for all layers.
+        watch::sample();
+
+        for layer in &mut self.layers {
+            layer.with_context(|layer, ctx| {
+                let delta_time = Instant::now()
+                    .checked_duration_since(start_of_first_frame)
+                    .unwrap_or(Duration::ZERO)
+                    .checked_sub(Duration::from_secs_f64(ctx.time()))
+                    .unwrap_or(Duration::ZERO);
+
+                ctx.io_mut().update_delta_time(delta_time);
+
+                let [w, h] = ctx.io().display_size;
+                let [fsw, fsh] = ctx.io().display_framebuffer_scale;
+
+                if (w * fsw) <= 0.0 || (h * fsh) <= 0.0 {
+                    error!("Insufficient display size: {w}x{h}");
+                    return Err(Error::from_hresult(HRESULT(-1)));
+                }
+
+                if mouse_latching != MouseLatching::Messages {
+                    if let Some(pos) = mouse::cursor_pos(hwnd) {
+                        ctx.io_mut().add_mouse_pos_event(pos);
+                    }
+                }
+
+                let ui = ctx.frame();
+
+                // An inactive frame still goes through imgui, so that time keeps flowing
+                // and pending input events are consumed, but nothing gets drawn.
+                if !layer.active {
+                    ctx.render();
+                    return Ok(());
+                }
+
+                keyboard_captured |= ui.io().want_capture_keyboard;
+
+                let transformed_colors = palette::push_style_transform(ui);
+                layer.render_loop.render(ui);
+                palette::pop_style_transform(transformed_colors);
+
+                if let Some(widgets) = benchmark_widgets.take() {
+                    benchmark::draw(ui, widgets);
+                }
+
+                // Move the software cursor to where the mouse is now, the UI itself was
+                // already laid out with the position sampled before the frame.
+                if mouse_latching == MouseLatching::BeforeSubmit {
+                    if let Some(pos) = mouse::cursor_pos(hwnd) {
+                        ctx.io_mut().mouse_pos = pos;
+                    }
+                }
+
+                let draw_data = ctx.render();
+
+                self.engine.render(draw_data, render_target.clone())
+            })??;
+        }
 
-        self.engine.render(draw_data, render_target)?;
+        keyboard::set_frame_state(hwnd, keyboard_captured);
+        let render_time = render_start.elapsed();
+        benchmark::end_frame(render_time);
+        sessions::record_frame(render_time);
+        schedule::tick();
 
         Ok(())
     }
 
-    pub(crate) fn context(&mut self) -> &mut Context {
-        &mut self.ctx
+    // Whether a reinitialization was requested after the pipeline was created.
+    pub(crate) fn is_stale(&self) -> bool {
+        self.generation != GENERATION.load(Ordering::SeqCst)
     }
 
-    pub(crate) fn render_loop(&mut self) -> &mut RenderLoop {
-        &mut self.render_loop
-    }
-
-    pub(crate) fn resize(&mut self, width: u32, height: u32) {
-        self.ctx.io_mut().display_size = [width as f32, height as f32];
+    pub(crate) fn engine_mut(&mut self) -> &mut T {
+        &mut self.engine
     }
 
     pub(crate) fn cleanup(&mut self) {
-        unsafe {
-            SetWindowLongPtrW(self.hwnd, GWLP_WNDPROC, self.shared_state.wnd_proc as usize as _)
-        };
+        let current = unsafe { GetWindowLongPtrW(self.hwnd, GWLP_WNDPROC) } as usize;
+        if current == pipeline_wnd_proc as usize {
+            unsafe {
+                SetWindowLongPtrW(self.hwnd, GWLP_WNDPROC, self.shared_state.wnd_proc as usize as _)
+            };
+        } else if current != 0 && current != self.shared_state.wnd_proc as usize {
+            // Restoring the original window procedure would also remove the ones
+            // installed on top of ours, e.g. by another overlay, which still call
+            // ours: leave it in place, forwarding messages.
+            warn!("Window procedure of {:?} was replaced, leaving it chained", self.hwnd);
+            self.shared_state.detached.store(true, Ordering::SeqCst);
+            return;
+        }
+
+        // Otherwise the window is gone, or its procedure was already restored.
+        PIPELINE_STATES.lock().remove(&self.hwnd.0);
     }
 
-    pub(crate) fn take(mut self) -> RenderLoop {
+    pub(crate) fn take(mut self) -> Vec<RenderLoop> {
         self.cleanup();
-        self.render_loop
+        // Hand the render loops back in the order they were registered.
+        self.layers.sort_by_key(|layer| layer.order);
+        self.layers.into_iter().map(|layer| layer.render_loop).collect()
     }
 }
 
+// Request every pipeline to be torn down and rebuilt on its next frame.
+pub(crate) fn request_reinitialization() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+// Tear down `pipeline` if it is stale, moving its render loops back into
+// `render_loops` so that the hook rebuilds it from scratch. Returns whether the
+// pipeline was torn down.
+pub(crate) fn reset_if_stale<T: RenderEngine>(
+    pipeline: &mut OnceCell<Mutex<Pipeline<T>>>,
+    render_loops: &mut OnceCell<Vec<RenderLoop>>,
+) -> bool {
+    if !pipeline.get().is_some_and(|pipeline| pipeline.lock().is_stale()) {
+        return false;
+    }
+
+    if let Some(pipeline) = pipeline.take() {
+        let loops = pipeline.into_inner().take();
+        if render_loops.set(loops).is_err() {
+            error!("Render loops were not moved into the pipeline");
+        }
+    }
+
+    true
+}
+
+// The state of the window `hwnd`, if a pipeline renders on it.
+pub(crate) fn window_state(hwnd: HWND) -> Option<WindowState> {
+    PIPELINE_STATES.lock().get(&hwnd.0).map(|shared_state| *shared_state.window_state.lock())
+}
+
+// Restore the original window procedure of every hooked window, leaving
+// everything else untouched. Used on process exit, when other threads may have
+// been terminated while holding locks, hence the `try_lock`.
+pub(crate) fn restore_wnd_procs() {
+    let Some(shared_states) = PIPELINE_STATES.try_lock() else {
+        return;
+    };
+
+    for (&hwnd, shared_state) in shared_states.iter() {
+        if !shared_state.detached.load(Ordering::SeqCst) {
+            unsafe {
+                SetWindowLongPtrW(HWND(hwnd), GWLP_WNDPROC, shared_state.wnd_proc as usize as _)
+            };
+        }
+    }
+}
+
+// Whether a window procedure of the DLL is still called by another one, in
+// which case unloading the DLL would crash the game.
+pub(crate) fn wnd_procs_chained() -> bool {
+    PIPELINE_STATES.lock().values().any(|shared_state| shared_state.detached.load(Ordering::SeqCst))
+}
+
 unsafe extern "system" fn pipeline_wnd_proc(
     hwnd: HWND,
     msg: u32,
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
+    let _call = HookCall::enter();
+
     let shared_state = {
         let Some(shared_state_guard) = PIPELINE_STATES.try_lock() else {
             error!("Could not lock shared state in window procedure");
@@ -195,6 +579,30 @@ unsafe extern "system" fn pipeline_wnd_proc(
         Arc::clone(shared_state)
     };
 
+    if shared_state.detached.load(Ordering::SeqCst) {
+        return CallWindowProcW(Some(shared_state.wnd_proc), hwnd, msg, wparam, lparam);
+    }
+
+    // The window is going away, possibly as part of the process exiting: restore
+    // the original window procedure so no further messages reach the pipeline.
+    if msg == WM_NCDESTROY {
+        hooks::forget_window(hwnd);
+        SetWindowLongPtrW(hwnd, GWLP_WNDPROC, shared_state.wnd_proc as usize as _);
+        return CallWindowProcW(Some(shared_state.wnd_proc), hwnd, msg, wparam, lparam);
+    }
+
+    // Posted by `set_borderless`, for the overlay only.
+    if msg == fullscreen::reframe_message() {
+        fullscreen::reframe(hwnd, wparam.0 != 0);
+        return LRESULT(0);
+    }
+
+    let window_state = {
+        let mut window_state = shared_state.window_state.lock();
+        *window_state = window_state.next(hwnd, msg, wparam);
+        *window_state
+    };
+
     if let Err(e) = shared_state.tx.send(PipelineMessage(hwnd, msg, wparam, lparam)) {
         error!("Could not send window message through pipeline: {e:?}");
     }
@@ -204,9 +612,25 @@ unsafe extern "system" fn pipeline_wnd_proc(
     let message_filter =
         MessageFilter::from_bits_retain(shared_state.message_filter.load(Ordering::SeqCst));
 
-    if message_filter.is_blocking(msg) {
+    let capture_filter =
+        MessageFilter::from_bits_retain(shared_state.capture_filter.load(Ordering::SeqCst));
+    let blocking = message_filter.is_blocking(msg)
+        || (capture_filter.is_blocking(msg) && !msg_filter::is_release(msg));
+
+    let passing_through = || {
+        msg_filter::is_passing_through(hwnd, msg, lparam, &shared_state.passthrough_regions.lock())
+    };
+
+    if blocking
+        && !window_state.must_pass(msg)
+        && !fullscreen::is_reframing(hwnd)
+        && !passing_through()
+    {
         LRESULT(1)
     } else {
+        // The overlay received the keys pressed; the game gets them remapped.
+        let (wparam, lparam) = remap::rewrite(msg, wparam, lparam);
+        keybinds::observe(msg, wparam, lparam);
         CallWindowProcW(Some(shared_state.wnd_proc), hwnd, msg, wparam, lparam)
     }
 }
@@ -0,0 +1,246 @@
+//! Recording and replay of overlay input.
+//!
+//! While recording, every input event the overlay translates from window
+//! messages is stored along with the index of the frame it was received in.
+//! The recording can then be replayed in-game, where it replaces live input
+//! frame by frame, or fed to a headless `imgui` context in a test. This makes
+//! reports like "my hotkey sometimes double-triggers" reproducible.
+//!
+//! Replayed events only reach `imgui`: [`ImguiRenderLoop::on_wnd_proc`] is
+//! not called for them.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::replay::{self, InputRecording};
+//!
+//! // Reproduce the bug in-game...
+//! replay::start_recording("input.txt");
+//! // ...and save the recording.
+//! replay::stop_recording().unwrap();
+//!
+//! // Later, replay it.
+//! replay::start_replay(InputRecording::load("input.txt").unwrap());
+//! ```
+//!
+//! [`ImguiRenderLoop::on_wnd_proc`]: crate::ImguiRenderLoop::on_wnd_proc
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::{fs, io, mem};
+
+use imgui::{Io, Key, MouseButton};
+use parking_lot::Mutex;
+
+static STATE: Mutex<State> = Mutex::new(State::Idle);
+
+// Modifier keys are not part of `Key::VARIANTS`.
+const MOD_KEYS: [Key; 4] = [Key::ModCtrl, Key::ModShift, Key::ModAlt, Key::ModSuper];
+
+enum State {
+    Idle,
+    Recording { path: PathBuf, frame: u64, recording: InputRecording },
+    Replaying { frame: u64, events: VecDeque<(u64, InputEvent)> },
+}
+
+/// An input event, as submitted to `imgui`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    /// A key was pressed or released.
+    Key(Key, bool),
+    /// A mouse button was pressed or released.
+    MouseButton(MouseButton, bool),
+    /// The mouse moved to a position in client coordinates.
+    MousePos([f32; 2]),
+    /// The mouse wheel scrolled horizontally and vertically.
+    MouseWheel([f32; 2]),
+    /// A character was typed.
+    Char(char),
+}
+
+impl InputEvent {
+    /// Submit the event to `imgui`.
+    pub fn apply(&self, io: &mut Io) {
+        match *self {
+            InputEvent::Key(key, down) => io.add_key_event(key, down),
+            InputEvent::MouseButton(button, down) => io.add_mouse_button_event(button, down),
+            InputEvent::MousePos(pos) => io.add_mouse_pos_event(pos),
+            InputEvent::MouseWheel(wheel) => io.add_mouse_wheel_event(wheel),
+            InputEvent::Char(c) => io.add_input_character(c),
+        }
+    }
+
+    fn to_line(self, frame: u64) -> String {
+        match self {
+            InputEvent::Key(key, down) => format!("{frame}\tkey\t{key:?}\t{}\n", down as u8),
+            InputEvent::MouseButton(button, down) => {
+                format!("{frame}\tbutton\t{button:?}\t{}\n", down as u8)
+            },
+            InputEvent::MousePos([x, y]) => format!("{frame}\tpos\t{x}\t{y}\n"),
+            InputEvent::MouseWheel([x, y]) => format!("{frame}\twheel\t{x}\t{y}\n"),
+            InputEvent::Char(c) => format!("{frame}\tchar\t{}\n", c as u32),
+        }
+    }
+
+    fn from_line(line: &str) -> Option<(u64, Self)> {
+        let mut fields = line.split('\t');
+        let frame = fields.next()?.parse().ok()?;
+        let kind = fields.next()?;
+        let (a, b) = (fields.next()?, fields.next());
+
+        let down = || match b {
+            Some("1") => Some(true),
+            Some("0") => Some(false),
+            _ => None,
+        };
+        let pair = || Some([a.parse().ok()?, b?.parse().ok()?]);
+
+        let event = match kind {
+            "key" => InputEvent::Key(
+                *Key::VARIANTS.iter().chain(&MOD_KEYS).find(|key| format!("{key:?}") == a)?,
+                down()?,
+            ),
+            "button" => InputEvent::MouseButton(
+                *MouseButton::VARIANTS.iter().find(|button| format!("{button:?}") == a)?,
+                down()?,
+            ),
+            "pos" => InputEvent::MousePos(pair()?),
+            "wheel" => InputEvent::MouseWheel(pair()?),
+            "char" => InputEvent::Char(char::from_u32(a.parse().ok()?)?),
+            _ => return None,
+        };
+
+        Some((frame, event))
+    }
+}
+
+/// A sequence of input events, each tagged with the index of the frame it
+/// was received in, counting from the start of the recording.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InputRecording {
+    events: Vec<(u64, InputEvent)>,
+}
+
+impl InputRecording {
+    /// Append an event received in `frame`.
+    pub fn push(&mut self, frame: u64, event: InputEvent) {
+        self.events.push((frame, event));
+    }
+
+    /// All the events, in the order they were received.
+    pub fn events(&self) -> &[(u64, InputEvent)] {
+        &self.events
+    }
+
+    /// The events received in `frame`.
+    ///
+    /// To replay the recording against a headless `imgui` context, apply
+    /// these to its [`Io`] before building each frame.
+    pub fn events_at(&self, frame: u64) -> impl Iterator<Item = &InputEvent> {
+        self.events.iter().filter(move |&&(f, _)| f == frame).map(|(_, event)| event)
+    }
+
+    /// Number of frames the recording spans.
+    pub fn frames(&self) -> u64 {
+        self.events.last().map(|&(frame, _)| frame + 1).unwrap_or(0)
+    }
+
+    /// Load a recording previously written by [`save`](Self::save).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let events = fs::read_to_string(path)?.lines().filter_map(InputEvent::from_line).collect();
+
+        Ok(Self { events })
+    }
+
+    /// Save the recording to a file, one event per line.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let contents: String =
+            self.events.iter().map(|&(frame, event)| event.to_line(frame)).collect();
+
+        fs::write(path, contents)
+    }
+}
+
+/// Start recording the overlay input, to be written to `path` by
+/// [`stop_recording`].
+///
+/// Stops any replay in progress.
+pub fn start_recording<P: AsRef<Path>>(path: P) {
+    *STATE.lock() = State::Recording {
+        path: path.as_ref().to_path_buf(),
+        frame: 0,
+        recording: InputRecording::default(),
+    };
+}
+
+/// Stop recording and write the recording to the path passed to
+/// [`start_recording`]. Does nothing if not recording.
+pub fn stop_recording() -> io::Result<()> {
+    let mut state = STATE.lock();
+
+    if let State::Recording { path, recording, .. } = &*state {
+        recording.save(path)?;
+        *state = State::Idle;
+    }
+
+    Ok(())
+}
+
+/// Replay a recording, starting from the next frame.
+///
+/// Live input is ignored until the replay ends or [`stop_replay`] is called.
+/// Stops any recording in progress without saving it.
+pub fn start_replay(recording: InputRecording) {
+    *STATE.lock() = State::Replaying { frame: 0, events: recording.events.into() };
+}
+
+/// Stop replaying and go back to live input.
+pub fn stop_replay() {
+    let mut state = STATE.lock();
+
+    if let State::Replaying { .. } = &*state {
+        *state = State::Idle;
+    }
+}
+
+/// Whether a recording is being replayed.
+pub fn is_replaying() -> bool {
+    matches!(&*STATE.lock(), State::Replaying { .. })
+}
+
+// Whether input events should be collected for recording this frame.
+pub(crate) fn is_recording() -> bool {
+    matches!(&*STATE.lock(), State::Recording { .. })
+}
+
+// Events to submit instead of live input in the current frame during a replay,
+// `None` otherwise. Advances the replay by one frame.
+pub(crate) fn next_frame() -> Option<Vec<InputEvent>> {
+    let mut state = STATE.lock();
+
+    match &mut *state {
+        State::Idle | State::Recording { .. } => None,
+        State::Replaying { frame, events } => {
+            let mut current = Vec::new();
+            while let Some(&(_, event)) = events.front().filter(|&&(f, _)| f == *frame) {
+                current.push(event);
+                events.pop_front();
+            }
+
+            *frame += 1;
+            if events.is_empty() {
+                *state = State::Idle;
+            }
+
+            Some(current)
+        },
+    }
+}
+
+// Store the events received in the current frame, and advance by one frame.
+pub(crate) fn record(events: &mut Vec<InputEvent>) {
+    if let State::Recording { frame, recording, .. } = &mut *STATE.lock() {
+        for event in mem::take(events) {
+            recording.push(*frame, event);
+        }
+        *frame += 1;
+    }
+}
@@ -0,0 +1,416 @@
+//! Save states of the game's memory, for practice tools.
+//!
+//! Practice tools let the user go back to a point of the game over and over,
+//! by saving the memory holding the game state and writing it back later.
+//! Mods [`register`] the regions of memory making up the state they care
+//! about; [`save`] copies them to a named slot, and [`load`] writes them
+//! back. Slots can be written to disk with [`export`] and read back with
+//! [`import`], to keep them across sessions.
+//!
+//! Saving and loading happen on the game's render thread, right before a
+//! frame is presented, as with
+//! [`run_on_game_thread`](crate::game_thread::run_on_game_thread): the game
+//! never sees half of a state, and the overlay never renders one.
+//!
+//! Slots are compressed with run-length encoding, which suits the zero-filled
+//! padding and sparse structures game memory is full of.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::savestate;
+//!
+//! savestate::register("player", 0x7ff6_1234_0000, 0x400);
+//!
+//! // From a background thread, or without waiting for the result.
+//! savestate::save("before boss").recv().unwrap().unwrap();
+//! savestate::load("before boss");
+//! savestate::export("before boss", "before_boss.state").unwrap();
+//! ```
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+
+use parking_lot::Mutex;
+use tracing::debug;
+
+use crate::{game_thread, memory};
+
+const MAGIC: &[u8; 4] = b"HHSS";
+const VERSION: u32 = 1;
+// Most bytes a byte of compressed data stands for: 2 bytes for a run of 130.
+const MAX_EXPANSION: usize = 65;
+
+static REGIONS: Mutex<Vec<Region>> = Mutex::new(Vec::new());
+static SLOTS: Mutex<Option<HashMap<String, Snapshot>>> = Mutex::new(None);
+
+/// A region of memory saved in every slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// Name of the region.
+    pub name: String,
+    /// Address of its first byte.
+    pub address: usize,
+    /// Size in bytes.
+    pub len: usize,
+}
+
+// A region as saved in a slot, its bytes compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SavedRegion {
+    name: String,
+    address: usize,
+    len: usize,
+    data: Vec<u8>,
+}
+
+// The regions saved in a slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snapshot {
+    regions: Vec<SavedRegion>,
+}
+
+/// Save the `len` bytes at `address` in every slot saved from now on, as
+/// `name`, replacing the region registered as `name`, if any.
+pub fn register(name: &str, address: usize, len: usize) {
+    let region = Region { name: name.to_string(), address, len };
+
+    let mut regions = REGIONS.lock();
+    match regions.iter_mut().find(|r| r.name == name) {
+        Some(r) => *r = region,
+        None => regions.push(region),
+    }
+}
+
+/// Stop saving the region registered as `name`.
+pub fn unregister(name: &str) {
+    REGIONS.lock().retain(|r| r.name != name);
+}
+
+/// The registered regions.
+pub fn regions() -> Vec<Region> {
+    REGIONS.lock().clone()
+}
+
+/// Names of the saved slots, sorted.
+pub fn slots() -> Vec<String> {
+    let mut slots: Vec<String> =
+        SLOTS.lock().iter().flat_map(|slots| slots.keys()).cloned().collect();
+    slots.sort();
+    slots
+}
+
+/// Forget the slot `slot`.
+pub fn delete(slot: &str) {
+    if let Some(slots) = SLOTS.lock().as_mut() {
+        slots.remove(slot);
+    }
+}
+
+/// Size of the slot `slot` in bytes, compressed and uncompressed.
+pub fn slot_size(slot: &str) -> Option<(usize, usize)> {
+    let slots = SLOTS.lock();
+    let snapshot = slots.as_ref()?.get(slot)?;
+    Some((
+        snapshot.regions.iter().map(|r| r.data.len()).sum(),
+        snapshot.regions.iter().map(|r| r.len).sum(),
+    ))
+}
+
+/// Save the registered regions to `slot` before the next frame is presented,
+/// replacing what `slot` held. Fails if some of the memory can't be read.
+///
+/// The returned receiver gets the result. Don't block on it from a render
+/// loop, which runs on the thread saving the slot.
+pub fn save(slot: &str) -> Receiver<io::Result<()>> {
+    let slot = slot.to_string();
+    game_thread::run_on_game_thread(move || {
+        let snapshot = capture(&REGIONS.lock())?;
+        debug!("Saved {} regions to slot {slot:?}", snapshot.regions.len());
+        SLOTS.lock().get_or_insert_with(HashMap::new).insert(slot, snapshot);
+        Ok(())
+    })
+}
+
+/// Write the regions saved in `slot` back before the next frame is presented.
+/// Fails if the slot doesn't exist or some of the memory can't be written.
+///
+/// The returned receiver gets the result. Don't block on it from a render
+/// loop, which runs on the thread loading the slot.
+pub fn load(slot: &str) -> Receiver<io::Result<()>> {
+    let slot = slot.to_string();
+    game_thread::run_on_game_thread(move || {
+        let snapshot = SLOTS
+            .lock()
+            .as_ref()
+            .and_then(|slots| slots.get(&slot).cloned())
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("No slot {slot:?}")))?;
+        restore(&snapshot)?;
+        debug!("Loaded {} regions from slot {slot:?}", snapshot.regions.len());
+        Ok(())
+    })
+}
+
+/// Write the slot `slot` to the file at `path`.
+pub fn export(slot: &str, path: impl AsRef<Path>) -> io::Result<()> {
+    let snapshot = SLOTS
+        .lock()
+        .as_ref()
+        .and_then(|slots| slots.get(slot).cloned())
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("No slot {slot:?}")))?;
+    fs::write(path, encode(&snapshot))
+}
+
+/// Read the file at `path`, written by [`export`], into the slot `slot`,
+/// replacing what `slot` held.
+pub fn import(slot: &str, path: impl AsRef<Path>) -> io::Result<()> {
+    let snapshot = decode(&fs::read(path)?)?;
+    SLOTS.lock().get_or_insert_with(HashMap::new).insert(slot.to_string(), snapshot);
+    Ok(())
+}
+
+fn capture(regions: &[Region]) -> io::Result<Snapshot> {
+    let regions = regions
+        .iter()
+        .map(|region| {
+            let bytes = memory::read(region.address, region.len)
+                .into_iter()
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        ErrorKind::PermissionDenied,
+                        format!("Couldn't read region {:?} at {:#x}", region.name, region.address),
+                    )
+                })?;
+            Ok(SavedRegion {
+                name: region.name.clone(),
+                address: region.address,
+                len: region.len,
+                data: compress(&bytes),
+            })
+        })
+        .collect::<io::Result<_>>()?;
+
+    Ok(Snapshot { regions })
+}
+
+fn restore(snapshot: &Snapshot) -> io::Result<()> {
+    // Decompress everything first, so that a corrupted region doesn't leave the
+    // state half restored.
+    let regions = snapshot
+        .regions
+        .iter()
+        .map(|region| {
+            let bytes = decompress(&region.data, region.len)?;
+            Ok((region, bytes))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    for (region, bytes) in regions {
+        // SAFETY: the bytes were read from the same addresses, and the user asked
+        // for them to be written back.
+        unsafe { memory::write(region.address, &bytes) }.map_err(|e| {
+            io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("Couldn't write region {:?} at {:#x}: {e}", region.name, region.address),
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+// Run-length encode `bytes`. A control byte with the high bit set is followed
+// by a byte repeated 3 to 130 times; without it, by 1 to 128 literal bytes.
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() / 2);
+    let mut literals_start = 0;
+    let mut i = 0;
+
+    let flush_literals = |out: &mut Vec<u8>, literals: &[u8]| {
+        for chunk in literals.chunks(128) {
+            out.push(chunk.len() as u8 - 1);
+            out.extend_from_slice(chunk);
+        }
+    };
+
+    while i < bytes.len() {
+        let run = bytes[i..].iter().take(130).take_while(|&&b| b == bytes[i]).count();
+        if run >= 3 {
+            flush_literals(&mut out, &bytes[literals_start..i]);
+            out.push(0x80 | (run - 3) as u8);
+            out.push(bytes[i]);
+            i += run;
+            literals_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    flush_literals(&mut out, &bytes[literals_start..]);
+
+    out
+}
+
+fn decompress(data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let corrupted = || io::Error::new(ErrorKind::InvalidData, "Corrupted save state");
+
+    // Don't trust `len` with an allocation before checking that the data can
+    // hold that many bytes.
+    if len > data.len().saturating_mul(MAX_EXPANSION) {
+        return Err(corrupted());
+    }
+
+    let mut out = Vec::with_capacity(len);
+    let mut data = data.iter().copied();
+    while let Some(control) = data.next() {
+        if out.len() > len {
+            return Err(corrupted());
+        }
+        if control & 0x80 != 0 {
+            let byte = data.next().ok_or_else(corrupted)?;
+            out.extend(std::iter::repeat(byte).take((control & 0x7f) as usize + 3));
+        } else {
+            for _ in 0..=control {
+                out.push(data.next().ok_or_else(corrupted)?);
+            }
+        }
+    }
+
+    if out.len() != len {
+        return Err(corrupted());
+    }
+    Ok(out)
+}
+
+fn encode(snapshot: &Snapshot) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(snapshot.regions.len() as u32).to_le_bytes());
+
+    for region in &snapshot.regions {
+        out.extend_from_slice(&(region.name.len() as u32).to_le_bytes());
+        out.extend_from_slice(region.name.as_bytes());
+        out.extend_from_slice(&(region.address as u64).to_le_bytes());
+        out.extend_from_slice(&(region.len as u64).to_le_bytes());
+        out.extend_from_slice(&(region.data.len() as u64).to_le_bytes());
+        out.extend_from_slice(&region.data);
+    }
+
+    out
+}
+
+fn decode(mut bytes: &[u8]) -> io::Result<Snapshot> {
+    let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    let mut take = |len: usize| {
+        if bytes.len() < len {
+            return Err(invalid("Truncated save state"));
+        }
+        let (head, tail) = bytes.split_at(len);
+        bytes = tail;
+        Ok(head)
+    };
+
+    if take(4)? != MAGIC {
+        return Err(invalid("Not a save state"));
+    }
+    let version = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    if version != VERSION {
+        return Err(invalid(&format!("Unsupported save state version {version}")));
+    }
+
+    let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let mut regions = Vec::new();
+    for _ in 0..count {
+        let name_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let name = String::from_utf8(take(name_len)?.to_vec())
+            .map_err(|_| invalid("Invalid region name"))?;
+        let mut take_usize = || {
+            usize::try_from(u64::from_le_bytes(take(8)?.try_into().unwrap()))
+                .map_err(|_| invalid("Region out of the address space"))
+        };
+        let address = take_usize()?;
+        let len = take_usize()?;
+        let data_len = take_usize()?;
+        let data = take(data_len)?.to_vec();
+        if len > data.len().saturating_mul(MAX_EXPANSION) {
+            return Err(invalid(&format!("Region {name:?} is larger than its data")));
+        }
+        regions.push(SavedRegion { name, address, len, data });
+    }
+
+    Ok(Snapshot { regions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(name: &str, address: usize, bytes: &[u8]) -> SavedRegion {
+        SavedRegion { name: name.to_string(), address, len: bytes.len(), data: compress(bytes) }
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let mut mixed = vec![0u8; 1000];
+        mixed.extend((0..=255).cycle().take(300));
+        mixed.extend([7, 7, 1, 7, 7, 7, 2, 2]);
+
+        for bytes in [vec![], vec![42], vec![1, 1], vec![0; 3], vec![0; 131], mixed] {
+            let data = compress(&bytes);
+            assert_eq!(decompress(&data, bytes.len()).unwrap(), bytes);
+        }
+
+        // Zeroes compress well.
+        assert_eq!(compress(&[0; 1300]).len(), 20);
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        let data = compress(&[1, 2, 3, 3, 3, 3]);
+        for len in [0, 5, 7] {
+            assert_eq!(decompress(&data, len).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+        for end in 1..data.len() {
+            assert!(decompress(&data[..end], 6).is_err());
+        }
+
+        // More than the data could hold, without allocating it.
+        assert!(decompress(&data, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let snapshot = Snapshot {
+            regions: vec![region("player", 0x1000, &[0; 64]), region("pos", 0x2000, &[1, 2, 3])],
+        };
+        assert_eq!(decode(&encode(&snapshot)).unwrap(), snapshot);
+        assert!(decode(&encode(&Snapshot { regions: vec![] })).unwrap().regions.is_empty());
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let snapshot = Snapshot { regions: vec![region("player", 0x1000, &[1, 2, 3, 4])] };
+        let bytes = encode(&snapshot);
+
+        for end in 0..bytes.len() {
+            assert_eq!(decode(&bytes[..end]).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(decode(&bad_magic).is_err());
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 2;
+        assert!(decode(&bad_version).is_err());
+
+        // The region length is read right after the name and the address.
+        let len_offset = 4 + 4 + 4 + 4 + "player".len() + 8;
+        let mut oversized = bytes.clone();
+        oversized[len_offset..len_offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(decode(&oversized).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,189 @@
+//! Running user logic at a controlled cadence, off the render thread.
+//!
+//! Plenty of mod logic doesn't need to run every frame: scanning a folder,
+//! searching the game's memory for a value, polling a web service. Running it
+//! from [`ImguiRenderLoop::render`](crate::ImguiRenderLoop::render) costs
+//! frame time, and throttling it means hand-rolled counters. Tasks registered
+//! here are driven by the frames the overlay renders, and run on a background
+//! worker thread instead:
+//!
+//! - [`every_n_frames`] runs a task once every `n` frames;
+//! - [`every_duration`] runs a task once per period of time;
+//! - [`when_idle`] runs a task whenever no other task is waiting or running.
+//!
+//! Tasks run one at a time. A task still running when it is due again skips
+//! that run instead of queueing up, so a slow task never piles up behind
+//! itself. A panicking task is logged and keeps being scheduled.
+//!
+//! Example usage:
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use hudhook::schedule;
+//!
+//! // Rescan the save folder every 5 seconds.
+//! let scan = schedule::every_duration(Duration::from_secs(5), || {
+//!     // std::fs::read_dir(...)
+//! });
+//!
+//! // Later, e.g. when the scan is disabled from the UI:
+//! schedule::cancel(scan);
+//! ```
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tracing::error;
+
+use crate::game_thread::panic_message;
+
+type Callback = Arc<Mutex<dyn FnMut() + Send>>;
+type Job = Box<dyn FnOnce() + Send>;
+
+static TASKS: Mutex<Vec<Task>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static FRAME: AtomicU64 = AtomicU64::new(0);
+static WORKER: Mutex<Option<(Sender<Job>, JoinHandle<()>)>> = Mutex::new(None);
+// Jobs sent to the worker that didn't return yet.
+static PENDING_JOBS: AtomicUsize = AtomicUsize::new(0);
+
+/// Identifies a scheduled task, to [`cancel`] it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaskId(u64);
+
+enum Cadence {
+    Frames { n: u64, next: u64 },
+    Period { period: Duration, next: Instant },
+    Idle,
+}
+
+struct Task {
+    id: TaskId,
+    cadence: Cadence,
+    callback: Callback,
+    running: Arc<AtomicBool>,
+}
+
+/// Run `f` once every `n` frames, starting `n` frames from now.
+pub fn every_n_frames<F: FnMut() + Send + 'static>(n: u64, f: F) -> TaskId {
+    let n = n.max(1);
+    add(Cadence::Frames { n, next: FRAME.load(Ordering::SeqCst) + n }, f)
+}
+
+/// Run `f` once per `period`, starting `period` from now. Checked once per
+/// frame, so periods shorter than a frame run once per frame.
+pub fn every_duration<F: FnMut() + Send + 'static>(period: Duration, f: F) -> TaskId {
+    add(Cadence::Period { period, next: Instant::now() + period }, f)
+}
+
+/// Run `f` whenever no other task is waiting or running, at most once per
+/// frame.
+pub fn when_idle<F: FnMut() + Send + 'static>(f: F) -> TaskId {
+    add(Cadence::Idle, f)
+}
+
+/// Stop scheduling the task `id`. A run already started completes. Returns
+/// `false` if the task doesn't exist.
+pub fn cancel(id: TaskId) -> bool {
+    let mut tasks = TASKS.lock();
+    let len = tasks.len();
+    tasks.retain(|task| task.id != id);
+    tasks.len() != len
+}
+
+fn add<F: FnMut() + Send + 'static>(cadence: Cadence, f: F) -> TaskId {
+    let id = TaskId(NEXT_ID.fetch_add(1, Ordering::SeqCst));
+    TASKS.lock().push(Task {
+        id,
+        cadence,
+        callback: Arc::new(Mutex::new(f)),
+        running: Arc::new(AtomicBool::new(false)),
+    });
+    id
+}
+
+// Send the tasks due this frame to the worker. Called once per rendered frame.
+pub(crate) fn tick() {
+    let frame = FRAME.fetch_add(1, Ordering::SeqCst) + 1;
+    let now = Instant::now();
+
+    let mut tasks = TASKS.lock();
+    if tasks.is_empty() {
+        return;
+    }
+
+    let mut dispatched = false;
+    for task in tasks.iter_mut() {
+        let due = match &mut task.cadence {
+            Cadence::Frames { n, next } if frame >= *next => {
+                *next = frame + *n;
+                true
+            },
+            Cadence::Period { period, next } if now >= *next => {
+                // Runs missed while the game didn't render are skipped.
+                *next += *period;
+                if *next < now {
+                    *next = now + *period;
+                }
+                true
+            },
+            _ => false,
+        };
+
+        if due && dispatch(task) {
+            dispatched = true;
+        }
+    }
+
+    if dispatched || PENDING_JOBS.load(Ordering::SeqCst) > 0 {
+        return;
+    }
+    for task in tasks.iter().filter(|task| matches!(task.cadence, Cadence::Idle)) {
+        dispatch(task);
+    }
+}
+
+// Drop the tasks and stop the worker, waiting for the runs already sent to it,
+// before the DLL is unloaded.
+pub(crate) fn clear() {
+    TASKS.lock().clear();
+    let worker = WORKER.lock().take();
+    if let Some((tx, thread)) = worker {
+        drop(tx);
+        thread.join().ok();
+    }
+}
+
+// Send a run of `task` to the worker, unless one is still in progress.
+fn dispatch(task: &Task) -> bool {
+    if task.running.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+
+    let callback = Arc::clone(&task.callback);
+    let running = Arc::clone(&task.running);
+    let job: Job = Box::new(move || {
+        if let Err(e) = panic::catch_unwind(AssertUnwindSafe(|| (&mut *callback.lock())())) {
+            error!("Scheduled task panicked: {}", panic_message(&*e));
+        }
+        running.store(false, Ordering::SeqCst);
+        PENDING_JOBS.fetch_sub(1, Ordering::SeqCst);
+    });
+
+    PENDING_JOBS.fetch_add(1, Ordering::SeqCst);
+    let mut worker = WORKER.lock();
+    let (tx, _) = worker.get_or_insert_with(|| {
+        let (tx, rx) = mpsc::channel::<Job>();
+        (tx, thread::spawn(move || rx.into_iter().for_each(|job| job())))
+    });
+    if tx.send(job).is_err() {
+        task.running.store(false, Ordering::SeqCst);
+        PENDING_JOBS.fetch_sub(1, Ordering::SeqCst);
+        return false;
+    }
+    true
+}
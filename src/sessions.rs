@@ -0,0 +1,312 @@
+//! Performance statistics of the overlay, over a session and across sessions.
+//!
+//! A session lasts from the hooks being applied to them being removed. Every
+//! frame rendered in the meantime is aggregated: how long the game took to
+//! produce it, and how long hudhook took to build and render the overlay.
+//! With [`set_path`], each session is appended to a file when it ends, so
+//! that users can check the cost of the overlay over time and across hudhook
+//! versions, in the window drawn by [`draw`].
+//!
+//! Unlike a [benchmark](crate::benchmark), which measures a standard UI for a
+//! few seconds, sessions measure the mod's own UI as it is actually used.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::sessions;
+//!
+//! // Before applying the hooks:
+//! sessions::set_path(sessions::default_path());
+//!
+//! // From `ImguiRenderLoop::render`:
+//! // ui.window("Performance history").build(|| sessions::draw(ui));
+//! ```
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use imgui::Ui;
+use parking_lot::Mutex;
+use tracing::{debug, error};
+
+use crate::util;
+use crate::version::HUDHOOK_VERSION;
+
+static PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+// Past sessions, oldest first, as read from the file and appended to since.
+static HISTORY: Mutex<Vec<SessionStats>> = Mutex::new(Vec::new());
+
+// Render times are counted in buckets of this width, up to `BUCKETS` of them:
+// longer frames count in the last bucket.
+const BUCKET_WIDTH: Duration = Duration::from_micros(10);
+const BUCKETS: usize = 5000;
+
+/// Aggregated timings of a session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionStats {
+    /// The hudhook version the session ran with.
+    pub version: String,
+    /// When the session started, in seconds since the Unix epoch.
+    pub started: u64,
+    /// How long the hooks were applied.
+    pub hooked: Duration,
+    /// Number of frames the overlay rendered.
+    pub frames: u64,
+    /// Average time between two frames, game included.
+    pub frame_time: Duration,
+    /// Average time hudhook took to build and render a frame.
+    pub render_time: Duration,
+    /// Median time hudhook took to build and render a frame.
+    pub render_time_p50: Duration,
+    /// 95th percentile of the time hudhook took to build and render a frame.
+    pub render_time_p95: Duration,
+    /// 99th percentile of the time hudhook took to build and render a frame.
+    pub render_time_p99: Duration,
+}
+
+impl SessionStats {
+    /// Share of the frame time spent by hudhook, in percent.
+    pub fn overlay_share(&self) -> f32 {
+        if self.frame_time.is_zero() {
+            return 0.0;
+        }
+        self.render_time.as_secs_f32() / self.frame_time.as_secs_f32() * 100.0
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            self.version,
+            self.started,
+            self.hooked.as_millis(),
+            self.frames,
+            self.frame_time.as_micros(),
+            self.render_time.as_micros(),
+            self.render_time_p50.as_micros(),
+            self.render_time_p95.as_micros(),
+            self.render_time_p99.as_micros(),
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let version = fields.next()?.to_string();
+        let mut number = || fields.next()?.parse::<u64>().ok();
+        let (started, hooked, frames) = (number()?, number()?, number()?);
+        let mut micros = || number().map(Duration::from_micros);
+
+        Some(Self {
+            version,
+            started,
+            hooked: Duration::from_millis(hooked),
+            frames,
+            frame_time: micros()?,
+            render_time: micros()?,
+            render_time_p50: micros()?,
+            render_time_p95: micros()?,
+            render_time_p99: micros()?,
+        })
+    }
+}
+
+struct Session {
+    started: SystemTime,
+    start: Instant,
+    frames: u64,
+    first_frame: Option<Instant>,
+    last_frame: Option<Instant>,
+    render_time: Duration,
+    histogram: Vec<u32>,
+}
+
+impl Session {
+    fn stats(&self) -> SessionStats {
+        let frames = self.frames;
+        // The first frame has no previous frame to measure the time from.
+        let frame_time = match (self.first_frame, self.last_frame) {
+            (Some(first), Some(last)) if frames > 1 => (last - first) / (frames - 1) as u32,
+            _ => Duration::ZERO,
+        };
+
+        let percentile = |p: u64| {
+            let rank = frames.saturating_sub(1) * p / 100;
+            let mut count = 0;
+            for (i, &n) in self.histogram.iter().enumerate() {
+                count += n as u64;
+                if count > rank {
+                    return BUCKET_WIDTH * i as u32;
+                }
+            }
+            BUCKET_WIDTH * BUCKETS as u32
+        };
+
+        SessionStats {
+            version: HUDHOOK_VERSION.to_string(),
+            started: self.started.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            hooked: self.start.elapsed(),
+            frames,
+            frame_time,
+            render_time: self.render_time.checked_div(frames as u32).unwrap_or_default(),
+            render_time_p50: percentile(50),
+            render_time_p95: percentile(95),
+            render_time_p99: percentile(99),
+        }
+    }
+}
+
+/// File sessions are persisted to by default: `hudhook-sessions.txt` next to
+/// the DLL [`hudhook`](crate) is linked into.
+pub fn default_path() -> Option<PathBuf> {
+    Some(util::get_dll_path()?.parent()?.join("hudhook-sessions.txt"))
+}
+
+/// Append each session to the file at `path` when it ends, one per line, and
+/// read the past sessions back from it. `None`, the default, keeps sessions
+/// in memory only.
+pub fn set_path(path: Option<PathBuf>) {
+    let history = match &path {
+        Some(path) => read(path),
+        None => Vec::new(),
+    };
+    *HISTORY.lock() = history;
+    *PATH.lock() = path;
+}
+
+/// The statistics of the session in progress.
+pub fn current() -> Option<SessionStats> {
+    SESSION.lock().as_ref().map(Session::stats)
+}
+
+/// The past sessions, oldest first.
+pub fn history() -> Vec<SessionStats> {
+    HISTORY.lock().clone()
+}
+
+/// Draw the past sessions and the current one into the current window, with
+/// the render time charted across sessions.
+pub fn draw(ui: &Ui) {
+    let history = history();
+    let current = current();
+    let sessions: Vec<&SessionStats> = history.iter().chain(current.as_ref()).collect();
+
+    if sessions.is_empty() {
+        ui.text_disabled("No sessions");
+        return;
+    }
+
+    if sessions.len() > 1 {
+        let width = ui.content_region_avail()[0];
+        let avg: Vec<f32> = sessions.iter().map(|s| ms(s.render_time)).collect();
+        let p99: Vec<f32> = sessions.iter().map(|s| ms(s.render_time_p99)).collect();
+        let max = p99.iter().copied().fold(0.1, f32::max);
+        ui.plot_lines("##hudhook_sessions_avg", &avg)
+            .overlay_text("Render time, average (ms)")
+            .scale_min(0.0)
+            .scale_max(max)
+            .graph_size([width, 60.0])
+            .build();
+        ui.plot_lines("##hudhook_sessions_p99", &p99)
+            .overlay_text("Render time, 99th percentile (ms)")
+            .scale_min(0.0)
+            .scale_max(max)
+            .graph_size([width, 60.0])
+            .build();
+    }
+
+    ui.columns(7, "##hudhook_sessions", false);
+    for header in ["Version", "Hooked", "Frames", "Frame", "Render", "p50 / p95 / p99", "Share"] {
+        ui.text_disabled(header);
+        ui.next_column();
+    }
+    for (i, session) in sessions.iter().enumerate().rev() {
+        if current.is_some() && i == sessions.len() - 1 {
+            ui.text(format!("{} (current)", session.version));
+        } else {
+            ui.text(&session.version);
+        }
+        ui.next_column();
+        ui.text(format!("{} min", session.hooked.as_secs() / 60));
+        ui.next_column();
+        ui.text(session.frames.to_string());
+        ui.next_column();
+        ui.text(format!("{:.2} ms", ms(session.frame_time)));
+        ui.next_column();
+        ui.text(format!("{:.2} ms", ms(session.render_time)));
+        ui.next_column();
+        ui.text(format!(
+            "{:.2} / {:.2} / {:.2} ms",
+            ms(session.render_time_p50),
+            ms(session.render_time_p95),
+            ms(session.render_time_p99)
+        ));
+        ui.next_column();
+        ui.text(format!("{:.1} %", session.overlay_share()));
+        ui.next_column();
+    }
+    ui.columns(1, "##hudhook_sessions", false);
+}
+
+// Start a session when the hooks are applied.
+pub(crate) fn start() {
+    *SESSION.lock() = Some(Session {
+        started: SystemTime::now(),
+        start: Instant::now(),
+        frames: 0,
+        first_frame: None,
+        last_frame: None,
+        render_time: Duration::ZERO,
+        histogram: vec![0; BUCKETS],
+    });
+}
+
+// Record the time the pipeline took to render a frame.
+pub(crate) fn record_frame(render_time: Duration) {
+    let now = Instant::now();
+
+    let mut session = SESSION.lock();
+    let Some(session) = session.as_mut() else {
+        return;
+    };
+
+    session.frames += 1;
+    session.first_frame.get_or_insert(now);
+    session.last_frame = Some(now);
+    session.render_time += render_time;
+    let bucket = (render_time.as_nanos() / BUCKET_WIDTH.as_nanos()) as usize;
+    session.histogram[bucket.min(BUCKETS - 1)] += 1;
+}
+
+// End the session when the hooks are removed, and persist it.
+pub(crate) fn end() {
+    let Some(stats) = SESSION.lock().take().map(|session| session.stats()) else {
+        return;
+    };
+    debug!("Session ended: {stats:?}");
+
+    if let Some(path) = PATH.lock().as_deref() {
+        if let Err(e) = append(path, &stats) {
+            error!("Couldn't save the session to {path:?}: {e:?}");
+        }
+    }
+    HISTORY.lock().push(stats);
+}
+
+fn read(path: &Path) -> Vec<SessionStats> {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents.lines().filter_map(SessionStats::from_line).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            error!("Couldn't read the sessions from {path:?}: {e:?}");
+            Vec::new()
+        },
+    }
+}
+
+fn append(path: &Path, stats: &SessionStats) -> io::Result<()> {
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(stats.to_line().as_bytes())
+}
+
+fn ms(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
@@ -0,0 +1,120 @@
+//! A small, stable entry point for the most common use case: one overlay, in
+//! a DLL injected into a game.
+//!
+//! [`run`] renders a render loop with whichever graphics API the game uses,
+//! and [`eject`] removes the overlay and unloads the DLL. Unlike the
+//! [builder](HudhookBuilder) and the hook objects they are built on,
+//! these functions keep their signatures across releases, so that simple
+//! mods only implementing [`ImguiRenderLoop::render`] keep building when
+//! [`hudhook`](crate) is upgraded.
+//!
+//! The graphics API is picked from the modules loaded in the game, preferring
+//! DirectX 12, then DirectX 11, DirectX 9 and OpenGL 3, among the APIs enabled
+//! by the crate features. If none of them is loaded yet, the overlay is
+//! applied as soon as the game loads one. Use the builder directly to pick
+//! the API, or for anything else.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::windows::Win32::Foundation::HINSTANCE;
+//! use hudhook::windows::Win32::System::SystemServices::DLL_PROCESS_ATTACH;
+//! use hudhook::ImguiRenderLoop;
+//!
+//! struct MyRenderLoop;
+//!
+//! impl ImguiRenderLoop for MyRenderLoop {
+//!     fn render(&mut self, ui: &mut hudhook::imgui::Ui) {
+//!         ui.window("My mod").build(|| {
+//!             if ui.button("Eject") {
+//!                 hudhook::simple::eject();
+//!             }
+//!         });
+//!     }
+//! }
+//!
+//! #[no_mangle]
+//! pub unsafe extern "stdcall" fn DllMain(_: HINSTANCE, reason: u32, _: *mut std::ffi::c_void) {
+//!     if reason == DLL_PROCESS_ATTACH {
+//!         hudhook::simple::run(MyRenderLoop);
+//!     }
+//! }
+//! ```
+use std::thread;
+
+use tracing::error;
+use windows::core::HSTRING;
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+
+use crate::util::current_module;
+use crate::{modules, Hudhook, HudhookBuilder, ImguiRenderLoop};
+
+// Graphics modules, in the order their API is preferred.
+const MODULES: &[&str] = &[
+    #[cfg(feature = "dx12")]
+    "d3d12.dll",
+    #[cfg(feature = "dx11")]
+    "d3d11.dll",
+    #[cfg(feature = "dx9")]
+    "d3d9.dll",
+    #[cfg(feature = "opengl3")]
+    "opengl32.dll",
+];
+
+/// Render `render_loop` over the game, with the graphics API it uses.
+///
+/// Returns right away: the hooks are applied on a thread of their own, so
+/// this can be called from `DllMain`. If they can't be applied, the error is
+/// logged and the DLL is ejected.
+pub fn run<T: ImguiRenderLoop + Send + Sync + 'static>(render_loop: T) {
+    thread::spawn(move || {
+        let hmodule = current_module();
+        let res = modules::apply_when_loaded(MODULES, move || {
+            let builder = Hudhook::builder();
+            let builder = match hmodule {
+                Some(hmodule) => builder.with_hmodule(hmodule.into()),
+                None => builder,
+            };
+            with_loaded_api(builder, render_loop).build()
+        });
+
+        if let Err(e) = res {
+            error!("Couldn't watch for graphics modules: {e:?}");
+            crate::eject();
+        }
+    });
+}
+
+/// Remove the overlay and unload the DLL, e.g. from a button of the render
+/// loop. See [`hudhook::eject`](crate::eject).
+pub fn eject() {
+    crate::eject();
+}
+
+// Add the hooks of the preferred API among the loaded modules.
+fn with_loaded_api<T: ImguiRenderLoop + Send + Sync + 'static>(
+    builder: HudhookBuilder,
+    render_loop: T,
+) -> HudhookBuilder {
+    let loaded = |module: &str| unsafe { GetModuleHandleW(&HSTRING::from(module)) }.is_ok();
+
+    #[cfg(feature = "dx12")]
+    if loaded("d3d12.dll") {
+        return builder.with::<crate::hooks::dx12::ImguiDx12Hooks>(render_loop);
+    }
+    #[cfg(feature = "dx11")]
+    if loaded("d3d11.dll") {
+        return builder.with::<crate::hooks::dx11::ImguiDx11Hooks>(render_loop);
+    }
+    #[cfg(feature = "dx9")]
+    if loaded("d3d9.dll") {
+        return builder.with::<crate::hooks::dx9::ImguiDx9Hooks>(render_loop);
+    }
+    #[cfg(feature = "opengl3")]
+    if loaded("opengl32.dll") {
+        return builder.with::<crate::hooks::opengl3::ImguiOpenGl3Hooks>(render_loop);
+    }
+
+    let _ = (loaded, render_loop);
+    error!("None of {MODULES:?} is loaded, not rendering the overlay");
+    builder
+}
@@ -0,0 +1,213 @@
+//! Render loop state that survives ejecting and reinjecting the DLL.
+//!
+//! Wrap a render loop implementing [`SerializableState`] in [`Persistent`] to
+//! keep its state, along with the positions and sizes of its `imgui` windows,
+//! across a hot reload. The state is written to a file in the temporary
+//! directory when the render loop is dropped on [`eject`](crate::eject), and
+//! read back and deleted when the render loop is created after the next
+//! injection in the same process.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::hooks::dx11::ImguiDx11Hooks;
+//! use hudhook::state::{Persistent, SerializableState};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Default, Serialize, Deserialize)]
+//! struct Config {
+//!     volume: f32,
+//! }
+//!
+//! #[derive(Default)]
+//! struct MyRenderLoop {
+//!     config: Config,
+//! }
+//!
+//! impl hudhook::ImguiRenderLoop for MyRenderLoop {
+//!     fn render(&mut self, ui: &mut imgui::Ui) {
+//!         ui.window("Settings").build(|| {
+//!             ui.slider("Volume", 0.0, 1.0, &mut self.config.volume);
+//!         });
+//!     }
+//! }
+//!
+//! impl SerializableState for MyRenderLoop {
+//!     type State = Config;
+//!
+//!     fn save_state(&self) -> Config {
+//!         Config { volume: self.config.volume }
+//!     }
+//!
+//!     fn restore_state(&mut self, state: Config) {
+//!         self.config = state;
+//!     }
+//! }
+//!
+//! hudhook::hudhook!(ImguiDx11Hooks, Persistent::new(MyRenderLoop::default()));
+//! ```
+use std::any::type_name;
+use std::path::PathBuf;
+use std::{env, fs};
+
+use imgui::{Context, FontAtlas, Io, Ui};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+use windows::Win32::System::Threading::GetCurrentProcessId;
+
+use crate::anchors::Region;
+use crate::{names, Activation, ImguiRenderLoop, MessageFilter, RenderContext};
+
+/// Runtime state of a render loop that can be saved on eject and restored on
+/// the next injection.
+pub trait SerializableState {
+    /// The saved state.
+    type State: Serialize + DeserializeOwned;
+
+    /// Capture the current state.
+    fn save_state(&self) -> Self::State;
+
+    /// Restore a state previously captured by
+    /// [`save_state`](Self::save_state).
+    fn restore_state(&mut self, state: Self::State);
+
+    /// Key identifying the state among the ones saved in the same process.
+    ///
+    /// Defaults to the type name of the render loop, which is stable as long
+    /// as the type isn't renamed or moved.
+    fn state_key() -> String {
+        type_name::<Self>().to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedState<S> {
+    state: S,
+    windows: String,
+}
+
+/// A render loop whose [`SerializableState`] and `imgui` window settings are
+/// persisted across eject and reinject.
+pub struct Persistent<T: SerializableState> {
+    render_loop: T,
+    windows: String,
+}
+
+impl<T: SerializableState> Persistent<T> {
+    /// Wrap `render_loop`, restoring the state saved by a previous injection
+    /// if there is one.
+    pub fn new(mut render_loop: T) -> Self {
+        let path = state_path::<T>();
+        let mut windows = String::new();
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                match serde_json::from_str::<SavedState<T::State>>(&contents) {
+                    Ok(saved) => {
+                        debug!("Restoring state from {path:?}");
+                        render_loop.restore_state(saved.state);
+                        windows = saved.windows;
+                    },
+                    // The state layout may have changed since it was saved.
+                    Err(e) => warn!("Could not restore state from {path:?}: {e:?}"),
+                }
+
+                if let Err(e) = fs::remove_file(&path) {
+                    error!("Could not remove {path:?}: {e:?}");
+                }
+            },
+            Err(e) => debug!("No state to restore from {path:?}: {e:?}"),
+        }
+
+        Self { render_loop, windows }
+    }
+
+    /// The wrapped render loop.
+    pub fn inner(&self) -> &T {
+        &self.render_loop
+    }
+
+    /// The wrapped render loop.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.render_loop
+    }
+}
+
+impl<T: SerializableState> Drop for Persistent<T> {
+    fn drop(&mut self) {
+        let path = state_path::<T>();
+        let saved = SavedState {
+            state: self.render_loop.save_state(),
+            windows: std::mem::take(&mut self.windows),
+        };
+
+        let res = serde_json::to_string(&saved)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| fs::write(&path, contents).map_err(|e| e.to_string()));
+
+        match res {
+            Ok(()) => debug!("Saved state to {path:?}"),
+            Err(e) => error!("Could not save state to {path:?}: {e}"),
+        }
+    }
+}
+
+impl<T: SerializableState + ImguiRenderLoop> ImguiRenderLoop for Persistent<T> {
+    fn setup_fonts(&mut self, fonts: &mut FontAtlas) {
+        self.render_loop.setup_fonts(fonts);
+    }
+
+    fn initialize<'a>(&'a mut self, ctx: &mut Context, render_context: &'a mut dyn RenderContext) {
+        if !self.windows.is_empty() {
+            ctx.load_ini_settings(&self.windows);
+        }
+
+        self.render_loop.initialize(ctx, render_context);
+    }
+
+    fn before_render<'a>(
+        &'a mut self,
+        ctx: &mut Context,
+        render_context: &'a mut dyn RenderContext,
+    ) {
+        // Without an ini file, imgui only flags when the settings changed.
+        if ctx.io().want_save_ini_settings {
+            self.windows.clear();
+            ctx.save_ini_settings(&mut self.windows);
+            ctx.io_mut().want_save_ini_settings = false;
+        }
+
+        self.render_loop.before_render(ctx, render_context);
+    }
+
+    fn render(&mut self, ui: &mut Ui) {
+        self.render_loop.render(ui);
+    }
+
+    fn on_wnd_proc(&self, hwnd: HWND, umsg: u32, wparam: WPARAM, lparam: LPARAM) {
+        self.render_loop.on_wnd_proc(hwnd, umsg, wparam, lparam);
+    }
+
+    fn message_filter(&self, io: &Io) -> MessageFilter {
+        self.render_loop.message_filter(io)
+    }
+
+    fn passthrough_regions(&self, io: &Io) -> Vec<Region> {
+        self.render_loop.passthrough_regions(io)
+    }
+
+    fn activation(&self) -> Activation {
+        self.render_loop.activation()
+    }
+}
+
+// Path of the file holding the state of `T` for the current process, e.g.
+// `%TEMP%\hudhook-state-1234-my_mod__MyRenderLoop.json`.
+fn state_path<T: SerializableState>() -> PathBuf {
+    let key: String =
+        T::state_key().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+
+    env::temp_dir()
+        .join(format!("{}-state-{}-{key}.json", names::prefix(), unsafe { GetCurrentProcessId() }))
+}
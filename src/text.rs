@@ -0,0 +1,116 @@
+//! Outlined and drop-shadowed text, for HUD elements drawn over bright game
+//! scenes.
+//!
+//! All passes of a styled text are appended to the same draw list and share
+//! the font atlas texture, so the backend draws them in a single draw call.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::text::{DrawListTextExt, TextStyle};
+//!
+//! // In `ImguiRenderLoop::render`:
+//! let style = TextStyle::default().with_outline([0.0, 0.0, 0.0, 1.0], 1.0);
+//! ui.get_foreground_draw_list().add_styled_text([10.0, 10.0], "Speed: 42", &style);
+//! ```
+use imgui::{DrawListMut, ImColor32, Ui};
+
+// Unit offsets of the outline passes, in all eight directions.
+const OUTLINE_DIRECTIONS: [[f32; 2]; 8] =
+    [[-1.0, -1.0], [0.0, -1.0], [1.0, -1.0], [-1.0, 0.0], [1.0, 0.0], [-1.0, 1.0], [0.0, 1.0], [
+        1.0, 1.0,
+    ]];
+
+/// An outline around text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outline {
+    /// Color of the outline.
+    pub color: ImColor32,
+    /// Thickness of the outline in pixels.
+    pub thickness: f32,
+}
+
+/// A drop shadow behind text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    /// Color of the shadow.
+    pub color: ImColor32,
+    /// Offset of the shadow from the text in pixels.
+    pub offset: [f32; 2],
+}
+
+/// How to draw a piece of text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    /// Color of the text itself.
+    pub color: ImColor32,
+    /// Optional outline.
+    pub outline: Option<Outline>,
+    /// Optional drop shadow, drawn behind the outline.
+    pub shadow: Option<Shadow>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self { color: ImColor32::WHITE, outline: None, shadow: None }
+    }
+}
+
+impl TextStyle {
+    /// Set the text color.
+    pub fn with_color(mut self, color: impl Into<ImColor32>) -> Self {
+        self.color = color.into();
+        self
+    }
+
+    /// Add an outline of the given color and thickness.
+    pub fn with_outline(mut self, color: impl Into<ImColor32>, thickness: f32) -> Self {
+        self.outline = Some(Outline { color: color.into(), thickness });
+        self
+    }
+
+    /// Add a drop shadow of the given color and offset.
+    pub fn with_shadow(mut self, color: impl Into<ImColor32>, offset: [f32; 2]) -> Self {
+        self.shadow = Some(Shadow { color: color.into(), offset });
+        self
+    }
+}
+
+/// Extension trait to draw styled text on an `imgui` draw list.
+pub trait DrawListTextExt {
+    /// Draw `text` at `pos` with the given style.
+    fn add_styled_text(&self, pos: [f32; 2], text: &str, style: &TextStyle);
+}
+
+impl DrawListTextExt for DrawListMut<'_> {
+    fn add_styled_text(&self, [x, y]: [f32; 2], text: &str, style: &TextStyle) {
+        if let Some(Shadow { color, offset: [dx, dy] }) = style.shadow {
+            self.add_text([x + dx, y + dy], color, text);
+        }
+
+        if let Some(Outline { color, thickness }) = style.outline {
+            // Thicker outlines are built from rings of one pixel each, so that
+            // there are no gaps between the diagonal and straight passes.
+            let rings = thickness.max(0.0).ceil() as usize;
+            for ring in 1..=rings {
+                let r = (ring as f32).min(thickness);
+                for [dx, dy] in OUTLINE_DIRECTIONS {
+                    self.add_text([x + dx * r, y + dy * r], color, text);
+                }
+            }
+        }
+
+        self.add_text([x, y], style.color, text);
+    }
+}
+
+/// Draw styled text at the cursor position of the current window, advancing
+/// the cursor like `Ui::text` does.
+pub fn styled_text(ui: &Ui, text: &str, style: &TextStyle) {
+    let pos = ui.cursor_screen_pos();
+    let size = ui.calc_text_size(text);
+
+    ui.get_window_draw_list().add_styled_text(pos, text, style);
+
+    let extent = style.outline.map_or(0.0, |outline| outline.thickness.max(0.0));
+    ui.dummy([size[0] + extent, size[1] + extent]);
+}
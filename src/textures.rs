@@ -0,0 +1,125 @@
+//! Loading textures from anywhere, not only from the render loop callbacks.
+//!
+//! [`RenderContext::load_texture`](crate::RenderContext::load_texture) uploads
+//! an image right away, but the render context is only handed to
+//! [`ImguiRenderLoop::initialize`](crate::ImguiRenderLoop::initialize) and
+//! [`ImguiRenderLoop::before_render`](crate::ImguiRenderLoop::before_render).
+//! Images that become available later, e.g. an icon downloaded on a
+//! background thread or a screenshot taken while building the UI, can be
+//! queued with [`load`] or [`replace`] instead: they are uploaded before the
+//! next frame, and the returned [`PendingTexture`] yields the [`TextureId`]
+//! to draw them with once they are.
+//!
+//! Like any [`TextureId`], those of textures loaded here are invalid once the
+//! renderer is [reinitialized](crate::reinitialize_renderer).
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::textures::{self, PendingTexture};
+//!
+//! // E.g. from a background thread, with the RGBA pixels of a 64x64 icon.
+//! let icon: PendingTexture = textures::load(vec![0xff; 64 * 64 * 4], 64, 64);
+//!
+//! // In `ImguiRenderLoop::render`:
+//! // if let Some(texture_id) = icon.texture_id() {
+//! //     ui.image_button("Icon", texture_id, [64.0, 64.0]);
+//! // }
+//! ```
+use std::mem;
+use std::sync::Arc;
+
+use imgui::TextureId;
+use parking_lot::Mutex;
+use tracing::error;
+use windows::core::Error;
+use windows::Win32::Foundation::E_INVALIDARG;
+
+use crate::RenderContext;
+
+static QUEUE: Mutex<Vec<Upload>> = Mutex::new(Vec::new());
+
+type Slot = Arc<Mutex<Option<Result<TextureId, Error>>>>;
+
+struct Upload {
+    texture_id: Option<TextureId>,
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    slot: Slot,
+}
+
+/// A texture queued with [`load`] or [`replace`].
+#[derive(Clone)]
+pub struct PendingTexture(Slot);
+
+impl PendingTexture {
+    /// The texture to draw, once it is uploaded.
+    pub fn texture_id(&self) -> Option<TextureId> {
+        self.0.lock().as_ref().and_then(|res| res.as_ref().ok().copied())
+    }
+
+    /// Whether the upload is done, successfully or not.
+    pub fn is_done(&self) -> bool {
+        self.0.lock().is_some()
+    }
+
+    /// Why the upload failed, if it did.
+    pub fn error(&self) -> Option<Error> {
+        self.0.lock().as_ref().and_then(|res| res.as_ref().err().cloned())
+    }
+}
+
+/// Queue uploading `data`, `width` by `height` RGBA pixels, to a new texture
+/// before the next frame.
+pub fn load(data: Vec<u8>, width: u32, height: u32) -> PendingTexture {
+    enqueue(None, data, width, height)
+}
+
+/// Queue uploading `data`, `width` by `height` RGBA pixels, to the existing
+/// texture `texture_id`, replacing its content, before the next frame.
+pub fn replace(texture_id: TextureId, data: Vec<u8>, width: u32, height: u32) -> PendingTexture {
+    enqueue(Some(texture_id), data, width, height)
+}
+
+fn enqueue(
+    texture_id: Option<TextureId>,
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> PendingTexture {
+    let slot = Slot::default();
+
+    if data.len() as u64 != width as u64 * height as u64 * 4 {
+        error!("Texture data is {} bytes, not {width}x{height} RGBA pixels", data.len());
+        *slot.lock() = Some(Err(Error::from_hresult(E_INVALIDARG)));
+    } else {
+        let upload = Upload { texture_id, data, width, height, slot: Arc::clone(&slot) };
+        QUEUE.lock().push(upload);
+    }
+
+    PendingTexture(slot)
+}
+
+// Upload the queued textures with the render context of a pipeline. Called
+// between frames.
+pub(crate) fn upload(render_context: &mut dyn RenderContext) {
+    let queue = mem::take(&mut *QUEUE.lock());
+
+    for Upload { texture_id, data, width, height, slot } in queue {
+        let res = match texture_id {
+            Some(texture_id) => {
+                render_context.replace_texture(texture_id, &data, width, height).map(|_| texture_id)
+            },
+            None => render_context.load_texture(&data, width, height),
+        };
+        if let Err(e) = &res {
+            error!("Couldn't upload texture: {e:?}");
+        }
+        *slot.lock() = Some(res);
+    }
+}
+
+// Drop the uploads still queued when the hooks are removed.
+pub(crate) fn clear() {
+    QUEUE.lock().clear();
+}
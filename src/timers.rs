@@ -0,0 +1,436 @@
+//! Timers and stopwatches for speedrun tooling.
+//!
+//! Timers live in a global registry, independent of the renderer, so they
+//! keep running across renderer rebuilds (e.g. on device resets) and can be
+//! driven from any thread: an IPC listener, a memory-reading loop, or the
+//! render loop itself.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::timers::{self, TimerEvent};
+//!
+//! // Set up the splits once.
+//! timers::timer("run").lock().set_splits(["Tutorial", "Castle", "Final boss"]);
+//!
+//! // From a thread watching the game state:
+//! timers::timer("run").lock().handle(TimerEvent::Start);
+//!
+//! // From `ImguiRenderLoop::render`:
+//! // ui.window("Timer").build(|| timers::draw(ui, &timers::timer("run").lock()));
+//! ```
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use imgui::Ui;
+use parking_lot::Mutex;
+
+static TIMERS: Mutex<Vec<(String, Arc<Mutex<SplitTimer>>)>> = Mutex::new(Vec::new());
+
+const COLOR_AHEAD: [f32; 4] = [0.3, 0.85, 0.3, 1.0];
+const COLOR_BEHIND: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+const COLOR_GOLD: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+const COLOR_NEUTRAL: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+/// Get the timer registered under `name`, creating it if it does not exist.
+pub fn timer(name: &str) -> Arc<Mutex<SplitTimer>> {
+    let mut timers = TIMERS.lock();
+
+    if let Some((_, timer)) = timers.iter().find(|(n, _)| n == name) {
+        return Arc::clone(timer);
+    }
+
+    let timer = Arc::new(Mutex::new(SplitTimer::default()));
+    timers.push((name.to_string(), Arc::clone(&timer)));
+    timer
+}
+
+/// Remove the timer registered under `name` from the registry.
+pub fn remove(name: &str) {
+    TIMERS.lock().retain(|(n, _)| n != name);
+}
+
+/// A high-precision stopwatch that can be paused and resumed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stopwatch {
+    accumulated: Duration,
+    started_at: Option<Instant>,
+}
+
+impl Stopwatch {
+    /// Create a stopped stopwatch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start or resume the stopwatch. Does nothing if already running.
+    pub fn start(&mut self) {
+        if self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+        }
+    }
+
+    /// Pause the stopwatch, keeping the elapsed time.
+    pub fn pause(&mut self) {
+        if let Some(started_at) = self.started_at.take() {
+            self.accumulated += started_at.elapsed();
+        }
+    }
+
+    /// Stop the stopwatch and reset the elapsed time to zero.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Overwrite the elapsed time, e.g. with an in-game time read from
+    /// memory. The running state is preserved.
+    pub fn set_elapsed(&mut self, elapsed: Duration) {
+        self.accumulated = elapsed;
+        if self.started_at.is_some() {
+            self.started_at = Some(Instant::now());
+        }
+    }
+
+    /// Whether the stopwatch is running.
+    pub fn is_running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    /// Total elapsed time.
+    pub fn elapsed(&self) -> Duration {
+        self.accumulated + self.started_at.map(|t| t.elapsed()).unwrap_or_default()
+    }
+}
+
+/// A single segment of a [`SplitTimer`].
+#[derive(Debug, Default, Clone)]
+pub struct Split {
+    /// Display name of the segment.
+    pub name: String,
+    /// Cumulative time at the end of this segment in the comparison run.
+    pub comparison: Option<Duration>,
+    /// Shortest recorded duration of this segment alone.
+    pub best_segment: Option<Duration>,
+    /// Cumulative time at which this segment was split in the current run.
+    /// `None` if it was skipped or not reached yet.
+    pub time: Option<Duration>,
+}
+
+/// An event reported to a [`SplitTimer`], typically from outside the render
+/// loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerEvent {
+    /// Start a new run. Ignored if a run is in progress.
+    Start,
+    /// Complete the current segment.
+    Split,
+    /// Move to the next segment without recording a time.
+    SkipSplit,
+    /// Go back to the previous segment.
+    UndoSplit,
+    /// Pause the timer.
+    Pause,
+    /// Resume a paused timer.
+    Resume,
+    /// Abort the run and return to the initial state.
+    Reset,
+}
+
+/// The state of a [`SplitTimer`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimerPhase {
+    /// No run is in progress.
+    #[default]
+    NotRunning,
+    /// A run is in progress.
+    Running,
+    /// A run is in progress, but the timer is paused.
+    Paused,
+    /// The last segment was split.
+    Ended,
+}
+
+/// A LiveSplit-like timer with a list of segments.
+#[derive(Debug, Default, Clone)]
+pub struct SplitTimer {
+    stopwatch: Stopwatch,
+    splits: Vec<Split>,
+    current: usize,
+    phase: TimerPhase,
+}
+
+impl SplitTimer {
+    /// Replace the segments with new ones, named after `names`. Resets the
+    /// timer.
+    pub fn set_splits<I, S>(&mut self, names: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.reset();
+        self.splits = names
+            .into_iter()
+            .map(|name| Split { name: name.into(), ..Default::default() })
+            .collect();
+    }
+
+    /// The segments of the timer.
+    pub fn splits(&self) -> &[Split] {
+        &self.splits
+    }
+
+    /// Mutable access to the segments, e.g. to load comparisons from disk.
+    pub fn splits_mut(&mut self) -> &mut [Split] {
+        &mut self.splits
+    }
+
+    /// Index of the segment currently being timed.
+    pub fn current_split(&self) -> usize {
+        self.current
+    }
+
+    /// The current phase of the timer.
+    pub fn phase(&self) -> TimerPhase {
+        self.phase
+    }
+
+    /// Total elapsed time of the current run.
+    pub fn elapsed(&self) -> Duration {
+        self.stopwatch.elapsed()
+    }
+
+    /// Mutable access to the underlying stopwatch, e.g. to sync it with an
+    /// in-game timer.
+    pub fn stopwatch_mut(&mut self) -> &mut Stopwatch {
+        &mut self.stopwatch
+    }
+
+    /// Apply an event to the timer. Events that make no sense in the current
+    /// phase are ignored.
+    pub fn handle(&mut self, event: TimerEvent) {
+        use TimerPhase::*;
+
+        match (event, self.phase) {
+            (TimerEvent::Start, NotRunning) => {
+                self.stopwatch.reset();
+                self.stopwatch.start();
+                self.phase = Running;
+            },
+            (TimerEvent::Split, Running) => {
+                let time = self.stopwatch.elapsed();
+                self.record_split(time);
+            },
+            (TimerEvent::SkipSplit, Running | Paused) if self.current + 1 < self.splits.len() => {
+                self.splits[self.current].time = None;
+                self.current += 1;
+            },
+            // The last segment doesn't advance the index when split.
+            (TimerEvent::UndoSplit, Ended) => {
+                self.splits[self.current].time = None;
+                self.stopwatch.start();
+                self.phase = Running;
+            },
+            (TimerEvent::UndoSplit, Running | Paused) if self.current > 0 => {
+                self.current -= 1;
+                self.splits[self.current].time = None;
+            },
+            (TimerEvent::Pause, Running) => {
+                self.stopwatch.pause();
+                self.phase = Paused;
+            },
+            (TimerEvent::Resume, Paused) => {
+                self.stopwatch.start();
+                self.phase = Running;
+            },
+            (TimerEvent::Reset, _) => self.reset(),
+            _ => {},
+        }
+    }
+
+    /// Reset the timer, discarding the current run. Comparisons and best
+    /// segments are kept.
+    pub fn reset(&mut self) {
+        self.stopwatch.reset();
+        self.current = 0;
+        self.phase = TimerPhase::NotRunning;
+        self.splits.iter_mut().for_each(|split| split.time = None);
+    }
+
+    /// Store the current run as the comparison for all segments, if it was
+    /// completed and faster than the existing one.
+    pub fn save_personal_best(&mut self) {
+        if self.phase != TimerPhase::Ended {
+            return;
+        }
+
+        let Some(last) = self.splits.last() else {
+            return;
+        };
+
+        let (Some(time), pb) = (last.time, last.comparison) else {
+            return;
+        };
+
+        if pb.map_or(true, |pb| time < pb) {
+            self.splits.iter_mut().for_each(|split| split.comparison = split.time);
+        }
+    }
+
+    // Record the cumulative time for the current segment and advance.
+    fn record_split(&mut self, time: Duration) {
+        let Some(split) = self.splits.get(self.current) else {
+            return;
+        };
+
+        // The segment time is unknown if the previous segment was skipped.
+        let segment = match self.current {
+            0 => Some(time),
+            _ => self.splits[self.current - 1].time.map(|prev| time.saturating_sub(prev)),
+        };
+        let is_best =
+            segment.is_some_and(|segment| split.best_segment.map_or(true, |best| segment < best));
+
+        let split = &mut self.splits[self.current];
+        split.time = Some(time);
+        if is_best {
+            split.best_segment = segment;
+        }
+
+        if self.current + 1 < self.splits.len() {
+            self.current += 1;
+        } else {
+            self.stopwatch.pause();
+            self.phase = TimerPhase::Ended;
+        }
+    }
+}
+
+/// Draw a LiveSplit-like view of `timer` into the current window: one row per
+/// segment with its delta against the comparison, followed by the main timer.
+pub fn draw(ui: &Ui, timer: &SplitTimer) {
+    let elapsed = timer.elapsed();
+
+    ui.columns(3, "##hudhook_timer_splits", false);
+    for (index, split) in timer.splits().iter().enumerate() {
+        let is_current = index == timer.current_split() && timer.phase() != TimerPhase::Ended;
+
+        if is_current {
+            ui.text_colored(COLOR_GOLD, &split.name);
+        } else {
+            ui.text(&split.name);
+        }
+        ui.next_column();
+
+        // Delta against the comparison, for completed segments and for the
+        // current one as soon as it falls behind.
+        let delta_time = match (split.time, is_current) {
+            (Some(time), _) => Some(time),
+            (None, true) if split.comparison.map(|c| elapsed > c).unwrap_or(false) => Some(elapsed),
+            _ => None,
+        };
+        match (delta_time, split.comparison) {
+            (Some(time), Some(comparison)) => {
+                let color = if time <= comparison { COLOR_AHEAD } else { COLOR_BEHIND };
+                ui.text_colored(color, format_delta(time, comparison));
+            },
+            _ => ui.text(""),
+        }
+        ui.next_column();
+
+        match (split.time, split.comparison) {
+            (Some(time), _) => ui.text(format_duration(time)),
+            (None, Some(comparison)) => ui.text_colored(COLOR_NEUTRAL, format_duration(comparison)),
+            (None, None) => ui.text("-"),
+        }
+        ui.next_column();
+    }
+    ui.columns(1, "##hudhook_timer_splits", false);
+
+    ui.separator();
+
+    let color = match timer.phase() {
+        TimerPhase::Running => COLOR_AHEAD,
+        TimerPhase::Ended => COLOR_GOLD,
+        TimerPhase::NotRunning | TimerPhase::Paused => COLOR_NEUTRAL,
+    };
+    ui.set_window_font_scale(2.0);
+    ui.text_colored(color, format_duration(elapsed));
+    ui.set_window_font_scale(1.0);
+}
+
+/// Format a duration as `h:mm:ss.cc`, omitting leading zero hours and
+/// minutes.
+pub fn format_duration(duration: Duration) -> String {
+    let centis = duration.as_millis() / 10;
+    let (hours, minutes, seconds, centis) =
+        (centis / 360000, centis / 6000 % 60, centis / 100 % 60, centis % 100);
+
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}.{centis:02}"),
+        (0, _) => format!("{minutes}:{seconds:02}.{centis:02}"),
+        _ => format!("{hours}:{minutes:02}:{seconds:02}.{centis:02}"),
+    }
+}
+
+// Format the signed difference between `time` and `comparison`.
+fn format_delta(time: Duration, comparison: Duration) -> String {
+    if time >= comparison {
+        format!("+{}", format_duration(time - comparison))
+    } else {
+        format!("-{}", format_duration(comparison - time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration_rollover() {
+        assert_eq!(format_duration(Duration::ZERO), "0.00");
+        assert_eq!(format_duration(Duration::from_millis(59_999)), "59.99");
+        assert_eq!(format_duration(Duration::from_secs(60)), "1:00.00");
+        assert_eq!(format_duration(Duration::from_millis(3_599_990)), "59:59.99");
+        assert_eq!(format_duration(Duration::from_secs(3600)), "1:00:00.00");
+        assert_eq!(format_duration(Duration::from_secs(25 * 3600 + 61)), "25:01:01.00");
+
+        assert_eq!(format_delta(Duration::from_secs(61), Duration::from_secs(60)), "+1.00");
+        assert_eq!(format_delta(Duration::from_secs(60), Duration::from_secs(121)), "-1:01.00");
+    }
+
+    // Split `timer` at `secs` seconds into the run.
+    fn split_at(timer: &mut SplitTimer, secs: u64) {
+        timer.stopwatch_mut().set_elapsed(Duration::from_secs(secs));
+        timer.handle(TimerEvent::Split);
+    }
+
+    #[test]
+    fn test_splits() {
+        let mut timer = SplitTimer::default();
+        timer.set_splits(["A", "B", "C"]);
+        timer.handle(TimerEvent::Start);
+        timer.stopwatch_mut().pause();
+
+        split_at(&mut timer, 10);
+        timer.handle(TimerEvent::SkipSplit);
+        split_at(&mut timer, 30);
+        assert_eq!(timer.phase(), TimerPhase::Ended);
+
+        let times: Vec<_> = timer.splits().iter().map(|split| split.time).collect();
+        assert_eq!(times, [Some(Duration::from_secs(10)), None, Some(Duration::from_secs(30))]);
+        // The segment after a skipped one has no known duration.
+        assert_eq!(timer.splits()[2].best_segment, None);
+
+        timer.save_personal_best();
+        assert_eq!(timer.splits()[2].comparison, Some(Duration::from_secs(30)));
+
+        timer.handle(TimerEvent::UndoSplit);
+        assert_eq!(timer.phase(), TimerPhase::Running);
+        assert_eq!(timer.current_split(), 2);
+        assert_eq!(timer.splits()[2].time, None);
+
+        timer.handle(TimerEvent::Reset);
+        assert_eq!(timer.phase(), TimerPhase::NotRunning);
+        assert_eq!(timer.current_split(), 0);
+        assert_eq!(timer.splits()[0].best_segment, Some(Duration::from_secs(10)));
+    }
+}
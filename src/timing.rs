@@ -0,0 +1,307 @@
+//! Display timing and framerate-independent animations.
+//!
+//! Animations that step by a fixed amount per frame run four times as fast at
+//! 240 Hz as they do at 60 Hz. The helpers in this module are driven by
+//! elapsed time instead, e.g. `Io::delta_time`, so they look identical at
+//! any framerate.
+//!
+//! [`present_stats`] tells how the frames the game presents reach the
+//! display, to check whether the overlay causes stutters: compare the dropped
+//! frames with the overlay shown and hidden.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::timing::{Animator, Easing};
+//!
+//! // Keep this around in your `ImguiRenderLoop`.
+//! let mut fade = Animator::new(0.0, 0.25, Easing::EaseOutCubic);
+//! fade.set_target(1.0);
+//!
+//! // In `ImguiRenderLoop::render`:
+//! let alpha = fade.update(ui.io().delta_time);
+//! ```
+use parking_lot::Mutex;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::Graphics::Dxgi::{
+    IDXGISwapChain, DXGI_ERROR_FRAME_STATISTICS_DISJOINT, DXGI_FRAME_STATISTICS,
+};
+use windows::Win32::Graphics::Gdi::{
+    EnumDisplaySettingsW, GetMonitorInfoW, MonitorFromWindow, DEVMODEW, ENUM_CURRENT_SETTINGS,
+    HMONITOR, MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST,
+};
+
+struct TimingState {
+    monitor: HMONITOR,
+    timing: DisplayTiming,
+}
+
+static TIMING_STATE: Mutex<TimingState> =
+    Mutex::new(TimingState { monitor: HMONITOR(0), timing: DisplayTiming::UNKNOWN });
+
+struct PresentState {
+    last: Option<DXGI_FRAME_STATISTICS>,
+    stats: Option<PresentStats>,
+}
+
+static PRESENT_STATE: Mutex<PresentState> = Mutex::new(PresentState { last: None, stats: None });
+
+/// Timing information about the display the overlay is presented on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTiming {
+    /// Refresh rate of the monitor the game window is on, in Hz.
+    pub refresh_rate: Option<f32>,
+    /// Sync interval the game last presented with: `0` without vsync, `1` or
+    /// more with vsync. Only known for DirectX 10+ games.
+    pub sync_interval: Option<u32>,
+}
+
+impl DisplayTiming {
+    const UNKNOWN: DisplayTiming = DisplayTiming { refresh_rate: None, sync_interval: None };
+
+    /// Whether the game presents with vsync, if known.
+    pub fn vsync(&self) -> Option<bool> {
+        self.sync_interval.map(|interval| interval > 0)
+    }
+
+    /// Expected duration of a frame in seconds, if the game is vsynced.
+    pub fn frame_time(&self) -> Option<f32> {
+        match (self.refresh_rate, self.sync_interval) {
+            (Some(rate), Some(interval)) if rate > 0.0 && interval > 0 => {
+                Some(interval as f32 / rate)
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Get the current display timing.
+pub fn display_timing() -> DisplayTiming {
+    TIMING_STATE.lock().timing
+}
+
+// Refresh the monitor refresh rate if the window moved to another monitor.
+pub(crate) fn update_monitor(hwnd: HWND) {
+    let monitor = unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) };
+
+    let mut state = TIMING_STATE.lock();
+    if state.monitor != monitor {
+        state.monitor = monitor;
+        state.timing.refresh_rate = unsafe { monitor_refresh_rate(monitor) };
+    }
+}
+
+// Record the sync interval passed to `Present`.
+pub(crate) fn set_sync_interval(sync_interval: u32) {
+    TIMING_STATE.lock().timing.sync_interval = Some(sync_interval);
+}
+
+/// Presentation statistics of the game's swap chain, as reported by DXGI.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PresentStats {
+    /// Number of times the game called `Present`.
+    pub present_count: u32,
+    /// Number of presented frames DXGI reported as displayed.
+    pub displayed_count: u32,
+    /// Vertical blank the last displayed frame was shown at.
+    pub refresh_count: u32,
+    /// Vertical blanks at which a new frame was due but the previous one was
+    /// shown again. Only counted while the game presents with vsync.
+    pub dropped_frames: u32,
+    /// Number of presentation glitches, i.e. runs of one or more dropped
+    /// frames.
+    pub glitches: u32,
+    /// Number of times the statistics were interrupted, e.g. by a mode change
+    /// or by the window going out of fullscreen. Frames dropped meanwhile
+    /// aren't counted.
+    pub disjoints: u32,
+}
+
+/// Get the presentation statistics of the game's swap chain, accumulated
+/// since the first frame or the last [`reset_present_stats`].
+///
+/// Only available for DirectX 10+ games presenting in fullscreen or with a
+/// flip model swap chain: DXGI doesn't track other swap chains.
+pub fn present_stats() -> Option<PresentStats> {
+    PRESENT_STATE.lock().stats
+}
+
+/// Reset the dropped frame and glitch counts of [`present_stats`].
+pub fn reset_present_stats() {
+    if let Some(stats) = PRESENT_STATE.lock().stats.as_mut() {
+        stats.dropped_frames = 0;
+        stats.glitches = 0;
+        stats.disjoints = 0;
+    }
+}
+
+// Sample the frame statistics of the swap chain the game presents to.
+pub(crate) unsafe fn update_present_stats(swap_chain: &IDXGISwapChain) {
+    let Ok(present_count) = swap_chain.GetLastPresentCount() else {
+        return;
+    };
+
+    let mut frame_stats = DXGI_FRAME_STATISTICS::default();
+    let sample = swap_chain.GetFrameStatistics(&mut frame_stats);
+
+    let sync_interval = TIMING_STATE.lock().timing.sync_interval.unwrap_or(0);
+
+    let mut state = PRESENT_STATE.lock();
+    let last = state.last;
+    let stats = state.stats.get_or_insert_with(PresentStats::default);
+    stats.present_count = present_count;
+
+    match sample {
+        Ok(()) => {
+            stats.displayed_count = frame_stats.PresentCount;
+            stats.refresh_count = frame_stats.PresentRefreshCount;
+
+            if let Some(last) = last.filter(|_| sync_interval > 0) {
+                let displayed = frame_stats.PresentCount.wrapping_sub(last.PresentCount);
+                let refreshes =
+                    frame_stats.PresentRefreshCount.wrapping_sub(last.PresentRefreshCount);
+                let expected = displayed.saturating_mul(sync_interval);
+
+                if displayed > 0 && refreshes > expected {
+                    stats.dropped_frames += (refreshes - expected) / sync_interval;
+                    stats.glitches += 1;
+                }
+            }
+
+            state.last = Some(frame_stats);
+        },
+        Err(e) if e.code() == DXGI_ERROR_FRAME_STATISTICS_DISJOINT => {
+            stats.disjoints += 1;
+            state.last = None;
+        },
+        // The swap chain isn't tracked by DXGI, e.g. windowed with the blt model.
+        Err(_) => state.last = None,
+    }
+}
+
+unsafe fn monitor_refresh_rate(monitor: HMONITOR) -> Option<f32> {
+    let mut info = MONITORINFOEXW {
+        monitorInfo: MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFOEXW>() as u32,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    GetMonitorInfoW(monitor, &mut info as *mut _ as *mut MONITORINFO).ok().ok()?;
+
+    let mut devmode =
+        DEVMODEW { dmSize: std::mem::size_of::<DEVMODEW>() as u16, ..Default::default() };
+    EnumDisplaySettingsW(PCWSTR(info.szDevice.as_ptr()), ENUM_CURRENT_SETTINGS, &mut devmode)
+        .ok()
+        .ok()?;
+
+    // 0 and 1 stand for the hardware's default refresh rate.
+    match devmode.dmDisplayFrequency {
+        0 | 1 => None,
+        hz => Some(hz as f32),
+    }
+}
+
+/// An easing curve, mapping linear progress in `[0, 1]` to eased progress.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Start slowly and accelerate.
+    EaseInQuad,
+    /// Start fast and decelerate.
+    EaseOutQuad,
+    /// Start fast and decelerate, more pronounced than
+    /// [`EaseOutQuad`](Easing::EaseOutQuad).
+    EaseOutCubic,
+    /// Accelerate, then decelerate.
+    EaseInOutCubic,
+}
+
+impl Easing {
+    /// Apply the curve to `t`, clamped to `[0, 1]`.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInQuad => t * t,
+            Easing::EaseOutQuad => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOutCubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            },
+        }
+    }
+}
+
+/// Animates a value towards a target over a fixed duration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animator {
+    from: f32,
+    to: f32,
+    value: f32,
+    elapsed: f32,
+    duration: f32,
+    easing: Easing,
+}
+
+impl Animator {
+    /// Create an animator resting at `value`, which takes `duration` seconds
+    /// to reach a new target.
+    pub fn new(value: f32, duration: f32, easing: Easing) -> Self {
+        Self { from: value, to: value, value, elapsed: duration, duration, easing }
+    }
+
+    /// Start animating from the current value towards `target`.
+    pub fn set_target(&mut self, target: f32) {
+        if target != self.to {
+            self.from = self.value;
+            self.to = target;
+            self.elapsed = 0.0;
+        }
+    }
+
+    /// Jump to `value` immediately.
+    pub fn set_value(&mut self, value: f32) {
+        *self = Self::new(value, self.duration, self.easing);
+    }
+
+    /// Advance the animation by `delta_time` seconds and return the new value.
+    pub fn update(&mut self, delta_time: f32) -> f32 {
+        self.elapsed = (self.elapsed + delta_time.max(0.0)).min(self.duration);
+
+        let t = if self.duration > 0.0 { self.elapsed / self.duration } else { 1.0 };
+        self.value = self.from + (self.to - self.from) * self.easing.apply(t);
+        self.value
+    }
+
+    /// The current value.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// The value being animated towards.
+    pub fn target(&self) -> f32 {
+        self.to
+    }
+
+    /// Whether the target has been reached.
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Move `current` towards `target` exponentially, covering the given fraction
+/// of the remaining distance per second, regardless of the framerate.
+///
+/// Useful for smoothing values that change every frame, such as a tracked
+/// position, where a fixed-duration [`Animator`] would restart constantly.
+pub fn approach(current: f32, target: f32, fraction_per_second: f32, delta_time: f32) -> f32 {
+    let remaining = (1.0 - fraction_per_second.clamp(0.0, 1.0)).powf(delta_time.max(0.0));
+    target + (current - target) * remaining
+}
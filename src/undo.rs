@@ -0,0 +1,489 @@
+//! Undo and redo of the edits made from the overlay.
+//!
+//! Editor-style overlays, e.g. level editors or trainers, let users change
+//! the game from the UI. Expressing each edit as a [`Command`] pushed on an
+//! [`UndoStack`] makes it reversible: the stack applies the command, and
+//! [`UndoStack::undo`] and [`UndoStack::redo`] walk the history back and
+//! forth. [`UndoStack::shortcuts`] binds them to `Ctrl+Z` and `Ctrl+Y`.
+//!
+//! Commands pushed between [`UndoStack::begin_group`] and
+//! [`UndoStack::end_group`] are undone as a whole, e.g. all the objects moved
+//! by a single drag, and consecutive commands can be merged into one with
+//! [`Command::merge`], e.g. the values a slider goes through. To persist the
+//! history, e.g. across a reinjection with [`state`](crate::state), read it
+//! with [`UndoStack::history`], restore it with [`UndoStack::restore`], and
+//! save it whenever it changes from [`UndoStack::with_on_change`].
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::undo::{Command, UndoStack};
+//! use hudhook::ImguiRenderLoop;
+//!
+//! struct SetGravity {
+//!     old: f32,
+//!     new: f32,
+//! }
+//!
+//! impl Command for SetGravity {
+//!     fn apply(&mut self) {
+//!         // Write `self.new` to the game.
+//!     }
+//!
+//!     fn undo(&mut self) {
+//!         // Write `self.old` to the game.
+//!     }
+//!
+//!     fn description(&self) -> String {
+//!         format!("Set gravity to {}", self.new)
+//!     }
+//!
+//!     fn merge(&mut self, next: &Self) -> bool {
+//!         self.new = next.new;
+//!         true
+//!     }
+//! }
+//!
+//! struct Editor {
+//!     gravity: f32,
+//!     history: UndoStack<SetGravity>,
+//! }
+//!
+//! impl ImguiRenderLoop for Editor {
+//!     fn render(&mut self, ui: &mut hudhook::imgui::Ui) {
+//!         self.history.shortcuts(ui);
+//!         ui.window("Editor").build(|| {
+//!             let old = self.gravity;
+//!             if ui.slider("Gravity", 0.0, 20.0, &mut self.gravity) {
+//!                 self.history.push(SetGravity { old, new: self.gravity });
+//!             }
+//!         });
+//!     }
+//! }
+//! ```
+use std::any::type_name;
+
+use imgui::{Key, Ui};
+#[cfg(feature = "state")]
+use serde::{Deserialize, Serialize};
+
+// Groups kept by default before the oldest ones are forgotten.
+const DEFAULT_LIMIT: usize = 256;
+
+/// A reversible edit.
+pub trait Command {
+    /// Make the edit.
+    fn apply(&mut self);
+
+    /// Revert the edit made by [`Command::apply`].
+    fn undo(&mut self);
+
+    /// Describes the edit to the user, e.g. in an "Undo ..." menu item.
+    /// Defaults to the name of the type.
+    fn description(&self) -> String {
+        type_name::<Self>().to_string()
+    }
+
+    /// Absorb `next`, an edit made right after this one and already applied,
+    /// so that both are undone at once. Returns `false`, the default, if they
+    /// must stay separate.
+    fn merge(&mut self, _next: &Self) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+}
+
+impl Command for Box<dyn Command + Send + Sync> {
+    fn apply(&mut self) {
+        (**self).apply()
+    }
+
+    fn undo(&mut self) {
+        (**self).undo()
+    }
+
+    fn description(&self) -> String {
+        (**self).description()
+    }
+}
+
+/// Commands undone and redone as a whole.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "state", derive(Serialize, Deserialize))]
+pub struct Group<C> {
+    /// Describes the group to the user.
+    pub description: String,
+    /// The commands, in the order they were applied.
+    pub commands: Vec<C>,
+}
+
+/// History of the edits made through [`Command`]s.
+pub struct UndoStack<C> {
+    undo: Vec<Group<C>>,
+    redo: Vec<Group<C>>,
+    // The group being recorded, and how many times it was begun.
+    open: Option<(Group<C>, usize)>,
+    limit: usize,
+    // Length of the undo history when it was last saved, if it can still be
+    // reached.
+    saved: Option<usize>,
+    on_change: Option<Box<dyn FnMut(&UndoStack<C>) + Send + Sync>>,
+}
+
+impl<C: Command> Default for UndoStack<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Command> UndoStack<C> {
+    /// Create an empty history.
+    pub fn new() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            open: None,
+            limit: DEFAULT_LIMIT,
+            saved: Some(0),
+            on_change: None,
+        }
+    }
+
+    /// Keep at most `limit` groups in the undo history, forgetting the
+    /// oldest ones. Defaults to 256.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    /// Call `on_change` whenever the history changes, e.g. to persist it.
+    pub fn with_on_change<F>(mut self, on_change: F) -> Self
+    where
+        F: FnMut(&UndoStack<C>) + Send + Sync + 'static,
+    {
+        self.on_change = Some(Box::new(on_change));
+        self
+    }
+
+    /// Apply `command` and add it to the history. Clears the redo history.
+    pub fn push(&mut self, mut command: C) {
+        command.apply();
+        self.record(command);
+    }
+
+    /// Add `command`, already applied, to the history. Clears the redo
+    /// history.
+    pub fn record(&mut self, command: C) {
+        self.redo.clear();
+        if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+
+        if let Some((group, _)) = &mut self.open {
+            push_merged(&mut group.commands, command);
+            return;
+        }
+
+        // Merging into the last group changes the state it leads to.
+        if let Some(last) = self.undo.last_mut() {
+            if let [previous] = last.commands.as_mut_slice() {
+                if previous.merge(&command) {
+                    last.description = previous.description();
+                    if self.saved == Some(self.undo.len()) {
+                        self.saved = None;
+                    }
+                    self.changed();
+                    return;
+                }
+            }
+        }
+
+        let description = command.description();
+        self.push_group(Group { description, commands: vec![command] });
+    }
+
+    /// Start recording the commands pushed until the matching
+    /// [`UndoStack::end_group`] as one group, described by `description`.
+    /// Groups begun within a group are part of it.
+    pub fn begin_group(&mut self, description: impl Into<String>) {
+        match &mut self.open {
+            Some((_, depth)) => *depth += 1,
+            None => {
+                let group = Group { description: description.into(), commands: Vec::new() };
+                self.open = Some((group, 1));
+            },
+        }
+    }
+
+    /// Stop recording the group begun by [`UndoStack::begin_group`]. Empty
+    /// groups are dropped.
+    pub fn end_group(&mut self) {
+        let Some((_, depth)) = &mut self.open else {
+            return;
+        };
+
+        *depth -= 1;
+        if *depth == 0 {
+            self.end_open_groups();
+        }
+    }
+
+    /// Revert the last group. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        self.end_open_groups();
+        let Some(mut group) = self.undo.pop() else {
+            return false;
+        };
+
+        group.commands.iter_mut().rev().for_each(C::undo);
+        self.redo.push(group);
+        self.changed();
+        true
+    }
+
+    /// Apply the last group undone again. Returns `false` if there is nothing
+    /// to redo.
+    pub fn redo(&mut self) -> bool {
+        self.end_open_groups();
+        let Some(mut group) = self.redo.pop() else {
+            return false;
+        };
+
+        group.commands.iter_mut().for_each(C::apply);
+        self.undo.push(group);
+        self.changed();
+        true
+    }
+
+    /// Whether [`UndoStack::undo`] would revert something.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.open.as_ref().is_some_and(|(g, _)| !g.commands.is_empty())
+    }
+
+    /// Whether [`UndoStack::redo`] would apply something.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Description of the group [`UndoStack::undo`] would revert.
+    pub fn undo_description(&self) -> Option<&str> {
+        self.undo.last().map(|group| group.description.as_str())
+    }
+
+    /// Description of the group [`UndoStack::redo`] would apply.
+    pub fn redo_description(&self) -> Option<&str> {
+        self.redo.last().map(|group| group.description.as_str())
+    }
+
+    /// The undo history, oldest group first, and the redo history, next group
+    /// to redo last.
+    pub fn history(&self) -> (&[Group<C>], &[Group<C>]) {
+        (&self.undo, &self.redo)
+    }
+
+    /// Replace the history with one read back from [`UndoStack::history`],
+    /// without applying or undoing anything: the game must be in the state
+    /// the history leads to.
+    pub fn restore(&mut self, undo: Vec<Group<C>>, redo: Vec<Group<C>>) {
+        self.undo = undo;
+        self.redo = redo;
+        self.open = None;
+        self.saved = None;
+        self.trim();
+    }
+
+    /// Forget the whole history, without undoing anything.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.saved = None;
+        self.changed();
+    }
+
+    /// Remember the current state as saved, e.g. after writing the edits to
+    /// a file.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Whether the edits were undone or redone back to the state last
+    /// [marked as saved](UndoStack::mark_saved), or to the initial state if
+    /// it never was.
+    pub fn is_saved(&self) -> bool {
+        self.open.is_none() && self.saved == Some(self.undo.len())
+    }
+
+    /// Undo on `Ctrl+Z`, and redo on `Ctrl+Y` or `Ctrl+Shift+Z`, unless a
+    /// text field, which has its own undo, has the keyboard focus. Call it
+    /// from [`ImguiRenderLoop::render`](crate::ImguiRenderLoop::render).
+    pub fn shortcuts(&mut self, ui: &Ui) {
+        let io = ui.io();
+        if !io.key_ctrl || io.want_text_input {
+            return;
+        }
+
+        if ui.is_key_pressed(Key::Y) || (io.key_shift && ui.is_key_pressed(Key::Z)) {
+            self.redo();
+        } else if ui.is_key_pressed(Key::Z) {
+            self.undo();
+        }
+    }
+
+    // Close the group left open, if any, e.g. by a drag interrupted by an undo.
+    fn end_open_groups(&mut self) {
+        if let Some((group, _)) = self.open.take() {
+            if !group.commands.is_empty() {
+                self.push_group(group);
+            }
+        }
+    }
+
+    fn push_group(&mut self, group: Group<C>) {
+        self.undo.push(group);
+        self.trim();
+        self.changed();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.undo.len().saturating_sub(self.limit);
+        if excess > 0 {
+            self.undo.drain(..excess);
+            self.saved = self.saved.and_then(|saved| saved.checked_sub(excess));
+        }
+    }
+
+    fn changed(&mut self) {
+        if let Some(mut on_change) = self.on_change.take() {
+            on_change(self);
+            self.on_change = Some(on_change);
+        }
+    }
+}
+
+// Add `command` after `commands`, merging it into the last one if possible.
+fn push_merged<C: Command>(commands: &mut Vec<C>, command: C) {
+    match commands.last_mut() {
+        Some(previous) if previous.merge(&command) => {},
+        _ => commands.push(command),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    // Adds `by` to a shared value, merging with the next addition if `merge`.
+    struct Add {
+        value: Rc<Cell<i32>>,
+        by: i32,
+        merge: bool,
+    }
+
+    impl Command for Add {
+        fn apply(&mut self) {
+            self.value.set(self.value.get() + self.by);
+        }
+
+        fn undo(&mut self) {
+            self.value.set(self.value.get() - self.by);
+        }
+
+        fn merge(&mut self, next: &Self) -> bool {
+            if self.merge && next.merge {
+                self.by += next.by;
+            }
+            self.merge && next.merge
+        }
+    }
+
+    fn add(value: &Rc<Cell<i32>>, by: i32) -> Add {
+        Add { value: Rc::clone(value), by, merge: false }
+    }
+
+    #[test]
+    fn test_undo_redo_bounds() {
+        let value = Rc::new(Cell::new(0));
+        let mut stack = UndoStack::new();
+        assert!(!stack.undo());
+        assert!(!stack.redo());
+
+        for by in [1, 2, 3] {
+            stack.push(add(&value, by));
+        }
+        assert_eq!(value.get(), 6);
+
+        assert!(stack.undo() && stack.undo() && stack.undo());
+        assert!(!stack.undo());
+        assert_eq!(value.get(), 0);
+        assert!(!stack.can_undo() && stack.can_redo());
+
+        assert!(stack.redo() && stack.redo() && stack.redo());
+        assert!(!stack.redo());
+        assert_eq!(value.get(), 6);
+        assert!(!stack.is_saved());
+    }
+
+    #[test]
+    fn test_push_clears_redo() {
+        let value = Rc::new(Cell::new(0));
+        let mut stack = UndoStack::new();
+        stack.push(add(&value, 1));
+        stack.push(add(&value, 2));
+        assert!(stack.undo());
+
+        stack.push(add(&value, 4));
+        assert!(!stack.can_redo());
+        assert_eq!(value.get(), 5);
+    }
+
+    #[test]
+    fn test_limit() {
+        let value = Rc::new(Cell::new(0));
+        let mut stack = UndoStack::new().with_limit(2);
+        for by in [1, 2, 3] {
+            stack.push(add(&value, by));
+        }
+
+        // The oldest addition was forgotten.
+        assert!(stack.undo() && stack.undo());
+        assert!(!stack.undo());
+        assert_eq!(value.get(), 1);
+        assert_eq!(stack.history().1.len(), 2);
+    }
+
+    #[test]
+    fn test_groups_and_merges() {
+        let value = Rc::new(Cell::new(0));
+        let mut stack = UndoStack::new();
+
+        stack.begin_group("Drag");
+        stack.push(add(&value, 1));
+        stack.begin_group("Nested");
+        stack.push(add(&value, 2));
+        stack.end_group();
+        stack.push(add(&value, 3));
+        stack.end_group();
+        assert_eq!(stack.undo_description(), Some("Drag"));
+
+        // Empty groups are dropped.
+        stack.begin_group("Nothing");
+        stack.end_group();
+
+        for by in [1, 1, 1] {
+            stack.push(Add { value: Rc::clone(&value), by, merge: true });
+        }
+        assert_eq!(stack.history().0.len(), 2);
+        assert_eq!(value.get(), 9);
+
+        assert!(stack.undo());
+        assert_eq!(value.get(), 6);
+        assert!(stack.undo());
+        assert_eq!(value.get(), 0);
+        assert!(stack.is_saved());
+    }
+}
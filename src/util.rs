@@ -6,34 +6,38 @@ use std::fmt::Display;
 use std::mem::ManuallyDrop;
 use std::os::windows::ffi::OsStringExt;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use tracing::{debug, error};
-use windows::core::s;
-use windows::Win32::Foundation::{HANDLE, HMODULE, HWND, MAX_PATH, RECT};
+use windows::core::{s, Interface, PCWSTR};
+use windows::Win32::Foundation::{HMODULE, HWND, MAX_PATH, RECT};
 use windows::Win32::Graphics::Direct3D::ID3DBlob;
 use windows::Win32::Graphics::Direct3D12::{
-    D3D12GetDebugInterface, ID3D12Debug, ID3D12Device, ID3D12Fence, ID3D12Resource,
-    D3D12_FENCE_FLAG_NONE, D3D12_RESOURCE_BARRIER, D3D12_RESOURCE_BARRIER_0,
-    D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES, D3D12_RESOURCE_BARRIER_FLAG_NONE,
-    D3D12_RESOURCE_BARRIER_TYPE_TRANSITION, D3D12_RESOURCE_STATES,
-    D3D12_RESOURCE_TRANSITION_BARRIER,
+    D3D12GetDebugInterface, ID3D12Debug, ID3D12Debug1, ID3D12DebugDevice, ID3D12Device,
+    ID3D12InfoQueue, ID3D12Resource, D3D12_MESSAGE, D3D12_MESSAGE_SEVERITY,
+    D3D12_MESSAGE_SEVERITY_CORRUPTION, D3D12_MESSAGE_SEVERITY_ERROR, D3D12_RESOURCE_BARRIER,
+    D3D12_RESOURCE_BARRIER_0, D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+    D3D12_RESOURCE_BARRIER_FLAG_NONE, D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+    D3D12_RESOURCE_STATES, D3D12_RESOURCE_TRANSITION_BARRIER, D3D12_RLDO_DETAIL,
+    D3D12_RLDO_IGNORE_INTERNAL,
 };
 use windows::Win32::Graphics::Dxgi::{
-    DXGIGetDebugInterface1, IDXGIInfoQueue, DXGI_DEBUG_ALL, DXGI_INFO_QUEUE_MESSAGE,
+    DXGIGetDebugInterface1, IDXGIDebug1, IDXGIInfoQueue, DXGI_DEBUG_ALL, DXGI_DEBUG_RLO_DETAIL,
+    DXGI_DEBUG_RLO_FLAGS, DXGI_DEBUG_RLO_IGNORE_INTERNAL, DXGI_INFO_QUEUE_MESSAGE,
 };
 use windows::Win32::System::LibraryLoader::{
-    GetModuleFileNameW, GetModuleHandleExA, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
-    GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+    GetModuleFileNameW, GetModuleHandleExA, GetModuleHandleExW,
+    GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
 };
 use windows::Win32::System::Memory::{
     VirtualQuery, MEMORY_BASIC_INFORMATION, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
     PAGE_PROTECTION_FLAGS, PAGE_READONLY, PAGE_READWRITE,
 };
 use windows::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO};
-use windows::Win32::System::Threading::{CreateEventExW, WaitForSingleObjectEx, CREATE_EVENT};
 use windows::Win32::UI::WindowsAndMessaging::GetClientRect;
 
+use crate::names;
+
 /// Helper for fallible [`windows`] APIs that have an out-param with a default
 /// value.
 ///
@@ -148,6 +152,108 @@ pub fn enable_debug_interface() {
     }
 }
 
+/// Enables the Direct3D12 debug interface along with GPU-based validation,
+/// which also catches resource state and barrier errors on the GPU timeline.
+///
+/// Rendering gets much slower, so use it to test overlays rather than in
+/// release builds. The same caveats as [`enable_debug_interface`] apply.
+pub fn enable_gpu_based_validation() {
+    let debug_interface: Result<ID3D12Debug1, _> =
+        try_out_ptr(|v| unsafe { D3D12GetDebugInterface(v) });
+
+    match debug_interface {
+        Ok(debug_interface) => unsafe {
+            debug_interface.EnableDebugLayer();
+            debug_interface.SetEnableGPUBasedValidation(true);
+        },
+        Err(e) => {
+            error!("Could not create debug interface: {e:?}")
+        },
+    }
+}
+
+/// Returns the errors the Direct3D12 debug layer reported for `device`, and
+/// clears its stored messages. Has effect only after [`enable_debug_interface`]
+/// or [`enable_gpu_based_validation`] has been called.
+pub fn take_d3d12_validation_errors(device: &ID3D12Device) -> Vec<String> {
+    let Ok(info_queue) = device.cast::<ID3D12InfoQueue>() else {
+        return Vec::new();
+    };
+
+    take_d3d12_messages(&info_queue)
+        .into_iter()
+        .filter(|(severity, _)| {
+            *severity == D3D12_MESSAGE_SEVERITY_ERROR
+                || *severity == D3D12_MESSAGE_SEVERITY_CORRUPTION
+        })
+        .map(|(_, msg)| msg)
+        .collect()
+}
+
+/// Returns the objects still alive on `device`, as reported by
+/// `ID3D12DebugDevice::ReportLiveDeviceObjects`, one message per object.
+/// Objects created by the game are included.
+///
+/// Stored messages of `device` are cleared. Has effect only after
+/// [`enable_debug_interface`] or [`enable_gpu_based_validation`] has been
+/// called.
+pub fn report_live_device_objects(device: &ID3D12Device) -> Vec<String> {
+    let (Ok(debug_device), Ok(info_queue)) =
+        (device.cast::<ID3D12DebugDevice>(), device.cast::<ID3D12InfoQueue>())
+    else {
+        return Vec::new();
+    };
+
+    unsafe { info_queue.ClearStoredMessages() };
+    if let Err(e) = unsafe {
+        debug_device.ReportLiveDeviceObjects(D3D12_RLDO_DETAIL | D3D12_RLDO_IGNORE_INTERNAL)
+    } {
+        error!("Could not report live device objects: {e:?}");
+        return Vec::new();
+    }
+
+    take_d3d12_messages(&info_queue).into_iter().map(|(_, msg)| msg).collect()
+}
+
+/// Returns the objects [`hudhook`](crate) created that are still alive on
+/// `device`, told apart from the game's by their debug name, which starts
+/// with the [name prefix](crate::names). The same caveats as
+/// [`report_live_device_objects`] apply.
+pub fn live_hudhook_objects(device: &ID3D12Device) -> Vec<String> {
+    let name = format!("Name: {} ", names::prefix());
+    report_live_device_objects(device).into_iter().filter(|msg| msg.contains(&name)).collect()
+}
+
+// Read the stored messages of a D3D12 info queue along with their severity,
+// and clear them.
+fn take_d3d12_messages(info_queue: &ID3D12InfoQueue) -> Vec<(D3D12_MESSAGE_SEVERITY, String)> {
+    let mut messages = Vec::new();
+    let n = unsafe { info_queue.GetNumStoredMessages() };
+    for i in 0..n {
+        let mut msg_len: usize = 0;
+        if unsafe { info_queue.GetMessage(i, None, &mut msg_len) }.is_err() {
+            continue;
+        }
+        let msg = vec![0u8; msg_len];
+        let pmsg = msg.as_ptr() as *mut D3D12_MESSAGE;
+        if unsafe { info_queue.GetMessage(i, Some(pmsg), &mut msg_len) }.is_err() {
+            continue;
+        }
+        let msg = unsafe { pmsg.as_ref().unwrap() };
+
+        messages.push((
+            msg.Severity,
+            String::from_utf8_lossy(unsafe {
+                std::slice::from_raw_parts(msg.pDescription, msg.DescriptionByteLength - 1)
+            })
+            .into_owned(),
+        ));
+    }
+    unsafe { info_queue.ClearStoredMessages() };
+
+    messages
+}
+
 /// Prints the DXGI debug messages on the debug trace. It is used internally for
 /// error reporting, but can be used by clients. Has effect only after
 /// [`enable_debug_interface`] has been called.
@@ -156,22 +262,56 @@ pub fn print_dxgi_debug_messages() {
         return;
     };
 
+    for msg in take_dxgi_debug_messages(&diq) {
+        debug!("[DIQ] {msg}");
+    }
+}
+
+/// Returns the DXGI and Direct3D objects still alive in the process, as
+/// reported by the DXGI debug layer, one message per object. Comparing the
+/// reports taken at two points in time shows the objects leaked in between.
+///
+/// Stored DXGI debug messages are cleared. Has effect only for devices created
+/// with the debug layer, e.g. DirectX 11 devices created with
+/// `D3D11_CREATE_DEVICE_DEBUG` or DirectX 12 devices created after
+/// [`enable_debug_interface`].
+pub fn report_live_objects() -> Vec<String> {
+    let (Ok(debug), Ok(diq)): (Result<IDXGIDebug1, _>, Result<IDXGIInfoQueue, _>) =
+        (unsafe { DXGIGetDebugInterface1(0) }, unsafe { DXGIGetDebugInterface1(0) })
+    else {
+        return Vec::new();
+    };
+
+    unsafe { diq.ClearStoredMessages(DXGI_DEBUG_ALL) };
+    let flags = DXGI_DEBUG_RLO_FLAGS(DXGI_DEBUG_RLO_DETAIL.0 | DXGI_DEBUG_RLO_IGNORE_INTERNAL.0);
+    if let Err(e) = unsafe { debug.ReportLiveObjects(DXGI_DEBUG_ALL, flags) } {
+        error!("Could not report live objects: {e:?}");
+        return Vec::new();
+    }
+
+    take_dxgi_debug_messages(&diq)
+}
+
+// Read the stored DXGI debug messages and clear them.
+fn take_dxgi_debug_messages(diq: &IDXGIInfoQueue) -> Vec<String> {
     let n = unsafe { diq.GetNumStoredMessages(DXGI_DEBUG_ALL) };
-    for i in 0..n {
-        let mut msg_len: usize = 0;
-        unsafe { diq.GetMessage(DXGI_DEBUG_ALL, i, None, &mut msg_len as _).unwrap() };
-        let diqm = vec![0u8; msg_len];
-        let pdiqm = diqm.as_ptr() as *mut DXGI_INFO_QUEUE_MESSAGE;
-        unsafe { diq.GetMessage(DXGI_DEBUG_ALL, i, Some(pdiqm), &mut msg_len as _).unwrap() };
-        let diqm = unsafe { pdiqm.as_ref().unwrap() };
-        debug!(
-            "[DIQ] {}",
+    let messages = (0..n)
+        .map(|i| {
+            let mut msg_len: usize = 0;
+            unsafe { diq.GetMessage(DXGI_DEBUG_ALL, i, None, &mut msg_len as _).unwrap() };
+            let diqm = vec![0u8; msg_len];
+            let pdiqm = diqm.as_ptr() as *mut DXGI_INFO_QUEUE_MESSAGE;
+            unsafe { diq.GetMessage(DXGI_DEBUG_ALL, i, Some(pdiqm), &mut msg_len as _).unwrap() };
+            let diqm = unsafe { pdiqm.as_ref().unwrap() };
             String::from_utf8_lossy(unsafe {
                 std::slice::from_raw_parts(diqm.pDescription, diqm.DescriptionByteLength - 1)
             })
-        );
-    }
+            .into_owned()
+        })
+        .collect();
     unsafe { diq.ClearStoredMessages(DXGI_DEBUG_ALL) };
+
+    messages
 }
 
 /// Helper that returns width and height of a given
@@ -202,6 +342,25 @@ pub fn get_dll_path() -> Option<PathBuf> {
     Some(OsString::from_wide(&sz_filename[..len]).into())
 }
 
+// The module `hudhook` is linked into: the one containing the code of this
+// very function.
+pub(crate) fn current_module() -> Option<HMODULE> {
+    let mut hmodule = HMODULE(0);
+    match unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT | GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            PCWSTR(current_module as *const () as *const u16),
+            &mut hmodule,
+        )
+    } {
+        Ok(()) => Some(hmodule),
+        Err(e) => {
+            error!("Couldn't find the module of the overlay: {e:?}");
+            None
+        },
+    }
+}
+
 /// Creates a [`D3D12_RESOURCE_BARRIER`].
 ///
 /// Use this function and the associated [`drop_barrier`] for correctly managing
@@ -240,51 +399,8 @@ pub fn drop_barrier(barrier: D3D12_RESOURCE_BARRIER) {
     let _ = ManuallyDrop::into_inner(transition.pResource);
 }
 
-/// Wrapper around [`windows::Win32::Graphics::Direct3D12::ID3D12Fence`].
-pub struct Fence {
-    fence: ID3D12Fence,
-    value: AtomicU64,
-    event: HANDLE,
-}
-
-impl Fence {
-    /// Construct the fence.
-    pub fn new(device: &ID3D12Device) -> windows::core::Result<Self> {
-        let fence = unsafe { device.CreateFence(0, D3D12_FENCE_FLAG_NONE) }?;
-        let value = AtomicU64::new(0);
-        let event = unsafe { CreateEventExW(None, None, CREATE_EVENT(0), 0x1f0003) }?;
-
-        Ok(Fence { fence, value, event })
-    }
-
-    /// Retrieve the underlying fence object to pass to the D3D12 APIs.
-    pub fn fence(&self) -> &ID3D12Fence {
-        &self.fence
-    }
-
-    /// Retrieve the current fence value.
-    pub fn value(&self) -> u64 {
-        self.value.load(Ordering::SeqCst)
-    }
-
-    /// Atomically increase the fence value.
-    pub fn incr(&self) {
-        self.value.fetch_add(1, Ordering::SeqCst);
-    }
-
-    /// Wait for completion of the fence.
-    pub fn wait(&self) -> windows::core::Result<()> {
-        let value = self.value();
-        unsafe {
-            if self.fence.GetCompletedValue() < value {
-                self.fence.SetEventOnCompletion(value, self.event)?;
-                WaitForSingleObjectEx(self.event, u32::MAX, false);
-            }
-        }
-
-        Ok(())
-    }
-}
+#[doc(no_inline)]
+pub use crate::compose::Fence;
 
 /// Returns a slice of **up to** `limit` elements of type `T` starting at `ptr`.
 ///
@@ -350,6 +466,72 @@ pub unsafe fn readable_region<T>(ptr: *const T, limit: usize) -> &'static [T] {
     std::slice::from_raw_parts(ptr, limit)
 }
 
+/// Log panics through [`tracing`] before the default panic handler runs.
+///
+/// Recommended for DLLs built with `panic = "abort"`, where a panic
+/// terminates the game immediately and the message printed to a console that
+/// usually doesn't exist would otherwise be lost.
+pub fn log_panics() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        let location =
+            info.location().map(|l| format!("{}:{}", l.file(), l.line())).unwrap_or_default();
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+
+        error!(
+            "Thread {:?} panicked at {location}: {payload}",
+            thread.name().unwrap_or("<unnamed>")
+        );
+        default_hook(info);
+    }));
+}
+
+static HOT_PATH_SAMPLING: AtomicU32 = AtomicU32::new(0);
+
+/// Log one out of every `n` trace events emitted in per-frame hot paths, such
+/// as the `Present` hooks, per call site. `0`, the default, disables them
+/// entirely.
+///
+/// These events flood the logs with `TRACE` enabled, and cost an atomic load
+/// per call even with tracing disabled, so they are only compiled in with
+/// the `hot-path-tracing` feature. Without it, this function does nothing.
+pub fn set_hot_path_sampling(n: u32) {
+    HOT_PATH_SAMPLING.store(n, Ordering::Relaxed);
+}
+
+// Whether the current hit of the call site owning `counter` should be logged.
+#[cfg(feature = "hot-path-tracing")]
+pub(crate) fn hot_path_sample(counter: &AtomicU32) -> bool {
+    match HOT_PATH_SAMPLING.load(Ordering::Relaxed) {
+        0 => false,
+        n => counter.fetch_add(1, Ordering::Relaxed) % n == 0,
+    }
+}
+
+// `trace!` for code that runs every frame, sampled according to
+// `set_hot_path_sampling` and compiled out without the `hot-path-tracing`
+// feature.
+macro_rules! trace_hot_path {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "hot-path-tracing")]
+        {
+            static COUNTER: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+            if ::tracing::enabled!(::tracing::Level::TRACE)
+                && $crate::util::hot_path_sample(&COUNTER)
+            {
+                ::tracing::trace!($($arg)*);
+            }
+        }
+    };
+}
+pub(crate) use trace_hot_path;
+
 #[cfg(test)]
 mod tests {
     use windows::Win32::System::Memory::{VirtualAlloc, VirtualProtect, MEM_COMMIT, PAGE_NOACCESS};
@@ -0,0 +1,111 @@
+//! Versions of [`hudhook`](crate) and of the libraries it re-exports.
+//!
+//! # Re-export policy
+//!
+//! [`hudhook`](crate) re-exports `imgui`, `windows` and `tracing`, and every
+//! public signature only uses types reachable through those re-exports. Use
+//! them as `hudhook::imgui`, `hudhook::windows` and `hudhook::tracing` instead
+//! of depending on the crates directly: that way, your mod can never end up
+//! with two incompatible copies of e.g. `imgui::Ui`, and bumping
+//! [`hudhook`](crate) bumps them for you.
+//!
+//! Bumping any re-exported crate to a semver-incompatible version is a
+//! breaking change of [`hudhook`](crate) itself.
+//!
+//! # Runtime checks
+//!
+//! Types can only be shared at compile time. Mods that exchange data at
+//! runtime with separately built DLLs, e.g. plugins receiving a pointer to the
+//! `imgui` context, can use [`check_hudhook_version`] and
+//! `check_dear_imgui_version` to make sure both sides agree.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::version::{check_hudhook_version, Version};
+//!
+//! // Version the plugin was built against, e.g. passed through its ABI.
+//! let plugin_version = Version::parse("0.8.0").unwrap();
+//! if !check_hudhook_version(plugin_version) {
+//!     hudhook::tracing::error!("Plugin requires hudhook {plugin_version}");
+//! }
+//! ```
+use std::fmt;
+
+/// Version of [`hudhook`](crate), as a string.
+pub const HUDHOOK_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A `major.minor.patch` version number.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// Major version.
+    pub major: u16,
+    /// Minor version.
+    pub minor: u16,
+    /// Patch version.
+    pub patch: u16,
+}
+
+impl Version {
+    /// Create a version.
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// Version of [`hudhook`](crate).
+    pub fn hudhook() -> Self {
+        Self::parse(HUDHOOK_VERSION).unwrap_or_default()
+    }
+
+    /// Parse a `major.minor.patch` version. Missing components default to
+    /// `0`, and pre-release or build suffixes are ignored.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.split(['-', '+']).next()?;
+        let mut parts = s.split('.').map(str::parse::<u16>);
+
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().transpose().ok()?.unwrap_or(0);
+        let patch = parts.next().transpose().ok()?.unwrap_or(0);
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(Self { major, minor, patch }),
+        }
+    }
+
+    /// Whether `self` and `other` are semver-compatible, i.e. code built
+    /// against one works with the other.
+    pub fn is_compatible_with(&self, other: &Version) -> bool {
+        match (self.major, other.major) {
+            (0, 0) => self.minor == other.minor,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Version of the Dear ImGui C++ library linked into [`hudhook`](crate).
+#[cfg(feature = "renderer")]
+pub fn dear_imgui_version() -> &'static str {
+    imgui::dear_imgui_version()
+}
+
+/// Whether code built against [`hudhook`](crate) `required` can run with this
+/// version of [`hudhook`](crate).
+pub fn check_hudhook_version(required: Version) -> bool {
+    let current = Version::hudhook();
+    current.is_compatible_with(&required) && current >= required
+}
+
+/// Whether `version`, as returned by `imgui::dear_imgui_version` in another
+/// module, matches the Dear ImGui version linked into [`hudhook`](crate).
+///
+/// Dear ImGui has no stable ABI, so sharing a context requires an exact match.
+#[cfg(feature = "renderer")]
+pub fn check_dear_imgui_version(version: &str) -> bool {
+    version == dear_imgui_version()
+}
@@ -0,0 +1,315 @@
+//! Watch list of values in the game's memory.
+//!
+//! Trainers and research HUDs display values the game keeps in memory, often
+//! behind a chain of pointers found with a memory scanner. Watches registered
+//! here are [sampled](sample) once per frame on the render thread, before the
+//! render loop runs, and [`draw`] displays them as a table, flashing the
+//! values that just changed. Reads go through [`memory::read`], so a chain
+//! that is broken, e.g. while the game loads a level, shows as unresolved
+//! rather than crashing the game.
+//!
+//! Example usage:
+//! ```no_run
+//! use hudhook::watch::{self, PointerChain, ValueType};
+//!
+//! // "game.exe"+0x1A2B3C -> +0x10 -> +0x48, as a memory scanner would show it.
+//! let health = PointerChain::module("game.exe", 0x1a2b3c).offset(0x10).offset(0x48);
+//! watch::add("Health", health, ValueType::F32);
+//!
+//! // From `ImguiRenderLoop::render`:
+//! // ui.window("Watches").build(|| watch::draw(ui));
+//! ```
+use std::time::{Duration, Instant};
+use std::{fmt, mem};
+
+use imgui::{StyleColor, Ui};
+use parking_lot::Mutex;
+use windows::core::HSTRING;
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+
+use crate::memory;
+
+static WATCHES: Mutex<Vec<Watch>> = Mutex::new(Vec::new());
+
+// How long a changed value stays highlighted.
+const FLASH_DURATION: Duration = Duration::from_millis(1000);
+const COLOR_CHANGED: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+const COLOR_UNRESOLVED: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+
+/// Where a pointer chain starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Base {
+    /// A fixed address.
+    Address(usize),
+    /// An offset from the base address of a module, e.g. `game.exe`, which
+    /// survives the module being loaded elsewhere.
+    Module(String, usize),
+}
+
+/// An address, possibly behind pointers: the address of the base is followed
+/// by adding each offset to the pointer read at the previous address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerChain {
+    /// Where the chain starts.
+    pub base: Base,
+    /// Offsets added to the pointers followed, in order.
+    pub offsets: Vec<isize>,
+}
+
+impl PointerChain {
+    /// A chain starting at `address`.
+    pub fn address(address: usize) -> Self {
+        Self { base: Base::Address(address), offsets: Vec::new() }
+    }
+
+    /// A chain starting `offset` bytes into the module named `module`.
+    pub fn module(module: impl Into<String>, offset: usize) -> Self {
+        Self { base: Base::Module(module.into(), offset), offsets: Vec::new() }
+    }
+
+    /// Follow the pointer at the current end of the chain, then add `offset`.
+    pub fn offset(mut self, offset: isize) -> Self {
+        self.offsets.push(offset);
+        self
+    }
+
+    /// The address the chain points to right now, unless the module isn't
+    /// loaded or one of the pointers can't be read or is null.
+    pub fn resolve(&self) -> Option<usize> {
+        let mut address = match &self.base {
+            Base::Address(address) => *address,
+            Base::Module(module, offset) => {
+                let module = unsafe { GetModuleHandleW(&HSTRING::from(module.as_str())) }.ok()?;
+                (module.0 as usize).checked_add(*offset)?
+            },
+        };
+
+        for offset in &self.offsets {
+            let pointer = match read_value(address, ValueType::Pointer)? {
+                Value::Pointer(0) => return None,
+                Value::Pointer(pointer) => pointer,
+                _ => unreachable!(),
+            };
+            address = pointer.checked_add_signed(*offset)?;
+        }
+
+        Some(address)
+    }
+}
+
+impl fmt::Display for PointerChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.base {
+            Base::Address(address) => write!(f, "{address:#x}")?,
+            Base::Module(module, offset) => write!(f, "\"{module}\"+{offset:#x}")?,
+        }
+        for offset in &self.offsets {
+            match offset {
+                0.. => write!(f, " -> +{offset:#x}")?,
+                _ => write!(f, " -> -{:#x}", offset.unsigned_abs())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the bytes at the end of a pointer chain are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    /// `u8`.
+    U8,
+    /// `u16`.
+    U16,
+    /// `u32`.
+    U32,
+    /// `u64`.
+    U64,
+    /// `i8`.
+    I8,
+    /// `i16`.
+    I16,
+    /// `i32`.
+    I32,
+    /// `i64`.
+    I64,
+    /// `f32`.
+    F32,
+    /// `f64`.
+    F64,
+    /// A pointer of the game's pointer width.
+    Pointer,
+}
+
+impl ValueType {
+    /// Size of the value, in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::U64 | Self::I64 | Self::F64 => 8,
+            Self::Pointer => mem::size_of::<usize>(),
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Value {
+        let mut buf = [0u8; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        let bits = u64::from_le_bytes(buf);
+
+        match self {
+            Self::U8 | Self::U16 | Self::U32 | Self::U64 => Value::Unsigned(bits),
+            Self::I8 => Value::Signed(bits as i8 as i64),
+            Self::I16 => Value::Signed(bits as i16 as i64),
+            Self::I32 => Value::Signed(bits as i32 as i64),
+            Self::I64 => Value::Signed(bits as i64),
+            Self::F32 => Value::Float(f32::from_bits(bits as u32) as f64),
+            Self::F64 => Value::Float(f64::from_bits(bits)),
+            Self::Pointer => Value::Pointer(bits as usize),
+        }
+    }
+}
+
+/// A value read from the game's memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    /// An unsigned integer.
+    Unsigned(u64),
+    /// A signed integer.
+    Signed(i64),
+    /// A floating point number.
+    Float(f64),
+    /// A pointer.
+    Pointer(usize),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsigned(value) => write!(f, "{value}"),
+            Self::Signed(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value:.3}"),
+            Self::Pointer(value) => write!(f, "{value:#x}"),
+        }
+    }
+}
+
+// Read a value of type `ty` at `address`, if all of its bytes can be read.
+fn read_value(address: usize, ty: ValueType) -> Option<Value> {
+    let bytes = memory::read(address, ty.size()).into_iter().collect::<Option<Vec<u8>>>()?;
+    Some(ty.decode(&bytes))
+}
+
+/// A registered watch, as of the last time it was sampled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watch {
+    /// Name of the watch.
+    pub name: String,
+    /// Where the value is.
+    pub chain: PointerChain,
+    /// How the value is interpreted.
+    pub ty: ValueType,
+    /// Address the chain resolved to, unless it couldn't be resolved.
+    pub address: Option<usize>,
+    /// The value, unless the chain couldn't be resolved or the value couldn't
+    /// be read.
+    pub value: Option<Value>,
+    /// When the value last changed.
+    pub changed_at: Option<Instant>,
+}
+
+/// Watch the value of type `ty` that `chain` points to as `name`, replacing
+/// the watch registered under `name`, if any.
+pub fn add(name: impl Into<String>, chain: PointerChain, ty: ValueType) {
+    let watch =
+        Watch { name: name.into(), chain, ty, address: None, value: None, changed_at: None };
+
+    let mut watches = WATCHES.lock();
+    match watches.iter_mut().find(|w| w.name == watch.name) {
+        Some(w) => *w = watch,
+        None => watches.push(watch),
+    }
+}
+
+/// Remove the watch registered under `name`.
+pub fn remove(name: &str) {
+    WATCHES.lock().retain(|w| w.name != name);
+}
+
+/// Remove all watches.
+pub fn clear() {
+    WATCHES.lock().clear();
+}
+
+/// The watch registered under `name`, as of the last time it was sampled.
+pub fn get(name: &str) -> Option<Watch> {
+    WATCHES.lock().iter().find(|w| w.name == name).cloned()
+}
+
+/// All watches, in registration order, as of the last time they were sampled.
+pub fn watches() -> Vec<Watch> {
+    WATCHES.lock().clone()
+}
+
+/// Resolve the chains and read the values of all watches. This happens once
+/// per frame on the render thread; tools needing fresher values, e.g. on a
+/// background thread, can call it more often.
+pub fn sample() {
+    let now = Instant::now();
+
+    for watch in WATCHES.lock().iter_mut() {
+        watch.address = watch.chain.resolve();
+        let value = watch.address.and_then(|address| read_value(address, watch.ty));
+
+        if watch.value.is_some() && value.is_some() && value != watch.value {
+            watch.changed_at = Some(now);
+        }
+        watch.value = value;
+    }
+}
+
+/// Draw the watches into the current window: one row per watch with its name,
+/// its value and its address. Values that changed recently are highlighted.
+pub fn draw(ui: &Ui) {
+    let now = Instant::now();
+
+    ui.columns(3, "##hudhook_watches", false);
+    for watch in watches() {
+        ui.text(&watch.name);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(watch.chain.to_string());
+        }
+        ui.next_column();
+
+        match watch.value {
+            Some(value) => {
+                // Fade from the highlight color back to the text color.
+                let elapsed = watch.changed_at.map(|t| now.duration_since(t));
+                match elapsed.filter(|&elapsed| elapsed < FLASH_DURATION) {
+                    Some(elapsed) => {
+                        let t = elapsed.as_secs_f32() / FLASH_DURATION.as_secs_f32();
+                        let text = ui.style_color(StyleColor::Text);
+                        let color: [f32; 4] =
+                            std::array::from_fn(|i| COLOR_CHANGED[i] * (1.0 - t) + text[i] * t);
+                        ui.text_colored(color, value.to_string());
+                    },
+                    None => ui.text(value.to_string()),
+                }
+            },
+            None => ui.text_colored(COLOR_UNRESOLVED, "??"),
+        }
+        ui.next_column();
+
+        match watch.address {
+            Some(address) => {
+                ui.text_disabled(format!("{address:#x}"));
+                if ui.is_item_clicked() {
+                    ui.set_clipboard_text(format!("{address:#x}"));
+                }
+            },
+            None => ui.text_disabled("-"),
+        }
+        ui.next_column();
+    }
+    ui.columns(1, "##hudhook_watches", false);
+}
@@ -21,5 +21,21 @@ fn test_imgui_dx12() {
     }
 
     thread::sleep(Duration::from_millis(25000));
+
+    // Resizing fails if the hooks leaked a reference to a back buffer.
+    for (width, height) in [(1024, 768), (640, 480), (800, 600)] {
+        dx12_harness.resize(width, height);
+        thread::sleep(Duration::from_millis(1000));
+    }
+
     drop(dx12_harness);
+
+    let errors = harness::dx12::resize_errors();
+    assert!(errors.is_empty(), "Resize errors:\n{}", errors.join("\n"));
+
+    #[cfg(feature = "debug-layer")]
+    {
+        let errors = harness::dx12::validation_errors();
+        assert!(errors.is_empty(), "D3D12 validation errors:\n{}", errors.join("\n"));
+    }
 }
@@ -0,0 +1,72 @@
+mod harness;
+mod hook;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use harness::dx12::Dx12Harness;
+use hudhook::hooks::dx12::{self, ImguiDx12Hooks, OverlayOutput, WindowVisibility};
+use hudhook::*;
+
+static ADDED: AtomicUsize = AtomicUsize::new(0);
+static RAN: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+// Counts the drops of the closures the callbacks capture.
+struct DropCounter;
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+struct CallbackLoop;
+
+impl ImguiRenderLoop for CallbackLoop {
+    fn render(&mut self, ui: &mut imgui::Ui) {
+        ui.window("Callbacks").build(|| {
+            let counter = DropCounter;
+            ui.get_window_draw_list()
+                .add_callback(move || {
+                    let _counter = counter;
+                    RAN.fetch_add(1, Ordering::SeqCst);
+                })
+                .build();
+            ADDED.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+}
+
+#[test]
+fn test_dx12_callbacks_in_both_outputs() {
+    hook::setup_tracing();
+
+    let dx12_harness = Dx12Harness::new();
+    thread::sleep(Duration::from_millis(1000));
+
+    // Both outputs replay the same draw data: the callbacks must still run,
+    // and their closures be freed, once per frame. The window is hidden from
+    // the first output, whose pass runs the callbacks.
+    dx12::set_overlay_output(OverlayOutput::Both);
+    dx12::set_window_visibility("Callbacks", WindowVisibility::SharedTextureOnly);
+    let hooks = Hudhook::builder().with::<ImguiDx12Hooks>(CallbackLoop).apply();
+    if let Err(e) = &hooks {
+        eprintln!("Couldn't apply hooks: {e:?}");
+    }
+
+    thread::sleep(Duration::from_millis(5000));
+
+    drop(hooks);
+    drop(dx12_harness);
+
+    let added = ADDED.load(Ordering::SeqCst);
+    let ran = RAN.load(Ordering::SeqCst);
+    let dropped = DROPPED.load(Ordering::SeqCst);
+    assert!(added > 0, "No frame was rendered");
+    // The callbacks of the last frame may not have run when the hooks were
+    // disabled.
+    assert!(ran <= added && ran + 1 >= added, "{ran} callbacks ran, {added} were added");
+    assert_eq!(dropped, ran, "{dropped} closures dropped, {ran} ran");
+}
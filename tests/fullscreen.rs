@@ -0,0 +1,48 @@
+mod harness;
+mod hook;
+
+use std::thread;
+use std::time::Duration;
+
+use harness::dx11::Dx11Harness;
+use hook::HookExample;
+use hudhook::hooks::dx11::ImguiDx11Hooks;
+use hudhook::hooks::is_render_disabled;
+use hudhook::*;
+
+#[test]
+fn test_fullscreen_alt_tab() {
+    hook::setup_tracing();
+
+    let dx11_harness = Dx11Harness::new("DX11 fullscreen");
+    thread::sleep(Duration::from_millis(500));
+
+    let hooks = Hudhook::builder().with::<ImguiDx11Hooks>(HookExample::new()).apply();
+    if let Err(e) = &hooks {
+        eprintln!("Couldn't apply hooks: {e:?}");
+    }
+
+    thread::sleep(Duration::from_millis(2000));
+    let hwnd = dx11_harness.hwnd();
+
+    dx11_harness.set_fullscreen(true);
+    thread::sleep(Duration::from_millis(2000));
+    eprintln!("Window state in fullscreen: {:?}", window_state(hwnd));
+
+    // Alt-tab out, and back in.
+    dx11_harness.minimize();
+    thread::sleep(Duration::from_millis(2000));
+    assert!(matches!(window_state(hwnd), Some(WindowState::Minimized { .. })));
+
+    dx11_harness.restore();
+    dx11_harness.set_fullscreen(true);
+    thread::sleep(Duration::from_millis(2000));
+    assert!(matches!(window_state(hwnd), Some(WindowState::Windowed | WindowState::Fullscreen)));
+    assert!(!is_render_disabled(), "The overlay stopped rendering after alt-tabbing");
+
+    dx11_harness.set_fullscreen(false);
+    thread::sleep(Duration::from_millis(1000));
+
+    drop(hooks);
+    drop(dx11_harness);
+}
@@ -0,0 +1,81 @@
+mod harness;
+mod hook;
+
+use std::thread;
+use std::time::Duration;
+
+use harness::dx11::Dx11Harness;
+use harness::golden;
+use hudhook::hooks::dx11::ImguiDx11Hooks;
+use hudhook::*;
+use imgui::{Condition, ConfigFlags, Context, WindowFlags};
+
+// Bits of the 256-bit hash allowed to differ from the golden image.
+const TOLERANCE: u32 = 12;
+
+// Draws the same frame every time, exercising blending and clipping.
+struct GoldenRenderLoop;
+
+impl ImguiRenderLoop for GoldenRenderLoop {
+    fn initialize<'a>(&'a mut self, ctx: &mut Context, _: &'a mut dyn RenderContext) {
+        // The real cursor must not hover anything.
+        ctx.io_mut().config_flags |= ConfigFlags::NO_MOUSE;
+    }
+
+    fn render(&mut self, ui: &mut imgui::Ui) {
+        {
+            // Translucent squares overlapping each other and the game.
+            let background = ui.get_background_draw_list();
+            background
+                .add_rect([400.0, 100.0], [600.0, 300.0], [1.0, 0.0, 0.0, 0.5])
+                .filled(true)
+                .build();
+            background
+                .add_rect([500.0, 200.0], [700.0, 400.0], [0.0, 0.0, 1.0, 0.5])
+                .filled(true)
+                .build();
+
+            // A circle clipped to its bottom right quarter.
+            background.with_clip_rect_intersect([200.0, 450.0], [400.0, 600.0], || {
+                background
+                    .add_circle([200.0, 450.0], 120.0, [1.0, 1.0, 0.0, 1.0])
+                    .filled(true)
+                    .build();
+            });
+        }
+
+        ui.window("Golden")
+            .position([20.0, 20.0], Condition::Always)
+            .size([300.0, 200.0], Condition::Always)
+            .flags(WindowFlags::NO_MOVE | WindowFlags::NO_RESIZE | WindowFlags::NO_COLLAPSE)
+            .build(|| {
+                ui.text("Golden image test");
+                ui.separator();
+                for i in 0..20 {
+                    // Most lines fall outside of the window and are clipped.
+                    ui.text(format!("Line {i}"));
+                }
+            });
+    }
+}
+
+#[test]
+fn test_golden_dx11() {
+    hook::setup_tracing();
+
+    let dx11_harness = Dx11Harness::new("DX11 golden");
+    thread::sleep(Duration::from_millis(500));
+
+    let hooks = Hudhook::builder().with::<ImguiDx11Hooks>(GoldenRenderLoop).apply();
+    if let Err(e) = &hooks {
+        eprintln!("Couldn't apply hooks: {e:?}");
+    }
+
+    thread::sleep(Duration::from_millis(2000));
+    let frame = golden::capture(dx11_harness.hwnd());
+
+    drop(hooks);
+    drop(dx11_harness);
+
+    golden::assert_matches_golden("dx11", &frame, TOLERANCE);
+}
@@ -0,0 +1,55 @@
+mod harness;
+mod hook;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use harness::dx12::Dx12Harness;
+use hudhook::hooks::dx12::ImguiDx12Hooks;
+use hudhook::*;
+
+static FRAMES: AtomicUsize = AtomicUsize::new(0);
+static GUARD: Mutex<Option<HudhookGuard>> = Mutex::new(None);
+
+// Drops the guard from within the render loop after a few frames.
+struct DroppingLoop;
+
+impl ImguiRenderLoop for DroppingLoop {
+    fn render(&mut self, ui: &mut imgui::Ui) {
+        ui.text("Dropping the guard soon");
+        if FRAMES.fetch_add(1, Ordering::SeqCst) == 60 {
+            drop(GUARD.lock().unwrap().take());
+        }
+    }
+}
+
+#[test]
+fn test_guard_teardown() {
+    hook::setup_tracing();
+
+    let dx12_harness = Dx12Harness::new();
+    thread::sleep(Duration::from_millis(1000));
+
+    match Hudhook::builder().with::<ImguiDx12Hooks>(DroppingLoop).apply() {
+        Ok(guard) => *GUARD.lock().unwrap() = Some(guard),
+        Err(e) => panic!("Couldn't apply hooks: {e:?}"),
+    }
+
+    thread::sleep(Duration::from_millis(5000));
+
+    // Dropping the guard from the render loop must neither deadlock nor leave
+    // the hooks rendering.
+    let frames = FRAMES.load(Ordering::SeqCst);
+    assert!(frames > 60, "The guard wasn't dropped, {frames} frames were rendered");
+    assert!(GUARD.lock().unwrap().is_none());
+    thread::sleep(Duration::from_millis(1000));
+    assert_eq!(FRAMES.load(Ordering::SeqCst), frames, "The hooks still render");
+
+    drop(dx12_harness);
+
+    // Unapplying the hooks must release everything the render engine created.
+    let leaked = hooks::dx12::leaked_objects();
+    assert!(leaked.is_empty(), "Leaked objects:\n{}", leaked.join("\n"));
+}
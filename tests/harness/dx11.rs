@@ -1,7 +1,7 @@
 use std::ffi::CString;
 use std::mem::MaybeUninit;
 use std::ptr::null_mut;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, OnceLock};
 use std::thread::{self, JoinHandle};
@@ -11,25 +11,31 @@ use windows::core::PCSTR;
 use windows::Win32::Foundation::{BOOL, HWND, LPARAM, LRESULT, RECT, WPARAM};
 use windows::Win32::Graphics::Direct3D::{D3D_DRIVER_TYPE_HARDWARE, D3D_FEATURE_LEVEL_11_0};
 use windows::Win32::Graphics::Direct3D11::{
-    D3D11CreateDeviceAndSwapChain, ID3D11Device, ID3D11DeviceContext, ID3D11Resource,
-    D3D11_CREATE_DEVICE_FLAG, D3D11_SDK_VERSION,
+    D3D11CreateDeviceAndSwapChain, ID3D11Device, ID3D11DeviceContext, ID3D11RenderTargetView,
+    ID3D11Resource, D3D11_CREATE_DEVICE_DEBUG, D3D11_CREATE_DEVICE_FLAG, D3D11_SDK_VERSION,
 };
 use windows::Win32::Graphics::Dxgi::Common::{
     DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_MODE_DESC, DXGI_RATIONAL, DXGI_SAMPLE_DESC,
 };
 use windows::Win32::Graphics::Dxgi::{
-    IDXGISwapChain, DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_EFFECT_DISCARD, DXGI_USAGE_RENDER_TARGET_OUTPUT,
+    IDXGIOutput, IDXGISwapChain, DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_EFFECT_DISCARD,
+    DXGI_USAGE_RENDER_TARGET_OUTPUT,
 };
 use windows::Win32::Graphics::Gdi::HBRUSH;
 use windows::Win32::System::LibraryLoader::GetModuleHandleA;
 use windows::Win32::UI::WindowsAndMessaging::{
     AdjustWindowRect, CreateWindowExA, DefWindowProcA, DispatchMessageA, PeekMessageA,
-    PostQuitMessage, RegisterClassA, SetTimer, TranslateMessage, CS_HREDRAW, CS_OWNDC, CS_VREDRAW,
-    HCURSOR, HICON, HMENU, PM_REMOVE, WINDOW_EX_STYLE, WM_DESTROY, WM_QUIT, WM_SIZE, WNDCLASSA,
-    WS_OVERLAPPEDWINDOW, WS_VISIBLE,
+    PostQuitMessage, RegisterClassA, SetTimer, SetWindowPos, ShowWindowAsync, TranslateMessage,
+    CS_HREDRAW, CS_OWNDC, CS_VREDRAW, HCURSOR, HICON, HMENU, PM_REMOVE, SWP_ASYNCWINDOWPOS,
+    SWP_NOMOVE, SWP_NOZORDER, SW_MINIMIZE, SW_RESTORE, WINDOW_EX_STYLE, WM_APP, WM_DESTROY,
+    WM_QUIT, WM_SIZE, WNDCLASSA, WS_OVERLAPPEDWINDOW, WS_VISIBLE,
 };
 
 static RESIZE: OnceLock<Sender<(u32, u32)>> = OnceLock::new();
+static FULLSCREEN: OnceLock<Sender<bool>> = OnceLock::new();
+static LIVE_OBJECTS: OnceLock<Sender<Sender<Vec<String>>>> = OnceLock::new();
+static WINDOW: OnceLock<isize> = OnceLock::new();
+static APP_MESSAGES: AtomicUsize = AtomicUsize::new(0);
 
 pub struct Dx11Harness {
     child: Option<JoinHandle<()>>,
@@ -40,6 +46,18 @@ pub struct Dx11Harness {
 impl Dx11Harness {
     #[allow(unused)]
     pub fn new(caption: &str) -> Self {
+        Self::create(caption, D3D11_CREATE_DEVICE_FLAG(0))
+    }
+
+    /// Create the device with the debug layer, so that
+    /// [`live_objects`](Self::live_objects) reports the objects alive.
+    /// Requires the graphics tools to be installed.
+    #[allow(unused)]
+    pub fn with_debug_layer(caption: &str) -> Self {
+        Self::create(caption, D3D11_CREATE_DEVICE_DEBUG)
+    }
+
+    fn create(caption: &str, flags: D3D11_CREATE_DEVICE_FLAG) -> Self {
         let done = Arc::new(AtomicBool::new(false));
         let caption = Arc::new(CString::new(caption).unwrap());
         let child = Some(thread::spawn({
@@ -83,6 +101,8 @@ impl Dx11Harness {
                     )
                 };
 
+                WINDOW.get_or_init(|| hwnd.0);
+
                 unsafe { util::enable_debug_interface() };
 
                 let mut p_device: Option<ID3D11Device> = None;
@@ -93,7 +113,7 @@ impl Dx11Harness {
                         None,
                         D3D_DRIVER_TYPE_HARDWARE,
                         None,
-                        D3D11_CREATE_DEVICE_FLAG(0),
+                        flags,
                         Some(&[D3D_FEATURE_LEVEL_11_0]),
                         D3D11_SDK_VERSION,
                         Some(&DXGI_SWAP_CHAIN_DESC {
@@ -123,12 +143,7 @@ impl Dx11Harness {
                 let device = p_device.unwrap();
                 let context = p_context.unwrap();
 
-                let backbuf: ID3D11Resource = unsafe { swap_chain.GetBuffer(0).unwrap() };
-
-                let mut rtv = util::try_out_ptr(|v| unsafe {
-                    device.CreateRenderTargetView(&backbuf, None, Some(v))
-                })
-                .unwrap();
+                let mut rtv = Some(create_render_target_view(&device, &swap_chain));
 
                 unsafe { SetTimer(hwnd, 0, 100, None) };
 
@@ -136,10 +151,18 @@ impl Dx11Harness {
 
                 RESIZE.get_or_init(move || tx);
 
+                let (fullscreen_tx, fullscreen_rx) = mpsc::channel();
+                FULLSCREEN.get_or_init(move || fullscreen_tx);
+
+                let (live_objects_tx, live_objects_rx) = mpsc::channel::<Sender<Vec<String>>>();
+                LIVE_OBJECTS.get_or_init(move || live_objects_tx);
+
                 loop {
                     unsafe { util::print_dxgi_debug_messages() };
 
-                    unsafe { context.ClearRenderTargetView(&rtv, &[0.2, 0.8, 0.2, 0.8]) };
+                    if let Some(rtv) = &rtv {
+                        unsafe { context.ClearRenderTargetView(rtv, &[0.2, 0.8, 0.2, 0.8]) };
+                    }
 
                     eprintln!("Present...");
                     unsafe { swap_chain.Present(1, 0).unwrap() };
@@ -149,20 +172,120 @@ impl Dx11Harness {
                         break;
                     }
 
-                    if let Some((width, height)) = rx.try_iter().last() {
+                    // Minimized windows are resized to nothing: keep the buffers as they are.
+                    if let Some((width, height)) =
+                        rx.try_iter().filter(|&(w, h)| w > 0 && h > 0).last()
+                    {
                         let desc =
                             util::try_out_param(|v| unsafe { swap_chain.GetDesc(v) }).unwrap();
+
+                        // Every reference to the buffers must be released before resizing them.
+                        rtv = None;
+                        unsafe { context.ClearState() };
+                        if let Err(e) = unsafe {
+                            swap_chain.ResizeBuffers(
+                                desc.BufferCount,
+                                width,
+                                height,
+                                desc.BufferDesc.Format,
+                                desc.Flags,
+                            )
+                        } {
+                            eprintln!("Couldn't resize buffers: {e:?}");
+                        }
+                        rtv = Some(create_render_target_view(&device, &swap_chain));
                     };
 
+                    for reply in live_objects_rx.try_iter() {
+                        reply.send(util::report_live_objects()).ok();
+                    }
+
+                    if let Some(fullscreen) = fullscreen_rx.try_iter().last() {
+                        // Exclusive fullscreen isn't available on every machine, e.g.
+                        // without a display attached.
+                        if let Err(e) = unsafe {
+                            swap_chain
+                                .SetFullscreenState(BOOL::from(fullscreen), None::<&IDXGIOutput>)
+                        } {
+                            eprintln!("Couldn't set fullscreen state: {e:?}");
+                        }
+                    }
+
                     if done.load(Ordering::SeqCst) {
                         break;
                     }
                 }
+
+                // Swap chains must leave fullscreen before being released.
+                unsafe {
+                    swap_chain.SetFullscreenState(BOOL::from(false), None::<&IDXGIOutput>).ok()
+                };
             }
         }));
 
         Self { child, done, _caption: caption }
     }
+
+    /// The harness window, once created.
+    #[allow(unused)]
+    pub fn hwnd(&self) -> HWND {
+        HWND(WINDOW.get().copied().unwrap_or_default())
+    }
+
+    /// Enter or leave exclusive fullscreen.
+    #[allow(unused)]
+    pub fn set_fullscreen(&self, fullscreen: bool) {
+        if let Some(tx) = FULLSCREEN.get() {
+            tx.send(fullscreen).ok();
+        }
+    }
+
+    /// Minimize the window, as alt-tabbing out of exclusive fullscreen does.
+    #[allow(unused)]
+    pub fn minimize(&self) {
+        unsafe { ShowWindowAsync(self.hwnd(), SW_MINIMIZE) };
+    }
+
+    /// Number of `WM_APP` range messages that reached the harness window
+    /// procedure.
+    #[allow(unused)]
+    pub fn app_messages(&self) -> usize {
+        APP_MESSAGES.load(Ordering::SeqCst)
+    }
+
+    /// Resize the window to `width` by `height` pixels.
+    #[allow(unused)]
+    pub fn resize(&self, width: i32, height: i32) {
+        unsafe {
+            SetWindowPos(
+                self.hwnd(),
+                HWND(0),
+                0,
+                0,
+                width,
+                height,
+                SWP_NOMOVE | SWP_NOZORDER | SWP_ASYNCWINDOWPOS,
+            )
+            .ok()
+        };
+    }
+
+    /// The objects alive on the harness device, as reported by the debug
+    /// layer. Empty without [`with_debug_layer`](Self::with_debug_layer).
+    #[allow(unused)]
+    pub fn live_objects(&self) -> Vec<String> {
+        let (tx, rx) = mpsc::channel();
+        match LIVE_OBJECTS.get() {
+            Some(live_objects) if live_objects.send(tx).is_ok() => rx.recv().unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Restore the window after [`minimize`](Self::minimize).
+    #[allow(unused)]
+    pub fn restore(&self) {
+        unsafe { ShowWindowAsync(self.hwnd(), SW_RESTORE) };
+    }
 }
 
 impl Drop for Dx11Harness {
@@ -172,6 +295,15 @@ impl Drop for Dx11Harness {
     }
 }
 
+fn create_render_target_view(
+    device: &ID3D11Device,
+    swap_chain: &IDXGISwapChain,
+) -> ID3D11RenderTargetView {
+    let backbuf: ID3D11Resource = unsafe { swap_chain.GetBuffer(0).unwrap() };
+    util::try_out_ptr(|v| unsafe { device.CreateRenderTargetView(&backbuf, None, Some(v)) })
+        .unwrap()
+}
+
 #[allow(unused)]
 fn handle_message(window: HWND) -> bool {
     unsafe {
@@ -203,6 +335,9 @@ pub unsafe extern "system" fn window_proc(
                 tx.send((width as _, height as _));
             }
         },
+        WM_APP..=0xbfff => {
+            APP_MESSAGES.fetch_add(1, Ordering::SeqCst);
+        },
         _ => {
             return DefWindowProcA(hwnd, msg, wparam, lparam);
         },
@@ -6,6 +6,7 @@ use std::thread::{self, JoinHandle};
 
 use hudhook::util;
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
 use tracing::{error, trace};
 use windows::core::{w, Interface, Result, PCSTR, PCWSTR};
 use windows::Win32::Foundation::*;
@@ -20,6 +21,22 @@ use windows::Win32::UI::WindowsAndMessaging::*;
 
 type Msg = (HWND, u32, WPARAM, LPARAM);
 static TX: OnceCell<Arc<Sender<Msg>>> = OnceCell::new();
+static RESIZE_ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+#[cfg(feature = "debug-layer")]
+static VALIDATION_ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Errors the D3D12 debug layer reported on the harness device so far.
+#[cfg(feature = "debug-layer")]
+pub fn validation_errors() -> Vec<String> {
+    VALIDATION_ERRORS.lock().clone()
+}
+
+/// Errors `IDXGISwapChain::ResizeBuffers` returned on the harness swap chain
+/// so far.
+#[allow(unused)]
+pub fn resize_errors() -> Vec<String> {
+    RESIZE_ERRORS.lock().clone()
+}
 
 pub struct Dx12Harness {
     child: Option<JoinHandle<()>>,
@@ -47,6 +64,15 @@ impl Dx12Harness {
 
         Self { child, done }
     }
+
+    /// Resize the swap chain buffers, as if the window was resized.
+    #[allow(unused)]
+    pub fn resize(&self, width: u32, height: u32) {
+        if let Some(tx) = TX.get() {
+            let lparam = LPARAM(((height << 16) | (width & 0xffff)) as isize);
+            tx.send((HWND::default(), WM_SIZE, WPARAM(0), lparam)).ok();
+        }
+    }
 }
 
 impl Drop for Dx12Harness {
@@ -96,7 +122,10 @@ unsafe fn run_harness(
     );
 
     trace!("Enabling debug");
+    #[cfg(not(feature = "debug-layer"))]
     util::enable_debug_interface();
+    #[cfg(feature = "debug-layer")]
+    util::enable_gpu_based_validation();
 
     let factory: IDXGIFactory2 = CreateDXGIFactory2(DXGI_CREATE_FACTORY_DEBUG)?;
     let adapter = factory.EnumAdapters(0)?;
@@ -182,6 +211,11 @@ unsafe fn run_harness(
 
     loop {
         util::print_dxgi_debug_messages();
+        #[cfg(feature = "debug-layer")]
+        for e in util::take_d3d12_validation_errors(&device) {
+            error!("[D3D12] {e}");
+            VALIDATION_ERRORS.lock().push(e);
+        }
         let rtv = rtv[swap_chain.GetCurrentBackBufferIndex() as usize];
         let back_buffer = swap_chain.GetBuffer(swap_chain.GetCurrentBackBufferIndex())?;
 
@@ -238,8 +272,20 @@ unsafe fn run_harness(
                     let height = hiword(lparam.0 as u32) as u32;
                     trace!("Resizing {width}x{height}");
 
-                    // TODO look deeper into this crash.
-                    // swap_chain.ResizeBuffers(2, width, height, DXGI_FORMAT_B8G8R8A8_UNORM, 0)?;
+                    // The overlay drew to the buffers after the last wait.
+                    command_queue.Signal(&fence, fence_val)?;
+                    if fence.GetCompletedValue() < fence_val {
+                        fence.SetEventOnCompletion(fence_val, fence_event)?;
+                        WaitForSingleObject(fence_event, INFINITE);
+                    }
+                    fence_val += 1;
+
+                    if let Err(e) =
+                        swap_chain.ResizeBuffers(2, width, height, DXGI_FORMAT_B8G8R8A8_UNORM, 0)
+                    {
+                        error!("Couldn't resize: {e:?}");
+                        RESIZE_ERRORS.lock().push(format!("{width}x{height}: {e:?}"));
+                    }
                     trace!("Resized");
 
                     let buf: ID3D12Resource = swap_chain.GetBuffer(0).unwrap();
@@ -0,0 +1,152 @@
+//! Golden image comparisons of hooked frames.
+//!
+//! Frames are captured from the harness window after the overlay is drawn,
+//! and compared to a golden image in `tests/golden` through a perceptual hash:
+//! small differences, e.g. in antialiasing between GPUs, are tolerated, while
+//! wrong blending or clipping flips enough bits of the hash to fail the test.
+//!
+//! Set `HUDHOOK_UPDATE_GOLDEN=1` to write the captured frames as the new
+//! golden images. Missing golden images are written as well.
+use std::ffi::c_void;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use windows::Win32::Foundation::{HWND, RECT};
+use windows::Win32::Graphics::Gdi::{
+    CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDC, GetDIBits,
+    ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
+};
+use windows::Win32::Storage::Xps::{PrintWindow, PRINT_WINDOW_FLAGS, PW_CLIENTONLY};
+use windows::Win32::UI::WindowsAndMessaging::{GetClientRect, PW_RENDERFULLCONTENT};
+
+// Side of the grid the hash compares neighboring cells of.
+const HASH_SIDE: u32 = 16;
+
+/// Perceptual hash of an image: one bit per cell of a 16x16 grid, set when
+/// the cell is brighter than the cell on its right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHash([u64; 4]);
+
+impl ImageHash {
+    /// Hash of `image`.
+    #[allow(unused)]
+    pub fn of(image: &RgbaImage) -> Self {
+        let gray = imageops::grayscale(image);
+        let small = imageops::resize(&gray, HASH_SIDE + 1, HASH_SIDE, FilterType::Triangle);
+
+        let mut bits = [0u64; 4];
+        for y in 0..HASH_SIDE {
+            for x in 0..HASH_SIDE {
+                if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                    let i = (y * HASH_SIDE + x) as usize;
+                    bits[i / 64] |= 1 << (i % 64);
+                }
+            }
+        }
+
+        Self(bits)
+    }
+
+    /// Number of bits differing between the hashes, out of 256.
+    #[allow(unused)]
+    pub fn distance(&self, other: &Self) -> u32 {
+        self.0.iter().zip(other.0.iter()).map(|(a, b)| (a ^ b).count_ones()).sum()
+    }
+}
+
+/// Capture the client area of `hwnd` as presented, overlay included.
+#[allow(unused)]
+pub fn capture(hwnd: HWND) -> RgbaImage {
+    unsafe {
+        let mut rect = RECT::default();
+        GetClientRect(hwnd, &mut rect).unwrap();
+        let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+
+        let window_dc = GetDC(hwnd);
+        let dc = CreateCompatibleDC(window_dc);
+        let bitmap = CreateCompatibleBitmap(window_dc, width, height);
+        let previous = SelectObject(dc, bitmap);
+
+        // Without `PW_RENDERFULLCONTENT`, DirectX and OpenGL content is black.
+        let flags = PRINT_WINDOW_FLAGS(PW_CLIENTONLY.0 | PW_RENDERFULLCONTENT);
+        let printed = PrintWindow(hwnd, dc, flags).as_bool();
+
+        let mut info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: width,
+                // Top-down rows.
+                biHeight: -height,
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+        GetDIBits(
+            dc,
+            bitmap,
+            0,
+            height as u32,
+            Some(pixels.as_mut_ptr() as *mut c_void),
+            &mut info,
+            DIB_RGB_COLORS,
+        );
+
+        SelectObject(dc, previous);
+        DeleteObject(bitmap);
+        DeleteDC(dc);
+        ReleaseDC(hwnd, window_dc);
+        assert!(printed, "Couldn't capture the harness window");
+
+        // BGRX to RGBA.
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+            pixel[3] = 0xff;
+        }
+
+        RgbaImage::from_raw(width as u32, height as u32, pixels).unwrap()
+    }
+}
+
+/// Assert that `frame` matches the golden image `name` within `tolerance`
+/// bits of their hashes. On failure, the frame is written to
+/// `target/golden/<name>.png` for inspection.
+#[allow(unused)]
+pub fn assert_matches_golden(name: &str, frame: &RgbaImage, tolerance: u32) {
+    let golden_path = golden_dir().join(format!("{name}.png"));
+
+    if std::env::var_os("HUDHOOK_UPDATE_GOLDEN").is_some() || !golden_path.exists() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        frame.save(&golden_path).unwrap();
+        eprintln!("Wrote golden image {golden_path:?}");
+        return;
+    }
+
+    let golden = image::open(&golden_path).unwrap().into_rgba8();
+    assert_eq!(
+        golden.dimensions(),
+        frame.dimensions(),
+        "Frame size differs from golden image {golden_path:?}"
+    );
+
+    let distance = ImageHash::of(&golden).distance(&ImageHash::of(frame));
+    if distance > tolerance {
+        let actual_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("golden");
+        std::fs::create_dir_all(&actual_dir).unwrap();
+        let actual_path = actual_dir.join(format!("{name}.png"));
+        frame.save(&actual_path).unwrap();
+        panic!(
+            "Frame differs from golden image {golden_path:?} by {distance} bits (tolerance \
+             {tolerance}); captured frame written to {actual_path:?}"
+        );
+    }
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
@@ -1,4 +1,5 @@
 pub mod dx11;
 pub mod dx12;
 pub mod dx9;
+pub mod golden;
 pub mod opengl3;
@@ -0,0 +1,118 @@
+mod harness;
+mod hook;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use harness::dx11::Dx11Harness;
+use hudhook::hooks::dx11::ImguiDx11Hooks;
+use hudhook::*;
+use imgui::Condition;
+
+const CYCLES: usize = 300;
+const SIZES: [(i32, i32); 5] = [(640, 480), (1024, 768), (320, 240), (1280, 720), (801, 599)];
+
+static FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+struct SoakRenderLoop;
+
+impl ImguiRenderLoop for SoakRenderLoop {
+    fn render(&mut self, ui: &mut imgui::Ui) {
+        let frame = FRAMES.fetch_add(1, Ordering::SeqCst);
+        ui.window("Soak").size([300.0, 100.0], Condition::FirstUseEver).build(|| {
+            ui.text(format!("Frame {frame}"));
+            let [w, h] = ui.io().display_size;
+            ui.text(format!("Display {w}x{h}"));
+        });
+    }
+}
+
+// Wait for the overlay to render a few frames, failing if it doesn't.
+fn wait_for_frames() {
+    let frames = FRAMES.load(Ordering::SeqCst);
+    for _ in 0..100 {
+        thread::sleep(Duration::from_millis(20));
+        if FRAMES.load(Ordering::SeqCst) >= frames + 2 {
+            return;
+        }
+    }
+    panic!("The overlay stopped rendering after frame {frames}");
+}
+
+#[test]
+fn test_resize_soak() {
+    hook::setup_tracing();
+
+    let dx11_harness = Dx11Harness::with_debug_layer("DX11 resize soak");
+    thread::sleep(Duration::from_millis(500));
+
+    let hooks = Hudhook::builder().with::<ImguiDx11Hooks>(SoakRenderLoop).apply();
+    if let Err(e) = &hooks {
+        eprintln!("Couldn't apply hooks: {e:?}");
+    }
+
+    // Back to a visible 800x600 window, rendering again.
+    let settle = || {
+        dx11_harness.set_fullscreen(false);
+        dx11_harness.restore();
+        dx11_harness.resize(800, 600);
+        thread::sleep(Duration::from_millis(500));
+        wait_for_frames();
+    };
+
+    // Go through every transition once, so that the objects created lazily
+    // exist before the baseline is taken.
+    dx11_harness.resize(SIZES[0].0, SIZES[0].1);
+    dx11_harness.minimize();
+    thread::sleep(Duration::from_millis(200));
+    dx11_harness.set_fullscreen(true);
+    thread::sleep(Duration::from_millis(500));
+    settle();
+    let baseline = dx11_harness.live_objects();
+
+    for i in 0..CYCLES {
+        match i % 4 {
+            0 | 2 => {
+                let (width, height) = SIZES[i / 4 % SIZES.len()];
+                dx11_harness.resize(width, height);
+            },
+            1 => {
+                dx11_harness.minimize();
+                thread::sleep(Duration::from_millis(50));
+                dx11_harness.restore();
+            },
+            _ => {
+                // Exclusive fullscreen isn't available on every machine, in which
+                // case the harness logs the failure and stays windowed.
+                dx11_harness.set_fullscreen(true);
+                thread::sleep(Duration::from_millis(100));
+                dx11_harness.set_fullscreen(false);
+            },
+        }
+        thread::sleep(Duration::from_millis(50));
+
+        // Minimized windows don't present.
+        if i % 4 != 1 {
+            wait_for_frames();
+        }
+    }
+
+    settle();
+    let live_objects = dx11_harness.live_objects();
+
+    drop(hooks);
+    drop(dx11_harness);
+
+    if baseline.is_empty() {
+        eprintln!("The debug layer isn't available: live objects weren't checked");
+        return;
+    }
+    assert!(
+        live_objects.len() <= baseline.len(),
+        "{} objects leaked over {CYCLES} cycles. Before:\n{}\nAfter:\n{}",
+        live_objects.len() - baseline.len(),
+        baseline.join("\n"),
+        live_objects.join("\n")
+    );
+}
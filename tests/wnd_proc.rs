@@ -0,0 +1,149 @@
+mod harness;
+mod hook;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use harness::dx11::Dx11Harness;
+use hudhook::hooks::dx11::ImguiDx11Hooks;
+use hudhook::*;
+use imgui::{Key, MouseButton};
+use parking_lot::Mutex;
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{
+    SendMessageW, WM_ACTIVATEAPP, WM_APP, WM_CHAR, WM_INPUT, WM_KEYDOWN, WM_KEYUP, WM_KILLFOCUS,
+    WM_LBUTTONDBLCLK, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL,
+    WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SETFOCUS, WM_XBUTTONDOWN,
+    WM_XBUTTONUP, XBUTTON1, XBUTTON2,
+};
+
+const SEED: u64 = 0x6875_6468_6f6f_6b21;
+const MESSAGES: usize = 20000;
+
+// Messages translated by the window procedure. System keys are left out:
+// the default window procedure of the harness would open the system menu or
+// close the window on some of them.
+const FUZZED: [u32; 19] = [
+    WM_INPUT,
+    WM_KEYDOWN,
+    WM_KEYUP,
+    WM_CHAR,
+    WM_LBUTTONDOWN,
+    WM_LBUTTONDBLCLK,
+    WM_LBUTTONUP,
+    WM_RBUTTONDOWN,
+    WM_RBUTTONUP,
+    WM_MBUTTONDOWN,
+    WM_MBUTTONUP,
+    WM_XBUTTONDOWN,
+    WM_XBUTTONUP,
+    WM_MOUSEWHEEL,
+    WM_MOUSEHWHEEL,
+    WM_MOUSEMOVE,
+    WM_SETFOCUS,
+    WM_KILLFOCUS,
+    WM_ACTIVATEAPP,
+];
+
+static FRAMES: AtomicUsize = AtomicUsize::new(0);
+static INPUTS_DOWN: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Records the keys and buttons `imgui` considers down every frame.
+struct InputStateRenderLoop;
+
+impl ImguiRenderLoop for InputStateRenderLoop {
+    fn render(&mut self, ui: &mut imgui::Ui) {
+        let keys = Key::VARIANTS.into_iter().filter(|&key| ui.is_key_down(key));
+        let buttons = MouseButton::VARIANTS.into_iter().filter(|&button| ui.is_mouse_down(button));
+        *INPUTS_DOWN.lock() =
+            keys.map(|k| format!("{k:?}")).chain(buttons.map(|b| format!("{b:?}"))).collect();
+
+        FRAMES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+// xorshift64*, so that failures reproduce with the same seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Mostly plausible values, sometimes garbage.
+    fn param(&mut self) -> usize {
+        match self.next() % 4 {
+            0 => (self.next() & 0xff) as usize,
+            1 => (self.next() & 0xffff_ffff) as usize,
+            2 => self.next() as usize,
+            _ => [0, usize::MAX, 0x8000_0000, 0xd800, 0x11_0000][(self.next() % 5) as usize],
+        }
+    }
+}
+
+fn send(hwnd: HWND, msg: u32, wparam: usize, lparam: usize) {
+    unsafe { SendMessageW(hwnd, msg, WPARAM(wparam), LPARAM(lparam as isize)) };
+}
+
+#[test]
+fn test_wnd_proc_fuzz() {
+    hook::setup_tracing();
+
+    let dx11_harness = Dx11Harness::new("DX11 window procedure fuzz");
+    thread::sleep(Duration::from_millis(500));
+
+    let hooks = Hudhook::builder().with::<ImguiDx11Hooks>(InputStateRenderLoop).apply();
+    if let Err(e) = &hooks {
+        eprintln!("Couldn't apply hooks: {e:?}");
+    }
+    thread::sleep(Duration::from_millis(1000));
+
+    let hwnd = dx11_harness.hwnd();
+    let mut rng = Rng(SEED);
+    let mut app_messages = 0;
+    eprintln!("Fuzzing the window procedure with seed {SEED:#x}");
+
+    for i in 0..MESSAGES {
+        let msg = FUZZED[(rng.next() % FUZZED.len() as u64) as usize];
+        let (wparam, lparam) = (rng.param(), rng.param());
+        send(hwnd, msg, wparam, lparam);
+
+        // Messages the overlay doesn't handle must reach the game.
+        if i % 50 == 0 {
+            send(hwnd, WM_APP + (rng.next() % 0x100) as u32, wparam, lparam);
+            app_messages += 1;
+        }
+    }
+
+    // Release everything the fuzzing may have pressed, and reactivate the window
+    // it may have deactivated.
+    for vk in 1..256 {
+        send(hwnd, WM_KEYUP, vk, 0xc000_0001);
+    }
+    send(hwnd, WM_LBUTTONUP, 0, 0);
+    send(hwnd, WM_RBUTTONUP, 0, 0);
+    send(hwnd, WM_MBUTTONUP, 0, 0);
+    send(hwnd, WM_XBUTTONUP, (XBUTTON1 as usize) << 16, 0);
+    send(hwnd, WM_XBUTTONUP, (XBUTTON2 as usize) << 16, 0);
+    send(hwnd, WM_ACTIVATEAPP, 1, 0);
+
+    thread::sleep(Duration::from_millis(500));
+    let frames = FRAMES.load(Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(500));
+
+    let frames_after = FRAMES.load(Ordering::SeqCst);
+    let inputs_down = INPUTS_DOWN.lock().clone();
+    let forwarded = dx11_harness.app_messages();
+
+    drop(hooks);
+    drop(dx11_harness);
+
+    assert!(frames > 0, "The overlay never rendered");
+    assert!(frames_after > frames, "The overlay stopped rendering");
+    assert!(inputs_down.is_empty(), "Inputs stuck down: {inputs_down:?}");
+    assert_eq!(forwarded, app_messages, "Messages weren't forwarded to the game");
+}